@@ -0,0 +1,151 @@
+extern crate criterion;
+extern crate formatting;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use std::sync::mpsc;
+use std::thread;
+
+use formatting::parser::Parser;
+use formatting::tokenize::{StrTokens, Tokens};
+
+/// A paragraph of ordinary prose, with a little markup.
+const PARAGRAPH: &str = "The quick brown fox jumps over the lazy dog, and then it \\emph{runs} \
+away into the forest. Nobody knows where it went, although some say that it was seen near the \
+river, drinking water and watching the boats go by. It is, after all, a very quick fox.\n\n";
+
+/// A paragraph consisting mostly of commands.
+const COMMANDS: &str = "\\section{Intro}\\label{intro} \\emph{a}\\bold{b}\\code{c} \\ref{intro} \
+\\emph{\\bold{d}} \\footnote{\\code{e}} \\link{f}{g} \\color{red}{h}\\emph{i}\\bold{j}\\code{k}\
+\\item \\emph{l}\n\n";
+
+/// A paragraph consisting mostly of verbatim and raw blocks.
+const VERBATIM: &str = "Some code:\n\n\\verbatim[rust]{fn main() {\n    let x = vec![1, 2, 3];\n\
+\x20   println!(\"{:?}\", x);\n}\n}\n\n\\raw|<span class=\"a||b\">raw</span>|\n\n\
+\\verbatim!x = {a, b}!\n\n";
+
+/// The depth of nesting in the nested corpus.
+const DEPTH: usize = 64;
+
+/// Returns a document of about a megabyte consisting of the given paragraph repeated.
+fn repeat(paragraph: &str) -> String {
+    paragraph.repeat((1 << 20) / paragraph.len())
+}
+
+/// Returns a deeply nested document of about a megabyte, alternating between groups and
+/// command arguments.
+fn nested() -> String {
+    let mut paragraph = String::new();
+    for i in 0..DEPTH {
+        paragraph.push_str(if i % 2 == 0 { "{a " } else { "\\emph{b " });
+    }
+    paragraph.push_str("deep");
+    paragraph.push_str(&"}".repeat(DEPTH));
+    paragraph.push_str("\n\n");
+    repeat(&paragraph)
+}
+
+/// Returns the generated corpora, each of about a megabyte, along with their names.
+fn corpora() -> Vec<(&'static str, String)> {
+    vec![
+        ("prose", repeat(PARAGRAPH)),
+        ("commands", repeat(COMMANDS)),
+        ("verbatim", repeat(VERBATIM)),
+        ("nested", nested()),
+    ]
+}
+
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        for &coalesce in &[false, true] {
+            let function = if coalesce { "coalesced" } else { "chars" };
+            group.bench_with_input(BenchmarkId::new(function, name), &input, |b, input| {
+                b.iter(|| {
+                    Tokens::new(input.as_bytes())
+                        .coalesce_text(coalesce)
+                        .map(Result::unwrap)
+                        .count()
+                })
+            });
+        }
+        group.bench_with_input(BenchmarkId::new("borrowed", name), &input, |b, input| {
+            b.iter(|| {
+                StrTokens::new(input)
+                    .coalesce_text(true)
+                    .map(Result::unwrap)
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn collect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("collect");
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(name, &input, |b, input| {
+            b.iter(|| {
+                Tokens::new(input.as_bytes())
+                    .coalesce_text(true)
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// The number of tokens sent at a time by the chunked channel benchmark.
+const CHUNK: usize = 256;
+
+fn channel(c: &mut Criterion) {
+    let input = repeat(PARAGRAPH);
+    let mut group = c.benchmark_group("channel");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("tokens", |b| {
+        b.iter(|| {
+            let (sender, receiver) = mpsc::channel();
+            let consumer = thread::spawn(move || receiver.iter().count());
+            let mut tokens = Tokens::new(input.as_bytes()).coalesce_text(true);
+            while let Some(token) = tokens.next_res().unwrap() {
+                sender.send(token).unwrap();
+            }
+            drop(sender);
+            consumer.join().unwrap()
+        })
+    });
+    group.bench_function("chunks", |b| {
+        b.iter(|| {
+            let (sender, receiver) = mpsc::channel::<Vec<_>>();
+            let consumer = thread::spawn(move || receiver.iter().map(|c| c.len()).sum::<usize>());
+            let mut tokens = Tokens::new(input.as_bytes()).coalesce_text(true);
+            loop {
+                let mut chunk = Vec::with_capacity(CHUNK);
+                if tokens.next_chunk(&mut chunk, CHUNK).unwrap() == 0 {
+                    break;
+                }
+                sender.send(chunk).unwrap();
+            }
+            drop(sender);
+            consumer.join().unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(name, &input, |b, input| {
+            b.iter(|| Parser::new(input.as_bytes()).parse().unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tokenize, collect, channel, parse);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+//! Generates the C header for the interface in `src/ffi.rs`, when the `ffi` feature is enabled.
+
+#[cfg(feature = "ffi")]
+extern crate cbindgen;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+/// Writes `include/formatting.h`, which is rewritten only if it has changed.
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    use std::env;
+    use std::path::Path;
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    let directory = env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by Cargo");
+    let directory = Path::new(&directory);
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        cpp_compat: true,
+        include_guard: Some("FORMATTING_H".into()),
+        autogen_warning: Some(
+            "/* Generated by the build script from src/ffi.rs; do not edit. */".into(),
+        ),
+        no_includes: true,
+        sys_includes: vec!["stddef.h".into()],
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(directory.join("src/ffi.rs"))
+        .generate()
+        .expect("src/ffi.rs can be parsed")
+        .write_to_file(directory.join("include/formatting.h"));
+}
@@ -0,0 +1,18 @@
+//! Parses arbitrary bytes.
+
+#![no_main]
+
+use formatting::parser::{Parser, ParserOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (&flags, input) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let options = ParserOptions::new()
+        .smart_punctuation(flags & 1 != 0)
+        .skip_space_before_arguments(flags & 2 != 0)
+        .skip_newline_before_arguments(flags & 4 != 0);
+    let _ = Parser::with_options(input, options).parse();
+});
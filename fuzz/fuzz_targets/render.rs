@@ -0,0 +1,22 @@
+//! Parses arbitrary bytes, and renders the document, if there is one, in every format.
+
+#![no_main]
+
+use formatting::parser::Parser;
+use formatting::render::html::{HtmlOptions, HtmlRenderer};
+use formatting::render::{ansi, latex, markdown, plain, roff, source};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let document = match Parser::new(data).parse() {
+        Ok(document) => document,
+        Err(_) => return,
+    };
+    let _ = HtmlRenderer::new(HtmlOptions::new()).render_to_string(&document);
+    let _ = plain::render_to_string(&document, &Default::default());
+    let _ = markdown::render_to_string(&document);
+    let _ = latex::render_to_string(&document, &Default::default());
+    let _ = roff::render_to_string(&document, &Default::default());
+    let _ = ansi::render_to_string(&document, &Default::default());
+    let _ = source::render_to_string(&document, &Default::default());
+});
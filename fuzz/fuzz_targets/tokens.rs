@@ -0,0 +1,54 @@
+//! Tokenizes arbitrary bytes with `Tokens`, and, if they are UTF-8, with `StrTokens`, which must
+//! produce the same tokens.
+
+#![no_main]
+
+use formatting::tokenize::{StrTokens, Tokens};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte chooses the options, so that each combination is explored.
+    let (&flags, input) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let smart = flags & 1 != 0;
+    let coalesce = flags & 2 != 0;
+    let chunk_size = match flags >> 2 {
+        0 => None,
+        size => Some(size as usize),
+    };
+    let mut tokens = Tokens::new(input)
+        .smart_punctuation(smart)
+        .coalesce_text(coalesce)
+        .verbatim_chunk_size(chunk_size);
+    let mut owned = Vec::new();
+    let owned_end = loop {
+        match tokens.next_res() {
+            Ok(Some(token)) => owned.push(token),
+            Ok(None) => break Ok(()),
+            Err(error) => break Err(error),
+        }
+    };
+    // `StrTokens` does not split verbatim contents into chunks.
+    let input = match std::str::from_utf8(input) {
+        Ok(input) if chunk_size.is_none() => input,
+        _ => return,
+    };
+    let mut tokens = StrTokens::new(input)
+        .smart_punctuation(smart)
+        .coalesce_text(coalesce);
+    let mut borrowed = Vec::new();
+    let borrowed_end = loop {
+        match tokens.next_res() {
+            Ok(Some(token)) => borrowed.push(token.into_owned()),
+            Ok(None) => break Ok(()),
+            Err(error) => break Err(error),
+        }
+    };
+    assert_eq!(owned, borrowed);
+    assert_eq!(
+        owned_end.map_err(|e| e.kind().clone()),
+        borrowed_end.map_err(|e| e.kind().clone())
+    );
+});
@@ -0,0 +1,110 @@
+#ifndef FORMATTING_H
+#define FORMATTING_H
+
+/* Generated by the build script from src/ffi.rs; do not edit. */
+
+#include <stddef.h>
+
+/**
+ * Renders the document as HTML.
+ */
+#define FORMATTING_HTML 0
+
+/**
+ * Renders the document as plain text.
+ */
+#define FORMATTING_PLAIN 1
+
+/**
+ * Renders the document as Markdown.
+ */
+#define FORMATTING_MARKDOWN 2
+
+/**
+ * The document was rendered.
+ */
+#define FORMATTING_OK 0
+
+/**
+ * The document could not be parsed or rendered; the diagnostics hold the error.
+ */
+#define FORMATTING_ERROR 1
+
+/**
+ * A pointer which must not be null was null, or the format was not recognized; the diagnostics
+ * describe which.
+ */
+#define FORMATTING_INVALID_ARGUMENT 2
+
+/**
+ * The library panicked, which indicates a bug; the diagnostics hold the message of the panic.
+ */
+#define FORMATTING_PANIC 3
+
+/**
+ * The result of `formatting_render`.
+ *
+ * Each string is UTF-8, and is followed by a NUL byte which is not counted in its length. The
+ * output contains a NUL byte before its end only if the input did. Both strings are owned by the
+ * library, and must be released with `formatting_result_free` rather than `free`.
+ */
+typedef struct FormattingResult {
+  /**
+   * The rendered output, or null if the document could not be rendered.
+   */
+  char *output;
+  /**
+   * The length of `output` in bytes, or zero if it is null.
+   */
+  size_t output_len;
+  /**
+   * The warnings and any error, as a JSON array in the format of `Diagnostics::to_json`. Never
+   * null once `formatting_render` has returned.
+   */
+  char *diagnostics;
+  /**
+   * The length of `diagnostics` in bytes.
+   */
+  size_t diagnostics_len;
+} FormattingResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Renders the `len` bytes of UTF-8 at `input` in the given format, one of `FORMATTING_HTML`,
+ * `FORMATTING_PLAIN`, and `FORMATTING_MARKDOWN`, with the default options, and stores the output
+ * and the diagnostics in `*out`. Returns `FORMATTING_OK` if the document was rendered, and one of
+ * the other `FORMATTING_` status codes otherwise. A panic within the library is caught and
+ * returned as `FORMATTING_PANIC`, rather than unwinding into the caller.
+ *
+ * # Safety
+ *
+ * `input` must point to `len` readable bytes, which are only borrowed for the duration of the
+ * call; it may be null only if `len` is zero. `out` must point to a `FormattingResult` which the
+ * caller may write to. Whatever `*out` held before is overwritten without being freed. Unless
+ * `out` is null, in which case `FORMATTING_INVALID_ARGUMENT` is returned and nothing is written,
+ * the caller owns the strings stored in `*out`, and must release them exactly once with
+ * `formatting_result_free`.
+ */
+int formatting_render(const char *input, size_t len, int format, struct FormattingResult *out);
+
+/**
+ * Releases the strings of a `FormattingResult` filled in by `formatting_render`, and sets its
+ * pointers to null and its lengths to zero, so that releasing it again does nothing. Does nothing
+ * if `result` is null. The `FormattingResult` itself is owned by the caller, and is not freed.
+ *
+ * # Safety
+ *
+ * `result` must be null, or point to a `FormattingResult` which was filled in by
+ * `formatting_render` or has already been passed to this function, and whose fields have not
+ * been changed since. The strings must not be used once they have been released.
+ */
+void formatting_result_free(struct FormattingResult *result);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FORMATTING_H */
@@ -0,0 +1,184 @@
+//! Procedural macros which parse documents at compile time, so that a syntax error in a document
+//! embedded in Rust source is a compiler error, rather than an error at run time. They are
+//! re-exported by `formatting` with its `macros` feature.
+//!
+//! A procedural macro crate cannot depend on `formatting`, since `formatting` depends on it, so
+//! the modules of the library which parse documents are compiled into this crate from their
+//! source, with the `std` feature.
+
+extern crate alloc;
+extern crate core;
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+#[macro_use]
+extern crate thiserror;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+use syn::{Expr, Item, Lit, LitStr};
+
+use parser::Parser;
+
+// The modules of the library are compiled for their parser alone, and use crate-relative paths,
+// so they are declared at the root, under the names which those paths expect.
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/ast/mod.rs"]
+mod ast;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/date.rs"]
+mod date;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/errors.rs"]
+mod errors;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/limits.rs"]
+mod limits;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/lints.rs"]
+mod lints;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/locale.rs"]
+mod locale;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/parser/mod.rs"]
+mod parser;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/stats.rs"]
+mod stats;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/tokenize/mod.rs"]
+mod tokenize;
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/values.rs"]
+mod values;
+
+/// Parses the document given as a string literal, or as an `include_str!` of a file, and expands
+/// to the same `&'static str`, or fails to compile if the document cannot be parsed.
+///
+/// The document is parsed with the default options. A syntax error is reported at the literal,
+/// or at the `include_str!`, with the line and column within the document in the message.
+///
+/// ```ignore
+/// const HELP: &str = formatting::doc!(r"\emph{Usage}: run it.");
+/// const GUIDE: &str = formatting::doc!(include_str!("guide.mle"));
+/// ```
+#[proc_macro]
+pub fn doc(input: TokenStream) -> TokenStream {
+    let expr = parse_macro_input!(input as Expr);
+    match check_document(&expr) {
+        Ok(()) => quote!(#expr).into(),
+        Err(error) => compile_error(error).into(),
+    }
+}
+
+/// Parses the document given by a `const` or `static` string, as with `doc!`, leaving the item as
+/// it is, or fails to compile if the document cannot be parsed.
+///
+/// ```ignore
+/// #[formatting::check]
+/// const HELP: &str = r"\emph{Usage}: run it.";
+/// ```
+#[proc_macro_attribute]
+pub fn check(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as Item);
+    let result = if !args.is_empty() {
+        let args = proc_macro2::TokenStream::from(args);
+        Err(syn::Error::new(args.span(), "`#[check]` takes no arguments"))
+    } else {
+        match item {
+            Item::Const(ref item) => check_document(&item.expr),
+            Item::Static(ref item) => check_document(&item.expr),
+            _ => Err(syn::Error::new(
+                item.span(),
+                "`#[check]` applies only to a `const` or `static` string",
+            )),
+        }
+    };
+    match result {
+        Ok(()) => quote!(#item).into(),
+        Err(error) => {
+            let error = compile_error(error);
+            quote!(#error #item).into()
+        }
+    }
+}
+
+/// Parses the document given by an expression, which must be a string literal or an
+/// `include_str!` of one, returning an error to report at the expression if it is invalid.
+fn check_document(expr: &Expr) -> Result<(), syn::Error> {
+    let (text, file) = match *expr {
+        Expr::Lit(ref lit) => match lit.lit {
+            Lit::Str(ref lit) => (lit.value(), None),
+            _ => return Err(expected(expr)),
+        },
+        Expr::Macro(ref mac) if mac.mac.path.is_ident("include_str") => {
+            let path = mac.mac.parse_body::<LitStr>()?;
+            let name = path.value();
+            let text = fs::read_to_string(resolve(&name, path.span())).map_err(|e| {
+                syn::Error::new(expr.span(), format!("could not read `{}`: {}", name, e))
+            })?;
+            (text, Some(name))
+        }
+        _ => return Err(expected(expr)),
+    };
+    match Parser::new(text.as_bytes()).parse() {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            let mut message = String::from("invalid document");
+            if let Some(file) = file {
+                message.push_str(&format!(" `{}`", file));
+            }
+            if let Some(position) = error.kind().position() {
+                let (line, column) = (position.line, position.column);
+                message.push_str(&format!(" in line {}, column {}", line, column));
+            }
+            let kind = error.kind();
+            message.push_str(&format!(": [{}] {}", kind.code(), kind));
+            Err(syn::Error::new(expr.span(), message))
+        }
+    }
+}
+
+/// Returns an invocation of `compile_error!` for each message of an error, at its span.
+///
+/// `syn::Error::to_compile_error` names the macro by `::core::compile_error`, which does not
+/// resolve in an edition 2015 crate without `extern crate core`, so the macro is named by itself,
+/// as the prelude gives it in every edition.
+fn compile_error(error: syn::Error) -> proc_macro2::TokenStream {
+    error
+        .into_iter()
+        .map(|error| {
+            let message = error.to_string();
+            quote_spanned!(error.span()=> compile_error! { #message })
+        })
+        .collect()
+}
+
+/// Returns the error for an expression which is not a document.
+fn expected(expr: &Expr) -> syn::Error {
+    syn::Error::new(
+        expr.span(),
+        "expected a string literal or `include_str!` of a document",
+    )
+}
+
+/// Returns the path of a file named by `include_str!`, which is relative to the file containing
+/// it, or to the root of the package if that is not known.
+fn resolve(name: &str, span: Span) -> PathBuf {
+    let directory = span
+        .unwrap()
+        .local_file()
+        .and_then(|file| file.parent().map(PathBuf::from))
+        .or_else(|| env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from))
+        .unwrap_or_default();
+    directory.join(name)
+}
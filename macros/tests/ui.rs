@@ -0,0 +1,11 @@
+//! Compiles the cases in `tests/ui`, checking that the documents in `pass` are accepted, and that
+//! those in `fail` are rejected with the errors in the `.stderr` file beside each of them.
+
+extern crate trybuild;
+
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/pass/*.rs");
+    cases.compile_fail("tests/ui/fail/*.rs");
+}
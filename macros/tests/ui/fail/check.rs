@@ -0,0 +1,14 @@
+extern crate formatting_macros;
+
+use formatting_macros::check;
+
+#[check]
+const HELP: &str = "\\emph{Usage";
+
+#[check(strict)]
+const USAGE: &str = "run it";
+
+#[check]
+fn main() {
+    let _ = (HELP, USAGE);
+}
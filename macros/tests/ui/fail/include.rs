@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate formatting_macros;
+
+const GUIDE: &str = doc!(include_str!("broken.mle"));
+
+fn main() {
+    let _ = GUIDE;
+}
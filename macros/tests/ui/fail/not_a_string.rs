@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate formatting_macros;
+
+const LENGTH: usize = doc!(1 + 2);
+
+fn main() {
+    let _ = LENGTH;
+}
@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate formatting_macros;
+
+const HELP: &str = doc!("first line\n\\emph{second");
+
+fn main() {
+    let _ = HELP;
+}
@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate formatting_macros;
+
+const HELP: &str = doc!("a } b");
+
+fn main() {
+    let _ = HELP;
+}
@@ -0,0 +1,14 @@
+extern crate formatting_macros;
+
+use formatting_macros::check;
+
+#[check]
+const HELP: &str = r"\emph{Usage}: run it.";
+
+#[check]
+static VERSION: &str = "version 1";
+
+fn main() {
+    assert_eq!(HELP, r"\emph{Usage}: run it.");
+    assert_eq!(VERSION, "version 1");
+}
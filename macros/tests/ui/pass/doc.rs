@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate formatting_macros;
+
+const HELP: &str = doc!(r"\emph{Usage}: run it.");
+const GUIDE: &str = doc!(include_str!("guide.mle"));
+
+fn main() {
+    assert_eq!(HELP, r"\emph{Usage}: run it.");
+    assert_eq!(GUIDE, include_str!("guide.mle"));
+}
@@ -0,0 +1,124 @@
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::slice;
+
+use super::{text_content, Node};
+use values::{Color, Length};
+
+/// The type of an argument of a command, against which the parser checks the argument.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ArgType {
+    /// Any markup, which is not checked.
+    #[default]
+    Markup,
+    /// A whole number, which may be negative, such as `3`.
+    Integer,
+    /// A length, as described in the `values` module, such as `1.5em`, `50%`, or `200`.
+    Length,
+    /// A color, as described in the `values` module, such as `red`, `#c00`, or
+    /// `rgb(204, 0, 0)`.
+    Color,
+    /// An identifier, such as a label: letters, digits, `-`, `_`, `:`, `.`, and `/`, with no
+    /// whitespace.
+    Identifier,
+    /// A comma-separated list of `key=value` pairs, such as `alt=Logo, width=200`, as described
+    /// at `key_values`. An empty list is allowed.
+    KeyValues,
+}
+
+/// The value of an argument of a command, converted to its type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+    /// Markup, as it was parsed.
+    Markup(Vec<Node>),
+    /// A whole number.
+    Integer(i64),
+    /// A length.
+    Length(Length),
+    /// A color.
+    Color(Color),
+    /// An identifier.
+    Identifier(String),
+    /// The keys and values, in the order given, without surrounding whitespace.
+    KeyValues(Vec<(String, String)>),
+}
+
+impl ArgType {
+    /// Returns a description of the type, for use in messages, such as "an integer".
+    pub fn description(self) -> &'static str {
+        match self {
+            ArgType::Markup => "markup",
+            ArgType::Integer => "an integer",
+            ArgType::Length => "a length, such as `1.5em`",
+            ArgType::Color => "a color, such as `red` or `#c00`",
+            ArgType::Identifier => "an identifier of letters, digits, `-`, `_`, `:`, `.`, or `/`",
+            ArgType::KeyValues => "a list of `key=value` pairs",
+        }
+    }
+
+    /// Converts an argument to this type, or returns `None` if it is not of this type. An
+    /// argument of any type other than markup must be text alone, without surrounding
+    /// whitespace; a `KeyValues` argument may also hold groups in its values.
+    pub fn parse(self, arg: &[Node]) -> Option<Arg> {
+        if self == ArgType::Markup {
+            return Some(Arg::Markup(arg.to_vec()));
+        }
+        if self == ArgType::KeyValues {
+            let pairs = key_values(arg);
+            let valid = pairs.iter().all(|(key, _)| is_identifier(key));
+            return if valid { Some(Arg::KeyValues(pairs)) } else { None };
+        }
+        if arg.iter().any(|node| !matches!(*node, Node::Text(_))) {
+            return None;
+        }
+        let text = text_content(arg);
+        let text = text.trim();
+        match self {
+            ArgType::Integer => text.parse().ok().map(Arg::Integer),
+            ArgType::Length => text.parse().ok().map(Arg::Length),
+            ArgType::Color => text.parse().ok().map(Arg::Color),
+            ArgType::Identifier if is_identifier(text) => Some(Arg::Identifier(text.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+/// Returns whether a string is a non-empty identifier.
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || "-_:./".contains(c))
+}
+
+/// Returns the `key=value` pairs of an argument, such as the options of an `\image`, separated by
+/// commas. A value containing a comma may be enclosed in a group. The keys and values are
+/// returned without surrounding whitespace, a pair without `=` is given an empty value, and
+/// pairs which are blank are omitted.
+pub fn key_values(arg: &[Node]) -> Vec<(String, String)> {
+    let mut options = vec![String::new()];
+    for node in arg {
+        match *node {
+            Node::Text(ref text) => {
+                let mut parts = text.split(',');
+                if let (Some(option), Some(part)) = (options.last_mut(), parts.next()) {
+                    option.push_str(part);
+                }
+                options.extend(parts.map(str::to_owned));
+            }
+            // A group is part of a single value, even if it contains commas.
+            ref node => {
+                if let Some(option) = options.last_mut() {
+                    option.push_str(&text_content(slice::from_ref(node)));
+                }
+            }
+        }
+    }
+    options
+        .iter()
+        .filter(|option| !option.trim().is_empty())
+        .map(|option| {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            (key.trim().to_owned(), value.trim().to_owned())
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+//! Construction of documents from data, such as generated reports and changelogs, without
+//! writing source and parsing it.
+//!
+//! A `DocumentBuilder` builds a list of blocks, and an `InlineBuilder` the inline content of a
+//! paragraph or of the argument of a command. Each method which adds nested content takes a
+//! closure, which is given a builder for that content, and each returns the builder, so that
+//! calls can be chained. Text is held as it is given, so it needs no escaping: the nodes built
+//! are those which parsing the source written for them by `render::source` gives, up to
+//! whitespace. Text which is instead to be embedded in source is escaped by
+//! `tokenize::escape_text`.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use super::{Command, Document, Environment, Node, Verbatim};
+
+/// A builder of a list of blocks, such as a document or the content of an environment.
+///
+/// Inline content added directly to a `DocumentBuilder`, by `text` or `command`, is collected
+/// into a paragraph, which is ended by the next block.
+#[derive(Clone, Debug, Default)]
+pub struct DocumentBuilder {
+    nodes: Vec<Node>,
+    /// The content of the paragraph being collected.
+    paragraph: InlineBuilder,
+}
+
+impl DocumentBuilder {
+    /// Constructs a new, empty `DocumentBuilder`.
+    pub fn new() -> DocumentBuilder {
+        DocumentBuilder::default()
+    }
+
+    /// Adds a `\section` with the given title, followed by the blocks added by `content`.
+    pub fn section<F>(&mut self, title: &str, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut DocumentBuilder),
+    {
+        self.heading("section", title, content)
+    }
+
+    /// Adds a `\subsection` with the given title, followed by the blocks added by `content`.
+    pub fn subsection<F>(&mut self, title: &str, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut DocumentBuilder),
+    {
+        self.heading("subsection", title, content)
+    }
+
+    fn heading<F>(&mut self, name: &str, title: &str, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut DocumentBuilder),
+    {
+        let title = InlineBuilder::new().text(title).build();
+        self.node(Node::Command(Command {
+            name: name.into(),
+            args: vec![title],
+        }));
+        content(self);
+        self.end_paragraph();
+        self
+    }
+
+    /// Adds a paragraph holding the inline content added by `content`. It is kept apart from any
+    /// text added to this builder before or after it.
+    pub fn paragraph<F>(&mut self, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut InlineBuilder),
+    {
+        self.end_paragraph();
+        content(&mut self.paragraph);
+        self.end_paragraph();
+        self
+    }
+
+    /// Adds text to the current paragraph, starting one if there is none.
+    pub fn text(&mut self, text: &str) -> &mut DocumentBuilder {
+        self.paragraph.text(text);
+        self
+    }
+
+    /// Adds a command to the current paragraph, starting one if there is none, as by
+    /// `InlineBuilder::command`.
+    pub fn command<F>(&mut self, name: &str, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut InlineBuilder),
+    {
+        self.paragraph.command(name, content);
+        self
+    }
+
+    /// Adds an environment holding the blocks added by `content`, such as a `quote`, or a list
+    /// whose items are added by `item`.
+    pub fn environment<F>(&mut self, name: &str, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut DocumentBuilder),
+    {
+        let nodes = DocumentBuilder::new().blocks(content);
+        self.node(Node::Environment(Environment {
+            name: name.into(),
+            args: Vec::new(),
+            nodes,
+        }))
+    }
+
+    /// Adds an `\item` of a list, holding the blocks added by `content`.
+    pub fn item<F>(&mut self, content: F) -> &mut DocumentBuilder
+    where
+        F: FnOnce(&mut DocumentBuilder),
+    {
+        let nodes = DocumentBuilder::new().blocks(content);
+        self.node(Node::Command(Command {
+            name: "item".into(),
+            args: vec![nodes],
+        }))
+    }
+
+    /// Adds a verbatim block, with the language it is written in, if any.
+    pub fn verbatim(&mut self, content: &str, language: Option<&str>) -> &mut DocumentBuilder {
+        self.node(Node::Verbatim(Verbatim {
+            content: content.into(),
+            language: language.map(String::from),
+        }))
+    }
+
+    /// Adds a block which has no method of its own, such as a `Node::Table`, or a command or
+    /// environment with several arguments, whose content may be built by `InlineBuilder::build`.
+    pub fn node(&mut self, node: Node) -> &mut DocumentBuilder {
+        self.end_paragraph();
+        self.nodes.push(node);
+        self
+    }
+
+    /// Returns the document built so far, leaving this builder empty.
+    pub fn build(&mut self) -> Document {
+        self.end_paragraph();
+        Document {
+            nodes: mem::take(&mut self.nodes),
+        }
+    }
+
+    /// Returns the blocks added by `content`.
+    fn blocks<F>(mut self, content: F) -> Vec<Node>
+    where
+        F: FnOnce(&mut DocumentBuilder),
+    {
+        content(&mut self);
+        self.build().nodes
+    }
+
+    /// Adds the current paragraph, if it is not empty, as a block.
+    fn end_paragraph(&mut self) {
+        if !self.paragraph.nodes.is_empty() {
+            let nodes = self.paragraph.build();
+            self.nodes.push(Node::Paragraph(nodes));
+        }
+    }
+}
+
+/// A builder of inline content, such as a paragraph or the argument of a command.
+#[derive(Clone, Debug, Default)]
+pub struct InlineBuilder {
+    nodes: Vec<Node>,
+}
+
+impl InlineBuilder {
+    /// Constructs a new, empty `InlineBuilder`.
+    pub fn new() -> InlineBuilder {
+        InlineBuilder::default()
+    }
+
+    /// Adds text, which is joined to any text immediately before it.
+    pub fn text(&mut self, text: &str) -> &mut InlineBuilder {
+        if let Some(Node::Text(last)) = self.nodes.last_mut() {
+            last.push_str(text);
+        } else if !text.is_empty() {
+            self.nodes.push(Node::Text(text.into()));
+        }
+        self
+    }
+
+    /// Adds a command with a single argument, holding the content added by `content`, such as
+    /// `\bold` or `\footnote`.
+    pub fn command<F>(&mut self, name: &str, content: F) -> &mut InlineBuilder
+    where
+        F: FnOnce(&mut InlineBuilder),
+    {
+        let mut arg = InlineBuilder::new();
+        content(&mut arg);
+        self.node(Node::Command(Command {
+            name: name.into(),
+            args: vec![arg.nodes],
+        }))
+    }
+
+    /// Adds a command without arguments, such as `\today`.
+    pub fn bare_command(&mut self, name: &str) -> &mut InlineBuilder {
+        self.node(Node::Command(Command {
+            name: name.into(),
+            args: Vec::new(),
+        }))
+    }
+
+    /// Adds a group holding the content added by `content`.
+    pub fn group<F>(&mut self, content: F) -> &mut InlineBuilder
+    where
+        F: FnOnce(&mut InlineBuilder),
+    {
+        let mut group = InlineBuilder::new();
+        content(&mut group);
+        self.node(Node::Group(group.nodes))
+    }
+
+    /// Adds a node which has no method of its own, such as a command with several arguments.
+    pub fn node(&mut self, node: Node) -> &mut InlineBuilder {
+        self.nodes.push(node);
+        self
+    }
+
+    /// Returns the content built so far, leaving this builder empty.
+    pub fn build(&mut self) -> Vec<Node> {
+        mem::take(&mut self.nodes)
+    }
+}
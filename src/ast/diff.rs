@@ -0,0 +1,327 @@
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{text_content, Document, Node, Span};
+
+/// The position of a node within a document, as a list of indices from the root.
+///
+/// The first index is that of a top-level node of the document. Each node below it adds two: the
+/// index of a list of its children, and the index of the node within that list. The lists of a
+/// paragraph or a group are just its content; those of a command are its arguments; those of an
+/// environment are its arguments followed by its content; and those of a table are its cells, row
+/// by row, followed by its caption. So `[2, 0, 1]` is the second node of the first argument of
+/// the third top-level node.
+pub type Path = Vec<usize>;
+
+/// A difference between two documents, as found by `diff`. Paths into the old document are named
+/// `old`, and those into the new document `new`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// A node of the new document which is not in the old one.
+    Inserted { new: Path },
+    /// A node of the old document which is not in the new one.
+    Deleted { old: Path },
+    /// A node which is the same in both documents, but in a different place among its siblings.
+    Moved { old: Path, new: Path },
+    /// A run of text, or the content of a `\verbatim` or `\raw` command, which was edited.
+    Text {
+        old: Path,
+        new: Path,
+        old_text: String,
+        new_text: String,
+    },
+    /// A node which was changed in some other way, such as a `\verbatim` command whose language
+    /// was changed, or a command given a different number of arguments, and which is reported as
+    /// a whole, rather than by the changes within it.
+    Modified { old: Path, new: Path },
+}
+
+impl Change {
+    /// Returns the path of the node in the old document, unless it was inserted.
+    pub fn old_path(&self) -> Option<&[usize]> {
+        match *self {
+            Change::Inserted { .. } => None,
+            Change::Deleted { ref old }
+            | Change::Moved { ref old, .. }
+            | Change::Text { ref old, .. }
+            | Change::Modified { ref old, .. } => Some(old),
+        }
+    }
+
+    /// Returns the path of the node in the new document, unless it was deleted.
+    pub fn new_path(&self) -> Option<&[usize]> {
+        match *self {
+            Change::Deleted { .. } => None,
+            Change::Inserted { ref new }
+            | Change::Moved { ref new, .. }
+            | Change::Text { ref new, .. }
+            | Change::Modified { ref new, .. } => Some(new),
+        }
+    }
+
+    /// Returns the spans in the old and the new source of the top-level nodes containing the
+    /// change, given the spans of the top-level nodes of each document, as returned by
+    /// `Blocks::parse_spanned`. Nodes below the top level do not have spans of their own.
+    pub fn spans(&self, old_spans: &[Span], new_spans: &[Span]) -> (Option<Span>, Option<Span>) {
+        let span = |path: Option<&[usize]>, spans: &[Span]| {
+            path.and_then(|path| path.first())
+                .and_then(|&i| spans.get(i))
+                .copied()
+        };
+        (span(self.old_path(), old_spans), span(self.new_path(), new_spans))
+    }
+}
+
+/// Returns the changes which turn the old document into the new one.
+///
+/// The nodes of each list of siblings are matched first by their longest common subsequence of
+/// equal nodes, which are unchanged. Of the rest, a node which is equal to one on the other side
+/// is reported as moved. Each remaining old node is then paired with a node of the same kind
+/// between the same unchanged neighbours in the new document, preferring the one with the most
+/// words in common, and the two are compared in turn: text is reported as edited, and the
+/// children of commands, environments, and other containers are compared in the same way. Nodes
+/// which are left unpaired are deleted or inserted.
+///
+/// The changes are ordered by their place in the documents, with those within a node following
+/// those before it.
+pub fn diff(old: &Document, new: &Document) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_lists(&old.nodes, &new.nodes, &[], &[], &mut changes);
+    changes
+}
+
+/// Adds the changes between two lists of sibling nodes, at the given paths, to `changes`.
+fn diff_lists(
+    old: &[Node],
+    new: &[Node],
+    old_path: &[usize],
+    new_path: &[usize],
+    changes: &mut Vec<Change>,
+) {
+    let path = |base: &[usize], i: usize| {
+        let mut path = base.to_vec();
+        path.push(i);
+        path
+    };
+    let common = common_subsequence(old, new);
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    for &(i, j) in &common {
+        old_matched[i] = true;
+        new_matched[j] = true;
+    }
+    // Nodes which are not in the common subsequence, but are equal to one on the other side,
+    // were moved; they are reported at their place in the new list.
+    let mut moved_from = vec![None; new.len()];
+    for (j, node) in new.iter().enumerate() {
+        if new_matched[j] {
+            continue;
+        }
+        let found = (0..old.len()).find(|&i| !old_matched[i] && old[i] == *node);
+        if let Some(i) = found {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            moved_from[j] = Some(i);
+        }
+    }
+    // The unchanged nodes divide both lists into gaps, within which the nodes are paired.
+    let ends = common.iter().copied().chain(Some((old.len(), new.len())));
+    let (mut i, mut j) = (0, 0);
+    for (old_end, new_end) in ends {
+        let olds: Vec<usize> = (i..old_end).filter(|&i| !old_matched[i]).collect();
+        let news: Vec<usize> = (j..new_end).filter(|&j| !new_matched[j]).collect();
+        let mut next = 0;
+        let mut pairs = Vec::new();
+        for &o in &olds {
+            let best = news[next..]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &n)| same_kind(&old[o], &new[n]))
+                .max_by_key(|&(k, &n)| (similarity(&old[o], &new[n]), usize::MAX - k));
+            if let Some((k, &n)) = best {
+                pairs.push((o, n));
+                next += k + 1;
+            }
+        }
+        // The changes in the gap are reported in order of the new list, with the old nodes which
+        // were deleted before a paired node reported just before it.
+        let mut deleted = olds
+            .into_iter()
+            .filter(|&o| !pairs.iter().any(|&(p, _)| p == o))
+            .peekable();
+        for n in j..new_end {
+            if let Some(&(o, _)) = pairs.iter().find(|&&(_, p)| p == n) {
+                while let Some(d) = deleted.next_if(|&d| d < o) {
+                    changes.push(Change::Deleted {
+                        old: path(old_path, d),
+                    });
+                }
+                compare(&old[o], &new[n], path(old_path, o), path(new_path, n), changes);
+            } else if let Some(o) = moved_from[n] {
+                changes.push(Change::Moved {
+                    old: path(old_path, o),
+                    new: path(new_path, n),
+                });
+            } else {
+                changes.push(Change::Inserted {
+                    new: path(new_path, n),
+                });
+            }
+        }
+        for d in deleted {
+            changes.push(Change::Deleted {
+                old: path(old_path, d),
+            });
+        }
+        i = old_end + 1;
+        j = new_end + 1;
+    }
+}
+
+/// Adds the changes between two paired nodes of the same kind to `changes`.
+fn compare(old: &Node, new: &Node, old_path: Path, new_path: Path, changes: &mut Vec<Change>) {
+    let text = |old_text: &str, new_text: &str| Change::Text {
+        old: old_path.clone(),
+        new: new_path.clone(),
+        old_text: old_text.into(),
+        new_text: new_text.into(),
+    };
+    match (old, new) {
+        (Node::Text(a), Node::Text(b)) | (Node::Raw(a), Node::Raw(b)) => {
+            changes.push(text(a, b))
+        }
+        (Node::Verbatim(a), Node::Verbatim(b)) if a.language == b.language => {
+            changes.push(text(&a.content, &b.content))
+        }
+        _ => match (children(old), children(new)) {
+            (Some(a), Some(b)) if a.len() == b.len() => {
+                for (k, (a, b)) in a.into_iter().zip(b).enumerate() {
+                    let mut old_path = old_path.clone();
+                    old_path.push(k);
+                    let mut new_path = new_path.clone();
+                    new_path.push(k);
+                    diff_lists(a, b, &old_path, &new_path, changes);
+                }
+            }
+            _ => changes.push(Change::Modified {
+                old: old_path,
+                new: new_path,
+            }),
+        },
+    }
+}
+
+/// Returns the lists of children of a node, as described by `Path`, or `None` for a node which
+/// has none, or a table whose shape is such that its children cannot be compared with those of
+/// another.
+fn children(node: &Node) -> Option<Vec<&[Node]>> {
+    match *node {
+        Node::Paragraph(ref nodes) | Node::Group(ref nodes) => Some(vec![nodes]),
+        Node::Command(ref command) => Some(command.args.iter().map(Vec::as_slice).collect()),
+        Node::Environment(ref env) => Some(
+            env.args
+                .iter()
+                .map(Vec::as_slice)
+                .chain(Some(env.nodes.as_slice()))
+                .collect(),
+        ),
+        Node::Table(ref table) => Some(
+            table
+                .rows
+                .iter()
+                .flatten()
+                .map(Vec::as_slice)
+                .chain(Some(table.caption.as_slice()))
+                .collect(),
+        ),
+        Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => None,
+    }
+}
+
+/// Returns the node at the given path within a list of top-level nodes, if there is one.
+#[cfg(feature = "testing")]
+pub(crate) fn node_at<'a>(nodes: &'a [Node], path: &[usize]) -> Option<&'a Node> {
+    let (&first, mut rest) = path.split_first()?;
+    let mut node = nodes.get(first)?;
+    while let [list, index, ref more @ ..] = *rest {
+        node = children(node)?.get(list)?.get(index)?;
+        rest = more;
+    }
+    Some(node).filter(|_| rest.is_empty())
+}
+
+/// Returns whether two nodes are of the same kind, and so may be compared: commands and
+/// environments of the same name, tables with the same columns and rows of the same lengths, or
+/// two nodes of any other kind which is the same.
+fn same_kind(a: &Node, b: &Node) -> bool {
+    match (a, b) {
+        (Node::Command(a), Node::Command(b)) => a.name == b.name,
+        (Node::Environment(a), Node::Environment(b)) => a.name == b.name,
+        (Node::Table(a), Node::Table(b)) => {
+            a.columns == b.columns
+                && a.header == b.header
+                && a.rows.len() == b.rows.len()
+                && a.rows.iter().zip(&b.rows).all(|(a, b)| a.len() == b.len())
+        }
+        (Node::Text(_), Node::Text(_))
+        | (Node::Paragraph(_), Node::Paragraph(_))
+        | (Node::Group(_), Node::Group(_))
+        | (Node::Verbatim(_), Node::Verbatim(_))
+        | (Node::Raw(_), Node::Raw(_)) => true,
+        _ => false,
+    }
+}
+
+/// Returns the number of distinct words which the text of both nodes contains.
+fn similarity(a: &Node, b: &Node) -> usize {
+    let a = text_content(core::slice::from_ref(a));
+    let b = text_content(core::slice::from_ref(b));
+    let a: BTreeSet<&str> = a.split_whitespace().collect();
+    b.split_whitespace()
+        .collect::<BTreeSet<&str>>()
+        .intersection(&a)
+        .count()
+}
+
+/// Returns the pairs of indices of a longest common subsequence of equal nodes of the two lists,
+/// in increasing order. Nodes which are equal at the start and the end of both lists are matched
+/// directly, so that the table of lengths covers only the part between them.
+fn common_subsequence(old: &[Node], new: &[Node]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    // `lengths[i][j]` is the length of the longest common subsequence of `a[i..]` and `b[j..]`.
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    let (old_start, new_start) = (old.len() - suffix, new.len() - suffix);
+    pairs.extend((0..suffix).map(|k| (old_start + k, new_start + k)));
+    pairs
+}
@@ -0,0 +1,247 @@
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::slice;
+
+use errors::Warning;
+
+pub use self::args::{key_values, Arg, ArgType};
+pub use self::diff::{diff, Change, Path};
+pub use self::semantic::Equivalence;
+
+mod args;
+pub mod builder;
+pub(crate) mod diff;
+mod semantic;
+
+/// A parsed document.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Document {
+    /// The top-level nodes of the document.
+    pub nodes: Vec<Node>,
+}
+
+impl Document {
+    /// Appends the nodes of another document to this one, such as the next chapter of a book
+    /// which was parsed separately, returning warnings about conflicts between the two.
+    ///
+    /// Sections, footnotes, and other numbered constructs are numbered when a document is
+    /// rendered, so those of `other` continue from those of this document. Metadata follows the
+    /// renderers, which use the first `\title`, `\date`, and `\lang` and every `\author`: a
+    /// `\title`, `\date`, or `\lang` of `other` is dropped if this document, or an earlier node of
+    /// `other`, already has one, with a `Warning::ConflictingMetadata` if they differ, and an
+    /// `\author` is dropped if the same author is already named. A `\label` of `other` which this
+    /// document already uses is kept, with a `Warning::DuplicateLabel`; references to it resolve
+    /// to the first.
+    ///
+    /// `file` is the name of the file from which `other` was parsed, and `spans` the span of each
+    /// of its nodes, as returned by `Blocks::parse_spanned`, which locate its duplicate labels;
+    /// `spans` may be empty if they are not known. Nodes carry no spans, so those of `other` still
+    /// refer to its own source.
+    pub fn append(&mut self, other: Document, file: &str, spans: &[Span]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        let mut labels = BTreeSet::new();
+        collect_labels(&self.nodes, &mut labels);
+        let mut existing: Vec<(String, String)> = self.nodes.iter().filter_map(metadata).collect();
+        for (i, node) in other.nodes.into_iter().enumerate() {
+            let mut node_labels = BTreeSet::new();
+            collect_labels(slice::from_ref(&node), &mut node_labels);
+            for label in node_labels.intersection(&labels) {
+                warnings.push(Warning::DuplicateLabel {
+                    label: label.clone(),
+                    file: String::from(file),
+                    span: spans.get(i).copied(),
+                });
+            }
+            if let Some((name, value)) = metadata(&node) {
+                let mut same = existing.iter().filter(|&(n, _)| *n == name);
+                if name == "author" {
+                    if same.any(|(_, v)| *v == value) {
+                        continue;
+                    }
+                } else if let Some((_, first)) = same.next() {
+                    if *first != value {
+                        warnings.push(Warning::ConflictingMetadata(name));
+                    }
+                    continue;
+                }
+                existing.push((name, value));
+            }
+            self.nodes.push(node);
+        }
+        warnings
+    }
+}
+
+/// Returns the name and text of a top-level `\title`, `\author`, `\date`, or `\lang` command.
+fn metadata(node: &Node) -> Option<(String, String)> {
+    match *node {
+        Node::Command(ref command)
+            if matches!(&*command.name, "title" | "author" | "date" | "lang") =>
+        {
+            let text = command.args.first().map_or_else(String::new, |arg| text_content(arg));
+            Some((command.name.clone(), String::from(text.trim())))
+        }
+        _ => None,
+    }
+}
+
+/// Adds the argument of each `\label` command within the nodes, at any depth, to `labels`.
+fn collect_labels(nodes: &[Node], labels: &mut BTreeSet<String>) {
+    for node in nodes {
+        match *node {
+            Node::Paragraph(ref nodes) | Node::Group(ref nodes) => collect_labels(nodes, labels),
+            Node::Command(ref command) => {
+                if command.name == "label" {
+                    if let Some(arg) = command.args.first() {
+                        labels.insert(String::from(text_content(arg).trim()));
+                    }
+                }
+                for arg in &command.args {
+                    collect_labels(arg, labels);
+                }
+            }
+            Node::Environment(ref env) => collect_labels(&env.nodes, labels),
+            Node::Table(ref table) => {
+                for cell in table.rows.iter().flatten() {
+                    collect_labels(cell, labels);
+                }
+                collect_labels(&table.caption, labels);
+            }
+            Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => {}
+        }
+    }
+}
+
+/// A node of the document tree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Node {
+    /// A run of ordinary text.
+    Text(String),
+    /// A paragraph of inline content.
+    Paragraph(Vec<Node>),
+    /// A group delimited by `{` and `}`.
+    Group(Vec<Node>),
+    /// A command.
+    Command(Command),
+    /// An environment delimited by `\begin` and `\end`.
+    Environment(Environment),
+    /// A table, given by a `table` environment.
+    Table(Table),
+    /// The contents of a `\verbatim` command, which renderers display literally.
+    Verbatim(Verbatim),
+    /// The contents of a `\raw` command, which renderers emit into the output without escaping.
+    Raw(String),
+}
+
+/// The contents of a `\verbatim` command, along with the language they are written in.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Verbatim {
+    /// The literal content.
+    pub content: String,
+    /// The language, such as a programming language, given in brackets after `\verbatim`.
+    pub language: Option<String>,
+}
+
+/// A command invocation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Command {
+    /// The name of the command, without the leading backslash.
+    pub name: String,
+    /// The arguments of the command.
+    pub args: Vec<Vec<Node>>,
+}
+
+/// An environment, along with its arguments and content.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Environment {
+    /// The name of the environment.
+    pub name: String,
+    /// The arguments given to the `\begin` command, after the name.
+    pub args: Vec<Vec<Node>>,
+    /// The content of the environment.
+    pub nodes: Vec<Node>,
+}
+
+/// A table, along with its caption.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Table {
+    /// The alignment of each column. Columns beyond those given are left-aligned.
+    pub columns: Vec<Alignment>,
+    /// Whether the first row is a header row.
+    pub header: bool,
+    /// The rows of the table, each a list of cells. Rows may have different numbers of cells.
+    pub rows: Vec<Vec<Vec<Node>>>,
+    /// The caption, which is empty if none was given.
+    pub caption: Vec<Node>,
+}
+
+impl Table {
+    /// Returns the number of columns, which is that of the longest row.
+    pub fn column_count(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Returns the alignment of the given column.
+    pub fn alignment(&self, column: usize) -> Alignment {
+        self.columns.get(column).copied().unwrap_or(Alignment::Left)
+    }
+}
+
+/// A range of bytes of the input, from which a node was parsed.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    /// The byte offset of the start of the range.
+    pub start: usize,
+    /// The byte offset of the end of the range, which is not included.
+    pub end: usize,
+}
+
+impl Span {
+    /// Returns whether the given byte offset is within the span.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+/// The alignment of a table column.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// Returns the text of the given nodes, ignoring any markup. The arguments of commands and the
+/// content of environments are included, as are the cells of tables, separated by spaces, but
+/// verbatim and raw content is not.
+pub fn text_content(nodes: &[Node]) -> String {
+    let mut text = String::new();
+    push_text_content(nodes, &mut text);
+    text
+}
+
+fn push_text_content(nodes: &[Node], text: &mut String) {
+    for node in nodes {
+        match *node {
+            Node::Text(ref t) => text.push_str(t),
+            Node::Paragraph(ref nodes) | Node::Group(ref nodes) => push_text_content(nodes, text),
+            Node::Command(ref command) => {
+                for arg in &command.args {
+                    push_text_content(arg, text);
+                }
+            }
+            Node::Environment(ref env) => push_text_content(&env.nodes, text),
+            Node::Table(ref table) => {
+                for cell in table.rows.iter().flatten() {
+                    if !text.is_empty() && !text.ends_with(char::is_whitespace) {
+                        text.push(' ');
+                    }
+                    push_text_content(cell, text);
+                }
+            }
+            Node::Verbatim(_) | Node::Raw(_) => {}
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{Command, Document, Environment, Node, Table};
+
+/// How nodes are compared by `Node::semantic_eq` and `Document::semantic_eq`, which ask whether
+/// two trees are the same document, whatever the differences in how it was written.
+///
+/// Both trees are normalized before they are compared: adjacent text nodes are merged, each run
+/// of whitespace in text becomes a single space, and whitespace at the start and end of a
+/// paragraph, a table cell, or a caption is removed, as is text which is only whitespace between
+/// blocks. Empty groups are removed, unless `ignore_empty_groups` is turned off. The content of
+/// `\verbatim` and `\raw` commands is compared exactly.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Equivalence {
+    ignore_empty_groups: bool,
+}
+
+impl Default for Equivalence {
+    fn default() -> Equivalence {
+        Equivalence {
+            ignore_empty_groups: true,
+        }
+    }
+}
+
+/// Where a list of nodes is, which determines which of its whitespace is insignificant.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Context {
+    /// Inline content whose whitespace is all significant, such as the argument of a command.
+    Inline,
+    /// Inline content whose leading and trailing whitespace is not, such as a paragraph.
+    Trimmed,
+    /// A list of blocks, such as a document, between which whitespace is not.
+    Blocks,
+}
+
+impl Equivalence {
+    /// Constructs a new `Equivalence` with the default settings.
+    pub fn new() -> Equivalence {
+        Equivalence::default()
+    }
+
+    /// Sets whether empty groups, `{}`, are removed before comparing. Defaults to `true`.
+    pub fn ignore_empty_groups(mut self, ignore: bool) -> Equivalence {
+        self.ignore_empty_groups = ignore;
+        self
+    }
+
+    /// Returns whether two nodes are semantically equal.
+    pub fn node_eq(&self, a: &Node, b: &Node) -> bool {
+        self.normalize_node(a) == self.normalize_node(b)
+    }
+
+    /// Returns whether two documents are semantically equal.
+    pub fn document_eq(&self, a: &Document, b: &Document) -> bool {
+        self.normalize(a) == self.normalize(b)
+    }
+
+    /// Returns the document in normal form, so that two documents are semantically equal if and
+    /// only if their normal forms are equal.
+    pub fn normalize(&self, document: &Document) -> Document {
+        Document {
+            nodes: self.normalize_list(&document.nodes, Context::Blocks),
+        }
+    }
+
+    /// Returns the node in normal form, as a list of nodes, since an empty group or text of only
+    /// whitespace may have no normal form at all.
+    pub fn normalize_node(&self, node: &Node) -> Vec<Node> {
+        self.normalize_list(core::slice::from_ref(node), Context::Trimmed)
+    }
+
+    /// Returns the list of nodes in normal form.
+    fn normalize_list(&self, nodes: &[Node], context: Context) -> Vec<Node> {
+        let mut normal = Vec::new();
+        for node in nodes {
+            let node = match *node {
+                Node::Text(ref text) => {
+                    if let Some(&mut Node::Text(ref mut last)) = normal.last_mut() {
+                        push_collapsed(last, text);
+                    } else {
+                        let mut collapsed = String::new();
+                        push_collapsed(&mut collapsed, text);
+                        normal.push(Node::Text(collapsed));
+                    }
+                    continue;
+                }
+                Node::Paragraph(ref nodes) => {
+                    Node::Paragraph(self.normalize_list(nodes, Context::Trimmed))
+                }
+                Node::Group(ref nodes) => {
+                    let nodes = self.normalize_list(nodes, Context::Inline);
+                    if nodes.is_empty() && self.ignore_empty_groups {
+                        continue;
+                    }
+                    Node::Group(nodes)
+                }
+                Node::Command(ref command) => Node::Command(Command {
+                    name: command.name.clone(),
+                    args: self.normalize_args(&command.args),
+                }),
+                Node::Environment(ref env) => Node::Environment(Environment {
+                    name: env.name.clone(),
+                    args: self.normalize_args(&env.args),
+                    nodes: self.normalize_list(&env.nodes, Context::Blocks),
+                }),
+                Node::Table(ref table) => Node::Table(Table {
+                    columns: table.columns.clone(),
+                    header: table.header,
+                    rows: table
+                        .rows
+                        .iter()
+                        .map(|row| {
+                            row.iter()
+                                .map(|cell| self.normalize_list(cell, Context::Trimmed))
+                                .collect()
+                        })
+                        .collect(),
+                    caption: self.normalize_list(&table.caption, Context::Trimmed),
+                }),
+                Node::Verbatim(_) | Node::Raw(_) => node.clone(),
+            };
+            normal.push(node);
+        }
+        match context {
+            Context::Inline => {}
+            Context::Trimmed => {
+                if let Some(&mut Node::Text(ref mut text)) = normal.first_mut() {
+                    let trimmed = text.trim_start().len();
+                    text.drain(..text.len() - trimmed);
+                }
+                if let Some(&mut Node::Text(ref mut text)) = normal.last_mut() {
+                    text.truncate(text.trim_end().len());
+                }
+            }
+            Context::Blocks => {
+                for node in &mut normal {
+                    if let Node::Text(ref mut text) = *node {
+                        *text = String::from(text.trim());
+                    }
+                }
+            }
+        }
+        normal.retain(|node| !matches!(*node, Node::Text(ref text) if text.is_empty()));
+        normal
+    }
+
+    /// Returns the arguments of a command or environment in normal form.
+    fn normalize_args(&self, args: &[Vec<Node>]) -> Vec<Vec<Node>> {
+        args.iter()
+            .map(|arg| self.normalize_list(arg, Context::Inline))
+            .collect()
+    }
+}
+
+/// Appends text to `out`, with each run of whitespace, including one which continues a run at
+/// the end of `out`, replaced by a single space.
+fn push_collapsed(out: &mut String, text: &str) {
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            out.push(c);
+        } else if !out.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+}
+
+impl Node {
+    /// Returns whether two nodes are the same, ignoring insignificant whitespace, the way text is
+    /// split into nodes, and empty groups, as described by `Equivalence`.
+    pub fn semantic_eq(&self, other: &Node) -> bool {
+        Equivalence::new().node_eq(self, other)
+    }
+}
+
+impl Document {
+    /// Returns whether two documents are the same, ignoring insignificant whitespace, the way
+    /// text is split into nodes, and empty groups, as described by `Equivalence`.
+    pub fn semantic_eq(&self, other: &Document) -> bool {
+        Equivalence::new().document_eq(self, other)
+    }
+}
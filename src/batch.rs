@@ -0,0 +1,197 @@
+//! Processing of many inputs at once, spread across threads.
+//!
+//! `process_all` parses and renders each of a list of files on a pool of worker threads, and
+//! returns the results in the order in which the files were given, so that the output does not
+//! depend on how the work happened to be scheduled.
+
+use std::fmt;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use ast::Document;
+use bibliography;
+use diagnostics::{Diagnostic, Diagnostics, Severity};
+use errors::Error;
+use parser::{Parser, ParserOptions};
+use render::RenderReport;
+
+/// The result of processing a single input of a batch.
+#[derive(Debug)]
+pub struct Processed<T> {
+    /// The path of the input.
+    pub path: PathBuf,
+    /// The rendered output, or the error which stopped the input from being opened, parsed, or
+    /// rendered.
+    pub result: Result<T, Error>,
+    /// The warnings of the parser and the renderer, followed by the error, if any. Each has the
+    /// name of the input as its `file`, unless it occurred in another file, such as a definitions
+    /// file.
+    pub diagnostics: Diagnostics,
+}
+
+/// The results of processing a batch of inputs, in the order in which the inputs were given.
+#[derive(Debug)]
+pub struct BatchReport<T> {
+    processed: Vec<Processed<T>>,
+}
+
+impl<T> BatchReport<T> {
+    /// Returns the result of each input, in the order in which the inputs were given.
+    pub fn processed(&self) -> &[Processed<T>] {
+        &self.processed
+    }
+
+    /// Returns the result of each input, in the order in which the inputs were given.
+    pub fn into_processed(self) -> Vec<Processed<T>> {
+        self.processed
+    }
+
+    /// Returns the number of inputs which could not be processed.
+    pub fn failed(&self) -> usize {
+        self.processed.iter().filter(|p| p.result.is_err()).count()
+    }
+
+    /// Returns the number of diagnostics with the given severity, across all inputs.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.processed
+            .iter()
+            .map(|p| p.diagnostics.count(severity))
+            .sum()
+    }
+
+    /// Returns whether any input produced a diagnostic with severity `Error`.
+    pub fn has_errors(&self) -> bool {
+        self.processed.iter().any(|p| p.diagnostics.has_errors())
+    }
+
+    /// Raises every warning of every input to severity `Error`, as with
+    /// `Diagnostics::deny_warnings`. The inputs which produced them are not counted as failed,
+    /// since they were still processed.
+    pub fn deny_warnings(&mut self) {
+        for processed in &mut self.processed {
+            processed.diagnostics.deny_warnings();
+        }
+    }
+}
+
+/// The diagnostics are written input by input, in the order in which the inputs were given, and
+/// sorted by position within each input, followed by a count of the failed inputs, errors, and
+/// warnings.
+impl<T> fmt::Display for BatchReport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for processed in &self.processed {
+            for diagnostic in processed.diagnostics.sorted() {
+                writeln!(f, "{}\n", diagnostic)?;
+            }
+        }
+        let count = |n, name| match n {
+            1 => format!("1 {}", name),
+            n => format!("{} {}s", n, name),
+        };
+        write!(
+            f,
+            "{} of {} failed: {}, {}",
+            self.failed(),
+            count(self.processed.len(), "input"),
+            count(self.count(Severity::Error), "error"),
+            count(self.count(Severity::Warning), "warning")
+        )
+    }
+}
+
+/// Parses each of the named files with the given options, resolves the citations of each
+/// document as with `bibliography::resolve`, relative to the directory of its file, and renders
+/// it with `render`, which returns the output along with the `RenderReport` of the renderer.
+///
+/// The inputs are shared between as many worker threads as the system can run in parallel, each
+/// of which takes the next unprocessed input whenever it finishes one. Every worker has its own
+/// copy of the options and its own parser, so nothing is shared between inputs. The results are
+/// returned in the order of `inputs`, whatever order they finished in. If `render` panics, the
+/// panic is resumed on the calling thread once the other workers have stopped.
+pub fn process_all<P, T, F>(inputs: &[P], options: &ParserOptions, render: F) -> BatchReport<T>
+where
+    P: AsRef<Path> + Sync,
+    T: Send,
+    F: Fn(&Document) -> Result<(T, RenderReport), Error> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(inputs.len());
+    let mut results: Vec<(usize, Processed<T>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let path = match inputs.get(index) {
+                            Some(path) => path.as_ref(),
+                            None => return results,
+                        };
+                        results.push((index, process(path, options.clone(), &render)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+    results.sort_by_key(|&(index, _)| index);
+    BatchReport {
+        processed: results.into_iter().map(|(_, p)| p).collect(),
+    }
+}
+
+/// Parses each of the named files with the given options without rendering them, as with
+/// `process_all`, to check them for errors and warnings.
+pub fn check_all<P>(inputs: &[P], options: &ParserOptions) -> BatchReport<()>
+where
+    P: AsRef<Path> + Sync,
+{
+    process_all(inputs, options, |_| Ok(((), RenderReport::new())))
+}
+
+/// Parses and renders a single input of a batch.
+fn process<T, F>(path: &Path, options: ParserOptions, render: &F) -> Processed<T>
+where
+    F: Fn(&Document) -> Result<(T, RenderReport), Error>,
+{
+    let name = path.display().to_string();
+    let mut diagnostics = Diagnostics::new();
+    let named = |mut diagnostic: Diagnostic| {
+        diagnostic.file.get_or_insert_with(|| name.clone());
+        diagnostic
+    };
+    let locale = options.get_locale().clone();
+    let result = Parser::open(path, options)
+        .and_then(|mut parser| {
+            let document = parser.parse();
+            for warning in parser.warnings() {
+                diagnostics.push(named(Diagnostic::warning(warning.clone())));
+            }
+            let mut document = document?;
+            let directory = path.parent().unwrap_or_else(|| Path::new(""));
+            bibliography::resolve(&mut document, directory, &locale)?;
+            Ok(document)
+        })
+        .and_then(|document| {
+            let (output, report) = render(&document)?;
+            for warning in report.warnings() {
+                diagnostics.push(named(Diagnostic::warning(warning.clone())));
+            }
+            Ok(output)
+        });
+    if let Err(ref error) = result {
+        diagnostics.push(named(Diagnostic::error(error)));
+    }
+    Processed {
+        path: path.to_owned(),
+        result,
+        diagnostics,
+    }
+}
@@ -0,0 +1,377 @@
+//! Citations of the entries of a bibliography, which `\cite` refers to by key.
+//!
+//! A bibliography file gives one entry per line, as its key, a colon, and the text of the
+//! reference:
+//!
+//! ```text
+//! # Lines starting with `#` are comments, and blank lines are ignored.
+//! knuth84: Donald E. Knuth. The TeXbook. Addison-Wesley, 1984.
+//! lamport94: Leslie Lamport. LaTeX: A Document Preparation System. Addison-Wesley,
+//!     second edition, 1994.
+//! ```
+//!
+//! A line starting with whitespace continues the text of the entry before it. Keys may not be
+//! empty or contain whitespace or commas, and no key may be given twice. The text is written as
+//! it is, rather than parsed as markup, with its whitespace collapsed.
+//!
+//! A document cites entries with `\cite{knuth84}`, or several at once with
+//! `\cite{knuth84,lamport94}`, and names the files holding them with `\bibliography{refs}`.
+//! `resolve` loads those files and replaces the `\bibliography` command with a references
+//! section: a `\section*{References}` heading, translated by the given `Locale`, followed by a
+//! `references` environment holding a `\bibitem{key}{text}` for each cited entry, in the order
+//! in which they are first cited. The renderers number the `\bibitem`s in order, and write each
+//! `\cite` as the numbers of the entries it cites, such as `[1, 3]`; a key with no `\bibitem` is
+//! written as `?`, with a `Warning::UnknownCitation`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ast::{self, Command, Document, Environment, Node};
+use errors::{Error, ErrorKind, Position, ResultExt, Warning};
+use locale::Locale;
+use render::RenderReport;
+
+/// The extension given to the name of a bibliography file which has none.
+pub const EXTENSION: &str = "refs";
+
+/// The entries of one or more bibliography files.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Bibliography {
+    /// The key and text of each entry, in the order in which they are given.
+    entries: Vec<(String, String)>,
+}
+
+impl Bibliography {
+    /// Constructs a new, empty `Bibliography`.
+    pub fn new() -> Bibliography {
+        Bibliography::default()
+    }
+
+    /// Parses the text of a bibliography file. Syntax errors, and keys given more than once, are
+    /// reported as `ErrorKind::BibliographySyntax`.
+    pub fn parse(text: &str) -> Result<Bibliography, Error> {
+        let mut bibliography = Bibliography::new();
+        for (i, line) in text.lines().enumerate() {
+            let position = Position {
+                line: i + 1,
+                column: 1,
+                offset: line.as_ptr() as usize - text.as_ptr() as usize,
+                utf16_column: 1,
+            };
+            let error = |message: &str| ErrorKind::BibliographySyntax {
+                message: message.to_owned(),
+                position,
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) {
+                match bibliography.entries.last_mut() {
+                    Some(&mut (_, ref mut text)) => push_words(text, line),
+                    None => return Err(error("a continuation line before the first entry").into()),
+                }
+                continue;
+            }
+            let (key, rest) = match line.split_once(':') {
+                Some((key, rest)) => (key.trim_end(), rest),
+                None => return Err(error("expected `key: text`").into()),
+            };
+            if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == ',') {
+                return Err(error("a key may not be empty or contain whitespace or commas").into());
+            }
+            if bibliography.get(key).is_some() {
+                return Err(error(&format!("the key `{}` is given more than once", key)).into());
+            }
+            let mut text = String::new();
+            push_words(&mut text, rest);
+            bibliography.entries.push((key.to_owned(), text));
+        }
+        Ok(bibliography)
+    }
+
+    /// Loads the named bibliography file, as with `parse`. Any errors are reported with the name
+    /// of the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Bibliography, Error> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).context(ErrorKind::Open(name.clone()))?;
+        Bibliography::parse(&text).map_err(|e| e.with_file(name))
+    }
+
+    /// Adds the entries of another bibliography, other than those whose keys this one already
+    /// has.
+    pub fn extend(&mut self, other: Bibliography) {
+        for (key, text) in other.entries {
+            if self.get(&key).is_none() {
+                self.entries.push((key, text));
+            }
+        }
+    }
+
+    /// Returns the text of the entry with the given key, if there is one.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, text)| &**text)
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the nodes of the references section for the given keys: a `\section*` heading,
+    /// which is the `references` translation of the locale, and a `references` environment
+    /// holding a `\bibitem` for each key which has an entry, in the order given. Returns no nodes
+    /// if none of the keys has an entry.
+    pub fn references(&self, keys: &[String], locale: &Locale) -> Vec<Node> {
+        let items: Vec<_> = keys
+            .iter()
+            .filter_map(|key| {
+                let text = self.get(key)?;
+                Some(Node::Command(Command {
+                    name: "bibitem".into(),
+                    args: vec![
+                        vec![Node::Text(key.clone())],
+                        vec![Node::Text(text.to_owned())],
+                    ],
+                }))
+            })
+            .collect();
+        if items.is_empty() {
+            return Vec::new();
+        }
+        vec![
+            Node::Command(Command {
+                name: "section*".into(),
+                args: vec![vec![Node::Text(locale.references().into())]],
+            }),
+            Node::Environment(Environment {
+                name: "references".into(),
+                args: Vec::new(),
+                nodes: items,
+            }),
+        ]
+    }
+}
+
+/// Appends the words of `s` to `text`, separated by single spaces.
+fn push_words(text: &mut String, s: &str) {
+    for word in s.split_whitespace() {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(word);
+    }
+}
+
+/// Loads the bibliography files named by the top-level `\bibliography` commands of a document,
+/// relative to the given directory, and replaces the first of the commands with the references
+/// section listing the cited entries, as with `Bibliography::references`. The others are
+/// removed. A `\bibliography` command may name several files, separated by commas, and
+/// `.refs` is added to a name without an extension. If an entry is given by more than one
+/// file, the first is used.
+///
+/// Citations of keys which no file has are left for the renderers, which warn about them.
+pub fn resolve<P: AsRef<Path>>(
+    document: &mut Document,
+    directory: P,
+    locale: &Locale,
+) -> Result<(), Error> {
+    let first = match document.nodes.iter().position(is_bibliography) {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    let mut bibliography = Bibliography::new();
+    for path in files(document, directory) {
+        bibliography.extend(Bibliography::load(path)?);
+    }
+    let references = bibliography.references(&citation_order(&document.nodes), locale);
+    let rest = document.nodes.split_off(first);
+    document.nodes.extend(references);
+    document.nodes.extend(rest.into_iter().filter(|node| !is_bibliography(node)));
+    Ok(())
+}
+
+/// Returns the paths of the bibliography files named by the top-level `\bibliography` commands
+/// of a document, relative to the given directory, which `resolve` reads. They are in the order
+/// in which they are first named, without duplicates, and are returned whether or not the files
+/// exist.
+pub fn files<P: AsRef<Path>>(document: &Document, directory: P) -> Vec<PathBuf> {
+    let directory = directory.as_ref();
+    let mut files = Vec::new();
+    for node in document.nodes.iter().filter(|node| is_bibliography(node)) {
+        if let Node::Command(ref command) = *node {
+            for name in comma_separated(command.args.first()) {
+                let path = file_path(directory, &name);
+                if !files.contains(&path) {
+                    files.push(path);
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Returns whether a node is a `\bibliography` command.
+fn is_bibliography(node: &Node) -> bool {
+    matches!(*node, Node::Command(ref command) if command.name == "bibliography")
+}
+
+/// Returns the path of the named bibliography file, relative to `directory`.
+fn file_path(directory: &Path, name: &str) -> PathBuf {
+    let mut path = directory.join(name);
+    if path.extension().is_none() {
+        path.set_extension(EXTENSION);
+    }
+    path
+}
+
+/// Returns the comma-separated names in an argument, without surrounding whitespace, omitting
+/// any which are empty.
+fn comma_separated(arg: Option<&Vec<Node>>) -> Vec<String> {
+    let text = arg.map(|arg| ast::text_content(arg)).unwrap_or_default();
+    text.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the keys cited by a `\cite` command, in the order given.
+pub fn cite_keys(command: &Command) -> Vec<String> {
+    comma_separated(command.args.first())
+}
+
+/// Returns the keys cited by the `\cite` commands among the nodes, at any depth, in the order in
+/// which they are first cited.
+pub fn citation_order(nodes: &[Node]) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut seen = HashSet::new();
+    for_each_command(nodes, &mut |command| {
+        if command.name == "cite" {
+            for key in cite_keys(command) {
+                if seen.insert(key.clone()) {
+                    keys.push(key);
+                }
+            }
+        }
+    });
+    keys
+}
+
+/// Calls `f` with each command among the nodes, at any depth, in document order.
+pub(crate) fn for_each_command<F: FnMut(&Command)>(nodes: &[Node], f: &mut F) {
+    for node in nodes {
+        match *node {
+            Node::Paragraph(ref nodes) | Node::Group(ref nodes) => for_each_command(nodes, f),
+            Node::Command(ref command) => {
+                f(command);
+                for arg in &command.args {
+                    for_each_command(arg, f);
+                }
+            }
+            Node::Environment(ref env) => {
+                for arg in &env.args {
+                    for_each_command(arg, f);
+                }
+                for_each_command(&env.nodes, f);
+            }
+            Node::Table(ref table) => {
+                for cell in table.rows.iter().flatten() {
+                    for_each_command(cell, f);
+                }
+                for_each_command(&table.caption, f);
+            }
+            Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => {}
+        }
+    }
+}
+
+/// The text written for a cited key which has no `\bibitem`.
+pub const UNKNOWN_CITATION: &str = "?";
+
+/// The numbers of the `\bibitem`s of a document, by which the renderers mark citations.
+#[derive(Clone, Debug, Default)]
+pub struct Citations {
+    numbers: HashMap<String, usize>,
+}
+
+impl Citations {
+    /// Numbers the `\bibitem`s among the given nodes in order, starting from 1. A key given to
+    /// more than one `\bibitem` keeps the first number.
+    pub fn new(nodes: &[Node]) -> Citations {
+        let mut numbers = HashMap::new();
+        let mut count = 0;
+        for_each_command(nodes, &mut |command| {
+            if command.name == "bibitem" {
+                count += 1;
+                numbers.entry(bibitem_key(command)).or_insert(count);
+            }
+        });
+        Citations { numbers }
+    }
+
+    /// Returns the number of the `\bibitem` with the given key, if there is one.
+    pub fn number(&self, key: &str) -> Option<usize> {
+        self.numbers.get(key).copied()
+    }
+
+    /// Returns the keys cited by a `\cite` command, each with the number of its `\bibitem`, or
+    /// `None` if it has none, in which case a warning is added to the report.
+    pub fn cited(
+        &self,
+        command: &Command,
+        report: &mut RenderReport,
+    ) -> Vec<(String, Option<usize>)> {
+        cite_keys(command)
+            .into_iter()
+            .map(|key| {
+                let number = self.number(&key);
+                if number.is_none() {
+                    report.warn(Warning::UnknownCitation(key.clone()));
+                }
+                (key, number)
+            })
+            .collect()
+    }
+
+    /// Returns the text of a `\cite` command for formats without links: the numbers of the cited
+    /// entries, separated by commas, in brackets, such as `[1, 3]`, with `?` for those which
+    /// have none.
+    pub fn marker(&self, command: &Command, report: &mut RenderReport) -> String {
+        let numbers: Vec<_> = self
+            .cited(command, report)
+            .into_iter()
+            .map(|(_, number)| number_text(number))
+            .collect();
+        format!("[{}]", numbers.join(", "))
+    }
+}
+
+/// Returns the text of the number of a cited entry, or `?` if it has none.
+pub fn number_text(number: Option<usize>) -> String {
+    number.map_or_else(|| UNKNOWN_CITATION.to_owned(), |number| number.to_string())
+}
+
+/// Returns the key of a `\bibitem` command.
+pub fn bibitem_key(command: &Command) -> String {
+    command
+        .args
+        .first()
+        .map(|arg| ast::text_content(arg).trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the identifier of the anchor of the entry with the given key, to which citations
+/// link in formats with links.
+pub fn anchor(key: &str) -> String {
+    format!("ref-{}", key)
+}
@@ -0,0 +1,435 @@
+//! A compact binary serialization of parsed documents, so that an input which has not changed
+//! need not be parsed again. Requires the `cache` feature.
+//!
+//! `Document::to_cache_bytes` writes a document, and `Document::from_cache_bytes` reads it back.
+//! The bytes start with a header of the magic bytes `FMTC`, the version of the format as a
+//! little-endian `u32`, and a little-endian `u64` key hashed from the version of this crate and
+//! the parser options which affect the nodes parsed. Bytes written by another version, or for
+//! other options, are rejected with `ErrorKind::StaleCache`, and bytes which are not a whole
+//! cache with `ErrorKind::InvalidCache`, so that a cache is never read as the wrong document.
+//!
+//! The key covers neither the input nor the contents of the definitions file, which the caller
+//! compares, as by `fingerprint`. Since `\today` is replaced when parsing, it covers the date
+//! written for it, so a cache written without `ParserOptions::today` is stale the next day.
+//! Titles, authors, and labels are nodes, and are cached with the rest of the document, but the
+//! warnings of the parser are not, so a document whose parsing gave warnings is best not cached,
+//! as the command-line tool does not, for them to be reported each time it is converted.
+//!
+//! After the header, the nodes are written depth first. Each node is a tag byte followed by its
+//! fields, in the order in which they are declared; lengths and counts are LEB128 numbers, and
+//! strings are their length followed by their UTF-8 bytes. The same document and options always
+//! give the same bytes.
+
+use std::fmt::{self, Write};
+use std::str;
+
+use ast::{Alignment, Command, Document, Environment, Node, Table, Verbatim};
+use date::Date;
+use errors::{Error, ErrorKind};
+use parser::ParserOptions;
+
+/// The bytes which start every cache.
+const MAGIC: &[u8; 4] = b"FMTC";
+
+/// The version of the format, which is increased whenever it changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// The greatest depth of nested lists of nodes which is read, which keeps a corrupt cache from
+/// overflowing the stack. The parser allows 128 nested constructs, each of which nests at most two
+/// lists.
+const MAX_DEPTH: usize = 512;
+
+const TEXT: u8 = 0;
+const PARAGRAPH: u8 = 1;
+const GROUP: u8 = 2;
+const COMMAND: u8 = 3;
+const ENVIRONMENT: u8 = 4;
+const TABLE: u8 = 5;
+const VERBATIM: u8 = 6;
+const RAW: u8 = 7;
+
+impl Document {
+    /// Serializes the document, as parsed with the given options, in the format described in the
+    /// `cache` module.
+    pub fn to_cache_bytes(&self, options: &ParserOptions) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&options_key(options).to_le_bytes());
+        write_nodes(&mut out, &self.nodes);
+        out
+    }
+
+    /// Deserializes a document written by `to_cache_bytes`. Fails with `ErrorKind::StaleCache` if
+    /// the bytes were written by another version of this crate or with other options, and with
+    /// `ErrorKind::InvalidCache` if they are not a whole cache.
+    pub fn from_cache_bytes(bytes: &[u8], options: &ParserOptions) -> Result<Document, Error> {
+        let mut decoder = Decoder { bytes, depth: 0 };
+        if decoder.array()? != *MAGIC {
+            return Err(ErrorKind::InvalidCache.into());
+        }
+        let version = u32::from_le_bytes(decoder.array()?);
+        let key = u64::from_le_bytes(decoder.array()?);
+        if version != FORMAT_VERSION || key != options_key(options) {
+            return Err(ErrorKind::StaleCache.into());
+        }
+        let nodes = decoder.nodes()?;
+        if !decoder.bytes.is_empty() {
+            return Err(ErrorKind::InvalidCache.into());
+        }
+        Ok(Document { nodes })
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of the given bytes. Unlike the hashers of the standard library,
+/// it is the same in every run and on every platform, so it can key a cache by the contents of
+/// its input.
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv::new();
+    hasher.write(bytes);
+    hasher.0
+}
+
+/// Returns the key of a cache written by this version of the crate with the given options.
+fn options_key(options: &ParserOptions) -> u64 {
+    let mut hasher = Fnv::new();
+    // The options are written with `Debug`, whose output is fixed for a given version of the
+    // crate, and hashed as they are written.
+    let _ = write!(
+        hasher,
+        "{:?}",
+        (
+            env!("CARGO_PKG_VERSION"),
+            options.get_target_format(),
+            options.get_definitions_file(),
+            options.get_skip_space_before_arguments(),
+            options.get_skip_newline_before_arguments(),
+            options.get_smart_punctuation(),
+            options.get_backslash_break(),
+            options.get_dedent_verbatim(),
+        )
+    );
+    let _ = write!(
+        hasher,
+        "{:?}",
+        (
+            options.get_strict_arguments(),
+            options.get_lints(),
+            options.get_limits(),
+            options.get_date_format(),
+            options.get_today().unwrap_or_else(Date::today),
+            options.get_locale(),
+        )
+    );
+    hasher.0
+}
+
+/// A 64-bit FNV-1a hasher.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl fmt::Write for Fnv {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn write_usize(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_usize(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_nodes(out: &mut Vec<u8>, nodes: &[Node]) {
+    write_usize(out, nodes.len());
+    for node in nodes {
+        write_node(out, node);
+    }
+}
+
+fn write_lists(out: &mut Vec<u8>, lists: &[Vec<Node>]) {
+    write_usize(out, lists.len());
+    for nodes in lists {
+        write_nodes(out, nodes);
+    }
+}
+
+fn write_node(out: &mut Vec<u8>, node: &Node) {
+    match *node {
+        Node::Text(ref text) => {
+            out.push(TEXT);
+            write_str(out, text);
+        }
+        Node::Paragraph(ref nodes) => {
+            out.push(PARAGRAPH);
+            write_nodes(out, nodes);
+        }
+        Node::Group(ref nodes) => {
+            out.push(GROUP);
+            write_nodes(out, nodes);
+        }
+        Node::Command(ref command) => {
+            out.push(COMMAND);
+            write_str(out, &command.name);
+            write_lists(out, &command.args);
+        }
+        Node::Environment(ref env) => {
+            out.push(ENVIRONMENT);
+            write_str(out, &env.name);
+            write_lists(out, &env.args);
+            write_nodes(out, &env.nodes);
+        }
+        Node::Table(ref table) => {
+            out.push(TABLE);
+            write_usize(out, table.columns.len());
+            out.extend(table.columns.iter().map(|&alignment| match alignment {
+                Alignment::Left => 0,
+                Alignment::Center => 1,
+                Alignment::Right => 2,
+            }));
+            out.push(u8::from(table.header));
+            write_usize(out, table.rows.len());
+            for row in &table.rows {
+                write_lists(out, row);
+            }
+            write_nodes(out, &table.caption);
+        }
+        Node::Verbatim(ref verbatim) => {
+            out.push(VERBATIM);
+            write_str(out, &verbatim.content);
+            match verbatim.language {
+                Some(ref language) => {
+                    out.push(1);
+                    write_str(out, language);
+                }
+                None => out.push(0),
+            }
+        }
+        Node::Raw(ref raw) => {
+            out.push(RAW);
+            write_str(out, raw);
+        }
+    }
+}
+
+/// A reader of the bytes written by `to_cache_bytes`, any of whose methods fails with
+/// `ErrorKind::InvalidCache` if the bytes are not as written.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    /// The number of lists of nodes being read.
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.bytes.len() {
+            return Err(ErrorKind::InvalidCache.into());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ErrorKind::InvalidCache.into()),
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        let mut n = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = usize::from(byte & 0x7f);
+            if shift >= usize::BITS || bits << shift >> shift != bits {
+                return Err(ErrorKind::InvalidCache.into());
+            }
+            n |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+        str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| ErrorKind::InvalidCache.into())
+    }
+
+    fn nodes(&mut self) -> Result<Vec<Node>, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(ErrorKind::InvalidCache.into());
+        }
+        self.depth += 1;
+        // Each node takes at least a byte, so a corrupt count soon runs out of bytes, and is not
+        // trusted to reserve space.
+        let count = self.usize()?;
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            nodes.push(self.node()?);
+        }
+        self.depth -= 1;
+        Ok(nodes)
+    }
+
+    fn lists(&mut self) -> Result<Vec<Vec<Node>>, Error> {
+        let count = self.usize()?;
+        let mut lists = Vec::new();
+        for _ in 0..count {
+            lists.push(self.nodes()?);
+        }
+        Ok(lists)
+    }
+
+    fn node(&mut self) -> Result<Node, Error> {
+        let node = match self.byte()? {
+            TEXT => Node::Text(self.string()?),
+            PARAGRAPH => Node::Paragraph(self.nodes()?),
+            GROUP => Node::Group(self.nodes()?),
+            COMMAND => Node::Command(Command {
+                name: self.string()?,
+                args: self.lists()?,
+            }),
+            ENVIRONMENT => Node::Environment(Environment {
+                name: self.string()?,
+                args: self.lists()?,
+                nodes: self.nodes()?,
+            }),
+            TABLE => Node::Table(self.table()?),
+            VERBATIM => Node::Verbatim(Verbatim {
+                content: self.string()?,
+                language: if self.bool()? {
+                    Some(self.string()?)
+                } else {
+                    None
+                },
+            }),
+            RAW => Node::Raw(self.string()?),
+            _ => return Err(ErrorKind::InvalidCache.into()),
+        };
+        Ok(node)
+    }
+
+    fn table(&mut self) -> Result<Table, Error> {
+        let count = self.usize()?;
+        let mut columns = Vec::new();
+        for _ in 0..count {
+            columns.push(match self.byte()? {
+                0 => Alignment::Left,
+                1 => Alignment::Center,
+                2 => Alignment::Right,
+                _ => return Err(ErrorKind::InvalidCache.into()),
+            });
+        }
+        let header = self.bool()?;
+        let count = self.usize()?;
+        let mut rows = Vec::new();
+        for _ in 0..count {
+            rows.push(self.lists()?);
+        }
+        Ok(Table {
+            columns,
+            header,
+            rows,
+            caption: self.nodes()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ast::Document;
+    use errors::ErrorKind;
+    use parser::{Parser, ParserOptions};
+    use super::FORMAT_VERSION;
+
+    /// A document with a node of each kind.
+    const SOURCE: &str = "\\section{Title}\\label{title}\n\nSome {grouped \\emph{text}}.\n\n\
+        \\begin{quote}\nQuoted.\n\\end{quote}\n\n\\begin{table}{lr}{A caption}\n\\header{a}{b}\n\
+        \\row{1}{2}\n\\end{table}\n\n\\verbatim{code}\n\n\\raw{<br>}\n";
+
+    fn document(options: &ParserOptions) -> Document {
+        Parser::with_options(SOURCE.as_bytes(), options.clone())
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let options = ParserOptions::new();
+        let document = document(&options);
+        let bytes = document.to_cache_bytes(&options);
+        assert_eq!(Document::from_cache_bytes(&bytes, &options).unwrap(), document);
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let options = ParserOptions::new();
+        let bytes = document(&options).to_cache_bytes(&options);
+        assert_eq!(document(&options).to_cache_bytes(&options), bytes);
+        let document = Document::from_cache_bytes(&bytes, &options).unwrap();
+        assert_eq!(document.to_cache_bytes(&options), bytes);
+    }
+
+    #[test]
+    fn rejects_other_versions_and_options() {
+        let options = ParserOptions::new();
+        let mut bytes = document(&options).to_cache_bytes(&options);
+        let other = ParserOptions::new().smart_punctuation(!options.get_smart_punctuation());
+        let error = Document::from_cache_bytes(&bytes, &other).unwrap_err();
+        assert!(matches!(*error.kind(), ErrorKind::StaleCache));
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = Document::from_cache_bytes(&bytes, &options).unwrap_err();
+        assert!(matches!(*error.kind(), ErrorKind::StaleCache));
+    }
+
+    #[test]
+    fn rejects_partial_caches() {
+        let options = ParserOptions::new();
+        let bytes = document(&options).to_cache_bytes(&options);
+        for len in 0..bytes.len() {
+            let error = Document::from_cache_bytes(&bytes[..len], &options).unwrap_err();
+            assert!(matches!(*error.kind(), ErrorKind::InvalidCache), "{} bytes", len);
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        let error = Document::from_cache_bytes(&extended, &options).unwrap_err();
+        assert!(matches!(*error.kind(), ErrorKind::InvalidCache));
+    }
+}
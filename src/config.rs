@@ -0,0 +1,629 @@
+//! Options for the parser and the renderers, loaded from a TOML configuration file, such as
+//! `formatting.toml`. Requires the `serde` feature.
+//!
+//! Each table of the file sets the options of one part of the crate, with a key for each of their
+//! settings, named as the method which sets it:
+//!
+//! ```toml
+//! [parser]
+//! smart_punctuation = true
+//! definitions = "macros.mle"
+//!
+//! [lints]
+//! trailing_whitespace = "warn"
+//! long_line = "deny"
+//! max_line_length = 80
+//!
+//! [html]
+//! unknown_commands = "error"
+//! fragment = false
+//! lang = "fr"
+//! stylesheets = ["style.css"]
+//!
+//! [html.commands.kbd]
+//! tag = "kbd"
+//! class = ["key"]
+//!
+//! [plain]
+//! wrap = true
+//! width = 72
+//!
+//! [locale]
+//! name = "de"
+//! ```
+//!
+//! The tables are `locale`, `parser`, `lints`, `html`, `plain`, `ansi`, `latex`, `roff`, and
+//! `source`. The `locale` table sets the locale of the parser and of the renderers which write
+//! strings of their own: `name` selects a built-in locale, `en`, `de`, or `fr`, and `file` loads
+//! a locale file, which takes precedence. The options of the ANSI renderer start from those given
+//! by `plain` and by the environment, as with `AnsiOptions::from_env`. Keys which are not
+//! recognized are reported as `Warning::UnknownConfigKey`, so that a misspelled key is noticed,
+//! and a value of the wrong type is an `ErrorKind::InvalidConfig` naming the key and the
+//! expected type.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use errors::{Error, ErrorKind, Position, ResultExt, Warning};
+use lints::{Level, Lint, Lints};
+use locale::Locale;
+use parser::ParserOptions;
+use render::ansi::{AnsiOptions, CodeStyle, ColorDepth};
+use render::html::{HtmlOptions, LineNumbers, RawPolicy, Tag, UnknownCommandPolicy};
+#[cfg(feature = "hyphenation")]
+use render::hyphenation::{Hyphenation, Language};
+use render::latex::LatexOptions;
+use render::plain::{PlainOptions, TableBorders};
+use render::roff::RoffOptions;
+use render::sanitize::Allowlist;
+use render::source::SourceOptions;
+use render::Direction;
+
+pub use convert::Options;
+
+/// The name of the configuration file which the command-line tool looks for next to its input.
+pub const FILE_NAME: &str = "formatting.toml";
+
+impl Options {
+    /// Loads options from the text of a configuration file. Relative paths in the file are left
+    /// as they are, and so are relative to the working directory.
+    pub fn from_toml_str(text: &str) -> Result<Options, Error> {
+        load(text, None)
+    }
+
+    /// Loads options from a configuration file read from the given reader, as with
+    /// `from_toml_str`.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Options, Error> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .with_context(|e| ErrorKind::from_io(e, Position::default()))?;
+        Options::from_toml_str(&text)
+    }
+
+    /// Loads options from the named configuration file. Relative paths in the file, such as that
+    /// of a definitions file, are relative to the directory containing it. Errors are attached
+    /// to the name of the file, other than those in a locale file it names, which keep the name
+    /// of that file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Options, Error> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).context(ErrorKind::Open(name.clone()))?;
+        load(&text, Some(path.parent().unwrap_or_else(|| Path::new("")))).map_err(|e| {
+            if e.file().is_some() {
+                e
+            } else {
+                e.with_file(name)
+            }
+        })
+    }
+}
+
+/// Loads options from the text of a configuration file, resolving relative paths against `base`
+/// if it is given.
+fn load(text: &str, base: Option<&Path>) -> Result<Options, Error> {
+    let table = text.parse::<Table>().map_err(|e| {
+        let offset = e.span().map_or(0, |span| span.start);
+        let message = e.message().trim_end().to_owned();
+        Error::new(
+            ErrorKind::ConfigSyntax {
+                message,
+                position: Position::at(text, offset),
+            },
+            e,
+        )
+    })?;
+    let mut root = Section {
+        prefix: String::new(),
+        table,
+    };
+    let mut warnings = Vec::new();
+    let path = |path: String| match base {
+        Some(base) => base.join(path),
+        None => PathBuf::from(path),
+    };
+    let mut locale = Locale::new();
+    if let Some(mut section) = root.section("locale")? {
+        if let Some(name) = section.choice("name", LOCALES, "`en`, `de`, or `fr`")? {
+            locale = Locale::builtin(name).unwrap_or_default();
+        }
+        if let Some(file) = section.string("file")? {
+            locale = Locale::load(path(file))?;
+        }
+        section.finish(&mut warnings);
+    }
+    let mut parser = ParserOptions::new().locale(locale.clone());
+    if let Some(mut section) = root.section("parser")? {
+        if let Some(format) = section.string("target_format")? {
+            parser = parser.target_format(format);
+        }
+        if let Some(definitions) = section.string("definitions")? {
+            parser = parser.definitions_file(path(definitions));
+        }
+        if let Some(skip) = section.bool("skip_space_before_arguments")? {
+            parser = parser.skip_space_before_arguments(skip);
+        }
+        if let Some(skip) = section.bool("skip_newline_before_arguments")? {
+            parser = parser.skip_newline_before_arguments(skip);
+        }
+        if let Some(smart) = section.bool("smart_punctuation")? {
+            parser = parser.smart_punctuation(smart);
+        }
+        if let Some(enabled) = section.bool("backslash_break")? {
+            parser = parser.backslash_break(enabled);
+        }
+        if let Some(dedent) = section.bool("dedent_verbatim")? {
+            parser = parser.dedent_verbatim(dedent);
+        }
+        if let Some(strict) = section.bool("strict_arguments")? {
+            parser = parser.strict_arguments(strict);
+        }
+        if let Some(format) = section.string("date_format")? {
+            parser = parser.date_format(format);
+        }
+        section.finish(&mut warnings);
+    }
+    if let Some(mut section) = root.section("lints")? {
+        let mut lints = Lints::new();
+        for &(key, lint) in LINTS {
+            if let Some(level) = section.choice(key, LEVELS, "`allow`, `warn`, or `deny`")? {
+                lints = lints.level(lint, level);
+            }
+        }
+        if let Some(length) = section.usize("max_line_length")? {
+            lints = lints.max_line_length(length);
+        }
+        parser = parser.lints(lints);
+        section.finish(&mut warnings);
+    }
+    let mut html = HtmlOptions::new().locale(locale.clone());
+    if let Some(mut section) = root.section("html")? {
+        let expected = "`skip`, `span`, or `error`";
+        if let Some(policy) = section.choice("unknown_commands", UNKNOWN_COMMANDS, expected)? {
+            html = html.unknown_commands(policy);
+        }
+        if let Some(mut tag) = section.section("unknown_tag")? {
+            html = html.unknown_tag(tag.tag()?);
+            tag.finish(&mut warnings);
+        }
+        if let Some(mut commands) = section.section("commands")? {
+            let names: Vec<String> = commands.table.keys().cloned().collect();
+            for name in names {
+                if let Some(mut tag) = commands.section(&name)? {
+                    html = html.map_command(name, tag.tag()?);
+                    tag.finish(&mut warnings);
+                }
+            }
+        }
+        if let Some(spans) = section.bool("group_spans")? {
+            html = html.group_spans(spans);
+        }
+        if let Some(captions) = section.bool("table_captions")? {
+            html = html.table_captions(captions);
+        }
+        if let Some(depth) = section.usize("toc_depth")? {
+            html = html.toc_depth(depth);
+        }
+        if let Some(level) = section.usize("split_at")? {
+            html = html.split_at(level);
+        }
+        if let Some(sections) = section.bool("sections")? {
+            html = html.sections(sections);
+        }
+        if let Some(number) = section.bool("number_headings")? {
+            html = html.number_headings(number);
+        }
+        if let Some(titles) = section.bool("reference_titles")? {
+            html = html.reference_titles(titles);
+        }
+        if let Some(reset) = section.bool("reset_abbreviations")? {
+            html = html.reset_abbreviations(reset);
+        }
+        let expected = "`allow`, `escape`, or `sanitize`";
+        if let Some(raw) = section.choice("raw", RAW_POLICIES, expected)? {
+            html = html.raw(match raw {
+                "escape" => RawPolicy::Escape,
+                "sanitize" => RawPolicy::Sanitize(Allowlist::basic()),
+                _ => RawPolicy::Allow,
+            });
+        }
+        if let Some(fragment) = section.bool("fragment")? {
+            html = html.fragment(fragment);
+        }
+        if let Some(lang) = section.string("lang")? {
+            html = html.lang(lang);
+        }
+        for stylesheet in section.strings("stylesheets")?.unwrap_or_default() {
+            html = html.stylesheet(stylesheet);
+        }
+        if let Some(style) = section.string("style")? {
+            html = html.style(style);
+        }
+        if let Some(template) = section.string("template")? {
+            html = html.template(template);
+        }
+        if let Some(directory) = section.string("image_directory")? {
+            html = html.image_directory(path(directory));
+        }
+        if let Some(ascii) = section.bool("ascii")? {
+            html = html.ascii(ascii);
+        }
+        let expected = "`auto`, `ltr`, or `rtl`";
+        if let Some(direction) = section.choice("direction", DIRECTIONS, expected)? {
+            html = html.direction(Some(direction));
+        }
+        let expected = "`table` or `gutter`";
+        if let Some(numbers) = section.choice("line_numbers", LINE_NUMBERS, expected)? {
+            html = html.line_numbers(Some(numbers));
+        }
+        if let Some(number) = section.usize("first_line_number")? {
+            html = html.first_line_number(number);
+        }
+        if let Some(stream) = section.bool("stream")? {
+            html = html.stream(stream);
+        }
+        section.finish(&mut warnings);
+    }
+    let mut plain = PlainOptions::new().locale(locale.clone());
+    if let Some(mut section) = root.section("plain")? {
+        if let Some(indent) = section.usize("verbatim_indent")? {
+            plain = plain.verbatim_indent(indent);
+        }
+        if let Some(unit) = section.usize("indent_unit")? {
+            plain = plain.indent_unit(unit);
+        }
+        if let Some(wrap) = section.bool("wrap")? {
+            plain = plain.wrap(wrap);
+        }
+        if let Some(justify) = section.bool("justify")? {
+            plain = plain.justify(justify);
+        }
+        if let Some(width) = section.usize("width")? {
+            plain = plain.width(width);
+        }
+        if let Some(width) = section.usize("max_column_width")? {
+            plain = plain.max_column_width(width);
+        }
+        let expected = "`ascii` or `unicode`";
+        if let Some(borders) = section.choice("table_borders", TABLE_BORDERS, expected)? {
+            plain = plain.table_borders(borders);
+        }
+        if let Some(prefix) = section.string("quote_prefix")? {
+            plain = plain.quote_prefix(prefix);
+        }
+        if let Some(gutter) = section.usize("column_gutter")? {
+            plain = plain.column_gutter(gutter);
+        }
+        if let Some(number) = section.bool("number_headings")? {
+            plain = plain.number_headings(number);
+        }
+        if let Some(reset) = section.bool("reset_abbreviations")? {
+            plain = plain.reset_abbreviations(reset);
+        }
+        #[cfg(feature = "bidi")]
+        {
+            let expected = "`auto`, `ltr`, or `rtl`";
+            if let Some(direction) = section.choice("direction", DIRECTIONS, expected)? {
+                plain = plain.direction(Some(direction));
+            }
+        }
+        #[cfg(feature = "hyphenation")]
+        {
+            let expected = "`en`, `fr`, or `de`";
+            if let Some(language) = section.choice("hyphenate", LANGUAGES, expected)? {
+                plain = plain.hyphenate(Some(Hyphenation::new(language)));
+            }
+        }
+        section.finish(&mut warnings);
+    }
+    let mut ansi = AnsiOptions::from_env().plain(plain.clone());
+    if let Some(mut section) = root.section("ansi")? {
+        if let Some(color) = section.bool("color")? {
+            ansi = ansi.color(color);
+        }
+        if let Some(italic) = section.bool("italic")? {
+            ansi = ansi.italic(italic);
+        }
+        if let Some(style) = section.choice("code_style", CODE_STYLES, "`dim` or `reverse`")? {
+            ansi = ansi.code_style(style);
+        }
+        let expected = "`16`, `256`, or `truecolor`";
+        if let Some(depth) = section.choice("color_depth", COLOR_DEPTHS, expected)? {
+            ansi = ansi.color_depth(depth);
+        }
+        if let Some(hyperlinks) = section.bool("hyperlinks")? {
+            ansi = ansi.hyperlinks(hyperlinks);
+        }
+        section.finish(&mut warnings);
+    }
+    let mut latex = LatexOptions::new();
+    if let Some(mut section) = root.section("latex")? {
+        if let Some(standalone) = section.bool("standalone")? {
+            latex = latex.standalone(standalone);
+        }
+        if let Some(class) = section.string("document_class")? {
+            latex = latex.document_class(class);
+        }
+        if let Some(preamble) = section.string("preamble")? {
+            latex = latex.preamble(preamble);
+        }
+        if let Some(pass_through) = section.bool("unknown_commands")? {
+            latex = latex.unknown_commands(pass_through);
+        }
+        if let Some(reset) = section.bool("reset_abbreviations")? {
+            latex = latex.reset_abbreviations(reset);
+        }
+        section.finish(&mut warnings);
+    }
+    let mut roff = RoffOptions::new().locale(locale);
+    if let Some(mut section) = root.section("roff")? {
+        if let Some(manual) = section.string("section")? {
+            roff = roff.section(manual);
+        }
+        if let Some(number) = section.bool("number_headings")? {
+            roff = roff.number_headings(number);
+        }
+        if let Some(reset) = section.bool("reset_abbreviations")? {
+            roff = roff.reset_abbreviations(reset);
+        }
+        section.finish(&mut warnings);
+    }
+    let mut source = SourceOptions::new();
+    if let Some(mut section) = root.section("source")? {
+        if let Some(width) = section.usize("width")? {
+            source = source.width(width);
+        }
+        if let Some(indent) = section.usize("indent")? {
+            source = source.indent(indent);
+        }
+        section.finish(&mut warnings);
+    }
+    root.finish(&mut warnings);
+    Ok(Options {
+        parser,
+        html,
+        plain,
+        ansi,
+        latex,
+        roff,
+        source,
+        warnings,
+    })
+}
+
+/// The keys of the `lints` table which set the level of a lint.
+const LINTS: &[(&str, Lint)] = &[
+    ("control_character", Lint::ControlCharacter),
+    ("long_line", Lint::LongLine),
+    ("mixed_indentation", Lint::MixedIndentation),
+    ("trailing_whitespace", Lint::TrailingWhitespace),
+    ("percent_comment", Lint::PercentComment),
+];
+
+const LEVELS: &[(&str, Level)] = &[
+    ("allow", Level::Allow),
+    ("warn", Level::Warn),
+    ("deny", Level::Deny),
+];
+
+const UNKNOWN_COMMANDS: &[(&str, UnknownCommandPolicy)] = &[
+    ("skip", UnknownCommandPolicy::Skip),
+    ("span", UnknownCommandPolicy::Span),
+    ("error", UnknownCommandPolicy::Error),
+];
+
+// `RawPolicy` is not `Copy`, since it may hold an `Allowlist`, so the names are mapped to it
+// once chosen.
+const RAW_POLICIES: &[(&str, &str)] = &[
+    ("allow", "allow"),
+    ("escape", "escape"),
+    ("sanitize", "sanitize"),
+];
+
+// `Locale` is not `Copy`, so the names are mapped to the built-in locales once chosen.
+const LOCALES: &[(&str, &str)] = &[("en", "en"), ("de", "de"), ("fr", "fr")];
+
+const TABLE_BORDERS: &[(&str, TableBorders)] = &[
+    ("ascii", TableBorders::Ascii),
+    ("unicode", TableBorders::Unicode),
+];
+
+const LINE_NUMBERS: &[(&str, LineNumbers)] = &[
+    ("table", LineNumbers::Table),
+    ("gutter", LineNumbers::Gutter),
+];
+
+#[cfg(feature = "hyphenation")]
+const LANGUAGES: &[(&str, Language)] = &[
+    ("en", Language::English),
+    ("fr", Language::French),
+    ("de", Language::German),
+];
+
+const DIRECTIONS: &[(&str, Direction)] = &[
+    ("auto", Direction::Auto),
+    ("ltr", Direction::LeftToRight),
+    ("rtl", Direction::RightToLeft),
+];
+
+const CODE_STYLES: &[(&str, CodeStyle)] = &[
+    ("dim", CodeStyle::Dim),
+    ("reverse", CodeStyle::Reverse),
+];
+
+const COLOR_DEPTHS: &[(&str, ColorDepth)] = &[
+    ("16", ColorDepth::Ansi16),
+    ("256", ColorDepth::Ansi256),
+    ("truecolor", ColorDepth::TrueColor),
+];
+
+/// Returns a description of the type of a value, for use in messages.
+fn found(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => format!("the string `{}`", s),
+        Value::Array(_) => "an array".into(),
+        Value::Table(_) => "a table".into(),
+        Value::Integer(n) => format!("the integer `{}`", n),
+        Value::Float(n) => format!("the float `{}`", n),
+        Value::Boolean(b) => format!("the boolean `{}`", b),
+        Value::Datetime(ref datetime) => format!("the datetime `{}`", datetime),
+    }
+}
+
+/// A table of the configuration file, from which keys are taken as they are read, so that those
+/// which remain are unknown.
+struct Section {
+    /// The dotted name of the table, followed by a dot, or empty for the root table.
+    prefix: String,
+    table: Table,
+}
+
+impl Section {
+    /// Removes a key, returning its full name and its value, if it is present.
+    fn take(&mut self, key: &str) -> Option<(String, Value)> {
+        let value = self.table.remove(key)?;
+        Some((format!("{}{}", self.prefix, key), value))
+    }
+
+    /// Returns the error for a key whose value is not of the expected type.
+    fn invalid(key: String, expected: &'static str, value: &Value) -> Error {
+        ErrorKind::InvalidConfig {
+            key,
+            expected,
+            found: found(value),
+        }
+        .into()
+    }
+
+    /// Takes a table.
+    fn section(&mut self, key: &str) -> Result<Option<Section>, Error> {
+        match self.take(key) {
+            Some((key, Value::Table(table))) => Ok(Some(Section {
+                prefix: format!("{}.", key),
+                table,
+            })),
+            Some((key, value)) => Err(Section::invalid(key, "a table", &value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes a boolean.
+    fn bool(&mut self, key: &str) -> Result<Option<bool>, Error> {
+        match self.take(key) {
+            Some((_, Value::Boolean(value))) => Ok(Some(value)),
+            Some((key, value)) => Err(Section::invalid(key, "a boolean", &value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes a non-negative integer.
+    fn usize(&mut self, key: &str) -> Result<Option<usize>, Error> {
+        match self.take(key) {
+            Some((key, Value::Integer(value))) => match usize::try_from(value) {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(Section::invalid(
+                    key,
+                    "a non-negative integer",
+                    &Value::Integer(value),
+                )),
+            },
+            Some((key, value)) => Err(Section::invalid(key, "a non-negative integer", &value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes a string.
+    fn string(&mut self, key: &str) -> Result<Option<String>, Error> {
+        match self.take(key) {
+            Some((_, Value::String(value))) => Ok(Some(value)),
+            Some((key, value)) => Err(Section::invalid(key, "a string", &value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes an array of strings. A single string is also accepted, as an array of one.
+    fn strings(&mut self, key: &str) -> Result<Option<Vec<String>>, Error> {
+        let expected = "an array of strings";
+        match self.take(key) {
+            Some((_, Value::String(value))) => Ok(Some(vec![value])),
+            Some((key, Value::Array(values))) => values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(value) => Ok(value),
+                    value => Err(Section::invalid(key.clone(), expected, &value)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Some),
+            Some((key, value)) => Err(Section::invalid(key, expected, &value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes a string which names one of the given choices, returning the chosen value.
+    fn choice<T: Copy>(
+        &mut self,
+        key: &str,
+        choices: &[(&str, T)],
+        expected: &'static str,
+    ) -> Result<Option<T>, Error> {
+        match self.take(key) {
+            Some((key, value)) => {
+                let choice = value
+                    .as_str()
+                    .and_then(|name| choices.iter().find(|&&(choice, _)| choice == name));
+                match choice {
+                    Some(&(_, choice)) => Ok(Some(choice)),
+                    None => Err(Section::invalid(key, expected, &value)),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Takes the keys of a table describing an HTML element: `tag`, the name of the element,
+    /// which is required, `class`, one or more classes, `attributes`, a table of attribute
+    /// values, `block`, and `replace`, as for the methods of `Tag`.
+    fn tag(&mut self) -> Result<Tag, Error> {
+        let name = match self.string("tag")? {
+            Some(name) => name,
+            None => {
+                return Err(ErrorKind::InvalidConfig {
+                    key: format!("{}tag", self.prefix),
+                    expected: "the name of an element",
+                    found: "nothing".into(),
+                }
+                .into())
+            }
+        };
+        let mut tag = Tag::new(name);
+        for class in self.strings("class")?.unwrap_or_default() {
+            tag = tag.class(class);
+        }
+        if let Some(mut attributes) = self.section("attributes")? {
+            let names: Vec<String> = attributes.table.keys().cloned().collect();
+            for name in names {
+                if let Some(value) = attributes.string(&name)? {
+                    tag = tag.attr(name, value);
+                }
+            }
+        }
+        if self.bool("block")? == Some(true) {
+            tag = tag.block();
+        }
+        if let Some(html) = self.string("replace")? {
+            tag = tag.replace(html);
+        }
+        Ok(tag)
+    }
+
+    /// Reports each key which has not been taken as unknown.
+    fn finish(self, warnings: &mut Vec<Warning>) {
+        for key in self.table.keys() {
+            warnings.push(Warning::UnknownConfigKey(format!("{}{}", self.prefix, key)));
+        }
+    }
+}
@@ -0,0 +1,433 @@
+//! Conversion of a document from one call: parsing, resolving citations, and rendering.
+//!
+//! `convert` reads a document, parses it with the parser options of an `Options`, resolves its
+//! citations as with `bibliography::resolve`, and renders it in an `OutputFormat` with the options
+//! of that renderer, returning the warnings of every stage in a `Report`. `convert_file` reads a
+//! named file, and `convert_str_to_string` converts a string to a string.
+//!
+//! HTML is rendered as the document is parsed, as with `html::stream`, rather than once the whole
+//! document has been parsed, if the document uses none of the features which streaming lacks,
+//! such as `\tableofcontents` and `\cite`, unless `HtmlOptions::stream` says otherwise. The
+//! command-line tool renders through `convert_file` and `convert`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::Instant;
+
+use ast::{Document, Span};
+use bibliography;
+use diagnostics::{Diagnostic, Diagnostics};
+use errors::{Error, ErrorKind, ResultExt, Warning};
+use parser::{Parser, ParserOptions};
+use render::ansi::{self, AnsiOptions};
+use render::html::{self, HtmlOptions, HtmlRenderer};
+use render::latex::{self, LatexOptions};
+#[cfg(feature = "serde")]
+use render::pandoc;
+use render::plain::{self, PlainOptions};
+use render::roff::{self, RoffOptions};
+use render::source::SourceOptions;
+use render::{markdown, FmtWriter, LimitWriter, RenderReport};
+use stats::Stats;
+
+/// The options of the parser and of each renderer. They are loaded from a configuration file by
+/// the functions of the `config` module, which requires the `serde` feature.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub parser: ParserOptions,
+    pub html: HtmlOptions,
+    pub plain: PlainOptions,
+    pub ansi: AnsiOptions,
+    pub latex: LatexOptions,
+    pub roff: RoffOptions,
+    pub source: SourceOptions,
+    pub(crate) warnings: Vec<Warning>,
+}
+
+impl Options {
+    /// Constructs a new `Options` with the default settings of each part.
+    pub fn new() -> Options {
+        Options::default()
+    }
+
+    /// Returns the warnings about the configuration file the options were loaded from, such as
+    /// unknown keys.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+impl Document {
+    /// Returns the paths of the files other than its input which converting the document reads,
+    /// for build systems which convert it again when any of them change: the bibliography files
+    /// named by its `\bibliography` commands, relative to `directory`, as `convert_file` reads
+    /// them for an input in that directory. They are in the order in which they are first named,
+    /// and files which do not exist are included, so that converting fails until they do.
+    ///
+    /// The citations of the document must not have been resolved, since resolving them removes
+    /// the `\bibliography` commands.
+    pub fn dependencies<P: AsRef<Path>>(&self, directory: P) -> Vec<PathBuf> {
+        bibliography::files(self, directory)
+    }
+}
+
+/// A format which `convert` renders to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OutputFormat {
+    /// HTML, with the options given by `Options::html`.
+    Html,
+    /// Plain text, with the options given by `Options::plain`.
+    Plain,
+    /// Text styled with ANSI escape sequences, with the options given by `Options::ansi`.
+    Ansi,
+    /// Markdown.
+    Markdown,
+    /// LaTeX, with the options given by `Options::latex`.
+    Latex,
+    /// A manual page, with the options given by `Options::roff`.
+    Roff,
+    /// Pandoc's JSON AST. Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    Pandoc,
+}
+
+/// Every format, in the order in which they are declared.
+const FORMATS: &[OutputFormat] = &[
+    OutputFormat::Html,
+    OutputFormat::Plain,
+    OutputFormat::Ansi,
+    OutputFormat::Markdown,
+    OutputFormat::Latex,
+    OutputFormat::Roff,
+    #[cfg(feature = "serde")]
+    OutputFormat::Pandoc,
+];
+
+impl OutputFormat {
+    /// Returns every format which is available.
+    pub fn all() -> &'static [OutputFormat] {
+        FORMATS
+    }
+
+    /// Returns the format with the given name, as returned by `name`, or `None` if there is no
+    /// such format.
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        FORMATS.iter().copied().find(|format| format.name() == name)
+    }
+
+    /// Returns the name of the format, such as `html` or `pandoc`, as given to the `--to` option
+    /// of the command-line tool.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Html => "html",
+            OutputFormat::Plain => "plain",
+            OutputFormat::Ansi => "ansi",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Latex => "latex",
+            OutputFormat::Roff => "roff",
+            #[cfg(feature = "serde")]
+            OutputFormat::Pandoc => "pandoc",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The diagnostics and statistics of a successful conversion.
+#[derive(Debug, Default)]
+pub struct Report {
+    diagnostics: Diagnostics,
+    stats: Stats,
+}
+
+impl Report {
+    /// Returns the warnings of the parser, followed by those of the renderer. Each has the name
+    /// of the input as its `file` if the input has a name, unless it occurred in another file,
+    /// such as a definitions file. The warnings of the HTML and plain text renderers are
+    /// positioned at the start of the block in which they occurred, unless HTML is always
+    /// streamed, as with `RenderReport::diagnostics`.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Returns the diagnostics, as with `diagnostics`.
+    pub fn into_diagnostics(self) -> Diagnostics {
+        self.diagnostics
+    }
+
+    /// Returns the counts of the work done by the parser, as with `Parser::stats`. If
+    /// `ParserOptions::timings` is set, the time taken to resolve citations and to render is
+    /// also given. When HTML is streamed, rendering is interleaved with parsing, and its time is
+    /// that of the whole conversion less the time spent parsing.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Adds warnings to the report, naming the input if it has a name.
+    fn warn(&mut self, warnings: &[Warning], name: Option<&str>) {
+        for warning in warnings {
+            self.push(Diagnostic::warning(warning.clone()), name);
+        }
+    }
+
+    /// Adds the warnings of a renderer to the report, positioned in the input if it is known, as
+    /// with `RenderReport::diagnostics`, and naming the input if it has a name.
+    fn warn_rendered(&mut self, rendered: &RenderReport, input: Option<&str>, name: Option<&str>) {
+        let diagnostics = match input {
+            Some(input) => rendered.diagnostics(input),
+            None => rendered.warnings().iter().cloned().map(Diagnostic::warning).collect(),
+        };
+        for diagnostic in diagnostics {
+            self.push(diagnostic, name);
+        }
+    }
+
+    /// Adds a diagnostic to the report, naming the input if it has a name.
+    fn push(&mut self, mut diagnostic: Diagnostic, name: Option<&str>) {
+        if let Some(name) = name {
+            diagnostic.file.get_or_insert_with(|| name.into());
+        }
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+/// Parses a document from `input`, resolves its citations, and renders it in the given format to
+/// `output`, which is flushed once the document has been rendered. The bibliography files named
+/// by `\bibliography` are read relative to the current directory.
+///
+/// Nothing is written until the whole document has been parsed, other than when streaming HTML,
+/// so a document which fails to parse leaves the output untouched. Unless `HtmlOptions::stream`
+/// always streams HTML, the whole input is read first, to decide whether to stream it and to
+/// position the warnings of the renderer. The warnings of the parser are lost if the document
+/// fails to parse, as they are with `Parser::parse`; use the parser directly to keep them. Output
+/// beyond the `Limits::max_output_size` of the parser options fails with
+/// `ErrorKind::LimitExceeded`, leaving what was written before it.
+pub fn convert<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    format: OutputFormat,
+    options: &Options,
+) -> Result<Report, Error> {
+    let mut input = input;
+    let mut source = Vec::new();
+    let stream = streams(&mut input, &mut source, format, options);
+    let parser = Parser::with_options((&source[..]).chain(input), options.parser.clone());
+    run(parser, &source, (None, Path::new("")), output, format, stream, options)
+}
+
+/// Converts the named file, as with `convert`. Errors and warnings are attached to the name of
+/// the file, and the bibliography files it names are read relative to the directory containing
+/// it.
+pub fn convert_file<P: AsRef<Path>, W: Write>(
+    path: P,
+    output: W,
+    format: OutputFormat,
+    options: &Options,
+) -> Result<Report, Error> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let file = File::open(path).context(ErrorKind::Open(name.clone()))?;
+    let mut input = BufReader::new(file);
+    let mut source = Vec::new();
+    let stream = streams(&mut input, &mut source, format, options);
+    let parser = Parser::with_options((&source[..]).chain(input), options.parser.clone())
+        .with_name(name.clone());
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    run(parser, &source, (Some(&name), directory), output, format, stream, options)
+}
+
+/// Returns whether a document is rendered as it is parsed: if it is rendered as HTML, as set by
+/// `HtmlOptions::stream`, or otherwise if `html::is_streamable` allows it and no definitions
+/// file could define commands which need the whole document. Unless HTML is always streamed, the
+/// whole of `input` is read into `source`, to decide, and to give the positions of the warnings
+/// of the renderer. It is to be parsed ahead of the rest of `input`; a read which fails leaves
+/// the rest to fail again in the parser, which reports the error with its position.
+fn streams<R: BufRead>(
+    input: &mut R,
+    source: &mut Vec<u8>,
+    format: OutputFormat,
+    options: &Options,
+) -> bool {
+    let html = format == OutputFormat::Html;
+    let stream = options.html.get_stream().filter(|_| html);
+    if stream == Some(true) {
+        return true;
+    }
+    input.read_to_end(source).is_ok()
+        && html
+        && stream.is_none()
+        && options.parser.get_definitions_file().is_none()
+        && html::is_streamable(source, &options.html)
+}
+
+/// Resolves the citations of a document which has already been parsed, such as one read from a
+/// cache, and renders it, as `convert_file` does for the document parsed from the named file. The
+/// report has only the warnings of the renderer, and no counts of the work done by the parser.
+pub fn convert_document<P: AsRef<Path>, W: Write>(
+    mut document: Document,
+    path: P,
+    output: W,
+    format: OutputFormat,
+    options: &Options,
+) -> Result<Report, Error> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    limited(output, options, |output| {
+        let mut report = Report::default();
+        let rendered = resolve_and_render(
+            &mut document,
+            &[],
+            directory,
+            output,
+            format,
+            options,
+            &mut report,
+        )?;
+        report.warn_rendered(&rendered, None, Some(&name));
+        output.flush().context(ErrorKind::Write)?;
+        Ok(report)
+    })
+}
+
+/// Converts a document given as a string, as with `convert`, returning the output as a `String`
+/// along with the report.
+pub fn convert_str_to_string(
+    input: &str,
+    format: OutputFormat,
+    options: &Options,
+) -> Result<(String, Report), Error> {
+    let mut output = String::new();
+    let report = convert(input.as_bytes(), FmtWriter::new(&mut output), format, options)?;
+    Ok((output, report))
+}
+
+/// Runs each stage of a conversion, failing with `Limit::Output` once the output passes
+/// `Limits::max_output_size`. `source` is the input read ahead by `streams`, and `paths` are as
+/// for `run_stages`.
+fn run<R: BufRead, W: Write>(
+    parser: Parser<R>,
+    source: &[u8],
+    paths: (Option<&str>, &Path),
+    output: W,
+    format: OutputFormat,
+    stream: bool,
+    options: &Options,
+) -> Result<Report, Error> {
+    // Nothing is read ahead when HTML is always streamed, so the positions are not known.
+    let input = str::from_utf8(source).ok().filter(|input| !input.is_empty());
+    limited(output, options, |output| {
+        run_stages(parser, input, paths, output, format, stream, options)
+    })
+}
+
+/// Runs `stages` with the output limited to `Limits::max_output_size`.
+fn limited<W, F>(output: W, options: &Options, stages: F) -> Result<Report, Error>
+where
+    W: Write,
+    F: FnOnce(&mut LimitWriter<W>) -> Result<Report, Error>,
+{
+    let limit = options.parser.get_limits().get_max_output_size();
+    let mut output = LimitWriter::new(output, limit);
+    let result = stages(&mut output);
+    output.check(result)
+}
+
+/// Runs each stage of a conversion, rendering HTML as it is parsed if `stream` is set. The
+/// warnings of the renderer are positioned in `input`, if it is the whole input. `paths` are the
+/// name of the input, if it has one, and the directory in which to find the files it names.
+fn run_stages<R: BufRead, W: Write>(
+    parser: Parser<R>,
+    input: Option<&str>,
+    (name, directory): (Option<&str>, &Path),
+    mut output: W,
+    format: OutputFormat,
+    stream: bool,
+    options: &Options,
+) -> Result<Report, Error> {
+    let mut report = Report::default();
+    let timings = options.parser.get_timings();
+    let start = Instant::now();
+    let mut blocks = parser.into_blocks();
+    if stream {
+        let rendered = html::stream_blocks(&mut blocks, &mut output, &options.html);
+        report.warn(blocks.warnings(), name);
+        report.warn_rendered(&rendered?, input, name);
+        report.stats = blocks.stats();
+        if timings {
+            let parse = report.stats.timings.parse.unwrap_or_default();
+            report.stats.timings.render = Some(start.elapsed().saturating_sub(parse));
+        }
+    } else {
+        let parsed = blocks.parse_spanned();
+        report.warn(blocks.warnings(), name);
+        let (mut document, spans) = parsed?;
+        report.stats = blocks.stats();
+        let output = &mut output;
+        let rendered = resolve_and_render(
+            &mut document,
+            &spans,
+            directory,
+            output,
+            format,
+            options,
+            &mut report,
+        )?;
+        report.warn_rendered(&rendered, input, name);
+    }
+    output.flush().context(ErrorKind::Write)?;
+    Ok(report)
+}
+
+/// Resolves the citations of a parsed document and renders it, adding the time taken to the
+/// report, if `ParserOptions::timings` is set, and returning the report of the renderer.
+fn resolve_and_render<W: Write>(
+    document: &mut Document,
+    spans: &[Span],
+    directory: &Path,
+    output: &mut W,
+    format: OutputFormat,
+    options: &Options,
+    report: &mut Report,
+) -> Result<RenderReport, Error> {
+    let start = Instant::now();
+    bibliography::resolve(document, directory, options.parser.get_locale())?;
+    let resolved = Instant::now();
+    let rendered = render(document, spans, format, options, output)?;
+    if options.parser.get_timings() {
+        report.stats.timings.resolve = Some(resolved - start);
+        report.stats.timings.render = Some(resolved.elapsed());
+    }
+    Ok(rendered)
+}
+
+/// Renders a parsed document in the given format. The warnings of the formats which map their
+/// output to the input are given the spans of the top-level nodes, if there are any.
+fn render<W: Write>(
+    document: &Document,
+    spans: &[Span],
+    format: OutputFormat,
+    options: &Options,
+    out: &mut W,
+) -> Result<RenderReport, Error> {
+    match format {
+        OutputFormat::Html => {
+            let renderer = HtmlRenderer::new(options.html.clone());
+            Ok(renderer.render_mapped(document, spans, out)?.0)
+        }
+        OutputFormat::Plain => Ok(plain::render_mapped(document, spans, &options.plain, out)?.0),
+        OutputFormat::Ansi => ansi::render(document, &options.ansi, out),
+        OutputFormat::Markdown => markdown::render(document, out),
+        OutputFormat::Latex => latex::render(document, &options.latex, out),
+        OutputFormat::Roff => roff::render(document, &options.roff, out),
+        #[cfg(feature = "serde")]
+        OutputFormat::Pandoc => pandoc::render(document, out),
+    }
+}
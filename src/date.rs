@@ -0,0 +1,212 @@
+//! Calendar dates, as written by `\today` and `\formatdate`.
+//!
+//! Dates are formatted by a subset of the conversions of `strftime`:
+//!
+//! | Conversion | Meaning                                   | Example      |
+//! |------------|-------------------------------------------|--------------|
+//! | `%Y`       | The year                                  | `2024`       |
+//! | `%y`       | The last two digits of the year           | `24`         |
+//! | `%m`       | The month, as two digits                  | `05`         |
+//! | `%B`       | The name of the month                     | `May`        |
+//! | `%b`       | The abbreviated name of the month         | `May`        |
+//! | `%-m`      | The month, without padding                | `5`          |
+//! | `%d`       | The day of the month, as two digits       | `01`         |
+//! | `%e`       | The day of the month, padded with a space | ` 1`         |
+//! | `%-d`      | The day of the month, without padding     | `1`          |
+//! | `%A`       | The name of the day of the week           | `Wednesday`  |
+//! | `%a`       | The abbreviated name of the day           | `Wed`        |
+//! | `%j`       | The day of the year, as three digits      | `122`        |
+//! | `%F`       | The same as `%Y-%m-%d`                    | `2024-05-01` |
+//! | `%%`       | A `%`                                     | `%`          |
+//!
+//! Any other character is written as it is. Names are those of a `Locale`, English by default,
+//! and the calendar is the proleptic Gregorian calendar.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use locale::Locale;
+
+/// The format of `\today` in English, unless another is given by `ParserOptions::date_format`,
+/// such as `May  1, 2024`. The renderers collapse the space which pads the day.
+pub const DEFAULT_FORMAT: &str = "%B %e, %Y";
+
+/// The environment variable which, if it holds a number of seconds since the Unix epoch, gives
+/// the date of `\today` in place of the clock, so that builds are reproducible.
+#[cfg(feature = "std")]
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// A day of the proleptic Gregorian calendar.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Date {
+    year: i32,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// Returns the date with the given year, month, and day of the month, each counting from 1,
+    /// or `None` if there is no such day.
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        if month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Date { year, month, day })
+    }
+
+    /// Parses a date written as `YYYY-MM-DD`, such as `2024-05-01`, with surrounding whitespace
+    /// ignored. Returns `None` if it is written any other way, or there is no such day.
+    pub fn parse(s: &str) -> Option<Date> {
+        let mut parts = s.trim().split('-');
+        let (year, month, day) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(year), Some(month), Some(day), None) => (year, month, day),
+            _ => return None,
+        };
+        let digits = |part: &str, len: usize| {
+            part.len() == len && part.bytes().all(|b| b.is_ascii_digit())
+        };
+        if !digits(year, 4) || !digits(month, 2) || !digits(day, 2) {
+            return None;
+        }
+        Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+    }
+
+    /// Returns the date, in UTC, of the given number of seconds since the Unix epoch.
+    pub fn from_unix_time(seconds: i64) -> Date {
+        // The days since 1 March of the year 0, in eras of 400 years, from Howard Hinnant's
+        // `civil_from_days`.
+        let days = seconds.div_euclid(86_400) + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date {
+            year: year as i32,
+            month,
+            day,
+        }
+    }
+
+    /// Returns the date given by the value of `SOURCE_DATE_EPOCH`, a number of seconds since the
+    /// Unix epoch, or `None` if it is not a number.
+    pub fn from_source_date_epoch(value: &str) -> Option<Date> {
+        value.trim().parse().ok().map(Date::from_unix_time)
+    }
+
+    /// Returns the current date, in UTC, or the date given by the `SOURCE_DATE_EPOCH`
+    /// environment variable if it is set to a number of seconds since the Unix epoch. Requires
+    /// the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn today() -> Date {
+        use std::env;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        if let Some(date) = env::var(SOURCE_DATE_EPOCH)
+            .ok()
+            .and_then(|value| Date::from_source_date_epoch(&value))
+        {
+            return date;
+        }
+        let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        };
+        Date::from_unix_time(seconds)
+    }
+
+    /// Returns the year.
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    /// Returns the month, from 1 to 12.
+    pub fn month(&self) -> u32 {
+        self.month
+    }
+
+    /// Returns the day of the month, from 1.
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    /// Returns the day of the year, from 1.
+    pub fn ordinal(&self) -> u32 {
+        (1..self.month).map(|month| days_in_month(self.year, month)).sum::<u32>() + self.day
+    }
+
+    /// Returns the day of the week, from 0 for Monday to 6 for Sunday.
+    pub fn weekday(&self) -> u32 {
+        // The days since the Unix epoch, from Howard Hinnant's `days_from_civil`; the epoch was
+        // a Thursday.
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + i64::from(self.day)
+            - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        (days + 3).rem_euclid(7) as u32
+    }
+
+    /// Formats the date by the conversions described in the module documentation, with the names
+    /// of the given locale. Returns `None` if the format has a conversion which is not one of
+    /// them, or ends with a lone `%`.
+    pub fn format(&self, format: &str, locale: &Locale) -> Option<String> {
+        let mut out = String::with_capacity(format.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            // Writing to a `String` cannot fail.
+            let _ = match chars.next()? {
+                'Y' => write!(out, "{:04}", self.year),
+                'y' => write!(out, "{:02}", self.year.rem_euclid(100)),
+                'm' => write!(out, "{:02}", self.month),
+                'B' => write!(out, "{}", locale.month(self.month, false)),
+                'b' => write!(out, "{}", locale.month(self.month, true)),
+                'd' => write!(out, "{:02}", self.day),
+                'e' => write!(out, "{:2}", self.day),
+                '-' => match chars.next()? {
+                    'd' => write!(out, "{}", self.day),
+                    'm' => write!(out, "{}", self.month),
+                    _ => return None,
+                },
+                'A' => write!(out, "{}", locale.weekday(self.weekday(), false)),
+                'a' => write!(out, "{}", locale.weekday(self.weekday(), true)),
+                'j' => write!(out, "{:03}", self.ordinal()),
+                'F' => write!(out, "{:04}-{:02}-{:02}", self.year, self.month, self.day),
+                '%' => write!(out, "%"),
+                _ => return None,
+            };
+        }
+        Some(out)
+    }
+}
+
+/// Returns whether a year is a leap year.
+fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Returns the number of days in a month, from 1 to 12, of a year.
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
@@ -0,0 +1,372 @@
+//! A collection of the errors, warnings, and notes produced while processing a document.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::slice;
+
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use errors::{Error, ErrorKind, Position, Warning};
+
+/// How serious a diagnostic is. Severities are ordered from least to most serious.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "lowercase"))]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The problem reported by a diagnostic.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    Error(ErrorKind),
+    Warning(Warning),
+    /// A note, which is described only by its message.
+    Note,
+}
+
+impl DiagnosticKind {
+    /// Returns the code of the error or warning, as given by `ErrorKind::code` or
+    /// `Warning::code`, or `None` for a note.
+    pub fn code(&self) -> Option<&'static str> {
+        match *self {
+            DiagnosticKind::Error(ref kind) => Some(kind.code()),
+            DiagnosticKind::Warning(ref warning) => Some(warning.code()),
+            DiagnosticKind::Note => None,
+        }
+    }
+}
+
+/// A single error, warning, or note, along with where it occurred.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub message: String,
+    /// The name of the file in which the problem occurred, if known.
+    pub file: Option<String>,
+    /// The position in the input at which the problem occurred, if known.
+    pub position: Option<Position>,
+    /// Other positions relevant to the problem, each with a message describing its relevance,
+    /// such as where an unclosed construct started.
+    pub related: Vec<(Position, String)>,
+}
+
+impl Diagnostic {
+    /// Constructs a new `Diagnostic` with severity `Error` for the given error. For an unclosed
+    /// `\verbatim` command, the position at which its delimiter was last looked for is related.
+    pub fn error(error: &Error) -> Diagnostic {
+        let related = match *error.kind() {
+            ErrorKind::UnclosedVerbatim { end, .. } => vec![(end, "not closed here".into())],
+            _ => Vec::new(),
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            kind: DiagnosticKind::Error(error.kind().clone()),
+            message: error.kind().to_string(),
+            file: error.file().map(String::from),
+            position: error.kind().position(),
+            related,
+        }
+    }
+
+    /// Constructs a new `Diagnostic` with severity `Warning` for the given warning.
+    pub fn warning(warning: Warning) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: warning.to_string(),
+            file: None,
+            position: warning.position(),
+            kind: DiagnosticKind::Warning(warning),
+            related: Vec::new(),
+        }
+    }
+
+    /// Constructs a new `Diagnostic` with severity `Note` and the given message.
+    pub fn note<S: Into<String>>(message: S, position: Option<Position>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Note,
+            kind: DiagnosticKind::Note,
+            message: message.into(),
+            file: None,
+            position,
+            related: Vec::new(),
+        }
+    }
+
+    /// Adds a related position, with a message describing its relevance.
+    pub fn with_related<S: Into<String>>(mut self, position: Position, message: S) -> Diagnostic {
+        self.related.push((position, message.into()));
+        self
+    }
+
+    /// Writes the location of the diagnostic, or of one of its related positions.
+    fn write_location(&self, f: &mut fmt::Formatter, position: Position) -> fmt::Result {
+        write!(f, "\n --> {}:{}", self.file.as_deref().unwrap_or("<input>"), position)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind.code() {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity, code, self.message)?,
+            None => write!(f, "{}: {}", self.severity, self.message)?,
+        }
+        if let Some(position) = self.position {
+            self.write_location(f, position)?;
+        }
+        for &(position, ref message) in &self.related {
+            write!(f, "\nnote: {}", message)?;
+            self.write_location(f, position)?;
+        }
+        Ok(())
+    }
+}
+
+/// The default for `Diagnostics::error_limit`.
+const DEFAULT_ERROR_LIMIT: usize = 50;
+
+/// A collection of diagnostics, such as the warnings of a parser and a renderer along with any
+/// error which stopped them.
+///
+/// The number of errors kept is limited, so that a single early mistake does not bury the
+/// problems after it under follow-on errors. Once the limit is reached, further errors are
+/// suppressed, and a note saying how many were suppressed is added in their place. Warnings and
+/// notes are not limited.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+    error_limit: usize,
+    /// The number of errors which have been kept.
+    errors: usize,
+    /// The number of errors which have been suppressed.
+    suppressed: usize,
+    /// The index in `entries` of the note about suppressed errors, once there are any.
+    limit_note: Option<usize>,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Diagnostics {
+        Diagnostics {
+            entries: Vec::new(),
+            error_limit: DEFAULT_ERROR_LIMIT,
+            errors: 0,
+            suppressed: 0,
+            limit_note: None,
+        }
+    }
+}
+
+impl Diagnostics {
+    /// Constructs a new, empty `Diagnostics`.
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Sets the number of errors kept before further errors are suppressed. Defaults to 50.
+    pub fn error_limit(mut self, limit: usize) -> Diagnostics {
+        self.error_limit = limit;
+        self
+    }
+
+    /// Returns the number of errors kept before further errors are suppressed.
+    pub fn get_error_limit(&self) -> usize {
+        self.error_limit
+    }
+
+    /// Returns whether the error limit has been reached, so that any further errors will be
+    /// suppressed. Code which recovers from errors to report as many as it can should stop once
+    /// this is the case, keeping what it has produced so far.
+    pub fn error_limit_reached(&self) -> bool {
+        self.errors >= self.error_limit
+    }
+
+    /// Returns the number of errors which have been suppressed because of the error limit.
+    pub fn suppressed(&self) -> usize {
+        self.suppressed
+    }
+
+    /// Adds a diagnostic. An error is suppressed instead if the error limit has been reached.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        if diagnostic.severity != Severity::Error {
+            self.entries.push(diagnostic);
+        } else if !self.error_limit_reached() {
+            self.errors += 1;
+            self.entries.push(diagnostic);
+        } else {
+            self.suppressed += 1;
+            let message = format!(
+                "too many errors, aborting: {} more suppressed after the first {}",
+                self.suppressed, self.error_limit
+            );
+            match self.limit_note {
+                Some(index) => self.entries[index].message = message,
+                None => {
+                    self.limit_note = Some(self.entries.len());
+                    self.entries.push(Diagnostic::note(message, None));
+                }
+            }
+        }
+    }
+
+    /// Raises every warning to severity `Error`, as for a `--deny-warnings` flag. Raised warnings
+    /// are not counted against the error limit.
+    pub fn deny_warnings(&mut self) {
+        for diagnostic in &mut self.entries {
+            if diagnostic.severity == Severity::Warning {
+                diagnostic.severity = Severity::Error;
+            }
+        }
+    }
+
+    /// Adds a diagnostic for the given error.
+    pub fn push_error(&mut self, error: &Error) {
+        self.push(Diagnostic::error(error));
+    }
+
+    /// Returns the diagnostics sorted by position, with those without a position last, followed by
+    /// the note about suppressed errors, if any. Diagnostics at the same position are in the order
+    /// in which they were added.
+    pub fn sorted(&self) -> Vec<&Diagnostic> {
+        let mut sorted: Vec<_> = self.entries.iter().enumerate().collect();
+        sorted.sort_by_key(|&(i, d)| {
+            (Some(i) == self.limit_note, d.position.is_none(), d.position)
+        });
+        sorted.into_iter().map(|(_, d)| d).collect()
+    }
+
+    /// Returns an iterator over the diagnostics in the order in which they were added.
+    pub fn iter(&self) -> slice::Iter<'_, Diagnostic> {
+        self.entries.iter()
+    }
+
+    /// Returns the number of diagnostics.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether there are no diagnostics.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of diagnostics with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.entries
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    /// Returns whether any diagnostic has severity `Error`.
+    pub fn has_errors(&self) -> bool {
+        self.entries.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    /// Returns the diagnostics as a JSON array, sorted as by `sorted`. Each diagnostic is an
+    /// object with the fields:
+    ///
+    /// - `severity`: `"error"`, `"warning"`, or `"note"`;
+    /// - `code`: the code of the error or warning, such as `"E0102"`, or `null` for a note;
+    /// - `message`: the message, as written by `Display`;
+    /// - `file`: the name of the file, or `null` if it is not known;
+    /// - `position`: an object with the one-based `line` and `column` and the zero-based byte
+    ///   `offset` at which the problem occurred, or `null` if it is not known;
+    /// - `related`: an array of objects with a `message` and a `position`, as above.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        // Serializing strings, numbers, and derived structs to a `String` cannot fail.
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Diagnostics are serialized as described by `Diagnostics::to_json`.
+#[cfg(feature = "serde")]
+impl Serialize for Diagnostic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Related<'a> {
+            message: &'a str,
+            position: Position,
+        }
+
+        let related: Vec<_> = self
+            .related
+            .iter()
+            .map(|&(position, ref message)| Related { message, position })
+            .collect();
+        let mut state = serializer.serialize_struct("Diagnostic", 6)?;
+        state.serialize_field("severity", &self.severity)?;
+        state.serialize_field("code", &self.kind.code())?;
+        state.serialize_field("message", &self.message)?;
+        state.serialize_field("file", &self.file)?;
+        state.serialize_field("position", &self.position)?;
+        state.serialize_field("related", &related)?;
+        state.end()
+    }
+}
+
+/// A collection of diagnostics is serialized as a sequence, sorted as by `Diagnostics::sorted`.
+#[cfg(feature = "serde")]
+impl Serialize for Diagnostics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.sorted())
+    }
+}
+
+impl Extend<Diagnostic> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        for diagnostic in iter {
+            self.push(diagnostic);
+        }
+    }
+}
+
+/// Warnings, such as those of a `Parser` or a `RenderReport`, are added with severity `Warning`.
+impl Extend<Warning> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Warning>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(Diagnostic::warning));
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The diagnostics are written sorted by position and separated by blank lines, followed by a
+/// count of the errors and warnings.
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in self.sorted() {
+            writeln!(f, "{}\n", diagnostic)?;
+        }
+        let count = |severity, name| match self.count(severity) {
+            1 => format!("1 {}", name),
+            n => format!("{} {}s", n, name),
+        };
+        write!(
+            f,
+            "{}, {}",
+            count(Severity::Error, "error"),
+            count(Severity::Warning, "warning")
+        )
+    }
+}
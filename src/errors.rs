@@ -1,66 +1,987 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use core::error;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::io;
-use std::fmt;
 
-use failure::{Backtrace, Context, Fail};
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+
+use ast::Span;
+use limits::Limit;
+#[cfg(feature = "encoding")]
+use tokenize::{DecodeError, Encoding};
+
+/// The underlying cause of an error, such as an `io::Error`.
+type Source = Box<dyn error::Error + Send + Sync + 'static>;
 
 #[derive(Debug)]
 pub struct Error {
-    inner: Context<ErrorKind>,
+    // Boxed, so that results are no larger than a pointer in the error case.
+    inner: Box<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    kind: ErrorKind,
+    source: Option<Source>,
+    file: Option<String>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Backtrace,
 }
 
 impl Error {
-    pub fn kind(&self) -> ErrorKind {
-        *self.inner.get_context()
+    /// Constructs a new `Error` of the given kind, caused by `source`, which may be another error
+    /// or a message.
+    pub fn new<E: Into<Source>>(kind: ErrorKind, source: E) -> Error {
+        Error::with_source(kind, Some(source.into()))
+    }
+
+    fn with_source(kind: ErrorKind, source: Option<Source>) -> Error {
+        Error {
+            inner: Box::new(Inner {
+                kind,
+                source,
+                file: None,
+                #[cfg(feature = "backtrace")]
+                backtrace: Backtrace::capture(),
+            }),
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    /// Returns the name of the file in which the error occurred, if known.
+    pub fn file(&self) -> Option<&str> {
+        self.inner.file.as_deref()
+    }
+
+    /// Attaches the name of the file in which the error occurred.
+    pub fn with_file<S: Into<String>>(mut self, file: S) -> Error {
+        self.inner.file = Some(file.into());
+        self
+    }
+
+    /// Returns the kind of the underlying IO error, if the error was caused by one, such as
+    /// `io::ErrorKind::NotFound` for an `ErrorKind::Open`.
+    #[cfg(feature = "std")]
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self.inner.kind {
+            ErrorKind::Io { kind, .. } => Some(kind),
+            _ => self.io_source().map(io::Error::kind),
+        }
+    }
+
+    /// Returns the OS error code of the underlying IO error, if the error was caused by one which
+    /// has a code.
+    #[cfg(feature = "std")]
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self.inner.kind {
+            ErrorKind::Io { os_error, .. } => os_error,
+            _ => self.io_source().and_then(io::Error::raw_os_error),
+        }
+    }
+
+    /// Returns the status with which a program should exit after failing with this error,
+    /// following the conventions of `sysexits.h`:
+    ///
+    /// - 65 (`EX_DATAERR`) for errors in the input, such as syntax errors, errors in definitions,
+    ///   denied lints, invalid UTF-8, unknown commands, and input nested too deeply;
+    /// - 66 (`EX_NOINPUT`) for an input or definitions file which could not be opened;
+    /// - 70 (`EX_SOFTWARE`) for a token which the renderer could not write, which indicates a bug;
+    /// - 74 (`EX_IOERR`) for other errors reading input or writing output;
+    /// - 78 (`EX_CONFIG`) for an invalid template or configuration file.
+    ///
+    /// 64 (`EX_USAGE`) is not used by any error, and is left for invalid command-line arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self.inner.kind {
+            ErrorKind::Open(_) => 66,
+            ErrorKind::Write => 74,
+            #[cfg(feature = "std")]
+            ErrorKind::Io { .. } => 74,
+            ErrorKind::UnwritableToken => 70,
+            ErrorKind::InvalidTemplate
+            | ErrorKind::ConfigSyntax { .. }
+            | ErrorKind::InvalidConfig { .. }
+            | ErrorKind::LocaleSyntax { .. } => 78,
+            _ => 65,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn io_source(&self) -> Option<&io::Error> {
+        self.inner.source.as_ref()?.downcast_ref()
     }
-}
 
-impl Fail for Error {
-    fn cause(&self) -> Option<&Fail> {
-        self.inner.cause()
+    /// Returns a `Display` for the error which shows, below the message, the line of `source` at
+    /// which the error occurred, with a caret under the offending column, in the style of
+    /// `rustc`. `source` should be the input in which the error occurred. For an unclosed
+    /// `\verbatim` command, the line on which it started is shown.
+    pub fn annotate<'a>(&'a self, source: &'a str) -> Annotated<'a> {
+        Annotated {
+            error: self,
+            source,
+        }
     }
 
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.inner.backtrace()
+    /// Returns the backtrace captured when the error was constructed. Whether it holds any frames
+    /// depends on the `RUST_BACKTRACE` and `RUST_LIB_BACKTRACE` environment variables.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.inner.backtrace
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.inner.source {
+            Some(ref source) => Some(&**source),
+            None => None,
+        }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.inner, f)
+        let file = self.inner.file.as_deref();
+        match self.inner.kind.position() {
+            Some(position) => write!(f, "{}:{}: ", file.unwrap_or("<input>"), position)?,
+            None => {
+                if let Some(file) = file {
+                    write!(f, "{}: ", file)?;
+                }
+            }
+        }
+        write!(f, "[{}] {}", self.inner.kind.code(), self.inner.kind)
     }
 }
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
-        Error {
-            inner: Context::new(kind),
+        Error::with_source(kind, None)
+    }
+}
+
+/// The `io::ErrorKind` is that of the underlying IO error, if there is one, and otherwise
+/// `InvalidData` for errors in the input and `Other` for errors while rendering. The `Error` is
+/// kept as the inner error of the `io::Error`, so the message is unchanged, and the `Error` can be
+/// recovered with `io::Error::into_inner` and `downcast`.
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        let kind = error.io_kind().unwrap_or(match error.inner.kind {
+            ErrorKind::Write
+            | ErrorKind::UnknownCommand(_)
+            | ErrorKind::InvalidTemplate
+            | ErrorKind::UnwritableToken
+            | ErrorKind::Cancelled => io::ErrorKind::Other,
+            _ => io::ErrorKind::InvalidData,
+        });
+        io::Error::new(kind, error)
+    }
+}
+
+/// Describes the closing delimiter of an unclosed `\verbatim` command, naming whitespace in
+/// words.
+fn describe_delimiter(delimiter: Option<char>) -> String {
+    match delimiter {
+        Some(' ') => "a space".into(),
+        Some('\t') => "a tab".into(),
+        Some('\n') => "a newline".into(),
+        Some(c) => format!("`{}`", c),
+        None => "a delimiter".into(),
+    }
+}
+
+/// An error along with the input in which it occurred, as returned by `Error::annotate`.
+#[derive(Clone, Copy, Debug)]
+pub struct Annotated<'a> {
+    error: &'a Error,
+    source: &'a str,
+}
+
+/// The number of columns to which a tab is expanded in an annotated error.
+const TAB_WIDTH: usize = 4;
+
+impl<'a> fmt::Display for Annotated<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = self.error.kind();
+        writeln!(f, "error[{}]: {}", kind.code(), kind)?;
+        let file = self.error.file().unwrap_or("<input>");
+        let position = match kind.position() {
+            Some(position) => position,
+            None => return write!(f, " --> {}", file),
+        };
+        write!(f, " --> {}:{}", file, position)?;
+        let line = match self.source.lines().nth(position.line.wrapping_sub(1)) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let expand = |c| if c == '\t' { TAB_WIDTH } else { 1 };
+        let before = position.column.saturating_sub(1);
+        let indent: usize = line.chars().take(before).map(expand).sum();
+        let rest: usize = line.chars().skip(before).map(expand).sum();
+        let width = kind.width().min(rest).max(1);
+        let number = position.line.to_string();
+        let gutter = " ".repeat(number.len());
+        writeln!(f)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", number, line.replace('\t', &" ".repeat(TAB_WIDTH)))?;
+        write!(f, "{} | {}{}", gutter, " ".repeat(indent), "^".repeat(width))
+    }
+}
+
+/// Methods for converting the errors of other libraries, such as `io::Error`, into an `Error`
+/// caused by them.
+pub trait ResultExt<T, E> {
+    /// Converts an error into an `Error` of the given kind.
+    fn context(self, kind: ErrorKind) -> Result<T, Error>;
+
+    /// Converts an error into an `Error` of the kind returned by `f`.
+    fn with_context<F: FnOnce(&E) -> ErrorKind>(self, f: F) -> Result<T, Error>;
+}
+
+impl<T, E: error::Error + Send + Sync + 'static> ResultExt<T, E> for Result<T, E> {
+    fn context(self, kind: ErrorKind) -> Result<T, Error> {
+        self.map_err(|e| Error::new(kind, e))
+    }
+
+    fn with_context<F: FnOnce(&E) -> ErrorKind>(self, f: F) -> Result<T, Error> {
+        self.map_err(|e| Error::new(f(&e), e))
+    }
+}
+
+/// A construct which the parser may be within when the input ends.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Construct {
+    /// A group, started by `{`.
+    Group,
+    /// A brace-delimited argument of a command or environment.
+    Argument,
+    /// A bracketed list of options.
+    Options,
+    /// The named environment, started by `\begin`.
+    Environment(String),
+    /// The body of a definition, such as that of a `\newcommand`.
+    Definition,
+}
+
+impl fmt::Display for Construct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Construct::Group => f.write_str("the group"),
+            Construct::Argument => f.write_str("the argument"),
+            Construct::Options => f.write_str("the options"),
+            Construct::Environment(ref name) => write!(f, "environment `{}`", name),
+            Construct::Definition => f.write_str("the body of the definition"),
+        }
+    }
+}
+
+/// A position in the input, as a line number and a (one-based) column, along with its byte
+/// offset.
+///
+/// `column` counts characters. `utf16_column` counts UTF-16 code units instead, as the positions
+/// of the Language Server Protocol do, so a character outside the Basic Multilingual Plane, such
+/// as an emoji, adds two to it rather than one. The positions produced by the crate have both,
+/// but a position constructed without `utf16_column`, such as with `Position::default()`, may be
+/// given it by `to_utf16`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+    pub utf16_column: usize,
+}
+
+impl Position {
+    /// Returns the position of the given byte offset in a text, whose first line is line 1.
+    pub fn at(text: &str, offset: usize) -> Position {
+        let before = &text[..text.floor_char_boundary(offset)];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            offset,
+            utf16_column: before[line_start..].encode_utf16().count() + 1,
+        }
+    }
+
+    /// Returns the position with its `utf16_column` computed from its `column`, given the text of
+    /// its line. A column past the end of the line, such as that of the end of input, counts one
+    /// unit for each column beyond the last character.
+    pub fn to_utf16(self, line: &str) -> Position {
+        let before = self.column.saturating_sub(1);
+        let units: usize = line.chars().take(before).map(char::len_utf16).sum();
+        let beyond = before.saturating_sub(line.chars().count());
+        Position {
+            utf16_column: units + beyond + 1,
+            ..self
         }
     }
 }
 
-impl From<Context<ErrorKind>> for Error {
-    fn from(inner: Context<ErrorKind>) -> Error {
-        Error { inner }
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Fail, PartialEq)]
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
 pub enum ErrorKind {
-    #[fail(display = "Unexpected end of input")]
-    EndOfInput,
-    #[fail(display = "Unclosed `\\verbatim` command (started at line {}, column {})", _0, _1)]
-    UnclosedVerbatim(usize, usize),
-    #[fail(display = "Invalid UTF-8 in line {}", _0)]
-    Unicode(usize),
-    #[fail(display = "An IO error occurred while reading line {}", _0)]
-    Io(usize),
+    /// The input ended within a token which started at `position`, such as after a lone `\`,
+    /// when `expected` was required to complete it.
+    #[error("Unexpected end of input in a token started at {position}: expected {expected}")]
+    EndOfInput {
+        expected: &'static str,
+        position: Position,
+    },
+    /// Something other than what the parser required was found, such as the end of input before
+    /// the `}` closing a group. `found` is a short form of what was found, such as `` `\section` ``
+    /// or `end of input`.
+    #[error("expected {expected}, found {found} at {position}")]
+    UnexpectedToken {
+        expected: &'static str,
+        found: String,
+        position: Position,
+    },
+    /// A `\verbatim` or `\raw` command which started at `start` was not closed by `delimiter`
+    /// before `end`, which is the end of input, or the end of the line for the language of a
+    /// `\verbatim` command. `delimiter` is `None` if the input ended before the command was given
+    /// one.
+    #[error(
+        "Unclosed `\\verbatim` command started at {start}: expected {} before {end}",
+        describe_delimiter(*.delimiter)
+    )]
+    UnclosedVerbatim {
+        start: Position,
+        delimiter: Option<char>,
+        end: Position,
+    },
+    #[error("Unexpected `}}` (at line {}, column {})", .0.line, .0.column)]
+    UnexpectedEndGroup(Position),
+    /// An `\\end` for a different environment than the one which is open.
+    #[error("`\\end{{{found}}}` does not match the open environment `{expected}` (at {position})")]
+    MismatchedEnvironment {
+        expected: String,
+        found: String,
+        position: Position,
+    },
+    #[error("Malformed definition (at line {}, column {})", .0.line, .0.column)]
+    InvalidDefinition(Position),
+    #[error("Reference to a nonexistent parameter (at line {}, column {})", .0.line, .0.column)]
+    InvalidParameter(Position),
+    #[error("Redefinition of an existing command or environment (at line {}, column {})", .0.line, .0.column)]
+    AlreadyDefined(Position),
+    #[error("Redefinition of an undefined command or environment (at line {}, column {})", .0.line, .0.column)]
+    NotDefined(Position),
+    #[error("Only definitions are allowed in a definitions file (at line {}, column {})", .0.line, .0.column)]
+    NotADefinition(Position),
+    #[error("Malformed table (at line {}, column {})", .0.line, .0.column)]
+    InvalidTable(Position),
+    /// The expansion of user-defined commands and environments, starting with the one at the
+    /// given position, did not end within the limits of the parser, as with a command which
+    /// expands to itself.
+    #[error("Macro expansion did not terminate (at line {}, column {})", .0.line, .0.column)]
+    ExpansionLimit(Position),
+    /// The input ended within the innermost of the constructs which were open, which started at
+    /// `position`.
+    #[error("End of input while {construct} opened at {position} was still open")]
+    Unclosed {
+        construct: Construct,
+        position: Position,
+    },
+    /// A construct which started at `position` was nested within more others than the parser
+    /// allows.
+    #[error("Too many levels of nesting in {construct} opened at {position}")]
+    TooDeep {
+        construct: Construct,
+        position: Position,
+    },
+    /// A value read from the input, such as a line, was longer than the `Limits` of the tokenizer
+    /// allow, or the input took more work to parse or render than they allow, which is `limit`,
+    /// in the units given by `Limit::units`. `position` is that of the command, for a command
+    /// name, verbatim content, or an expansion; that of the first character past the limit, for a
+    /// line; that of the token past the limit, for the number of tokens; and, for the output,
+    /// the position within the output of the first byte which could not be written, which
+    /// `position` does not return.
+    #[error(
+        "{what} exceeds the limit of {limit} {} (at line {}, column {})",
+        .what.units(),
+        .position.line,
+        .position.column
+    )]
+    LimitExceeded {
+        what: Limit,
+        limit: usize,
+        position: Position,
+    },
+    /// A lint which is denied by the `Lints` of the parser, or an argument of the wrong type, if
+    /// `ParserOptions::strict_arguments` is set.
+    #[error("Denied lint: {0}")]
+    Denied(Warning),
+    #[error("A token cannot be written as source")]
+    UnwritableToken,
+    /// Parsing or rendering was stopped by setting its cancellation token.
+    #[error("Parsing or rendering was cancelled")]
+    Cancelled,
+    /// A cached document was written by another version of the crate, or with other parser
+    /// options, than those it was read with. Requires the `cache` feature to occur.
+    #[error("The cache was written by another version or with other options")]
+    StaleCache,
+    /// Bytes read as a cached document are truncated, or were not written by
+    /// `Document::to_cache_bytes`. Requires the `cache` feature to occur.
+    #[error("The cache is truncated or corrupt")]
+    InvalidCache,
+    #[error("Could not open the file `{0}`")]
+    Open(String),
+    #[error("An IO error occurred while writing output")]
+    Write,
+    #[error("Unknown command `\\{0}`")]
+    UnknownCommand(String),
+    #[error("Invalid HTML template")]
+    InvalidTemplate,
+    #[error(
+        "Invalid configuration in line {}, column {}: {message}",
+        .position.line,
+        .position.column
+    )]
+    ConfigSyntax { message: String, position: Position },
+    #[error("Expected {expected} for `{key}` in the configuration, found {found}")]
+    InvalidConfig {
+        key: String,
+        expected: &'static str,
+        found: String,
+    },
+    /// A line of a bibliography file is not an entry, a continuation, or a comment, or gives a
+    /// key which is invalid or already given.
+    #[error(
+        "Invalid bibliography in line {}, column {}: {message}",
+        .position.line,
+        .position.column
+    )]
+    BibliographySyntax { message: String, position: Position },
+    /// A line of a locale file is not a translation, a continuation, or a comment, or gives a
+    /// key which is unknown or a value which is invalid.
+    #[error(
+        "Invalid locale in line {}, column {}: {message}",
+        .position.line,
+        .position.column
+    )]
+    LocaleSyntax { message: String, position: Position },
+    #[error("The document is nested too deeply to render")]
+    NestingTooDeep,
+    #[error("Invalid UTF-8 in line {}, column {}", .0.line, .0.column)]
+    Unicode(Position),
+    /// An IO error other than invalid UTF-8 occurred while reading input, of the given kind and
+    /// with the given OS error code, if any. Requires the `std` feature.
+    #[cfg(feature = "std")]
+    #[error(
+        "An IO error occurred while reading line {}, column {}",
+        .position.line,
+        .position.column
+    )]
+    Io {
+        position: Position,
+        kind: io::ErrorKind,
+        os_error: Option<i32>,
+    },
+    /// The input of a `Decoder` was not valid in its encoding, at the character at the given
+    /// position of the decoded text. Requires the `encoding` feature.
+    #[cfg(feature = "encoding")]
+    #[error(
+        "Invalid {encoding} in line {}, column {}",
+        .position.line,
+        .position.column
+    )]
+    Undecodable {
+        encoding: Encoding,
+        position: Position,
+    },
 }
 
 impl ErrorKind {
-    pub fn from_io(err: &io::Error, line: usize) -> ErrorKind {
+    /// Returns a short code identifying the kind of error, such as `E0102` for an unclosed
+    /// `\verbatim` command. Codes are stable, and are grouped by hundreds: syntax errors in the
+    /// `E01xx` range, errors in definitions in `E02xx`, errors in configuration and bibliography
+    /// files in `E03xx`, input and output errors, cancellation, and invalid caches in `E04xx`, and
+    /// rendering errors in `E05xx`.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorKind::EndOfInput { .. } => "E0100",
+            ErrorKind::UnexpectedToken { .. } => "E0101",
+            ErrorKind::UnclosedVerbatim { .. } => "E0102",
+            ErrorKind::UnexpectedEndGroup(_) => "E0103",
+            ErrorKind::MismatchedEnvironment { .. } => "E0104",
+            ErrorKind::InvalidTable(_) => "E0105",
+            ErrorKind::Denied(_) => "E0106",
+            ErrorKind::Unclosed { .. } => "E0107",
+            ErrorKind::TooDeep { .. } => "E0108",
+            ErrorKind::LimitExceeded { .. } => "E0109",
+            ErrorKind::InvalidDefinition(_) => "E0200",
+            ErrorKind::InvalidParameter(_) => "E0201",
+            ErrorKind::AlreadyDefined(_) => "E0202",
+            ErrorKind::NotDefined(_) => "E0203",
+            ErrorKind::NotADefinition(_) => "E0204",
+            ErrorKind::ExpansionLimit(_) => "E0205",
+            ErrorKind::ConfigSyntax { .. } => "E0300",
+            ErrorKind::InvalidConfig { .. } => "E0301",
+            ErrorKind::BibliographySyntax { .. } => "E0302",
+            ErrorKind::LocaleSyntax { .. } => "E0303",
+            ErrorKind::Open(_) => "E0400",
+            ErrorKind::Write => "E0401",
+            ErrorKind::Unicode(_) => "E0402",
+            #[cfg(feature = "std")]
+            ErrorKind::Io { .. } => "E0403",
+            #[cfg(feature = "encoding")]
+            ErrorKind::Undecodable { .. } => "E0404",
+            ErrorKind::Cancelled => "E0405",
+            ErrorKind::StaleCache => "E0406",
+            ErrorKind::InvalidCache => "E0407",
+            ErrorKind::UnknownCommand(_) => "E0500",
+            ErrorKind::InvalidTemplate => "E0501",
+            ErrorKind::NestingTooDeep => "E0502",
+            ErrorKind::UnwritableToken => "E0503",
+        }
+    }
+
+    /// Returns a longer description of the kind of error, explaining its usual causes and how to
+    /// fix them, with an example.
+    pub fn explanation(&self) -> &'static str {
+        match *self {
+            ErrorKind::EndOfInput { .. } => {
+                "The input ended in the middle of a token, such as after a lone `\\` at the end \
+                 of the input. Remove the backslash, or complete the command or escape:\n\n    \
+                 50\\% done"
+            }
+            ErrorKind::UnexpectedToken { .. } => {
+                "The parser found something other than what the syntax requires at that point, \
+                 most often the end of input before the `}` closing a group or the `\\end` \
+                 closing an environment. Close the construct:\n\n    \\emph{important}"
+            }
+            ErrorKind::UnclosedVerbatim { .. } => {
+                "A `\\verbatim` or `\\raw` command was not closed before the end of its line or \
+                 of the input. The content ends at the next occurrence of the character after \
+                 the command, or at the matching `}` if that character is `{`:\n\n    \
+                 \\verbatim|let x = 1;|"
+            }
+            ErrorKind::UnexpectedEndGroup(_) => {
+                "A `}` appeared with no open group for it to close. Remove it, or add the \
+                 missing `{`, or write `\\}` for a literal brace:\n\n    {\\bf bold} text"
+            }
+            ErrorKind::MismatchedEnvironment { .. } => {
+                "An `\\end` named a different environment than the one which is open. \
+                 Environments must be closed in the reverse order in which they were \
+                 opened:\n\n    \\begin{quote}\\begin{itemize}\\item x\\end{itemize}\\end{quote}"
+            }
+            ErrorKind::InvalidTable(_) => {
+                "A `table` environment contained something other than rows, or its column \
+                 specification contained something other than `l`, `c`, and `r`. Each row must \
+                 be a `\\row` command, or a `\\header` command before any rows, with one \
+                 argument per cell:\n\n    \\begin{table}{lr}\\row{Name}{Count}\\end{table}"
+            }
+            ErrorKind::Unclosed { .. } => {
+                "The input ended while a group, argument, list of options, environment, or \
+                 definition was open. The message names the innermost of them and where it \
+                 started; close it with `}`, `]`, or the matching `\\end`:\n\n    \
+                 \\begin{quote}{\\em text}\\end{quote}"
+            }
+            ErrorKind::TooDeep { .. } => {
+                "Groups, arguments, lists of options, or environments were nested more than 128 \
+                 levels deep, which usually happens only with generated input. Reduce the \
+                 nesting, for example by closing each group before opening the next:\n\n    \
+                 {\\em a}{\\em b}"
+            }
+            ErrorKind::LimitExceeded { .. } => {
+                "A command name, the contents of a `\\verbatim` or `\\raw` command, or a line \
+                 was longer than the limits of the tokenizer allow, or the document had more \
+                 tokens, more macro expansions, or more output than the limits of the parser \
+                 allow, which usually happens only with generated or malicious input. Shorten \
+                 it, split long verbatim content into several commands, or raise the limit:\n\n    \
+                 Limits::new().max_line_length(1 << 26)"
+            }
+            ErrorKind::Denied(_) => {
+                "The input contained something which is valid, but which the lints given in the \
+                 parser options deny, such as trailing whitespace or a line which is too long. \
+                 Fix the input as described by the message, or lower the level of the lint:\n\n    \
+                 Lints::new().level(Lint::TrailingWhitespace, Level::Warn)\n\n\
+                 An argument of a command which is not of the type the command expects, such as \
+                 a `\\label` containing spaces, is also denied if the parser options set \
+                 `strict_arguments`."
+            }
+            ErrorKind::InvalidDefinition(_) => {
+                "A definition such as `\\newcommand` was malformed: the name must be a single \
+                 command in braces, the argument count a number in brackets, and the body a \
+                 group:\n\n    \\newcommand{\\greet}[1]{Hello, #1!}"
+            }
+            ErrorKind::InvalidParameter(_) => {
+                "The body of a command or environment referred to a parameter beyond the number \
+                 of arguments it was defined with. Increase the argument count, or fix the \
+                 reference:\n\n    \\newcommand{\\pair}[2]{#1 and #2}"
+            }
+            ErrorKind::AlreadyDefined(_) => {
+                "`\\newcommand` or `\\newenvironment` named a command or environment which is \
+                 already defined. Choose another name, or use `\\renewcommand` or \
+                 `\\renewenvironment` to replace it:\n\n    \\renewcommand{\\emph}[1]{#1}"
+            }
+            ErrorKind::NotDefined(_) => {
+                "`\\renewcommand` or `\\renewenvironment` named a command or environment which \
+                 is not defined, so there is nothing to replace. Use `\\newcommand` or \
+                 `\\newenvironment` instead:\n\n    \\newcommand{\\note}[1]{(#1)}"
+            }
+            ErrorKind::NotADefinition(_) => {
+                "A definitions file contained something other than definitions and whitespace. \
+                 Move any text or other commands into the document itself:\n\n    \
+                 \\newcommand{\\product}{Formatting}"
+            }
+            ErrorKind::ExpansionLimit(_) => {
+                "A user-defined command or environment kept expanding without reading any more \
+                 of the input, or expanded to an excessive number of tokens, usually because its \
+                 body uses the command itself. Make sure that each expansion ends:\n\n    \
+                 \\newcommand{\\twice}[1]{#1#1}"
+            }
+            ErrorKind::ConfigSyntax { .. } => {
+                "The configuration file is not valid TOML, for example because a string is not \
+                 quoted or a table is given twice. Fix the syntax at the given position:\n\n    \
+                 [html]\n    lang = \"en\""
+            }
+            ErrorKind::InvalidConfig { .. } => {
+                "A key of the configuration file has a value of the wrong type, or one which is \
+                 not among those it accepts. Give a value of the expected type:\n\n    \
+                 [plain]\n    width = 72"
+            }
+            ErrorKind::BibliographySyntax { .. } => {
+                "A line of a bibliography file named by `\\bibliography` is not an entry, or \
+                 gives a key with whitespace or a comma in it, or one which an earlier entry \
+                 gives. Give each entry as its key, a colon, and its text, indenting any \
+                 continuation lines:\n\n    \
+                 knuth84: Donald E. Knuth.\n        The TeXbook. Addison-Wesley, 1984."
+            }
+            ErrorKind::LocaleSyntax { .. } => {
+                "A line of a locale file is not a translation, or gives a key which is not one \
+                 of `references`, `index_start`, `date_format`, `months`, `months_short`, \
+                 `weekdays`, or `weekdays_short`, or an empty value, or the wrong number of \
+                 names. Give each translation as its key, `=`, and its value:\n\n    \
+                 references = Literatur\n    \
+                 weekdays_short = Mo, Di, Mi, Do, Fr, Sa, So"
+            }
+            ErrorKind::Open(_) => {
+                "A file, such as a definitions file, could not be opened. Check that the path \
+                 is correct, relative to the current directory, and that the file is readable."
+            }
+            ErrorKind::Write => {
+                "The output could not be written, for example because the disk is full or the \
+                 reader of a pipe has exited. The underlying IO error is given as the source of \
+                 the error."
+            }
+            ErrorKind::Unicode(_) => {
+                "The input was not valid UTF-8. Convert the file to UTF-8, for example with \
+                 `iconv -f latin1 -t utf-8`, or remove the invalid bytes at the given column."
+            }
+            #[cfg(feature = "std")]
+            ErrorKind::Io { .. } => {
+                "The input could not be read, for example because of a permission or device \
+                 error. The underlying IO error is given as the source of the error."
+            }
+            #[cfg(feature = "encoding")]
+            ErrorKind::Undecodable { .. } => {
+                "The input was not valid in the encoding it was read in, for example because \
+                 UTF-16 input had an unpaired surrogate or an odd number of bytes. Check that \
+                 the encoding is the right one, or read the input with a lossy `Decoder`, which \
+                 replaces invalid input with U+FFFD."
+            }
+            ErrorKind::UnknownCommand(_) => {
+                "The renderer was configured to reject commands it does not recognize, and the \
+                 document used one. Fix the name of the command, define it with \
+                 `\\newcommand`, or choose a more lenient policy:\n\n    \\emph{text}"
+            }
+            ErrorKind::InvalidTemplate => {
+                "An HTML template used an unknown placeholder, or did not contain `{{content}}` \
+                 exactly once. The placeholders are `{{title}}`, `{{authors}}`, `{{date}}`, \
+                 `{{toc}}`, and `{{content}}`:\n\n    <body>{{content}}</body>"
+            }
+            ErrorKind::NestingTooDeep => {
+                "The document nested groups, commands, or environments more deeply than the \
+                 renderer allows, which usually happens only with generated input. Reduce the \
+                 nesting, for example by splitting deeply nested lists."
+            }
+            ErrorKind::UnwritableToken => {
+                "A token cannot be written back as source, such as verbatim content which \
+                 contains every possible delimiter. Split the content into several `\\verbatim` \
+                 commands."
+            }
+            ErrorKind::Cancelled => {
+                "The program which was parsing or rendering the document set the cancellation \
+                 token given in its options, usually because the result was no longer needed, \
+                 such as when the document was edited again. This is not a problem with the \
+                 document."
+            }
+            ErrorKind::StaleCache => {
+                "A document was read from a cache written by another version of the program, or \
+                 with other parser options, so it may not be the document which parsing the \
+                 input now gives. Parse the input instead, and write the cache again; the \
+                 command-line tool does so itself."
+            }
+            ErrorKind::InvalidCache => {
+                "A document was read from bytes which are not a whole cache, for example because \
+                 writing the cache was interrupted, or because another file was read in its \
+                 place. Parse the input instead, and write the cache again; the command-line \
+                 tool does so itself."
+            }
+        }
+    }
+
+    /// Returns the position in the input at which the error occurred, if it is known. For an
+    /// unclosed `\verbatim` command, this is where the command started. An output which exceeded
+    /// its limit has no position in the input.
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            ErrorKind::LimitExceeded {
+                what: Limit::Output,
+                ..
+            } => None,
+            ErrorKind::EndOfInput { position, .. }
+            | ErrorKind::UnexpectedToken { position, .. }
+            | ErrorKind::Unclosed { position, .. }
+            | ErrorKind::TooDeep { position, .. }
+            | ErrorKind::LimitExceeded { position, .. }
+            | ErrorKind::MismatchedEnvironment { position, .. }
+            | ErrorKind::UnclosedVerbatim {
+                start: position, ..
+            }
+            | ErrorKind::UnexpectedEndGroup(position)
+            | ErrorKind::InvalidDefinition(position)
+            | ErrorKind::InvalidParameter(position)
+            | ErrorKind::AlreadyDefined(position)
+            | ErrorKind::NotDefined(position)
+            | ErrorKind::NotADefinition(position)
+            | ErrorKind::InvalidTable(position)
+            | ErrorKind::ExpansionLimit(position)
+            | ErrorKind::ConfigSyntax { position, .. }
+            | ErrorKind::BibliographySyntax { position, .. }
+            | ErrorKind::LocaleSyntax { position, .. }
+            | ErrorKind::Unicode(position) => Some(position),
+            #[cfg(feature = "std")]
+            ErrorKind::Io { position, .. } => Some(position),
+            #[cfg(feature = "encoding")]
+            ErrorKind::Undecodable { position, .. } => Some(position),
+            ErrorKind::Denied(ref warning) => warning.position(),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of columns of input which the error concerns, starting at its position:
+    /// the length of the token found in place of an expected one, and otherwise 1.
+    fn width(&self) -> usize {
+        match *self {
+            ErrorKind::UnexpectedToken { ref found, .. }
+                if found.len() > 2 && found.starts_with('`') && found.ends_with('`') =>
+            {
+                found.chars().count() - 2
+            }
+            _ => 1,
+        }
+    }
+
+    /// Returns the kind of an error encountered while reading input at the given position.
+    #[cfg(feature = "std")]
+    pub fn from_io(err: &io::Error, position: Position) -> ErrorKind {
+        #[cfg(feature = "encoding")]
+        {
+            if let Some(error) = err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()) {
+                return ErrorKind::Undecodable {
+                    encoding: error.encoding,
+                    position,
+                };
+            }
+        }
         match err.kind() {
-            io::ErrorKind::InvalidData => ErrorKind::Unicode(line),
-            _ => ErrorKind::Io(line),
+            io::ErrorKind::InvalidData => ErrorKind::Unicode(position),
+            kind => ErrorKind::Io {
+                position,
+                kind,
+                os_error: err.raw_os_error(),
+            },
+        }
+    }
+}
+
+/// A non-fatal problem encountered while parsing or rendering.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum Warning {
+    #[error(
+        "Unknown output format `{0}` (at line {line}, column {column})",
+        line = .1.line,
+        column = .1.column
+    )]
+    UnknownFormat(String, Position),
+    #[error("Unknown command `\\{0}` passed through to the output")]
+    UnknownCommand(String),
+    #[error("Reference to an undefined label `{0}`")]
+    UnresolvedReference(String),
+    #[error("No `\\{0}` for a template placeholder")]
+    MissingMetadata(String),
+    #[error("Image `{0}` has no alternative text")]
+    MissingAltText(String),
+    #[error("Image `{0}` does not exist")]
+    MissingImage(String),
+    /// A `\label` of a document appended with `Document::append` is already used by the
+    /// document it is appended to. `span` is that of the top-level node of the appended document
+    /// which holds the label, in the source of `file`, if it is known.
+    #[error("Label `{label}` in `{file}` is already used by an earlier merged document")]
+    DuplicateLabel {
+        label: String,
+        file: String,
+        span: Option<Span>,
+    },
+    #[error("Merged documents give different `\\{0}`s; the first is used")]
+    ConflictingMetadata(String),
+    #[error("Citation of `{0}`, which is not in the bibliography")]
+    UnknownCitation(String),
+    #[error("Abbreviation `{0}` has no definition")]
+    UndefinedAbbreviation(String),
+    #[error("The page has no language; give it with `\\lang` or the `lang` option")]
+    MissingLanguage,
+    #[error("Heading `{0}` is an `<h{1}>` following an `<h{2}>`, which skips a level")]
+    SkippedHeadingLevel(String, usize, usize),
+    #[error("Indentation level {0} is deeper than {1}, which is used instead")]
+    IndentTooDeep(usize, usize),
+    #[error(
+        "Control character {0:?} (at line {line}, column {column})",
+        line = .1.line,
+        column = .1.column
+    )]
+    ControlCharacter(char, Position),
+    #[error(
+        "Line longer than {0} characters (at line {line}, column {column})",
+        line = .1.line,
+        column = .1.column
+    )]
+    LongLine(usize, Position),
+    #[error("Indentation mixes tabs and spaces (at line {}, column {})", .0.line, .0.column)]
+    MixedIndentation(Position),
+    #[error("Trailing whitespace (at line {}, column {})", .0.line, .0.column)]
+    TrailingWhitespace(Position),
+    #[error(
+        "`%` does not start a comment, so the rest of the line is text (at line {}, column {})",
+        .0.line,
+        .0.column
+    )]
+    PercentComment(Position),
+    #[error(
+        "`\\item` of a description list has no term (at line {}, column {})",
+        .0.line,
+        .0.column
+    )]
+    MissingTerm(Position),
+    #[error(
+        "`{0}` is not a valid date written as `YYYY-MM-DD` (at line {line}, column {column})",
+        line = .1.line,
+        column = .1.column
+    )]
+    InvalidDate(String, Position),
+    #[error(
+        "Invalid date format `{0}` (at line {line}, column {column})",
+        line = .1.line,
+        column = .1.column
+    )]
+    InvalidDateFormat(String, Position),
+    #[error(
+        "Argument {1} of `\\{0}` should be {2} (at line {line}, column {column})",
+        line = .3.line,
+        column = .3.column
+    )]
+    InvalidArgument(String, usize, &'static str, Position),
+    #[error("Unknown key `{0}` in the configuration")]
+    UnknownConfigKey(String),
+}
+
+impl Warning {
+    /// Returns a short code identifying the kind of warning, such as `W0501` for a reference to
+    /// an undefined label, grouped in the same way as the codes of `ErrorKind`.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Warning::UnknownFormat(..) => "W0100",
+            Warning::ControlCharacter(..) => "W0101",
+            Warning::LongLine(..) => "W0102",
+            Warning::MixedIndentation(_) => "W0103",
+            Warning::TrailingWhitespace(_) => "W0104",
+            Warning::MissingTerm(_) => "W0105",
+            Warning::InvalidDate(..) => "W0106",
+            Warning::InvalidDateFormat(..) => "W0107",
+            Warning::InvalidArgument(..) => "W0108",
+            Warning::PercentComment(_) => "W0109",
+            Warning::UnknownConfigKey(_) => "W0300",
+            Warning::UnknownCommand(_) => "W0500",
+            Warning::UnresolvedReference(_) => "W0501",
+            Warning::MissingMetadata(_) => "W0502",
+            Warning::MissingAltText(_) => "W0503",
+            Warning::MissingImage(_) => "W0504",
+            Warning::DuplicateLabel { .. } => "W0505",
+            Warning::ConflictingMetadata(_) => "W0506",
+            Warning::UnknownCitation(_) => "W0507",
+            Warning::UndefinedAbbreviation(_) => "W0508",
+            Warning::MissingLanguage => "W0509",
+            Warning::SkippedHeadingLevel(..) => "W0510",
+            Warning::IndentTooDeep(..) => "W0511",
+        }
+    }
+
+    /// Returns the position in the input at which the warning occurred, if it is known.
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            Warning::UnknownFormat(_, position)
+            | Warning::ControlCharacter(_, position)
+            | Warning::LongLine(_, position)
+            | Warning::MixedIndentation(position)
+            | Warning::TrailingWhitespace(position)
+            | Warning::PercentComment(position)
+            | Warning::MissingTerm(position)
+            | Warning::InvalidDate(_, position)
+            | Warning::InvalidDateFormat(_, position)
+            | Warning::InvalidArgument(.., position) => Some(position),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeSet;
+    use alloc::vec::Vec;
+
+    /// Returns the codes returned by `ErrorKind::code` and `Warning::code`, read from the source,
+    /// so that every variant is included, whichever features are enabled.
+    fn codes() -> Vec<&'static str> {
+        include_str!("errors.rs")
+            .lines()
+            .map(str::trim_start)
+            .filter(|line| line.starts_with("ErrorKind::") || line.starts_with("Warning::"))
+            .filter_map(|line| line.split("=> \"").nth(1))
+            .filter_map(|rest| rest.split('"').next())
+            .collect()
+    }
+
+    #[test]
+    fn codes_are_well_formed() {
+        let codes = codes();
+        assert!(codes.len() > 50);
+        for code in codes {
+            assert_eq!(code.len(), 5, "{}", code);
+            assert!(code.starts_with(['E', 'W']), "{}", code);
+            assert!(code[1..].bytes().all(|b| b.is_ascii_digit()), "{}", code);
+        }
+    }
+
+    #[test]
+    fn codes_are_unique() {
+        let mut seen = BTreeSet::new();
+        for code in codes() {
+            assert!(seen.insert(code), "{} is used more than once", code);
         }
     }
 }
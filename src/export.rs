@@ -0,0 +1,634 @@
+//! Export of the content of a document as JSON, for search indexers and other tools which want
+//! the text of a document and how it is divided, rather than its syntax. Requires the `serde`
+//! feature.
+//!
+//! Unlike the AST, the export has no groups or command names: formatting such as `\emph` and
+//! `\bold` is flattened into the text, references, citations, and abbreviations are replaced by
+//! the text which the plain-text renderer writes for them, and commands which produce no content
+//! of their own, such as `\label` and `\index`, are dropped. The headings nest according to the
+//! outline, so a subsection is found within its section.
+//!
+//! `content_json` writes an object with the fields:
+//!
+//! - `title`: the text of the first `\title`, or `null` if there is none;
+//! - `authors`: an array of the text of each `\author`;
+//! - `date`: the text of the first `\date`, or `null` if there is none;
+//! - `blocks`: an array of the blocks before the first heading;
+//! - `sections`: an array of the top-level sections.
+//!
+//! Each section is an object with the fields:
+//!
+//! - `level`: 1 for a `\section`, and 2 for a `\subsection`;
+//! - `number`: the number of the heading, such as `"2.1"`, or `null` if it is unnumbered;
+//! - `anchor`: the identifier of the heading, as used by the HTML renderer;
+//! - `title`: the text of the heading;
+//! - `blocks`: an array of the blocks of the section, before its first subsection;
+//! - `sections`: an array of its subsections.
+//!
+//! Each block is an object whose `type` is one of:
+//!
+//! - `"paragraph"`, with the `text` of the paragraph, and arrays of the `links` and `images`
+//!   within it. Each link has its `text` and its `url`, and each image its `path`, and its `alt`
+//!   text, `width`, and `height`, each of which is `null` if it is not given;
+//! - `"code"`, for a verbatim block, with its `language`, which may be `null`, and its `text`,
+//!   exactly as it was written;
+//! - `"list"`, with its `kind`, `"bullet"`, `"ordered"`, or `"description"`, and an array of its
+//!   `items`, each of which has a `term`, which is `null` other than in a description list, and
+//!   an array of `blocks`;
+//! - `"table"`, with whether its first row is a `header`, its `rows`, an array of arrays of the
+//!   text of each cell, its `caption`, which may be `null`, and the `links` and `images` within
+//!   its cells and caption, as for a paragraph;
+//! - `"quote"`, with an array of its `blocks`, and the text of its `attribution`, which may be
+//!   `null`;
+//! - `"note"`, for a footnote, with an array of its `blocks`. It follows the block which
+//!   contains the footnote.
+//!
+//! The text of paragraphs, cells, titles, and terms has its whitespace collapsed. The entries of a
+//! bibliography are paragraphs starting with their number, such as `[1]`, and the other
+//! environments are replaced by their content. The fields of each object are always present, and
+//! are written in the order given here.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::slice;
+
+use ast::{self, Command, Document, Environment, Node, Table};
+use bibliography::{self, Citations};
+use errors::{Error, ErrorKind};
+use render::abbreviations::Abbreviations;
+use render::outline::{self, Heading, Outliner};
+use render::{has_link_text, is_horizontal_rule, item_term, link_address, link_url};
+use render::{Image, RenderReport, DEFAULT_MAX_DEPTH};
+
+/// Returns the content of a document as JSON, as described in the module documentation. Fails
+/// with `ErrorKind::NestingTooDeep` if nodes are nested more than `DEFAULT_MAX_DEPTH` levels
+/// deep. Warnings, such as those about images without alternative text, are discarded.
+pub fn content_json(document: &Document) -> Result<String, Error> {
+    let headings = Outliner::new().headings(&document.nodes);
+    let mut exporter = Exporter {
+        outline: headings.clone(),
+        headings: headings.into(),
+        citations: Citations::new(&document.nodes),
+        abbreviations: Abbreviations::new(&document.nodes),
+        report: RenderReport::new(),
+    };
+    let content = exporter.content(&document.nodes)?;
+    // Serializing strings, numbers, and derived structs to a `String` cannot fail.
+    Ok(serde_json::to_string_pretty(&content).unwrap_or_default())
+}
+
+/// The content of a whole document.
+#[derive(Serialize)]
+struct Content {
+    title: Option<String>,
+    authors: Vec<String>,
+    date: Option<String>,
+    blocks: Vec<Block>,
+    sections: Vec<Section>,
+}
+
+/// A heading, along with the content which follows it up to the next heading of the same or a
+/// shallower level.
+#[derive(Serialize)]
+struct Section {
+    level: usize,
+    number: Option<String>,
+    anchor: String,
+    title: String,
+    blocks: Vec<Block>,
+    sections: Vec<Section>,
+}
+
+/// A block of content.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block {
+    Paragraph {
+        text: String,
+        links: Vec<Link>,
+        images: Vec<Image>,
+    },
+    Code {
+        language: Option<String>,
+        text: String,
+    },
+    List {
+        kind: ListKind,
+        items: Vec<Item>,
+    },
+    Table {
+        header: bool,
+        rows: Vec<Vec<String>>,
+        caption: Option<String>,
+        links: Vec<Link>,
+        images: Vec<Image>,
+    },
+    Quote {
+        blocks: Vec<Block>,
+        attribution: Option<String>,
+    },
+    Note {
+        blocks: Vec<Block>,
+    },
+}
+
+/// The kind of a list.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ListKind {
+    Bullet,
+    Ordered,
+    Description,
+}
+
+/// An item of a list.
+#[derive(Serialize)]
+struct Item {
+    term: Option<String>,
+    blocks: Vec<Block>,
+}
+
+/// A link, given by a `\link` command.
+#[derive(Serialize)]
+struct Link {
+    text: String,
+    url: String,
+}
+
+/// The inline content of a block, as it is collected.
+#[derive(Default)]
+struct Inline {
+    text: String,
+    links: Vec<Link>,
+    images: Vec<Image>,
+    /// The blocks of each footnote, which follow the block.
+    notes: Vec<Vec<Block>>,
+}
+
+/// Returns text with each run of whitespace replaced by a single space, and without whitespace at
+/// the start or end.
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns collapsed text, or `None` if it is empty.
+fn non_empty(text: &str) -> Option<String> {
+    Some(collapse(text)).filter(|text| !text.is_empty())
+}
+
+/// Returns whether a node is a top-level heading, which starts a section.
+fn is_heading(node: &Node) -> bool {
+    matches!(*node, Node::Command(ref command) if outline::heading_level(&command.name).is_some())
+}
+
+/// Returns whether a node is a block, rather than inline content, when it appears among blocks.
+fn is_block(node: &Node) -> bool {
+    match *node {
+        Node::Paragraph(_)
+        | Node::Environment(_)
+        | Node::Table(_)
+        | Node::Verbatim(_)
+        | Node::Raw(_) => true,
+        Node::Command(ref command) => {
+            outline::heading_level(&command.name).is_some()
+                || is_horizontal_rule(&command.name)
+                || is_omitted(&command.name)
+                || matches!(&*command.name, "centered" | "rightalign" | "printindex")
+        }
+        Node::Text(_) | Node::Group(_) => false,
+    }
+}
+
+/// Returns whether a command produces no content of its own: the metadata, and the commands which
+/// affect only other parts of the document.
+fn is_omitted(name: &str) -> bool {
+    matches!(
+        name,
+        "title"
+            | "author"
+            | "date"
+            | "lang"
+            | "label"
+            | "index"
+            | "tableofcontents"
+            | "appendix"
+            | "bibliography"
+            | "defabbr"
+    )
+}
+
+/// Returns whether a node is an `\item` command.
+fn is_item(node: &Node) -> bool {
+    matches!(*node, Node::Command(ref command) if command.name == "item")
+}
+
+/// The state of the export of a document.
+struct Exporter {
+    /// All of the headings of the document, for resolving references.
+    outline: Vec<Heading>,
+    /// The headings which have not yet been exported.
+    headings: VecDeque<Heading>,
+    /// The numbers of the entries of the bibliography, for resolving citations.
+    citations: Citations,
+    /// The abbreviations of the document, and which have been used.
+    abbreviations: Abbreviations,
+    report: RenderReport,
+}
+
+impl Exporter {
+    /// Returns the content of a document, divided into sections at its top-level headings.
+    fn content(&mut self, nodes: &[Node]) -> Result<Content, Error> {
+        let mut content = Content {
+            title: None,
+            authors: Vec::new(),
+            date: None,
+            blocks: Vec::new(),
+            sections: Vec::new(),
+        };
+        for node in nodes {
+            let command = match *node {
+                Node::Command(ref command) => command,
+                _ => continue,
+            };
+            let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+            match &*command.name {
+                "title" if content.title.is_none() => content.title = Some(self.text(arg)?),
+                "date" if content.date.is_none() => content.date = Some(self.text(arg)?),
+                "author" => content.authors.push(self.text(arg)?),
+                _ => {}
+            }
+        }
+        // The sections which are open, from the outermost to the innermost.
+        let mut open: Vec<Section> = Vec::new();
+        let mut rest = nodes;
+        loop {
+            let end = rest.iter().position(is_heading).unwrap_or(rest.len());
+            let blocks = self.blocks(&rest[..end], 0)?;
+            match open.last_mut() {
+                Some(section) => section.blocks.extend(blocks),
+                None => content.blocks.extend(blocks),
+            }
+            let command = match rest.get(end) {
+                Some(Node::Command(command)) => command,
+                _ => break,
+            };
+            rest = &rest[end + 1..];
+            let section = self.section(command)?;
+            while open.last().is_some_and(|last| last.level >= section.level) {
+                close(&mut open, &mut content);
+            }
+            open.push(section);
+        }
+        while !open.is_empty() {
+            close(&mut open, &mut content);
+        }
+        Ok(content)
+    }
+
+    /// Returns an empty section for a heading command.
+    fn section(&mut self, command: &Command) -> Result<Section, Error> {
+        let level = outline::heading_level(&command.name).unwrap_or(1);
+        Ok(match self.headings.pop_front() {
+            Some(heading) => Section {
+                level,
+                number: heading.formatted_number,
+                anchor: heading.id,
+                title: heading.title,
+                blocks: Vec::new(),
+                sections: Vec::new(),
+            },
+            None => {
+                let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+                Section {
+                    level,
+                    number: None,
+                    anchor: String::new(),
+                    title: self.text(arg)?,
+                    blocks: Vec::new(),
+                    sections: Vec::new(),
+                }
+            }
+        })
+    }
+
+    /// Returns the blocks for a list of nodes. Inline content which is not within a paragraph,
+    /// such as the text of a list item, becomes a paragraph.
+    fn blocks(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Block>, Error> {
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        let mut blocks = Vec::new();
+        let mut rest = nodes;
+        while let Some(node) = rest.first() {
+            if is_block(node) {
+                self.block(node, depth, &mut blocks)?;
+                rest = &rest[1..];
+                continue;
+            }
+            let end = rest.iter().position(is_block).unwrap_or(rest.len());
+            self.paragraph(&rest[..end], depth, &mut blocks)?;
+            rest = &rest[end..];
+        }
+        Ok(blocks)
+    }
+
+    /// Adds the blocks for a node for which `is_block` holds.
+    fn block(&mut self, node: &Node, depth: usize, blocks: &mut Vec<Block>) -> Result<(), Error> {
+        match *node {
+            Node::Paragraph(ref nodes) => self.paragraph(nodes, depth, blocks)?,
+            Node::Environment(ref env) => self.environment(env, depth, blocks)?,
+            Node::Table(ref table) => self.table(table, depth, blocks)?,
+            Node::Verbatim(ref verbatim) => blocks.push(Block::Code {
+                language: verbatim.language.clone(),
+                text: verbatim.content.clone(),
+            }),
+            Node::Command(ref command) => {
+                let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+                if outline::heading_level(&command.name).is_some() {
+                    // A heading within other content does not start a section.
+                    let title = self.section(command)?.title;
+                    if !title.is_empty() {
+                        blocks.push(paragraph(title, Vec::new(), Vec::new()));
+                    }
+                } else if command.name == "centered" || command.name == "rightalign" {
+                    blocks.extend(self.blocks(arg, depth + 1)?);
+                }
+            }
+            Node::Text(_) | Node::Group(_) | Node::Raw(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Adds a paragraph of inline content, unless it is empty, followed by its footnotes.
+    fn paragraph(
+        &mut self,
+        nodes: &[Node],
+        depth: usize,
+        blocks: &mut Vec<Block>,
+    ) -> Result<(), Error> {
+        let mut inline = Inline::default();
+        self.inlines(nodes, depth + 1, &mut inline)?;
+        let text = collapse(&inline.text);
+        if !text.is_empty() || !inline.links.is_empty() || !inline.images.is_empty() {
+            blocks.push(paragraph(text, inline.links, inline.images));
+        }
+        push_notes(inline.notes, blocks);
+        Ok(())
+    }
+
+    /// Adds the blocks for an environment.
+    fn environment(
+        &mut self,
+        env: &Environment,
+        depth: usize,
+        blocks: &mut Vec<Block>,
+    ) -> Result<(), Error> {
+        let kind = match &*env.name {
+            "itemize" => ListKind::Bullet,
+            "enumerate" => ListKind::Ordered,
+            "description" => ListKind::Description,
+            "quote" => {
+                let attribution = env.args.first().map(|arg| self.text(arg)).transpose()?;
+                blocks.push(Block::Quote {
+                    blocks: self.blocks(&env.nodes, depth + 1)?,
+                    attribution: attribution.filter(|text| !text.is_empty()),
+                });
+                return Ok(());
+            }
+            "references" => return self.references(&env.nodes, depth, blocks),
+            _ => {
+                blocks.extend(self.blocks(&env.nodes, depth + 1)?);
+                return Ok(());
+            }
+        };
+        let items = self.items(&env.nodes, depth)?;
+        blocks.push(Block::List { kind, items });
+        Ok(())
+    }
+
+    /// Returns the items of a list. Content before the first `\item` forms an item of its own.
+    fn items(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Item>, Error> {
+        let mut items = Vec::new();
+        let mut rest = nodes;
+        while !rest.is_empty() {
+            let item = match rest[0] {
+                Node::Command(ref command) if command.name == "item" => {
+                    rest = &rest[1..];
+                    match item_term(command) {
+                        Some(term) => Item {
+                            term: Some(self.text(term)?),
+                            blocks: self.blocks(&command.args[1], depth + 1)?,
+                        },
+                        None => {
+                            let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+                            Item {
+                                term: None,
+                                blocks: self.blocks(arg, depth + 1)?,
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    let end = rest.iter().position(is_item).unwrap_or(rest.len());
+                    let blocks = self.blocks(&rest[..end], depth + 1)?;
+                    rest = &rest[end..];
+                    if blocks.is_empty() {
+                        continue;
+                    }
+                    Item { term: None, blocks }
+                }
+            };
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    /// Adds the blocks of each `\bibitem` of a `references` environment, whose first paragraph
+    /// starts with the number of the entry in brackets. Content other than the `\bibitem`s is
+    /// exported as it is.
+    fn references(
+        &mut self,
+        nodes: &[Node],
+        depth: usize,
+        blocks: &mut Vec<Block>,
+    ) -> Result<(), Error> {
+        for node in nodes {
+            let command = match *node {
+                Node::Command(ref command) if command.name == "bibitem" => command,
+                ref node => {
+                    blocks.extend(self.blocks(slice::from_ref(node), depth + 1)?);
+                    continue;
+                }
+            };
+            let key = bibliography::bibitem_key(command);
+            let label = format!("[{}]", bibliography::number_text(self.citations.number(&key)));
+            let arg = command.args.get(1).map_or(&[][..], |arg| &arg[..]);
+            let mut entry = self.blocks(arg, depth + 1)?;
+            match entry.first_mut() {
+                Some(Block::Paragraph { ref mut text, .. }) => {
+                    *text = format!("{} {}", label, text);
+                }
+                _ => entry.insert(0, paragraph(label, Vec::new(), Vec::new())),
+            }
+            blocks.extend(entry);
+        }
+        Ok(())
+    }
+
+    /// Adds a table, followed by the footnotes of its cells and caption. Rows with fewer cells
+    /// than the table has columns are filled out with empty cells.
+    fn table(&mut self, table: &Table, depth: usize, blocks: &mut Vec<Block>) -> Result<(), Error> {
+        let count = table.column_count();
+        let mut inline = Inline::default();
+        let mut rows = Vec::new();
+        for row in &table.rows {
+            let mut cells = Vec::new();
+            for column in 0..count {
+                if let Some(nodes) = row.get(column) {
+                    self.inlines(nodes, depth + 1, &mut inline)?;
+                }
+                cells.push(collapse(&mem::take(&mut inline.text)));
+            }
+            rows.push(cells);
+        }
+        self.inlines(&table.caption, depth + 1, &mut inline)?;
+        blocks.push(Block::Table {
+            header: table.header,
+            rows,
+            caption: non_empty(&inline.text),
+            links: inline.links,
+            images: inline.images,
+        });
+        push_notes(inline.notes, blocks);
+        Ok(())
+    }
+
+    /// Returns the text of inline content, such as a title, without its links, images, or
+    /// footnotes.
+    fn text(&mut self, nodes: &[Node]) -> Result<String, Error> {
+        let mut inline = Inline::default();
+        self.inlines(nodes, 1, &mut inline)?;
+        Ok(collapse(&inline.text))
+    }
+
+    /// Adds the text, links, images, and footnotes of a list of nodes to `inline`. Blocks within
+    /// inline content are flattened into its text.
+    fn inlines(&mut self, nodes: &[Node], depth: usize, inline: &mut Inline) -> Result<(), Error> {
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        for node in nodes {
+            match *node {
+                Node::Text(ref text) => inline.text.push_str(text),
+                Node::Paragraph(ref nodes) | Node::Group(ref nodes) => {
+                    self.inlines(nodes, depth + 1, inline)?
+                }
+                Node::Command(ref command) => self.inline_command(command, depth, inline)?,
+                Node::Environment(ref env) => {
+                    inline.text.push(' ');
+                    self.inlines(&env.nodes, depth + 1, inline)?;
+                    inline.text.push(' ');
+                }
+                Node::Table(ref table) => {
+                    for cell in table.rows.iter().flatten() {
+                        inline.text.push(' ');
+                        self.inlines(cell, depth + 1, inline)?;
+                    }
+                    inline.text.push(' ');
+                }
+                Node::Verbatim(ref verbatim) => inline.text.push_str(&verbatim.content),
+                Node::Raw(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the content of a command to `inline`.
+    fn inline_command(
+        &mut self,
+        command: &Command,
+        depth: usize,
+        inline: &mut Inline,
+    ) -> Result<(), Error> {
+        let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+        match &*command.name {
+            "code" => inline.text.push_str(&ast::text_content(arg)),
+            "break" => inline.text.push(' '),
+            "footnote" => {
+                let blocks = self.blocks(arg, depth + 1)?;
+                inline.notes.push(blocks);
+            }
+            "link" => {
+                let start = inline.text.len();
+                if has_link_text(command) {
+                    self.inlines(&command.args[1], depth + 1, inline)?;
+                } else {
+                    inline.text.push_str(&link_address(command));
+                }
+                let text = collapse(&inline.text[start..]);
+                inline.links.push(Link {
+                    text,
+                    url: link_url(command),
+                });
+            }
+            "image" => inline.images.push(Image::new(command, &mut self.report)),
+            "cite" => {
+                let marker = self.citations.marker(command, &mut self.report);
+                inline.text.push_str(&marker);
+            }
+            "abbr" => {
+                let abbreviation = self.abbreviations.use_abbreviation(command, &mut self.report);
+                inline.text.push_str(&abbreviation.text());
+            }
+            "ref" => {
+                let text = outline::reference_text(&self.outline, command, &mut self.report);
+                inline.text.push_str(&text);
+            }
+            "color" => {
+                if let Some(text) = command.args.get(1) {
+                    self.inlines(text, depth + 1, inline)?;
+                }
+            }
+            name if is_omitted(name) || is_horizontal_rule(name) => {}
+            name => {
+                if outline::heading_level(name).is_some() {
+                    self.headings.pop_front();
+                }
+                for arg in &command.args {
+                    self.inlines(arg, depth + 1, inline)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a paragraph block.
+fn paragraph(text: String, links: Vec<Link>, images: Vec<Image>) -> Block {
+    Block::Paragraph {
+        text,
+        links,
+        images,
+    }
+}
+
+/// Adds a note block for each footnote which has content.
+fn push_notes(notes: Vec<Vec<Block>>, blocks: &mut Vec<Block>) {
+    blocks.extend(
+        notes
+            .into_iter()
+            .filter(|note| !note.is_empty())
+            .map(|blocks| Block::Note { blocks }),
+    );
+}
+
+/// Closes the innermost open section, adding it to the section which contains it, or to the
+/// content if it is a top-level section.
+fn close(open: &mut Vec<Section>, content: &mut Content) {
+    if let Some(section) = open.pop() {
+        match open.last_mut() {
+            Some(parent) => parent.sections.push(section),
+            None => content.sections.push(section),
+        }
+    }
+}
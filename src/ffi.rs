@@ -0,0 +1,207 @@
+//! A C interface for rendering documents, for embedding in programs written in other languages.
+//!
+//! The header `include/formatting.h` is generated from this module by the build script when the
+//! `ffi` feature is enabled. The shared library is built with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+
+use std::any::Any;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use diagnostics::{Diagnostic, Diagnostics, Severity};
+use parser::Parser;
+use render::html::{HtmlOptions, HtmlRenderer};
+use render::plain::{self, PlainOptions};
+use render::markdown;
+
+/// Renders the document as HTML.
+pub const FORMATTING_HTML: c_int = 0;
+/// Renders the document as plain text.
+pub const FORMATTING_PLAIN: c_int = 1;
+/// Renders the document as Markdown.
+pub const FORMATTING_MARKDOWN: c_int = 2;
+
+/// The document was rendered.
+pub const FORMATTING_OK: c_int = 0;
+/// The document could not be parsed or rendered; the diagnostics hold the error.
+pub const FORMATTING_ERROR: c_int = 1;
+/// A pointer which must not be null was null, or the format was not recognized; the diagnostics
+/// describe which.
+pub const FORMATTING_INVALID_ARGUMENT: c_int = 2;
+/// The library panicked, which indicates a bug; the diagnostics hold the message of the panic.
+pub const FORMATTING_PANIC: c_int = 3;
+
+/// The result of `formatting_render`.
+///
+/// Each string is UTF-8, and is followed by a NUL byte which is not counted in its length. The
+/// output contains a NUL byte before its end only if the input did. Both strings are owned by the
+/// library, and must be released with `formatting_result_free` rather than `free`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FormattingResult {
+    /// The rendered output, or null if the document could not be rendered.
+    pub output: *mut c_char,
+    /// The length of `output` in bytes, or zero if it is null.
+    pub output_len: usize,
+    /// The warnings and any error, as a JSON array in the format of `Diagnostics::to_json`. Never
+    /// null once `formatting_render` has returned.
+    pub diagnostics: *mut c_char,
+    /// The length of `diagnostics` in bytes.
+    pub diagnostics_len: usize,
+}
+
+/// Renders the `len` bytes of UTF-8 at `input` in the given format, one of `FORMATTING_HTML`,
+/// `FORMATTING_PLAIN`, and `FORMATTING_MARKDOWN`, with the default options, and stores the output
+/// and the diagnostics in `*out`. Returns `FORMATTING_OK` if the document was rendered, and one of
+/// the other `FORMATTING_` status codes otherwise. A panic within the library is caught and
+/// returned as `FORMATTING_PANIC`, rather than unwinding into the caller.
+///
+/// # Safety
+///
+/// `input` must point to `len` readable bytes, which are only borrowed for the duration of the
+/// call; it may be null only if `len` is zero. `out` must point to a `FormattingResult` which the
+/// caller may write to. Whatever `*out` held before is overwritten without being freed. Unless
+/// `out` is null, in which case `FORMATTING_INVALID_ARGUMENT` is returned and nothing is written,
+/// the caller owns the strings stored in `*out`, and must release them exactly once with
+/// `formatting_result_free`.
+#[no_mangle]
+pub unsafe extern "C" fn formatting_render(
+    input: *const c_char,
+    len: usize,
+    format: c_int,
+    out: *mut FormattingResult,
+) -> c_int {
+    if out.is_null() {
+        return FORMATTING_INVALID_ARGUMENT;
+    }
+    let (status, output, diagnostics) = if input.is_null() && len != 0 {
+        invalid_argument("`input` is null, but `len` is not zero")
+    } else {
+        let input = if len == 0 {
+            &[][..]
+        } else {
+            slice::from_raw_parts(input as *const u8, len)
+        };
+        match panic::catch_unwind(AssertUnwindSafe(|| render(input, format))) {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = format!("the library panicked: {}", panic_message(&*payload));
+                (FORMATTING_PANIC, None, error_diagnostics(message))
+            }
+        }
+    };
+    let (output, output_len) = match output {
+        Some(output) => into_raw(output),
+        None => (ptr::null_mut(), 0),
+    };
+    let (diagnostics, diagnostics_len) = into_raw(diagnostics.to_json().into_bytes());
+    ptr::write(
+        out,
+        FormattingResult {
+            output,
+            output_len,
+            diagnostics,
+            diagnostics_len,
+        },
+    );
+    status
+}
+
+/// Releases the strings of a `FormattingResult` filled in by `formatting_render`, and sets its
+/// pointers to null and its lengths to zero, so that releasing it again does nothing. Does nothing
+/// if `result` is null. The `FormattingResult` itself is owned by the caller, and is not freed.
+///
+/// # Safety
+///
+/// `result` must be null, or point to a `FormattingResult` which was filled in by
+/// `formatting_render` or has already been passed to this function, and whose fields have not
+/// been changed since. The strings must not be used once they have been released.
+#[no_mangle]
+pub unsafe extern "C" fn formatting_result_free(result: *mut FormattingResult) {
+    if result.is_null() {
+        return;
+    }
+    let result = &mut *result;
+    free_raw(result.output, result.output_len);
+    free_raw(result.diagnostics, result.diagnostics_len);
+    *result = FormattingResult {
+        output: ptr::null_mut(),
+        output_len: 0,
+        diagnostics: ptr::null_mut(),
+        diagnostics_len: 0,
+    };
+}
+
+/// Parses and renders the input, returning the status, the output if there is any, and the
+/// diagnostics.
+fn render(input: &[u8], format: c_int) -> (c_int, Option<Vec<u8>>, Diagnostics) {
+    if ![FORMATTING_HTML, FORMATTING_PLAIN, FORMATTING_MARKDOWN].contains(&format) {
+        return invalid_argument(&format!("{} is not a format", format));
+    }
+    let mut diagnostics = Diagnostics::new();
+    let mut parser = Parser::new(input);
+    let document = parser.parse();
+    diagnostics.extend(parser.warnings().iter().cloned());
+    let rendered = document.and_then(|document| {
+        let mut output = Vec::new();
+        let report = match format {
+            FORMATTING_HTML => HtmlRenderer::new(HtmlOptions::new()).render(&document, &mut output),
+            FORMATTING_PLAIN => plain::render(&document, &PlainOptions::default(), &mut output),
+            _ => markdown::render(&document, &mut output),
+        }?;
+        diagnostics.extend(report.warnings().iter().cloned());
+        Ok(output)
+    });
+    match rendered {
+        Ok(output) => (FORMATTING_OK, Some(output), diagnostics),
+        Err(error) => {
+            diagnostics.push_error(&error);
+            (FORMATTING_ERROR, None, diagnostics)
+        }
+    }
+}
+
+/// Returns the result of a call with an invalid argument.
+fn invalid_argument(message: &str) -> (c_int, Option<Vec<u8>>, Diagnostics) {
+    let message = format!("invalid argument: {}", message);
+    (FORMATTING_INVALID_ARGUMENT, None, error_diagnostics(message))
+}
+
+/// Returns diagnostics holding a single error with the given message, for a failure which is not
+/// described by an `Error`.
+fn error_diagnostics(message: String) -> Diagnostics {
+    let mut diagnostic = Diagnostic::note(message, None);
+    diagnostic.severity = Severity::Error;
+    let mut diagnostics = Diagnostics::new();
+    diagnostics.push(diagnostic);
+    diagnostics
+}
+
+/// Returns the message of a panic, if it has one.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("", String::as_str),
+    }
+}
+
+/// Releases ownership of a string, with a NUL byte appended, returning a pointer to it and its
+/// length without the NUL byte.
+fn into_raw(mut bytes: Vec<u8>) -> (*mut c_char, usize) {
+    let len = bytes.len();
+    bytes.push(0);
+    (Box::into_raw(bytes.into_boxed_slice()) as *mut c_char, len)
+}
+
+/// Frees a string returned by `into_raw`, unless the pointer is null.
+///
+/// # Safety
+///
+/// The pointer and length must have been returned by `into_raw`, and not freed since.
+unsafe fn free_raw(string: *mut c_char, len: usize) {
+    if !string.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(string as *mut u8, len + 1)));
+    }
+}
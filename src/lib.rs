@@ -0,0 +1,90 @@
+//! `convert` parses a document and renders it in any of the output formats in a single call; the
+//! modules below give control over each stage.
+//!
+//! Without the default `std` feature, the crate needs only `core` and `alloc`, and provides the
+//! AST, the errors and diagnostics, the lints, dates, lengths, and the tokenizer over a `&str`,
+//! `StrTokens`. The parser, the renderers, `Date::today`, and everything else which reads or
+//! writes through `std::io` or the system clock require `std`.
+//!
+//! No input makes the tokenizers, the parser, or the renderers panic or run forever: arbitrary
+//! bytes produce either `Ok` or `Err`. Nesting and the expansion of user-defined commands are
+//! limited, so that deeply nested or self-referential input is reported as an error, rather than
+//! overflowing the stack or exhausting memory. So are the lengths of lines, command names, and
+//! verbatim content, as set by `limits::Limits`. The targets in `fuzz` check this.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "macros")]
+extern crate formatting_macros;
+#[cfg(feature = "hyphenation")]
+extern crate hypher;
+#[cfg(feature = "watch")]
+extern crate notify;
+#[cfg(feature = "testing")]
+extern crate proptest;
+#[cfg(feature = "terminal")]
+extern crate terminal_size;
+#[macro_use]
+extern crate thiserror;
+#[cfg(feature = "serde")]
+extern crate toml;
+#[cfg(feature = "bidi")]
+extern crate unicode_bidi;
+#[cfg(feature = "unicode")]
+extern crate unicode_segmentation;
+#[cfg(feature = "unicode-width")]
+extern crate unicode_width;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
+pub mod ast;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod bibliography;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "serde")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod convert;
+pub mod date;
+pub mod diagnostics;
+pub mod errors;
+#[cfg(feature = "serde")]
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod limits;
+pub mod lints;
+pub mod locale;
+#[cfg(feature = "std")]
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod render;
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tokenize;
+pub mod values;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod watch;
+
+#[cfg(feature = "std")]
+pub use convert::{convert, convert_str_to_string, Options, OutputFormat, Report};
+#[cfg(feature = "macros")]
+pub use formatting_macros::{check, doc};
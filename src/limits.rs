@@ -0,0 +1,168 @@
+//! Limits on the sizes of the values which the tokenizer buffers, so that untrusted input fails
+//! with an error before it exhausts memory, and on the total work done to parse and render it,
+//! so that it fails before it takes excessive time or produces excessive output.
+
+use core::fmt;
+
+/// A limit set by `Limits`, which is named by `ErrorKind::LimitExceeded`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Limit {
+    /// The length of a command name, set by `Limits::max_name_length`.
+    Name,
+    /// The length of the contents of a `\verbatim` or `\raw` command, set by
+    /// `Limits::max_verbatim_length`.
+    Verbatim,
+    /// The length of a line, set by `Limits::max_line_length`.
+    Line,
+    /// The number of tokens read from the input, set by `Limits::max_tokens`.
+    Tokens,
+    /// The number of expansions of user-defined commands and environments, set by
+    /// `Limits::max_expansions`.
+    Expansions,
+    /// The size of the rendered output, set by `Limits::max_output_size`.
+    Output,
+}
+
+impl Limit {
+    /// Returns the units in which the limit is given, such as `bytes`.
+    pub fn units(self) -> &'static str {
+        match self {
+            Limit::Name | Limit::Verbatim | Limit::Line | Limit::Output => "bytes",
+            Limit::Tokens => "tokens",
+            Limit::Expansions => "expansions",
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Name => f.write_str("The length of a command name"),
+            Limit::Verbatim => f.write_str("The length of verbatim content"),
+            Limit::Line => f.write_str("The length of a line"),
+            Limit::Tokens => f.write_str("The number of tokens"),
+            Limit::Expansions => f.write_str("The number of macro expansions"),
+            Limit::Output => f.write_str("The size of the output"),
+        }
+    }
+}
+
+/// The largest values which the tokenizer reads, each as a number of bytes, and the most work
+/// which parsing and rendering a document may take. Input which exceeds one fails with
+/// `ErrorKind::LimitExceeded` as soon as it is read past the limit, so that no more than the
+/// limit is ever held in memory.
+///
+/// The defaults of the limits on values are far larger than any document written by hand needs,
+/// but small enough to buffer: 1 KiB for command names, 16 MiB for lines, and 64 MiB for verbatim
+/// content. The limits on the work done are unlimited by default, and are meant to be set by
+/// services which convert documents they do not trust.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    max_name_length: usize,
+    max_verbatim_length: usize,
+    max_line_length: usize,
+    max_tokens: usize,
+    max_expansions: usize,
+    max_output_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_name_length: 1 << 10,
+            max_verbatim_length: 1 << 26,
+            max_line_length: 1 << 24,
+            max_tokens: usize::MAX,
+            max_expansions: usize::MAX,
+            max_output_size: usize::MAX,
+        }
+    }
+}
+
+impl Limits {
+    /// Constructs a new `Limits` with the default limits.
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// Sets the number of bytes above which a command name, not counting the backslash, fails
+    /// with `Limit::Name`. Defaults to 1 KiB.
+    pub fn max_name_length(mut self, length: usize) -> Limits {
+        self.max_name_length = length;
+        self
+    }
+
+    /// Returns the number of bytes above which a command name fails.
+    pub fn get_max_name_length(&self) -> usize {
+        self.max_name_length
+    }
+
+    /// Sets the number of bytes above which the contents of a `\verbatim` or `\raw` command fail
+    /// with `Limit::Verbatim`. Contents read in chunks, as set by `Tokens::verbatim_chunk_size`,
+    /// are never held in memory as a whole, so they are not limited. Defaults to 64 MiB.
+    pub fn max_verbatim_length(mut self, length: usize) -> Limits {
+        self.max_verbatim_length = length;
+        self
+    }
+
+    /// Returns the number of bytes above which the contents of a `\verbatim` or `\raw` command
+    /// fail.
+    pub fn get_max_verbatim_length(&self) -> usize {
+        self.max_verbatim_length
+    }
+
+    /// Sets the number of bytes, not counting the line ending, above which a line fails with
+    /// `Limit::Line`. Unlike `Lint::LongLine`, which only reports a long line, this bounds the
+    /// memory used to read one, since input is read a line at a time. Defaults to 16 MiB.
+    pub fn max_line_length(mut self, length: usize) -> Limits {
+        self.max_line_length = length;
+        self
+    }
+
+    /// Returns the number of bytes above which a line fails.
+    pub fn get_max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Sets the number of tokens read from the input, not counting those of a definitions file,
+    /// above which reading fails with `Limit::Tokens`. Text is read by the parser in runs, each a
+    /// single token, so this bounds the number of commands, groups, and other markup rather than
+    /// the length of the text. Defaults to unlimited.
+    pub fn max_tokens(mut self, count: usize) -> Limits {
+        self.max_tokens = count;
+        self
+    }
+
+    /// Returns the number of tokens above which reading fails.
+    pub fn get_max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    /// Sets the number of expansions of user-defined commands and environments, over the whole
+    /// document, above which parsing fails with `Limit::Expansions`. Unlike the bound on the
+    /// expansions between two tokens of the input, which stops a command which expands to itself,
+    /// this stops definitions which each expand to several uses of the next, whose expansions
+    /// grow exponentially with their number. Defaults to unlimited.
+    pub fn max_expansions(mut self, count: usize) -> Limits {
+        self.max_expansions = count;
+        self
+    }
+
+    /// Returns the number of expansions above which parsing fails.
+    pub fn get_max_expansions(&self) -> usize {
+        self.max_expansions
+    }
+
+    /// Sets the number of bytes of rendered output above which rendering fails with
+    /// `Limit::Output`, as enforced by `convert` and by `render::LimitWriter`. Defaults to
+    /// unlimited.
+    pub fn max_output_size(mut self, size: usize) -> Limits {
+        self.max_output_size = size;
+        self
+    }
+
+    /// Returns the number of bytes of output above which rendering fails.
+    pub fn get_max_output_size(&self) -> usize {
+        self.max_output_size
+    }
+}
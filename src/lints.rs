@@ -0,0 +1,270 @@
+//! Optional checks for suspicious but valid input, such as trailing whitespace, which are reported
+//! as warnings, or as errors if they are denied.
+
+use alloc::vec::Vec;
+
+use errors::{Error, ErrorKind, Position, Warning};
+
+/// How a lint is reported.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+    /// The lint is not checked.
+    Allow,
+    /// The lint is reported as a warning.
+    Warn,
+    /// The lint is reported as an `ErrorKind::Denied` error, which stops parsing.
+    Deny,
+}
+
+/// A check for suspicious input.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Lint {
+    /// A control character other than a tab or a line ending, reported as
+    /// `Warning::ControlCharacter`.
+    ControlCharacter,
+    /// A line with more characters than the limit set by `Lints::max_line_length`, reported as
+    /// `Warning::LongLine`.
+    LongLine,
+    /// Indentation containing both tabs and spaces, reported as `Warning::MixedIndentation`.
+    MixedIndentation,
+    /// Whitespace at the end of a line, reported as `Warning::TrailingWhitespace`.
+    TrailingWhitespace,
+    /// A `%` which starts a line, or a word following anything but a number, as a comment would
+    /// in LaTeX, reported as `Warning::PercentComment`. The input has no comments, so the `%` and
+    /// the rest of the line are rendered as text, which is seldom what was meant. A `%` following
+    /// a number, as in `50%` or `50 %`, is not reported.
+    PercentComment,
+}
+
+/// The lints, in the order in which their levels are stored.
+const LINTS: [Lint; 5] = [
+    Lint::ControlCharacter,
+    Lint::LongLine,
+    Lint::MixedIndentation,
+    Lint::TrailingWhitespace,
+    Lint::PercentComment,
+];
+
+/// The level of each lint, and the settings of those which have any.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lints {
+    levels: [Level; 5],
+    max_line_length: usize,
+}
+
+impl Default for Lints {
+    fn default() -> Lints {
+        Lints::all(Level::Allow)
+    }
+}
+
+impl Lints {
+    /// Constructs a new `Lints` with every lint allowed, so that none are checked.
+    pub fn new() -> Lints {
+        Lints::default()
+    }
+
+    /// Constructs a new `Lints` with every lint at the given level.
+    pub fn all(level: Level) -> Lints {
+        Lints {
+            levels: [level; 5],
+            max_line_length: 100,
+        }
+    }
+
+    /// Sets the level of a lint.
+    pub fn level(mut self, lint: Lint, level: Level) -> Lints {
+        self.levels[lint as usize] = level;
+        self
+    }
+
+    /// Raises the level of each lint which is below the given level to it, leaving the others, so
+    /// that `at_least(Level::Warn)` checks every lint, while those which are denied stay denied.
+    pub fn at_least(mut self, level: Level) -> Lints {
+        for lint in &mut self.levels {
+            *lint = (*lint).max(level);
+        }
+        self
+    }
+
+    /// Returns the level of a lint.
+    pub fn get_level(&self, lint: Lint) -> Level {
+        self.levels[lint as usize]
+    }
+
+    /// Sets the number of characters, not counting the line ending, above which a line is reported
+    /// by `Lint::LongLine`. Defaults to 100.
+    pub fn max_line_length(mut self, length: usize) -> Lints {
+        self.max_line_length = length;
+        self
+    }
+
+    /// Returns the number of characters above which a line is reported by `Lint::LongLine`.
+    pub fn get_max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Returns whether every lint is allowed.
+    fn allows_all(&self) -> bool {
+        LINTS.iter().all(|&lint| self.get_level(lint) == Level::Allow)
+    }
+
+    /// Checks a line of input, including its line ending, which starts at the given position.
+    /// Warnings are added to `warnings`, and the first denied lint, if any, is returned as an
+    /// error. Each lint is reported at most once per line.
+    pub(crate) fn check_line(
+        &self,
+        line: &str,
+        start: Position,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<(), Error> {
+        if self.allows_all() {
+            return Ok(());
+        }
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+        let at = |index: usize| Position {
+            line: start.line,
+            column: start.column + content[..index].chars().count(),
+            offset: start.offset + index,
+            utf16_column: start.utf16_column + content[..index].encode_utf16().count(),
+        };
+        let mut report = |lint: Lint, warning: Warning| match self.get_level(lint) {
+            Level::Allow => Ok(()),
+            Level::Warn => {
+                warnings.push(warning);
+                Ok(())
+            }
+            Level::Deny => Err(Error::from(ErrorKind::Denied(warning))),
+        };
+
+        let control = content
+            .char_indices()
+            .find(|&(_, c)| c.is_control() && c != '\t');
+        if let Some((index, c)) = control {
+            report(Lint::ControlCharacter, Warning::ControlCharacter(c, at(index)))?;
+        }
+        if let Some((index, _)) = content.char_indices().nth(self.max_line_length) {
+            let warning = Warning::LongLine(self.max_line_length, at(index));
+            report(Lint::LongLine, warning)?;
+        }
+        let indentation = &content[..content.len() - content.trim_start_matches([' ', '\t']).len()];
+        if let Some(first) = indentation.chars().next() {
+            if let Some(index) = indentation.find(|c| c != first) {
+                report(Lint::MixedIndentation, Warning::MixedIndentation(at(index)))?;
+            }
+        }
+        let trimmed = content.trim_end();
+        if trimmed.len() < content.len() {
+            let warning = Warning::TrailingWhitespace(at(trimmed.len()));
+            report(Lint::TrailingWhitespace, warning)?;
+        }
+        if let Some(index) = percent_comment(content) {
+            report(Lint::PercentComment, Warning::PercentComment(at(index)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the index of the first `%` in a line which looks like the start of a comment, as
+/// described for `Lint::PercentComment`.
+fn percent_comment(line: &str) -> Option<usize> {
+    line.match_indices('%').map(|(index, _)| index).find(|&index| {
+        let before = &line[..index];
+        let starts_word = before.is_empty() || before.ends_with(char::is_whitespace);
+        starts_word && !before.trim_end().ends_with(|c: char| c.is_ascii_digit())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// The position at which the lines checked start.
+    const START: Position = Position {
+        line: 3,
+        column: 1,
+        offset: 20,
+        utf16_column: 1,
+    };
+
+    /// Returns the position of the character at the given index in a line starting at `START`.
+    fn at(index: usize) -> Position {
+        Position {
+            line: 3,
+            column: index + 1,
+            offset: 20 + index,
+            utf16_column: index + 1,
+        }
+    }
+
+    /// Checks a line, returning the warnings.
+    fn check(lints: &Lints, line: &str) -> Result<Vec<Warning>, Error> {
+        let mut warnings = Vec::new();
+        lints.check_line(line, START, &mut warnings)?;
+        Ok(warnings)
+    }
+
+    /// A line for each lint which is reported by it alone, with the warning.
+    fn cases() -> [(Lint, &'static str, Warning); 5] {
+        [
+            (Lint::ControlCharacter, "a\u{7}b\n", Warning::ControlCharacter('\u{7}', at(1))),
+            (Lint::LongLine, "abcdefghijkl\n", Warning::LongLine(10, at(10))),
+            (Lint::MixedIndentation, " \tx\n", Warning::MixedIndentation(at(1))),
+            (Lint::TrailingWhitespace, "x \r\n", Warning::TrailingWhitespace(at(1))),
+            (Lint::PercentComment, "x % note\n", Warning::PercentComment(at(2))),
+        ]
+    }
+
+    #[test]
+    fn each_lint_is_reported_once() {
+        let lints = Lints::all(Level::Warn).max_line_length(10);
+        for (lint, line, warning) in cases() {
+            assert_eq!(check(&lints, line).unwrap(), [warning], "{:?}", lint);
+        }
+    }
+
+    #[test]
+    fn allowed_lints_are_silent() {
+        for (lint, line, _) in cases() {
+            let lints = Lints::all(Level::Warn).max_line_length(10).level(lint, Level::Allow);
+            assert_eq!(check(&lints, line).unwrap(), [], "{:?}", lint);
+            assert_eq!(check(&Lints::new(), line).unwrap(), [], "{:?}", lint);
+        }
+    }
+
+    #[test]
+    fn denied_lints_are_errors() {
+        for (lint, line, warning) in cases() {
+            let lints = Lints::new().max_line_length(10).level(lint, Level::Deny);
+            let error = check(&lints, line).unwrap_err();
+            assert_eq!(*error.kind(), ErrorKind::Denied(warning), "{:?}", lint);
+        }
+    }
+
+    #[test]
+    fn at_least_keeps_denied_lints() {
+        let lints = Lints::new()
+            .level(Lint::LongLine, Level::Deny)
+            .at_least(Level::Warn);
+        for lint in LINTS {
+            let level = if lint == Lint::LongLine { Level::Deny } else { Level::Warn };
+            assert_eq!(lints.get_level(lint), level, "{:?}", lint);
+        }
+    }
+
+    #[test]
+    fn percent_signs() {
+        let lints = Lints::new().level(Lint::PercentComment, Level::Warn);
+        let comments = [("% note", 0), ("  % note", 2), ("50% of it % note", 10)];
+        for &(line, index) in &comments {
+            let warning = Warning::PercentComment(at(index));
+            assert_eq!(check(&lints, line).unwrap(), [warning], "{}", line);
+        }
+        for line in &["50% of it", "50 % of it", "a%b", "\\verb|%|", "100%\n"] {
+            assert_eq!(check(&lints, line).unwrap(), [], "{}", line);
+        }
+    }
+}
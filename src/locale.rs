@@ -0,0 +1,280 @@
+//! Translations of the strings which the parser and the renderers write of their own, rather
+//! than take from the document.
+//!
+//! A `Locale` gives a translation for each of these keys:
+//!
+//! - `references`: the heading of the references section added by `bibliography::resolve`;
+//! - `index_start`: the location of the entries of the index which come before the first heading;
+//! - `contents`: the name of the table of contents, which labels it in HTML;
+//! - `date_format`: the format of the date written by `\today`, as described in the `date`
+//!   module, unless `ParserOptions::date_format` is set;
+//! - `months` and `months_short`: the names of the months, and their abbreviations, written by
+//!   the `%B` and `%b` conversions, as a comma-separated list of twelve, from January;
+//! - `weekdays` and `weekdays_short`: the names of the days of the week, and their
+//!   abbreviations, written by the `%A` and `%a` conversions, as a comma-separated list of seven,
+//!   from Monday.
+//!
+//! English is the default, and `Locale::builtin` also provides German and French. A locale file
+//! gives translations as `key = value` lines, with blank lines and lines starting with `#`
+//! ignored, and any keys it does not give keep their English translations:
+//!
+//! ```text
+//! # Swedish
+//! references = Referenser
+//! index_start = början
+//! date_format = %-d %B %Y
+//! months = januari, februari, mars, april, maj, juni, juli, augusti, september, oktober,
+//!     november, december
+//! ```
+//!
+//! A line starting with whitespace continues the value of the line before it.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use date;
+#[cfg(feature = "std")]
+use errors::ResultExt;
+use errors::{Error, ErrorKind, Position};
+
+/// The extension of a locale file.
+pub const EXTENSION: &str = "locale";
+
+/// The English translations, which are the default.
+const ENGLISH: &[(&str, &str)] = &[
+    ("references", "References"),
+    ("index_start", "start"),
+    ("contents", "Contents"),
+    ("date_format", date::DEFAULT_FORMAT),
+    (
+        "months",
+        "January, February, March, April, May, June, July, August, September, October, \
+         November, December",
+    ),
+    ("months_short", "Jan, Feb, Mar, Apr, May, Jun, Jul, Aug, Sep, Oct, Nov, Dec"),
+    (
+        "weekdays",
+        "Monday, Tuesday, Wednesday, Thursday, Friday, Saturday, Sunday",
+    ),
+    ("weekdays_short", "Mon, Tue, Wed, Thu, Fri, Sat, Sun"),
+];
+
+const GERMAN: &[(&str, &str)] = &[
+    ("references", "Literatur"),
+    ("index_start", "Anfang"),
+    ("contents", "Inhalt"),
+    ("date_format", "%-d. %B %Y"),
+    (
+        "months",
+        "Januar, Februar, März, April, Mai, Juni, Juli, August, September, Oktober, November, \
+         Dezember",
+    ),
+    ("months_short", "Jan, Feb, Mär, Apr, Mai, Jun, Jul, Aug, Sep, Okt, Nov, Dez"),
+    (
+        "weekdays",
+        "Montag, Dienstag, Mittwoch, Donnerstag, Freitag, Samstag, Sonntag",
+    ),
+    ("weekdays_short", "Mo, Di, Mi, Do, Fr, Sa, So"),
+];
+
+const FRENCH: &[(&str, &str)] = &[
+    ("references", "Références"),
+    ("index_start", "début"),
+    ("contents", "Table des matières"),
+    ("date_format", "%-d %B %Y"),
+    (
+        "months",
+        "janvier, février, mars, avril, mai, juin, juillet, août, septembre, octobre, \
+         novembre, décembre",
+    ),
+    (
+        "months_short",
+        "janv., févr., mars, avr., mai, juin, juil., août, sept., oct., nov., déc.",
+    ),
+    (
+        "weekdays",
+        "lundi, mardi, mercredi, jeudi, vendredi, samedi, dimanche",
+    ),
+    ("weekdays_short", "lun., mar., mer., jeu., ven., sam., dim."),
+];
+
+/// The names of the built-in locales, and their translations.
+const BUILTINS: &[(&str, &[(&str, &str)])] = &[("en", ENGLISH), ("de", GERMAN), ("fr", FRENCH)];
+
+/// The translations of the strings written by the parser and the renderers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Locale {
+    references: String,
+    index_start: String,
+    contents: String,
+    date_format: String,
+    months: Vec<String>,
+    months_short: Vec<String>,
+    weekdays: Vec<String>,
+    weekdays_short: Vec<String>,
+}
+
+impl Default for Locale {
+    fn default() -> Locale {
+        Locale::from_table(ENGLISH)
+    }
+}
+
+impl Locale {
+    /// Constructs a new `Locale` with the English translations.
+    pub fn new() -> Locale {
+        Locale::default()
+    }
+
+    /// Returns the built-in locale with the given name: `en` for English, `de` for German, or
+    /// `fr` for French. Returns `None` for any other name.
+    pub fn builtin(name: &str) -> Option<Locale> {
+        BUILTINS
+            .iter()
+            .find(|&&(builtin, _)| builtin == name)
+            .map(|&(_, table)| Locale::from_table(table))
+    }
+
+    fn from_table(table: &[(&str, &str)]) -> Locale {
+        let mut locale = Locale {
+            references: String::new(),
+            index_start: String::new(),
+            contents: String::new(),
+            date_format: String::new(),
+            months: Vec::new(),
+            months_short: Vec::new(),
+            weekdays: Vec::new(),
+            weekdays_short: Vec::new(),
+        };
+        for &(key, value) in table {
+            // The built-in tables are complete and valid.
+            let _ = locale.set(key, value);
+        }
+        locale
+    }
+
+    /// Parses the text of a locale file, starting from the English translations. Invalid lines,
+    /// unknown keys, and lists of names of the wrong length are reported as
+    /// `ErrorKind::LocaleSyntax`.
+    pub fn parse(text: &str) -> Result<Locale, Error> {
+        let mut locale = Locale::default();
+        let mut entries: Vec<(&str, String, Position)> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let position = Position {
+                line: i + 1,
+                column: 1,
+                offset: line.as_ptr() as usize - text.as_ptr() as usize,
+                utf16_column: 1,
+            };
+            let error = |message: String| {
+                Error::from(ErrorKind::LocaleSyntax { message, position })
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) {
+                match entries.last_mut() {
+                    Some(&mut (_, ref mut value, _)) => {
+                        value.push(' ');
+                        value.push_str(line.trim());
+                    }
+                    None => return Err(error("a continuation line before the first key".into())),
+                }
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => entries.push((key.trim(), value.trim().to_owned(), position)),
+                None => return Err(error("expected `key = value`".into())),
+            }
+        }
+        for (key, value, position) in entries {
+            locale
+                .set(key, &value)
+                .map_err(|message| ErrorKind::LocaleSyntax { message, position })?;
+        }
+        Ok(locale)
+    }
+
+    /// Loads the named locale file, as with `parse`. Any errors are reported with the name of the
+    /// file. Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Locale, Error> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).context(ErrorKind::Open(name.clone()))?;
+        Locale::parse(&text).map_err(|e| e.with_file(name))
+    }
+
+    /// Sets the translation of a key, or returns a message describing why it cannot be set.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let (list, len) = match key {
+            "references" => return set_string(&mut self.references, value),
+            "index_start" => return set_string(&mut self.index_start, value),
+            "contents" => return set_string(&mut self.contents, value),
+            "date_format" => return set_string(&mut self.date_format, value),
+            "months" => (&mut self.months, 12),
+            "months_short" => (&mut self.months_short, 12),
+            "weekdays" => (&mut self.weekdays, 7),
+            "weekdays_short" => (&mut self.weekdays_short, 7),
+            _ => return Err(format!("unknown key `{}`", key)),
+        };
+        let names: Vec<String> = value.split(',').map(|name| name.trim().to_owned()).collect();
+        if names.len() != len || names.iter().any(String::is_empty) {
+            return Err(format!("`{}` must be {} comma-separated names", key, len));
+        }
+        *list = names;
+        Ok(())
+    }
+
+    /// Returns the heading of the references section.
+    pub fn references(&self) -> &str {
+        &self.references
+    }
+
+    /// Returns the location of the entries of the index before the first heading.
+    pub fn index_start(&self) -> &str {
+        &self.index_start
+    }
+
+    /// Returns the name of the table of contents.
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// Returns the format of the date written by `\today`.
+    pub fn date_format(&self) -> &str {
+        &self.date_format
+    }
+
+    /// Returns the name of a month, from 1 for January to 12, or its abbreviation if `short` is
+    /// set.
+    pub fn month(&self, month: u32, short: bool) -> &str {
+        let names = if short { &self.months_short } else { &self.months };
+        names.get(month as usize - 1).map_or("", |name| &**name)
+    }
+
+    /// Returns the name of a day of the week, from 0 for Monday to 6 for Sunday, or its
+    /// abbreviation if `short` is set.
+    pub fn weekday(&self, weekday: u32, short: bool) -> &str {
+        let names = if short {
+            &self.weekdays_short
+        } else {
+            &self.weekdays
+        };
+        names.get(weekday as usize).map_or("", |name| &**name)
+    }
+}
+
+/// Sets a translation which is a single string, which may not be empty.
+fn set_string(field: &mut String, value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("a translation may not be empty".into());
+    }
+    *field = value.to_owned();
+    Ok(())
+}
@@ -1,10 +1,932 @@
-extern crate failure;
+//! A command-line interface to the tokenizer, the parser, and the renderers.
+//!
+//! Errors and warnings are written to standard error, and the process exits with the code given
+//! by `Error::exit_code`, or 64 (`EX_USAGE`) for invalid arguments. `check` exits with the code of
+//! the first input which failed, or 65 (`EX_DATAERR`) if only denied warnings were produced.
+
+extern crate formatting;
+#[cfg(feature = "serde")]
 #[macro_use]
-extern crate failure_derive;
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use formatting::ast::Document;
+use formatting::batch::{self, BatchReport};
+use formatting::convert::{self, Options, OutputFormat, Report};
+use formatting::diagnostics::Diagnostic;
+use formatting::errors::{Error, ErrorKind, Position, ResultExt, Warning};
+use formatting::lints::Level;
+use formatting::parser::{Parser, ParserOptions};
+use formatting::render::ansi::AnsiOptions;
+use formatting::render::plain::PlainOptions;
+use formatting::render::source;
+use formatting::tokenize::{Token, Tokens};
+use formatting::watch::Watcher;
+
+#[cfg(feature = "cache")]
+use formatting::cache;
+#[cfg(feature = "serde")]
+use formatting::config;
+#[cfg(feature = "serde")]
+use formatting::diagnostics::Severity;
+
+const USAGE: &str = "\
+usage: formatting tokens [--json] FILE
+       formatting ast [--config CONFIG] FILE
+       formatting expand [--config CONFIG] FILE
+       formatting render --to FORMAT [-o OUTPUT] [--width N] [--watch] [--stats] [--config CONFIG]
+                         [--cache-dir DIR] [--depfile DEPFILE] FILE
+       formatting render --list-deps [--config CONFIG] FILE
+       formatting check [--strict] [--deny-warnings] [--json] [--config CONFIG] PATH...
+
+Reads FILE, or standard input if FILE is `-`. Each PATH of `check` is a file, a directory, which
+is searched for `.mle` files, or a pattern in which `*` and `?` match within a file name. The
+bibliography files named by `\\bibliography` are read from the directory of each input, or the
+current directory for standard input.
+
+The options of the parser and the renderers are read from CONFIG, or else from `formatting.toml`
+in the directory of FILE, or in the current directory for `check` and standard input, if it
+exists. Options given on the command line override those of the file.
+
+commands:
+    tokens    writes each token of the input, with its line and column
+    ast       writes the document parsed from the input
+    expand    writes the document parsed from the input as source, with its macros and
+              conditionals expanded, in the layout set by the `source` options
+    render    writes the document rendered in the given format
+    check     parses each input without writing any output, and reports the diagnostics
+
+options:
+    --json            writes the tokens as JSON lines, or the diagnostics as JSON (requires the
+                      `serde` feature)
+    --strict          checks every lint, reporting each as a warning, or as an error if the
+                      configuration denies it
+    --deny-warnings   treats warnings as errors
+    --to FORMAT       the format to render to: html, plain, ansi, markdown, latex, roff, or
+                      pandoc, which is pandoc's JSON AST (requires the `serde` feature)
+    -o, --output OUTPUT
+                      writes the output to OUTPUT rather than standard output
+    --width N         wraps plain and ansi output to N columns, and lays it out in them, rather
+                      than the width of the terminal, if standard output is one, or else
+                      `COLUMNS` or 80
+    --watch           renders again whenever FILE changes, until interrupted (requires `-o`)
+    --stats           writes the number of lines, bytes, and tokens read, the greatest nesting
+                      depth, the number of macro expansions, and the time taken by each phase to
+                      standard error after rendering
+    --config CONFIG   reads the options from CONFIG (requires the `serde` feature)
+    --cache-dir DIR   keeps the document parsed from FILE in DIR, and reads it from there rather
+                      than parsing FILE again while FILE, its definitions file, the options, and
+                      the version of the program are unchanged; a cache which cannot be read is
+                      ignored, and a document which gave warnings is not cached, so that they are
+                      shown each time (requires the `cache` feature)
+    --list-deps       writes the files which rendering FILE reads, other than FILE and CONFIG,
+                      one per line, rather than rendering it: the definitions file, if any, and
+                      the bibliography files, including those which do not exist
+    --depfile DEPFILE
+                      writes a Makefile rule to DEPFILE before rendering, making OUTPUT depend on
+                      FILE and the files listed by `--list-deps` (requires `-o`)
+    -h, --help        shows this message";
+
+/// The exit code for invalid command-line arguments.
+const EX_USAGE: i32 = 64;
+
+/// The exit code of `check` when warnings were denied.
+const EX_DATAERR: i32 = 65;
+
+/// The extension of the files which `check` looks for in a directory.
+const EXTENSION: &str = "mle";
+
+/// A command given on the command line.
+#[derive(Debug)]
+enum Command {
+    Tokens { json: bool, input: String },
+    Ast {
+        config: Option<String>,
+        input: String,
+    },
+    Expand {
+        config: Option<String>,
+        input: String,
+    },
+    Render {
+        format: OutputFormat,
+        output: Option<String>,
+        config: Option<String>,
+        input: String,
+        width: Option<usize>,
+        cache_dir: Option<String>,
+        depfile: Option<String>,
+        watch: bool,
+        stats: bool,
+    },
+    Dependencies {
+        config: Option<String>,
+        input: String,
+    },
+    Check {
+        strict: bool,
+        deny_warnings: bool,
+        json: bool,
+        config: Option<String>,
+        inputs: Vec<String>,
+    },
+    Help,
+}
+
+/// Parses the command-line arguments, not including the name of the program.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err("no command given".into()),
+    };
+    if command == "-h" || command == "--help" {
+        return Ok(Command::Help);
+    }
+    let mut json = false;
+    let mut format = None;
+    let mut output = None;
+    let mut config = None;
+    let mut strict = false;
+    let mut deny_warnings = false;
+    let mut watch = false;
+    let mut stats = false;
+    let mut width = None;
+    let mut cache_dir = None;
+    let mut depfile = None;
+    let mut list_deps = false;
+    let mut inputs = Vec::new();
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--json" if command == "tokens" || command == "check" => json = true,
+            "--strict" if command == "check" => strict = true,
+            "--deny-warnings" if command == "check" => deny_warnings = true,
+            "--watch" if command == "render" => watch = true,
+            "--stats" if command == "render" => stats = true,
+            "--list-deps" if command == "render" => list_deps = true,
+            "--to" if command == "render" => {
+                let name = args.next().ok_or("`--to` requires a format")?;
+                format = match OutputFormat::from_name(&name) {
+                    Some(format) => Some(format),
+                    None if name == "pandoc" => {
+                        return Err("`--to pandoc` requires the `serde` feature".into());
+                    }
+                    None => return Err(format!("unknown format `{}`", name)),
+                };
+            }
+            "--width" if command == "render" => {
+                let n = args.next().ok_or("`--width` requires a number")?;
+                match n.parse() {
+                    Ok(n) if n > 0 => width = Some(n),
+                    _ => return Err(format!("invalid width `{}`", n)),
+                }
+            }
+            "--cache-dir" if command == "render" => match args.next() {
+                Some(path) => cache_dir = Some(path),
+                None => return Err("`--cache-dir` requires a directory".into()),
+            },
+            "--depfile" if command == "render" => match args.next() {
+                Some(path) => depfile = Some(path),
+                None => return Err("`--depfile` requires a file name".into()),
+            },
+            "-o" | "--output" if command == "render" => match args.next() {
+                Some(path) => output = Some(path),
+                None => return Err(format!("`{}` requires a file name", arg)),
+            },
+            "--config" if command != "tokens" => match args.next() {
+                Some(path) => config = Some(path),
+                None => return Err("`--config` requires a file name".into()),
+            },
+            "-" if command == "check" => {
+                return Err("`check` cannot read standard input".into());
+            }
+            "-" => inputs.push(arg),
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option `{}` for `{}`", arg, command));
+            }
+            _ if !inputs.is_empty() && command != "check" => {
+                return Err(format!("unexpected argument `{}`", arg));
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    if command == "check" && !inputs.is_empty() {
+        return Ok(Command::Check {
+            strict,
+            deny_warnings,
+            json,
+            config,
+            inputs,
+        });
+    }
+    let input = inputs
+        .pop()
+        .ok_or_else(|| format!("`{}` requires an input file", command))?;
+    match &*command {
+        "tokens" => Ok(Command::Tokens { json, input }),
+        "ast" => Ok(Command::Ast { config, input }),
+        "expand" => Ok(Command::Expand { config, input }),
+        "render" if list_deps => Ok(Command::Dependencies { config, input }),
+        "render" if depfile.is_some() && output.is_none() => {
+            Err("`--depfile` requires `-o`".into())
+        }
+        "render" if depfile.is_some() && input == "-" => {
+            Err("`--depfile` cannot read standard input".into())
+        }
+        "render" if watch && output.is_none() => Err("`--watch` requires `-o`".into()),
+        "render" if watch && input == "-" => Err("`--watch` cannot read standard input".into()),
+        "render" if cache_dir.is_some() && input == "-" => {
+            Err("`--cache-dir` cannot cache standard input".into())
+        }
+        "render" => match format {
+            Some(format) => Ok(Command::Render {
+                format,
+                output,
+                config,
+                input,
+                width,
+                cache_dir,
+                depfile,
+                watch,
+                stats,
+            }),
+            None => Err("`render` requires `--to`".into()),
+        },
+        _ => Err(format!("unknown command `{}`", command)),
+    }
+}
+
+/// An input file, or standard input.
+struct Input {
+    /// The name of the input, which is attached to errors and warnings.
+    name: String,
+    reader: Box<dyn BufRead>,
+}
+
+impl Input {
+    /// Opens the named file, or standard input if the name is `-`.
+    fn open(path: &str) -> Result<Input, Error> {
+        if path == "-" {
+            return Ok(Input {
+                name: "<stdin>".into(),
+                reader: Box::new(BufReader::new(io::stdin())),
+            });
+        }
+        let file = File::open(path).context(ErrorKind::Open(path.into()))?;
+        Ok(Input {
+            name: path.into(),
+            reader: Box::new(BufReader::new(file)),
+        })
+    }
+
+    /// Parses the input with the given options, reporting any warnings.
+    fn parse(self, options: ParserOptions) -> Result<Document, Error> {
+        let mut parser = Parser::with_options(self.reader, options).with_name(&*self.name);
+        let document = parser.parse();
+        warn(&self.name, parser.warnings());
+        document
+    }
+}
+
+/// Returns the options used without a configuration file: the default options, except that those
+/// of the ANSI renderer are found from the environment, as with `AnsiOptions::from_env`, as they
+/// are when loaded from a configuration file.
+fn default_options() -> Options {
+    let mut options = Options::default();
+    options.ansi = AnsiOptions::from_env();
+    options
+}
+
+/// Loads the named configuration file, or else `formatting.toml` in the directory of the named
+/// input, if it exists, or the current directory for standard input. Returns `default_options`
+/// if there is no configuration file.
+#[cfg(feature = "serde")]
+fn load_options(config: Option<&str>, input: &str) -> Result<Options, Error> {
+    let path = match config {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = directory(input).join(config::FILE_NAME);
+            if !path.is_file() {
+                return Ok(default_options());
+            }
+            path
+        }
+    };
+    let options = Options::from_file(&path)?;
+    warn(&path.display().to_string(), options.warnings());
+    Ok(options)
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_options(_config: Option<&str>, _input: &str) -> Result<Options, Error> {
+    Ok(default_options())
+}
+
+/// Writes the diagnostics of a conversion to standard error, with the name of the input.
+fn report(name: &str, report: &Report) {
+    for diagnostic in report.diagnostics() {
+        let mut diagnostic = diagnostic.clone();
+        diagnostic.file.get_or_insert_with(|| name.into());
+        eprintln!("{}\n", diagnostic);
+    }
+}
+
+/// Writes the given warnings to standard error, with the name of the input.
+fn warn(name: &str, warnings: &[Warning]) {
+    for warning in warnings {
+        let mut diagnostic = Diagnostic::warning(warning.clone());
+        diagnostic.file.get_or_insert_with(|| name.into());
+        eprintln!("{}\n", diagnostic);
+    }
+}
+
+/// A single token and the position at which it started, as written by `tokens --json`.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct PositionedToken<'a> {
+    position: Position,
+    token: &'a Token,
+}
+
+/// Writes each token of the input on its own line.
+fn tokens<W: Write>(input: Input, json: bool, out: &mut W) -> Result<(), Error> {
+    let mut tokens = Tokens::new(input.reader)
+        .coalesce_text(true)
+        .with_name(input.name);
+    while let Some(token) = tokens.next_res()? {
+        let position = tokens.location();
+        if json {
+            write_json(out, position, &token)?;
+        } else {
+            writeln!(out, "{}\t{:?}", position, token).context(ErrorKind::Write)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn write_json<W: Write>(
+    out: &mut W,
+    position: Position,
+    token: &Token,
+) -> Result<(), Error> {
+    serde_json::to_writer(&mut *out, &PositionedToken { position, token })
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(out))
+        .context(ErrorKind::Write)
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json<W: Write>(
+    _out: &mut W,
+    _position: Position,
+    _token: &Token,
+) -> Result<(), Error> {
+    unreachable!("`--json` is rejected without the `serde` feature")
+}
+
+/// Returns the files named by the arguments of `check`: each directory is replaced by the `.mle`
+/// files within it, recursively, and each pattern by the paths which match it. Other arguments are
+/// kept as they are, so that a missing file is reported when it is opened.
+fn expand_inputs(args: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        let paths = if arg.contains(['*', '?']) {
+            let paths = expand_pattern(Path::new(arg))?;
+            if paths.is_empty() {
+                return Err(ErrorKind::Open(arg.clone()).into());
+            }
+            paths
+        } else {
+            vec![PathBuf::from(arg)]
+        };
+        for path in paths {
+            if path.is_dir() {
+                find_files(&path, &mut inputs)?;
+            } else {
+                inputs.push(path);
+            }
+        }
+    }
+    Ok(inputs)
+}
+
+/// Returns the paths which match a pattern, in which `*` and `?` match within a single component.
+/// Hidden files are matched only by a component which starts with `.`.
+fn expand_pattern(pattern: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let name = match component {
+            Component::Normal(name) => name.to_string_lossy(),
+            _ => {
+                paths.iter_mut().for_each(|path| path.push(component));
+                continue;
+            }
+        };
+        if !name.contains(['*', '?']) {
+            paths.iter_mut().for_each(|path| path.push(&*name));
+            continue;
+        }
+        let mut matched = Vec::new();
+        for path in paths {
+            let directory = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                &path
+            };
+            // A path which is not a directory simply matches nothing.
+            let entries = match fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let entry = entry.context(ErrorKind::Open(directory.display().to_string()))?;
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                if (name.starts_with('.') || !file_name.starts_with('.'))
+                    && matches(name.as_bytes(), file_name.as_bytes())
+                {
+                    matched.push(path.join(&*file_name));
+                }
+            }
+        }
+        matched.sort();
+        paths = matched;
+    }
+    paths.retain(|path| path.exists());
+    Ok(paths)
+}
+
+/// Returns whether a file name matches a pattern, in which `*` matches any sequence of
+/// characters, and `?` matches any single character.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Some((b'?', rest)) => match str::from_utf8(name).ok().and_then(|s| s.chars().next()) {
+            Some(c) => matches(rest, &name[c.len_utf8()..]),
+            None => false,
+        },
+        Some((&c, rest)) => name.first() == Some(&c) && matches(rest, &name[1..]),
+    }
+}
+
+/// Adds the `.mle` files within a directory and its subdirectories to `files`, in order of name.
+fn find_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let context = || ErrorKind::Open(directory.display().to_string());
+    let mut entries = fs::read_dir(directory)
+        .and_then(|entries| entries.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>())
+        .with_context(|_| context())?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == EXTENSION) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The diagnostics of all of the inputs of `check`, as written by `check --json`.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct CheckReport<'a> {
+    diagnostics: Vec<&'a Diagnostic>,
+    inputs: usize,
+    failed: usize,
+    errors: usize,
+    warnings: usize,
+    notes: usize,
+}
+
+#[cfg(feature = "serde")]
+fn write_report<W: Write>(out: &mut W, report: &BatchReport<()>) -> Result<(), Error> {
+    let processed = report.processed();
+    let report = CheckReport {
+        diagnostics: processed
+            .iter()
+            .flat_map(|p| p.diagnostics.sorted())
+            .collect(),
+        inputs: processed.len(),
+        failed: report.failed(),
+        errors: report.count(Severity::Error),
+        warnings: report.count(Severity::Warning),
+        notes: report.count(Severity::Note),
+    };
+    serde_json::to_writer_pretty(&mut *out, &report)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(out))
+        .context(ErrorKind::Write)
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_report<W: Write>(_out: &mut W, _report: &BatchReport<()>) -> Result<(), Error> {
+    unreachable!("`--json` is rejected without the `serde` feature")
+}
+
+/// Checks each of the inputs, and writes the diagnostics to standard error, or as JSON to `out`.
+/// Returns the exit code: that of the first input which failed, if any, or `EX_DATAERR` if any
+/// warnings were denied.
+fn check<W: Write>(
+    inputs: &[String],
+    strict: bool,
+    deny_warnings: bool,
+    json: bool,
+    config: Option<&str>,
+    out: &mut W,
+) -> Result<i32, Error> {
+    let mut options = load_options(config, "-")?.parser;
+    if strict {
+        let lints = options.get_lints().clone().at_least(Level::Warn);
+        options = options.lints(lints);
+    }
+    let mut report = batch::check_all(&expand_inputs(inputs)?, &options);
+    if deny_warnings {
+        report.deny_warnings();
+    }
+    if json {
+        write_report(out, &report)?;
+    } else {
+        eprintln!("{}", report);
+    }
+    let failed = report.processed().iter().find_map(|p| p.result.as_ref().err());
+    Ok(match failed {
+        Some(error) => error.exit_code(),
+        None if report.has_errors() => EX_DATAERR,
+        None => 0,
+    })
+}
+
+/// Converts the input to the named file, or to standard output, and reports the warnings,
+/// followed by the statistics of the conversion if `stats` is set. Text is wrapped to `width`,
+/// if given, or else as described for `PlainOptions::get_width`.
+fn render_file(
+    input: &str,
+    format: OutputFormat,
+    config: Option<&str>,
+    path: Option<&str>,
+    width: Option<usize>,
+    cache_dir: Option<&str>,
+    stats: bool,
+) -> Result<(), Error> {
+    let mut options = load_options(config, input)?;
+    set_width(&mut options, width, path.is_none() && io::stdout().is_terminal());
+    if stats {
+        options.parser = options.parser.timings(true);
+    }
+    let mut out = Output::new(path);
+    let result = match input {
+        "-" => convert::convert(BufReader::new(io::stdin()), &mut out, format, &options)
+            .map_err(|e| if e.file().is_some() { e } else { e.with_file("<stdin>") }),
+        input => match cache_dir {
+            Some(directory) => parse_cached(input, &options.parser, Path::new(directory))
+                .and_then(|document| {
+                    convert::convert_document(document, input, &mut out, format, &options)
+                }),
+            None => convert::convert_file(input, &mut out, format, &options),
+        },
+    };
+    if let (Some(path), Some(error)) = (path, out.error.take()) {
+        return Err(error).context(ErrorKind::Open(path.into()));
+    }
+    let name = if input == "-" { "<stdin>" } else { input };
+    let result = result?;
+    report(name, &result);
+    if stats {
+        eprintln!("{}", result.stats());
+    }
+    Ok(())
+}
+
+/// Parses the named file, reading the document from the cache in `directory` instead if the file
+/// and its definitions file have not changed since it was cached, and the options and the version
+/// of the program are the same. Otherwise, or if the cache cannot be read, the file is parsed, its
+/// warnings reported, and the document cached, unless there were warnings, which are not cached,
+/// so that they are reported each time. Failing to write the cache is not an error.
+///
+/// The cache of each input is named after a hash of its path, and starts with hashes of the
+/// contents of the input and of the definitions file, followed by the cached document.
+#[cfg(feature = "cache")]
+fn parse_cached(input: &str, options: &ParserOptions, directory: &Path) -> Result<Document, Error> {
+    let source = fs::read(input).context(ErrorKind::Open(input.into()))?;
+    let definitions = options.get_definitions_file().and_then(|path| fs::read(path).ok());
+    let definitions = definitions.unwrap_or_default();
+    let mut header = Vec::with_capacity(16);
+    header.extend_from_slice(&cache::fingerprint(&source).to_le_bytes());
+    header.extend_from_slice(&cache::fingerprint(&definitions).to_le_bytes());
+    let path = fs::canonicalize(input).unwrap_or_else(|_| PathBuf::from(input));
+    let key = cache::fingerprint(path.to_string_lossy().as_bytes());
+    let path = directory.join(format!("{:016x}.cache", key));
+    if let Ok(cached) = fs::read(&path) {
+        if let Some(bytes) = cached.strip_prefix(&*header) {
+            if let Ok(document) = Document::from_cache_bytes(bytes, options) {
+                return Ok(document);
+            }
+        }
+    }
+    let mut parser = Parser::with_options(&*source, options.clone()).with_name(input);
+    let document = parser.parse();
+    warn(input, parser.warnings());
+    let document = document?;
+    if !parser.warnings().is_empty() {
+        return Ok(document);
+    }
+    header.extend_from_slice(&document.to_cache_bytes(options));
+    let _ = fs::create_dir_all(directory).and_then(|()| fs::write(&path, header));
+    Ok(document)
+}
+
+#[cfg(not(feature = "cache"))]
+fn parse_cached(
+    input: &str,
+    options: &ParserOptions,
+    _directory: &Path,
+) -> Result<Document, Error> {
+    Input::open(input)?.parse(options.clone())
+}
+
+/// Returns the files which rendering the named input reads, other than the input and the
+/// configuration file: the definitions file, if any, followed by the bibliography files the input
+/// names, whether or not they exist. The warnings of the parser are not reported, since rendering
+/// reports them.
+fn dependencies(input: &str, options: &Options) -> Result<Vec<PathBuf>, Error> {
+    let input = Input::open(input)?;
+    let parser = Parser::with_options(input.reader, options.parser.clone());
+    let document = parser.with_name(&*input.name).parse()?;
+    let definitions = options.parser.get_definitions_file().map(Path::to_owned);
+    let mut dependencies: Vec<PathBuf> = definitions.into_iter().collect();
+    dependencies.extend(document.dependencies(directory(&input.name)));
+    Ok(dependencies)
+}
 
-mod errors;
-mod parser;
+/// Writes a Makefile rule to the file at `path`, making `target` depend on the input and on the
+/// files it reads.
+fn write_depfile(
+    path: &str,
+    target: &str,
+    input: &str,
+    dependencies: &[PathBuf],
+) -> Result<(), Error> {
+    let mut rule = escape_make(target);
+    rule.push(':');
+    rule.push(' ');
+    rule.push_str(&escape_make(input));
+    for dependency in dependencies {
+        rule.push(' ');
+        rule.push_str(&escape_make(&dependency.to_string_lossy()));
+    }
+    rule.push('\n');
+    fs::write(path, rule).context(ErrorKind::Open(path.into()))
+}
+
+/// Escapes a file name for a Makefile rule: whitespace and `#` are preceded by a backslash, and
+/// `$` is doubled.
+fn escape_make(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            ' ' | '\t' | '#' => escaped.push('\\'),
+            '$' => escaped.push('$'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Sets the width of the plain-text and ANSI output, if given, to which paragraphs are then
+/// wrapped, and whether it is written to a terminal.
+fn set_width(options: &mut Options, width: Option<usize>, terminal: bool) {
+    let layout = |plain: PlainOptions| match width {
+        Some(width) => plain.terminal(terminal).width(width).wrap(true),
+        None => plain.terminal(terminal),
+    };
+    options.plain = layout(mem::take(&mut options.plain));
+    let plain = layout(options.ansi.get_plain().clone());
+    options.ansi = mem::take(&mut options.ansi).plain(plain);
+}
+
+/// Renders the input to the named file, and again whenever it changes, until the process is
+/// interrupted. Each build is followed by a status line on standard error, after any
+/// diagnostics; a failed build is reported, and the input is watched as before. The configuration
+/// file is read again for each build.
+fn watch(
+    input: &str,
+    format: OutputFormat,
+    config: Option<&str>,
+    path: &str,
+    width: Option<usize>,
+    cache_dir: Option<&str>,
+    stats: bool,
+) -> ! {
+    // The watcher is started first, so that a change made during the first build is not missed.
+    let mut watcher = Watcher::new([input]);
+    loop {
+        let status = match render_file(input, format, config, Some(path), width, cache_dir, stats) {
+            Ok(()) => format!("rendered `{}` to `{}`", input, path),
+            Err(error) => {
+                eprintln!("{}
+", Diagnostic::error(&error));
+                format!("failed to render `{}`", input)
+            }
+        };
+        eprintln!("[{}] {}; watching for changes", timestamp(), status);
+        watcher.wait();
+    }
+}
+
+/// Returns the current time of day in UTC, as `HH:MM:SS`.
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Returns the directory of the named input, in which its configuration file and the bibliography
+/// files it names are looked for, or the current directory for standard input.
+fn directory(input: &str) -> &Path {
+    match input {
+        "-" => Path::new(""),
+        input => Path::new(input).parent().unwrap_or_else(|| Path::new("")),
+    }
+}
+
+/// The output of `render`: the named file, which is created only once something is written to it,
+/// so that it is not left empty by a syntax error, or else standard output.
+struct Output<'a> {
+    path: Option<&'a str>,
+    writer: Option<Box<dyn Write>>,
+    /// The error which stopped the file from being created, to be reported with its name.
+    error: Option<io::Error>,
+}
+
+impl<'a> Output<'a> {
+    fn new(path: Option<&'a str>) -> Output<'a> {
+        Output {
+            path,
+            writer: None,
+            error: None,
+        }
+    }
+
+    /// Returns the writer, creating the file if it has not yet been created.
+    fn writer(&mut self) -> io::Result<&mut Box<dyn Write>> {
+        let writer: Box<dyn Write> = match (self.writer.take(), self.path) {
+            (Some(writer), _) => writer,
+            (None, Some(path)) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(error) => {
+                    let copy = io::Error::new(error.kind(), error.to_string());
+                    self.error = Some(error);
+                    return Err(copy);
+                }
+            },
+            (None, None) => Box::new(BufWriter::new(io::stdout())),
+        };
+        Ok(self.writer.insert(writer))
+    }
+}
+
+impl<'a> Write for Output<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer()?.flush()
+    }
+}
+
+fn run(command: Command) -> Result<i32, Error> {
+    let mut out = BufWriter::new(io::stdout());
+    let mut code = 0;
+    if let Command::Render {
+        output: Some(ref target),
+        depfile: Some(ref depfile),
+        ref config,
+        ref input,
+        ..
+    } = command
+    {
+        let options = load_options(config.as_deref(), input)?;
+        write_depfile(depfile, target, input, &dependencies(input, &options)?)?;
+    }
+    match command {
+        Command::Tokens { json, input } => tokens(Input::open(&input)?, json, &mut out)?,
+        Command::Ast { config, input } => {
+            let options = load_options(config.as_deref(), &input)?;
+            let document = Input::open(&input)?.parse(options.parser)?;
+            writeln!(out, "{:#?}", document).context(ErrorKind::Write)?;
+        }
+        Command::Expand { config, input } => {
+            let options = load_options(config.as_deref(), &input)?;
+            let document = Input::open(&input)?.parse(options.parser)?;
+            source::render(&document, &options.source, &mut out)?;
+        }
+        Command::Render {
+            format,
+            output: Some(ref path),
+            ref config,
+            ref input,
+            width,
+            ref cache_dir,
+            watch: true,
+            stats,
+            ..
+        } => watch(input, format, config.as_deref(), path, width, cache_dir.as_deref(), stats),
+        Command::Render {
+            format,
+            output: ref path,
+            ref config,
+            ref input,
+            width,
+            ref cache_dir,
+            stats,
+            ..
+        } => {
+            let cache_dir = cache_dir.as_deref();
+            render_file(input, format, config.as_deref(), path.as_deref(), width, cache_dir, stats)?
+        }
+        Command::Dependencies { config, input } => {
+            let options = load_options(config.as_deref(), &input)?;
+            for dependency in dependencies(&input, &options)? {
+                writeln!(out, "{}", dependency.display()).context(ErrorKind::Write)?;
+            }
+        }
+        Command::Check {
+            strict,
+            deny_warnings,
+            json,
+            config,
+            inputs,
+        } => code = check(&inputs, strict, deny_warnings, json, config.as_deref(), &mut out)?,
+        Command::Help => writeln!(out, "{}", USAGE).context(ErrorKind::Write)?,
+    }
+    out.flush().context(ErrorKind::Write)?;
+    Ok(code)
+}
 
 fn main() {
-    println!("Hello, world!");
+    let command = match parse_args(env::args().skip(1)) {
+        Ok(Command::Tokens { json: true, .. }) | Ok(Command::Check { json: true, .. })
+            if cfg!(not(feature = "serde")) =>
+        {
+            Err("`--json` requires the `serde` feature".to_string())
+        }
+        Ok(Command::Ast {
+            config: Some(_), ..
+        })
+        | Ok(Command::Expand {
+            config: Some(_), ..
+        })
+        | Ok(Command::Render {
+            config: Some(_), ..
+        })
+        | Ok(Command::Dependencies {
+            config: Some(_), ..
+        })
+        | Ok(Command::Check {
+            config: Some(_), ..
+        }) if cfg!(not(feature = "serde")) => {
+            Err("`--config` requires the `serde` feature".to_string())
+        }
+        Ok(Command::Render {
+            cache_dir: Some(_), ..
+        }) if cfg!(not(feature = "cache")) => {
+            Err("`--cache-dir` requires the `cache` feature".to_string())
+        }
+        command => command,
+    };
+    let command = match command {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(EX_USAGE);
+        }
+    };
+    match run(command) {
+        Ok(0) => {}
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("{}", Diagnostic::error(&error));
+            process::exit(error.exit_code());
+        }
+    }
 }
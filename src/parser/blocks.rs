@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::time::Instant;
+
+use ast::{Document, Node, Span};
+use errors::{Error, Warning};
+use stats::Stats;
+use super::{paragraphs, Parser, Terminator};
+
+/// An iterator over the top-level blocks of a document, which parses the input incrementally.
+///
+/// Each block is produced as soon as it is complete, so that only the block being parsed is held
+/// in memory, and the blocks are the same as the nodes of the `Document` produced by
+/// `Parser::parse`. The definitions file given in the parser's options is loaded before the
+/// first block is parsed.
+#[derive(Debug)]
+pub struct Blocks<R> {
+    parser: Parser<R>,
+    /// The top-level nodes which have been parsed, but not yet grouped into blocks.
+    nodes: Vec<Node>,
+    /// The blocks which are complete, but have not yet been returned, with their spans.
+    ready: VecDeque<(Node, Span)>,
+    /// The byte offset at which the span of the next block starts.
+    start: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: BufRead> Blocks<R> {
+    pub(super) fn new(parser: Parser<R>) -> Blocks<R> {
+        Blocks {
+            parser,
+            nodes: Vec::new(),
+            ready: VecDeque::new(),
+            start: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Returns the warnings produced so far.
+    pub fn warnings(&self) -> &[Warning] {
+        self.parser.warnings()
+    }
+
+    /// Returns the counts of the work done so far, as with `Parser::stats`. The time taken to
+    /// parse, if it is measured, counts only the time spent producing blocks.
+    pub fn stats(&self) -> Stats {
+        self.parser.stats()
+    }
+
+    /// Returns the next block, or `None` if the end of input has been reached.
+    pub fn next_res(&mut self) -> Result<Option<Node>, Error> {
+        Ok(self.next_spanned()?.map(|(node, _)| node))
+    }
+
+    /// Returns the next block along with the span of the input it was parsed from, or `None` if
+    /// the end of input has been reached.
+    ///
+    /// The spans of successive blocks do not overlap, and the whitespace between two blocks may
+    /// belong to either or neither of them. They are approximate, since the parser may read a few
+    /// tokens past the end of a block before it is complete, such as the whitespace after a
+    /// command with optional arguments.
+    pub fn next_spanned(&mut self) -> Result<Option<(Node, Span)>, Error> {
+        let start = self.parser.options.get_timings().then(Instant::now);
+        let block = self
+            .read_spanned()
+            .map_err(|e| self.parser.input.name_error(e));
+        if let Some(start) = start {
+            self.parser.add_parse_time(start.elapsed());
+        }
+        block
+    }
+
+    fn read_spanned(&mut self) -> Result<Option<(Node, Span)>, Error> {
+        if !self.started {
+            self.started = true;
+            self.parser.load_options_definitions()?;
+        }
+        while self.ready.is_empty() && !self.finished {
+            let before = self.parser.position();
+            if self
+                .parser
+                .parse_node(&mut self.nodes, Terminator::EndOfInput)?
+            {
+                self.split_complete(before);
+            } else {
+                self.finished = true;
+                let nodes = self.nodes.split_off(0);
+                let end = self.parser.position();
+                self.push_ready(nodes, end, end);
+            }
+        }
+        Ok(self.ready.pop_front())
+    }
+
+    /// Parses the rest of the input into a `Document`, along with the span of each of its nodes,
+    /// as given by `next_spanned`.
+    pub fn parse_spanned(&mut self) -> Result<(Document, Vec<Span>), Error> {
+        let mut nodes = Vec::new();
+        let mut spans = Vec::new();
+        while let Some((node, span)) = self.next_spanned()? {
+            nodes.push(node);
+            spans.push(span);
+        }
+        Ok((Document { nodes }, spans))
+    }
+
+    /// Moves the blocks which can no longer be extended by further input from `nodes` to
+    /// `ready`. This is the case once a block node has been parsed, or a blank line has been
+    /// followed by more text or another node. The last node was parsed from the input after
+    /// `before`.
+    fn split_complete(&mut self, before: usize) {
+        let last_is_block = self.nodes.last().is_some_and(|node| self.parser.is_block(node));
+        if !last_is_block && self.blank_line_before_last() {
+            let last = self.nodes.pop();
+            let nodes = self.nodes.split_off(0);
+            self.push_ready(nodes, before, before);
+            self.nodes.extend(last);
+            return;
+        }
+        let rest = match self.nodes.last_mut() {
+            Some(node) if self.parser.is_block(node) => None,
+            Some(&mut Node::Text(ref mut text)) => {
+                // The text after the last blank line which is followed by more text starts a new
+                // block, so that each paragraph of a run of text has a span of its own.
+                let mut split = None;
+                let mut search = 0;
+                while let Some((_, end)) = paragraphs::find_blank_line(&text[search..]) {
+                    if search + end == text.len() {
+                        break;
+                    }
+                    search += end;
+                    split = Some(search);
+                }
+                match split {
+                    Some(split) => Some(Node::Text(text.split_off(split))),
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        let nodes = self.nodes.split_off(0);
+        let end = match rest {
+            Some(Node::Text(ref text)) => self.parser.position().saturating_sub(text.len()),
+            _ => self.parser.position(),
+        };
+        self.push_ready(nodes, before, end);
+        self.nodes.extend(rest);
+    }
+
+    /// Returns whether the node before the last is text ending with a blank line.
+    fn blank_line_before_last(&self) -> bool {
+        let before_last = self.nodes.len().checked_sub(2).map(|i| &self.nodes[i]);
+        match before_last {
+            Some(Node::Text(text)) => {
+                paragraphs::find_blank_line(&text[text.trim_end().len()..]).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Groups the given nodes into blocks, and adds them to `ready`, with spans ending at `end`.
+    /// A paragraph followed by a block node is split between them at `split`, where the block
+    /// node started; any other blocks produced at once share a span.
+    fn push_ready(&mut self, nodes: Vec<Node>, split: usize, end: usize) {
+        let blocks = self.parser.split_paragraphs(nodes);
+        let start = self.start;
+        let end = end.max(start);
+        let spans = if blocks.len() == 2 && split > start && split < end {
+            vec![Span { start, end: split }, Span { start: split, end }]
+        } else {
+            vec![Span { start, end }; blocks.len()]
+        };
+        self.ready.extend(blocks.into_iter().zip(spans));
+        self.start = end;
+    }
+}
+
+impl<R: BufRead> Iterator for Blocks<R> {
+    type Item = Result<Node, Error>;
+
+    fn next(&mut self) -> Option<Result<Node, Error>> {
+        self.next_res().transpose()
+    }
+}
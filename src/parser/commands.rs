@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use ast::ArgType;
+use tokenize::Name;
+
+/// A description of how a command is parsed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CommandSpec {
+    /// The number of brace-delimited arguments the command takes.
+    pub arguments: usize,
+    /// Whether the command forms a block of its own, such as a heading, rather than appearing
+    /// within a paragraph.
+    pub block: bool,
+    /// Whether the command takes a bracketed list of options, such as `[alt=Logo]`, before its
+    /// arguments. The options are given to the command as its first argument, which is empty if
+    /// they are omitted.
+    pub options: bool,
+    /// Whether the command has a starred form, such as `\section*`, which is parsed as a command
+    /// whose name ends with `*`.
+    pub starred: bool,
+    /// The types of the arguments, starting with the options, if the command takes any, against
+    /// which the parser checks them. Arguments beyond the end of the list are markup.
+    pub types: &'static [ArgType],
+}
+
+impl CommandSpec {
+    /// Constructs a new `CommandSpec` for an inline command taking the given number of arguments.
+    pub fn new(arguments: usize) -> CommandSpec {
+        CommandSpec {
+            arguments,
+            block: false,
+            options: false,
+            starred: false,
+            types: &[],
+        }
+    }
+
+    /// Constructs a new `CommandSpec` for a block command taking the given number of arguments.
+    pub fn block(arguments: usize) -> CommandSpec {
+        CommandSpec {
+            arguments,
+            block: true,
+            options: false,
+            starred: false,
+            types: &[],
+        }
+    }
+
+    /// Returns this `CommandSpec`, for a command which also takes a bracketed list of options.
+    pub fn with_options(mut self) -> CommandSpec {
+        self.options = true;
+        self
+    }
+
+    /// Returns this `CommandSpec`, for a command which also has a starred form.
+    pub fn with_starred_form(mut self) -> CommandSpec {
+        self.starred = true;
+        self
+    }
+
+    /// Returns this `CommandSpec`, with the given types of its arguments, starting with the
+    /// options, if the command takes any.
+    pub fn with_types(mut self, types: &'static [ArgType]) -> CommandSpec {
+        self.types = types;
+        self
+    }
+
+    /// Returns the type of the argument with the given index, counting from 0 and starting with
+    /// the options, if the command takes any.
+    pub fn argument_type(&self, index: usize) -> ArgType {
+        self.types.get(index).cloned().unwrap_or_default()
+    }
+}
+
+/// Returns the number of arguments taken by `\begin` for a built-in environment.
+pub fn environment_arguments(name: &str) -> usize {
+    match name {
+        "indent" => 1,
+        "columns" => 1,
+        "table" => 2,
+        _ => 0,
+    }
+}
+
+/// Returns the types of the arguments taken by `\begin` for a built-in environment, after its
+/// options, if it takes any.
+pub fn environment_argument_types(name: &str) -> &'static [ArgType] {
+    match name {
+        "indent" | "columns" => &[ArgType::Integer],
+        _ => &[],
+    }
+}
+
+/// Returns whether a built-in environment takes a bracketed list of options before its arguments.
+pub fn environment_options(name: &str) -> bool {
+    name == "quote"
+}
+
+/// Returns the table of built-in commands.
+pub fn builtins() -> HashMap<Name, CommandSpec> {
+    let mut commands = HashMap::new();
+    commands.insert("ifformat".into(), CommandSpec::new(3));
+    commands.insert("today".into(), CommandSpec::new(0));
+    commands.insert("formatdate".into(), CommandSpec::new(2));
+    commands.insert("emph".into(), CommandSpec::new(1));
+    commands.insert("bold".into(), CommandSpec::new(1));
+    commands.insert("code".into(), CommandSpec::new(1));
+    commands.insert("color".into(), CommandSpec::new(2).with_types(&[ArgType::Color]));
+    commands.insert("footnote".into(), CommandSpec::new(1));
+    commands.insert("ref".into(), CommandSpec::new(1).with_types(&[ArgType::Identifier]));
+    commands.insert("cite".into(), CommandSpec::new(1));
+    commands.insert("index".into(), CommandSpec::new(1));
+    commands.insert("abbr".into(), CommandSpec::new(2));
+    let image = CommandSpec::new(1).with_options().with_types(&[ArgType::KeyValues]);
+    commands.insert("image".into(), image);
+    commands.insert("link".into(), CommandSpec::new(2));
+    commands.insert("break".into(), CommandSpec::new(0));
+    commands.insert("section".into(), CommandSpec::block(1).with_starred_form());
+    commands.insert("subsection".into(), CommandSpec::block(1).with_starred_form());
+    commands.insert("appendix".into(), CommandSpec::block(0));
+    commands.insert("item".into(), CommandSpec::block(0));
+    commands.insert("centered".into(), CommandSpec::block(1));
+    commands.insert("rightalign".into(), CommandSpec::block(1));
+    commands.insert("title".into(), CommandSpec::block(1));
+    commands.insert("date".into(), CommandSpec::block(1));
+    commands.insert("author".into(), CommandSpec::block(1));
+    commands.insert("lang".into(), CommandSpec::block(1));
+    commands.insert("label".into(), CommandSpec::block(1).with_types(&[ArgType::Identifier]));
+    commands.insert("bibliography".into(), CommandSpec::block(1));
+    commands.insert("bibitem".into(), CommandSpec::block(2).with_types(&[ArgType::Identifier]));
+    commands.insert("defabbr".into(), CommandSpec::block(2));
+    commands.insert("tableofcontents".into(), CommandSpec::block(0));
+    commands.insert("printindex".into(), CommandSpec::block(0));
+    commands.insert("hrule".into(), CommandSpec::block(1));
+    commands.insert("rule".into(), CommandSpec::block(1));
+    commands
+}
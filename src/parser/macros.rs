@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use errors::{Construct, Error, ErrorKind, Position};
+use tokenize::Token;
+use super::{Parser, Pending};
+
+/// A user-defined command, as created by `\newcommand` or `\def`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Macro {
+    /// The number of arguments the command takes.
+    pub arguments: usize,
+    /// The unexpanded body of the command.
+    pub body: Vec<Token>,
+}
+
+/// A user-defined environment, as created by `\newenvironment`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EnvironmentMacro {
+    /// The number of arguments taken by `\begin` for this environment.
+    pub arguments: usize,
+    /// The unexpanded tokens inserted in place of `\begin`.
+    pub begin: Vec<Token>,
+    /// The unexpanded tokens inserted in place of `\end`.
+    pub end: Vec<Token>,
+}
+
+/// The definitions made within a single group or environment.
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    macros: HashMap<String, Macro>,
+    environments: HashMap<String, EnvironmentMacro>,
+}
+
+impl<R: BufRead> Parser<R> {
+    /// Enters a new scope, such as at the start of a group or environment.
+    pub(super) fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Leaves the innermost scope, discarding any local definitions made in it.
+    pub(super) fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Returns the innermost definition of the named command.
+    pub(super) fn lookup_macro(&self, name: &str) -> Option<&Macro> {
+        self.scopes.iter().rev().filter_map(|s| s.macros.get(name)).next()
+    }
+
+    /// Returns the innermost definition of the named environment.
+    pub(super) fn lookup_environment(&self, name: &str) -> Option<&EnvironmentMacro> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter_map(|s| s.environments.get(name))
+            .next()
+    }
+
+    /// Defines a command in the innermost scope, or, if `global` is set, in the outermost scope,
+    /// replacing any definitions in the scopes between.
+    fn define_macro(&mut self, name: String, mac: Macro, global: bool) {
+        if global {
+            for scope in &mut self.scopes {
+                scope.macros.remove(&name);
+            }
+        }
+        let scope = if global {
+            self.scopes.first_mut()
+        } else {
+            self.scopes.last_mut()
+        };
+        if let Some(scope) = scope {
+            scope.macros.insert(name, mac);
+        }
+    }
+
+    /// Defines an environment, with the same scoping rules as `define_macro`.
+    fn define_environment_macro(&mut self, name: String, env: EnvironmentMacro, global: bool) {
+        if global {
+            for scope in &mut self.scopes {
+                scope.environments.remove(&name);
+            }
+        }
+        let scope = if global {
+            self.scopes.first_mut()
+        } else {
+            self.scopes.last_mut()
+        };
+        if let Some(scope) = scope {
+            scope.environments.insert(name, env);
+        }
+    }
+
+    /// Handles a definition command, after the command itself has been read. If `global` is set,
+    /// the definition is made in the outermost scope.
+    pub(super) fn parse_definition(
+        &mut self,
+        name: &str,
+        global: bool,
+        position: Position,
+    ) -> Result<(), Error> {
+        match name {
+            "newcommand" => self.define_command(false, global, position),
+            "renewcommand" => self.define_command(true, global, position),
+            "newenvironment" => self.define_environment(false, global, position),
+            "renewenvironment" => self.define_environment(true, global, position),
+            "def" => self.define_def(global, position),
+            "let" => self.define_let(global, position),
+            "global" => match self.next_pending()? {
+                Some(Pending::Token(Token::Command(ref name))) if name != "global" => {
+                    self.parse_definition(name, true, position)
+                }
+                _ => Err(ErrorKind::InvalidDefinition(position).into()),
+            },
+            _ => Err(ErrorKind::InvalidDefinition(position).into()),
+        }
+    }
+
+    /// Reads the name of the command being defined, which may optionally be enclosed in braces.
+    fn read_command_name(&mut self, position: Position) -> Result<String, Error> {
+        let braced = self.eat_begin_group()?;
+        let name = match self.next_pending()? {
+            Some(Pending::Token(Token::Command(name))) => name,
+            _ => return Err(ErrorKind::InvalidDefinition(position).into()),
+        };
+        if braced && !matches!(self.next_pending()?, Some(Pending::Token(Token::EndGroup))) {
+            return Err(ErrorKind::InvalidDefinition(position).into());
+        }
+        Ok(name.into())
+    }
+
+    /// Reads a brace-delimited body of a definition, without expanding it.
+    fn read_body(&mut self, position: Position) -> Result<Vec<Token>, Error> {
+        if !self.eat_begin_group()? {
+            return Err(ErrorKind::InvalidDefinition(position).into());
+        }
+        let start = self.location();
+        self.in_construct(Construct::Definition, start, Parser::read_raw_group)
+    }
+
+    /// Reads the optional `[n]` argument count of a definition, returning 0 if it is absent.
+    fn read_argument_count(&mut self, position: Position) -> Result<usize, Error> {
+        if self.peek_token()? != Some(&Token::Char('[')) {
+            return Ok(0);
+        }
+        self.pending.pop();
+        let mut count = String::new();
+        loop {
+            match self.next_pending()? {
+                Some(Pending::Token(Token::Char(']'))) => break,
+                Some(Pending::Token(Token::Char(c))) => count.push(c),
+                _ => return Err(ErrorKind::InvalidDefinition(position).into()),
+            }
+        }
+        match count.trim().parse() {
+            Ok(n) if n <= 9 => Ok(n),
+            _ => Err(ErrorKind::InvalidDefinition(position).into()),
+        }
+    }
+
+    /// Handles `\newcommand{\name}[n]{body}`, or `\renewcommand` if `redefine` is set.
+    fn define_command(
+        &mut self,
+        redefine: bool,
+        global: bool,
+        position: Position,
+    ) -> Result<(), Error> {
+        let name = self.read_command_name(position)?;
+        let defined = self.lookup_macro(&name).is_some() || self.commands.contains_key(&*name);
+        check_redefinition(redefine, defined, position)?;
+        let arguments = self.read_argument_count(position)?;
+        let body = self.read_body(position)?;
+        self.define_macro(name, Macro { arguments, body }, global);
+        Ok(())
+    }
+
+    /// Handles `\newenvironment{name}[n]{begin}{end}`, or `\renewenvironment` if `redefine` is
+    /// set.
+    fn define_environment(
+        &mut self,
+        redefine: bool,
+        global: bool,
+        position: Position,
+    ) -> Result<(), Error> {
+        let name = self.read_name()?;
+        let defined = self.lookup_environment(&name).is_some();
+        check_redefinition(redefine, defined, position)?;
+        let arguments = self.read_argument_count(position)?;
+        let begin = self.read_body(position)?;
+        let end = self.read_body(position)?;
+        let env = EnvironmentMacro {
+            arguments,
+            begin,
+            end,
+        };
+        self.define_environment_macro(name, env, global);
+        Ok(())
+    }
+
+    /// Handles `\def\name#1#2{body}`, which, unlike `\newcommand`, may freely replace an existing
+    /// definition. The parameters must be numbered consecutively from 1.
+    fn define_def(&mut self, global: bool, position: Position) -> Result<(), Error> {
+        let name = self.read_command_name(position)?;
+        let mut arguments = 0;
+        while self.peek_token()? == Some(&Token::Char('#')) {
+            self.pending.pop();
+            match self.next_pending()? {
+                Some(Pending::Token(Token::Char(c)))
+                    if c.to_digit(10) == Some(arguments as u32 + 1) =>
+                {
+                    arguments += 1
+                }
+                _ => return Err(ErrorKind::InvalidDefinition(position).into()),
+            }
+        }
+        let body = self.read_body(position)?;
+        self.define_macro(name, Macro { arguments, body }, global);
+        Ok(())
+    }
+
+    /// Handles `\let\new\old` or `\let\new=\old`, which gives `\new` the current meaning of
+    /// `\old`. If `\old` is not a user-defined command, `\new` becomes an alias for it.
+    fn define_let(&mut self, global: bool, position: Position) -> Result<(), Error> {
+        let name = self.read_command_name(position)?;
+        while let Some(&Token::Char(c)) = self.peek_token()? {
+            if c != '=' && !c.is_whitespace() {
+                break;
+            }
+            self.pending.pop();
+        }
+        let old = match self.next_pending()? {
+            Some(Pending::Token(Token::Command(old))) => old,
+            _ => return Err(ErrorKind::InvalidDefinition(position).into()),
+        };
+        let mac = match self.lookup_macro(&old) {
+            Some(mac) => mac.clone(),
+            None => Macro {
+                arguments: 0,
+                body: vec![Token::Command(old)],
+            },
+        };
+        self.define_macro(name, mac, global);
+        Ok(())
+    }
+}
+
+/// Checks whether a definition is allowed: `\newcommand` and `\newenvironment` may not replace an
+/// existing definition, and `\renewcommand` and `\renewenvironment` may only replace one.
+fn check_redefinition(
+    redefine: bool,
+    defined: bool,
+    position: Position,
+) -> Result<(), Error> {
+    match (redefine, defined) {
+        (false, true) => Err(ErrorKind::AlreadyDefined(position).into()),
+        (true, false) => Err(ErrorKind::NotDefined(position).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces the parameters `#1` through `#9` in `body` with the corresponding arguments, and
+/// `##` with a single `#`, for the command or environment at `position`. Fails with
+/// `ErrorKind::InvalidParameter` if the body refers to a parameter which is not present in
+/// `args`, and with `ErrorKind::ExpansionLimit` if the result would hold more than `limit`
+/// tokens.
+pub fn substitute(
+    body: &[Token],
+    args: &[Vec<Token>],
+    limit: usize,
+    position: Position,
+) -> Result<Vec<Token>, ErrorKind> {
+    let invalid = || ErrorKind::InvalidParameter(position);
+    let argument = |c: char| {
+        let index = c.to_digit(10).filter(|&d| d > 0).ok_or_else(invalid)? as usize;
+        args.get(index - 1).ok_or_else(invalid)
+    };
+    let mut result = Vec::with_capacity(body.len().min(limit));
+    let mut tokens = body.iter();
+    while let Some(token) = tokens.next() {
+        if result.len() >= limit {
+            return Err(ErrorKind::ExpansionLimit(position));
+        }
+        if *token != Token::Char('#') {
+            result.push(token.clone());
+            continue;
+        }
+        match tokens.next() {
+            Some(&Token::Char('#')) => result.push(Token::Char('#')),
+            Some(&Token::Char(c)) => {
+                let arg = argument(c)?;
+                if arg.len() > limit - result.len() {
+                    return Err(ErrorKind::ExpansionLimit(position));
+                }
+                result.extend(arg.iter().cloned());
+            }
+            // A run of text read with `Tokens::coalesce_text` may start with the digit.
+            Some(Token::Text(text)) => {
+                let mut chars = text.chars();
+                let arg = argument(chars.next().ok_or_else(invalid)?)?;
+                if arg.len() > limit - result.len() {
+                    return Err(ErrorKind::ExpansionLimit(position));
+                }
+                result.extend(arg.iter().cloned());
+                let rest = chars.as_str();
+                let mut rest_chars = rest.chars();
+                match (rest_chars.next(), rest_chars.next()) {
+                    (Some(c), None) => result.push(Token::Char(c)),
+                    (Some(_), Some(_)) => result.push(Token::Text(rest.into())),
+                    _ => {}
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if result.len() > limit {
+        return Err(ErrorKind::ExpansionLimit(position));
+    }
+    Ok(result)
+}
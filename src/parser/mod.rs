@@ -1,12 +1,1017 @@
-use failure::{Backtrace, Context, Fail, ResultExt};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use errors::{Error, ErrorKind};
-use self::bufread::BufReadIter;
+use ast::{self, Alignment, ArgType, Command, Document, Environment, Node, Table};
+use date::Date;
+use errors::{Construct, Error, ErrorKind, Position, ResultExt, Warning};
+use limits::Limit;
+use stats::Stats;
+use tokenize::{Name, Rewriting, Token, TokenRewriter, Tokens};
+use self::macros::Scope;
 
-mod bufread;
+pub use self::blocks::Blocks;
+pub use self::commands::CommandSpec;
+pub use self::options::{ParserOptions, KNOWN_FORMATS};
+
+mod blocks;
+mod commands;
+mod macros;
+mod options;
+mod paragraphs;
+
+/// The commands which define other commands or environments, and which are the only commands
+/// that may appear in a definitions file.
+const DEFINITION_COMMANDS: &[&str] = &[
+    "newcommand",
+    "renewcommand",
+    "newenvironment",
+    "renewenvironment",
+    "def",
+    "let",
+    "global",
+];
+
+/// The characters which the parser matches on, and which therefore end a run of text read by
+/// `Tokens`, along with `\`, `{`, and `}`.
+const SPECIAL_CHARS: &str = " \t\r\n[]*#=";
+
+/// The greatest number of constructs which may be open at once, which keeps deeply nested input
+/// from overflowing the stack, even the 2 MiB stack of a spawned thread in a debug build.
+const MAX_DEPTH: usize = 128;
+
+/// The greatest number of expansions of user-defined commands and environments between two
+/// tokens read from the input, which stops a command which expands to itself.
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// The greatest number of items which may be pending, which stops the expansion of a command from
+/// growing without bound, as when it doubles its argument each time.
+const MAX_PENDING: usize = 1 << 20;
+
+/// The environments whose content is grouped into `\item`s.
+const LIST_ENVIRONMENTS: &[&str] = &["itemize", "enumerate", "description"];
+
+/// The commands which give the rows of a table, taking one argument per cell.
+const TABLE_ROWS: &[&str] = &["header", "row"];
 
 /// A structure for parsing an input stream
 #[derive(Debug)]
 pub struct Parser<R> {
-    input: BufReadIter<R>,
+    input: Tokens<R>,
+    /// The rewriters applied to the tokens read from the input, if any.
+    rewriting: Option<Rewriting<Box<dyn TokenRewriter + Send>>>,
+    /// Items which have been read from the input or produced by macro expansion, but not yet
+    /// consumed, in reverse order.
+    pending: Vec<Pending>,
+    options: ParserOptions,
+    commands: HashMap<Name, CommandSpec>,
+    /// The stack of scopes holding user definitions, from outermost to innermost.
+    scopes: Vec<Scope>,
+    /// The stack of constructs which are open, with the positions at which they started, from
+    /// outermost to innermost.
+    open: Vec<(Construct, Position)>,
+    /// The number of expansions since a token was last read from the input.
+    expansions: usize,
+    /// The greatest depth of `open`, the number of expansions in all, and the time spent parsing,
+    /// to which the counts of the tokenizer are added by `stats`.
+    stats: Stats,
+    warnings: Vec<Warning>,
+}
+
+/// An item waiting to be consumed by the parser.
+#[derive(Debug)]
+enum Pending {
+    /// A token.
+    Token(Token),
+    /// The point in the expansion of a user-defined environment where its content belongs. The
+    /// content is parsed up to the matching `\end`, which is then replaced by the given tokens.
+    /// The position is that of the `\begin`.
+    EnvironmentContent(String, Vec<Token>, Position),
+    /// The end of the expansion of a user-defined environment, where its scope ends.
+    PopScope,
+}
+
+impl Parser<BufReader<File>> {
+    /// Constructs a new `Parser` reading from the named file, with the given options. The parser
+    /// is named after the file, so that errors are reported with its name.
+    pub fn open<P: AsRef<Path>>(path: P, options: ParserOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let file = File::open(path).context(ErrorKind::Open(name.clone()))?;
+        Ok(Parser::with_options(BufReader::new(file), options).with_name(name))
+    }
+
+    /// Parses the named file into a `Document`, as with `open` and `parse`. Any warnings are
+    /// discarded; to inspect them, use `open` instead.
+    pub fn parse_file<P: AsRef<Path>>(path: P, options: ParserOptions) -> Result<Document, Error> {
+        Parser::open(path, options)?.parse()
+    }
+}
+
+/// The condition which ends a sequence of nodes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Terminator<'a> {
+    /// The end of input.
+    EndOfInput,
+    /// A `}`.
+    EndGroup,
+    /// An `\end` for the named environment.
+    End(&'a str),
+    /// A `]`, ending a list of options.
+    EndOptions,
+}
+
+impl<'a> Terminator<'a> {
+    /// Returns a description of the terminator, for use as the `expected` field of an
+    /// `ErrorKind::UnexpectedToken`.
+    fn expected(&self) -> &'static str {
+        match *self {
+            Terminator::EndOfInput => "an open environment",
+            Terminator::EndGroup => "`}` to close the group",
+            Terminator::End(_) => "`\\end` to close the environment",
+            Terminator::EndOptions => "`]` to close the options",
+        }
+    }
+}
+
+impl<R: BufRead> Parser<R> {
+    /// Constructs a new `Parser` from the given `BufRead`, with the default options.
+    pub fn new(input: R) -> Parser<R> {
+        Parser::with_options(input, ParserOptions::default())
+    }
+
+    /// Constructs a new `Parser` from the given `BufRead` and options.
+    pub fn with_options(input: R, options: ParserOptions) -> Parser<R> {
+        Parser {
+            input: Tokens::new(input)
+                .coalesce_text(true)
+                .special_chars(SPECIAL_CHARS)
+                .smart_punctuation(options.get_smart_punctuation())
+                .lints(options.get_lints().clone())
+                .limits(options.get_limits()),
+            rewriting: None,
+            pending: Vec::new(),
+            options,
+            commands: commands::builtins(),
+            scopes: vec![Scope::default()],
+            open: Vec::new(),
+            expansions: 0,
+            stats: Stats::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Sets the name of the input, such as the name of the file it was read from, which is
+    /// attached to any errors, as with `Tokens::with_name`.
+    pub fn with_name<S: Into<String>>(self, name: S) -> Parser<R> {
+        Parser {
+            input: self.input.with_name(name),
+            ..self
+        }
+    }
+
+    /// Applies a `TokenRewriter` to the tokens read from the input, after any given before it.
+    ///
+    /// The tokens of definitions are rewritten as they are read, so the tokens which an expansion
+    /// produces are not rewritten again. The definitions file is not rewritten. Errors are
+    /// reported at the position of the last token read, which may be beyond the tokens held back
+    /// by a rewriter.
+    pub fn with_rewriter<T>(mut self, rewriter: T) -> Parser<R>
+    where
+        T: TokenRewriter + Send + 'static,
+    {
+        let rewriter: Box<dyn TokenRewriter + Send> = match self.rewriting.take() {
+            Some(rewriting) => Box::new(rewriting.into_inner().then(rewriter)),
+            None => Box::new(rewriter),
+        };
+        self.rewriting = Some(Rewriting::new(rewriter));
+        self
+    }
+
+    /// Returns the warnings produced so far.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the counts of the work done so far: the lines, bytes, and tokens read from the
+    /// input, not counting those of the definitions file, the greatest nesting depth, and the
+    /// number of expansions. The time taken to parse is given only if `ParserOptions::timings` is
+    /// set.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            max_depth: self.stats.max_depth,
+            expansions: self.stats.expansions,
+            timings: self.stats.timings,
+            ..self.input.stats()
+        }
+    }
+
+    /// Loads command and environment definitions from the given input, before the document is
+    /// parsed. The input may contain only definitions and whitespace.
+    pub fn load_definitions<D: BufRead>(&mut self, input: D) -> Result<(), Error> {
+        let parser = Parser::with_options(input, self.options.clone());
+        self.load_definitions_from(parser)
+    }
+
+    /// Loads command and environment definitions from the named file, as with
+    /// `load_definitions`. Any errors are reported with the name of the file.
+    pub fn load_definitions_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let parser = Parser::open(path, self.options.clone())?;
+        self.load_definitions_from(parser)
+    }
+
+    /// Parses the entire input into a `Document`, after loading the definitions file given in the
+    /// options, if any.
+    ///
+    /// This never panics, whatever the input. Constructs nested more than 128 levels deep fail
+    /// with `ErrorKind::TooDeep`, and user-defined commands or environments which expand
+    /// without end fail with `ErrorKind::ExpansionLimit`.
+    pub fn parse(&mut self) -> Result<Document, Error> {
+        self.timed(|parser| {
+            parser
+                .load_options_definitions()
+                .and_then(|()| parser.parse_nodes(Terminator::EndOfInput))
+                .map(|nodes| Document {
+                    nodes: parser.split_paragraphs(nodes),
+                })
+                .map_err(|e| parser.input.name_error(e))
+        })
+    }
+
+    /// Runs `f`, adding the time it takes to the time spent parsing if `ParserOptions::timings`
+    /// is set.
+    fn timed<T, F: FnOnce(&mut Self) -> T>(&mut self, f: F) -> T {
+        if !self.options.get_timings() {
+            return f(self);
+        }
+        let start = Instant::now();
+        let result = f(self);
+        self.add_parse_time(start.elapsed());
+        result
+    }
+
+    /// Adds to the time spent parsing.
+    fn add_parse_time(&mut self, time: Duration) {
+        *self.stats.timings.parse.get_or_insert(Duration::ZERO) += time;
+    }
+
+    /// Parses the input one top-level block at a time, as with `Blocks`.
+    pub fn into_blocks(self) -> Blocks<R> {
+        Blocks::new(self)
+    }
+
+    /// Returns the byte offset in the input up to which tokens have been read, including any
+    /// which are pending.
+    fn position(&self) -> usize {
+        self.input.position()
+    }
+
+    /// Loads the definitions file given in the options, if any.
+    fn load_options_definitions(&mut self) -> Result<(), Error> {
+        if let Some(path) = self.options.get_definitions_file().map(Path::to_owned) {
+            self.load_definitions_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the definitions parsed by another parser into the current scope.
+    fn load_definitions_from<D: BufRead>(&mut self, mut parser: Parser<D>) -> Result<(), Error> {
+        mem::swap(&mut parser.scopes, &mut self.scopes);
+        let result = parser.parse_definitions();
+        mem::swap(&mut parser.scopes, &mut self.scopes);
+        self.warnings.append(&mut parser.warnings);
+        result.map_err(|e| parser.input.name_error(e))
+    }
+
+    /// Parses a sequence of definitions, separated by whitespace.
+    fn parse_definitions(&mut self) -> Result<(), Error> {
+        loop {
+            match self.next_pending()? {
+                None => return Ok(()),
+                Some(Pending::Token(Token::Char(c))) if c.is_whitespace() => {}
+                Some(Pending::Token(Token::Command(name)))
+                    if DEFINITION_COMMANDS.contains(&&*name) =>
+                {
+                    self.parse_command(name, &mut Vec::new())?
+                }
+                _ => {
+                    return Err(
+                        ErrorKind::NotADefinition(self.location()).into(),
+                    )
+                }
+            }
+        }
+    }
+
+    /// Returns the next pending item, reading a token from the input if there are none, or `None`
+    /// if the end of input has been reached.
+    fn next_pending(&mut self) -> Result<Option<Pending>, Error> {
+        if self.options.is_cancelled() {
+            return Err(ErrorKind::Cancelled.into());
+        }
+        match self.pending.pop() {
+            Some(pending) => Ok(Some(pending)),
+            None => Ok(self.next_input_token()?.map(Pending::Token)),
+        }
+    }
+
+    /// Reads a token from the input, collecting any warnings produced by the lints.
+    fn next_input_token(&mut self) -> Result<Option<Token>, Error> {
+        let input = &mut self.input;
+        let token = match self.rewriting {
+            Some(ref mut rewriting) => rewriting.next(|| input.next_res()),
+            None => input.next_res(),
+        };
+        self.warnings.append(&mut self.input.take_warnings());
+        self.expansions = 0;
+        token
+    }
+
+    /// Returns the position of the most recently read token.
+    fn location(&self) -> Position {
+        self.input.location()
+    }
+
+    /// Returns an `ErrorKind::UnexpectedToken` error at the position of the most recently read
+    /// token, which found the given item, or the end of input if it is `None`.
+    fn unexpected(&self, expected: &'static str, found: Option<&Pending>) -> Error {
+        let found = match found {
+            Some(Pending::Token(token)) => token.summary(),
+            Some(_) => "the end of a user-defined environment".into(),
+            None => "end of input".into(),
+        };
+        ErrorKind::UnexpectedToken {
+            expected,
+            found,
+            position: self.location(),
+        }
+        .into()
+    }
+
+    /// Returns an `ErrorKind::Unclosed` error for the innermost open construct, on reaching the end
+    /// of input.
+    fn unclosed(&self, expected: &'static str) -> Error {
+        match self.open.last() {
+            Some(&(ref construct, position)) => ErrorKind::Unclosed {
+                construct: construct.clone(),
+                position,
+            }
+            .into(),
+            None => self.unexpected(expected, None),
+        }
+    }
+
+    /// Calls `f` within the given construct, which started at `position`, so that reaching the
+    /// end of input within it is reported as such. Fails with `ErrorKind::TooDeep` if `MAX_DEPTH`
+    /// constructs are already open.
+    fn in_construct<T, F>(
+        &mut self,
+        construct: Construct,
+        position: Position,
+        f: F,
+    ) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        if self.open.len() >= MAX_DEPTH {
+            return Err(ErrorKind::TooDeep {
+                construct,
+                position,
+            }
+            .into());
+        }
+        self.open.push((construct, position));
+        self.stats.max_depth = self.stats.max_depth.max(self.open.len());
+        let result = f(self);
+        self.open.pop();
+        result
+    }
+
+    /// Returns the next token without consuming it. Returns `None` at the end of input, or if the
+    /// next item is not a token.
+    fn peek_token(&mut self) -> Result<Option<&Token>, Error> {
+        if self.pending.is_empty() {
+            if let Some(token) = self.next_input_token()? {
+                self.pending.push(Pending::Token(token));
+            }
+        }
+        match self.pending.last() {
+            Some(Pending::Token(token)) => Ok(Some(token)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Consumes the next token if it is a `{`, returning whether it was.
+    fn eat_begin_group(&mut self) -> Result<bool, Error> {
+        if self.peek_token()? == Some(&Token::BeginGroup) {
+            self.pending.pop();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Consumes the `{` starting an argument, if present, returning whether it was. Depending on
+    /// the options, whitespace before the `{` may be skipped; if no `{` follows, the whitespace is
+    /// left in place.
+    fn eat_argument_start(&mut self) -> Result<bool, Error> {
+        let mut skipped = Vec::new();
+        let mut newline = false;
+        loop {
+            let c = match self.peek_token()? {
+                Some(&Token::Char(c)) => c,
+                _ => break,
+            };
+            match c {
+                ' ' | '\t' if self.options.get_skip_space_before_arguments() => {}
+                '\n' if !newline && self.options.get_skip_newline_before_arguments() => {
+                    newline = true
+                }
+                _ => break,
+            }
+            skipped.extend(self.pending.pop());
+        }
+        if self.eat_begin_group()? {
+            return Ok(true);
+        }
+        while let Some(pending) = skipped.pop() {
+            self.pending.push(pending);
+        }
+        Ok(false)
+    }
+
+    /// Counts an expansion of the user-defined command or environment at `position`, failing with
+    /// `ErrorKind::ExpansionLimit` if there have been `MAX_EXPANSIONS` since a token was last
+    /// read from the input, or with `Limit::Expansions` if there have been more in all than the
+    /// limits allow. Returns the greatest number of tokens which the expansion may push.
+    fn expand(&mut self, position: Position) -> Result<usize, Error> {
+        self.expansions += 1;
+        self.stats.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(ErrorKind::ExpansionLimit(position).into());
+        }
+        let max = self.options.get_limits().get_max_expansions();
+        if self.stats.expansions > max {
+            return Err(ErrorKind::LimitExceeded {
+                what: Limit::Expansions,
+                limit: max,
+                position,
+            }
+            .into());
+        }
+        Ok(MAX_PENDING.saturating_sub(self.pending.len()))
+    }
+
+    /// Pushes tokens to be read before the rest of the input.
+    fn push_tokens(&mut self, tokens: Vec<Token>) {
+        self.pending
+            .extend(tokens.into_iter().rev().map(Pending::Token));
+    }
+
+    /// Parses a sequence of nodes, up to the given terminator. Reaching the end of input is an
+    /// error unless the terminator is `EndOfInput`, and encountering a different terminator is
+    /// always an error.
+    fn parse_nodes(&mut self, terminator: Terminator) -> Result<Vec<Node>, Error> {
+        let mut nodes = Vec::new();
+        while self.parse_node(&mut nodes, terminator)? {}
+        Ok(nodes)
+    }
+
+    /// Parses the next item of a sequence of nodes, as with `parse_nodes`, and appends the result
+    /// to `nodes`. Returns `false` once the terminator has been reached.
+    fn parse_node(&mut self, nodes: &mut Vec<Node>, terminator: Terminator) -> Result<bool, Error> {
+        let token = match self.next_pending()? {
+            Some(Pending::Token(token)) => token,
+            Some(Pending::EnvironmentContent(name, end, position)) => {
+                let construct = Construct::Environment(name.clone());
+                let content = self.in_construct(construct, position, |p| {
+                    p.parse_nodes(Terminator::End(&name))
+                })?;
+                for node in content {
+                    push_node(nodes, node);
+                }
+                self.pending.push(Pending::PopScope);
+                self.push_tokens(end);
+                return Ok(true);
+            }
+            Some(Pending::PopScope) => {
+                self.pop_scope();
+                return Ok(true);
+            }
+            None if terminator == Terminator::EndOfInput => return Ok(false),
+            None => return Err(self.unclosed(terminator.expected())),
+        };
+        match token {
+            Token::Char(']') if terminator == Terminator::EndOptions => return Ok(false),
+            Token::Char('\\') if self.options.get_backslash_break() => {
+                let name = "break".into();
+                nodes.push(Node::Command(Command { name, args: Vec::new() }));
+            }
+            Token::Char(c) => push_char(nodes, c),
+            Token::Text(text) => push_node(nodes, Node::Text(text.into())),
+            Token::Command(ref name) if name == "end" => {
+                let position = self.location();
+                let name = self.read_name()?;
+                return match terminator {
+                    Terminator::End(open) if open == name => Ok(false),
+                    Terminator::End(open) => Err(ErrorKind::MismatchedEnvironment {
+                        expected: open.into(),
+                        found: name,
+                        position,
+                    }
+                    .into()),
+                    _ => Err(ErrorKind::UnexpectedToken {
+                        expected: terminator.expected(),
+                        found: format!("`\\end{{{}}}`", name),
+                        position,
+                    }
+                    .into()),
+                };
+            }
+            Token::Command(name) => self.parse_command(name, nodes)?,
+            Token::BeginGroup => {
+                let position = self.location();
+                let group = self.in_construct(Construct::Group, position, |p| {
+                    p.parse_group(Terminator::EndGroup)
+                })?;
+                nodes.push(Node::Group(group));
+            }
+            Token::EndGroup if terminator == Terminator::EndGroup => return Ok(false),
+            Token::EndGroup => {
+                return Err(
+                    ErrorKind::UnexpectedEndGroup(self.location()).into(),
+                )
+            }
+            Token::Verbatim(mut verbatim) => {
+                if self.options.get_dedent_verbatim() {
+                    verbatim.content = dedent(&verbatim.content);
+                }
+                nodes.push(Node::Verbatim(*verbatim));
+            }
+            Token::Raw(content) => nodes.push(Node::Raw(content.into())),
+            // The parser's own tokenizer never splits verbatim contents into chunks.
+            Token::VerbatimChunk(_) | Token::RawChunk(_) => {
+                return Err(ErrorKind::UnexpectedToken {
+                    expected: "a whole verbatim argument",
+                    found: token.summary(),
+                    position: self.location(),
+                }
+                .into())
+            }
+        }
+        Ok(true)
+    }
+
+    /// Groups a sequence of nodes into paragraphs and blocks.
+    fn split_paragraphs(&self, nodes: Vec<Node>) -> Vec<Node> {
+        paragraphs::split_paragraphs(nodes, |node| self.is_block(node))
+    }
+
+    /// Groups the content of a list environment into items, as with `paragraphs::split_items`.
+    /// The items of a `description` list already hold their terms.
+    fn split_items(&self, name: &str, nodes: Vec<Node>) -> Vec<Node> {
+        let terms = name == "description";
+        paragraphs::split_items(nodes, terms, |node| self.is_block(node))
+    }
+
+    /// Returns whether a node forms a block of its own, rather than being part of a paragraph. The
+    /// starred form of a command is a block if the command is.
+    fn is_block(&self, node: &Node) -> bool {
+        match *node {
+            Node::Command(ref command) => self
+                .commands
+                .get(command.name.strip_suffix('*').unwrap_or(&command.name))
+                .is_some_and(|spec| spec.block),
+            Node::Paragraph(_) | Node::Environment(_) | Node::Table(_) | Node::Verbatim(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Parses a sequence of nodes in a new scope, up to the given terminator.
+    fn parse_group(&mut self, terminator: Terminator) -> Result<Vec<Node>, Error> {
+        self.push_scope();
+        let result = self.parse_nodes(terminator);
+        self.pop_scope();
+        result
+    }
+
+    /// Parses the arguments of a command, and appends the resulting nodes to `nodes`.
+    fn parse_command(&mut self, name: Name, nodes: &mut Vec<Node>) -> Result<(), Error> {
+        let position = self.location();
+        if let Some(mac) = self.lookup_macro(&name).cloned() {
+            let args = self.read_raw_arguments(mac.arguments)?;
+            let limit = self.expand(position)?;
+            let body = macros::substitute(&mac.body, &args, limit, position)?;
+            self.push_tokens(body);
+            return Ok(());
+        }
+        if DEFINITION_COMMANDS.contains(&&*name) {
+            return self.parse_definition(&name, false, position);
+        }
+        if name == "begin" {
+            return self.parse_environment(nodes);
+        }
+        if TABLE_ROWS.contains(&&*name) {
+            let args = self.parse_arguments(usize::MAX)?;
+            let name = name.into();
+            nodes.push(Node::Command(Command { name, args }));
+            return Ok(());
+        }
+        let spec = self.commands.get(&*name).cloned().unwrap_or_default();
+        let mut name = String::from(name);
+        if spec.starred && self.peek_token()? == Some(&Token::Char('*')) {
+            self.pending.pop();
+            name.push('*');
+        }
+        let mut args = Vec::new();
+        let mut positions = Vec::new();
+        if spec.options {
+            let mut start = position;
+            args.push(self.parse_options_at(&mut start)?);
+            positions.push(start);
+        }
+        args.extend(self.parse_arguments_at(spec.arguments, &mut positions)?);
+        self.check_arguments(&name, &args, spec.types, &positions)?;
+        if name == "item" && self.in_description() {
+            args.push(self.parse_term(position)?);
+        }
+        match &*name {
+            "ifformat" => {
+                let branch = self.if_format(args, position);
+                for node in branch {
+                    push_node(nodes, node);
+                }
+            }
+            "today" => {
+                let today = self.options.get_today().unwrap_or_else(Date::today);
+                let format = self.options.get_date_format().to_owned();
+                let text = self.format_date(today, &format, position);
+                push_node(nodes, Node::Text(text));
+            }
+            "formatdate" => {
+                let text = self.format_date_command(&args, position);
+                push_node(nodes, Node::Text(text));
+            }
+            _ => nodes.push(Node::Command(Command { name, args })),
+        }
+        Ok(())
+    }
+
+    /// Parses up to `count` brace-delimited arguments.
+    fn parse_arguments(&mut self, count: usize) -> Result<Vec<Vec<Node>>, Error> {
+        self.parse_arguments_at(count, &mut Vec::new())
+    }
+
+    /// Parses up to `count` brace-delimited arguments, as with `parse_arguments`, and appends the
+    /// position of the `{` starting each of them to `positions`.
+    fn parse_arguments_at(
+        &mut self,
+        count: usize,
+        positions: &mut Vec<Position>,
+    ) -> Result<Vec<Vec<Node>>, Error> {
+        let mut args = Vec::new();
+        while args.len() < count && self.eat_argument_start()? {
+            let position = self.location();
+            positions.push(position);
+            args.push(self.in_construct(Construct::Argument, position, |p| {
+                p.parse_group(Terminator::EndGroup)
+            })?);
+        }
+        Ok(args)
+    }
+
+    /// Parses a bracketed list of options, if one follows immediately, or otherwise returns no
+    /// nodes. A `]` within a group does not end the options.
+    fn parse_options(&mut self) -> Result<Vec<Node>, Error> {
+        let mut start = self.location();
+        self.parse_options_at(&mut start)
+    }
+
+    /// Parses a bracketed list of options, as with `parse_options`, and sets `start` to the
+    /// position of the `[` if there is one.
+    fn parse_options_at(&mut self, start: &mut Position) -> Result<Vec<Node>, Error> {
+        if self.peek_token()? != Some(&Token::Char('[')) {
+            return Ok(Vec::new());
+        }
+        self.pending.pop();
+        let position = self.location();
+        *start = position;
+        self.in_construct(Construct::Options, position, |p| {
+            p.parse_group(Terminator::EndOptions)
+        })
+    }
+
+    /// Checks the arguments of a command or environment against their types, given the position
+    /// at which each starts. An argument of the wrong type is kept as it is, with a
+    /// `Warning::InvalidArgument`, or is an `ErrorKind::Denied` error if
+    /// `ParserOptions::strict_arguments` is set.
+    fn check_arguments(
+        &mut self,
+        name: &str,
+        args: &[Vec<Node>],
+        types: &[ArgType],
+        positions: &[Position],
+    ) -> Result<(), Error> {
+        for (index, (arg, &ty)) in args.iter().zip(types).enumerate() {
+            if ty.parse(arg).is_some() {
+                continue;
+            }
+            let position = positions.get(index).cloned().unwrap_or_else(|| self.location());
+            let expected = ty.description();
+            let warning = Warning::InvalidArgument(name.into(), index + 1, expected, position);
+            if self.options.get_strict_arguments() {
+                return Err(ErrorKind::Denied(warning).into());
+            }
+            self.warnings.push(warning);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the innermost open construct, other than a user-defined environment, whose
+    /// content belongs to the enclosing one, is a `description` environment, so that an `\item`
+    /// starts with its term.
+    fn in_description(&self) -> bool {
+        let innermost = self.open.iter().rev().find(|(construct, _)| match *construct {
+            Construct::Environment(ref name) => self.lookup_environment(name).is_none(),
+            _ => true,
+        });
+        matches!(
+            innermost,
+            Some(&(Construct::Environment(ref name), _)) if name == "description"
+        )
+    }
+
+    /// Parses the bracketed term of an `\item` of a `description` list. An item without one is
+    /// given an empty term, with a warning.
+    fn parse_term(&mut self, position: Position) -> Result<Vec<Node>, Error> {
+        if self.peek_token()? != Some(&Token::Char('[')) {
+            self.warnings.push(Warning::MissingTerm(position));
+        }
+        self.parse_options()
+    }
+
+    /// Reads up to `count` brace-delimited arguments as unparsed tokens. Missing arguments are
+    /// treated as empty.
+    fn read_raw_arguments(&mut self, count: usize) -> Result<Vec<Vec<Token>>, Error> {
+        let mut args = Vec::with_capacity(count);
+        while args.len() < count && self.eat_argument_start()? {
+            let position = self.location();
+            args.push(self.in_construct(Construct::Argument, position, Parser::read_raw_group)?);
+        }
+        args.resize(count, Vec::new());
+        Ok(args)
+    }
+
+    /// Reads tokens up to the `}` matching an already-consumed `{`, without parsing them.
+    fn read_raw_group(&mut self) -> Result<Vec<Token>, Error> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = match self.next_pending()? {
+                Some(Pending::Token(token)) => token,
+                None => return Err(self.unclosed("`}` to close the argument")),
+                other => {
+                    return Err(self.unexpected("`}` to close the argument", other.as_ref()))
+                }
+            };
+            match token {
+                Token::BeginGroup => depth += 1,
+                Token::EndGroup if depth == 0 => break,
+                Token::EndGroup => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    /// Reads a brace-delimited name, such as the name of an environment, consisting only of
+    /// ordinary characters.
+    fn read_name(&mut self) -> Result<String, Error> {
+        if !self.eat_begin_group()? {
+            let found = self.next_pending()?;
+            return Err(self.unexpected("`{` to start a name", found.as_ref()));
+        }
+        let mut name = String::new();
+        loop {
+            match self.next_pending()? {
+                Some(Pending::Token(Token::Char(c))) => name.push(c),
+                Some(Pending::Token(Token::Text(text))) => name.push_str(&text),
+                Some(Pending::Token(Token::EndGroup)) => return Ok(name),
+                None => return Err(self.unexpected("`}` to close the name", None)),
+                found => {
+                    return Err(self.unexpected("a name of ordinary characters", found.as_ref()))
+                }
+            }
+        }
+    }
+
+    /// Parses an environment, after the initial `\begin`, and appends the resulting nodes to
+    /// `nodes`.
+    ///
+    /// A user-defined environment is replaced by its begin tokens, followed by its content, and
+    /// then its end tokens; both may refer to the arguments of the `\begin` command. Any other
+    /// environment becomes a `Node::Environment`, whose content is grouped into items if it is a
+    /// list, except for a `table` environment, which becomes a `Node::Table`.
+    fn parse_environment(&mut self, nodes: &mut Vec<Node>) -> Result<(), Error> {
+        let position = self.location();
+        let name = self.read_name()?;
+        if let Some(env) = self.lookup_environment(&name).cloned() {
+            let args = self.read_raw_arguments(env.arguments)?;
+            let limit = self.expand(position)?;
+            let begin = macros::substitute(&env.begin, &args, limit, position)?;
+            let end = macros::substitute(&env.end, &args, limit - begin.len(), position)?;
+            self.push_scope();
+            self.pending
+                .push(Pending::EnvironmentContent(name, end, position));
+            self.push_tokens(begin);
+            return Ok(());
+        }
+        let mut args = Vec::new();
+        if commands::environment_options(&name) {
+            args.push(self.parse_options()?);
+        }
+        let start = args.len();
+        let mut positions = Vec::new();
+        let count = commands::environment_arguments(&name);
+        args.extend(self.parse_arguments_at(count, &mut positions)?);
+        let types = commands::environment_argument_types(&name);
+        let command = format!("begin{{{}}}", name);
+        self.check_arguments(&command, &args[start..], types, &positions)?;
+        let construct = Construct::Environment(name.clone());
+        let content = self.in_construct(construct, position, |p| {
+            p.parse_group(Terminator::End(&name))
+        })?;
+        if name == "table" {
+            nodes.push(Node::Table(table(args, content, position)?));
+            return Ok(());
+        }
+        let content = if LIST_ENVIRONMENTS.contains(&&*name) {
+            self.split_items(&name, content)
+        } else {
+            self.split_paragraphs(content)
+        };
+        nodes.push(Node::Environment(Environment {
+            name,
+            args,
+            nodes: content,
+        }));
+        Ok(())
+    }
+
+    /// Formats a date, or warns if the format is invalid, in which case the date is written as
+    /// `YYYY-MM-DD`.
+    fn format_date(&mut self, date: Date, format: &str, position: Position) -> String {
+        let locale = self.options.get_locale();
+        match date.format(format, locale) {
+            Some(text) => text,
+            None => {
+                let text = date.format("%F", locale).unwrap_or_default();
+                self.warnings
+                    .push(Warning::InvalidDateFormat(format.into(), position));
+                text
+            }
+        }
+    }
+
+    /// Returns the text of a `\formatdate` command, whose first argument is a date written as
+    /// `YYYY-MM-DD`, and whose second is the format to write it in, as described in the `date`
+    /// module. If the date cannot be parsed, it is written as it is, with a warning.
+    fn format_date_command(&mut self, args: &[Vec<Node>], position: Position) -> String {
+        let text = args.first().map(|arg| ast::text_content(arg)).unwrap_or_default();
+        let format = match args.get(1) {
+            Some(arg) => ast::text_content(arg),
+            None => self.options.get_locale().date_format().to_owned(),
+        };
+        match Date::parse(&text) {
+            Some(date) => self.format_date(date, &format, position),
+            None => {
+                self.warnings
+                    .push(Warning::InvalidDate(text.trim().to_owned(), position));
+                text
+            }
+        }
+    }
+
+    /// Selects the branch of an `\ifformat` command matching the target format. The first
+    /// argument is a comma-separated list of format names; if the target format is among them,
+    /// the second argument is selected, and otherwise (including when no target format has been
+    /// set) the third is.
+    fn if_format(&mut self, args: Vec<Vec<Node>>, position: Position) -> Vec<Node> {
+        let mut args = args.into_iter();
+        let formats = args.next().map(|arg| ast::text_content(&arg)).unwrap_or_default();
+        let then = args.next().unwrap_or_default();
+        let otherwise = args.next().unwrap_or_default();
+        let mut matched = false;
+        for format in formats.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            if !KNOWN_FORMATS.contains(&format) {
+                self.warnings
+                    .push(Warning::UnknownFormat(format.into(), position));
+            }
+            matched |= self.options.get_target_format() == Some(format);
+        }
+        if matched {
+            then
+        } else {
+            otherwise
+        }
+    }
+}
+
+/// Constructs a table from the arguments and content of a `table` environment, which started at
+/// the given position.
+///
+/// The first argument gives the alignment of each column as one of the letters `l`, `c`, and `r`,
+/// and the second gives the caption. The content must consist of `\row` commands, taking one
+/// argument per cell, except that the first may instead be a `\header`.
+fn table(
+    args: Vec<Vec<Node>>,
+    content: Vec<Node>,
+    position: Position,
+) -> Result<Table, Error> {
+    let invalid = || Error::from(ErrorKind::InvalidTable(position));
+    let mut args = args.into_iter();
+    let columns = args
+        .next()
+        .map(|arg| ast::text_content(&arg))
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            'l' => Ok(Alignment::Left),
+            'c' => Ok(Alignment::Center),
+            'r' => Ok(Alignment::Right),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<_, _>>()?;
+    let caption = args.next().unwrap_or_default();
+    let mut header = false;
+    let mut rows = Vec::new();
+    for node in content {
+        match node {
+            Node::Text(ref text) if text.trim().is_empty() => {}
+            Node::Command(command) if command.name == "row" => rows.push(command.args),
+            Node::Command(command) if command.name == "header" && rows.is_empty() => {
+                header = true;
+                rows.push(command.args);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(Table {
+        columns,
+        header,
+        rows,
+        caption,
+    })
+}
+
+/// Removes the indentation common to the lines of verbatim content after the first, as described
+/// at `ParserOptions::dedent_verbatim`.
+fn dedent(content: &str) -> String {
+    let is_blank = |line: &str| line.chars().all(|c| c == ' ' || c == '\t');
+    let mut lines = content.split('\n');
+    let first = lines.next().unwrap_or_default();
+    let mut common: Option<&str> = None;
+    for line in lines.clone().filter(|line| !is_blank(line)) {
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        let len = match common {
+            Some(common) => common.bytes().zip(indent.bytes()).take_while(|(a, b)| a == b).count(),
+            None => indent.len(),
+        };
+        common = Some(&indent[..len]);
+    }
+    let common = common.map_or(0, str::len);
+    let mut dedented = String::with_capacity(content.len());
+    dedented.push_str(first);
+    for line in lines {
+        dedented.push('\n');
+        if !is_blank(line) {
+            dedented.push_str(&line[common..]);
+        }
+    }
+    dedented
+}
+
+/// Appends a character to the list of nodes, extending the last node if it is a `Node::Text`.
+fn push_char(nodes: &mut Vec<Node>, c: char) {
+    if let Some(&mut Node::Text(ref mut text)) = nodes.last_mut() {
+        text.push(c);
+        return;
+    }
+    nodes.push(Node::Text(c.to_string()));
+}
+
+/// Appends a node to the list of nodes, merging adjacent `Node::Text`s.
+fn push_node(nodes: &mut Vec<Node>, node: Node) {
+    if let Node::Text(ref new) = node {
+        if let Some(&mut Node::Text(ref mut text)) = nodes.last_mut() {
+            text.push_str(new);
+            return;
+        }
+    }
+    nodes.push(node);
 }
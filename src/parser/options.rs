@@ -0,0 +1,239 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use date::Date;
+use limits::Limits;
+use lints::Lints;
+use locale::Locale;
+
+/// The names of the output formats recognized by `\ifformat`.
+pub const KNOWN_FORMATS: &[&str] = &["html", "plain", "ansi", "markdown", "latex", "roff"];
+
+/// Options controlling the behavior of a `Parser`.
+#[derive(Clone, Debug, Default)]
+pub struct ParserOptions {
+    target_format: Option<String>,
+    definitions_file: Option<PathBuf>,
+    skip_space_before_arguments: bool,
+    skip_newline_before_arguments: bool,
+    smart_punctuation: bool,
+    backslash_break: bool,
+    dedent_verbatim: bool,
+    strict_arguments: bool,
+    lints: Lints,
+    limits: Limits,
+    date_format: Option<String>,
+    today: Option<Date>,
+    locale: Locale,
+    cancel_token: Option<Arc<AtomicBool>>,
+    timings: bool,
+}
+
+impl ParserOptions {
+    /// Constructs a new `ParserOptions` with the default settings.
+    pub fn new() -> ParserOptions {
+        ParserOptions::default()
+    }
+
+    /// Sets the output format that the document is being parsed for, which selects the branch
+    /// taken by `\ifformat`.
+    pub fn target_format<S: Into<String>>(mut self, format: S) -> ParserOptions {
+        self.target_format = Some(format.into());
+        self
+    }
+
+    /// Returns the output format that the document is being parsed for, if any.
+    pub fn get_target_format(&self) -> Option<&str> {
+        self.target_format.as_deref()
+    }
+
+    /// Sets a file of definitions to load before parsing the document. See
+    /// `Parser::load_definitions`.
+    pub fn definitions_file<P: Into<PathBuf>>(mut self, path: P) -> ParserOptions {
+        self.definitions_file = Some(path.into());
+        self
+    }
+
+    /// Returns the file of definitions to load before parsing the document, if any.
+    pub fn get_definitions_file(&self) -> Option<&Path> {
+        self.definitions_file.as_deref()
+    }
+
+    /// Sets whether spaces and tabs between a command and its arguments are skipped, for commands
+    /// which take arguments. Defaults to `false`.
+    pub fn skip_space_before_arguments(mut self, skip: bool) -> ParserOptions {
+        self.skip_space_before_arguments = skip;
+        self
+    }
+
+    /// Returns whether spaces and tabs between a command and its arguments are skipped.
+    pub fn get_skip_space_before_arguments(&self) -> bool {
+        self.skip_space_before_arguments
+    }
+
+    /// Sets whether a single newline between a command and its arguments is skipped, for commands
+    /// which take arguments. A blank line is never skipped, since it separates paragraphs.
+    /// Defaults to `false`.
+    pub fn skip_newline_before_arguments(mut self, skip: bool) -> ParserOptions {
+        self.skip_newline_before_arguments = skip;
+        self
+    }
+
+    /// Returns whether a single newline between a command and its arguments is skipped.
+    pub fn get_skip_newline_before_arguments(&self) -> bool {
+        self.skip_newline_before_arguments
+    }
+    /// Sets whether typographic quotes, dashes, and ellipses are substituted for their ASCII
+    /// equivalents in the text, as described at `Tokens::smart_punctuation`. Verbatim and raw
+    /// content are never changed. Defaults to `false`.
+    pub fn smart_punctuation(mut self, smart: bool) -> ParserOptions {
+        self.smart_punctuation = smart;
+        self
+    }
+
+    /// Returns whether typographic punctuation is substituted in the text.
+    pub fn get_smart_punctuation(&self) -> bool {
+        self.smart_punctuation
+    }
+
+    /// Sets whether `\\` is a line break, the same as `\break`, rather than a backslash in the
+    /// text. A backslash can then only be written in verbatim or raw content. Defaults to `false`.
+    pub fn backslash_break(mut self, enabled: bool) -> ParserOptions {
+        self.backslash_break = enabled;
+        self
+    }
+
+    /// Returns whether `\\` is a line break.
+    pub fn get_backslash_break(&self) -> bool {
+        self.backslash_break
+    }
+
+    /// Sets whether the indentation common to the lines of the contents of each `\verbatim`
+    /// command is removed, so that a listing indented to match the source around it is not
+    /// shifted right when rendered. The indentation removed is the longest run of spaces and
+    /// tabs which starts every line, where a tab and a space are never taken to match. The first
+    /// line, which follows the opening delimiter on the line of the command, is left as it is,
+    /// and does not count, and lines of only spaces and tabs are emptied, and do not count either.
+    /// Defaults to `false`.
+    pub fn dedent_verbatim(mut self, dedent: bool) -> ParserOptions {
+        self.dedent_verbatim = dedent;
+        self
+    }
+
+    /// Returns whether the common indentation of verbatim contents is removed.
+    pub fn get_dedent_verbatim(&self) -> bool {
+        self.dedent_verbatim
+    }
+
+    /// Sets whether an argument of a command which is not of the type given by its
+    /// `CommandSpec`, such as a `\label` containing spaces, stops parsing with an
+    /// `ErrorKind::Denied` error, rather than being kept with a `Warning::InvalidArgument`.
+    /// Defaults to `false`.
+    pub fn strict_arguments(mut self, strict: bool) -> ParserOptions {
+        self.strict_arguments = strict;
+        self
+    }
+
+    /// Returns whether an argument of the wrong type stops parsing.
+    pub fn get_strict_arguments(&self) -> bool {
+        self.strict_arguments
+    }
+
+    /// Sets the lints checked on the input, including any definitions files. Warnings are
+    /// reported by `Parser::warnings`, and a denied lint stops parsing with an
+    /// `ErrorKind::Denied` error. Defaults to allowing every lint.
+    pub fn lints(mut self, lints: Lints) -> ParserOptions {
+        self.lints = lints;
+        self
+    }
+
+    /// Returns the lints checked on the input.
+    pub fn get_lints(&self) -> &Lints {
+        &self.lints
+    }
+
+    /// Sets the limits on the lengths of command names, verbatim contents, and lines of the
+    /// input, including any definitions files, as described at `Tokens::limits`. Defaults to
+    /// `Limits::default()`.
+    pub fn limits(mut self, limits: Limits) -> ParserOptions {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the limits on the input.
+    pub fn get_limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Sets the format of the date written by `\today`, by the conversions described in the
+    /// `date` module. Defaults to the date format of the locale.
+    pub fn date_format<S: Into<String>>(mut self, format: S) -> ParserOptions {
+        self.date_format = Some(format.into());
+        self
+    }
+
+    /// Returns the format of the date written by `\today`.
+    pub fn get_date_format(&self) -> &str {
+        self.date_format
+            .as_deref()
+            .unwrap_or_else(|| self.locale.date_format())
+    }
+
+    /// Sets the date written by `\today`, in place of that given by `Date::today`, such as to
+    /// render a document as of a fixed day. Defaults to `None`.
+    pub fn today(mut self, today: Option<Date>) -> ParserOptions {
+        self.today = today;
+        self
+    }
+
+    /// Returns the date written by `\today`, if it is set.
+    pub fn get_today(&self) -> Option<Date> {
+        self.today
+    }
+
+    /// Sets the locale whose names and date format are used to write dates. Defaults to English.
+    pub fn locale(mut self, locale: Locale) -> ParserOptions {
+        self.locale = locale;
+        self
+    }
+
+    /// Returns the locale used to write dates.
+    pub fn get_locale(&self) -> &Locale {
+        &self.locale
+    }
+
+    /// Sets a flag which, once it is set, from this or any other thread, stops parsing with an
+    /// `ErrorKind::Cancelled` error, so that a parse which is no longer wanted can be abandoned.
+    /// The flag is checked before each token is read, including the tokens of definitions files
+    /// and of the expansions of user-defined commands. Defaults to none.
+    pub fn cancel_token(mut self, token: Arc<AtomicBool>) -> ParserOptions {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// Returns the flag which cancels parsing, if any.
+    pub fn get_cancel_token(&self) -> Option<&Arc<AtomicBool>> {
+        self.cancel_token.as_ref()
+    }
+
+    /// Sets whether the time taken to parse is measured, and given by `Parser::stats`. `convert`
+    /// also measures resolving citations and rendering if it is set. Defaults to `false`, so that
+    /// the parser does not read the clock for each block when nothing will look at the time.
+    pub fn timings(mut self, timings: bool) -> ParserOptions {
+        self.timings = timings;
+        self
+    }
+
+    /// Returns whether the time taken to parse is measured.
+    pub fn get_timings(&self) -> bool {
+        self.timings
+    }
+
+    /// Returns whether the flag which cancels parsing is set.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel_token
+            .as_ref()
+            .is_some_and(|token| token.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,121 @@
+use ast::{Command, Node};
+
+/// Groups a sequence of nodes into paragraphs. Paragraphs are separated by blank lines, and by
+/// block nodes (as determined by `is_block`), which are left outside of any paragraph. Whitespace
+/// at the start and end of each paragraph is removed, and empty paragraphs are dropped.
+pub fn split_paragraphs<F>(nodes: Vec<Node>, is_block: F) -> Vec<Node>
+where
+    F: Fn(&Node) -> bool,
+{
+    let mut blocks = Vec::new();
+    let mut paragraph = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Text(mut text) => {
+                while let Some((start, end)) = find_blank_line(&text) {
+                    let rest = text.split_off(end);
+                    text.truncate(start);
+                    paragraph.push(Node::Text(text));
+                    finish_paragraph(&mut blocks, &mut paragraph);
+                    text = rest;
+                }
+                paragraph.push(Node::Text(text));
+            }
+            node => {
+                if is_block(&node) {
+                    finish_paragraph(&mut blocks, &mut paragraph);
+                    blocks.push(node);
+                } else {
+                    paragraph.push(node);
+                }
+            }
+        }
+    }
+    finish_paragraph(&mut blocks, &mut paragraph);
+    blocks
+}
+
+/// Groups the content of a list environment into items, each of which becomes an `\item` command
+/// whose last argument holds the content up to the next `\item`, split into paragraphs. Content
+/// before the first `\item` is split into paragraphs and left outside of any item. If `terms` is
+/// set, as for a `description` list, each `\item` already holds its term as its first argument.
+pub fn split_items<F>(nodes: Vec<Node>, terms: bool, is_block: F) -> Vec<Node>
+where
+    F: Fn(&Node) -> bool,
+{
+    let mut blocks = Vec::new();
+    let mut item = None;
+    let mut content = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Command(ref command)
+                if command.name == "item" && command.args.len() == usize::from(terms) =>
+            {
+                finish_item(&mut blocks, item.take(), &mut content, &is_block);
+                item = Some(command.clone());
+            }
+            node => content.push(node),
+        }
+    }
+    finish_item(&mut blocks, item, &mut content, &is_block);
+    blocks
+}
+
+/// Moves the content of an item into `blocks`, inside the item if there is one.
+fn finish_item<F>(
+    blocks: &mut Vec<Node>,
+    item: Option<Command>,
+    content: &mut Vec<Node>,
+    is_block: &F,
+) where
+    F: Fn(&Node) -> bool,
+{
+    let content = split_paragraphs(content.split_off(0), is_block);
+    match item {
+        Some(mut item) => {
+            item.args.push(content);
+            blocks.push(Node::Command(item));
+        }
+        None => blocks.extend(content),
+    }
+}
+
+/// Trims the whitespace from the ends of `paragraph`, and, if anything is left, moves it into
+/// `blocks` as a `Node::Paragraph`.
+fn finish_paragraph(blocks: &mut Vec<Node>, paragraph: &mut Vec<Node>) {
+    while let Some(&mut Node::Text(ref mut text)) = paragraph.first_mut() {
+        let trimmed = text.len() - text.trim_start().len();
+        text.drain(..trimmed);
+        if !text.is_empty() {
+            break;
+        }
+        paragraph.remove(0);
+    }
+    while let Some(&mut Node::Text(ref mut text)) = paragraph.last_mut() {
+        let trimmed = text.trim_end().len();
+        text.truncate(trimmed);
+        if !text.is_empty() {
+            break;
+        }
+        paragraph.pop();
+    }
+    if !paragraph.is_empty() {
+        blocks.push(Node::Paragraph(paragraph.split_off(0)));
+    }
+}
+
+/// Finds the first blank line in `text`, returning the byte range from the end of the preceding
+/// line to the start of the next non-whitespace character.
+pub fn find_blank_line(text: &str) -> Option<(usize, usize)> {
+    let mut search = 0;
+    while let Some(i) = text[search..].find('\n') {
+        let start = search + i;
+        let rest = text[start + 1..].trim_start_matches([' ', '\t']);
+        if rest.starts_with('\n') {
+            let end = text.len() - text[start..].trim_start().len();
+            return Some((start, end));
+        }
+        search = start + 1;
+    }
+    None
+}
@@ -0,0 +1,160 @@
+//! Abbreviations, which `\abbr` expands at their first use.
+//!
+//! `\defabbr{AST}{abstract syntax tree}` defines the abbreviation `AST`, and `\abbr{AST}` uses it.
+//! The first use of an abbreviation is written as its expansion followed by the abbreviation in
+//! parentheses, "abstract syntax tree (AST)", and later uses as the abbreviation alone. A use may
+//! also give the expansion itself, as `\abbr{AST}{abstract syntax tree}`, which defines the
+//! abbreviation as `\defabbr` does. Definitions apply to the whole document, wherever they are
+//! given, and if an abbreviation is defined more than once, the first definition is used. An
+//! abbreviation with no definition is written as it is, with a
+//! `Warning::UndefinedAbbreviation`.
+//!
+//! Which use is the first depends on the order in which a renderer writes the document, so each
+//! renderer keeps track of the uses with an `Abbreviations`. If `reset_at_sections` is set, each
+//! abbreviation is expanded again at its first use after every `\section`, as at the start of a
+//! chapter.
+
+use std::collections::{HashMap, HashSet};
+
+use ast::{self, Command, Node};
+use errors::Warning;
+use super::outline;
+use super::RenderReport;
+
+/// A use of an abbreviation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Abbreviation {
+    /// The abbreviation.
+    pub key: String,
+    /// The expansion of the abbreviation, or `None` if it has no definition.
+    pub expansion: Option<String>,
+    /// Whether this is the first use of the abbreviation, which is written with its expansion.
+    pub first: bool,
+}
+
+impl Abbreviation {
+    /// Returns the text of the use for formats without markup: the expansion followed by the
+    /// abbreviation in parentheses for the first use, or the abbreviation alone otherwise.
+    pub fn text(&self) -> String {
+        match self.expansion {
+            Some(ref expansion) if self.first => format!("{} ({})", expansion, self.key),
+            _ => self.key.clone(),
+        }
+    }
+}
+
+/// The definitions of the abbreviations of a document, and which of them have been used.
+#[derive(Clone, Debug, Default)]
+pub struct Abbreviations {
+    definitions: HashMap<String, String>,
+    used: HashSet<String>,
+    reset_at_sections: bool,
+}
+
+impl Abbreviations {
+    /// Constructs a new `Abbreviations` holding the definitions given among the nodes, at any
+    /// depth, by `\defabbr` and by `\abbr` with an expansion.
+    pub fn new(nodes: &[Node]) -> Abbreviations {
+        let mut abbreviations = Abbreviations::default();
+        abbreviations.collect(nodes);
+        abbreviations
+    }
+
+    /// Returns this `Abbreviations`, set to forget the uses so far at every `\section`, so that
+    /// each abbreviation is expanded again at its first use in the section.
+    pub fn reset_at_sections(mut self, reset: bool) -> Abbreviations {
+        self.reset_at_sections = reset;
+        self
+    }
+
+    /// Adds the definitions among the nodes.
+    fn collect(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match *node {
+                Node::Command(ref command) => {
+                    self.define(command);
+                    for arg in &command.args {
+                        self.collect(arg);
+                    }
+                }
+                Node::Paragraph(ref nodes) | Node::Group(ref nodes) => self.collect(nodes),
+                Node::Environment(ref env) => {
+                    for arg in &env.args {
+                        self.collect(arg);
+                    }
+                    self.collect(&env.nodes);
+                }
+                Node::Table(ref table) => {
+                    for cell in table.rows.iter().flatten() {
+                        self.collect(cell);
+                    }
+                    self.collect(&table.caption);
+                }
+                Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => {}
+            }
+        }
+    }
+
+    /// Adds the definition given by a command, if it is a `\defabbr`, or an `\abbr` with an
+    /// expansion, and the abbreviation has no definition yet. A definition with an empty
+    /// abbreviation or expansion is ignored.
+    pub fn define(&mut self, command: &Command) {
+        if command.name != "defabbr" && command.name != "abbr" {
+            return;
+        }
+        let key = key(command);
+        let expansion = command
+            .args
+            .get(1)
+            .map(|arg| ast::text_content(arg).split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        if !key.is_empty() && !expansion.is_empty() {
+            self.definitions.entry(key).or_insert(expansion);
+        }
+    }
+
+    /// Returns the expansion of an abbreviation, if it has a definition.
+    pub fn expansion(&self, key: &str) -> Option<&str> {
+        self.definitions.get(key).map(|expansion| &**expansion)
+    }
+
+    /// Returns the use of an abbreviation by an `\abbr` command, and records it, so that later
+    /// uses are not the first. If the abbreviation has no definition, a warning is added to the
+    /// report.
+    pub fn use_abbreviation(
+        &mut self,
+        command: &Command,
+        report: &mut RenderReport,
+    ) -> Abbreviation {
+        self.define(command);
+        let key = key(command);
+        let expansion = self.expansion(&key).map(str::to_owned);
+        if expansion.is_none() {
+            report.warn(Warning::UndefinedAbbreviation(key.clone()));
+        }
+        let first = self.used.insert(key.clone());
+        Abbreviation {
+            key,
+            expansion,
+            first,
+        }
+    }
+
+    /// Notes a heading command, forgetting the uses so far if it is a `\section` and the uses
+    /// are reset at sections.
+    pub fn heading(&mut self, command: &Command) {
+        if self.reset_at_sections && outline::heading_level(&command.name) == Some(1) {
+            self.used.clear();
+        }
+    }
+}
+
+/// Returns the abbreviation used or defined by an `\abbr` or `\defabbr` command, which is its
+/// first argument, without surrounding whitespace.
+pub fn key(command: &Command) -> String {
+    command
+        .args
+        .first()
+        .map(|arg| ast::text_content(arg).trim().to_owned())
+        .unwrap_or_default()
+}
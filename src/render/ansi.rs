@@ -0,0 +1,510 @@
+use std::env;
+use std::fmt;
+use std::io::Write;
+
+use ast::{self, Command, Document, Environment, Table, Verbatim};
+use errors::Error;
+use values::{self, Color};
+use super::outline;
+use super::plain::{self, PlainOptions, PlainWriter};
+use super::{has_link_text, link_address, link_url, render_document, FmtWriter, RenderReport};
+use super::{Renderer, Visit};
+
+/// How `\code` and verbatim blocks are styled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CodeStyle {
+    /// Dimmed text.
+    Dim,
+    /// Reverse video.
+    Reverse,
+}
+
+/// How many colors the terminal can show, which the colors given by `\color` are matched to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorDepth {
+    /// The 16 colors of the basic palette, whose appearance varies between terminals.
+    Ansi16,
+    /// The 256 colors of the extended palette.
+    Ansi256,
+    /// Any color, given by its red, green, and blue components.
+    TrueColor,
+}
+
+/// Options controlling the output of the ANSI terminal renderer.
+#[derive(Clone, Debug)]
+pub struct AnsiOptions {
+    plain: PlainOptions,
+    color: bool,
+    color_depth: ColorDepth,
+    italic: bool,
+    code_style: CodeStyle,
+    hyperlinks: bool,
+}
+
+impl Default for AnsiOptions {
+    fn default() -> AnsiOptions {
+        AnsiOptions {
+            plain: PlainOptions::default(),
+            color: true,
+            color_depth: ColorDepth::Ansi16,
+            italic: true,
+            code_style: CodeStyle::Dim,
+            hyperlinks: false,
+        }
+    }
+}
+
+impl AnsiOptions {
+    /// Constructs a new `AnsiOptions` with the default settings.
+    pub fn new() -> AnsiOptions {
+        AnsiOptions::default()
+    }
+
+    /// Constructs a new `AnsiOptions` with the default settings, except that styling is disabled
+    /// if the `NO_COLOR` environment variable is set to a non-empty value, and that the color
+    /// depth is `ColorDepth::TrueColor` if `COLORTERM` is `truecolor` or `24bit`, or
+    /// `ColorDepth::Ansi256` if `TERM` ends with `256color`.
+    pub fn from_env() -> AnsiOptions {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let depth = if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if env::var("TERM").is_ok_and(|term| term.ends_with("256color")) {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        };
+        AnsiOptions::default().color(!no_color).color_depth(depth)
+    }
+
+    /// Sets the options controlling the layout of the text, which is the same as that of the
+    /// plain-text renderer.
+    pub fn plain(mut self, plain: PlainOptions) -> AnsiOptions {
+        self.plain = plain;
+        self
+    }
+
+    /// Returns the options controlling the layout of the text.
+    pub fn get_plain(&self) -> &PlainOptions {
+        &self.plain
+    }
+
+    /// Sets whether escape sequences are emitted at all. If this is `false`, the output is
+    /// identical to that of the plain-text renderer. Defaults to `true`.
+    pub fn color(mut self, color: bool) -> AnsiOptions {
+        self.color = color;
+        self
+    }
+
+    /// Sets how many colors the terminal can show. A color given to `\color` as one of the eight
+    /// colors of a terminal, such as `red` or `bright-red`, is always written as that color of
+    /// the basic palette; any other is written as the nearest color the terminal can show.
+    /// Defaults to `ColorDepth::Ansi16`.
+    pub fn color_depth(mut self, depth: ColorDepth) -> AnsiOptions {
+        self.color_depth = depth;
+        self
+    }
+
+    /// Sets whether `\emph` is rendered in italics, rather than underlined, for terminals which do
+    /// not support italics. Defaults to `true`.
+    pub fn italic(mut self, italic: bool) -> AnsiOptions {
+        self.italic = italic;
+        self
+    }
+
+    /// Sets how `\code` and verbatim blocks are styled. Defaults to `CodeStyle::Dim`.
+    pub fn code_style(mut self, style: CodeStyle) -> AnsiOptions {
+        self.code_style = style;
+        self
+    }
+
+    /// Sets whether links are written as OSC 8 hyperlinks, which many terminals show as text that
+    /// can be clicked, rather than with their URL following them in angle brackets. Defaults to
+    /// `false`.
+    pub fn hyperlinks(mut self, hyperlinks: bool) -> AnsiOptions {
+        self.hyperlinks = hyperlinks;
+        self
+    }
+}
+
+/// Renders a document as styled text for a terminal to the given output.
+///
+/// The layout is that of the plain-text renderer, with `\bold`, `\emph`, `\code`, `\color`,
+/// headings, and verbatim blocks styled using SGR escape sequences. Links may be written as
+/// hyperlinks, if `AnsiOptions::hyperlinks` is set.
+pub fn render<W: Write>(
+    document: &Document,
+    options: &AnsiOptions,
+    out: &mut W,
+) -> Result<RenderReport, Error> {
+    if !options.color {
+        return plain::render(document, &options.plain, out);
+    }
+    let mut writer = AnsiWriter {
+        options,
+        plain: PlainWriter::new(document, &options.plain, out),
+        styles: Vec::new(),
+    };
+    render_document(document, &mut writer)?;
+    plain::write_footnotes(
+        &mut writer,
+        |writer| &mut writer.plain,
+        Some(plain::FOOTNOTE_SEPARATOR),
+        plain::footnote_marker,
+    )?;
+    Ok(writer.plain.report)
+}
+
+/// Renders a document as styled text for a terminal to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    options: &AnsiOptions,
+    out: &mut F,
+) -> Result<RenderReport, Error> {
+    render(document, options, &mut FmtWriter::new(out))
+}
+
+/// Renders a document as styled text for a terminal, returning the result as a `String`. Any
+/// warnings are discarded.
+pub fn render_to_string(document: &Document, options: &AnsiOptions) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, options, &mut out)?;
+    Ok(out)
+}
+
+/// A combination of text attributes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Style {
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    reverse: bool,
+    /// The foreground color.
+    color: Option<Foreground>,
+}
+
+/// A foreground color, as it is written in an SGR escape sequence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Foreground {
+    /// A color of the basic palette, as its SGR parameter, such as 31 for red.
+    Basic(u8),
+    /// A color of the 256-color palette, as its index.
+    Indexed(u8),
+    /// A color given by its components.
+    Rgb(Color),
+}
+
+impl Style {
+    /// Returns the style with the attributes of `other` added to it.
+    fn with(self, other: Style) -> Style {
+        Style {
+            bold: self.bold || other.bold,
+            dim: self.dim || other.dim,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            reverse: self.reverse || other.reverse,
+            color: other.color.or(self.color),
+        }
+    }
+
+    /// Returns the escape sequence which resets all attributes and then applies this style.
+    fn escape(self) -> String {
+        let mut escape = String::from("\x1b[0");
+        let flags = [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.reverse, "7"),
+        ];
+        for &(set, code) in &flags {
+            if set {
+                escape.push(';');
+                escape.push_str(code);
+            }
+        }
+        match self.color {
+            Some(Foreground::Basic(code)) => escape.push_str(&format!(";{}", code)),
+            Some(Foreground::Indexed(index)) => escape.push_str(&format!(";38;5;{}", index)),
+            Some(Foreground::Rgb(color)) => escape.push_str(&format!(
+                ";38;2;{};{};{}",
+                color.red, color.green, color.blue
+            )),
+            None => {}
+        }
+        escape.push('m');
+        escape
+    }
+}
+
+/// Returns the foreground color given by the argument of `\color`, or `None` if it is not a
+/// color. The eight colors of a terminal, optionally prefixed with `bright-`, are always those of
+/// the basic palette, and any other color is matched to the nearest the terminal can show.
+fn foreground(text: &str, depth: ColorDepth) -> Option<Foreground> {
+    let (base, name) = match text.strip_prefix("bright-") {
+        Some(name) => (90, name),
+        None => (30, text),
+    };
+    if let Some(offset) = values::TERMINAL_COLORS.iter().position(|&color| color == name) {
+        return Some(Foreground::Basic(base + offset as u8));
+    }
+    let color: Color = text.parse().ok()?;
+    Some(match depth {
+        ColorDepth::Ansi16 => match color.to_ansi16() {
+            index @ 0..=7 => Foreground::Basic(30 + index),
+            index => Foreground::Basic(90 + index - 8),
+        },
+        ColorDepth::Ansi256 => Foreground::Indexed(color.to_ansi256()),
+        ColorDepth::TrueColor => Foreground::Rgb(color),
+    })
+}
+
+/// The `Renderer` implementation backing `render`, which adds styling to a `PlainWriter`.
+struct AnsiWriter<'a, W: 'a> {
+    options: &'a AnsiOptions,
+    plain: PlainWriter<'a, W>,
+    /// The styles of the enclosing constructs, innermost last.
+    styles: Vec<Style>,
+}
+
+impl<'a, W: Write> AnsiWriter<'a, W> {
+    /// Returns the style added by a command, or `None` if the command is not styled.
+    fn command_style(&self, command: &Command) -> Option<Style> {
+        let style = match outline::unstarred(&command.name) {
+            "bold" | "section" | "subsection" => Style {
+                bold: true,
+                ..Style::default()
+            },
+            "emph" if self.options.italic => Style {
+                italic: true,
+                ..Style::default()
+            },
+            "emph" => Style {
+                underline: true,
+                ..Style::default()
+            },
+            "code" => self.code_style(),
+            "color" => Style {
+                color: command
+                    .args
+                    .first()
+                    .and_then(|arg| {
+                        foreground(ast::text_content(arg).trim(), self.options.color_depth)
+                    }),
+                ..Style::default()
+            },
+            _ => return None,
+        };
+        Some(style)
+    }
+
+    fn code_style(&self) -> Style {
+        match self.options.code_style {
+            CodeStyle::Dim => Style {
+                dim: true,
+                ..Style::default()
+            },
+            CodeStyle::Reverse => Style {
+                reverse: true,
+                ..Style::default()
+            },
+        }
+    }
+
+    fn current_style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+}
+
+impl<'a, W: Write> Renderer for AnsiWriter<'a, W> {
+    fn is_cancelled(&self) -> bool {
+        self.plain.is_cancelled()
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        self.plain.text(text)
+    }
+
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        let content = &verbatim.content;
+        let style = self.code_style();
+        self.plain
+            .styled_verbatim(content, &style.escape(), &Style::default().escape())
+    }
+
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        self.plain.raw(content)
+    }
+
+    fn begin_paragraph(&mut self) -> Result<(), Error> {
+        self.plain.begin_paragraph()
+    }
+
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        self.plain.end_paragraph()
+    }
+
+    fn begin_group(&mut self) -> Result<(), Error> {
+        self.plain.begin_group()
+    }
+
+    fn end_group(&mut self) -> Result<(), Error> {
+        self.plain.end_group()
+    }
+
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        if command.name == "link" && self.options.hyperlinks {
+            self.plain
+                .push_markup(&format!("\x1b]8;;{}\x1b\\", link_url(command)));
+            if !has_link_text(command) {
+                self.plain.text(&link_address(command))?;
+            }
+            return Ok(Visit::Children);
+        }
+        let visit = self.plain.begin_command(command)?;
+        if let Some(style) = self.command_style(command) {
+            let style = self.current_style().with(style);
+            self.styles.push(style);
+            self.plain.push_markup(&style.escape());
+        }
+        Ok(visit)
+    }
+
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        if command.name == "link" && self.options.hyperlinks {
+            self.plain.push_markup("\x1b]8;;\x1b\\");
+            return Ok(());
+        }
+        if self.command_style(command).is_some() {
+            self.styles.pop();
+            let style = self.current_style();
+            self.plain.push_markup(&style.escape());
+        }
+        self.plain.end_command(command)
+    }
+
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        self.plain.begin_argument(command, index)
+    }
+
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        self.plain.end_argument(command, index)
+    }
+
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        self.plain.begin_environment(env)
+    }
+
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        self.plain.end_environment(env)
+    }
+
+    fn begin_table(&mut self, table: &Table) -> Result<Visit, Error> {
+        self.plain.begin_table(table)
+    }
+
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        self.plain.end_table(table)
+    }
+
+    fn begin_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        self.plain.begin_row(table, row)
+    }
+
+    fn end_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        self.plain.end_row(table, row)
+    }
+
+    fn begin_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        self.plain.begin_cell(table, row, column)
+    }
+
+    fn end_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        self.plain.end_cell(table, row, column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ast::Document;
+    use parser::Parser;
+    use super::super::plain::{self, PlainOptions};
+    use super::{render_to_string, AnsiOptions, CodeStyle, ColorDepth};
+
+    /// Documents using each construct which is styled, and some which are not.
+    const DOCUMENTS: &[&str] = &[
+        "a \\bold{b \\emph{c \\color{red}{d} e} f} g \\code{h}",
+        "\\section{Heading}\n\nText with a \\link{https://example.com}{link}.",
+        "\\begin{itemize}\n\\item \\bold{one}\n\\item \\emph{two}\n\\end{itemize}",
+        "\\verbatim{let x = 1;}\n\nAfter\\footnote{A \\bold{note}.}.",
+        "\\begin{table}{lr}{\\emph{Caption}}\n\\header{a}{\\bold{b}}\n\\row{c}{d}\n\\end{table}",
+        "\\color{#336699}{blue} and \\color{notacolor}{none}, in a paragraph long enough that it \
+         is wrapped, with \\bold{styles which span the point at which it is wrapped}.",
+    ];
+
+    fn parse(source: &str) -> Document {
+        Parser::new(source.as_bytes()).parse().expect("parsing")
+    }
+
+    fn ansi(source: &str, options: &AnsiOptions) -> String {
+        render_to_string(&parse(source), options).expect("rendering")
+    }
+
+    /// Removes the SGR escape sequences from styled output.
+    fn strip(styled: &str) -> String {
+        let mut text = String::new();
+        let mut rest = styled;
+        while let Some(start) = rest.find("\x1b[") {
+            text.push_str(&rest[..start]);
+            let end = rest[start..].find('m').expect("an unterminated escape sequence");
+            rest = &rest[start + end + 1..];
+        }
+        text.push_str(rest);
+        text
+    }
+
+    #[test]
+    fn nested_styles_restore_the_outer_style() {
+        assert_eq!(
+            ansi(DOCUMENTS[0], &AnsiOptions::new()),
+            "a \x1b[0;1mb \x1b[0;1;3mc \x1b[0;1;3;31md\x1b[0;1;3m e\x1b[0;1m f\x1b[0m g \
+             \x1b[0;2mh\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn fallback_styles() {
+        let options = AnsiOptions::new().italic(false).code_style(CodeStyle::Reverse);
+        assert_eq!(
+            ansi("\\emph{a \\code{b}} c", &options),
+            "\x1b[0;4ma \x1b[0;4;7mb\x1b[0;4m\x1b[0m c\n"
+        );
+    }
+
+    #[test]
+    fn colors_are_matched_to_the_depth() {
+        let source = "\\color{red}{a}\\color{#336699}{b}";
+        let depths = [
+            (ColorDepth::Ansi16, "\x1b[0;31ma\x1b[0m\x1b[0;90mb\x1b[0m\n"),
+            (ColorDepth::Ansi256, "\x1b[0;31ma\x1b[0m\x1b[0;38;5;60mb\x1b[0m\n"),
+            (ColorDepth::TrueColor, "\x1b[0;31ma\x1b[0m\x1b[0;38;2;51;102;153mb\x1b[0m\n"),
+        ];
+        for &(depth, expected) in &depths {
+            let options = AnsiOptions::new().color_depth(depth);
+            assert_eq!(ansi(source, &options), expected, "{:?}", depth);
+        }
+    }
+
+    #[test]
+    fn output_without_color_is_plain() {
+        let layout = PlainOptions::new().width(40).wrap(true);
+        for source in DOCUMENTS {
+            let plain = plain::render_to_string(&parse(source), &layout).expect("rendering");
+            let options = AnsiOptions::new().plain(layout.clone());
+            assert_eq!(ansi(source, &options.clone().color(false)), plain, "{:?}", source);
+            // Styling changes nothing but the escape sequences.
+            assert_eq!(strip(&ansi(source, &options)), plain, "{:?}", source);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Reordering of the lines written by the text renderers for display, by the Unicode
+//! bidirectional algorithm. Requires the `bidi` feature.
+
+use unicode_bidi::{self, Level, ParagraphBidiInfo};
+
+use super::plain::escape_length;
+use super::Direction;
+
+/// The escape sequences in effect at a point of a line: the last SGR sequence, which sets every
+/// attribute, and the last hyperlink. `None` is the state at the start of the paragraph.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct State<S> {
+    style: Option<S>,
+    link: Option<S>,
+}
+
+impl<'a> State<&'a str> {
+    /// Returns the state in effect given this one, which was reached from the start of a line,
+    /// and the state `start` in effect at the start of the line.
+    fn after(&self, start: &State<String>) -> State<String> {
+        State {
+            style: self.style.map(String::from).or_else(|| start.style.clone()),
+            link: self.link.map(String::from).or_else(|| start.link.clone()),
+        }
+    }
+
+    /// Records the escape sequence `escape` as part of the state.
+    fn apply(&mut self, escape: &'a str) {
+        if escape.starts_with("\x1b]") {
+            self.link = Some(escape);
+        } else {
+            self.style = Some(escape);
+        }
+    }
+}
+
+/// Reorders the lines of a paragraph, in logical order, into the order in which they are to be
+/// displayed, with the given paragraph direction. Each line is reordered on its own, as the
+/// lines are laid out after wrapping. Characters in right-to-left runs which come in mirrored
+/// pairs, such as brackets, are mirrored.
+///
+/// The lines may hold ANSI escape sequences, which are moved with the text they style, so that
+/// the styles of the paragraph are unchanged; a style which continues from one line to the next
+/// still does. Lines with no right-to-left text are unchanged.
+pub(super) fn reorder(lines: &mut [String], direction: Direction) {
+    let level = match direction {
+        Direction::LeftToRight => Level::ltr(),
+        Direction::RightToLeft => Level::rtl(),
+        Direction::Auto => {
+            let base = lines
+                .iter()
+                .map(|line| unicode_bidi::get_base_direction(&*visible_text(line)))
+                .find(|base| *base != unicode_bidi::Direction::Mixed);
+            match base {
+                Some(unicode_bidi::Direction::Rtl) => Level::rtl(),
+                _ => Level::ltr(),
+            }
+        }
+    };
+    let mut state = State::default();
+    for line in lines {
+        if let Some(reordered) = reorder_line(line, level, &mut state) {
+            *line = reordered;
+        }
+    }
+}
+
+/// Returns `s` without its ANSI escape sequences.
+fn visible_text(s: &str) -> String {
+    let mut text = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('\x1b') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start + escape_length(&rest[start..])..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Returns a line reordered for display at the given paragraph level, or `None` if it has no
+/// right-to-left text, given the escape sequences in effect at its start, which are updated to
+/// those in effect at its end.
+fn reorder_line(line: &str, level: Level, state: &mut State<String>) -> Option<String> {
+    // The visible characters, along with the escape sequences in effect at each.
+    let mut chars = Vec::new();
+    let mut text = String::with_capacity(line.len());
+    let mut current = State::default();
+    let mut rest = line;
+    while !rest.is_empty() {
+        if rest.starts_with('\x1b') {
+            let (escape, after) = rest.split_at(escape_length(rest));
+            current.apply(escape);
+            rest = after;
+        } else if let Some(c) = rest.chars().next() {
+            chars.push((c, current.clone()));
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    let start = state.clone();
+    let end = current.after(&start);
+    *state = end.clone();
+    let info = ParagraphBidiInfo::new(&text, Some(level));
+    if !info.has_rtl() {
+        return None;
+    }
+    let levels = info.reordered_levels_per_char(0..text.len());
+    let mut reordered = String::with_capacity(line.len());
+    let mut shown = start.clone();
+    for i in ParagraphBidiInfo::reorder_visual(&levels) {
+        let (c, ref escapes) = chars[i];
+        switch(&mut reordered, &mut shown, &escapes.after(&start));
+        reordered.push(if levels[i].is_rtl() { mirror(c) } else { c });
+    }
+    switch(&mut reordered, &mut shown, &end);
+    Some(reordered)
+}
+
+/// Writes the escape sequences which change the state `shown` to `wanted`.
+fn switch(out: &mut String, shown: &mut State<String>, wanted: &State<String>) {
+    if shown.link != wanted.link {
+        out.push_str(wanted.link.as_deref().unwrap_or("\x1b]8;;\x1b\\"));
+    }
+    if shown.style != wanted.style {
+        out.push_str(wanted.style.as_deref().unwrap_or("\x1b[0m"));
+    }
+    *shown = wanted.clone();
+}
+
+/// Returns the mirrored form of a bracket or other paired character, which is displayed in a
+/// right-to-left run, or the character itself if it has none.
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        c => c,
+    }
+}
@@ -0,0 +1,2417 @@
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use ast::{self, Alignment, Command, Document, Environment, Node, Span, Table, Verbatim};
+use bibliography::{self, Citations};
+use errors::{Error, ErrorKind, ResultExt, Warning};
+use locale::Locale;
+use parser::{Blocks, Parser};
+use tokenize::SOFT_HYPHEN;
+use values::Color;
+use super::abbreviations::Abbreviations;
+use super::index::{self, Entry, Group};
+use super::outline::{self, Anchors, Heading, NumberingScheme, Outliner};
+use super::plain;
+use super::sanitize::{sanitize, Allowlist};
+use super::{has_link_text, is_horizontal_rule, link_address, link_url, render_document};
+use super::{render_document_mapped, CountingWriter, FmtWriter, Image, RenderReport, Renderer};
+use super::{is_set, item_term, quote_attribution, Direction, RuleStyle, SourceMap, Visit};
+
+/// What to do with a command which the renderer does not recognize.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnknownCommandPolicy {
+    /// Omit the command and its arguments from the output.
+    Skip,
+    /// Render the arguments inside the element given by `HtmlOptions::unknown_tag`, which is by
+    /// default a `<span>` with the class `cmd-{name}`.
+    Span,
+    /// Fail with `ErrorKind::UnknownCommand`.
+    Error,
+}
+
+/// What to do with raw content, given by `\raw`. Unless it is escaped, a paragraph of nothing but
+/// raw content is written without a `<p>` around it, so that it may hold block elements.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RawPolicy {
+    /// Write raw content into the output as it is.
+    Allow,
+    /// Escape raw content, as for text.
+    Escape,
+    /// Remove everything from raw content which is not permitted by the allowlist, as with
+    /// `sanitize::sanitize`.
+    Sanitize(Allowlist),
+}
+
+/// How the lines of verbatim blocks are numbered, as set by `HtmlOptions::line_numbers`.
+///
+/// Either way, the `n`th verbatim block of the document has the `id` `listing-n`, and each of its
+/// lines is a `<span class="line">` with the `id` `listing-n.Lm`, where `m` is its number, so that
+/// a line can be linked to. The numbers are never part of the text of the code, so that copying
+/// the code does not copy them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineNumbers {
+    /// A `<table class="listing">` of one row, with the numbers in a `<pre>` in the first cell,
+    /// each linking to its line, and the code in the second.
+    Table,
+    /// A `<pre>` in which each line starts with an empty `<a class="line-number">` linking to the
+    /// line, with its number in the `data-line` attribute, to be shown by CSS such as
+    /// `.line-number::before { content: attr(data-line) }`.
+    Gutter,
+}
+
+/// The element which a command is rendered as.
+///
+/// Class names and attribute values may contain the placeholders `{name}`, which is replaced by
+/// the name of the command, `{1}` through `{9}`, which are replaced by the text of the
+/// corresponding argument, and `{color}`, which is replaced by the first argument written as a
+/// CSS color, as described at `values::Color`. A class name or attribute containing `{color}` is
+/// omitted if the argument is not a color.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tag {
+    name: String,
+    classes: Vec<String>,
+    attributes: Vec<(String, String)>,
+    block: bool,
+    replacement: Option<String>,
+}
+
+impl Tag {
+    /// Constructs a new `Tag` for an inline element with the given name, which wraps the
+    /// arguments of the command.
+    pub fn new<S: Into<String>>(name: S) -> Tag {
+        Tag {
+            name: name.into(),
+            classes: Vec::new(),
+            attributes: Vec::new(),
+            block: false,
+            replacement: None,
+        }
+    }
+
+    /// Adds a class to the element.
+    pub fn class<S: Into<String>>(mut self, class: S) -> Tag {
+        self.classes.push(class.into());
+        self
+    }
+
+    /// Adds an attribute to the element.
+    pub fn attr<S: Into<String>, T: Into<String>>(mut self, name: S, value: T) -> Tag {
+        self.attributes.push((name.into(), value.into()));
+        self
+    }
+
+    /// Marks the element as a block element, which is followed by a line break.
+    pub fn block(mut self) -> Tag {
+        self.block = true;
+        self
+    }
+
+    /// Replaces the arguments of the command with the given HTML, which is written without
+    /// escaping.
+    pub fn replace<S: Into<String>>(mut self, html: S) -> Tag {
+        self.replacement = Some(html.into());
+        self
+    }
+}
+
+/// A function which highlights verbatim content, given the content and its language, returning
+/// HTML or `None` if it cannot highlight it.
+pub type HighlightFn = dyn Fn(&str, Option<&str>) -> Option<String>;
+
+/// A highlighter, as set by `HtmlOptions::highlighter`.
+#[derive(Clone)]
+struct Highlighter(Rc<HighlightFn>);
+
+impl fmt::Debug for Highlighter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Highlighter")
+    }
+}
+
+/// Options controlling the behavior of an `HtmlRenderer`.
+#[derive(Clone, Debug)]
+pub struct HtmlOptions {
+    unknown_commands: UnknownCommandPolicy,
+    unknown_tag: Tag,
+    commands: HashMap<String, Tag>,
+    group_spans: bool,
+    table_captions: bool,
+    highlighter: Option<Highlighter>,
+    toc_depth: usize,
+    split_level: usize,
+    numbering: NumberingScheme,
+    number_headings: bool,
+    reference_titles: bool,
+    reset_abbreviations: bool,
+    sections: bool,
+    locale: Locale,
+    raw: RawPolicy,
+    fragment: bool,
+    lang: Option<String>,
+    stylesheets: Vec<String>,
+    style: Option<String>,
+    template: Option<String>,
+    image_directory: Option<PathBuf>,
+    ascii: bool,
+    direction: Option<Direction>,
+    line_numbers: Option<LineNumbers>,
+    first_line_number: usize,
+    source_positions: bool,
+    stream: Option<bool>,
+    cancel_token: Option<Arc<AtomicBool>>,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> HtmlOptions {
+        HtmlOptions {
+            unknown_commands: UnknownCommandPolicy::Span,
+            unknown_tag: Tag::new("span").class("cmd-{name}"),
+            commands: HashMap::new(),
+            group_spans: false,
+            table_captions: false,
+            highlighter: None,
+            toc_depth: 2,
+            split_level: 1,
+            numbering: NumberingScheme::default(),
+            number_headings: true,
+            reference_titles: false,
+            reset_abbreviations: false,
+            sections: false,
+            locale: Locale::default(),
+            raw: RawPolicy::Allow,
+            fragment: true,
+            lang: None,
+            stylesheets: Vec::new(),
+            style: None,
+            template: None,
+            image_directory: None,
+            ascii: false,
+            direction: None,
+            line_numbers: None,
+            first_line_number: 1,
+            source_positions: false,
+            stream: None,
+            cancel_token: None,
+        }
+    }
+}
+
+impl HtmlOptions {
+    /// Constructs a new `HtmlOptions` with the default settings.
+    pub fn new() -> HtmlOptions {
+        HtmlOptions::default()
+    }
+
+    /// Sets what to do with unrecognized commands. Defaults to `UnknownCommandPolicy::Span`.
+    pub fn unknown_commands(mut self, policy: UnknownCommandPolicy) -> HtmlOptions {
+        self.unknown_commands = policy;
+        self
+    }
+
+    /// Sets the element used for unrecognized commands under `UnknownCommandPolicy::Span`.
+    /// Defaults to a `<span>` with the class `cmd-{name}`.
+    pub fn unknown_tag(mut self, tag: Tag) -> HtmlOptions {
+        self.unknown_tag = tag;
+        self
+    }
+
+    /// Sets the element used for the named command, replacing the built-in element if there is
+    /// one.
+    pub fn map_command<S: Into<String>>(mut self, name: S, tag: Tag) -> HtmlOptions {
+        self.commands.insert(name.into(), tag);
+        self
+    }
+
+    /// Returns the element used for the named command, or `None` if the command is not
+    /// recognized.
+    pub fn command_tag(&self, name: &str) -> Option<Tag> {
+        self.commands.get(name).cloned().or_else(|| default_tag(name))
+    }
+
+    /// Sets whether groups are rendered as `<span>` elements, rather than transparently.
+    /// Defaults to `false`.
+    pub fn group_spans(mut self, spans: bool) -> HtmlOptions {
+        self.group_spans = spans;
+        self
+    }
+
+    /// Sets whether a table with a caption is given a `<caption>` element. Defaults to `false`.
+    pub fn table_captions(mut self, captions: bool) -> HtmlOptions {
+        self.table_captions = captions;
+        self
+    }
+
+    /// Sets a function to highlight verbatim blocks, which is given the content of each block and
+    /// its language, if any. If it returns `Some`, the result is written inside the `<pre>`
+    /// element without escaping, in place of the escaped content.
+    pub fn highlighter(mut self, highlighter: Box<HighlightFn>) -> HtmlOptions {
+        self.highlighter = Some(Highlighter(Rc::from(highlighter)));
+        self
+    }
+
+    /// Sets the deepest level of heading listed by `\tableofcontents`, where `\section` is level 1
+    /// and `\subsection` is level 2. Defaults to 2.
+    pub fn toc_depth(mut self, depth: usize) -> HtmlOptions {
+        self.toc_depth = depth;
+        self
+    }
+
+    /// Sets the deepest level of heading at which `render_site` starts a new page, where
+    /// `\section` is level 1 and `\subsection` is level 2. Other ways of rendering ignore it.
+    /// Defaults to 1.
+    pub fn split_at(mut self, level: usize) -> HtmlOptions {
+        self.split_level = level;
+        self
+    }
+
+    /// Sets the scheme by which headings are numbered in headings, the table of contents, and
+    /// references. Defaults to `NumberingScheme::new()`.
+    pub fn numbering(mut self, numbering: NumberingScheme) -> HtmlOptions {
+        self.numbering = numbering;
+        self
+    }
+
+    /// Sets whether each numbered heading, and its entry in the table of contents, starts with its
+    /// number, in a `<span class="heading-number">`. Defaults to `true`.
+    pub fn number_headings(mut self, number: bool) -> HtmlOptions {
+        self.number_headings = number;
+        self
+    }
+
+    /// Sets whether a `\ref` is shown as the title of the heading it refers to, rather than its
+    /// number. Defaults to `false`.
+    pub fn reference_titles(mut self, titles: bool) -> HtmlOptions {
+        self.reference_titles = titles;
+        self
+    }
+
+    /// Sets whether each abbreviation is expanded again at its first use after every `\section`,
+    /// rather than only at its first use in the document. Defaults to `false`.
+    pub fn reset_abbreviations(mut self, reset: bool) -> HtmlOptions {
+        self.reset_abbreviations = reset;
+        self
+    }
+
+    /// Sets the locale by which the strings written by the renderer rather than taken from the
+    /// document, such as the location of the entries of the index before the first heading, are
+    /// translated. Defaults to English.
+    pub fn locale(mut self, locale: Locale) -> HtmlOptions {
+        self.locale = locale;
+        self
+    }
+
+    /// Sets what to do with raw content, which should not be written as it is if the document is
+    /// not trusted. Defaults to `RawPolicy::Allow`.
+    pub fn raw(mut self, policy: RawPolicy) -> HtmlOptions {
+        self.raw = policy;
+        self
+    }
+
+    /// Sets whether only the markup of the content is written, rather than a complete page with
+    /// a doctype, `<head>`, and `<body>`. The content is the same either way. Defaults to `true`.
+    pub fn fragment(mut self, fragment: bool) -> HtmlOptions {
+        self.fragment = fragment;
+        self
+    }
+
+    /// Sets the language given by the `lang` attribute of a complete page, and by `{{lang}}` in a
+    /// template, for documents which do not give their own with `\lang`. A page whose language is
+    /// given by neither is written without one, with a `Warning::MissingLanguage`. Defaults to
+    /// none.
+    pub fn lang<S: Into<String>>(mut self, lang: S) -> HtmlOptions {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    /// Sets whether each heading and the content which follows it, up to the next heading of the
+    /// same or a higher level, are wrapped in a `<section>` element labelled by the heading, so
+    /// that sections nest as the outline does. Defaults to `false`.
+    pub fn sections(mut self, sections: bool) -> HtmlOptions {
+        self.sections = sections;
+        self
+    }
+
+    /// Adds a stylesheet to be linked from the `<head>` of a complete page.
+    pub fn stylesheet<S: Into<String>>(mut self, href: S) -> HtmlOptions {
+        self.stylesheets.push(href.into());
+        self
+    }
+
+    /// Sets CSS to be included in a `<style>` element in the `<head>` of a complete page, after
+    /// any linked stylesheets. The CSS is written as it is.
+    pub fn style<S: Into<String>>(mut self, css: S) -> HtmlOptions {
+        self.style = Some(css.into());
+        self
+    }
+
+    /// Sets a template for the page, which is used in place of the built-in page whether or not
+    /// `HtmlOptions::fragment` is set.
+    ///
+    /// The template must contain the placeholder `{{content}}` exactly once, which is replaced by
+    /// the rendered content. It may also contain `{{toc}}`, which is replaced by the table of
+    /// contents, as for `\tableofcontents`, and `{{title}}`, `{{authors}}`, and `{{date}}`, which
+    /// are replaced by the escaped text of the `\title`, `\author`, and `\date` commands at the
+    /// top level of the document, with multiple authors separated by commas. If there is no such
+    /// command, the placeholder is replaced by nothing, with a warning. `{{lang}}` is replaced by
+    /// the language of the page, as described for `HtmlOptions::lang`. On the pages written by
+    /// `render_site`, `{{prev}}`, `{{next}}`, and `{{up}}` are replaced by links to the previous
+    /// and next pages and to the page above, if there are such pages, and `{{nav}}` by a `<nav>`
+    /// holding all three; elsewhere, they are replaced by nothing. Any other placeholder is an
+    /// error.
+    pub fn template<S: Into<String>>(mut self, template: S) -> HtmlOptions {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Sets a directory relative to which the paths of images are checked, adding a warning for
+    /// each image which does not exist. Images given by URLs are not checked. By default, no
+    /// images are checked.
+    pub fn image_directory<P: Into<PathBuf>>(mut self, directory: P) -> HtmlOptions {
+        self.image_directory = Some(directory.into());
+        self
+    }
+
+    /// Sets whether the output consists only of ASCII characters, with every other character
+    /// written as a numeric character reference, such as `&#x2014;`. This applies to all of the
+    /// output, including attribute values, verbatim blocks, and raw content, so that non-ASCII
+    /// characters in raw `<script>` or `<style>` elements, where references are not recognized,
+    /// are not preserved. Defaults to `false`.
+    pub fn ascii(mut self, ascii: bool) -> HtmlOptions {
+        self.ascii = ascii;
+        self
+    }
+
+    /// Sets the direction of paragraphs, which is given as a `dir` attribute of each paragraph,
+    /// block element, and table cell, and of the `<html>` element of a complete page, so that
+    /// the browser lays out right-to-left text, rather than the renderer reordering it as the
+    /// text renderers do. `Direction::Auto` lets the browser detect the direction of each
+    /// element from its text. Defaults to `None`, which writes no `dir` attributes.
+    pub fn direction(mut self, direction: Option<Direction>) -> HtmlOptions {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets how the lines of verbatim blocks are numbered. Defaults to `None`, which writes each
+    /// block as a `<pre>` of the code alone.
+    pub fn line_numbers(mut self, line_numbers: Option<LineNumbers>) -> HtmlOptions {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    /// Sets the number of the first line of each verbatim block, when `line_numbers` is set.
+    /// Defaults to 1.
+    pub fn first_line_number(mut self, number: usize) -> HtmlOptions {
+        self.first_line_number = number;
+        self
+    }
+
+    /// Sets whether the first block-level element written for each top-level node, such as a
+    /// paragraph, heading, list, or verbatim block, is given a `data-source-line` attribute
+    /// holding the line of the input on which the node starts, so that a preview can be scrolled
+    /// to match the input. Nested elements, such as the items of a list, and inline elements are
+    /// not given one. Only `HtmlRenderer::render_positioned`, which is given the input, writes the
+    /// attributes. Defaults to `false`.
+    pub fn source_positions(mut self, positions: bool) -> HtmlOptions {
+        self.source_positions = positions;
+        self
+    }
+
+    /// Returns the `dir` attribute given by `direction`, with a leading space, or an empty
+    /// string if there is none.
+    fn dir_attribute(&self) -> &'static str {
+        match self.direction {
+            None => "",
+            Some(Direction::Auto) => " dir=\"auto\"",
+            Some(Direction::LeftToRight) => " dir=\"ltr\"",
+            Some(Direction::RightToLeft) => " dir=\"rtl\"",
+        }
+    }
+
+    /// Sets a flag which, once it is set, from this or any other thread, stops rendering with an
+    /// `ErrorKind::Cancelled` error, as `ParserOptions::cancel_token` does for the parser. The
+    /// flag is checked before each node is rendered, including the content of footnotes. Defaults
+    /// to none.
+    pub fn cancel_token(mut self, token: Arc<AtomicBool>) -> HtmlOptions {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// Sets whether `convert` renders the document as it is parsed, as `stream` does, rather than
+    /// parsing the whole document first, overriding its own choice. Streaming with `true` has
+    /// the limitations described at `stream`; in particular, citations are not resolved. Other
+    /// ways of rendering ignore it. By default, `convert` streams a document only if it uses none
+    /// of the features which streaming lacks.
+    pub fn stream(mut self, stream: bool) -> HtmlOptions {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Returns whether `convert` renders the document as it is parsed, or `None` if it chooses for
+    /// itself.
+    pub fn get_stream(&self) -> Option<bool> {
+        self.stream
+    }
+}
+
+/// An adapter which writes each non-ASCII character as a numeric character reference, for
+/// `HtmlOptions::ascii`.
+///
+/// The renderer only ever writes complete strings, so each write is converted on its own; a write
+/// which is not valid UTF-8 is passed on unchanged.
+struct AsciiWriter<'a, W: 'a> {
+    inner: &'a mut W,
+}
+
+impl<'a, W: Write> Write for AsciiWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = match str::from_utf8(buf) {
+            Ok(s) => s,
+            Err(_) => return self.inner.write(buf),
+        };
+        let mut start = 0;
+        for (i, c) in s.char_indices().filter(|&(_, c)| !c.is_ascii()) {
+            self.inner.write_all(&buf[start..i])?;
+            write!(self.inner, "&#x{:X};", c as u32)?;
+            start = i + c.len_utf8();
+        }
+        self.inner.write_all(&buf[start..])?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A placeholder in a template.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Placeholder {
+    Title,
+    Authors,
+    Date,
+    Lang,
+    Content,
+    Toc,
+    Previous,
+    Next,
+    Up,
+    Nav,
+}
+
+/// A part of a template.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TemplatePart<'a> {
+    Text(&'a str),
+    Placeholder(Placeholder),
+}
+
+/// Splits a template into text and placeholders, failing with `ErrorKind::InvalidTemplate` if it
+/// contains an unknown placeholder, or does not contain `{{content}}` exactly once.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart<'_>>, Error> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        parts.push(TemplatePart::Text(&rest[..start]));
+        let placeholder = match rest[start + 2..end].trim() {
+            "title" => Placeholder::Title,
+            "authors" => Placeholder::Authors,
+            "date" => Placeholder::Date,
+            "lang" => Placeholder::Lang,
+            "content" => Placeholder::Content,
+            "toc" => Placeholder::Toc,
+            "prev" => Placeholder::Previous,
+            "next" => Placeholder::Next,
+            "up" => Placeholder::Up,
+            "nav" => Placeholder::Nav,
+            name => {
+                let message = format!("Unknown placeholder `{{{{{}}}}}`", name);
+                return Err(Error::new(ErrorKind::InvalidTemplate, message));
+            }
+        };
+        parts.push(TemplatePart::Placeholder(placeholder));
+        rest = &rest[end + 2..];
+    }
+    parts.push(TemplatePart::Text(rest));
+    let content = TemplatePart::Placeholder(Placeholder::Content);
+    if parts.iter().filter(|&&part| part == content).count() != 1 {
+        let message = "The template must contain `{{content}}` exactly once";
+        return Err(Error::new(ErrorKind::InvalidTemplate, message));
+    }
+    Ok(parts)
+}
+
+/// The metadata of a document, given by commands at its top level.
+#[derive(Clone, Debug, Default)]
+struct Metadata {
+    title: Option<String>,
+    authors: Vec<String>,
+    date: Option<String>,
+    lang: Option<String>,
+}
+
+/// Returns whether a command gives a piece of metadata.
+fn is_metadata(command: &Command) -> bool {
+    matches!(&*command.name, "title" | "author" | "date" | "lang")
+}
+
+impl Metadata {
+    /// Collects the metadata from the top-level nodes of a document. If a command is repeated,
+    /// the first is used, except for `\author`.
+    fn new(nodes: &[Node]) -> Metadata {
+        let mut metadata = Metadata::default();
+        for node in nodes {
+            let command = match *node {
+                Node::Command(ref command) if is_metadata(command) => command,
+                _ => continue,
+            };
+            let text = command
+                .args
+                .first()
+                .map(|arg| ast::text_content(arg).trim().to_owned())
+                .unwrap_or_default();
+            match &*command.name {
+                "title" => {
+                    metadata.title.get_or_insert(text);
+                }
+                "date" => {
+                    metadata.date.get_or_insert(text);
+                }
+                "lang" => {
+                    metadata.lang.get_or_insert(text);
+                }
+                _ => metadata.authors.push(text),
+            }
+        }
+        metadata
+    }
+
+    /// Returns the text for a placeholder, or `None` if the document does not give it.
+    fn get(&self, placeholder: Placeholder) -> Option<String> {
+        match placeholder {
+            Placeholder::Title => self.title.clone(),
+            Placeholder::Date => self.date.clone(),
+            Placeholder::Authors if !self.authors.is_empty() => Some(self.authors.join(", ")),
+            _ => None,
+        }
+    }
+}
+
+/// A renderer producing HTML.
+#[derive(Clone, Debug, Default)]
+pub struct HtmlRenderer {
+    options: HtmlOptions,
+}
+
+impl HtmlRenderer {
+    /// Constructs a new `HtmlRenderer` with the given options.
+    pub fn new(options: HtmlOptions) -> HtmlRenderer {
+        HtmlRenderer { options }
+    }
+
+    /// Renders a document to the given output.
+    ///
+    /// Each heading is given an `id`, which is taken from a `\label` in or immediately after it,
+    /// or otherwise derived from its title, with a numeric suffix if it would duplicate an earlier
+    /// one. `\tableofcontents` is rendered as a nested list of links to the headings.
+    ///
+    /// Each `\index` command is rendered as an empty `<span>` whose `id` is its anchor, as
+    /// described in the `index` module, and `\printindex` as the index, whose locations link to
+    /// the anchors.
+    ///
+    /// Footnotes are numbered in order, and are written in a section at the end of the document,
+    /// each with a link back to where it is referenced.
+    ///
+    /// Each `\abbr` is written as an `<abbr>` element whose `title` is the expansion of the
+    /// abbreviation, preceded on its first use by the expansion, with the element in parentheses.
+    ///
+    /// Each citation is written as links to the cited entries, marked by their numbers in
+    /// brackets. The `\bibitem`s of a `references` environment are written as an ordered list,
+    /// whose items have `id`s of `ref-` followed by their keys.
+    ///
+    /// Unless `HtmlOptions::fragment` is set, the content is wrapped in a complete page, whose
+    /// `<title>` is taken from the `\title` command at the top level of the document, if any, or
+    /// in the template given by `HtmlOptions::template`.
+    pub fn render<W: Write>(
+        &self,
+        document: &Document,
+        out: &mut W,
+    ) -> Result<RenderReport, Error> {
+        let rendered = if self.options.ascii {
+            self.render_html(document, None, None, &mut AsciiWriter { inner: out })
+        } else {
+            self.render_html(document, None, None, out)
+        };
+        rendered.map(|(report, _)| report)
+    }
+
+    /// Renders a document as with `render`, along with a `SourceMap` from the output to the given
+    /// spans of input of each top-level node, such as those produced by `Blocks::parse_spanned`.
+    pub fn render_mapped<W: Write>(
+        &self,
+        document: &Document,
+        spans: &[Span],
+        out: &mut W,
+    ) -> Result<(RenderReport, SourceMap), Error> {
+        let count = Cell::new(0);
+        let mut out = CountingWriter {
+            inner: out,
+            count: &count,
+        };
+        let mapping = Some((spans, &count));
+        if self.options.ascii {
+            return self.render_html(document, mapping, None, &mut AsciiWriter { inner: &mut out });
+        }
+        self.render_html(document, mapping, None, &mut out)
+    }
+
+    /// Renders a document as with `render_mapped`, given the input from which it was parsed, so
+    /// that, if `HtmlOptions::source_positions` is set, the first block-level element of each
+    /// top-level node is given a `data-source-line` attribute holding the line of the input on
+    /// which the node starts. The line is the first line of its span which is not blank.
+    pub fn render_positioned<W: Write>(
+        &self,
+        document: &Document,
+        input: &str,
+        spans: &[Span],
+        out: &mut W,
+    ) -> Result<(RenderReport, SourceMap), Error> {
+        let count = Cell::new(0);
+        let mut out = CountingWriter {
+            inner: out,
+            count: &count,
+        };
+        let mapping = Some((spans, &count));
+        let input = Some(input);
+        if self.options.ascii {
+            let mut out = AsciiWriter { inner: &mut out };
+            return self.render_html(document, mapping, input, &mut out);
+        }
+        self.render_html(document, mapping, input, &mut out)
+    }
+
+    fn render_html<W: Write>(
+        &self,
+        document: &Document,
+        mapping: Option<(&[Span], &Cell<usize>)>,
+        input: Option<&str>,
+        out: &mut W,
+    ) -> Result<(RenderReport, SourceMap), Error> {
+        let headings = outline::headings(&document.nodes, &self.options.numbering);
+        let mut writer = HtmlWriter {
+            options: &self.options,
+            out,
+            index: index::groups(&document.nodes, &headings, &self.options.locale),
+            index_anchors: 0,
+            headings: headings.iter().cloned().collect(),
+            outline: headings,
+            complete_outline: true,
+            footnotes: Vec::new(),
+            listings: 0,
+            citations: Citations::new(&document.nodes),
+            abbreviations: Abbreviations::new(&document.nodes)
+                .reset_at_sections(self.options.reset_abbreviations),
+            report: RenderReport::new(),
+            metadata: Metadata::default(),
+            input: input.filter(|_| self.options.source_positions),
+            source_line: None,
+            heading_element: 1,
+            sections: Vec::new(),
+            open: Vec::new(),
+            site: None,
+        };
+        writer.write_head(Metadata::new(&document.nodes))?;
+        let map = match mapping {
+            Some((spans, count)) => render_document_mapped(document, spans, &mut writer, count)?,
+            None => {
+                render_document(document, &mut writer)?;
+                SourceMap::new()
+            }
+        };
+        writer.close_sections(0)?;
+        // The footnotes are not rendered from a top-level node of their own.
+        writer.source_line = None;
+        writer.report.set_span(None);
+        writer.write_footnotes()?;
+        writer.write_tail()?;
+        Ok((writer.report, map))
+    }
+
+    /// Renders a document to the given `fmt::Write`.
+    pub fn render_fmt<F: fmt::Write + ?Sized>(
+        &self,
+        document: &Document,
+        out: &mut F,
+    ) -> Result<RenderReport, Error> {
+        self.render(document, &mut FmtWriter::new(out))
+    }
+
+    /// Renders a document, returning the result as a `String`. Any warnings are discarded.
+    pub fn render_to_string(&self, document: &Document) -> Result<String, Error> {
+        let mut out = String::new();
+        self.render_fmt(document, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// Parses a document from `input` and renders it as HTML to the given output, one top-level block
+/// at a time, so that the whole document is never held in memory.
+///
+/// The output is the same as that of `HtmlRenderer::render` on the parsed document. Features which
+/// need to see the whole document before writing any output are not available when streaming; in
+/// particular, `\tableofcontents` and `\printindex` are omitted, a `\ref` to a later heading and
+/// every `\cite` are unresolved, an `\abbr` is only expanded by a definition earlier in the
+/// document, and the metadata of a complete page or template is only found in the `\title`,
+/// `\author`, and `\date` commands at the very start of the document.
+pub fn stream<R: BufRead, W: Write>(
+    input: R,
+    out: &mut W,
+    options: &HtmlOptions,
+) -> Result<RenderReport, Error> {
+    stream_blocks(&mut Parser::new(input).into_blocks(), out, options)
+}
+
+/// The commands which `stream` does not render as `HtmlRenderer::render` does, because they need
+/// the whole document, and those which define commands, whose expansions may include them.
+const WHOLE_DOCUMENT_COMMANDS: &[&str] = &[
+    "tableofcontents",
+    "printindex",
+    "ref",
+    "cite",
+    "abbr",
+    "bibliography",
+    "newcommand",
+    "renewcommand",
+    "newenvironment",
+    "renewenvironment",
+    "def",
+    "let",
+];
+
+/// Returns whether streaming the document parsed from `source` with `stream` gives the same
+/// output as rendering the whole of it with these options: the options do not ask for source
+/// positions, and `source` names none of the commands which streaming lacks, nor any metadata if
+/// the output has a head. A command is found by a backslash followed by its name anywhere in
+/// `source`, even in verbatim content, so some documents which could be streamed are not.
+pub(crate) fn is_streamable(source: &[u8], options: &HtmlOptions) -> bool {
+    if options.source_positions {
+        return false;
+    }
+    let head = !options.fragment || options.template.is_some();
+    !source.split(|&b| b == b'\\').skip(1).any(|rest| {
+        let length = rest.iter().take_while(|b| b.is_ascii_alphabetic()).count();
+        let name = &rest[..length];
+        WHOLE_DOCUMENT_COMMANDS.iter().any(|command| command.as_bytes() == name)
+            || (head && matches!(name, b"title" | b"author" | b"date" | b"lang"))
+    })
+}
+
+/// Renders the blocks of a document as HTML as they are parsed, as `stream` does. The warnings of
+/// the parser are left in `blocks`.
+pub(crate) fn stream_blocks<R: BufRead, W: Write>(
+    blocks: &mut Blocks<R>,
+    out: &mut W,
+    options: &HtmlOptions,
+) -> Result<RenderReport, Error> {
+    if options.ascii {
+        return stream_html(blocks, &mut AsciiWriter { inner: out }, options);
+    }
+    stream_html(blocks, out, options)
+}
+
+fn stream_html<R: BufRead, W: Write>(
+    blocks: &mut Blocks<R>,
+    out: &mut W,
+    options: &HtmlOptions,
+) -> Result<RenderReport, Error> {
+    let mut writer = HtmlWriter {
+        options,
+        out,
+        index: Vec::new(),
+        index_anchors: 0,
+        headings: VecDeque::new(),
+        outline: Vec::new(),
+        complete_outline: false,
+        footnotes: Vec::new(),
+        listings: 0,
+        citations: Citations::default(),
+        abbreviations: Abbreviations::default().reset_at_sections(options.reset_abbreviations),
+        report: RenderReport::new(),
+        metadata: Metadata::default(),
+        input: None,
+        source_line: None,
+        heading_element: 1,
+        sections: Vec::new(),
+        open: Vec::new(),
+        site: None,
+    };
+    let mut outliner = Outliner::new().numbering(options.numbering.clone());
+    let mut blocks = iter::from_fn(|| blocks.next_spanned().transpose()).peekable();
+    let mut leading = Vec::new();
+    while let Some(Ok((Node::Command(command), _))) = blocks.peek() {
+        if !is_metadata(command) {
+            break;
+        }
+        leading.extend(blocks.next().transpose()?.map(|(node, _)| node));
+    }
+    writer.write_head(Metadata::new(&leading))?;
+    while let Some(block) = blocks.next() {
+        let (node, span) = block?;
+        writer.report.set_span(Some(span));
+        let mut nodes = vec![node];
+        // A `\label` following a heading gives its identifier, so they are rendered together.
+        if let Some(Ok((Node::Command(ref label), _))) = blocks.peek() {
+            if label.name == "label" {
+                nodes.extend(blocks.next().transpose()?.map(|(node, _)| node));
+            }
+        }
+        let document = Document { nodes };
+        let headings = outliner.headings(&document.nodes);
+        writer.outline.extend(headings.iter().cloned());
+        writer.headings = headings.into();
+        render_document(&document, &mut writer)?;
+    }
+    writer.report.set_span(None);
+    writer.close_sections(0)?;
+    writer.write_footnotes()?;
+    writer.write_tail()?;
+    Ok(writer.report)
+}
+
+/// Renders a document as a site of several HTML pages, written to files in the given directory,
+/// which is created if it does not exist.
+///
+/// The document is split before each heading at its top level whose level is at most that set
+/// by `HtmlOptions::split_at`, so that by default each `\section` starts a page. Each page is
+/// written to a file named after the `id` of its heading, such as `introduction.html`, with a
+/// numeric suffix if it would duplicate another. The nodes before the first such heading, such
+/// as the title, are written to `index.html`, followed by the table of contents unless they have
+/// a `\tableofcontents` of their own.
+///
+/// Each page is complete, whether or not `HtmlOptions::fragment` is set, and is rendered as by
+/// `HtmlRenderer::render` otherwise: the headings, the index, and the bibliography are those of
+/// the whole document, and links to them name the file of the page they are on. The `<title>` of
+/// each page after the index is the title of its heading followed by that of the document.
+/// Footnotes are written at the end of the page which references them, and are numbered from 1
+/// on each page.
+///
+/// The built-in page links to the previous and next pages, and to the page of the enclosing
+/// heading, or the index, at the start and the end of its body. A template places these links
+/// with `{{nav}}`, or each of them with `{{prev}}`, `{{next}}`, and `{{up}}`.
+pub fn render_site<P: AsRef<Path>>(
+    document: &Document,
+    directory: P,
+    options: &HtmlOptions,
+) -> Result<RenderReport, Error> {
+    let directory = directory.as_ref();
+    fs::create_dir_all(directory).context(ErrorKind::Open(directory.display().to_string()))?;
+    let options = options.clone().fragment(false);
+    let mut parts = vec![Vec::new()];
+    for node in &document.nodes {
+        if let Node::Command(ref command) = *node {
+            let level = outline::heading_level(&command.name);
+            if level.is_some_and(|level| level <= options.split_level) {
+                parts.push(Vec::new());
+            }
+        }
+        if let Some(part) = parts.last_mut() {
+            part.push(node.clone());
+        }
+    }
+    let mut has_toc = false;
+    bibliography::for_each_command(&parts[0], &mut |command| {
+        has_toc |= command.name == "tableofcontents";
+    });
+    if !has_toc {
+        parts[0].push(Node::Command(Command {
+            name: "tableofcontents".into(),
+            args: Vec::new(),
+        }));
+    }
+
+    // The headings of each page are found in turn, so that their ids are unique across pages.
+    let mut outliner = Outliner::new().numbering(options.numbering.clone());
+    let headings: Vec<Vec<Heading>> = parts.iter().map(|nodes| outliner.headings(nodes)).collect();
+    let outline: Vec<Heading> = headings.iter().flatten().cloned().collect();
+    let metadata = Metadata::new(&document.nodes);
+
+    let mut files = Anchors::new();
+    files.unique("index");
+    let mut pages = vec![Page {
+        file: "index.html".into(),
+        title: (metadata.title.clone())
+            .unwrap_or_else(|| options.locale.contents().to_owned()),
+        level: 0,
+        up: None,
+    }];
+    for part in &headings[1..] {
+        let (id, title, level) = match part.first() {
+            Some(heading) => (&*heading.id, heading.title.clone(), heading.level),
+            None => ("page", String::new(), 1),
+        };
+        let up = pages.iter().rposition(|page| page.level < level);
+        pages.push(Page {
+            file: format!("{}.html", files.unique(id)),
+            title,
+            level,
+            up,
+        });
+    }
+    let mut anchors = HashMap::new();
+    let mut index_anchors = 0;
+    for (page, (nodes, headings)) in parts.iter().zip(&headings).enumerate() {
+        for heading in headings {
+            anchors.insert(heading.id.clone(), page);
+        }
+        bibliography::for_each_command(nodes, &mut |command| {
+            if command.name == "bibitem" {
+                let anchor = bibliography::anchor(&bibliography::bibitem_key(command));
+                anchors.entry(anchor).or_insert(page);
+            }
+        });
+        for _ in 0..index::count(nodes) {
+            index_anchors += 1;
+            anchors.insert(index::anchor(index_anchors), page);
+        }
+    }
+
+    let files: Vec<PathBuf> = pages.iter().map(|page| directory.join(&page.file)).collect();
+    let mut buffer = Vec::new();
+    let mut writer = HtmlWriter {
+        options: &options,
+        out: &mut buffer,
+        index: index::groups(&document.nodes, &outline, &options.locale),
+        index_anchors: 0,
+        headings: VecDeque::new(),
+        outline,
+        complete_outline: true,
+        footnotes: Vec::new(),
+        listings: 0,
+        citations: Citations::new(&document.nodes),
+        abbreviations: Abbreviations::new(&document.nodes)
+            .reset_at_sections(options.reset_abbreviations),
+        report: RenderReport::new(),
+        metadata: Metadata::default(),
+        input: None,
+        source_line: None,
+        heading_element: 1,
+        sections: Vec::new(),
+        open: Vec::new(),
+        site: Some(Site {
+            pages,
+            anchors,
+            current: 0,
+        }),
+    };
+    for (page, (nodes, headings)) in parts.into_iter().zip(headings).enumerate() {
+        if let Some(ref mut site) = writer.site {
+            site.current = page;
+        }
+        writer.headings = headings.into();
+        writer.footnotes.clear();
+        writer.heading_element = 1;
+        writer.write_head(metadata.clone())?;
+        render_document(&Document { nodes }, &mut writer)?;
+        writer.close_sections(0)?;
+        writer.write_footnotes()?;
+        writer.write_tail()?;
+        write_page(&files[page], &mem::take(writer.out), options.ascii)?;
+    }
+    Ok(writer.report)
+}
+
+/// Writes a page of a site to a file, escaping any characters outside ASCII if `ascii` is set.
+fn write_page(path: &Path, html: &[u8], ascii: bool) -> Result<(), Error> {
+    let file = File::create(path).context(ErrorKind::Open(path.display().to_string()))?;
+    let mut out = BufWriter::new(file);
+    let written = if ascii {
+        AsciiWriter { inner: &mut out }.write_all(html)
+    } else {
+        out.write_all(html)
+    };
+    written.and_then(|()| out.flush()).context(ErrorKind::Write)
+}
+
+/// The pages of a site written by `render_site`.
+#[derive(Clone, Debug)]
+struct Site {
+    pages: Vec<Page>,
+    /// The position among the pages of the page of each heading, `\bibitem`, and `\index`
+    /// command, by its anchor.
+    anchors: HashMap<String, usize>,
+    /// The position among the pages of the page being written.
+    current: usize,
+}
+
+/// A page of a site.
+#[derive(Clone, Debug)]
+struct Page {
+    /// The name of the file of the page.
+    file: String,
+    /// The title of the heading which starts the page, or for the index page that of the
+    /// document, or the name of the table of contents if it has none.
+    title: String,
+    /// The level of the heading which starts the page, or 0 for the index page.
+    level: usize,
+    /// The position among the pages of the page of the enclosing heading, or of the index page,
+    /// which is `None` for the index page itself.
+    up: Option<usize>,
+}
+
+/// Returns the `href` of a link to an anchor, which names the file of the page it is on if that
+/// is not the page of the site being written.
+fn href(site: Option<&Site>, anchor: &str) -> String {
+    let page = site.and_then(|site| Some((site, *site.anchors.get(anchor)?)));
+    match page {
+        Some((site, page)) if page != site.current => {
+            format!("{}#{}", site.pages[page].file, anchor)
+        }
+        _ => format!("#{}", anchor),
+    }
+}
+
+/// A paragraph or block-level element which is open while rendering a document.
+///
+/// A `<p>` element cannot contain block-level elements, so one nested in a paragraph, such as a
+/// `\verbatim` in a group, closes the `<p>` before it, which is reopened by the content after it.
+#[derive(Clone, Debug)]
+enum Open {
+    /// A paragraph, with whether its `<p>` element is open, and the start tags and names of the
+    /// inline elements open in it, which are closed and reopened with the `<p>`.
+    Paragraph(bool, Vec<(String, String)>),
+    /// A block-level element, in which paragraphs may be nested.
+    Block,
+}
+
+/// The `Renderer` implementation backing `HtmlRenderer`.
+struct HtmlWriter<'a, W: 'a> {
+    options: &'a HtmlOptions,
+    out: &'a mut W,
+    /// The headings which have not yet been rendered.
+    headings: VecDeque<Heading>,
+    /// The headings of the document which are known, which are all of them unless streaming.
+    outline: Vec<Heading>,
+    /// Whether all of the headings of the document are known.
+    complete_outline: bool,
+    /// The index of the document, which is empty when streaming.
+    index: Vec<Group>,
+    /// The number of `\index` commands written so far.
+    index_anchors: usize,
+    /// The content of the footnotes, in order of their numbers.
+    footnotes: Vec<Vec<Node>>,
+    /// The number of verbatim blocks written with numbered lines so far.
+    listings: usize,
+    /// The numbers of the entries of the bibliography, which are unknown when streaming.
+    citations: Citations,
+    /// The abbreviations of the document, which are defined as they are found when streaming,
+    /// and which have been used.
+    abbreviations: Abbreviations,
+    /// The warnings encountered so far.
+    report: RenderReport,
+    /// The metadata of the document, for use in the page.
+    metadata: Metadata,
+    /// The input from which the document was parsed, if the lines of its nodes are written as
+    /// `data-source-line` attributes.
+    input: Option<&'a str>,
+    /// The line of the current top-level node, until it is written on the first block-level
+    /// element of the node.
+    source_line: Option<usize>,
+    /// The level of the last heading element written, or 1, for the title of the page, before
+    /// the first.
+    heading_element: usize,
+    /// The levels of the headings whose `<section>` elements are open, innermost last.
+    sections: Vec<usize>,
+    /// The paragraphs, and the block-level elements nested in them, which are open, innermost
+    /// last.
+    open: Vec<Open>,
+    /// The pages of the site being written by `render_site`, if any.
+    site: Option<Site>,
+}
+
+impl<'a, W: Write> HtmlWriter<'a, W> {
+    /// Returns the `href` of a link to an anchor, as the free function `href` does.
+    fn href(&self, anchor: &str) -> String {
+        href(self.site.as_ref(), anchor)
+    }
+
+    /// Closes the `<p>` element of the innermost paragraph, if it is open, before a block-level
+    /// element, along with the inline elements open in it.
+    fn suspend_paragraph(&mut self) -> Result<(), Error> {
+        if let Some(&mut Open::Paragraph(ref mut open @ true, ref inline)) = self.open.last_mut() {
+            for (_, name) in inline.iter().rev() {
+                write!(self.out, "</{}>", name).context(ErrorKind::Write)?;
+            }
+            write_str(self.out, "</p>\n")?;
+            *open = false;
+        }
+        Ok(())
+    }
+
+    /// Reopens the `<p>` element of the innermost paragraph, and the inline elements open in it,
+    /// if it was closed before a block-level element, before the inline content after it.
+    fn resume_paragraph(&mut self) -> Result<(), Error> {
+        if let Some(&mut Open::Paragraph(ref mut open @ false, ref inline)) = self.open.last_mut() {
+            write_str(self.out, "<p")?;
+            write_str(self.out, self.options.dir_attribute())?;
+            write_str(self.out, ">")?;
+            for (start, _) in inline {
+                write_str(self.out, start)?;
+            }
+            *open = true;
+        }
+        Ok(())
+    }
+
+    /// Begins a block-level element which may contain paragraphs, closing the `<p>` element of
+    /// the paragraph it is nested in, if any.
+    fn begin_nested_block(&mut self) -> Result<(), Error> {
+        self.suspend_paragraph()?;
+        self.open.push(Open::Block);
+        Ok(())
+    }
+
+    /// Ends a block-level element begun by `begin_nested_block`.
+    fn end_nested_block(&mut self) {
+        self.open.pop();
+    }
+
+    /// Writes the start tag of an inline element, which is closed and reopened with the `<p>`
+    /// element of the paragraph it is in.
+    fn begin_inline(&mut self, start: String, name: &str) -> Result<(), Error> {
+        self.resume_paragraph()?;
+        write_str(self.out, &start)?;
+        if let Some(&mut Open::Paragraph(_, ref mut inline)) = self.open.last_mut() {
+            inline.push((start, name.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Writes the end tag of an inline element begun by `begin_inline`, unless the `<p>` element
+    /// it was in has been closed, which closed it as well.
+    fn end_inline(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(&mut Open::Paragraph(open, ref mut inline)) = self.open.last_mut() {
+            inline.pop();
+            if !open {
+                return Ok(());
+            }
+        }
+        write!(self.out, "</{}>", name).context(ErrorKind::Write)
+    }
+
+    /// Writes the `data-source-line` attribute of the current top-level node, with a leading
+    /// space, if it has not yet been written.
+    fn write_source_line(&mut self) -> Result<(), Error> {
+        match self.source_line.take() {
+            Some(line) => {
+                write!(self.out, " data-source-line=\"{}\"", line).context(ErrorKind::Write)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the element for a command, or `None` if the command is to be omitted.
+    fn tag(&self, command: &Command) -> Result<Option<Tag>, Error> {
+        if let "label" | "tableofcontents" | "footnote" | "ref" | "appendix" | "index"
+        | "printindex" = &*command.name
+        {
+            return Ok(None);
+        }
+        if let "image" | "link" | "break" | "cite" | "bibliography" | "abbr" | "defabbr" =
+            &*command.name
+        {
+            return Ok(None);
+        }
+        // Metadata is written in the head of a complete page, if anywhere.
+        if is_metadata(command) || is_horizontal_rule(&command.name) {
+            return Ok(None);
+        }
+        if let Some(tag) = self.options.command_tag(&command.name) {
+            return Ok(Some(tag));
+        }
+        match self.options.unknown_commands {
+            UnknownCommandPolicy::Skip => Ok(None),
+            UnknownCommandPolicy::Span => Ok(Some(self.options.unknown_tag.clone())),
+            UnknownCommandPolicy::Error => {
+                Err(ErrorKind::UnknownCommand(command.name.clone()).into())
+            }
+        }
+    }
+
+    /// Writes an `\image` command as an `<img>` element, checking that the image exists if
+    /// `HtmlOptions::image_directory` is set. An image with a caption is written in a `<figure>`
+    /// with a `<figcaption>`, in place of the paragraph if it is the whole of one.
+    fn image(&mut self, command: &Command) -> Result<(), Error> {
+        let image = Image::new(command, &mut self.report);
+        if let Some(ref directory) = self.options.image_directory {
+            if !image.is_url() && !directory.join(&image.path).exists() {
+                self.report.warn(Warning::MissingImage(image.path.clone()));
+            }
+        }
+        if image.caption.is_some() {
+            write_str(self.out, "<figure")?;
+            self.write_source_line()?;
+            write_str(self.out, self.options.dir_attribute())?;
+            write_str(self.out, ">")?;
+        }
+        write_str(self.out, "<img src=\"")?;
+        write_escaped_attribute(self.out, &image.path)?;
+        write_str(self.out, "\"")?;
+        let attributes = [("alt", &image.alt), ("width", &image.width), ("height", &image.height)];
+        for &(name, value) in &attributes {
+            if let Some(ref value) = *value {
+                write!(self.out, " {}=\"", name).context(ErrorKind::Write)?;
+                write_escaped_attribute(self.out, value)?;
+                write_str(self.out, "\"")?;
+            }
+        }
+        write_str(self.out, ">")?;
+        if let Some(ref caption) = image.caption {
+            write_str(self.out, "<figcaption>")?;
+            write_escaped(self.out, caption)?;
+            write_str(self.out, "</figcaption></figure>\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes a `\ref` command as a link to the heading with its label, showing the number or
+    /// title of the heading depending on the options, or its title if it is unnumbered, or as
+    /// `[??]` if there is no such heading.
+    fn reference(&mut self, command: &Command) -> Result<(), Error> {
+        let label = outline::reference_label(command);
+        let heading = match outline::find_heading(&self.outline, &label) {
+            Some(heading) => heading,
+            None => {
+                self.report.warn(Warning::UnresolvedReference(label));
+                return write_str(self.out, outline::UNRESOLVED_REFERENCE);
+            }
+        };
+        let href = self.href(&heading.id);
+        write_str(self.out, "<a class=\"ref\" href=\"")?;
+        write_escaped_attribute(self.out, &href)?;
+        write_str(self.out, "\">")?;
+        if self.options.reference_titles || heading.formatted_number.is_none() {
+            write_escaped(self.out, &heading.title)?;
+        } else {
+            write_escaped(self.out, &heading.number_text())?;
+        }
+        write_str(self.out, "</a>")
+    }
+
+    /// Writes a `\cite` command as links to the cited entries, marked by their numbers, or `?` for
+    /// those which are not in the bibliography, in brackets.
+    fn citation(&mut self, command: &Command) -> Result<(), Error> {
+        write_str(self.out, "[")?;
+        let cited = self.citations.cited(command, &mut self.report);
+        for (i, (key, number)) in cited.iter().enumerate() {
+            if i > 0 {
+                write_str(self.out, ", ")?;
+            }
+            match *number {
+                Some(number) => {
+                    let href = self.href(&bibliography::anchor(key));
+                    write_str(self.out, "<a class=\"cite\" href=\"")?;
+                    write_escaped_attribute(self.out, &href)?;
+                    write!(self.out, "\">{}</a>", number).context(ErrorKind::Write)?;
+                }
+                None => write_str(self.out, bibliography::UNKNOWN_CITATION)?,
+            }
+        }
+        write_str(self.out, "]")
+    }
+
+    /// Writes an `\abbr` command as an `<abbr>` element whose `title` is the expansion of the
+    /// abbreviation, preceded by the expansion on its first use, or as the abbreviation alone if
+    /// it has no definition.
+    fn abbreviation(&mut self, command: &Command) -> Result<(), Error> {
+        let abbreviation = self
+            .abbreviations
+            .use_abbreviation(command, &mut self.report);
+        let expansion = match abbreviation.expansion {
+            Some(ref expansion) => expansion,
+            None => return write_escaped(self.out, &abbreviation.key),
+        };
+        if abbreviation.first {
+            write_escaped(self.out, expansion)?;
+            write_str(self.out, " (")?;
+        }
+        write_str(self.out, "<abbr title=\"")?;
+        write_escaped_attribute(self.out, expansion)?;
+        write_str(self.out, "\">")?;
+        write_escaped(self.out, &abbreviation.key)?;
+        write_str(self.out, "</abbr>")?;
+        if abbreviation.first {
+            write_str(self.out, ")")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the start tag of the `<code>` element of a verbatim block, whose class names its
+    /// language, if it has one.
+    fn code_start(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        match verbatim.language {
+            Some(ref language) => {
+                write_str(self.out, "<code class=\"language-")?;
+                write_escaped_attribute(self.out, language)?;
+                write_str(self.out, "\">")
+            }
+            None => write_str(self.out, "<code>"),
+        }
+    }
+
+    /// Returns the content of a verbatim block as HTML, highlighted by the highlighter if there
+    /// is one which can highlight it, and otherwise escaped.
+    fn verbatim_html(&self, verbatim: &Verbatim) -> String {
+        let language = verbatim.language.as_deref();
+        self.options
+            .highlighter
+            .as_ref()
+            .and_then(|highlighter| (highlighter.0)(&verbatim.content, language))
+            .unwrap_or_else(|| escape(&verbatim.content, false))
+    }
+
+    /// Writes a verbatim block with its lines numbered, as described at `LineNumbers`. A line
+    /// ending at the end of the content does not start another line.
+    fn numbered_verbatim(
+        &mut self,
+        verbatim: &Verbatim,
+        line_numbers: LineNumbers,
+    ) -> Result<(), Error> {
+        self.listings += 1;
+        let anchor = format!("listing-{}", self.listings);
+        let mut lines = split_lines(&self.verbatim_html(verbatim));
+        lines.truncate(verbatim.content.split_terminator('\n').count());
+        let first = self.options.first_line_number;
+        let gutter = line_numbers == LineNumbers::Gutter;
+        if gutter {
+            write!(self.out, "<pre id=\"{}\"", anchor).context(ErrorKind::Write)?;
+            self.write_source_line()?;
+            write_str(self.out, ">")?;
+        } else {
+            write!(self.out, "<table class=\"listing\" id=\"{}\"", anchor)
+                .context(ErrorKind::Write)?;
+            self.write_source_line()?;
+            write_str(self.out, "><tr><td class=\"line-numbers\"><pre>")?;
+            for (i, number) in (first..).take(lines.len()).enumerate() {
+                let separator = if i > 0 { "\n" } else { "" };
+                write!(self.out, "{}<a href=\"#{}.L{2}\">{2}</a>", separator, anchor, number)
+                    .context(ErrorKind::Write)?;
+            }
+            write_str(self.out, "</pre></td><td class=\"code\"><pre>")?;
+        }
+        self.code_start(verbatim)?;
+        for (i, (line, number)) in lines.iter().zip(first..).enumerate() {
+            if i > 0 {
+                write_str(self.out, "\n")?;
+            }
+            let id = format!("{}.L{}", anchor, number);
+            write!(self.out, "<span class=\"line\" id=\"{}\">", id).context(ErrorKind::Write)?;
+            if gutter {
+                write!(
+                    self.out,
+                    "<a class=\"line-number\" href=\"#{}\" data-line=\"{}\"></a>",
+                    id, number
+                )
+                .context(ErrorKind::Write)?;
+            }
+            write_str(self.out, line)?;
+            write_str(self.out, "</span>")?;
+        }
+        if verbatim.content.ends_with('\n') {
+            write_str(self.out, "\n")?;
+        }
+        write_str(self.out, "</code></pre>")?;
+        if !gutter {
+            write_str(self.out, "</td></tr></table>")?;
+        }
+        write_str(self.out, "\n")
+    }
+
+    /// Writes the footnotes at the end of the document, as an ordered list of which each item links
+    /// back to where the footnote is referenced. If there are no footnotes, nothing is written.
+    fn write_footnotes(&mut self) -> Result<(), Error> {
+        if self.footnotes.is_empty() {
+            return Ok(());
+        }
+        write_str(self.out, "<section class=\"footnotes\">\n<ol>\n")?;
+        let mut index = 0;
+        // Footnotes may contain footnotes of their own, which are added to the end of the list.
+        while let Some(content) = self.footnotes.get(index).cloned() {
+            index += 1;
+            write!(self.out, "<li id=\"fn-{}\">", index).context(ErrorKind::Write)?;
+            render_document(&Document { nodes: content }, self)?;
+            writeln!(
+                self.out,
+                " <a href=\"#fnref-{}\" class=\"footnote-backref\">\u{21a9}</a></li>",
+                index
+            )
+            .context(ErrorKind::Write)?;
+        }
+        write_str(self.out, "</ol>\n</section>\n")
+    }
+
+    /// Writes the start of a page with the given metadata, up to the content: the part of the
+    /// template before `{{content}}`, if there is a template, or otherwise the start of the
+    /// built-in page up to the opening `<body>` tag, unless only a fragment is to be written.
+    fn write_head(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = metadata;
+        if let Some(ref template) = self.options.template {
+            let parts = parse_template(template)?;
+            let content = TemplatePart::Placeholder(Placeholder::Content);
+            let end = parts.iter().position(|&part| part == content).unwrap_or(0);
+            return self.write_template(&parts[..end]);
+        }
+        if self.options.fragment {
+            return Ok(());
+        }
+        write_str(self.out, "<!doctype html>\n<html")?;
+        match self.language() {
+            Some(lang) => {
+                write_str(self.out, " lang=\"")?;
+                write_escaped_attribute(self.out, &lang)?;
+                write_str(self.out, "\"")?;
+            }
+            None => self.missing_language(),
+        }
+        write_str(self.out, self.options.dir_attribute())?;
+        write_str(self.out, ">\n<head>\n<meta charset=\"utf-8\">\n<title>")?;
+        let mut title = self.metadata.title.clone().unwrap_or_default();
+        if let Some(ref site) = self.site {
+            if site.current > 0 {
+                let page = &site.pages[site.current].title;
+                title = if title.is_empty() {
+                    page.clone()
+                } else {
+                    format!("{} - {}", page, title)
+                };
+            }
+        }
+        write_escaped(self.out, &title)?;
+        write_str(self.out, "</title>\n")?;
+        for href in &self.options.stylesheets {
+            write_str(self.out, "<link rel=\"stylesheet\" href=\"")?;
+            write_escaped_attribute(self.out, href)?;
+            write_str(self.out, "\">\n")?;
+        }
+        if let Some(ref style) = self.options.style {
+            writeln!(self.out, "<style>\n{}\n</style>", style.trim_end())
+                .context(ErrorKind::Write)?;
+        }
+        write_str(self.out, "</head>\n<body>\n")?;
+        self.write_nav()
+    }
+
+    /// Writes the end of a page, after the content, as with `write_head`.
+    fn write_tail(&mut self) -> Result<(), Error> {
+        if let Some(ref template) = self.options.template {
+            let parts = parse_template(template)?;
+            let content = TemplatePart::Placeholder(Placeholder::Content);
+            let start = parts.iter().position(|&part| part == content).map_or(0, |i| i + 1);
+            return self.write_template(&parts[start..]);
+        }
+        if self.options.fragment {
+            return Ok(());
+        }
+        self.write_nav()?;
+        write_str(self.out, "</body>\n</html>\n")
+    }
+
+    /// Writes the links to the previous, enclosing, and next pages of a site as a `<nav>`
+    /// element, or nothing if no site is being written.
+    fn write_nav(&mut self) -> Result<(), Error> {
+        if self.site.is_none() {
+            return Ok(());
+        }
+        write_str(self.out, "<nav class=\"pages\">\n")?;
+        for &placeholder in &[Placeholder::Previous, Placeholder::Up, Placeholder::Next] {
+            if self.write_page_link(placeholder)? {
+                write_str(self.out, "\n")?;
+            }
+        }
+        write_str(self.out, "</nav>\n")
+    }
+
+    /// Writes a link to the page of a site for `{{prev}}`, `{{next}}`, or `{{up}}`, whose text is
+    /// the title of the page. Returns whether there is such a page; there is none before the first
+    /// page, after the last, or above the index page, nor if no site is being written.
+    fn write_page_link(&mut self, placeholder: Placeholder) -> Result<bool, Error> {
+        let site = match self.site {
+            Some(ref site) => site,
+            None => return Ok(false),
+        };
+        let (class, page) = match placeholder {
+            Placeholder::Previous => ("prev", site.current.checked_sub(1)),
+            Placeholder::Next => ("next", Some(site.current + 1)),
+            _ => ("up", site.pages[site.current].up),
+        };
+        let page = match page.and_then(|page| site.pages.get(page)) {
+            Some(page) => page,
+            None => return Ok(false),
+        };
+        write!(self.out, "<a class=\"{}\" href=\"", class).context(ErrorKind::Write)?;
+        write_escaped_attribute(self.out, &page.file)?;
+        write_str(self.out, "\">")?;
+        write_escaped(self.out, &page.title)?;
+        write_str(self.out, "</a>")?;
+        Ok(true)
+    }
+
+    /// Returns the language of the page, given by `\lang`, or else by the options.
+    fn language(&self) -> Option<String> {
+        let lang = self.metadata.lang.as_ref().or(self.options.lang.as_ref());
+        lang.cloned()
+    }
+
+    /// Warns that the page has no language. The pages of a site share it, so it is reported only
+    /// on the first.
+    fn missing_language(&mut self) {
+        if self.site.as_ref().is_none_or(|site| site.current == 0) {
+            self.report.warn(Warning::MissingLanguage);
+        }
+    }
+
+    /// Warns if a heading element of the given level is more than one level deeper than the
+    /// heading before it, as an `<h4>` following an `<h2>` is, so that assistive technology
+    /// navigating by headings finds a gap.
+    fn check_heading_level(&mut self, command: &Command, level: usize) {
+        if level > self.heading_element + 1 {
+            let title = command.args.first().map(|arg| ast::text_content(arg));
+            let title = title.unwrap_or_default().trim().to_owned();
+            let warning = Warning::SkippedHeadingLevel(title, level, self.heading_element);
+            self.report.warn(warning);
+        }
+        self.heading_element = level;
+    }
+
+    /// Closes the `<section>` elements of the headings of the given level or deeper, so that all
+    /// of them are closed for level 0.
+    fn close_sections(&mut self, level: usize) -> Result<(), Error> {
+        while self.sections.last().is_some_and(|&open| open >= level) {
+            self.sections.pop();
+            write_str(self.out, "</section>\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes part of a template, with its placeholders other than `{{content}}` replaced.
+    fn write_template(&mut self, parts: &[TemplatePart]) -> Result<(), Error> {
+        for &part in parts {
+            match part {
+                TemplatePart::Text(text) => write_str(self.out, text)?,
+                TemplatePart::Placeholder(Placeholder::Toc) => self.table_of_contents()?,
+                TemplatePart::Placeholder(Placeholder::Content) => {}
+                TemplatePart::Placeholder(Placeholder::Nav) => self.write_nav()?,
+                TemplatePart::Placeholder(Placeholder::Lang) => match self.language() {
+                    Some(lang) => write_escaped(self.out, &lang)?,
+                    None => self.missing_language(),
+                },
+                TemplatePart::Placeholder(
+                    placeholder @ (Placeholder::Previous | Placeholder::Next | Placeholder::Up),
+                ) => {
+                    self.write_page_link(placeholder)?;
+                }
+                TemplatePart::Placeholder(placeholder) => match self.metadata.get(placeholder) {
+                    Some(text) => write_escaped(self.out, &text)?,
+                    // The pages of a site share the metadata of the document, so its absence is
+                    // reported only on the first.
+                    None if self.site.as_ref().is_some_and(|site| site.current > 0) => {}
+                    None => {
+                        let command = match placeholder {
+                            Placeholder::Title => "title",
+                            Placeholder::Authors => "author",
+                            _ => "date",
+                        };
+                        self.report.warn(Warning::MissingMetadata(command.into()));
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the table of contents, as nested lists of links to the headings up to the depth
+    /// given in the options, if the headings are known.
+    fn table_of_contents(&mut self) -> Result<(), Error> {
+        if !self.complete_outline {
+            return Ok(());
+        }
+        let outline = &self.outline;
+        let depth = self.options.toc_depth;
+        write_str(self.out, "<nav class=\"toc\" aria-label=\"")?;
+        write_escaped_attribute(self.out, self.options.locale.contents())?;
+        write_str(self.out, "\">\n")?;
+        // The levels of the lists which are open, innermost last.
+        let mut open: Vec<usize> = Vec::new();
+        for heading in outline.iter().filter(|heading| heading.level <= depth) {
+            while open.last().is_some_and(|&level| level > heading.level) {
+                open.pop();
+                write_str(self.out, "</li>\n</ul>\n")?;
+            }
+            match open.last() {
+                Some(&level) if level == heading.level => write_str(self.out, "</li>\n")?,
+                Some(_) => {
+                    write_str(self.out, "\n<ul>\n")?;
+                    open.push(heading.level);
+                }
+                None => {
+                    write_str(self.out, "<ul>\n")?;
+                    open.push(heading.level);
+                }
+            }
+            let href = self.href(&heading.id);
+            write_str(self.out, "<li><a href=\"")?;
+            write_escaped_attribute(self.out, &href)?;
+            write_str(self.out, "\">")?;
+            if let Some(number) = heading_number(self.options, heading) {
+                write_heading_number(self.out, number)?;
+            }
+            write_escaped(self.out, &heading.title)?;
+            write_str(self.out, "</a>")?;
+        }
+        for _ in open {
+            write_str(self.out, "</li>\n</ul>\n")?;
+        }
+        write_str(self.out, "</nav>\n")
+    }
+
+    /// Writes the index, as a list of the entries under a heading for each letter, if it has any
+    /// entries.
+    fn print_index(&mut self) -> Result<(), Error> {
+        if self.index.is_empty() {
+            return Ok(());
+        }
+        write_str(self.out, "<div class=\"index\">\n")?;
+        for group in &self.index {
+            write_str(self.out, "<h3 class=\"index-letter\">")?;
+            write_escaped(self.out, &group.letter)?;
+            write_str(self.out, "</h3>\n")?;
+            write_index_entries(self.out, &group.entries, self.site.as_ref())?;
+        }
+        write_str(self.out, "</div>\n")
+    }
+}
+
+/// Writes entries of the index as a list, each with links to its locations, and with its
+/// sub-entries in a nested list.
+fn write_index_entries<W: Write>(
+    out: &mut W,
+    entries: &[Entry],
+    site: Option<&Site>,
+) -> Result<(), Error> {
+    write_str(out, "<ul>\n")?;
+    for entry in entries {
+        write_str(out, "<li>")?;
+        write_escaped(out, &entry.term)?;
+        for location in &entry.locations {
+            write_str(out, ", <a href=\"")?;
+            write_escaped_attribute(out, &href(site, &location.anchor))?;
+            write_str(out, "\">")?;
+            write_escaped(out, &location.text)?;
+            write_str(out, "</a>")?;
+        }
+        if !entry.subentries.is_empty() {
+            write_str(out, "\n")?;
+            write_index_entries(out, &entry.subentries, site)?;
+        }
+        write_str(out, "</li>\n")?;
+    }
+    write_str(out, "</ul>\n")
+}
+
+impl<'a, W: Write> Renderer for HtmlWriter<'a, W> {
+    fn is_cancelled(&self) -> bool {
+        is_set(self.options.cancel_token.as_ref())
+    }
+
+    fn begin_block(&mut self, span: Span) -> Result<(), Error> {
+        self.source_line = self.input.map(|input| source_line(input, span));
+        self.report.set_span(Some(span));
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        // The space between a block-level element and the content after it is not significant.
+        let text = match self.open.last() {
+            Some(&Open::Paragraph(false, _)) => text.trim_start(),
+            _ => text,
+        };
+        if !text.is_empty() {
+            self.resume_paragraph()?;
+        }
+        write_escaped(self.out, text)
+    }
+
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        self.suspend_paragraph()?;
+        if let Some(line_numbers) = self.options.line_numbers {
+            return self.numbered_verbatim(verbatim, line_numbers);
+        }
+        write_str(self.out, "<pre")?;
+        self.write_source_line()?;
+        write_str(self.out, ">")?;
+        self.code_start(verbatim)?;
+        let html = self.verbatim_html(verbatim);
+        write_str(self.out, &html)?;
+        write_str(self.out, "</code></pre>\n")
+    }
+
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        self.resume_paragraph()?;
+        match self.options.raw {
+            RawPolicy::Allow => write_str(self.out, content),
+            RawPolicy::Escape => write_escaped(self.out, content),
+            RawPolicy::Sanitize(ref allowlist) => {
+                write_str(self.out, &sanitize(content, allowlist))
+            }
+        }
+    }
+
+    fn begin_paragraph(&mut self) -> Result<(), Error> {
+        write_str(self.out, "<p")?;
+        self.write_source_line()?;
+        write_str(self.out, self.options.dir_attribute())?;
+        write_str(self.out, ">")?;
+        self.open.push(Open::Paragraph(true, Vec::new()));
+        Ok(())
+    }
+
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        match self.open.pop() {
+            Some(Open::Paragraph(false, _)) => Ok(()),
+            _ => write_str(self.out, "</p>\n"),
+        }
+    }
+
+    fn wraps_paragraph(&self, nodes: &[Node]) -> bool {
+        let escaped = matches!(self.options.raw, RawPolicy::Escape);
+        !is_figure(nodes) && (escaped || !is_raw_block(nodes))
+    }
+
+    fn end_unwrapped_paragraph(&mut self, nodes: &[Node]) -> Result<(), Error> {
+        if is_raw_block(nodes) {
+            write_str(self.out, "\n")?;
+        }
+        Ok(())
+    }
+
+    fn begin_group(&mut self) -> Result<(), Error> {
+        if self.options.group_spans {
+            self.begin_inline("<span>".to_owned(), "span")?;
+        }
+        Ok(())
+    }
+
+    fn end_group(&mut self) -> Result<(), Error> {
+        if self.options.group_spans {
+            self.end_inline("span")?;
+        }
+        Ok(())
+    }
+
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        if let "tableofcontents" | "printindex" = &*command.name {
+            self.suspend_paragraph()?;
+        }
+        if let "ref" | "cite" | "abbr" | "index" | "image" | "break" | "footnote" = &*command.name {
+            self.resume_paragraph()?;
+        }
+        if command.name == "tableofcontents" {
+            self.table_of_contents()?;
+        }
+        if command.name == "ref" {
+            self.reference(command)?;
+        }
+        if command.name == "cite" {
+            self.citation(command)?;
+        }
+        if command.name == "abbr" {
+            self.abbreviation(command)?;
+        }
+        if command.name == "defabbr" {
+            self.abbreviations.define(command);
+        }
+        if command.name == "index" {
+            self.index_anchors += 1;
+            write_str(self.out, "<span class=\"index\" id=\"")?;
+            write_escaped_attribute(self.out, &index::anchor(self.index_anchors))?;
+            write_str(self.out, "\"></span>")?;
+        }
+        if command.name == "printindex" {
+            self.print_index()?;
+        }
+        if command.name == "image" {
+            self.image(command)?;
+        }
+        if command.name == "break" {
+            write_str(self.out, "<br>")?;
+        }
+        if command.name == "link" {
+            let start = format!("<a href=\"{}\">", escape(&link_url(command), true));
+            self.begin_inline(start, "a")?;
+            if !has_link_text(command) {
+                write_escaped(self.out, &link_address(command))?;
+            }
+            return Ok(Visit::Children);
+        }
+        if is_horizontal_rule(&command.name) {
+            self.suspend_paragraph()?;
+            write_str(self.out, "<hr")?;
+            self.write_source_line()?;
+            if let Some(width) = RuleStyle::new(command).width {
+                write!(self.out, " style=\"width: {}%\"", width * 100.0).context(ErrorKind::Write)?;
+            }
+            write_str(self.out, ">\n")?;
+        }
+        if command.name == "footnote" {
+            self.footnotes
+                .push(command.args.first().cloned().unwrap_or_default());
+            let number = self.footnotes.len();
+            write!(
+                self.out,
+                "<sup class=\"footnote-ref\"><a href=\"#fn-{0}\" id=\"fnref-{0}\">{0}</a></sup>",
+                number
+            )
+            .context(ErrorKind::Write)?;
+        }
+        // The term and content of a description item are written by `begin_argument`.
+        if item_term(command).is_some() {
+            return Ok(Visit::Children);
+        }
+        if command.name == "bibitem" {
+            self.begin_nested_block()?;
+            write_str(self.out, "<li id=\"")?;
+            let key = bibliography::bibitem_key(command);
+            write_escaped_attribute(self.out, &bibliography::anchor(&key))?;
+            write_str(self.out, "\"")?;
+            self.write_source_line()?;
+            write_str(self.out, self.options.dir_attribute())?;
+            write_str(self.out, ">")?;
+            return Ok(Visit::Children);
+        }
+        let tag = match self.tag(command)? {
+            Some(tag) => tag,
+            None => return Ok(Visit::SkipChildren),
+        };
+        if self.options.command_tag(&command.name).is_none() {
+            self.report.warn(Warning::UnknownCommand(command.name.clone()));
+        }
+        if tag.block {
+            self.begin_nested_block()?;
+        }
+        let level = outline::heading_level(&command.name);
+        if let Some(level) = level.filter(|_| self.options.sections) {
+            self.close_sections(level)?;
+            write_str(self.out, "<section")?;
+            if let Some(heading) = self.headings.front() {
+                write_str(self.out, " aria-labelledby=\"")?;
+                write_escaped_attribute(self.out, &heading.id)?;
+                write_str(self.out, "\"")?;
+            }
+            write_str(self.out, ">\n")?;
+            self.sections.push(level);
+        }
+        if let Some(level) = heading_element(&tag.name) {
+            self.check_heading_level(command, level);
+        }
+        // The start tag of an inline element is kept, to reopen it in a paragraph after a
+        // block-level element nested in it.
+        let mut start = format!("<{}", tag.name);
+        let classes: Vec<_> = tag
+            .classes
+            .iter()
+            .filter_map(|class| expand_template(class, command))
+            .collect();
+        if !classes.is_empty() {
+            start += &format!(" class=\"{}\"", escape(&classes.join(" "), true));
+        }
+        let mut number = None;
+        if outline::heading_level(&command.name).is_some() {
+            self.abbreviations.heading(command);
+            if let Some(heading) = self.headings.pop_front() {
+                start += &format!(" id=\"{}\"", escape(&heading.id, true));
+                number = heading_number(self.options, &heading).map(str::to_owned);
+            }
+        }
+        for (name, value) in &tag.attributes {
+            let value = match expand_template(value, command) {
+                Some(value) => value,
+                None => continue,
+            };
+            start += &format!(" {}=\"{}\"", name, escape(&value, true));
+        }
+        if tag.block {
+            write_str(self.out, &start)?;
+            self.write_source_line()?;
+            write_str(self.out, self.options.dir_attribute())?;
+            write_str(self.out, ">")?;
+        } else {
+            start.push('>');
+            self.begin_inline(start, &tag.name)?;
+        }
+        match tag.replacement {
+            Some(ref html) => {
+                write_str(self.out, html)?;
+                Ok(Visit::SkipChildren)
+            }
+            None => {
+                if let Some(number) = number {
+                    write_heading_number(self.out, &number)?;
+                }
+                Ok(Visit::Children)
+            }
+        }
+    }
+
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        if command.name == "link" {
+            return self.end_inline("a");
+        }
+        if item_term(command).is_some() {
+            return Ok(());
+        }
+        if command.name == "bibitem" {
+            self.end_nested_block();
+            return write_str(self.out, "</li>\n");
+        }
+        if let Some(tag) = self.tag(command)? {
+            if tag.block {
+                writeln!(self.out, "</{}>", tag.name).context(ErrorKind::Write)?;
+                self.end_nested_block();
+            } else {
+                self.end_inline(&tag.name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        // The first argument of `\color` names the color, that of `\link` gives the URL, and that
+        // of `\bibitem` gives the key, and none is part of the content.
+        if let ("color" | "link" | "bibitem", 0) = (&*command.name, index) {
+            return Ok(Visit::SkipChildren);
+        }
+        if item_term(command).is_some() {
+            let tag = if index == 0 { "<dt" } else { "<dd" };
+            write_str(self.out, tag)?;
+            self.write_source_line()?;
+            write_str(self.out, self.options.dir_attribute())?;
+            write_str(self.out, ">")?;
+        }
+        Ok(Visit::Children)
+    }
+
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        if item_term(command).is_some() {
+            let tag = if index == 0 { "</dt>\n" } else { "</dd>\n" };
+            write_str(self.out, tag)?;
+        }
+        Ok(())
+    }
+
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        self.begin_nested_block()?;
+        // A quote with an attribution is placed in a figure, whose caption holds the attribution.
+        if env.name == "quote" && quote_attribution(env).is_some() {
+            write_str(self.out, "<figure")?;
+            self.write_source_line()?;
+            write_str(self.out, ">\n")?;
+        }
+        if env.name == "references" {
+            write_str(self.out, "<ol class=\"references\"")?;
+            self.write_source_line()?;
+            write_str(self.out, ">\n")?;
+            return Ok(Visit::Children);
+        }
+        if let Some(tag) = environment_tag(&env.name) {
+            write!(self.out, "<{}", tag).context(ErrorKind::Write)?;
+            self.write_source_line()?;
+            write_str(self.out, ">\n")?;
+            return Ok(Visit::Children);
+        }
+        write_str(self.out, "<div")?;
+        self.write_source_line()?;
+        write_str(self.out, " class=\"env-")?;
+        write_escaped_attribute(self.out, &env.name)?;
+        if env.name == "indent" {
+            let level = plain::indent_level(env, &mut self.report);
+            write!(self.out, "\" style=\"margin-left: {}em", level).context(ErrorKind::Write)?;
+        } else if env.name == "columns" {
+            let count = plain::column_count(env);
+            write!(self.out, "\" style=\"column-count: {}", count).context(ErrorKind::Write)?;
+        }
+        write_str(self.out, "\">\n")?;
+        Ok(Visit::Children)
+    }
+
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        self.end_nested_block();
+        let tag = environment_tag(&env.name).unwrap_or("div");
+        writeln!(self.out, "</{}>", tag).context(ErrorKind::Write)?;
+        if env.name == "quote" {
+            if let Some(attribution) = quote_attribution(env) {
+                write_str(self.out, "<figcaption>")?;
+                write_escaped(self.out, &attribution)?;
+                write_str(self.out, "</figcaption>\n</figure>\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_table(&mut self, table: &Table) -> Result<Visit, Error> {
+        self.begin_nested_block()?;
+        write_str(self.out, "<table")?;
+        self.write_source_line()?;
+        write_str(self.out, ">\n")?;
+        if self.options.table_captions && !table.caption.is_empty() {
+            write_str(self.out, "<caption>")?;
+            let caption = Document {
+                nodes: table.caption.clone(),
+            };
+            render_document(&caption, self)?;
+            write_str(self.out, "</caption>\n")?;
+        }
+        Ok(Visit::Children)
+    }
+
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        // The body is only written if there is a row other than the header.
+        if table.rows.len() > usize::from(table.header) {
+            write_str(self.out, "</tbody>\n")?;
+        }
+        self.end_nested_block();
+        write_str(self.out, "</table>\n")
+    }
+
+    fn begin_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        if row == 0 && table.header {
+            write_str(self.out, "<thead>\n")?;
+        } else if row == 0 || (row == 1 && table.header) {
+            write_str(self.out, "<tbody>\n")?;
+        }
+        write_str(self.out, "<tr>")
+    }
+
+    fn end_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        // Missing cells of a short row are written empty, so that every row has every column.
+        for column in table.rows[row].len()..table.column_count() {
+            self.begin_cell(table, row, column)?;
+            self.end_cell(table, row, column)?;
+        }
+        write_str(self.out, "</tr>\n")?;
+        if row == 0 && table.header {
+            write_str(self.out, "</thead>\n")?;
+        }
+        Ok(())
+    }
+
+    fn begin_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        write!(self.out, "<{}", cell_tag(table, row)).context(ErrorKind::Write)?;
+        match table.alignment(column) {
+            Alignment::Left => {}
+            Alignment::Center => write_str(self.out, " style=\"text-align: center\"")?,
+            Alignment::Right => write_str(self.out, " style=\"text-align: right\"")?,
+        }
+        write_str(self.out, self.options.dir_attribute())?;
+        write_str(self.out, ">")
+    }
+
+    fn end_cell(&mut self, table: &Table, row: usize, _column: usize) -> Result<(), Error> {
+        write!(self.out, "</{}>", cell_tag(table, row)).context(ErrorKind::Write)?;
+        Ok(())
+    }
+}
+
+/// Returns the number with which a heading starts, or `None` if it is unnumbered, or if headings
+/// are not numbered.
+fn heading_number<'a>(options: &HtmlOptions, heading: &'a Heading) -> Option<&'a str> {
+    heading
+        .formatted_number
+        .as_deref()
+        .filter(|_| options.number_headings)
+}
+
+/// Writes the number of a heading, followed by a space.
+fn write_heading_number<W: Write>(out: &mut W, number: &str) -> Result<(), Error> {
+    write_str(out, "<span class=\"heading-number\">")?;
+    write_escaped(out, number)?;
+    write_str(out, "</span> ")
+}
+
+/// Returns whether the nodes of a paragraph are a single `\image` with a caption, apart from
+/// whitespace, which is written as a `<figure>` in place of the paragraph.
+fn is_figure(nodes: &[Node]) -> bool {
+    let mut nodes = nodes
+        .iter()
+        .filter(|node| !matches!(**node, Node::Text(ref text) if text.trim().is_empty()));
+    match (nodes.next(), nodes.next()) {
+        (Some(Node::Command(command)), None) if command.name == "image" => {
+            Image::parse(command).caption.is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Returns whether the nodes of a paragraph are only `\raw` content, apart from whitespace, which
+/// is written as it is rather than in a `<p>`, since it may be a block element of its own.
+fn is_raw_block(nodes: &[Node]) -> bool {
+    let mut nodes = nodes
+        .iter()
+        .filter(|node| !matches!(**node, Node::Text(ref text) if text.trim().is_empty()))
+        .peekable();
+    nodes.peek().is_some() && nodes.all(|node| matches!(*node, Node::Raw(_)))
+}
+
+/// Returns the level of a heading element, such as 2 for `h2`, or `None` if the element is not a
+/// heading.
+fn heading_element(name: &str) -> Option<usize> {
+    match name.strip_prefix('h')?.parse() {
+        Ok(level @ 1..=6) => Some(level),
+        _ => None,
+    }
+}
+
+/// Returns the element for a built-in command.
+fn default_tag(name: &str) -> Option<Tag> {
+    let tag = match outline::unstarred(name) {
+        "emph" => Tag::new("em"),
+        "bold" => Tag::new("strong"),
+        "code" => Tag::new("code"),
+        "color" => Tag::new("span").attr("style", "color: {color}"),
+        "section" => Tag::new("h2").block(),
+        "subsection" => Tag::new("h3").block(),
+        "item" => Tag::new("li").block(),
+        "centered" => Tag::new("div").attr("style", "text-align: center").block(),
+        "rightalign" => Tag::new("div").attr("style", "text-align: right").block(),
+        _ => return None,
+    };
+    Some(tag)
+}
+
+/// Replaces the placeholders in a class name or attribute value of a `Tag` with the name or
+/// arguments of the command, or returns `None` if it contains `{color}` and the first argument is
+/// not a color.
+fn expand_template(template: &str, command: &Command) -> Option<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let key = &rest[1..end];
+        if key == "name" {
+            expanded.push_str(&command.name);
+        } else if key == "color" {
+            let color = command
+                .args
+                .first()
+                .and_then(|arg| ast::text_content(arg).trim().parse::<Color>().ok());
+            expanded.push_str(&color?.to_css());
+        } else if let Some(arg) = key
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .and_then(|n| command.args.get(n - 1))
+        {
+            expanded.push_str(ast::text_content(arg).trim());
+        } else {
+            expanded.push_str(&rest[..=end]);
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Some(expanded)
+}
+
+/// Returns the line of the input on which a top-level node starts: the first line of its span
+/// which is not blank, or the line on which it starts if it is all whitespace.
+fn source_line(input: &str, span: Span) -> usize {
+    let spanned = input.get(span.start..span.end.min(input.len())).unwrap_or("");
+    let start = span.start + (spanned.len() - spanned.trim_start().len());
+    input[..input.floor_char_boundary(start)].matches('\n').count() + 1
+}
+
+/// Returns the tag for a built-in environment.
+fn environment_tag(name: &str) -> Option<&'static str> {
+    match name {
+        "itemize" => Some("ul"),
+        "enumerate" => Some("ol"),
+        "description" => Some("dl"),
+        "references" => Some("ol"),
+        "quote" => Some("blockquote"),
+        _ => None,
+    }
+}
+
+/// Returns the element for the cells of the given row of a table.
+fn cell_tag(table: &Table, row: usize) -> &'static str {
+    if row == 0 && table.header {
+        "th"
+    } else {
+        "td"
+    }
+}
+
+/// Splits HTML into lines, closing the elements which are open at the end of each line and
+/// opening them again at the start of the next, so that each line can be wrapped in an element
+/// of its own. Every element is assumed to be closed, so that this is only suitable for
+/// highlighted code, which consists of text and `<span>`s.
+fn split_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    // The start tags of the open elements, outermost first.
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut rest = html;
+    while let Some(i) = rest.find(['<', '\n']) {
+        line.push_str(&rest[..i]);
+        if rest[i..].starts_with('\n') {
+            for tag in open.iter().rev() {
+                let name = tag[1..].split(|c: char| c.is_whitespace() || c == '>').next();
+                line.push_str(&format!("</{}>", name.unwrap_or_default()));
+            }
+            lines.push(mem::replace(&mut line, open.concat()));
+            rest = &rest[i + 1..];
+        } else {
+            let end = rest[i..].find('>').map_or(rest.len(), |end| i + end + 1);
+            let tag = &rest[i..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else if !tag.starts_with("<!") && !tag.ends_with("/>") {
+                open.push(tag);
+            }
+            line.push_str(tag);
+            rest = &rest[end..];
+        }
+    }
+    line.push_str(rest);
+    lines.push(line);
+    lines
+}
+
+/// Writes a string to the output without escaping it.
+fn write_str<W: Write>(out: &mut W, s: &str) -> Result<(), Error> {
+    out.write_all(s.as_bytes()).context(ErrorKind::Write)?;
+    Ok(())
+}
+
+/// Writes text content to the output, escaping `&`, `<`, and `>`.
+fn write_escaped<W: Write>(out: &mut W, s: &str) -> Result<(), Error> {
+    write_str(out, &escape(s, false))
+}
+
+/// Writes an attribute value to the output, escaping quotes as well as `&`, `<`, and `>`.
+fn write_escaped_attribute<W: Write>(out: &mut W, s: &str) -> Result<(), Error> {
+    write_str(out, &escape(s, true))
+}
+
+/// Escapes the characters of `s` which are special in HTML text, and, if `attribute` is set, in
+/// quoted attribute values.
+pub fn escape(s: &str, attribute: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            '\'' if attribute => escaped.push_str("&#39;"),
+            SOFT_HYPHEN if !attribute => escaped.push_str("&shy;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::Parser;
+    use super::{is_streamable, stream, HtmlOptions, HtmlRenderer, WHOLE_DOCUMENT_COMMANDS};
+
+    /// Documents which use none of the commands that streaming lacks.
+    const STREAMABLE: &[&str] = &[
+        "",
+        "A paragraph with \\emph{emphasis} and \\bold{bold {grouped} text}.\n\nAnother.",
+        "\\section{One}\n\nText.\n\n\\subsection{Two}\n\n\\section{One}\n\nMore text.",
+        "Text\\footnote{A note.} and\\footnote{another}.\n\n\\section{After}",
+        "\\begin{itemize}\n\\item a\n\\item b \\begin{enumerate}\n\\item c\n\\end{enumerate}\n\
+         \\end{itemize}",
+        "\\begin{quote}[Someone]\nQuoted.\n\\end{quote}\n\n\\verbatim[rust]{fn main() {}}",
+        "\\begin{table}{lr}{Caption}\n\\header{a}{b}\n\\row{c}{d}\n\\end{table}",
+        "lead {text \\verbatim{code} tail} end \\link{https://example.com}{a link}",
+        "\\section{A}\\label{a}\n\nCaf\u{e9} \\index{caf\u{e9}} & <b>\n\n---",
+    ];
+
+    fn render(source: &str, options: &HtmlOptions) -> String {
+        let document = Parser::new(source.as_bytes()).parse().expect("parsing");
+        let mut out = Vec::new();
+        HtmlRenderer::new(options.clone())
+            .render(&document, &mut out)
+            .expect("rendering");
+        String::from_utf8(out).expect("the output is UTF-8")
+    }
+
+    fn streamed(source: &str, options: &HtmlOptions) -> String {
+        let mut out = Vec::new();
+        stream(source.as_bytes(), &mut out, options).expect("streaming");
+        String::from_utf8(out).expect("the output is UTF-8")
+    }
+
+    #[test]
+    fn streaming_gives_the_same_output() {
+        let options = [
+            HtmlOptions::new(),
+            HtmlOptions::new().fragment(false),
+            HtmlOptions::new().sections(true),
+            HtmlOptions::new().ascii(true),
+        ];
+        for source in STREAMABLE {
+            for options in &options {
+                assert!(is_streamable(source.as_bytes(), options), "{:?}", source);
+                assert_eq!(streamed(source, options), render(source, options), "{:?}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn whole_document_commands_are_not_streamable() {
+        let options = HtmlOptions::new();
+        for command in WHOLE_DOCUMENT_COMMANDS {
+            let source = format!("Text \\{}{{x}}.", command);
+            assert!(!is_streamable(source.as_bytes(), &options), "{}", command);
+        }
+        // Metadata only matters if the output has a head.
+        let source = b"\\title{T}\n\nText.";
+        assert!(is_streamable(source, &options));
+        assert!(!is_streamable(source, &HtmlOptions::new().fragment(false)));
+        assert!(!is_streamable(b"Text.", &HtmlOptions::new().source_positions(true)));
+        // A command whose name only starts with that of another is not mistaken for it.
+        assert!(is_streamable(b"\\reference{x} \\citation{y}", &options));
+    }
+
+    #[test]
+    fn whole_document_commands_stream_differently() {
+        let options = HtmlOptions::new();
+        let sources = [
+            "\\tableofcontents\n\n\\section{A}",
+            "See \\ref{later}.\n\n\\section{Later}\\label{later}",
+            "\\index{a}\n\n\\printindex",
+        ];
+        for source in &sources {
+            assert_ne!(streamed(source, &options), render(source, &options), "{:?}", source);
+        }
+    }
+}
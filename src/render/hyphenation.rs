@@ -0,0 +1,126 @@
+//! Automatic hyphenation of the words of wrapped text, by the Knuth–Liang hyphenation patterns of
+//! a language, which are bundled by the `hypher` crate. Requires the `hyphenation` feature.
+//!
+//! A word is hyphenated only if it has no soft hyphens of its own, given by `\-`, since those
+//! mark the only points at which the author wants it broken, and only if it consists of letters,
+//! apart from any punctuation before or after it. Words holding digits, hyphens, or other symbols,
+//! such as identifiers and paths, are left whole.
+
+use std::fmt;
+
+use hypher::{self, Lang};
+
+use tokenize::SOFT_HYPHEN;
+
+/// A language whose hyphenation patterns are available.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Language {
+    /// US English.
+    English,
+    /// French.
+    French,
+    /// German.
+    German,
+}
+
+impl Language {
+    /// Returns the language with the given ISO 639-1 code, such as `en`, or `None` if its patterns
+    /// are not available.
+    pub fn from_code(code: &str) -> Option<Language> {
+        match code {
+            "en" => Some(Language::English),
+            "fr" => Some(Language::French),
+            "de" => Some(Language::German),
+            _ => None,
+        }
+    }
+
+    /// Returns the ISO 639-1 code of the language.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+            Language::German => "de",
+        }
+    }
+
+    fn lang(self) -> Lang {
+        match self {
+            Language::English => Lang::English,
+            Language::French => Lang::French,
+            Language::German => Lang::German,
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// How the words of wrapped text are hyphenated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Hyphenation {
+    language: Language,
+    min_word_length: usize,
+    min_left: usize,
+    min_right: usize,
+}
+
+impl Hyphenation {
+    /// Constructs a new `Hyphenation` by the patterns of the given language. Words of fewer than
+    /// 5 letters are not hyphenated, and the fragments left at the start and end of a word are at
+    /// least as long as is conventional for the language, such as 2 and 3 letters for English.
+    pub fn new(language: Language) -> Hyphenation {
+        let (min_left, min_right) = language.lang().bounds();
+        Hyphenation {
+            language,
+            min_word_length: 5,
+            min_left,
+            min_right,
+        }
+    }
+
+    /// Sets the number of letters below which a word is not hyphenated. Defaults to 5.
+    pub fn min_word_length(mut self, length: usize) -> Hyphenation {
+        self.min_word_length = length;
+        self
+    }
+
+    /// Sets the fewest letters which may be left before and after a break. Each is at least 1.
+    pub fn min_fragments(mut self, left: usize, right: usize) -> Hyphenation {
+        self.min_left = left.max(1);
+        self.min_right = right.max(1);
+        self
+    }
+
+    /// Returns the language whose patterns are used.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// Returns `word` with a soft hyphen at each point at which it may be broken, or `None` if it
+    /// is not to be hyphenated, as described in the module documentation.
+    pub fn hyphenate(&self, word: &str) -> Option<String> {
+        if word.contains(SOFT_HYPHEN) {
+            return None;
+        }
+        let start = word.find(char::is_alphabetic)?;
+        let (last, c) = word.char_indices().rev().find(|&(_, c)| c.is_alphabetic())?;
+        let end = last + c.len_utf8();
+        let core = &word[start..end];
+        if !core.chars().all(char::is_alphabetic)
+            || core.chars().count() < self.min_word_length
+        {
+            return None;
+        }
+        let syllables =
+            hypher::hyphenate_bounded(core, self.language.lang(), self.min_left, self.min_right);
+        let mut hyphenated = String::with_capacity(word.len() + 8);
+        hyphenated.push_str(&word[..start]);
+        hyphenated.push_str(&syllables.join(&SOFT_HYPHEN.to_string()));
+        hyphenated.push_str(&word[end..]);
+        Some(hyphenated)
+    }
+}
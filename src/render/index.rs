@@ -0,0 +1,223 @@
+//! The index of a document, formed by its `\index` commands.
+//!
+//! `\index{parsing}` adds the term "parsing" to the index, at the location of the command, and
+//! `\index{errors!recovery}` adds "recovery" as a sub-entry of "errors"; sub-entries may be
+//! nested to any depth. Each location is the heading which the command follows, and each
+//! `\index` command is also given an anchor of its own, `idx-` followed by its position among
+//! the `\index` commands of the document, so that formats with links can link to the command
+//! itself, whether or not its heading is numbered.
+//!
+//! `\printindex` writes the index, as the entries sorted alphabetically, ignoring case, and
+//! grouped by their first letter. Entries which differ only in case keep the order in which they
+//! first appear. An index with no entries is not written at all.
+
+use ast::{self, Command, Node};
+use locale::Locale;
+use super::outline::{self, Heading};
+
+/// The group of the entries whose terms do not start with a letter.
+pub const SYMBOLS: &str = "#";
+
+/// A term of the index, along with its locations and its sub-entries.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    /// The term, with whitespace collapsed.
+    pub term: String,
+    /// The locations of the term, in the order in which they appear, with at most one for each
+    /// heading. A term which only has sub-entries has no locations of its own.
+    pub locations: Vec<Location>,
+    /// The sub-entries, sorted.
+    pub subentries: Vec<Entry>,
+}
+
+/// A location of a term of the index.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    /// The anchor of the first `\index` command for the term under this heading.
+    pub anchor: String,
+    /// The text of the location, which is the number of the heading preceded by `§`, or its
+    /// title if it is unnumbered, or the `index_start` translation of the locale before the first
+    /// heading.
+    pub text: String,
+}
+
+/// The entries of the index whose terms start with the same letter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Group {
+    /// The letter, in upper case, or `SYMBOLS` for terms which do not start with a letter.
+    pub letter: String,
+    /// The entries, sorted.
+    pub entries: Vec<Entry>,
+}
+
+impl Entry {
+    /// Returns the text of the entry for formats without links: its term followed by its
+    /// locations, separated by commas.
+    pub fn text(&self) -> String {
+        let mut text = self.term.clone();
+        for location in &self.locations {
+            text.push_str(", ");
+            text.push_str(&location.text);
+        }
+        text
+    }
+}
+
+/// Returns the anchor of the `\index` command with the given position among those of the
+/// document, starting from 1.
+pub fn anchor(number: usize) -> String {
+    format!("idx-{}", number)
+}
+
+/// Returns the terms given by an `\index` command, from the outermost entry to the sub-entry,
+/// without any which are empty.
+pub fn terms(command: &Command) -> Vec<String> {
+    let text = command
+        .args
+        .first()
+        .map(|arg| ast::text_content(arg))
+        .unwrap_or_default();
+    text.split('!')
+        .map(|term| term.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+/// Returns the index of the `\index` commands among the given nodes, whose headings are those
+/// given, grouped by letter, with the locations before the first heading translated by the
+/// locale. Returns no groups if there are no `\index` commands.
+pub fn groups(nodes: &[Node], headings: &[Heading], locale: &Locale) -> Vec<Group> {
+    let mut builder = Builder {
+        headings,
+        start: locale.index_start(),
+        heading: None,
+        count: 0,
+        entries: Vec::new(),
+    };
+    builder.walk(nodes);
+    let mut entries = builder.entries;
+    sort(&mut entries);
+    let mut groups: Vec<Group> = Vec::new();
+    for entry in entries {
+        let letter = letter(&entry.term);
+        match groups.last_mut() {
+            Some(group) if group.letter == letter => group.entries.push(entry),
+            _ => groups.push(Group {
+                letter,
+                entries: vec![entry],
+            }),
+        }
+    }
+    groups
+}
+
+/// Returns the number of `\index` commands among the given nodes, which are given anchors in the
+/// order in which they are rendered.
+pub(crate) fn count(nodes: &[Node]) -> usize {
+    let mut builder = Builder {
+        headings: &[],
+        start: "",
+        heading: None,
+        count: 0,
+        entries: Vec::new(),
+    };
+    builder.walk(nodes);
+    builder.count
+}
+
+/// Returns the group of a term.
+fn letter(term: &str) -> String {
+    match term.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => SYMBOLS.to_owned(),
+    }
+}
+
+/// Sorts entries and their sub-entries alphabetically, ignoring case, with the terms which do
+/// not start with a letter first. The sort is stable.
+fn sort(entries: &mut [Entry]) {
+    entries.sort_by_cached_key(|entry| {
+        let letter = entry.term.chars().next().is_some_and(char::is_alphabetic);
+        (letter, entry.term.to_lowercase())
+    });
+    for entry in entries {
+        sort(&mut entry.subentries);
+    }
+}
+
+/// The state of the collection of the entries of an index.
+struct Builder<'a> {
+    headings: &'a [Heading],
+    /// The text of the location before the first heading.
+    start: &'a str,
+    /// The position among the headings of the most recent heading.
+    heading: Option<usize>,
+    /// The number of `\index` commands so far.
+    count: usize,
+    entries: Vec<Entry>,
+}
+
+impl<'a> Builder<'a> {
+    /// Adds the `\index` commands among the nodes, visiting them in the order in which they are
+    /// rendered.
+    fn walk(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match *node {
+                Node::Command(ref command) => {
+                    if outline::heading_level(&command.name).is_some() {
+                        self.heading = Some(self.heading.map_or(0, |heading| heading + 1));
+                    }
+                    if command.name == "index" {
+                        self.count += 1;
+                        self.add(command);
+                    }
+                    for arg in &command.args {
+                        self.walk(arg);
+                    }
+                }
+                Node::Paragraph(ref nodes) | Node::Group(ref nodes) => self.walk(nodes),
+                Node::Environment(ref env) => self.walk(&env.nodes),
+                Node::Table(ref table) => {
+                    for cell in table.rows.iter().flatten() {
+                        self.walk(cell);
+                    }
+                }
+                Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => {}
+            }
+        }
+    }
+
+    /// Adds the terms of an `\index` command at the current location.
+    fn add(&mut self, command: &Command) {
+        let text = match self.heading.and_then(|heading| self.headings.get(heading)) {
+            Some(heading) => outline::heading_reference(heading),
+            None => self.start.to_owned(),
+        };
+        let mut entries = &mut self.entries;
+        let mut terms = terms(command).into_iter().peekable();
+        while let Some(term) = terms.next() {
+            let i = match entries.iter().position(|entry| entry.term == term) {
+                Some(i) => i,
+                None => {
+                    entries.push(Entry {
+                        term,
+                        locations: Vec::new(),
+                        subentries: Vec::new(),
+                    });
+                    entries.len() - 1
+                }
+            };
+            let entry = &mut entries[i];
+            if terms.peek().is_none() {
+                if !entry.locations.iter().any(|location| location.text == text) {
+                    entry.locations.push(Location {
+                        anchor: anchor(self.count),
+                        text,
+                    });
+                }
+                return;
+            }
+            entries = &mut entry.subentries;
+        }
+    }
+}
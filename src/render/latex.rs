@@ -0,0 +1,531 @@
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use ast::{self, Alignment, Command, Document, Environment, Node, Table, Verbatim};
+use bibliography::{self, Citations};
+use errors::{Error, ErrorKind, ResultExt};
+use tokenize::SOFT_HYPHEN;
+use super::abbreviations::Abbreviations;
+use super::index::terms as index_terms;
+use super::outline;
+use super::plain;
+use super::{has_link_text, is_horizontal_rule, link_url, render_document, FmtWriter};
+use super::{is_set, item_term, quote_attribution, RenderReport, Renderer, RuleStyle, Visit};
+
+/// The preamble used for standalone documents by default.
+pub const DEFAULT_PREAMBLE: &str = "\\usepackage[T1]{fontenc}\n\\usepackage[utf8]{inputenc}\n\
+    \\usepackage{xcolor}\n\\usepackage{hyperref}\n\\usepackage{makeidx}\n\\makeindex\n\
+    \\usepackage{multicol}\n";
+
+/// Options controlling the output of the LaTeX renderer.
+#[derive(Clone, Debug)]
+pub struct LatexOptions {
+    standalone: bool,
+    document_class: String,
+    preamble: String,
+    unknown_commands: bool,
+    reset_abbreviations: bool,
+    cancel_token: Option<Arc<AtomicBool>>,
+}
+
+impl Default for LatexOptions {
+    fn default() -> LatexOptions {
+        LatexOptions {
+            standalone: false,
+            document_class: "article".into(),
+            preamble: DEFAULT_PREAMBLE.into(),
+            unknown_commands: false,
+            reset_abbreviations: false,
+            cancel_token: None,
+        }
+    }
+}
+
+impl LatexOptions {
+    /// Constructs a new `LatexOptions` with the default settings.
+    pub fn new() -> LatexOptions {
+        LatexOptions::default()
+    }
+
+    /// Sets whether a complete document is produced, with a `\documentclass`, the preamble, and a
+    /// `document` environment, rather than a fragment. Defaults to `false`.
+    pub fn standalone(mut self, standalone: bool) -> LatexOptions {
+        self.standalone = standalone;
+        self
+    }
+
+    /// Sets the document class of a standalone document. Defaults to `article`.
+    pub fn document_class<S: Into<String>>(mut self, class: S) -> LatexOptions {
+        self.document_class = class.into();
+        self
+    }
+
+    /// Sets the preamble of a standalone document, which is written between the
+    /// `\documentclass` and the start of the document. Defaults to `DEFAULT_PREAMBLE`.
+    pub fn preamble<S: Into<String>>(mut self, preamble: S) -> LatexOptions {
+        self.preamble = preamble.into();
+        self
+    }
+
+    /// Sets whether unrecognized commands are written as they appear in the input, since they may
+    /// be valid LaTeX, rather than omitted. Defaults to `false`.
+    pub fn unknown_commands(mut self, pass_through: bool) -> LatexOptions {
+        self.unknown_commands = pass_through;
+        self
+    }
+
+    /// Sets whether each abbreviation is expanded again at its first use after every `\section`,
+    /// rather than only at its first use in the document. Defaults to `false`.
+    pub fn reset_abbreviations(mut self, reset: bool) -> LatexOptions {
+        self.reset_abbreviations = reset;
+        self
+    }
+
+    /// Sets a flag which stops rendering with an `ErrorKind::Cancelled` error once it is set, as
+    /// with `HtmlOptions::cancel_token`. Defaults to none.
+    pub fn cancel_token(mut self, token: Arc<AtomicBool>) -> LatexOptions {
+        self.cancel_token = Some(token);
+        self
+    }
+}
+
+/// Renders a document as LaTeX to the given output.
+///
+/// Citations are written as the numbers of the cited entries in brackets, and a `references`
+/// environment as a list of its `\bibitem`s, labelled by the same numbers. `\index` and
+/// `\printindex` are written as they are, for `makeindex` to process, which needs the `makeidx`
+/// package and `\makeindex` in the preamble, as in `DEFAULT_PREAMBLE`. Abbreviations are written as
+/// text, with their expansion at their first use, as described in the `abbreviations` module. A
+/// `columns` environment becomes a `multicols` environment, which needs the `multicol` package.
+/// A table with a caption is placed in a `table` float, with the caption given by `\caption`.
+pub fn render<W: Write>(
+    document: &Document,
+    options: &LatexOptions,
+    out: &mut W,
+) -> Result<RenderReport, Error> {
+    if options.standalone {
+        write!(out, "\\documentclass{{{}}}\n{}", options.document_class, options.preamble)
+            .context(ErrorKind::Write)?;
+        write_str(out, "\\begin{document}\n")?;
+    }
+    let mut writer = LatexWriter {
+        options,
+        out,
+        citations: Citations::new(&document.nodes),
+        abbreviations: Abbreviations::new(&document.nodes)
+            .reset_at_sections(options.reset_abbreviations),
+        report: RenderReport::new(),
+    };
+    render_document(document, &mut writer)?;
+    let report = writer.report;
+    if options.standalone {
+        write_str(out, "\\end{document}\n")?;
+    }
+    Ok(report)
+}
+
+/// Renders a document as LaTeX to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    options: &LatexOptions,
+    out: &mut F,
+) -> Result<RenderReport, Error> {
+    render(document, options, &mut FmtWriter::new(out))
+}
+
+/// Renders a document as LaTeX, returning the result as a `String`. Any warnings
+/// are discarded.
+pub fn render_to_string(document: &Document, options: &LatexOptions) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, options, &mut out)?;
+    Ok(out)
+}
+
+/// Escapes the characters of `s` which are special in LaTeX.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            SOFT_HYPHEN => escaped.push_str("\\-"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the LaTeX command corresponding to a built-in command, and whether it forms a block.
+fn command_name(name: &str) -> Option<(&'static str, bool)> {
+    match outline::unstarred(name) {
+        "emph" => Some(("emph", false)),
+        "bold" => Some(("textbf", false)),
+        "code" => Some(("texttt", false)),
+        "color" => Some(("textcolor", false)),
+        "footnote" => Some(("footnote", false)),
+        "ref" => Some(("ref", false)),
+        "index" => Some(("index", false)),
+        "section" => Some(("section", true)),
+        "subsection" => Some(("subsection", true)),
+        "label" => Some(("label", true)),
+        "tableofcontents" => Some(("tableofcontents", true)),
+        "printindex" => Some(("printindex", true)),
+        "appendix" => Some(("appendix", true)),
+        _ => None,
+    }
+}
+
+/// Returns the LaTeX environment corresponding to a built-in command whose argument forms its
+/// content.
+fn command_environment(name: &str) -> Option<&'static str> {
+    match name {
+        "centered" => Some("center"),
+        "rightalign" => Some("flushright"),
+        _ => None,
+    }
+}
+
+/// Returns whether the arguments of a command are written in braces, which is the case unless
+/// the command is written as an `\item` or an environment.
+fn braced_arguments(name: &str) -> bool {
+    name != "item" && name != "bibitem" && command_environment(name).is_none()
+}
+
+/// Returns the LaTeX environment corresponding to an environment.
+fn environment_name(name: &str) -> &str {
+    match name {
+        "indent" => "list",
+        "columns" => "multicols",
+        "references" => "itemize",
+        name => name,
+    }
+}
+
+/// Quotes the characters of a term of an index entry which are special to `makeindex`.
+fn quote_index_term(term: &str) -> String {
+    let mut quoted = String::with_capacity(term.len());
+    for c in term.chars() {
+        if let '!' | '@' | '|' | '"' = c {
+            quoted.push('"');
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+/// Returns the column specifier of `tabular` for an alignment.
+fn column_type(alignment: Alignment) -> char {
+    match alignment {
+        Alignment::Left => 'l',
+        Alignment::Center => 'c',
+        Alignment::Right => 'r',
+    }
+}
+
+/// Returns whether the given nodes contain a `\break` outside of a nested table.
+fn has_break(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match *node {
+        Node::Paragraph(ref nodes) | Node::Group(ref nodes) => has_break(nodes),
+        Node::Command(ref command) => {
+            command.name == "break" || command.args.iter().any(|arg| has_break(arg))
+        }
+        Node::Environment(ref env) => has_break(&env.nodes),
+        _ => false,
+    })
+}
+
+/// Returns whether a cell of a table contains a `\break`, which must be written within a nested
+/// `tabular`, since `\\` would otherwise end the row.
+fn cell_has_break(table: &Table, row: usize, column: usize) -> bool {
+    table.rows[row].get(column).is_some_and(|cell| has_break(cell))
+}
+
+/// Writes a string to the output without escaping it.
+fn write_str<W: Write + ?Sized>(out: &mut W, s: &str) -> Result<(), Error> {
+    out.write_all(s.as_bytes()).context(ErrorKind::Write)?;
+    Ok(())
+}
+
+/// The `Renderer` implementation backing `render`.
+struct LatexWriter<'a, W: 'a> {
+    options: &'a LatexOptions,
+    out: &'a mut W,
+    /// The numbers of the entries of the bibliography.
+    citations: Citations,
+    /// The abbreviations of the document, and which have been used.
+    abbreviations: Abbreviations,
+    /// The warnings encountered so far.
+    report: RenderReport,
+}
+
+impl<'a, W: Write> LatexWriter<'a, W> {
+    /// Writes verbatim content which cannot be placed in a `verbatim` environment, because it
+    /// contains `\end{verbatim}`, as escaped typewriter text with its spaces and line breaks
+    /// preserved.
+    fn escaped_verbatim(&mut self, content: &str) -> Result<(), Error> {
+        write_str(self.out, "\\begin{flushleft}\\ttfamily\n")?;
+        for line in content.lines() {
+            write_str(self.out, "\\mbox{}")?;
+            write_str(self.out, &escape(line).replace(' ', "\\ "))?;
+            write_str(self.out, "\\\\\n")?;
+        }
+        write_str(self.out, "\\end{flushleft}\n\n")
+    }
+}
+
+impl<'a, W: Write> Renderer for LatexWriter<'a, W> {
+    fn is_cancelled(&self) -> bool {
+        is_set(self.options.cancel_token.as_ref())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        write_str(self.out, &escape(text))
+    }
+
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        let content = &verbatim.content;
+        if content.contains("\\end{verbatim}") {
+            return self.escaped_verbatim(content);
+        }
+        write_str(self.out, "\\begin{verbatim}\n")?;
+        write_str(self.out, content)?;
+        if !content.ends_with('\n') {
+            write_str(self.out, "\n")?;
+        }
+        write_str(self.out, "\\end{verbatim}\n\n")
+    }
+
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        write_str(self.out, content)
+    }
+
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        write_str(self.out, "\n\n")
+    }
+
+    fn begin_group(&mut self) -> Result<(), Error> {
+        write_str(self.out, "{")
+    }
+
+    fn end_group(&mut self) -> Result<(), Error> {
+        write_str(self.out, "}")
+    }
+
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        if command.name == "item" {
+            // The term of a description item is written in brackets by `begin_argument`.
+            let space = if item_term(command).is_some() { "" } else { " " };
+            write!(self.out, "\\item{}", space).context(ErrorKind::Write)?;
+            return Ok(Visit::Children);
+        }
+        if command.name == "bibitem" {
+            let number = self.citations.number(&bibliography::bibitem_key(command));
+            write!(self.out, "\\item[{{[{}]}}] ", bibliography::number_text(number))
+                .context(ErrorKind::Write)?;
+            return Ok(Visit::Children);
+        }
+        if command.name == "cite" {
+            let marker = self.citations.marker(command, &mut self.report);
+            write_str(self.out, &marker)?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "abbr" {
+            let abbreviation = self
+                .abbreviations
+                .use_abbreviation(command, &mut self.report);
+            self.text(&abbreviation.text())?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "defabbr" || command.name == "lang" {
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "break" {
+            // `\leavevmode` allows a break at the start of a paragraph, where `\\` alone is an
+            // error, and the braces stop a following `[` from being taken as its argument.
+            write_str(self.out, "\\leavevmode\\\\{}")?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "link" {
+            let name = if has_link_text(command) { "\\href" } else { "\\url" };
+            write_str(self.out, name)?;
+            return Ok(Visit::Children);
+        }
+        if is_horizontal_rule(&command.name) {
+            // A rule of relative width is drawn by `\hrulefill` within a box of that width.
+            match RuleStyle::new(command).width {
+                Some(width) => write!(
+                    self.out,
+                    "\\noindent\\makebox[{}\\linewidth][l]{{\\hrulefill}}\n\n",
+                    width
+                )
+                .context(ErrorKind::Write)?,
+                None => write_str(self.out, "\\hrule\n\n")?,
+            }
+            return Ok(Visit::SkipChildren);
+        }
+        if let Some(env) = command_environment(&command.name) {
+            writeln!(self.out, "\\begin{{{}}}", env).context(ErrorKind::Write)?;
+            return Ok(Visit::Children);
+        }
+        self.abbreviations.heading(command);
+        let name = match command_name(&command.name) {
+            Some((name, _)) if outline::is_starred(&command.name) => format!("{}*", name),
+            Some((name, _)) => name.to_owned(),
+            None if self.options.unknown_commands => command.name.clone(),
+            None => return Ok(Visit::SkipChildren),
+        };
+        write!(self.out, "\\{}", name).context(ErrorKind::Write)?;
+        Ok(Visit::Children)
+    }
+
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        if let Some(env) = command_environment(&command.name) {
+            write!(self.out, "\n\\end{{{}}}\n\n", env).context(ErrorKind::Write)?;
+        } else if command.name == "bibitem" {
+            write_str(self.out, "\n\n")?;
+        } else if let Some((_, true)) = command_name(&command.name) {
+            write_str(self.out, "\n\n")?;
+        }
+        Ok(())
+    }
+
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        // The braces keep a `]` in the term from ending it.
+        if item_term(command).is_some() && index == 0 {
+            write_str(self.out, "[{")?;
+            return Ok(Visit::Children);
+        }
+        // The key of a `\bibitem` is only used to number it.
+        if let ("bibitem", 0) = (&*command.name, index) {
+            return Ok(Visit::SkipChildren);
+        }
+        if !braced_arguments(&command.name) {
+            return Ok(Visit::Children);
+        }
+        write_str(self.out, "{")?;
+        // Color names and labels are written as they are, rather than rendered as text.
+        if let ("color", 0) | ("label", 0) | ("ref", 0) = (&*command.name, index) {
+            let name = ast::text_content(&command.args[0]);
+            write_str(self.out, name.trim())?;
+            return Ok(Visit::SkipChildren);
+        }
+        // The terms of an index entry are escaped for LaTeX, and then for `makeindex`, whose
+        // special characters are quoted with `"`, and separated by `!`.
+        if let ("index", 0) = (&*command.name, index) {
+            let terms: Vec<_> = index_terms(command)
+                .iter()
+                .map(|term| quote_index_term(&escape(term)))
+                .collect();
+            write_str(self.out, &terms.join("!"))?;
+            return Ok(Visit::SkipChildren);
+        }
+        // Only `%` and `#` are special in a URL, once it has been percent-encoded.
+        if let ("link", 0) = (&*command.name, index) {
+            let url = link_url(command).replace('%', "\\%").replace('#', "\\#");
+            write_str(self.out, &url)?;
+            return Ok(Visit::SkipChildren);
+        }
+        Ok(Visit::Children)
+    }
+
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        if item_term(command).is_some() && index == 0 {
+            write_str(self.out, "}] ")?;
+        } else if braced_arguments(&command.name) {
+            write_str(self.out, "}")?;
+        }
+        Ok(())
+    }
+
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        // An `indent` environment becomes a list with a single unlabelled item and the given left
+        // margin.
+        if env.name == "indent" {
+            let level = plain::indent_level(env, &mut self.report);
+            writeln!(
+                self.out,
+                "\\begin{{list}}{{}}{{\\setlength{{\\leftmargin}}{{{}em}}}}\\item[]",
+                level
+            )
+            .context(ErrorKind::Write)?;
+            return Ok(Visit::Children);
+        }
+        if env.name == "columns" {
+            let count = plain::column_count(env);
+            writeln!(self.out, "\\begin{{multicols}}{{{}}}", count).context(ErrorKind::Write)?;
+            return Ok(Visit::Children);
+        }
+        writeln!(self.out, "\\begin{{{}}}", environment_name(&env.name))
+            .context(ErrorKind::Write)?;
+        Ok(Visit::Children)
+    }
+
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        if env.name == "quote" {
+            if let Some(attribution) = quote_attribution(env) {
+                writeln!(self.out, "\\par\\hfill {}", escape(&attribution))
+                    .context(ErrorKind::Write)?;
+            }
+        }
+        write!(self.out, "\\end{{{}}}\n\n", environment_name(&env.name))
+            .context(ErrorKind::Write)?;
+        Ok(())
+    }
+
+    fn begin_table(&mut self, table: &Table) -> Result<Visit, Error> {
+        let columns: String = (0..table.column_count())
+            .map(|column| column_type(table.alignment(column)))
+            .collect();
+        if !table.caption.is_empty() {
+            write_str(self.out, "\\begin{table}[htbp]\n\\centering\n")?;
+        }
+        writeln!(self.out, "\\begin{{tabular}}{{{}}}", columns).context(ErrorKind::Write)?;
+        Ok(Visit::Children)
+    }
+
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        if table.caption.is_empty() {
+            return write_str(self.out, "\\end{tabular}\n\n");
+        }
+        write_str(self.out, "\\end{tabular}\n\\caption{")?;
+        let caption = Document {
+            nodes: table.caption.clone(),
+        };
+        render_document(&caption, self)?;
+        write_str(self.out, "}\n\\end{table}\n\n")
+    }
+
+    fn end_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        write_str(self.out, " \\\\\n")?;
+        if row == 0 && table.header {
+            write_str(self.out, "\\hline\n")?;
+        }
+        Ok(())
+    }
+
+    fn begin_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        if column > 0 {
+            write_str(self.out, " & ")?;
+        }
+        if cell_has_break(table, row, column) {
+            let column = column_type(table.alignment(column));
+            write!(self.out, "\\begin{{tabular}}[t]{{@{{}}{}@{{}}}}", column)
+                .context(ErrorKind::Write)?;
+        }
+        Ok(())
+    }
+
+    fn end_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        if cell_has_break(table, row, column) {
+            write_str(self.out, "\\end{tabular}")?;
+        }
+        Ok(())
+    }
+}
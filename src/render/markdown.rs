@@ -0,0 +1,423 @@
+use std::fmt;
+use std::io::Write;
+
+use ast::{self, Alignment, Command, Document, Environment, Table, Verbatim};
+use errors::Error;
+use super::outline;
+use super::plain::{self, PlainOptions, PlainWriter};
+use super::{has_link_text, is_horizontal_rule, item_term, link_address, link_url};
+use super::quote_attribution;
+use super::render_document;
+use super::{FmtWriter, Image, RenderReport, Renderer, Visit};
+
+/// Renders a document as Markdown to the given output.
+///
+/// Headings become ATX headings, `\emph` and `\bold` become `*` and `**` emphasis, `\code` becomes
+/// a code span, links become `[text](url)`, images become `![alt](path)`, verbatim blocks become
+/// fenced code blocks, lists become `-` or `1.` items, `description` lists become terms each
+/// followed by a `:` definition, `quote` environments become `>` block quotes, tables become pipe
+/// tables followed by any caption as a paragraph, horizontal rules become `---`, and
+/// `\printindex` becomes a `-` list of the entries of the index under a bold line for each
+/// letter. A `\break` ends its line with a backslash, or becomes `<br>` in a table cell.
+/// Characters of the text which would otherwise be interpreted as Markdown are escaped. As with
+/// the plain-text renderer, abbreviations are expanded at their first use, and commands which
+/// produce no text are omitted.
+pub fn render<W: Write>(document: &Document, out: &mut W) -> Result<RenderReport, Error> {
+    let options = PlainOptions::default().quote_prefix("> ");
+    let mut writer = MarkdownWriter {
+        plain: PlainWriter::new(document, &options, out),
+        rows: Vec::new(),
+    };
+    render_document(document, &mut writer)?;
+    plain::write_footnotes(&mut writer, |writer| &mut writer.plain, None, |number| {
+        format!("[^{}]: ", number)
+    })?;
+    Ok(writer.plain.report)
+}
+
+/// Renders a document as Markdown to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    out: &mut F,
+) -> Result<RenderReport, Error> {
+    render(document, &mut FmtWriter::new(out))
+}
+
+/// Renders a document as Markdown, returning the result as a `String`. Any warnings
+/// are discarded.
+pub fn render_to_string(document: &Document) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, &mut out)?;
+    Ok(out)
+}
+
+/// Escapes the characters of `s` which may be interpreted as Markdown inline syntax.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if let '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '&' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes the start of a line of text which would otherwise begin a block, such as a heading,
+/// list item, or block quote.
+fn escape_line_start(line: &str) -> String {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let split = match line[digits..].chars().next() {
+        Some('.') | Some(')') if digits > 0 => digits,
+        Some('#') | Some('>') | Some('-') | Some('+') | Some('=') | Some('~') | Some(':')
+            if digits == 0 =>
+        {
+            0
+        }
+        _ => return line.to_owned(),
+    };
+    format!("{}\\{}", &line[..split], &line[split..])
+}
+
+/// Returns `path` as the destination of a link or image, enclosed in `<>` if it contains
+/// characters which would otherwise end it early.
+fn link_destination(path: &str) -> String {
+    if !path.contains(|c: char| c.is_whitespace() || "()<>".contains(c)) {
+        return path.to_owned();
+    }
+    let mut escaped = String::from("<");
+    for c in path.chars() {
+        if let '\\' | '<' | '>' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('>');
+    escaped
+}
+
+/// Returns the length of the longest run of backticks in `s`.
+fn longest_backtick_run(s: &str) -> usize {
+    s.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// Returns `content` as a code span, delimited by enough backticks that it cannot be closed
+/// early.
+fn code_span(content: &str) -> String {
+    let content = content.replace('\n', " ");
+    let fence = "`".repeat(longest_backtick_run(&content) + 1);
+    let pad = content.starts_with('`')
+        || content.ends_with('`')
+        || (content.starts_with(' ') && content.ends_with(' ') && !content.trim().is_empty());
+    let pad = if pad { " " } else { "" };
+    format!("{}{}{}{}{}", fence, pad, content, pad, fence)
+}
+
+/// Returns the ATX heading prefix for a heading command, or `None` if the command is not a
+/// heading.
+fn heading_prefix(name: &str) -> Option<&'static str> {
+    match outline::unstarred(name) {
+        "section" => Some("# "),
+        "subsection" => Some("## "),
+        _ => None,
+    }
+}
+
+/// Returns the emphasis delimiter for an inline formatting command.
+fn emphasis(name: &str) -> Option<&'static str> {
+    match name {
+        "emph" => Some("*"),
+        "bold" => Some("**"),
+        _ => None,
+    }
+}
+
+/// Returns whether a command aligns its content, which Markdown cannot express, so that it is
+/// rendered as an ordinary paragraph.
+fn is_alignment(name: &str) -> bool {
+    name == "centered" || name == "rightalign"
+}
+
+/// Returns a row of a pipe table, with empty cells added to make up the given number of columns.
+fn table_row(cells: &[String], count: usize) -> String {
+    let mut row = String::from("|");
+    for column in 0..count {
+        row.push(' ');
+        row.push_str(cells.get(column).map_or("", |cell| &**cell));
+        row.push_str(" |");
+    }
+    row
+}
+
+/// The `Renderer` implementation backing `render`, which lays out its blocks and lists with a
+/// `PlainWriter`.
+struct MarkdownWriter<'a, W: 'a> {
+    plain: PlainWriter<'a, W>,
+    /// The cells of the rows of the current table.
+    rows: Vec<Vec<String>>,
+}
+
+impl<'a, W: Write> MarkdownWriter<'a, W> {
+    /// Writes the text of the current paragraph which has not been written, if any, as a
+    /// paragraph, as a block nested within it is about to start.
+    fn end_pending_paragraph(&mut self) -> Result<(), Error> {
+        if self.plain.has_pending_text() {
+            self.end_paragraph()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the caption of a table, if it has one, as a paragraph after it, since pipe tables
+    /// have no captions.
+    fn table_caption(&mut self, table: &Table) -> Result<(), Error> {
+        if table.caption.is_empty() {
+            return Ok(());
+        }
+        self.begin_block()?;
+        let caption = Document {
+            nodes: table.caption.clone(),
+        };
+        render_document(&caption, self)?;
+        self.end_paragraph()
+    }
+
+    /// Starts a new block, as `PlainWriter::begin_block` does, but with the text before it
+    /// written as Markdown.
+    fn begin_block(&mut self) -> Result<(), Error> {
+        self.end_pending_paragraph()?;
+        self.plain.begin_block()
+    }
+}
+
+impl<'a, W: Write> Renderer for MarkdownWriter<'a, W> {
+    fn is_cancelled(&self) -> bool {
+        self.plain.is_cancelled()
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        self.plain.text(&escape(text))
+    }
+
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        let content = &verbatim.content;
+        let fence = "`".repeat(3.max(longest_backtick_run(content) + 1));
+        let language = verbatim.language.as_deref().unwrap_or_default();
+        self.begin_block()?;
+        self.plain.write_line(&format!("{}{}", fence, language))?;
+        for line in content.lines() {
+            self.plain.write_line(line)?;
+        }
+        self.plain.write_line(&fence)
+    }
+
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        self.plain.raw(content)
+    }
+
+    fn begin_paragraph(&mut self) -> Result<(), Error> {
+        self.begin_block()
+    }
+
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        // Each line but the last ends with a backslash, which is a hard line break.
+        let lines = self.plain.take_text_lines();
+        let last = lines.len() - 1;
+        for (i, line) in lines.iter().enumerate() {
+            let hard_break = if i < last { "\\" } else { "" };
+            self.plain.write_line(&format!("{}{}", escape_line_start(line), hard_break))?;
+        }
+        Ok(())
+    }
+
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        if let Some(delimiter) = emphasis(&command.name) {
+            self.plain.push_markup(delimiter);
+            return Ok(Visit::Children);
+        }
+        if heading_prefix(&command.name).is_some() {
+            self.plain.abbreviations.heading(command);
+            self.begin_block()?;
+            return Ok(Visit::Children);
+        }
+        if is_alignment(&command.name) {
+            self.begin_paragraph()?;
+            return Ok(Visit::Children);
+        }
+        if is_horizontal_rule(&command.name) {
+            self.begin_block()?;
+            self.plain.write_line("---")?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "footnote" {
+            let number = self.plain.add_footnote(command);
+            self.plain.raw(&format!("[^{}]", number))?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "link" {
+            self.plain.push_markup("[");
+            if !has_link_text(command) {
+                self.text(&link_address(command))?;
+            }
+            return Ok(Visit::Children);
+        }
+        if command.name == "image" {
+            let image = Image::new(command, &mut self.plain.report);
+            let alt = escape(image.alt.as_deref().unwrap_or_default());
+            let image = format!("![{}]({})", alt, link_destination(&image.path));
+            self.plain.raw(&image)?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "printindex" {
+            self.plain.write_index(
+                |letter| format!("**{}**", escape(letter)),
+                |entry, depth| format!("{}- {}", "  ".repeat(depth), escape(&entry.text())),
+            )?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "abbr" {
+            let abbreviation = self
+                .plain
+                .abbreviations
+                .use_abbreviation(command, &mut self.plain.report);
+            self.text(&abbreviation.text())?;
+            return Ok(Visit::SkipChildren);
+        }
+        if command.name == "code" {
+            let content = command.args.first().map(|arg| ast::text_content(arg));
+            self.plain.raw(&code_span(&content.unwrap_or_default()))?;
+            return Ok(Visit::SkipChildren);
+        }
+        self.plain.begin_command(command)
+    }
+
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        if let Some(delimiter) = emphasis(&command.name) {
+            self.plain.push_markup(delimiter);
+            return Ok(());
+        }
+        if let Some(prefix) = heading_prefix(&command.name) {
+            let heading = format!("{}{}", prefix, self.plain.take_text());
+            return self.plain.write_line(&heading);
+        }
+        if is_alignment(&command.name) {
+            return self.end_paragraph();
+        }
+        if command.name == "link" {
+            // Parentheses would end the destination early if they were unbalanced.
+            let url = link_url(command).replace('(', "%28").replace(')', "%29");
+            self.plain.push_markup(&format!("]({})", url));
+            return Ok(());
+        }
+        if let "code" | "footnote" | "image" = &*command.name {
+            return Ok(());
+        }
+        self.plain.end_command(command)
+    }
+
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        if let ("bibitem", 1) = (&*command.name, index) {
+            self.begin_paragraph()?;
+            return Ok(Visit::Children);
+        }
+        self.plain.begin_argument(command, index)
+    }
+
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        if let ("bibitem", 1) = (&*command.name, index) {
+            return self.end_paragraph();
+        }
+        // The term of a description item is a line of its own, and the item's content is its
+        // definition. A term may not be empty, so a missing one is a non-breaking space.
+        if item_term(command).is_some() && index == 0 {
+            let term = self.plain.take_text();
+            let term = if term.is_empty() {
+                "&nbsp;".to_owned()
+            } else {
+                escape_line_start(&term)
+            };
+            self.plain.write_line(&term)?;
+            self.plain.end_term(":   ".to_owned(), 4);
+            return Ok(());
+        }
+        self.plain.end_argument(command, index)
+    }
+
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        // Indented text would become a code block, so the content of an `indent` environment is
+        // rendered as it is. Markdown has no columns, so neither is that of a `columns`
+        // environment laid out.
+        if let "indent" | "columns" = &*env.name {
+            return Ok(Visit::Children);
+        }
+        self.end_pending_paragraph()?;
+        self.plain.begin_environment(env)
+    }
+
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        if let "indent" | "columns" = &*env.name {
+            return Ok(());
+        }
+        if env.name == "quote" {
+            if let Some(attribution) = quote_attribution(env) {
+                self.begin_paragraph()?;
+                self.text(&attribution)?;
+                self.end_paragraph()?;
+            }
+            self.plain.pop_level();
+            return Ok(());
+        }
+        self.plain.end_environment(env)
+    }
+
+    fn begin_table(&mut self, _table: &Table) -> Result<Visit, Error> {
+        self.begin_block()?;
+        self.rows.clear();
+        Ok(Visit::Children)
+    }
+
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        let count = table.column_count();
+        if count == 0 {
+            return self.table_caption(table);
+        }
+        let mut rows = self.rows.split_off(0).into_iter();
+        // A pipe table must have a header row, so an empty one is added if there is none.
+        let header = if table.header {
+            rows.next().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        self.plain.write_line(&table_row(&header, count))?;
+        let delimiters: Vec<_> = (0..count)
+            .map(|column| match table.alignment(column) {
+                Alignment::Left => "---",
+                Alignment::Center => ":-:",
+                Alignment::Right => "--:",
+            })
+            .collect();
+        self.plain.write_line(&format!("| {} |", delimiters.join(" | ")))?;
+        for row in rows {
+            self.plain.write_line(&table_row(&row, count))?;
+        }
+        self.table_caption(table)
+    }
+
+    fn begin_row(&mut self, _table: &Table, _row: usize) -> Result<(), Error> {
+        self.rows.push(Vec::new());
+        Ok(())
+    }
+
+    fn begin_cell(&mut self, _table: &Table, _row: usize, _column: usize) -> Result<(), Error> {
+        self.plain.take_text();
+        Ok(())
+    }
+
+    fn end_cell(&mut self, _table: &Table, _row: usize, _column: usize) -> Result<(), Error> {
+        // A cell must be on one line, so its line breaks are written as HTML.
+        let cell = self.plain.take_text_lines().join("<br>").replace('|', "\\|");
+        if let Some(row) = self.rows.last_mut() {
+            row.push(cell);
+        }
+        Ok(())
+    }
+}
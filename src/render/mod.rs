@@ -0,0 +1,782 @@
+//! Renderers, which convert a parsed `Document` into an output format.
+//!
+//! Output formats implement the `Renderer` trait, whose methods are called by `render_document`
+//! as it walks the document tree in order. The `render` function of each format returns a
+//! `RenderReport` of the non-fatal problems it encountered, and fails only if no output can be
+//! produced.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::slice;
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use ast::{self, Command, Document, Environment, Node, Span, Table, Verbatim};
+use diagnostics::Diagnostic;
+use errors::{Error, ErrorKind, Position, Warning};
+use limits::Limit;
+
+pub mod abbreviations;
+pub mod ansi;
+#[cfg(feature = "bidi")]
+mod bidi;
+pub mod html;
+#[cfg(feature = "hyphenation")]
+pub mod hyphenation;
+pub mod index;
+pub mod latex;
+pub mod markdown;
+pub mod outline;
+#[cfg(feature = "serde")]
+pub mod pandoc;
+pub mod plain;
+pub mod roff;
+pub mod sanitize;
+pub mod source;
+mod terminal;
+
+/// An adapter which allows the renderers, which write to an `io::Write`, to write directly to a
+/// `fmt::Write`, such as a `String`.
+///
+/// Renderers only ever write complete strings, so each write is passed on to the `fmt::Write` as
+/// it is; a write which is not valid UTF-8 fails.
+#[derive(Debug)]
+pub struct FmtWriter<'a, F: ?Sized + 'a> {
+    inner: &'a mut F,
+}
+
+impl<'a, F: fmt::Write + ?Sized> FmtWriter<'a, F> {
+    /// Constructs a new `FmtWriter` writing to the given `fmt::Write`.
+    pub fn new(inner: &'a mut F) -> FmtWriter<'a, F> {
+        FmtWriter { inner }
+    }
+}
+
+impl<'a, F: fmt::Write + ?Sized> io::Write for FmtWriter<'a, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.inner.write_str(s).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An adapter which fails once more than a given number of bytes would be written through it, so
+/// that the output of a renderer is bounded, as `convert` does with `Limits::max_output_size`.
+///
+/// The write which would pass the limit fails, and nothing of it is written, so that the output
+/// is never cut within a character; every write after it fails too. A renderer whose write fails
+/// returns `ErrorKind::Write`, for which `check` substitutes the `ErrorKind::LimitExceeded` that
+/// caused it.
+#[derive(Debug)]
+pub struct LimitWriter<W> {
+    inner: W,
+    limit: usize,
+    /// The position in the output of the next byte to be written.
+    position: Position,
+    exceeded: bool,
+}
+
+impl<W: io::Write> LimitWriter<W> {
+    /// Constructs a new `LimitWriter` which writes at most `limit` bytes to `inner`.
+    pub fn new(inner: W, limit: usize) -> LimitWriter<W> {
+        LimitWriter {
+            inner,
+            limit,
+            position: Position {
+                line: 1,
+                column: 1,
+                offset: 0,
+                utf16_column: 1,
+            },
+            exceeded: false,
+        }
+    }
+
+    /// Returns the result of rendering through this writer, unless a write failed because it
+    /// would have passed the limit, in which case returns an `ErrorKind::LimitExceeded` for
+    /// `Limit::Output`, whose position is that of the first byte which was not written.
+    pub fn check<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        if !self.exceeded {
+            return result;
+        }
+        Err(ErrorKind::LimitExceeded {
+            what: Limit::Output,
+            limit: self.limit,
+            position: self.position,
+        }
+        .into())
+    }
+
+    /// Returns the writer to which the output is written.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Advances the position past the given bytes of output, counting columns in characters
+    /// whether or not a character is split between writes.
+    fn advance(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            if byte == b'\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+                self.position.utf16_column = 1;
+            } else if byte & 0xc0 != 0x80 {
+                self.position.column += 1;
+                self.position.utf16_column += if byte >= 0xf0 { 2 } else { 1 };
+            }
+        }
+        self.position.offset += buf.len();
+    }
+}
+
+impl<W: io::Write> io::Write for LimitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let available = self.limit - self.position.offset;
+        if self.exceeded || buf.len() > available {
+            if !self.exceeded {
+                self.advance(&buf[..available]);
+                self.exceeded = true;
+            }
+            return Err(io::Error::other("the output exceeds its limit"));
+        }
+        let written = self.inner.write(buf)?;
+        self.advance(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An adapter which counts the bytes written through it, so that the output written for each node
+/// can be recorded in a `SourceMap`.
+#[derive(Debug)]
+struct CountingWriter<'a, W: ?Sized + 'a> {
+    inner: &'a mut W,
+    count: &'a Cell<usize>,
+}
+
+impl<'a, W: io::Write + ?Sized> io::Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count.set(self.count.get() + written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A map from byte ranges of rendered output back to the spans of input they were rendered from.
+///
+/// Entries are recorded for each top-level node, so that the output of everything within a
+/// paragraph, list, or table maps to the span of the whole block. Output which was not rendered
+/// from a node, such as the head of an HTML document or a list of footnotes, is not mapped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceMap {
+    entries: Vec<(Range<usize>, Span)>,
+}
+
+impl SourceMap {
+    /// Constructs a new, empty `SourceMap`.
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Returns the entries, in the order of their output ranges, which do not overlap.
+    pub fn entries(&self) -> &[(Range<usize>, Span)] {
+        &self.entries
+    }
+
+    /// Adds an entry, unless the output range is empty. Entries must be recorded in order.
+    pub fn record(&mut self, output: Range<usize>, input: Span) {
+        if output.start < output.end {
+            self.entries.push((output, input));
+        }
+    }
+
+    /// Returns the span of input from which the output at the given byte offset was rendered, or
+    /// `None` if that output is not mapped.
+    pub fn input_for(&self, output_offset: usize) -> Option<Span> {
+        let index = self
+            .entries
+            .partition_point(|(output, _)| output.end <= output_offset);
+        match self.entries.get(index) {
+            Some((output, span)) if output.start <= output_offset => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+/// The non-fatal problems encountered while rendering a document, such as references to labels
+/// which are not defined.
+///
+/// When the renderer is given the span of each top-level node, as by `HtmlRenderer::render_mapped`
+/// or `plain::render_mapped`, each warning has the span of the node being rendered when it was
+/// found, from which `diagnostics` gives its position.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RenderReport {
+    warnings: Vec<Warning>,
+    /// The span of the top-level node being rendered when each warning was added, if known.
+    spans: Vec<Option<Span>>,
+    /// The span of the top-level node being rendered, if known.
+    span: Option<Span>,
+}
+
+impl RenderReport {
+    /// Constructs a new, empty `RenderReport`.
+    pub fn new() -> RenderReport {
+        RenderReport::default()
+    }
+
+    /// Returns the warnings, in the order in which they were encountered.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns whether there are no warnings.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Returns the span of the top-level node being rendered when each warning was added, in the
+    /// same order as `warnings`, or `None` for those added outside of any node, or if the spans
+    /// of the nodes were not given.
+    pub fn spans(&self) -> &[Option<Span>] {
+        &self.spans
+    }
+
+    /// Sets the span of the top-level node being rendered, which is given with the warnings added
+    /// until it is next set.
+    pub fn set_span(&mut self, span: Option<Span>) {
+        self.span = span;
+    }
+
+    /// Adds a warning to the report, with the span of the top-level node being rendered.
+    pub fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+        self.spans.push(self.span);
+    }
+
+    /// Returns a `Diagnostic` for each warning, given the input from which the document was
+    /// parsed. A warning with a span is positioned at the first character of its span which is
+    /// not whitespace, unless it has a position of its own.
+    pub fn diagnostics(&self, input: &str) -> Vec<Diagnostic> {
+        let warnings = self.warnings.iter().zip(&self.spans);
+        warnings
+            .map(|(warning, span)| {
+                let mut diagnostic = Diagnostic::warning(warning.clone());
+                if diagnostic.position.is_none() {
+                    diagnostic.position = span.and_then(|span| span_start(input, span));
+                }
+                diagnostic
+            })
+            .collect()
+    }
+}
+
+/// Returns the position of the first character of a span of the input which is not whitespace,
+/// or of its start if it is all whitespace, or `None` if the span is not within the input.
+fn span_start(input: &str, span: Span) -> Option<Position> {
+    let spanned = input.get(span.start..span.end.min(input.len()))?;
+    let start = span.start + (spanned.len() - spanned.trim_start().len());
+    Some(Position::at(input, start))
+}
+
+/// The maximum nesting depth used by `render_document`.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Whether `render_document` should descend into the children of a node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Visit {
+    /// Render the children of the node.
+    Children,
+    /// Skip the children of the node. The corresponding `end_*` method is still called.
+    SkipChildren,
+}
+
+/// A set of callbacks for producing output from a document.
+///
+/// All methods other than `text` and `verbatim` have default implementations which do nothing,
+/// so that a renderer need only handle the constructs it cares about.
+pub trait Renderer {
+    /// Returns whether rendering should stop with an `ErrorKind::Cancelled` error, which is
+    /// checked before each node is rendered. By default, rendering is never cancelled.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+
+    /// Called before each top-level node is rendered, when the output is mapped to the input, with
+    /// the span of the input from which the node was parsed.
+    fn begin_block(&mut self, _span: Span) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Renders a run of text.
+    fn text(&mut self, text: &str) -> Result<(), Error>;
+
+    /// Renders the contents of a `\verbatim` command.
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error>;
+
+    /// Renders the contents of a `\raw` command. By default, raw content is omitted, since it is
+    /// generally specific to one output format.
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        let _ = content;
+        Ok(())
+    }
+
+    /// Called at the start of a paragraph.
+    fn begin_paragraph(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called at the end of a paragraph.
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns whether a paragraph with the given nodes is rendered with `begin_paragraph` and
+    /// `end_paragraph` around its nodes, rather than as its nodes alone, such as when a renderer
+    /// writes them as a block of another kind. By default, every paragraph is.
+    fn wraps_paragraph(&self, nodes: &[Node]) -> bool {
+        let _ = nodes;
+        true
+    }
+
+    /// Called after the nodes of a paragraph which is not wrapped, as decided by
+    /// `wraps_paragraph`.
+    fn end_unwrapped_paragraph(&mut self, nodes: &[Node]) -> Result<(), Error> {
+        let _ = nodes;
+        Ok(())
+    }
+
+    /// Called at the start of a group.
+    fn begin_group(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called at the end of a group.
+    fn end_group(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called before the arguments of a command are rendered.
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        let _ = command;
+        Ok(Visit::Children)
+    }
+
+    /// Called after the arguments of a command are rendered.
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        let _ = command;
+        Ok(())
+    }
+
+    /// Called before each argument of a command is rendered. Returning `Visit::SkipChildren`
+    /// omits the argument, such as one which names a setting rather than holding content.
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        let _ = (command, index);
+        Ok(Visit::Children)
+    }
+
+    /// Called after each argument of a command is rendered.
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        let _ = (command, index);
+        Ok(())
+    }
+
+    /// Called before the content of an environment is rendered.
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        let _ = env;
+        Ok(Visit::Children)
+    }
+
+    /// Called after the content of an environment is rendered.
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        let _ = env;
+        Ok(())
+    }
+
+    /// Called before the rows of a table are rendered.
+    fn begin_table(&mut self, table: &Table) -> Result<Visit, Error> {
+        let _ = table;
+        Ok(Visit::Children)
+    }
+
+    /// Called after the rows of a table are rendered.
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        let _ = table;
+        Ok(())
+    }
+
+    /// Called before each row of a table is rendered.
+    fn begin_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        let _ = (table, row);
+        Ok(())
+    }
+
+    /// Called after each row of a table is rendered.
+    fn end_row(&mut self, table: &Table, row: usize) -> Result<(), Error> {
+        let _ = (table, row);
+        Ok(())
+    }
+
+    /// Called before each cell of a table is rendered.
+    fn begin_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        let _ = (table, row, column);
+        Ok(())
+    }
+
+    /// Called after each cell of a table is rendered.
+    fn end_cell(&mut self, table: &Table, row: usize, column: usize) -> Result<(), Error> {
+        let _ = (table, row, column);
+        Ok(())
+    }
+}
+
+/// Returns the text of the argument of the first top-level command with the given name, or an
+/// empty string if there is none.
+pub fn metadata(document: &Document, name: &str) -> String {
+    document
+        .nodes
+        .iter()
+        .filter_map(|node| match *node {
+            Node::Command(ref command) if command.name == name => command.args.first(),
+            _ => None,
+        })
+        .map(|arg| ast::text_content(arg).trim().to_owned())
+        .next()
+        .unwrap_or_default()
+}
+
+/// Returns whether the cancellation token given in the options of a renderer, if any, is set.
+fn is_set(token: Option<&Arc<AtomicBool>>) -> bool {
+    token.is_some_and(|token| token.load(Ordering::Relaxed))
+}
+
+/// Returns whether a command is a horizontal rule, `\hrule` or `\rule`.
+pub fn is_horizontal_rule(name: &str) -> bool {
+    name == "hrule" || name == "rule"
+}
+
+/// The appearance of a horizontal rule, given by the optional argument of `\hrule` or `\rule`.
+///
+/// The argument holds a character with which the text renderers draw the rule, such as
+/// `\hrule{=}`, a width relative to the available width, either as a fraction or a percentage,
+/// such as `\hrule{0.5}` or `\hrule{50%}`, or both, separated by whitespace.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RuleStyle {
+    /// The character with which the rule is drawn, if one is given.
+    pub character: Option<char>,
+    /// The width of the rule, as a fraction of the available width between 0 and 1, if one is
+    /// given.
+    pub width: Option<f64>,
+}
+
+impl RuleStyle {
+    /// Returns the appearance of the rule given by a `\hrule` or `\rule` command. Parts of the
+    /// argument which are neither a character nor a width are ignored.
+    pub fn new(command: &Command) -> RuleStyle {
+        let mut style = RuleStyle::default();
+        let argument = command
+            .args
+            .first()
+            .map(|arg| ast::text_content(arg))
+            .unwrap_or_default();
+        for word in argument.split_whitespace() {
+            let width = match word.strip_suffix('%') {
+                Some(percentage) => percentage.parse::<f64>().ok().map(|p| p / 100.0),
+                None => word.parse::<f64>().ok(),
+            };
+            if let Some(width) = width.filter(|&width| width > 0.0) {
+                style.width = Some(width.min(1.0));
+                continue;
+            }
+            let mut chars = word.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                style.character = Some(c);
+            }
+        }
+        style
+    }
+
+    /// Returns the number of columns taken by the rule, out of the given available width, and
+    /// at least 1.
+    pub fn columns(&self, available: usize) -> usize {
+        match self.width {
+            Some(width) => ((available as f64 * width).round() as usize).clamp(1, available.max(1)),
+            None => available.max(1),
+        }
+    }
+}
+
+/// The direction in which the text of paragraphs runs, for text which mixes right-to-left scripts,
+/// such as Hebrew and Arabic, with left-to-right ones.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// The direction of each paragraph is that of its first strongly directional character, as
+    /// in the Unicode bidirectional algorithm, or left to right if it has none.
+    Auto,
+    /// Every paragraph runs left to right.
+    LeftToRight,
+    /// Every paragraph runs right to left.
+    RightToLeft,
+}
+
+/// An image, given by an `\image` command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Image {
+    /// The path or URL of the image.
+    pub path: String,
+    /// The alternative text describing the image, if it is given. Empty alternative text marks
+    /// the image as decorative.
+    pub alt: Option<String>,
+    /// The width of the image, if it is given.
+    pub width: Option<String>,
+    /// The height of the image, if it is given.
+    pub height: Option<String>,
+    /// The caption of the image, if it is given.
+    pub caption: Option<String>,
+}
+
+impl Image {
+    /// Returns the image given by an `\image` command, whose options are a comma-separated list
+    /// of `key=value` pairs, such as `\image[alt=Logo, width=200]{logo.png}`. A value containing
+    /// a comma may be enclosed in a group. The keys `alt`, `width`, `height`, and `caption` are
+    /// recognized, and any others are ignored. If the image has no alternative text, a warning is
+    /// added to the report.
+    pub fn new(command: &Command, report: &mut RenderReport) -> Image {
+        let image = Image::parse(command);
+        if image.alt.is_none() {
+            report.warn(Warning::MissingAltText(image.path.clone()));
+        }
+        image
+    }
+
+    /// Returns the image given by an `\image` command, as with `new`, without checking it.
+    pub(crate) fn parse(command: &Command) -> Image {
+        let mut image = Image {
+            path: command
+                .args
+                .get(1)
+                .map(|arg| ast::text_content(arg).trim().to_owned())
+                .unwrap_or_default(),
+            ..Image::default()
+        };
+        let options = command.args.first().map_or(&[][..], |arg| &arg[..]);
+        for (key, value) in ast::key_values(options) {
+            let value = Some(value);
+            match &*key {
+                "alt" => image.alt = value,
+                "width" => image.width = value,
+                "height" => image.height = value,
+                "caption" => image.caption = value,
+                _ => {}
+            }
+        }
+        image
+    }
+
+    /// Returns whether the path of the image is a URL with a scheme, rather than a path to a
+    /// local file.
+    pub fn is_url(&self) -> bool {
+        self.path.contains("://") || self.path.starts_with("data:")
+    }
+}
+
+/// Returns the URL of a `\link` command as it is written, which is its first argument.
+pub fn link_address(command: &Command) -> String {
+    command
+        .args
+        .first()
+        .map(|arg| ast::text_content(arg).trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the URL of a `\link` command, percent-encoded with `encode_url`.
+pub fn link_url(command: &Command) -> String {
+    encode_url(&link_address(command))
+}
+
+/// Returns whether a `\link` command has text of its own, given by its second argument, rather
+/// than showing its URL.
+pub fn has_link_text(command: &Command) -> bool {
+    command.args.len() > 1
+}
+
+/// Returns the term of an `\item` of a `description` list, which is its first argument, or `None`
+/// for any other command. The item's content is its second argument.
+pub fn item_term(command: &Command) -> Option<&[Node]> {
+    match command.args.len() {
+        2 if command.name == "item" => Some(&command.args[0]),
+        _ => None,
+    }
+}
+
+/// Returns the line attributing a `quote` environment, which is written after its content: the
+/// text of its optional argument, after an em dash. Returns `None` if the argument is missing or
+/// empty.
+pub fn quote_attribution(env: &Environment) -> Option<String> {
+    let text = ast::text_content(env.args.first()?);
+    let words: Vec<_> = text.split_whitespace().collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(format!("\u{2014} {}", words.join(" ")))
+    }
+}
+
+/// Percent-encodes the characters of a URL which may not appear in a URL as they are: spaces and
+/// other whitespace, control characters, non-ASCII characters, which are encoded as their UTF-8
+/// bytes, and the characters `"<>\^`{|}`. Any `%` is assumed to already start an encoded byte,
+/// and is left as it is.
+pub fn encode_url(url: &str) -> String {
+    let mut encoded = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_ascii_graphic() && !"\"<>\\^`{|}".contains(c) {
+            encoded.push(c);
+        } else {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+/// Renders a document by walking its tree and calling the methods of `renderer`, with a maximum
+/// nesting depth of `DEFAULT_MAX_DEPTH`.
+pub fn render_document<R: Renderer + ?Sized>(
+    document: &Document,
+    renderer: &mut R,
+) -> Result<(), Error> {
+    render_document_with_depth(document, renderer, DEFAULT_MAX_DEPTH)
+}
+
+/// Renders a document as with `render_document`, failing with `ErrorKind::NestingTooDeep` if
+/// nodes are nested more than `max_depth` levels deep.
+pub fn render_document_with_depth<R: Renderer + ?Sized>(
+    document: &Document,
+    renderer: &mut R,
+    max_depth: usize,
+) -> Result<(), Error> {
+    Walker {
+        renderer,
+        max_depth,
+    }
+    .nodes(&document.nodes, 0)
+}
+
+/// Renders a document as with `render_document`, recording in a `SourceMap` the output written for
+/// each top-level node, as counted by `count`, against the corresponding span in `spans`.
+fn render_document_mapped<R: Renderer + ?Sized>(
+    document: &Document,
+    spans: &[Span],
+    renderer: &mut R,
+    count: &Cell<usize>,
+) -> Result<SourceMap, Error> {
+    let mut walker = Walker {
+        renderer,
+        max_depth: DEFAULT_MAX_DEPTH,
+    };
+    let mut map = SourceMap::new();
+    for (i, node) in document.nodes.iter().enumerate() {
+        let start = count.get();
+        if let Some(&span) = spans.get(i) {
+            walker.renderer.begin_block(span)?;
+        }
+        walker.nodes(slice::from_ref(node), 0)?;
+        if let Some(&span) = spans.get(i) {
+            map.record(start..count.get(), span);
+        }
+    }
+    Ok(map)
+}
+
+/// The state of a walk over a document tree.
+struct Walker<'a, R: ?Sized + 'a> {
+    renderer: &'a mut R,
+    max_depth: usize,
+}
+
+impl<'a, R: Renderer + ?Sized> Walker<'a, R> {
+    fn nodes(&mut self, nodes: &[Node], depth: usize) -> Result<(), Error> {
+        if depth > self.max_depth {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        for node in nodes {
+            self.node(node, depth)?;
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node, depth: usize) -> Result<(), Error> {
+        if self.renderer.is_cancelled() {
+            return Err(ErrorKind::Cancelled.into());
+        }
+        match *node {
+            Node::Text(ref text) => self.renderer.text(text),
+            Node::Paragraph(ref nodes) if !self.renderer.wraps_paragraph(nodes) => {
+                self.nodes(nodes, depth + 1)?;
+                self.renderer.end_unwrapped_paragraph(nodes)
+            }
+            Node::Paragraph(ref nodes) => {
+                self.renderer.begin_paragraph()?;
+                self.nodes(nodes, depth + 1)?;
+                self.renderer.end_paragraph()
+            }
+            Node::Group(ref nodes) => {
+                self.renderer.begin_group()?;
+                self.nodes(nodes, depth + 1)?;
+                self.renderer.end_group()
+            }
+            Node::Command(ref command) => {
+                if self.renderer.begin_command(command)? == Visit::Children {
+                    for (index, arg) in command.args.iter().enumerate() {
+                        if self.renderer.begin_argument(command, index)? == Visit::Children {
+                            self.nodes(arg, depth + 1)?;
+                        }
+                        self.renderer.end_argument(command, index)?;
+                    }
+                }
+                self.renderer.end_command(command)
+            }
+            Node::Environment(ref env) => {
+                if self.renderer.begin_environment(env)? == Visit::Children {
+                    self.nodes(&env.nodes, depth + 1)?;
+                }
+                self.renderer.end_environment(env)
+            }
+            Node::Table(ref table) => {
+                if self.renderer.begin_table(table)? == Visit::Children {
+                    self.table(table, depth)?;
+                }
+                self.renderer.end_table(table)
+            }
+            Node::Verbatim(ref verbatim) => self.renderer.verbatim(verbatim),
+            Node::Raw(ref content) => self.renderer.raw(content),
+        }
+    }
+    fn table(&mut self, table: &Table, depth: usize) -> Result<(), Error> {
+        for (row, cells) in table.rows.iter().enumerate() {
+            self.renderer.begin_row(table, row)?;
+            for (column, cell) in cells.iter().enumerate() {
+                self.renderer.begin_cell(table, row, column)?;
+                self.nodes(cell, depth + 1)?;
+                self.renderer.end_cell(table, row, column)?;
+            }
+            self.renderer.end_row(table, row)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+//! The outline of a document, formed by its headings.
+
+use std::collections::{HashMap, HashSet};
+
+use ast::{self, Command, Node};
+use errors::Warning;
+use super::RenderReport;
+
+/// A heading of a document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Heading {
+    /// The level of the heading, which is 1 for `\section` and 2 for `\subsection`.
+    pub level: usize,
+    /// The number of the heading, with one component for each level, such as `[2, 3]` for the
+    /// third subsection of the second section, which is empty for a starred heading.
+    pub number: Vec<usize>,
+    /// The number of the heading as formatted by the numbering scheme, or `None` if the heading
+    /// is unnumbered, because it is starred or deeper than the scheme numbers.
+    pub formatted_number: Option<String>,
+    /// The text of the heading.
+    pub title: String,
+    /// The unique identifier of the heading, for use as an anchor.
+    pub id: String,
+    /// The label given to the heading with `\label`, if any.
+    pub label: Option<String>,
+}
+
+impl Heading {
+    /// Returns the formatted number of the heading, or an empty string if it is unnumbered.
+    pub fn number_text(&self) -> String {
+        self.formatted_number.clone().unwrap_or_default()
+    }
+}
+
+/// Returns the level of a heading command, or `None` if the command is not a heading. The starred
+/// form of a heading, such as `\section*`, has the same level as the heading.
+pub fn heading_level(name: &str) -> Option<usize> {
+    match unstarred(name) {
+        "section" => Some(1),
+        "subsection" => Some(2),
+        _ => None,
+    }
+}
+
+/// Returns the name of a command without the `*` which marks its starred form, if any.
+pub fn unstarred(name: &str) -> &str {
+    name.strip_suffix('*').unwrap_or(name)
+}
+
+/// Returns whether a command is the starred form of a heading, which is unnumbered.
+pub fn is_starred(name: &str) -> bool {
+    name.ends_with('*')
+}
+
+/// The style in which a component of a heading number is written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NumberStyle {
+    /// `1`, `2`, `3`.
+    Arabic,
+    /// `a`, `b`, `c`, continuing with `aa` after `z`.
+    LowerAlpha,
+    /// `A`, `B`, `C`, continuing with `AA` after `Z`.
+    UpperAlpha,
+    /// `i`, `ii`, `iii`.
+    LowerRoman,
+    /// `I`, `II`, `III`.
+    UpperRoman,
+}
+
+impl NumberStyle {
+    /// Returns a number written in this style. Numbers which cannot be written in the style, such
+    /// as 0, or roman numerals above 3999, are written in arabic numerals.
+    pub fn format(self, number: usize) -> String {
+        match self {
+            NumberStyle::Arabic => number.to_string(),
+            _ if number == 0 => number.to_string(),
+            NumberStyle::LowerAlpha => alphabetic(number, b'a'),
+            NumberStyle::UpperAlpha => alphabetic(number, b'A'),
+            NumberStyle::LowerRoman => roman(number).unwrap_or_else(|| number.to_string()),
+            NumberStyle::UpperRoman => {
+                roman(number).map_or_else(|| number.to_string(), |r| r.to_uppercase())
+            }
+        }
+    }
+}
+
+/// Returns a positive number as letters, in bijective base 26, starting from `first`.
+fn alphabetic(mut number: usize, first: u8) -> String {
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push((first + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Returns a positive number below 4000 as lowercase roman numerals.
+fn roman(mut number: usize) -> Option<String> {
+    const NUMERALS: &[(usize, &str)] = &[
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    if number >= 4000 {
+        return None;
+    }
+    let mut numerals = String::new();
+    for &(value, numeral) in NUMERALS {
+        while number >= value {
+            numerals.push_str(numeral);
+            number -= value;
+        }
+    }
+    Some(numerals)
+}
+
+/// How the headings of a document are numbered.
+///
+/// Each level of heading has a `NumberStyle`; levels deeper than those given a style use the
+/// style of the deepest level which has one. The components of a number are joined by a
+/// separator, and headings deeper than a given depth are left unnumbered, although they are still
+/// counted. After an `\appendix` command, the top-level count starts again, and headings are
+/// numbered by the appendix scheme instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberingScheme {
+    styles: Vec<NumberStyle>,
+    separator: String,
+    depth: usize,
+    appendix: Option<Box<NumberingScheme>>,
+}
+
+impl Default for NumberingScheme {
+    fn default() -> NumberingScheme {
+        NumberingScheme {
+            styles: vec![NumberStyle::Arabic],
+            separator: ".".into(),
+            depth: usize::MAX,
+            appendix: Some(Box::new(NumberingScheme {
+                styles: vec![NumberStyle::UpperAlpha, NumberStyle::Arabic],
+                separator: ".".into(),
+                depth: usize::MAX,
+                appendix: None,
+            })),
+        }
+    }
+}
+
+impl NumberingScheme {
+    /// Constructs a new `NumberingScheme` with the default settings, which number headings such
+    /// as `1.2`, and appendices such as `A.2`.
+    pub fn new() -> NumberingScheme {
+        NumberingScheme::default()
+    }
+
+    /// Sets the style of each level of heading, starting from level 1. Defaults to
+    /// `NumberStyle::Arabic` for every level.
+    pub fn styles(mut self, styles: &[NumberStyle]) -> NumberingScheme {
+        if !styles.is_empty() {
+            self.styles = styles.to_vec();
+        }
+        self
+    }
+
+    /// Sets the style of the given level of heading, where `\section` is level 1, leaving the
+    /// style of any shallower levels without one as that of the deepest level which has one.
+    pub fn style(mut self, level: usize, style: NumberStyle) -> NumberingScheme {
+        if level > 0 {
+            let deepest = self.style_of(level);
+            self.styles.resize(level.max(self.styles.len()), deepest);
+            self.styles[level - 1] = style;
+        }
+        self
+    }
+
+    /// Sets the separator between the components of a number. Defaults to `.`.
+    pub fn separator<S: Into<String>>(mut self, separator: S) -> NumberingScheme {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Sets the deepest level of heading which is numbered, where `\section` is level 1. By
+    /// default, every level is numbered.
+    pub fn depth(mut self, depth: usize) -> NumberingScheme {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets the scheme by which headings are numbered after an `\appendix` command, or `None` to
+    /// keep numbering them by this scheme, with the top-level count starting again. Any appendix
+    /// scheme of `scheme` itself is ignored. Defaults to a scheme which numbers appendices with
+    /// uppercase letters, such as `A.2`.
+    pub fn appendix(mut self, scheme: Option<NumberingScheme>) -> NumberingScheme {
+        self.appendix = scheme.map(|scheme| {
+            Box::new(NumberingScheme {
+                appendix: None,
+                ..scheme
+            })
+        });
+        self
+    }
+
+    /// Returns the style of the given level of heading.
+    fn style_of(&self, level: usize) -> NumberStyle {
+        self.styles
+            .get(level.saturating_sub(1))
+            .or_else(|| self.styles.last())
+            .copied()
+            .unwrap_or(NumberStyle::Arabic)
+    }
+
+    /// Returns a heading number formatted by this scheme, or `None` if it is deeper than the
+    /// scheme numbers.
+    pub fn format(&self, number: &[usize]) -> Option<String> {
+        if number.is_empty() || number.len() > self.depth {
+            return None;
+        }
+        let components: Vec<_> = number
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| self.style_of(i + 1).format(n))
+            .collect();
+        Some(components.join(&self.separator))
+    }
+}
+
+/// Returns an identifier derived from a heading title, consisting of its alphanumeric characters
+/// in lowercase, with each run of other characters replaced by a `-`.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+    if slug.is_empty() {
+        slug.push_str("section");
+    }
+    slug
+}
+
+/// A set of identifiers which have been given to headings, used to keep them unique.
+#[derive(Clone, Debug, Default)]
+pub struct Anchors {
+    used: HashSet<String>,
+    /// The suffix from which to search for each identifier which has been asked for, below
+    /// which every suffix is used, so that asking for the same identifier repeatedly is not
+    /// quadratic.
+    next: HashMap<String, usize>,
+}
+
+impl Anchors {
+    /// Constructs a new, empty `Anchors`.
+    pub fn new() -> Anchors {
+        Anchors::default()
+    }
+
+    /// Returns `id` if it has not been used, or otherwise `id` followed by the first numeric
+    /// suffix, starting from `-2`, which has not been used, and marks the result as used.
+    pub fn unique(&mut self, id: &str) -> String {
+        let mut unique = id.to_owned();
+        if self.used.contains(&unique) {
+            let mut suffix = self.next.get(id).copied().unwrap_or(2);
+            loop {
+                unique = format!("{}-{}", id, suffix);
+                suffix += 1;
+                if !self.used.contains(&unique) {
+                    break;
+                }
+            }
+            self.next.insert(id.to_owned(), suffix);
+        }
+        self.used.insert(unique.clone());
+        unique
+    }
+}
+
+/// Returns the headings among the given nodes, numbered by the given scheme, as with
+/// `Outliner::headings`.
+pub fn headings(nodes: &[Node], numbering: &NumberingScheme) -> Vec<Heading> {
+    Outliner::new().numbering(numbering.clone()).headings(nodes)
+}
+
+/// Returns the heading with the given label, if there is one.
+pub fn find_heading<'a>(headings: &'a [Heading], label: &str) -> Option<&'a Heading> {
+    headings
+        .iter()
+        .find(|heading| heading.label.as_deref() == Some(label))
+}
+
+/// A builder of the outline of a document, which may be given the document in parts.
+#[derive(Clone, Debug, Default)]
+pub struct Outliner {
+    anchors: Anchors,
+    /// The number of the most recent heading.
+    number: Vec<usize>,
+    numbering: NumberingScheme,
+    /// Whether an `\appendix` command has been seen.
+    appendix: bool,
+}
+
+impl Outliner {
+    /// Constructs a new `Outliner`, for the start of a document.
+    pub fn new() -> Outliner {
+        Outliner::default()
+    }
+
+    /// Sets the scheme by which headings are numbered. Defaults to `NumberingScheme::new()`.
+    pub fn numbering(mut self, numbering: NumberingScheme) -> Outliner {
+        self.numbering = numbering;
+        self
+    }
+
+    /// Returns the headings among the given nodes, in the order in which they are rendered,
+    /// numbered and with unique identifiers following on from any headings previously given to
+    /// this `Outliner`.
+    ///
+    /// The identifier of a heading is given by a `\label` in its title or immediately following
+    /// it, if there is one, and is otherwise derived from its title by `slugify`. Starred
+    /// headings are unnumbered, and do not affect the numbers of other headings.
+    pub fn headings(&mut self, nodes: &[Node]) -> Vec<Heading> {
+        let mut headings = Vec::new();
+        self.push_headings(nodes, &mut headings);
+        headings
+    }
+
+    fn push_headings(&mut self, nodes: &[Node], headings: &mut Vec<Heading>) {
+        for (i, node) in nodes.iter().enumerate() {
+            match *node {
+                Node::Command(ref command) => {
+                    if command.name == "appendix" {
+                        self.appendix = true;
+                        self.number.clear();
+                    }
+                    if let Some(level) = heading_level(&command.name) {
+                        let title = command.args.first().map_or(&[][..], |arg| &arg[..]);
+                        let label =
+                            find_label(title).or_else(|| following_label(&nodes[i + 1..]));
+                        let id = match label {
+                            Some(ref label) => self.anchors.unique(label),
+                            None => self
+                                .anchors
+                                .unique(&slugify(ast::text_content(title).trim())),
+                        };
+                        let number = if is_starred(&command.name) {
+                            Vec::new()
+                        } else {
+                            self.number.resize(level, 0);
+                            self.number[level - 1] += 1;
+                            self.number.clone()
+                        };
+                        let scheme = match self.numbering.appendix {
+                            Some(ref appendix) if self.appendix => appendix,
+                            _ => &self.numbering,
+                        };
+                        headings.push(Heading {
+                            level,
+                            formatted_number: scheme.format(&number),
+                            number,
+                            title: title_text(title),
+                            id,
+                            label,
+                        });
+                    }
+                    for arg in &command.args {
+                        self.push_headings(arg, headings);
+                    }
+                }
+                Node::Paragraph(ref nodes) | Node::Group(ref nodes) => {
+                    self.push_headings(nodes, headings)
+                }
+                Node::Environment(ref env) => self.push_headings(&env.nodes, headings),
+                Node::Table(ref table) => {
+                    for cell in table.rows.iter().flatten() {
+                        self.push_headings(cell, headings);
+                    }
+                }
+                Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => {}
+            }
+        }
+    }
+}
+
+/// The text of a `\ref` command whose label does not belong to any heading.
+pub const UNRESOLVED_REFERENCE: &str = "[??]";
+
+/// Returns the text of a `\ref` command for formats without links: the number of the referenced
+/// heading, preceded by `§`, or its title if it is unnumbered, or `[??]` if there is no heading
+/// with the label, in which case a warning is added to the report.
+pub fn reference_text(
+    headings: &[Heading],
+    command: &Command,
+    report: &mut RenderReport,
+) -> String {
+    let label = reference_label(command);
+    match find_heading(headings, &label) {
+        Some(heading) => heading_reference(heading),
+        None => {
+            report.warn(Warning::UnresolvedReference(label));
+            UNRESOLVED_REFERENCE.to_owned()
+        }
+    }
+}
+
+/// Returns the text by which a heading is referred to in formats without links: its number,
+/// preceded by `§`, or its title if it is unnumbered.
+pub fn heading_reference(heading: &Heading) -> String {
+    match heading.formatted_number {
+        Some(ref number) => format!("\u{a7}{}", number),
+        None => heading.title.clone(),
+    }
+}
+
+/// Returns the label referred to by a `\ref` command.
+pub fn reference_label(command: &Command) -> String {
+    command
+        .args
+        .first()
+        .map(|arg| ast::text_content(arg).trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the text of a heading title, without any `\label`s, with whitespace collapsed.
+fn title_text(title: &[Node]) -> String {
+    let nodes: Vec<_> = title
+        .iter()
+        .filter(|node| !is_label(node))
+        .cloned()
+        .collect();
+    ast::text_content(&nodes)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the argument of the first `\label` directly within the given nodes.
+fn find_label(nodes: &[Node]) -> Option<String> {
+    nodes.iter().find_map(label)
+}
+
+/// Returns the argument of a `\label` at the start of the given nodes, ignoring whitespace.
+fn following_label(nodes: &[Node]) -> Option<String> {
+    nodes
+        .iter()
+        .find(|node| !matches!(**node, Node::Text(ref text) if text.trim().is_empty()))
+        .and_then(label)
+}
+
+/// Returns whether a node is a `\label` command.
+fn is_label(node: &Node) -> bool {
+    label(node).is_some()
+}
+
+/// Returns the argument of a `\label` command, or `None` if the node is not one.
+fn label(node: &Node) -> Option<String> {
+    match *node {
+        Node::Command(Command { ref name, ref args }) if name == "label" => args
+            .first()
+            .map(|arg| ast::text_content(arg).trim().to_owned()),
+        _ => None,
+    }
+}
@@ -0,0 +1,772 @@
+//! Conversion of documents into pandoc's JSON representation of its AST, so that pandoc can
+//! convert them further, to EPUB, DOCX, or PDF for example. Requires the `serde` feature.
+//!
+//! The output follows version 1.23.1 of pandoc-types, `PANDOC_API_VERSION`, which pandoc 3.0 and
+//! later read. It is equivalent to that of `pandoc -t json`, and can be read with
+//! `pandoc -f json`.
+//!
+//! Each construct which pandoc has no element for is lowered to a `Div`, if it is a block, or a
+//! `Span`, if it is inline, with the name of the command or environment as its class, so that it
+//! can be picked out by a filter.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::slice;
+
+use serde_json::{Map, Value};
+
+use ast::{self, Alignment, Command, Document, Environment, Node, Table, Verbatim};
+use bibliography::{self, Citations};
+use errors::{Error, ErrorKind, ResultExt};
+use locale::Locale;
+use super::abbreviations::Abbreviations;
+use super::index::{self, Entry, Group};
+use super::outline::{self, Heading, Outliner};
+use super::{has_link_text, is_horizontal_rule, item_term, link_address, link_url};
+use super::quote_attribution;
+use super::{FmtWriter, Image, RenderReport, DEFAULT_MAX_DEPTH};
+
+/// The version of pandoc-types whose JSON format is produced, as its `pandoc-api-version`.
+pub const PANDOC_API_VERSION: [u64; 3] = [1, 23, 1];
+
+/// The format given to `RawBlock` and `RawInline` elements. Raw content is passed through by
+/// every renderer, so it is assumed to be HTML, the format it is most often written in.
+const RAW_FORMAT: &str = "html";
+
+/// Renders a document as pandoc JSON to the given output.
+///
+/// Headings become `Header`s, `\emph` and `\bold` become `Emph` and `Strong`, `\code` and verbatim
+/// text become `Code` or `CodeBlock`, links, images, and footnotes become `Link`, `Image`, and
+/// `Note`, lists become `BulletList` or `OrderedList`, `description` lists become `DefinitionList`,
+/// `quote` environments become `BlockQuote`, tables become `Table`, and horizontal rules become
+/// `HorizontalRule`. References become links to the heading they refer to. Citations become
+/// `Cite`s, holding links to the cited entries marked by their numbers, and a `references`
+/// environment becomes a `Div` with the identifier `refs` holding a `Div` for each `\bibitem`, as
+/// pandoc's own citation processing produces. Each `\index` becomes an empty `Span` whose
+/// identifier is its anchor, and `\printindex` becomes a `Div` of the index, whose locations link
+/// to the anchors. Each `\abbr` becomes a `Span` with the class `abbr`, whose `title` is the
+/// expansion of the abbreviation, holding its text as described in the `abbreviations` module.
+/// The title, the authors, the date, and the language are stored in the metadata, as `title`,
+/// `author`, `date`, and `lang`, rather than in the body.
+pub fn render<W: Write>(document: &Document, out: &mut W) -> Result<RenderReport, Error> {
+    let mut report = RenderReport::default();
+    let value = to_value(document, &mut report)?;
+    serde_json::to_writer(&mut *out, &value).context(ErrorKind::Write)?;
+    out.write_all(b"\n").context(ErrorKind::Write)?;
+    Ok(report)
+}
+
+/// Renders a document as pandoc JSON to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    out: &mut F,
+) -> Result<RenderReport, Error> {
+    render(document, &mut FmtWriter::new(out))
+}
+
+/// Renders a document as pandoc JSON, returning the result as a `String`. Any warnings are
+/// discarded.
+pub fn render_to_string(document: &Document) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, &mut out)?;
+    Ok(out)
+}
+
+/// Converts a document into pandoc's representation, as a JSON value, adding any warnings to the
+/// report. Fails with `ErrorKind::NestingTooDeep` if nodes are nested more than
+/// `DEFAULT_MAX_DEPTH` levels deep.
+pub fn to_value(document: &Document, report: &mut RenderReport) -> Result<Value, Error> {
+    let headings = Outliner::new().headings(&document.nodes);
+    let mut converter = Converter {
+        index: index::groups(&document.nodes, &headings, &Locale::default()),
+        index_anchors: 0,
+        outline: headings.clone(),
+        headings: headings.into(),
+        citations: Citations::new(&document.nodes),
+        abbreviations: Abbreviations::new(&document.nodes),
+        report,
+    };
+    let meta = converter.meta(&document.nodes)?;
+    let blocks = converter.blocks(&document.nodes, 0)?;
+    let mut root = Map::new();
+    root.insert("pandoc-api-version".into(), PANDOC_API_VERSION.to_vec().into());
+    root.insert("meta".into(), Value::Object(meta));
+    root.insert("blocks".into(), Value::Array(blocks));
+    Ok(Value::Object(root))
+}
+
+/// Returns an element of pandoc's AST, with the given constructor and contents.
+fn element(tag: &str, content: Value) -> Value {
+    let mut element = Map::new();
+    element.insert("t".into(), tag.into());
+    element.insert("c".into(), content);
+    Value::Object(element)
+}
+
+/// Returns an element of pandoc's AST which has no contents, such as `Space`.
+fn constant(tag: &str) -> Value {
+    let mut element = Map::new();
+    element.insert("t".into(), tag.into());
+    Value::Object(element)
+}
+
+/// Returns the attributes of an element: its identifier, its classes, and its other attributes.
+fn attr(id: &str, classes: &[&str], attributes: &[(&str, &str)]) -> Value {
+    let attributes = attributes
+        .iter()
+        .map(|&(key, value)| Value::from(vec![key, value]))
+        .collect::<Vec<_>>();
+    Value::from(vec![id.into(), classes.to_vec().into(), Value::from(attributes)])
+}
+
+/// Returns the empty attributes.
+fn no_attr() -> Value {
+    attr("", &[], &[])
+}
+
+/// Returns whether an inline element is whitespace, `Space` or `SoftBreak`.
+fn is_space(inline: &Value) -> bool {
+    matches!(inline["t"].as_str(), Some("Space") | Some("SoftBreak"))
+}
+
+/// Returns whether a node is an item of a description list.
+fn is_definition(node: &Node) -> bool {
+    matches!(*node, Node::Command(ref command) if item_term(command).is_some())
+}
+
+/// Returns whether an inline element is a `LineBreak`.
+fn is_line_break(inline: &Value) -> bool {
+    inline["t"] == "LineBreak"
+}
+
+/// Appends the inline elements for a run of text to `inlines`, splitting it into words,
+/// separated by `Space`, or by `SoftBreak` where the whitespace contains a line break.
+/// Whitespace is collapsed, including that at the end of `inlines`, and dropped after a
+/// `LineBreak`.
+fn push_text(text: &str, inlines: &mut Vec<Value>) {
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end > 0 {
+            inlines.push(element("Str", rest[..end].into()));
+            rest = &rest[end..];
+            continue;
+        }
+        let end = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        if !inlines.last().is_some_and(|last| is_space(last) || is_line_break(last)) {
+            let space = if rest[..end].contains('\n') {
+                "SoftBreak"
+            } else {
+                "Space"
+            };
+            inlines.push(constant(space));
+        }
+        rest = &rest[end..];
+    }
+}
+
+/// Returns the inline elements for a run of text with no markup, such as the address of a link.
+fn plain_text(text: &str) -> Vec<Value> {
+    let mut inlines = Vec::new();
+    push_text(text, &mut inlines);
+    inlines
+}
+
+/// Removes whitespace from the start and end of a list of inline elements.
+fn trim(mut inlines: Vec<Value>) -> Vec<Value> {
+    while inlines.last().is_some_and(is_space) {
+        inlines.pop();
+    }
+    let start = inlines.iter().take_while(|inline| is_space(inline)).count();
+    inlines.split_off(start)
+}
+
+/// Returns a `Code` or `CodeBlock` element for verbatim text, whose class is its language, if it
+/// has one.
+fn code(tag: &str, verbatim: &Verbatim) -> Value {
+    let attr = match verbatim.language {
+        Some(ref language) => attr("", &[language.as_str()], &[]),
+        None => no_attr(),
+    };
+    element(tag, vec![attr, verbatim.content.clone().into()].into())
+}
+
+/// Returns a `RawBlock` or `RawInline` element for raw content.
+fn raw(tag: &str, content: &str) -> Value {
+    element(tag, vec![RAW_FORMAT, content].into())
+}
+
+/// Returns pandoc's name for the alignment of a table column.
+fn alignment(alignment: Alignment) -> Value {
+    constant(match alignment {
+        Alignment::Left => "AlignLeft",
+        Alignment::Center => "AlignCenter",
+        Alignment::Right => "AlignRight",
+    })
+}
+
+/// Returns a cell of a table, spanning one row and one column, which contains the given blocks.
+fn cell(blocks: Vec<Value>) -> Value {
+    let alignment = constant("AlignDefault");
+    Value::from(vec![no_attr(), alignment, 1.into(), 1.into(), blocks.into()])
+}
+
+/// Returns whether a node is a block, rather than inline content, when it appears among blocks.
+fn is_block(node: &Node) -> bool {
+    match *node {
+        Node::Paragraph(_)
+        | Node::Environment(_)
+        | Node::Table(_)
+        | Node::Verbatim(_)
+        | Node::Raw(_) => true,
+        Node::Command(ref command) => {
+            outline::heading_level(&command.name).is_some()
+                || is_horizontal_rule(&command.name)
+                || is_alignment(&command.name)
+                || is_omitted(&command.name)
+                || command.name == "printindex"
+        }
+        Node::Text(_) | Node::Group(_) => false,
+    }
+}
+
+/// Returns whether a command aligns its content, which pandoc cannot express.
+fn is_alignment(name: &str) -> bool {
+    name == "centered" || name == "rightalign"
+}
+
+/// Returns whether a command produces no content of its own: the metadata, which is stored in
+/// `meta`, and the commands which affect only other parts of the document.
+fn is_omitted(name: &str) -> bool {
+    matches!(
+        name,
+        "title"
+            | "author"
+            | "date"
+            | "lang"
+            | "label"
+            | "tableofcontents"
+            | "appendix"
+            | "bibliography"
+            | "defabbr"
+    )
+}
+
+/// The state of the conversion of a document.
+struct Converter<'a> {
+    /// All of the headings of the document, for resolving references.
+    outline: Vec<Heading>,
+    /// The headings which have not yet been converted.
+    headings: VecDeque<Heading>,
+    /// The numbers of the entries of the bibliography, for resolving citations.
+    citations: Citations,
+    /// The index of the document.
+    index: Vec<Group>,
+    /// The number of `\index` commands converted so far.
+    index_anchors: usize,
+    /// The abbreviations of the document, and which have been used.
+    abbreviations: Abbreviations,
+    report: &'a mut RenderReport,
+}
+
+impl<'a> Converter<'a> {
+    /// Returns the metadata given by the top-level commands of a document. If `\title`, `\date`,
+    /// or `\lang` is repeated, the first is used; each `\author` adds an author.
+    fn meta(&mut self, nodes: &[Node]) -> Result<Map<String, Value>, Error> {
+        let mut meta = Map::new();
+        let mut authors = Vec::new();
+        for node in nodes {
+            let command = match *node {
+                Node::Command(ref command) => command,
+                _ => continue,
+            };
+            let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+            match &*command.name {
+                "title" | "date" if !meta.contains_key(&command.name) => {
+                    let value = element("MetaInlines", trim(self.inlines(arg, 1)?).into());
+                    meta.insert(command.name.clone(), value);
+                }
+                "author" => {
+                    let inlines = trim(self.inlines(arg, 1)?);
+                    authors.push(element("MetaInlines", inlines.into()));
+                }
+                "lang" if !meta.contains_key("lang") => {
+                    let lang = ast::text_content(arg).trim().to_owned();
+                    meta.insert("lang".into(), element("MetaString", lang.into()));
+                }
+                _ => {}
+            }
+        }
+        if !authors.is_empty() {
+            meta.insert("author".into(), element("MetaList", authors.into()));
+        }
+        Ok(meta)
+    }
+
+    /// Returns the block elements for a list of nodes. Inline content which is not within a
+    /// paragraph, such as the text of a list item, becomes a `Plain` block.
+    fn blocks(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Value>, Error> {
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        let mut blocks = Vec::new();
+        let mut rest = nodes;
+        while let Some(node) = rest.first() {
+            if is_block(node) {
+                blocks.extend(self.block(node, depth)?);
+                rest = &rest[1..];
+                continue;
+            }
+            let end = rest.iter().position(is_block).unwrap_or(rest.len());
+            let inlines = trim(self.inlines(&rest[..end], depth + 1)?);
+            if !inlines.is_empty() {
+                blocks.push(element("Plain", inlines.into()));
+            }
+            rest = &rest[end..];
+        }
+        Ok(blocks)
+    }
+
+    /// Returns the block element for a node for which `is_block` holds, or `None` if it produces
+    /// no content.
+    fn block(&mut self, node: &Node, depth: usize) -> Result<Option<Value>, Error> {
+        let block = match *node {
+            Node::Paragraph(ref nodes) => {
+                let inlines = trim(self.inlines(nodes, depth + 1)?);
+                if inlines.is_empty() {
+                    return Ok(None);
+                }
+                element("Para", inlines.into())
+            }
+            Node::Environment(ref env) => self.environment(env, depth)?,
+            Node::Table(ref table) => self.table(table, depth)?,
+            Node::Verbatim(ref verbatim) => code("CodeBlock", verbatim),
+            Node::Raw(ref content) => raw("RawBlock", content),
+            Node::Command(ref command) => return self.block_command(command, depth),
+            Node::Text(_) | Node::Group(_) => return Ok(None),
+        };
+        Ok(Some(block))
+    }
+
+    /// Returns the block element for a command for which `is_block` holds, or `None` if it
+    /// produces no content.
+    fn block_command(&mut self, command: &Command, depth: usize) -> Result<Option<Value>, Error> {
+        let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+        if let Some(level) = outline::heading_level(&command.name) {
+            let heading = self.headings.pop_front();
+            let id = heading.as_ref().map_or("", |heading| &*heading.id);
+            let classes: &[&str] = if outline::is_starred(&command.name) {
+                &["unnumbered"]
+            } else {
+                &[]
+            };
+            let title = trim(self.inlines(arg, depth + 1)?);
+            return Ok(Some(element(
+                "Header",
+                vec![level.into(), attr(id, classes, &[]), title.into()].into(),
+            )));
+        }
+        if is_horizontal_rule(&command.name) {
+            return Ok(Some(constant("HorizontalRule")));
+        }
+        if command.name == "printindex" {
+            if self.index.is_empty() {
+                return Ok(None);
+            }
+            let mut blocks = Vec::new();
+            for group in &self.index {
+                let letter = element("Strong", plain_text(&group.letter).into());
+                blocks.push(element("Para", vec![letter].into()));
+                blocks.push(index_list(&group.entries));
+            }
+            return Ok(Some(element(
+                "Div",
+                vec![attr("", &["index"], &[]), blocks.into()].into(),
+            )));
+        }
+        if is_alignment(&command.name) {
+            let blocks = self.blocks(arg, depth + 1)?;
+            return Ok(Some(element(
+                "Div",
+                vec![attr("", &[&command.name], &[]), blocks.into()].into(),
+            )));
+        }
+        Ok(None)
+    }
+
+    /// Returns the block element for an environment.
+    fn environment(&mut self, env: &Environment, depth: usize) -> Result<Value, Error> {
+        match &*env.name {
+            "itemize" => Ok(element("BulletList", self.items(&env.nodes, depth)?.into())),
+            "enumerate" => {
+                let attributes = vec![1.into(), constant("Decimal"), constant("Period")];
+                let items = self.items(&env.nodes, depth)?;
+                Ok(element("OrderedList", vec![Value::from(attributes), items.into()].into()))
+            }
+            "description" => {
+                let definitions = self.definitions(&env.nodes, depth)?;
+                Ok(element("DefinitionList", definitions.into()))
+            }
+            "references" => {
+                let entries = self.references(&env.nodes, depth)?;
+                let attr = attr("refs", &["references"], &[]);
+                Ok(element("Div", vec![attr, entries.into()].into()))
+            }
+            "quote" => {
+                let mut blocks = self.blocks(&env.nodes, depth + 1)?;
+                if let Some(attribution) = quote_attribution(env) {
+                    blocks.push(element("Para", plain_text(&attribution).into()));
+                }
+                Ok(element("BlockQuote", blocks.into()))
+            }
+            _ => {
+                let blocks = self.blocks(&env.nodes, depth + 1)?;
+                Ok(element("Div", vec![attr("", &[&env.name], &[]), blocks.into()].into()))
+            }
+        }
+    }
+
+    /// Returns the blocks of each item of a list. Content before the first `\item` forms an item
+    /// of its own.
+    fn items(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Value>, Error> {
+        let mut items: Vec<Value> = Vec::new();
+        let mut rest = nodes;
+        while !rest.is_empty() {
+            let item = match rest[0] {
+                Node::Command(ref command) if command.name == "item" => {
+                    rest = &rest[1..];
+                    let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+                    self.blocks(arg, depth + 1)?
+                }
+                _ => {
+                    let end = rest
+                        .iter()
+                        .position(|node| {
+                            matches!(*node, Node::Command(ref command) if command.name == "item")
+                        })
+                        .unwrap_or(rest.len());
+                    let blocks = self.blocks(&rest[..end], depth + 1)?;
+                    rest = &rest[end..];
+                    if blocks.is_empty() {
+                        continue;
+                    }
+                    blocks
+                }
+            };
+            items.push(item.into());
+        }
+        Ok(items)
+    }
+
+    /// Returns the term and the definition of each item of a description list. Content before the
+    /// first `\item` forms an item of its own, with an empty term.
+    fn definitions(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Value>, Error> {
+        let mut definitions: Vec<Value> = Vec::new();
+        let mut rest = nodes;
+        while !rest.is_empty() {
+            let (term, blocks) = match rest[0] {
+                Node::Command(ref command) if item_term(command).is_some() => {
+                    rest = &rest[1..];
+                    let mut term = self.inlines(&command.args[0], depth + 1)?;
+                    while term.last().is_some_and(is_space) {
+                        term.pop();
+                    }
+                    let start = term.iter().take_while(|inline| is_space(inline)).count();
+                    term.drain(..start);
+                    (term, self.blocks(&command.args[1], depth + 1)?)
+                }
+                _ => {
+                    let end = rest.iter().position(is_definition).unwrap_or(rest.len());
+                    let blocks = self.blocks(&rest[..end], depth + 1)?;
+                    rest = &rest[end..];
+                    if blocks.is_empty() {
+                        continue;
+                    }
+                    (Vec::new(), blocks)
+                }
+            };
+            definitions.push(vec![Value::from(term), vec![Value::from(blocks)].into()].into());
+        }
+        Ok(definitions)
+    }
+
+    /// Returns a `Div` for each `\bibitem` of a `references` environment, whose first paragraph
+    /// starts with the number of the entry in brackets. Content other than the `\bibitem`s is
+    /// converted as it is.
+    fn references(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Value>, Error> {
+        let mut entries = Vec::new();
+        for node in nodes {
+            let command = match *node {
+                Node::Command(ref command) if command.name == "bibitem" => command,
+                ref node => {
+                    entries.extend(self.blocks(slice::from_ref(node), depth + 1)?);
+                    continue;
+                }
+            };
+            let key = bibliography::bibitem_key(command);
+            let number = bibliography::number_text(self.citations.number(&key));
+            let mut label = plain_text(&format!("[{}]", number));
+            label.push(constant("Space"));
+            let arg = command.args.get(1).map_or(&[][..], |arg| &arg[..]);
+            let mut blocks = self.blocks(arg, depth + 1)?;
+            let first = blocks
+                .first_mut()
+                .filter(|block| matches!(block["t"].as_str(), Some("Para") | Some("Plain")))
+                .and_then(|block| block["c"].as_array_mut());
+            match first {
+                Some(inlines) => {
+                    inlines.splice(..0, label);
+                }
+                None => blocks.insert(0, element("Plain", trim(label).into())),
+            }
+            let attr = attr(&bibliography::anchor(&key), &["csl-entry"], &[]);
+            entries.push(element("Div", vec![attr, blocks.into()].into()));
+        }
+        Ok(entries)
+    }
+
+    /// Returns the `Table` element for a table. Rows with fewer cells than the table has columns
+    /// are filled out with empty cells.
+    fn table(&mut self, table: &Table, depth: usize) -> Result<Value, Error> {
+        let count = table.column_count();
+        let mut rows = Vec::new();
+        for row in &table.rows {
+            let mut cells = Vec::new();
+            for column in 0..count {
+                let blocks = match row.get(column) {
+                    Some(nodes) => self.blocks(nodes, depth + 1)?,
+                    None => Vec::new(),
+                };
+                cells.push(cell(blocks));
+            }
+            rows.push(Value::from(vec![no_attr(), cells.into()]));
+        }
+        let body = if table.header && !rows.is_empty() {
+            rows.split_off(1)
+        } else {
+            rows.split_off(0)
+        };
+        let caption = trim(self.inlines(&table.caption, depth + 1)?);
+        let caption = if caption.is_empty() {
+            Vec::new()
+        } else {
+            vec![element("Plain", caption.into())]
+        };
+        let columns = (0..count)
+            .map(|column| {
+                Value::from(vec![alignment(table.alignment(column)), constant("ColWidthDefault")])
+            })
+            .collect::<Vec<_>>();
+        let head = vec![no_attr(), rows.into()];
+        let body = vec![no_attr(), 0.into(), Value::Array(Vec::new()), body.into()];
+        let foot = vec![no_attr(), Value::Array(Vec::new())];
+        Ok(element(
+            "Table",
+            vec![
+                no_attr(),
+                vec![Value::Null, caption.into()].into(),
+                columns.into(),
+                head.into(),
+                vec![Value::from(body)].into(),
+                foot.into(),
+            ]
+            .into(),
+        ))
+    }
+
+    /// Returns the inline elements for a list of nodes. Blocks within inline content, such as a
+    /// list within a footnote's text, are lowered to `Span`s.
+    fn inlines(&mut self, nodes: &[Node], depth: usize) -> Result<Vec<Value>, Error> {
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        let mut inlines = Vec::new();
+        for node in nodes {
+            match *node {
+                Node::Text(ref text) => push_text(text, &mut inlines),
+                Node::Paragraph(ref nodes) | Node::Group(ref nodes) => {
+                    inlines.extend(self.inlines(nodes, depth + 1)?)
+                }
+                Node::Command(ref command) => {
+                    if let Some(inline) = self.inline_command(command, depth)? {
+                        // Whitespace before a line break is dropped, as it is after one.
+                        if is_line_break(&inline) {
+                            while inlines.last().is_some_and(is_space) {
+                                inlines.pop();
+                            }
+                        }
+                        inlines.push(inline);
+                    }
+                }
+                Node::Environment(ref env) => {
+                    let content = self.inlines(&env.nodes, depth + 1)?;
+                    inlines.push(span(&env.name, &[], content));
+                }
+                Node::Table(ref table) => {
+                    let mut content = Vec::new();
+                    for cell in table.rows.iter().flatten() {
+                        if !content.is_empty() {
+                            content.push(constant("Space"));
+                        }
+                        content.extend(trim(self.inlines(cell, depth + 1)?));
+                    }
+                    inlines.push(span("table", &[], content));
+                }
+                Node::Verbatim(ref verbatim) => inlines.push(code("Code", verbatim)),
+                Node::Raw(ref content) => inlines.push(raw("RawInline", content)),
+            }
+        }
+        Ok(inlines)
+    }
+
+    /// Returns the inline element for a command, or `None` if it produces no content.
+    fn inline_command(&mut self, command: &Command, depth: usize) -> Result<Option<Value>, Error> {
+        let arg = command.args.first().map_or(&[][..], |arg| &arg[..]);
+        let inline = match &*command.name {
+            "emph" => element("Emph", self.inlines(arg, depth + 1)?.into()),
+            "bold" => element("Strong", self.inlines(arg, depth + 1)?.into()),
+            "code" => element("Code", vec![no_attr(), ast::text_content(arg).into()].into()),
+            "break" => constant("LineBreak"),
+            "footnote" => element("Note", self.blocks(arg, depth + 1)?.into()),
+            "link" => {
+                let text = if has_link_text(command) {
+                    self.inlines(&command.args[1], depth + 1)?
+                } else {
+                    plain_text(&link_address(command))
+                };
+                let target = vec![link_url(command), String::new()];
+                element("Link", vec![no_attr(), text.into(), target.into()].into())
+            }
+            "image" => {
+                let image = Image::new(command, self.report);
+                let mut attributes = Vec::new();
+                if let Some(ref width) = image.width {
+                    attributes.push(("width", &**width));
+                }
+                if let Some(ref height) = image.height {
+                    attributes.push(("height", &**height));
+                }
+                let alt = plain_text(image.alt.as_deref().unwrap_or_default());
+                let target = vec![image.path.clone(), String::new()];
+                let content = vec![attr("", &[], &attributes), alt.into(), target.into()];
+                element("Image", content.into())
+            }
+            "index" => {
+                self.index_anchors += 1;
+                let id = index::anchor(self.index_anchors);
+                element("Span", vec![attr(&id, &["index"], &[]), Vec::<Value>::new().into()].into())
+            }
+            "cite" => {
+                let mut citations = Vec::new();
+                let mut inlines = plain_text("[");
+                let cited = self.citations.cited(command, self.report);
+                for (i, (key, number)) in cited.into_iter().enumerate() {
+                    if i > 0 {
+                        inlines.extend(plain_text(","));
+                        inlines.push(constant("Space"));
+                    }
+                    let text = plain_text(&bibliography::number_text(number));
+                    match number {
+                        Some(_) => {
+                            let target = format!("#{}", bibliography::anchor(&key));
+                            let target = vec![target, String::new()];
+                            let content = vec![no_attr(), text.into(), target.into()];
+                            inlines.push(element("Link", content.into()));
+                        }
+                        None => inlines.extend(text),
+                    }
+                    citations.push(citation(&key));
+                }
+                inlines.extend(plain_text("]"));
+                element("Cite", vec![Value::from(citations), inlines.into()].into())
+            }
+            "abbr" => {
+                let abbreviation = self.abbreviations.use_abbreviation(command, self.report);
+                let text = plain_text(&abbreviation.text());
+                match abbreviation.expansion {
+                    Some(ref expansion) => span("abbr", &[("title", expansion)], text),
+                    None => span("abbr", &[], text),
+                }
+            }
+            "ref" => {
+                let text = outline::reference_text(&self.outline, command, self.report);
+                let label = outline::reference_label(command);
+                match outline::find_heading(&self.outline, &label) {
+                    Some(heading) => {
+                        let target = vec![format!("#{}", heading.id), String::new()];
+                        let content = vec![no_attr(), plain_text(&text).into(), target.into()];
+                        element("Link", content.into())
+                    }
+                    None => span("ref", &[], plain_text(&text)),
+                }
+            }
+            "color" => {
+                let color = ast::text_content(arg).trim().to_owned();
+                let style = format!("color: {}", color);
+                let content = match command.args.get(1) {
+                    Some(text) => self.inlines(text, depth + 1)?,
+                    None => Vec::new(),
+                };
+                span("color", &[("style", &style)], content)
+            }
+            name if is_omitted(name) || is_horizontal_rule(name) => return Ok(None),
+            name => {
+                if outline::heading_level(name).is_some() {
+                    self.headings.pop_front();
+                }
+                let mut content = Vec::new();
+                for arg in &command.args {
+                    content.extend(self.inlines(arg, depth + 1)?);
+                }
+                span(name, &[], content)
+            }
+        };
+        Ok(Some(inline))
+    }
+}
+
+/// Returns a `BulletList` of entries of the index, each with links to its locations, and with its
+/// sub-entries in a nested list.
+fn index_list(entries: &[Entry]) -> Value {
+    let items: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            let mut inlines = plain_text(&entry.term);
+            for location in &entry.locations {
+                inlines.extend(plain_text(","));
+                inlines.push(constant("Space"));
+                let target = vec![format!("#{}", location.anchor), String::new()];
+                let text = plain_text(&location.text);
+                inlines.push(element("Link", vec![no_attr(), text.into(), target.into()].into()));
+            }
+            let mut blocks = vec![element("Plain", inlines.into())];
+            if !entry.subentries.is_empty() {
+                blocks.push(index_list(&entry.subentries));
+            }
+            Value::from(blocks)
+        })
+        .collect();
+    element("BulletList", items.into())
+}
+
+/// Returns a `Citation` of the entry with the given key, as cited normally, with no prefix or
+/// suffix.
+fn citation(key: &str) -> Value {
+    let mut citation = Map::new();
+    citation.insert("citationId".into(), key.into());
+    citation.insert("citationPrefix".into(), Value::Array(Vec::new()));
+    citation.insert("citationSuffix".into(), Value::Array(Vec::new()));
+    citation.insert("citationMode".into(), constant("NormalCitation"));
+    citation.insert("citationNoteNum".into(), 0.into());
+    citation.insert("citationHash".into(), 0.into());
+    Value::Object(citation)
+}
+
+/// Returns a `Span` with the given class and other attributes, containing the given inline
+/// elements.
+fn span(class: &str, attributes: &[(&str, &str)], content: Vec<Value>) -> Value {
+    element("Span", vec![attr("", &[class], attributes), content.into()].into())
+}
@@ -0,0 +1,1551 @@
+use std::cell;
+use std::fmt;
+use std::io::Write;
+use std::mem;
+use std::num::IntErrorKind;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use ast::{self, Alignment, Command, Document, Environment, Node, Span, Table, Verbatim};
+use bibliography::{self, Citations};
+use errors::{Error, ErrorKind, ResultExt, Warning};
+use locale::Locale;
+use tokenize::SOFT_HYPHEN;
+use super::abbreviations::Abbreviations;
+use super::index::{self, Entry, Group};
+use super::outline::{self, Heading, NumberingScheme};
+use super::{has_link_text, is_horizontal_rule, link_url, render_document, FmtWriter, Image};
+use super::{render_document_mapped, CountingWriter, RenderReport, Renderer, RuleStyle};
+#[cfg(feature = "bidi")]
+use super::{bidi, Direction};
+#[cfg(feature = "hyphenation")]
+use super::hyphenation::Hyphenation;
+use super::{is_set, item_term, quote_attribution, terminal, SourceMap, Visit};
+
+/// Options controlling the output of the plain-text renderer.
+#[derive(Clone, Debug)]
+pub struct PlainOptions {
+    verbatim_indent: usize,
+    indent_unit: usize,
+    wrap: bool,
+    justify: bool,
+    width: Option<usize>,
+    terminal: bool,
+    max_column_width: usize,
+    table_borders: TableBorders,
+    quote_prefix: String,
+    column_gutter: usize,
+    numbering: NumberingScheme,
+    number_headings: bool,
+    reset_abbreviations: bool,
+    locale: Locale,
+    #[cfg(feature = "bidi")]
+    direction: Option<Direction>,
+    #[cfg(feature = "hyphenation")]
+    hyphenation: Option<Hyphenation>,
+    cancel_token: Option<Arc<AtomicBool>>,
+}
+
+/// The characters with which the borders of tables, and horizontal rules, are drawn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TableBorders {
+    /// `+`, `-`, `=`, and `|`.
+    Ascii,
+    /// Box-drawing characters.
+    Unicode,
+}
+
+impl Default for PlainOptions {
+    fn default() -> PlainOptions {
+        PlainOptions {
+            verbatim_indent: 4,
+            indent_unit: 2,
+            wrap: false,
+            justify: false,
+            width: None,
+            terminal: false,
+            max_column_width: 30,
+            table_borders: TableBorders::Ascii,
+            quote_prefix: "| ".into(),
+            column_gutter: 4,
+            numbering: NumberingScheme::default(),
+            number_headings: true,
+            reset_abbreviations: false,
+            locale: Locale::default(),
+            #[cfg(feature = "bidi")]
+            direction: None,
+            #[cfg(feature = "hyphenation")]
+            hyphenation: None,
+            cancel_token: None,
+        }
+    }
+}
+
+impl PlainOptions {
+    /// Constructs a new `PlainOptions` with the default settings.
+    pub fn new() -> PlainOptions {
+        PlainOptions::default()
+    }
+
+    /// Sets the number of spaces by which verbatim blocks are indented. Defaults to 4.
+    pub fn verbatim_indent(mut self, indent: usize) -> PlainOptions {
+        self.verbatim_indent = indent;
+        self
+    }
+
+    /// Sets the number of spaces by which each level of an `indent` environment indents its
+    /// content. Defaults to 2.
+    pub fn indent_unit(mut self, unit: usize) -> PlainOptions {
+        self.indent_unit = unit;
+        self
+    }
+
+    /// Sets whether paragraphs are wrapped to the width given by `width`, rather than each being
+    /// written on a single line. Defaults to `false`.
+    pub fn wrap(mut self, wrap: bool) -> PlainOptions {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Sets whether each line of a wrapped paragraph, other than the last line and those ended by
+    /// a `\break`, is justified: spaces are added between its words until it fills the width, as
+    /// evenly as they can be, with any left over going to the leftmost gaps. Lines holding a single
+    /// word are left as they are, and the lines of headings, verbatim blocks, table cells, and
+    /// `\centered` and `\rightalign` are never justified. Has no effect unless `wrap` is set.
+    /// Defaults to `false`.
+    pub fn justify(mut self, justify: bool) -> PlainOptions {
+        self.justify = justify;
+        self
+    }
+
+    /// Sets the width of the output, in terminal columns, which takes precedence over any width
+    /// found as described for `get_width`.
+    pub fn width(mut self, width: usize) -> PlainOptions {
+        self.width = Some(width);
+        self
+    }
+
+    /// Sets whether the output is written to a terminal, as standard output, whose width is then
+    /// used unless one is given by `width`. Finding the width of the terminal requires the
+    /// `terminal` feature. Defaults to `false`.
+    pub fn terminal(mut self, terminal: bool) -> PlainOptions {
+        self.terminal = terminal;
+        self
+    }
+
+    /// Returns the width of the output, to which paragraphs are wrapped, and within which lines
+    /// are aligned and tables and horizontal rules are laid out. This is the width given by
+    /// `width`, if any; otherwise the width of the terminal, if `terminal` is set and it can be
+    /// found; otherwise the value of the `COLUMNS` environment variable; otherwise 80.
+    pub fn get_width(&self) -> usize {
+        terminal::resolve_width(self.width, self.terminal, terminal::detect_width)
+    }
+
+    /// Sets the maximum width of a table column, beyond which the contents of its cells are
+    /// wrapped. Defaults to 30.
+    pub fn max_column_width(mut self, width: usize) -> PlainOptions {
+        self.max_column_width = width;
+        self
+    }
+
+    /// Sets the characters with which the borders of tables, and horizontal rules without a
+    /// character of their own, are drawn. Defaults to `TableBorders::Ascii`.
+    pub fn table_borders(mut self, borders: TableBorders) -> PlainOptions {
+        self.table_borders = borders;
+        self
+    }
+
+    /// Sets the prefix written before each line of a `quote` environment, such as `"│ "`.
+    /// Nested quotes repeat it, and the content is wrapped to the width left after it. Defaults
+    /// to `"| "`.
+    pub fn quote_prefix<S: Into<String>>(mut self, prefix: S) -> PlainOptions {
+        self.quote_prefix = prefix.into();
+        self
+    }
+
+    /// Sets the number of spaces between the columns of a `columns` environment. Defaults to 4.
+    pub fn column_gutter(mut self, gutter: usize) -> PlainOptions {
+        self.column_gutter = gutter;
+        self
+    }
+
+    /// Sets the scheme by which headings are numbered in headings and references. Defaults to
+    /// `NumberingScheme::new()`.
+    pub fn numbering(mut self, numbering: NumberingScheme) -> PlainOptions {
+        self.numbering = numbering;
+        self
+    }
+
+    /// Sets whether each numbered heading starts with its number. Defaults to `true`.
+    pub fn number_headings(mut self, number: bool) -> PlainOptions {
+        self.number_headings = number;
+        self
+    }
+
+    /// Sets whether each abbreviation is expanded again at its first use after every `\section`,
+    /// rather than only at its first use in the document. Defaults to `false`.
+    pub fn reset_abbreviations(mut self, reset: bool) -> PlainOptions {
+        self.reset_abbreviations = reset;
+        self
+    }
+
+    /// Sets the locale by which the strings written by the renderer rather than taken from the
+    /// document, such as the location of the entries of the index before the first heading, are
+    /// translated. Defaults to English.
+    pub fn locale(mut self, locale: Locale) -> PlainOptions {
+        self.locale = locale;
+        self
+    }
+
+    /// Sets the direction of paragraphs, and with it whether each line of text is reordered for
+    /// display by the Unicode bidirectional algorithm, so that right-to-left runs, such as Hebrew
+    /// or Arabic, read correctly in a terminal which shows characters in the order they are
+    /// written. Lines are reordered after wrapping, and before alignment, so that centered and
+    /// right-aligned lines, and the cells of tables, are measured as they are displayed.
+    /// Defaults to `None`, which writes text in logical order. Requires the `bidi` feature.
+    #[cfg(feature = "bidi")]
+    pub fn direction(mut self, direction: Option<Direction>) -> PlainOptions {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets how words are hyphenated when wrapping, so that a word which does not fit at the end
+    /// of a line may be broken, with a `-`, at a point given by the hyphenation patterns of a
+    /// language, as described in the `hyphenation` module. Words with `\-` are broken only there,
+    /// and verbatim text, `\code`, and URLs are never hyphenated. Only text which is wrapped is
+    /// hyphenated. Defaults to `None`, which breaks words only at `\-`. Requires the
+    /// `hyphenation` feature.
+    #[cfg(feature = "hyphenation")]
+    pub fn hyphenate(mut self, hyphenation: Option<Hyphenation>) -> PlainOptions {
+        self.hyphenation = hyphenation;
+        self
+    }
+
+    /// Sets a flag which stops rendering with an `ErrorKind::Cancelled` error once it is set, as
+    /// with `HtmlOptions::cancel_token`. The flag also cancels the ANSI renderer, which takes these
+    /// options for its layout. Defaults to none.
+    pub fn cancel_token(mut self, token: Arc<AtomicBool>) -> PlainOptions {
+        self.cancel_token = Some(token);
+        self
+    }
+}
+
+/// Renders a document as plain text to the given output.
+///
+/// Formatting commands such as `\emph` contribute only their text, headings are underlined, and
+/// verbatim blocks are indented. Whitespace within paragraphs is collapsed, and paragraphs are
+/// wrapped if `PlainOptions::wrap` is set, hyphenating words if `PlainOptions::hyphenate` is set
+/// and justifying lines if `PlainOptions::justify` is set. A `\break` always starts a new line,
+/// which is wrapped on its own, so that consecutive breaks leave empty lines; breaks at the end of
+/// a paragraph are dropped. The content of `\centered` and `\rightalign` is always wrapped, and
+/// each line is aligned within the width. The content of an `indent` environment, including
+/// verbatim blocks, is indented by its level (1 by default) times `PlainOptions::indent_unit`, and
+/// nested environments add to the indentation. Each line of a `quote` environment starts with
+/// `PlainOptions::quote_prefix`, once for each quote it is within, and a quote with an attribution
+/// ends with it, after a dash. The content of a `columns` environment is always wrapped, to the
+/// width of a column, and laid out in the number of columns given by its argument (2 by default),
+/// separated by `PlainOptions::column_gutter` and balanced in height, so that each but the last
+/// holds as many lines as the first; a verbatim block too wide for a column is written across the
+/// full width between them. Each item of a `description` list starts with its term and a colon, and
+/// the rest of its content is indented to line up after them. Tables are drawn with borders, and
+/// the contents of their cells are wrapped to fit within the width, with any caption below them
+/// as a paragraph. Footnotes are marked with their
+/// numbers in brackets, and are listed below a separator at the end of the document. Citations are
+/// written as the numbers of the cited entries in brackets, as are the `\bibitem`s of a
+/// `references` environment, before their text, which is indented to line up after them.
+/// Abbreviations are written with their expansion at their first use, as described in the
+/// `abbreviations` module. `\printindex` writes the index, as described in the `index` module, with
+/// a line for each letter followed by its entries, each with its locations after it, and
+/// sub-entries indented. Links are followed by their URL in angle brackets, and links without text
+/// of their own are written as their URL alone. Images are written as their alternative text, or
+/// their path if they have none, in brackets, and decorative images are omitted. Horizontal rules
+/// span the width left by any indentation, or the given fraction of it. Blocks are separated by
+/// blank lines. Commands which produce no text are omitted.
+pub fn render<W: Write>(
+    document: &Document,
+    options: &PlainOptions,
+    out: &mut W,
+) -> Result<RenderReport, Error> {
+    render_plain(document, None, options, out).map(|(report, _)| report)
+}
+
+/// Renders a document as plain text, as with `render`, along with a `SourceMap` from the output
+/// to the given spans of input of each top-level node, such as those produced by
+/// `Blocks::parse_spanned`.
+pub fn render_mapped<W: Write>(
+    document: &Document,
+    spans: &[Span],
+    options: &PlainOptions,
+    out: &mut W,
+) -> Result<(RenderReport, SourceMap), Error> {
+    let count = cell::Cell::new(0);
+    let mut out = CountingWriter {
+        inner: out,
+        count: &count,
+    };
+    render_plain(document, Some((spans, &count)), options, &mut out)
+}
+
+fn render_plain<W: Write>(
+    document: &Document,
+    mapping: Option<(&[Span], &cell::Cell<usize>)>,
+    options: &PlainOptions,
+    out: &mut W,
+) -> Result<(RenderReport, SourceMap), Error> {
+    let mut writer = PlainWriter::new(document, options, out);
+    let map = match mapping {
+        Some((spans, count)) => render_document_mapped(document, spans, &mut writer, count)?,
+        None => {
+            render_document(document, &mut writer)?;
+            SourceMap::new()
+        }
+    };
+    writer.report.set_span(None);
+    write_footnotes(
+        &mut writer,
+        |writer| writer,
+        Some(FOOTNOTE_SEPARATOR),
+        footnote_marker,
+    )?;
+    Ok((writer.report, map))
+}
+
+/// Renders a document as plain text to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    options: &PlainOptions,
+    out: &mut F,
+) -> Result<RenderReport, Error> {
+    render(document, options, &mut FmtWriter::new(out))
+}
+
+/// Renders a document as plain text, returning the result as a `String`. Any warnings
+/// are discarded.
+pub fn render_to_string(document: &Document, options: &PlainOptions) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, options, &mut out)?;
+    Ok(out)
+}
+
+/// The line separating the footnotes from the rest of the document.
+pub(super) const FOOTNOTE_SEPARATOR: &str = "-----";
+
+/// Returns the marker which starts the footnote with the given number.
+pub(super) fn footnote_marker(number: usize) -> String {
+    format!("[{}] ", number)
+}
+
+/// Writes the footnotes collected by a `PlainWriter` at the end of the document, each as a
+/// paragraph starting with the given marker, after the given separator line, if any. If there
+/// are no footnotes, nothing is written.
+///
+/// The content of the footnotes is rendered with `renderer`, which writes through the
+/// `PlainWriter` returned by `plain`, so that renderers which add to the plain-text layout can
+/// also style their footnotes.
+pub(super) fn write_footnotes<'a, R, W>(
+    renderer: &mut R,
+    plain: fn(&mut R) -> &mut PlainWriter<'a, W>,
+    separator: Option<&str>,
+    marker: fn(usize) -> String,
+) -> Result<(), Error>
+where
+    R: Renderer,
+    W: Write + 'a,
+{
+    let mut index = 0;
+    // Footnotes may contain footnotes of their own, which are added to the end of the list.
+    while let Some(content) = plain(renderer).footnotes.get(index).cloned() {
+        if index == 0 {
+            if let Some(separator) = separator {
+                plain(renderer).begin_block()?;
+                plain(renderer).write_line(separator)?;
+            }
+        }
+        index += 1;
+        plain(renderer).begin_block()?;
+        plain(renderer).raw(&marker(index))?;
+        render_document(&Document { nodes: content }, renderer)?;
+        renderer.end_paragraph()?;
+    }
+    Ok(())
+}
+
+/// Returns the underline character for a heading command, or `None` if the command is not a
+/// heading.
+fn heading_underline(name: &str) -> Option<char> {
+    match outline::unstarred(name) {
+        "section" => Some('='),
+        "subsection" => Some('-'),
+        _ => None,
+    }
+}
+
+/// A horizontal rule of a table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Rule {
+    Top,
+    /// The rule below a header row.
+    Header,
+    /// The rule between two other rows.
+    Row,
+    Bottom,
+}
+
+impl TableBorders {
+    /// Returns the characters of a horizontal rule: its left end, the line itself, the junctions
+    /// between columns, and its right end.
+    fn rule(self, rule: Rule) -> [char; 4] {
+        match (self, rule) {
+            (TableBorders::Ascii, Rule::Header) => ['+', '=', '+', '+'],
+            (TableBorders::Ascii, _) => ['+', '-', '+', '+'],
+            (TableBorders::Unicode, Rule::Top) => ['┌', '─', '┬', '┐'],
+            (TableBorders::Unicode, Rule::Header) => ['╞', '═', '╪', '╡'],
+            (TableBorders::Unicode, Rule::Row) => ['├', '─', '┼', '┤'],
+            (TableBorders::Unicode, Rule::Bottom) => ['└', '─', '┴', '┘'],
+        }
+    }
+
+    /// Returns the character separating the cells of a row.
+    fn vertical(self) -> char {
+        match self {
+            TableBorders::Ascii => '|',
+            TableBorders::Unicode => '│',
+        }
+    }
+
+    /// Returns the character with which a horizontal rule is drawn.
+    fn horizontal(self) -> char {
+        match self {
+            TableBorders::Ascii => '-',
+            TableBorders::Unicode => '─',
+        }
+    }
+}
+
+/// Returns a horizontal rule of a table with the given column widths.
+fn table_rule(borders: TableBorders, rule: Rule, widths: &[usize]) -> String {
+    let [left, line, junction, right] = borders.rule(rule);
+    let mut s = left.to_string();
+    for (i, &width) in widths.iter().enumerate() {
+        if i > 0 {
+            s.push(junction);
+        }
+        s.extend((0..width + 2).map(|_| line));
+    }
+    s.push(right);
+    s
+}
+
+/// Pads a line of a cell with spaces to the given width, according to the alignment of its
+/// column.
+fn pad_cell(line: &str, width: usize, alignment: Alignment) -> String {
+    let extra = width.saturating_sub(visible_width(line));
+    let before = match alignment {
+        Alignment::Left => 0,
+        Alignment::Center => extra / 2,
+        Alignment::Right => extra,
+    };
+    format!("{}{}{}", " ".repeat(before), line, " ".repeat(extra - before))
+}
+
+/// The deepest level of an `indent` environment.
+pub(super) const MAX_INDENT_LEVEL: usize = 16;
+
+/// Returns the level of an `indent` environment, given by its argument, which defaults to 1. A
+/// level deeper than `MAX_INDENT_LEVEL` is reduced to it, with a `Warning::IndentTooDeep`.
+pub(super) fn indent_level(env: &Environment, report: &mut RenderReport) -> usize {
+    let level = match env.args.first().map(|arg| ast::text_content(arg).trim().parse::<usize>()) {
+        Some(Ok(level)) => level,
+        Some(Err(ref e)) if *e.kind() == IntErrorKind::PosOverflow => usize::MAX,
+        _ => 1,
+    };
+    if level > MAX_INDENT_LEVEL {
+        report.warn(Warning::IndentTooDeep(level, MAX_INDENT_LEVEL));
+        return MAX_INDENT_LEVEL;
+    }
+    level
+}
+
+/// Returns the number of columns of a `columns` environment, given by its argument, which
+/// defaults to 2. There is always at least one.
+pub(super) fn column_count(env: &Environment) -> usize {
+    env.args
+        .first()
+        .and_then(|arg| ast::text_content(arg).trim().parse().ok())
+        .unwrap_or(2)
+        .max(1)
+}
+
+/// Lays out the lines of a `columns` environment side by side in `count` columns of `width`,
+/// separated by `gutter` spaces. The columns are balanced: each but the last holds as many lines
+/// as the first, which holds the lines divided by the columns, rounded up. Blank lines which would
+/// start or end a column are dropped.
+fn balance_columns(lines: &[String], count: usize, width: usize, gutter: usize) -> Vec<String> {
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|line| !line.is_empty()).map_or(start, |i| i + 1);
+    let lines = &lines[start..end];
+    let height = lines.len().div_ceil(count);
+    let mut columns: Vec<&[String]> = Vec::with_capacity(count);
+    let mut rest = lines;
+    while !rest.is_empty() {
+        let blank = rest.iter().take_while(|line| line.is_empty()).count();
+        rest = &rest[blank..];
+        let (column, after) = rest.split_at(height.min(rest.len()));
+        let blank = column.iter().rev().take_while(|line| line.is_empty()).count();
+        columns.push(&column[..column.len() - blank]);
+        rest = after;
+    }
+    let rows = columns.iter().map(|column| column.len()).max().unwrap_or(0);
+    let mut laid_out = Vec::with_capacity(rows);
+    for row in 0..rows {
+        let mut line = String::new();
+        for (i, column) in columns.iter().enumerate() {
+            let cell = column.get(row).map_or("", |cell| cell.as_str());
+            if i > 0 {
+                line.push_str(&" ".repeat(gutter));
+            }
+            line.push_str(cell);
+            line.push_str(&" ".repeat(width.saturating_sub(visible_width(cell))));
+        }
+        laid_out.push(line.trim_end().to_owned());
+    }
+    laid_out
+}
+
+/// Returns the divisor of the space left over on each line by which an alignment command indents
+/// the line, or `None` if the command is not an alignment command.
+fn alignment(name: &str) -> Option<usize> {
+    match name {
+        "centered" => Some(2),
+        "rightalign" => Some(1),
+        _ => None,
+    }
+}
+
+/// Returns the display width of `s`, skipping ANSI escape sequences.
+pub(super) fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut rest = s;
+    while let Some(start) = rest.find('\x1b') {
+        width += text_width(&rest[..start]);
+        rest = &rest[start + escape_length(&rest[start..])..];
+    }
+    width + text_width(rest)
+}
+
+/// Returns the length in bytes of the ANSI escape sequence at the start of `s`, which starts with
+/// `ESC`, or of the rest of `s` if the sequence is not terminated.
+pub(super) fn escape_length(s: &str) -> usize {
+    if s[1..].starts_with(']') {
+        // An operating system command, such as a hyperlink, ends with BEL or `ESC \`.
+        s[1..].find(['\x07', '\x1b']).map_or(s.len(), |i| {
+            let terminator = if s[1 + i..].starts_with('\x07') { 1 } else { 2 };
+            (1 + i + terminator).min(s.len())
+        })
+    } else {
+        // Skip to the final byte of the control sequence.
+        s[1..]
+            .find(|c| ('@'..='~').contains(&c) && c != '[')
+            .map_or(s.len(), |i| 1 + i + 1)
+    }
+}
+
+/// Returns the display width of `s`, in terminal columns.
+#[cfg(feature = "unicode-width")]
+pub(super) fn text_width(s: &str) -> usize {
+    unicode_width::UnicodeWidthStr::width(s)
+}
+
+/// Returns the display width of `s`, counting each character as one column. Enabling the
+/// `unicode-width` feature gives correct widths for wide and zero-width characters.
+#[cfg(not(feature = "unicode-width"))]
+pub(super) fn text_width(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Returns `s` with its soft hyphens removed.
+fn remove_soft_hyphens(s: &str) -> String {
+    s.replace(SOFT_HYPHEN, "")
+}
+
+/// Returns the byte offset of the last soft hyphen in `word` at which it can be broken, such that
+/// the part before it, followed by a `-`, is at most `available` columns wide.
+fn hyphenation_point(word: &str, available: usize) -> Option<usize> {
+    word.match_indices(SOFT_HYPHEN)
+        .map(|(i, _)| i)
+        .rev()
+        .find(|&i| i > 0 && visible_width(&remove_soft_hyphens(&word[..i])) < available)
+}
+
+/// Returns `word` with soft hyphens at the points at which it may be broken, if
+/// `PlainOptions::hyphenate` is set and the word is to be hyphenated.
+#[cfg_attr(not(feature = "hyphenation"), allow(unused_variables))]
+fn hyphenate(word: &str, options: &PlainOptions) -> Option<String> {
+    #[cfg(feature = "hyphenation")]
+    {
+        if let Some(ref hyphenation) = options.hyphenation {
+            return hyphenation.hyphenate(word);
+        }
+    }
+    None
+}
+
+/// Breaks the text of a line into lines at spaces outside of its raw content, so as to fit within
+/// `width` columns, except that a word which is too long on its own is given a line to itself. A
+/// word which does not fit may also be broken at a soft hyphen, or at a point given by
+/// `PlainOptions::hyphenate` outside of raw content and `\code`, with a `-` at the end of the
+/// line. The soft hyphens are removed from the result. If `justify` is set, every line but the last
+/// is justified to `width` with `justify_line`.
+fn wrap_lines(line: &Line, width: usize, justify: bool, options: &PlainOptions) -> Vec<String> {
+    let (text, raw, code) = (&line.text, &line.raw, &line.code);
+    let mut lines = Vec::new();
+    let mut line_width = 0;
+    // The byte offsets in `line` of the spaces between its words.
+    let mut gaps = Vec::new();
+    let mut end_line = |line: &mut String, gaps: &mut Vec<usize>| {
+        if justify {
+            justify_line(line, gaps, width);
+        }
+        gaps.clear();
+        lines.push(line.split_off(0));
+    };
+    let breakable = |i: usize| !raw.iter().any(|&(start, end)| start <= i && i < end);
+    let mut start = 0;
+    let ends = text
+        .match_indices(' ')
+        .map(|(i, _)| i)
+        .filter(|&i| breakable(i))
+        .chain(Some(text.len()));
+    let mut line = String::new();
+    for end in ends {
+        let fixed = raw.iter().chain(code).any(|&(s, e)| s < end && start < e);
+        let hyphenated = if fixed {
+            None
+        } else {
+            hyphenate(&text[start..end], options)
+        };
+        let mut word = hyphenated.as_deref().unwrap_or(&text[start..end]);
+        start = end + 1;
+        loop {
+            let space = if line.is_empty() { 0 } else { 1 };
+            let word_width = visible_width(&remove_soft_hyphens(word));
+            if line_width + space + word_width <= width {
+                if space > 0 {
+                    gaps.push(line.len());
+                    line.push(' ');
+                }
+                line.push_str(&remove_soft_hyphens(word));
+                line_width += space + word_width;
+                break;
+            }
+            let available = width.saturating_sub(line_width + space);
+            if let Some(split) = hyphenation_point(word, available) {
+                if space > 0 {
+                    gaps.push(line.len());
+                    line.push(' ');
+                }
+                line.push_str(&remove_soft_hyphens(&word[..split]));
+                line.push('-');
+                end_line(&mut line, &mut gaps);
+                line_width = 0;
+                word = &word[split + SOFT_HYPHEN.len_utf8()..];
+            } else if line.is_empty() {
+                // The word is too long to fit on a line of its own.
+                line.push_str(&remove_soft_hyphens(word));
+                line_width = word_width;
+                break;
+            } else {
+                end_line(&mut line, &mut gaps);
+                line_width = 0;
+            }
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// Widens the gaps between the words of a line, at the given byte offsets of its spaces, so that
+/// it fills `width` columns. Each gap is widened by the same number of spaces, and any left over
+/// are added one to each gap from the left, so that the same line is always justified the same
+/// way. A line without gaps, or which already fills the width, is left as it is.
+fn justify_line(line: &mut String, gaps: &[usize], width: usize) {
+    let extra = width.saturating_sub(visible_width(line));
+    if gaps.is_empty() || extra == 0 {
+        return;
+    }
+    let (each, remainder) = (extra / gaps.len(), extra % gaps.len());
+    let mut justified = String::with_capacity(line.len() + extra);
+    let mut start = 0;
+    for (n, &gap) in gaps.iter().enumerate() {
+        justified.push_str(&line[start..gap]);
+        let spaces = each + if n < remainder { 1 } else { 0 };
+        justified.extend((0..spaces).map(|_| ' '));
+        start = gap;
+    }
+    justified.push_str(&line[start..]);
+    *line = justified;
+}
+
+/// A line of a paragraph or table cell, ended by a `\break` or by the end of the paragraph or
+/// cell, as collected while rendering it.
+struct Line {
+    text: String,
+    /// The byte ranges of the text holding raw content.
+    raw: Vec<(usize, usize)>,
+    /// The byte ranges of the text within `\code`, which is never hyphenated.
+    code: Vec<(usize, usize)>,
+}
+
+/// The kind of a list.
+enum ListKind {
+    Bullets,
+    /// A numbered list, holding the number of the next item.
+    Numbers(usize),
+    /// A `description` list, whose items are marked by their terms.
+    Terms,
+    /// A `references` environment, whose `\bibitem`s are marked by their numbers.
+    References,
+}
+
+/// A `columns` environment whose lines are being collected, to be laid out side by side once it
+/// ends, or once a verbatim block too wide for its columns interrupts it.
+struct Columns {
+    count: usize,
+    /// The width of each column.
+    width: usize,
+    /// The lines written since the columns were last laid out, each with the indentation of the
+    /// levels within the environment.
+    lines: Vec<String>,
+    /// The levels of indentation outside the environment, which apply to its columns as a whole.
+    levels: Vec<Level>,
+    /// Whether any lines of the environment have been written, so that the next are separated
+    /// from them by a blank line.
+    started: bool,
+}
+
+/// A level of indentation, for a list, an `indent` environment, or a `quote` environment.
+struct Level {
+    /// The kind of list, or `None` for an `indent` or `quote` environment.
+    list: Option<ListKind>,
+    /// The width by which the content is indented. For a list, this is the width of the marker
+    /// of the current item.
+    indent: usize,
+    /// For a `quote` environment, the prefix written before each line in place of the
+    /// indentation.
+    prefix: Option<String>,
+    /// For a list, the marker of the current item, if its first line has not yet been written.
+    marker: Option<String>,
+}
+
+/// The `Renderer` implementation backing `render`, which is also used by the ANSI renderer to lay
+/// out its text.
+pub(super) struct PlainWriter<'a, W: 'a> {
+    options: &'a PlainOptions,
+    out: &'a mut W,
+    /// The width of the output, as given by `PlainOptions::get_width` when rendering began.
+    width: usize,
+    /// The text of the current paragraph or heading, with whitespace collapsed.
+    buffer: String,
+    /// Whether the buffer is empty or ends with a collapsed space, ignoring escape sequences.
+    at_space: bool,
+    /// The byte ranges of the buffer holding raw content, which is never broken across lines.
+    raw: Vec<(usize, usize)>,
+    /// The byte ranges of the buffer within `\code`, which is never hyphenated.
+    code: Vec<(usize, usize)>,
+    /// The start in the buffer of the `\code` being rendered, if any.
+    code_start: Option<usize>,
+    /// Whether any block has been written yet.
+    started: bool,
+    /// The levels of indentation of the current block, innermost last.
+    levels: Vec<Level>,
+    /// The lines of the current paragraph, heading, or cell which have been ended by a `\break`,
+    /// before the one held in `buffer`.
+    lines: Vec<Line>,
+    /// The cells of the rows of the current table, each as its lines.
+    rows: Vec<Vec<Vec<Line>>>,
+    /// The `columns` environments being collected, innermost last.
+    columns: Vec<Columns>,
+    /// The content of the footnotes, in order of their numbers.
+    footnotes: Vec<Vec<Node>>,
+    /// The headings of the document.
+    headings: Vec<Heading>,
+    /// The index in `headings` of the next heading to be rendered.
+    next_heading: usize,
+    /// The numbers of the entries of the bibliography.
+    citations: Citations,
+    /// The index of the document.
+    index: Vec<Group>,
+    /// The abbreviations of the document, and which have been used.
+    pub(super) abbreviations: Abbreviations,
+    /// The warnings encountered so far.
+    pub(super) report: RenderReport,
+}
+
+impl<'a, W: Write> PlainWriter<'a, W> {
+    /// Constructs a new `PlainWriter` for the given document, whose headings, `\bibitem`s, and
+    /// abbreviations are used to resolve references, citations, and `\abbr`s.
+    pub(super) fn new(
+        document: &Document,
+        options: &'a PlainOptions,
+        out: &'a mut W,
+    ) -> PlainWriter<'a, W> {
+        let headings = outline::headings(&document.nodes, &options.numbering);
+        PlainWriter {
+            options,
+            out,
+            width: options.get_width(),
+            buffer: String::new(),
+            at_space: true,
+            raw: Vec::new(),
+            code: Vec::new(),
+            code_start: None,
+            started: false,
+            levels: Vec::new(),
+            lines: Vec::new(),
+            rows: Vec::new(),
+            columns: Vec::new(),
+            footnotes: Vec::new(),
+            index: index::groups(&document.nodes, &headings, &options.locale),
+            headings,
+            next_heading: 0,
+            citations: Citations::new(&document.nodes),
+            abbreviations: Abbreviations::new(&document.nodes)
+                .reset_at_sections(options.reset_abbreviations),
+            report: RenderReport::new(),
+        }
+    }
+
+    /// Appends markup, such as an escape sequence, to the current paragraph or heading. Markup
+    /// does not affect whitespace collapsing.
+    pub(super) fn push_markup(&mut self, escape: &str) {
+        self.buffer.push_str(escape);
+    }
+
+    /// Adds a footnote with the content of the given command, to be written by `write_footnotes`,
+    /// and returns its number.
+    pub(super) fn add_footnote(&mut self, command: &Command) -> usize {
+        self.footnotes
+            .push(command.args.first().cloned().unwrap_or_default());
+        self.footnotes.len()
+    }
+
+    /// Ends the current line of the paragraph at a `\break`. Whitespace around the break is
+    /// dropped.
+    pub(super) fn line_break(&mut self) {
+        if let Some(start) = self.code_start.replace(0) {
+            self.code.push((start, self.buffer.len()));
+        }
+        let raw = self.raw.split_off(0);
+        let code = self.code.split_off(0);
+        let text = self.take_buffer();
+        self.lines.push(Line { text, raw, code });
+    }
+
+    /// Returns the lines of the current paragraph, heading, or cell, as ended by `\break`, and
+    /// clears it. Breaks at the end are dropped, so there is always at least one line, and it is
+    /// only empty if there are no others.
+    fn take_broken_lines(&mut self) -> Vec<Line> {
+        self.line_break();
+        let mut lines = self.lines.split_off(0);
+        while lines.len() > 1 && lines.last().is_some_and(|line| line.text.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+
+    /// Returns the lines of the current paragraph or heading, as ended by `\break`, without
+    /// trailing whitespace or soft hyphens, and clears it.
+    pub(super) fn take_text_lines(&mut self) -> Vec<String> {
+        let lines = self.take_broken_lines();
+        lines.iter().map(|line| remove_soft_hyphens(&line.text)).collect()
+    }
+
+    /// Returns the text of the current paragraph or heading, without trailing whitespace or soft
+    /// hyphens, and clears it. Lines ended by `\break` are joined by a space.
+    pub(super) fn take_text(&mut self) -> String {
+        self.take_text_lines().join(" ")
+    }
+
+    /// Returns the contents of the buffer, without trailing whitespace, and clears it.
+    fn take_buffer(&mut self) -> String {
+        let len = self.buffer.trim_end().len();
+        self.buffer.truncate(len);
+        self.at_space = true;
+        self.raw.clear();
+        self.code.clear();
+        self.buffer.split_off(0)
+    }
+
+    /// Returns the width available for the content of the current block, within the enclosing
+    /// list items, and within a column of the innermost `columns` environment, if any.
+    fn content_width(&self) -> usize {
+        let depth: usize = self.levels.iter().map(|level| level.indent).sum();
+        let width = self.columns.last().map_or(self.width, |columns| columns.width);
+        width.saturating_sub(depth).max(1)
+    }
+
+    /// Starts collecting the lines of a `columns` environment, in columns which share the width
+    /// left by the enclosing levels, less the gutters between them. There are no more columns
+    /// than that width, since each is at least one terminal column wide.
+    fn begin_columns(&mut self, count: usize) -> Result<(), Error> {
+        self.begin_block()?;
+        let count = count.min(self.content_width());
+        let gutters = self.options.column_gutter.saturating_mul(count - 1);
+        let width = (self.content_width().saturating_sub(gutters) / count).max(1);
+        self.columns.push(Columns {
+            count,
+            width,
+            lines: Vec::new(),
+            levels: mem::take(&mut self.levels),
+            started: false,
+        });
+        self.started = false;
+        Ok(())
+    }
+
+    /// Lays out the lines collected by the innermost `columns` environment with `balance_columns`
+    /// and writes them at the levels outside it. The environment is removed, and returned so that
+    /// it can be resumed, and the levels outside it are left in place.
+    fn write_columns(&mut self) -> Result<Option<Columns>, Error> {
+        let mut columns = match self.columns.pop() {
+            Some(columns) => columns,
+            None => return Ok(None),
+        };
+        let gutter = self.options.column_gutter;
+        let lines = balance_columns(&columns.lines, columns.count, columns.width, gutter);
+        columns.lines.clear();
+        let inner = mem::replace(&mut self.levels, mem::take(&mut columns.levels));
+        columns.levels = inner;
+        if !lines.is_empty() {
+            if columns.started {
+                self.write_line("")?;
+            }
+            for line in &lines {
+                self.write_line(line)?;
+            }
+            columns.started = true;
+        }
+        self.started = columns.started;
+        Ok(Some(columns))
+    }
+
+    /// Writes a verbatim block which is too wide for the columns of the innermost `columns`
+    /// environment across their full width, after laying out the lines collected before it. The
+    /// lines after it are collected again.
+    fn break_columns(&mut self, content: &str, begin: &str, end: &str) -> Result<(), Error> {
+        let mut columns = match self.write_columns()? {
+            Some(columns) => columns,
+            None => return Ok(()),
+        };
+        self.styled_verbatim(content, begin, end)?;
+        columns.started = true;
+        mem::swap(&mut self.levels, &mut columns.levels);
+        self.columns.push(columns);
+        self.started = false;
+        Ok(())
+    }
+
+    /// Ends the innermost `columns` environment, writing its remaining lines.
+    fn end_columns(&mut self) -> Result<(), Error> {
+        self.write_columns().map(|_| ())
+    }
+
+    /// Returns the text of the current paragraph broken into lines, as with `take_text_lines`. If
+    /// `wrap` is set, each line is wrapped with `wrap_lines` to the width left by the enclosing
+    /// list items, and justified to that width if `justify` is also set.
+    fn take_lines(&mut self, wrap: bool, justify: bool) -> Vec<String> {
+        let width = self.content_width();
+        let mut lines = Vec::new();
+        for line in self.take_broken_lines() {
+            if wrap {
+                lines.extend(wrap_lines(&line, width, justify, self.options));
+            } else {
+                lines.push(remove_soft_hyphens(&line.text));
+            }
+        }
+        self.display_order(&mut lines);
+        lines
+    }
+
+    /// Reorders the lines of a paragraph for display, if `PlainOptions::direction` is set.
+    #[cfg_attr(not(feature = "bidi"), allow(unused_variables))]
+    fn display_order(&self, lines: &mut [String]) {
+        #[cfg(feature = "bidi")]
+        {
+            if let Some(direction) = self.options.direction {
+                bidi::reorder(lines, direction);
+            }
+        }
+    }
+
+    /// Writes the table whose cells have been collected in `rows`.
+    fn write_table(&mut self, table: &Table) -> Result<(), Error> {
+        let rows = self.rows.split_off(0);
+        let count = table.column_count();
+        if count == 0 {
+            return Ok(());
+        }
+        let mut widths = vec![1; count];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                for line in cell {
+                    let natural = visible_width(&remove_soft_hyphens(&line.text));
+                    *width = (*width).max(natural.min(self.options.max_column_width));
+                }
+            }
+        }
+        // Shrink the widest column until the table fits, allowing for the borders and padding.
+        let available = self.content_width().saturating_sub(3 * count + 1);
+        while widths.iter().sum::<usize>() > available {
+            let widest = (0..count).max_by_key(|&i| (widths[i], count - i)).unwrap_or(0);
+            if widths[widest] <= 1 {
+                break;
+            }
+            widths[widest] -= 1;
+        }
+        let lines: Vec<Vec<Vec<String>>> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| {
+                        let mut lines: Vec<_> = cell
+                            .iter()
+                            .flat_map(|line| wrap_lines(line, width, false, self.options))
+                            .collect();
+                        self.display_order(&mut lines);
+                        lines
+                    })
+                    .collect()
+            })
+            .collect();
+        // A word which is too long to be wrapped widens its column.
+        for row in &lines {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                for line in cell {
+                    *width = (*width).max(visible_width(line));
+                }
+            }
+        }
+        let borders = self.options.table_borders;
+        let vertical = borders.vertical();
+        self.write_line(&table_rule(borders, Rule::Top, &widths))?;
+        for (i, row) in lines.iter().enumerate() {
+            if i > 0 {
+                let rule = if i == 1 && table.header {
+                    Rule::Header
+                } else {
+                    Rule::Row
+                };
+                self.write_line(&table_rule(borders, rule, &widths))?;
+            }
+            let height = row.iter().map(Vec::len).max().unwrap_or(1);
+            for k in 0..height {
+                let mut line = vertical.to_string();
+                for (column, &width) in widths.iter().enumerate() {
+                    let text = row
+                        .get(column)
+                        .and_then(|cell| cell.get(k))
+                        .map_or("", |line| &**line);
+                    line.push(' ');
+                    line.push_str(&pad_cell(text, width, table.alignment(column)));
+                    line.push(' ');
+                    line.push(vertical);
+                }
+                self.write_line(&line)?;
+            }
+        }
+        self.write_line(&table_rule(borders, Rule::Bottom, &widths))
+    }
+
+    /// Writes a horizontal rule as a block, spanning the width left by the enclosing list items.
+    pub(super) fn horizontal_rule(&mut self, style: RuleStyle) -> Result<(), Error> {
+        self.begin_block()?;
+        let c = style
+            .character
+            .unwrap_or_else(|| self.options.table_borders.horizontal());
+        let columns = style.columns(self.content_width());
+        let count = (columns / text_width(c.encode_utf8(&mut [0; 4])).max(1)).max(1);
+        let rule: String = (0..count).map(|_| c).collect();
+        self.write_line(&rule)
+    }
+
+    /// Writes the index, if it has any entries, as a block for the heading of each group,
+    /// followed by a block of its entries, one to a line, with each line given by `entry` from
+    /// the entry and its depth, which is 0 for an entry and 1 for a sub-entry.
+    pub(super) fn write_index<H, E>(&mut self, heading: H, entry: E) -> Result<(), Error>
+    where
+        H: Fn(&str) -> String,
+        E: Fn(&Entry, usize) -> String,
+    {
+        for group in self.index.clone() {
+            self.begin_block()?;
+            self.write_line(&heading(&group.letter))?;
+            self.begin_block()?;
+            self.write_index_entries(&group.entries, 0, &entry)?;
+        }
+        Ok(())
+    }
+
+    fn write_index_entries<E>(
+        &mut self,
+        entries: &[Entry],
+        depth: usize,
+        line: &E,
+    ) -> Result<(), Error>
+    where
+        E: Fn(&Entry, usize) -> String,
+    {
+        for entry in entries {
+            self.write_line(&line(entry, depth))?;
+            self.write_index_entries(&entry.subentries, depth + 1, line)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a verbatim block, surrounding each line with the given escape sequences.
+    pub(super) fn styled_verbatim(
+        &mut self,
+        content: &str,
+        begin: &str,
+        end: &str,
+    ) -> Result<(), Error> {
+        if !self.columns.is_empty() {
+            let width = self.content_width();
+            let indent = self.options.verbatim_indent;
+            if content.lines().any(|line| indent + visible_width(line) > width) {
+                return self.break_columns(content, begin, end);
+            }
+        }
+        self.begin_block()?;
+        let indent = " ".repeat(self.options.verbatim_indent);
+        for line in content.lines() {
+            if line.is_empty() {
+                self.write_line("")?;
+            } else {
+                self.write_line(&format!("{}{}{}{}", indent, begin, line, end))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<(), Error> {
+        self.out.write_all(s.as_bytes()).context(ErrorKind::Write)?;
+        Ok(())
+    }
+
+    /// Writes a line of a block, indented to the level of the enclosing list items, and after
+    /// the prefixes of the enclosing quotes. The first non-empty line of a list item is preceded
+    /// by its marker, in place of the indentation of its list, so that the first line of an item
+    /// which starts with a nested list holds the markers of both. An empty line has only the
+    /// prefixes, without trailing whitespace. Within a `columns` environment, the line is
+    /// collected to be laid out in its columns rather than written.
+    pub(super) fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let mut indent = String::new();
+        for level in &mut self.levels {
+            let marker = if line.is_empty() {
+                None
+            } else {
+                level.marker.take()
+            };
+            match (&level.prefix, marker) {
+                (Some(prefix), _) => indent.push_str(prefix),
+                (None, Some(marker)) => indent.push_str(&marker),
+                (None, None) => indent.push_str(&" ".repeat(level.indent)),
+            }
+        }
+        if let Some(columns) = self.columns.last_mut() {
+            if line.is_empty() {
+                indent.truncate(indent.trim_end().len());
+            } else {
+                indent.push_str(line);
+            }
+            columns.lines.push(indent);
+            return Ok(());
+        }
+        if line.is_empty() {
+            self.write_str(indent.trim_end())?;
+        } else {
+            self.write_str(&indent)?;
+            self.write_str(line)?;
+        }
+        self.write_str("\n")
+    }
+
+    /// Ends the term of an item of a `description` list, which has been collected as the text of
+    /// a block. The first line of the item's content follows in the same block, after `marker`,
+    /// and the rest of its lines are indented by `indent`.
+    pub(super) fn end_term(&mut self, marker: String, indent: usize) {
+        if let Some(level) = self.levels.last_mut() {
+            level.indent = indent;
+            level.marker = Some(marker);
+        }
+        self.started = false;
+    }
+
+    /// Ends the innermost level of indentation, that of a list, an `indent` environment, or a
+    /// `quote` environment.
+    pub(super) fn pop_level(&mut self) {
+        self.levels.pop();
+    }
+
+    /// Returns whether the current paragraph has text which has not been written.
+    pub(super) fn has_pending_text(&self) -> bool {
+        visible_width(self.buffer.trim()) > 0
+            || self.lines.iter().any(|line| visible_width(line.text.trim()) > 0)
+    }
+
+    /// Starts a new block, separating it from the previous one by a blank line. Text of the
+    /// current paragraph which has not been written, such as that before a verbatim block or a
+    /// list within a group, is first written as a paragraph of its own.
+    pub(super) fn begin_block(&mut self) -> Result<(), Error> {
+        if self.has_pending_text() {
+            self.end_paragraph()?;
+        }
+        if self.started {
+            self.write_line("")?;
+        }
+        self.started = true;
+        self.buffer.clear();
+        self.raw.clear();
+        self.code.clear();
+        self.lines.clear();
+        self.at_space = true;
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Renderer for PlainWriter<'a, W> {
+    fn is_cancelled(&self) -> bool {
+        is_set(self.options.cancel_token.as_ref())
+    }
+
+    fn begin_block(&mut self, span: Span) -> Result<(), Error> {
+        self.report.set_span(Some(span));
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        for c in text.chars() {
+            if !c.is_whitespace() {
+                self.buffer.push(c);
+                self.at_space = false;
+            } else if !self.at_space {
+                self.buffer.push(' ');
+                self.at_space = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        let content = &verbatim.content;
+        self.styled_verbatim(content, "", "")
+    }
+
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        if !content.is_empty() {
+            let start = self.buffer.len();
+            self.buffer.push_str(content);
+            self.raw.push((start, self.buffer.len()));
+            self.at_space = content.ends_with(' ');
+        }
+        Ok(())
+    }
+
+    fn begin_paragraph(&mut self) -> Result<(), Error> {
+        self.begin_block()
+    }
+
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        let wrap = self.options.wrap || !self.columns.is_empty();
+        for line in self.take_lines(wrap, self.options.justify) {
+            self.write_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        match &*command.name {
+            "emph" | "bold" | "color" | "link" => Ok(Visit::Children),
+            "code" => {
+                self.code_start = Some(self.buffer.len());
+                Ok(Visit::Children)
+            }
+            "break" => {
+                self.line_break();
+                Ok(Visit::SkipChildren)
+            }
+            "ref" => {
+                let reference = outline::reference_text(&self.headings, command, &mut self.report);
+                self.raw(&reference)?;
+                Ok(Visit::SkipChildren)
+            }
+            "printindex" => {
+                let indent = self.options.indent_unit;
+                self.write_index(str::to_owned, |entry, depth| {
+                    format!("{}{}", " ".repeat(depth * indent), entry.text())
+                })?;
+                Ok(Visit::SkipChildren)
+            }
+            "cite" => {
+                let marker = self.citations.marker(command, &mut self.report);
+                self.raw(&marker)?;
+                Ok(Visit::SkipChildren)
+            }
+            "abbr" => {
+                let abbreviation = self
+                    .abbreviations
+                    .use_abbreviation(command, &mut self.report);
+                self.text(&abbreviation.text())?;
+                Ok(Visit::SkipChildren)
+            }
+            "footnote" => {
+                let number = self.add_footnote(command);
+                self.raw(&format!("[{}]", number))?;
+                Ok(Visit::SkipChildren)
+            }
+            "image" => {
+                let image = Image::new(command, &mut self.report);
+                match image.alt {
+                    Some(ref alt) if alt.is_empty() => {}
+                    alt => self.raw(&format!("[{}]", alt.unwrap_or(image.path)))?,
+                }
+                Ok(Visit::SkipChildren)
+            }
+            "item" => {
+                if let Some(level) = self.levels.last_mut() {
+                    let marker = match level.list {
+                        Some(ListKind::Numbers(ref mut number)) => {
+                            *number += 1;
+                            format!("{}. ", *number - 1)
+                        }
+                        Some(ListKind::Bullets) => "- ".to_owned(),
+                        // The marker is the term, which is the item's first argument.
+                        Some(ListKind::Terms) | Some(ListKind::References) | None => {
+                            return Ok(Visit::Children)
+                        }
+                    };
+                    level.indent = marker.len();
+                    level.marker = Some(marker);
+                }
+                Ok(Visit::Children)
+            }
+            "bibitem" => {
+                let number = self.citations.number(&bibliography::bibitem_key(command));
+                let marker = format!("[{}] ", bibliography::number_text(number));
+                if let Some(level) = self.levels.last_mut() {
+                    level.indent = marker.len();
+                    level.marker = Some(marker);
+                }
+                Ok(Visit::Children)
+            }
+            name if is_horizontal_rule(name) => {
+                self.horizontal_rule(RuleStyle::new(command))?;
+                Ok(Visit::SkipChildren)
+            }
+            name if heading_underline(name).is_some() || alignment(name).is_some() => {
+                self.abbreviations.heading(command);
+                self.begin_block()?;
+                Ok(Visit::Children)
+            }
+            _ => Ok(Visit::SkipChildren),
+        }
+    }
+
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        if let Some(underline) = heading_underline(&command.name) {
+            let mut heading = [self.take_text()];
+            self.display_order(&mut heading);
+            let [heading] = heading;
+            let underline: String = (0..visible_width(&heading)).map(|_| underline).collect();
+            self.write_line(&heading)?;
+            self.write_line(&underline)?;
+        } else if let Some(align) = alignment(&command.name) {
+            let width = self.content_width();
+            for line in self.take_lines(true, false) {
+                let padding = width.saturating_sub(visible_width(&line)) / align;
+                self.write_line(&format!("{}{}", " ".repeat(padding), line))?;
+            }
+        } else if command.name == "code" {
+            if let Some(start) = self.code_start.take() {
+                self.code.push((start, self.buffer.len()));
+            }
+        } else if command.name == "link" {
+            if has_link_text(command) {
+                self.text(" ")?;
+            }
+            self.raw(&format!("<{}>", link_url(command)))?;
+        } else if command.name == "item" || command.name == "bibitem" {
+            // An empty item is still written, as a line holding only its marker.
+            if let Some(marker) = self.levels.last_mut().and_then(|level| level.marker.take()) {
+                self.begin_block()?;
+                if let Some(level) = self.levels.last_mut() {
+                    level.indent = 0;
+                }
+                self.write_line(marker.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        // The first argument of `\color` names the color, that of `\link` gives the URL, and that
+        // of `\bibitem` gives the key, and none is part of the content.
+        if let ("color" | "link" | "bibitem", 0) = (&*command.name, index) {
+            return Ok(Visit::SkipChildren);
+        }
+        // A numbered heading starts with its number, ahead of its title.
+        if heading_underline(&command.name).is_some() && index == 0 {
+            let number = self
+                .headings
+                .get(self.next_heading)
+                .and_then(|heading| heading.formatted_number.clone())
+                .filter(|_| self.options.number_headings);
+            self.next_heading += 1;
+            if let Some(number) = number {
+                self.text(&format!("{} ", number))?;
+            }
+        }
+        // The term of a description item is collected as a block, which its content continues.
+        if item_term(command).is_some() && index == 0 {
+            self.begin_block()?;
+            if let Some(level) = self.levels.last_mut() {
+                level.indent = 0;
+            }
+        }
+        // The text of a `\bibitem` is a paragraph, which follows its marker.
+        if let ("bibitem", 1) = (&*command.name, index) {
+            self.begin_paragraph()?;
+        }
+        Ok(Visit::Children)
+    }
+
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        if item_term(command).is_some() && index == 0 {
+            let marker = format!("{} : ", self.take_text());
+            let marker = marker.trim_start().to_owned();
+            let indent = visible_width(&marker);
+            self.end_term(marker, indent);
+        }
+        if let ("bibitem", 1) = (&*command.name, index) {
+            self.end_paragraph()?;
+        }
+        Ok(())
+    }
+
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        let level = match &*env.name {
+            "itemize" => Level {
+                list: Some(ListKind::Bullets),
+                indent: 0,
+                prefix: None,
+                marker: None,
+            },
+            "enumerate" => Level {
+                list: Some(ListKind::Numbers(1)),
+                indent: 0,
+                prefix: None,
+                marker: None,
+            },
+            "description" => Level {
+                list: Some(ListKind::Terms),
+                indent: 0,
+                prefix: None,
+                marker: None,
+            },
+            "references" => Level {
+                list: Some(ListKind::References),
+                indent: 0,
+                prefix: None,
+                marker: None,
+            },
+            "indent" => Level {
+                list: None,
+                indent: indent_level(env, &mut self.report)
+                    .saturating_mul(self.options.indent_unit),
+                prefix: None,
+                marker: None,
+            },
+            "columns" => {
+                self.begin_columns(column_count(env))?;
+                return Ok(Visit::Children);
+            }
+            "quote" => {
+                // The quote is separated from the previous block outside of it, so that the blank
+                // line has no prefix, and its first block is not separated again.
+                self.begin_block()?;
+                self.started = false;
+                let prefix = self.options.quote_prefix.clone();
+                Level {
+                    list: None,
+                    indent: visible_width(&prefix),
+                    prefix: Some(prefix),
+                    marker: None,
+                }
+            }
+            _ => return Ok(Visit::Children),
+        };
+        // Text before the environment within the same paragraph keeps the indentation outside it.
+        if self.has_pending_text() {
+            self.end_paragraph()?;
+        }
+        self.levels.push(level);
+        Ok(Visit::Children)
+    }
+
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        if env.name == "quote" {
+            if let Some(attribution) = quote_attribution(env) {
+                self.begin_block()?;
+                self.text(&attribution)?;
+                self.end_paragraph()?;
+            }
+        }
+        if let "itemize" | "enumerate" | "description" | "references" | "indent" | "quote" =
+            &*env.name
+        {
+            self.pop_level();
+        } else if env.name == "columns" {
+            self.end_columns()?;
+        }
+        Ok(())
+    }
+
+    fn begin_table(&mut self, _table: &Table) -> Result<Visit, Error> {
+        self.begin_block()?;
+        self.rows.clear();
+        Ok(Visit::Children)
+    }
+
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        self.write_table(table)?;
+        if !table.caption.is_empty() {
+            self.begin_block()?;
+            let caption = Document {
+                nodes: table.caption.clone(),
+            };
+            render_document(&caption, self)?;
+            self.end_paragraph()?;
+        }
+        Ok(())
+    }
+
+    fn begin_row(&mut self, _table: &Table, _row: usize) -> Result<(), Error> {
+        self.rows.push(Vec::new());
+        Ok(())
+    }
+
+    fn begin_cell(&mut self, _table: &Table, _row: usize, _column: usize) -> Result<(), Error> {
+        self.take_broken_lines();
+        Ok(())
+    }
+
+    fn end_cell(&mut self, _table: &Table, _row: usize, _column: usize) -> Result<(), Error> {
+        let cell = self.take_broken_lines();
+        if let Some(row) = self.rows.last_mut() {
+            row.push(cell);
+        }
+        Ok(())
+    }
+}
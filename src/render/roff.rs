@@ -0,0 +1,726 @@
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use ast::{Alignment, Command, Document, Environment, Node, Table, Verbatim};
+use bibliography::{self, Citations};
+use errors::{Error, ErrorKind, ResultExt};
+use locale::Locale;
+use tokenize::SOFT_HYPHEN;
+use super::abbreviations::Abbreviations;
+use super::index::{self, Entry, Group};
+use super::outline::{self, Heading, NumberingScheme};
+use super::plain;
+use super::{metadata, quote_attribution, render_document, FmtWriter, RenderReport};
+use super::{has_link_text, is_horizontal_rule, is_set, item_term, link_url};
+use super::{Image, Renderer, RuleStyle, Visit};
+
+/// Options controlling the output of the roff renderer.
+#[derive(Clone, Debug)]
+pub struct RoffOptions {
+    section: String,
+    numbering: NumberingScheme,
+    number_headings: bool,
+    reset_abbreviations: bool,
+    locale: Locale,
+    cancel_token: Option<Arc<AtomicBool>>,
+}
+
+impl Default for RoffOptions {
+    fn default() -> RoffOptions {
+        RoffOptions {
+            section: "1".into(),
+            numbering: NumberingScheme::default(),
+            number_headings: true,
+            reset_abbreviations: false,
+            locale: Locale::default(),
+            cancel_token: None,
+        }
+    }
+}
+
+impl RoffOptions {
+    /// Constructs a new `RoffOptions` with the default settings.
+    pub fn new() -> RoffOptions {
+        RoffOptions::default()
+    }
+
+    /// Sets the manual section given in the `.TH` header. Defaults to `1`.
+    pub fn section<S: Into<String>>(mut self, section: S) -> RoffOptions {
+        self.section = section.into();
+        self
+    }
+
+    /// Sets the scheme by which headings are numbered in headings and references. Defaults to
+    /// `NumberingScheme::new()`.
+    pub fn numbering(mut self, numbering: NumberingScheme) -> RoffOptions {
+        self.numbering = numbering;
+        self
+    }
+
+    /// Sets whether each numbered heading starts with its number. Defaults to `true`.
+    pub fn number_headings(mut self, number: bool) -> RoffOptions {
+        self.number_headings = number;
+        self
+    }
+
+    /// Sets whether each abbreviation is expanded again at its first use after every `\section`,
+    /// rather than only at its first use in the document. Defaults to `false`.
+    pub fn reset_abbreviations(mut self, reset: bool) -> RoffOptions {
+        self.reset_abbreviations = reset;
+        self
+    }
+
+    /// Sets the locale by which the strings written by the renderer rather than taken from the
+    /// document, such as the location of the entries of the index before the first heading, are
+    /// translated. Defaults to English.
+    pub fn locale(mut self, locale: Locale) -> RoffOptions {
+        self.locale = locale;
+        self
+    }
+
+    /// Sets a flag which stops rendering with an `ErrorKind::Cancelled` error once it is set, as
+    /// with `HtmlOptions::cancel_token`. Defaults to none.
+    pub fn cancel_token(mut self, token: Arc<AtomicBool>) -> RoffOptions {
+        self.cancel_token = Some(token);
+        self
+    }
+}
+
+/// Renders a document as a man(7) page to the given output.
+///
+/// The `.TH` header is taken from the `\title` and `\date` commands at the top level of the
+/// document, which are otherwise omitted. Headings become `.SH` and `.SS`, lists become `.IP`
+/// paragraphs, the items of `description` lists become `.TP` paragraphs tagged with their terms,
+/// citations are written as the numbers of the cited entries in brackets, the `\bibitem`s of a
+/// `references` environment become `.IP` paragraphs tagged with the same numbers, verbatim blocks
+/// are written in no-fill mode, `\printindex` becomes a paragraph for each letter of the index,
+/// with its entries on lines of their own, and `\break` becomes `.br`. Tables are written for
+/// tbl(1), followed by their captions, and horizontal rules are drawn with `\l`. As with the
+/// plain-text renderer, links are followed by their URLs in angle brackets, images are written as
+/// their alternative text in brackets, footnotes are numbered in brackets and written at the end
+/// of the document, abbreviations are expanded at their first use, and commands which produce no
+/// text are omitted.
+pub fn render<W: Write>(
+    document: &Document,
+    options: &RoffOptions,
+    out: &mut W,
+) -> Result<RenderReport, Error> {
+    let title = metadata(document, "title").to_uppercase();
+    let date = metadata(document, "date");
+    // The comment tells man(1) to run the page through tbl(1).
+    if has_table(&document.nodes) {
+        writeln!(out, "'\\\" t").context(ErrorKind::Write)?;
+    }
+    writeln!(
+        out,
+        ".TH {} {} {}",
+        quote(&title),
+        quote(&options.section),
+        quote(&date)
+    )
+    .context(ErrorKind::Write)?;
+    let headings = outline::headings(&document.nodes, &options.numbering);
+    let mut writer = RoffWriter {
+        out,
+        number_headings: options.number_headings,
+        cancel_token: options.cancel_token.as_ref(),
+        buffer: String::new(),
+        at_space: true,
+        lines: Vec::new(),
+        fonts: Vec::new(),
+        lists: Vec::new(),
+        item_started: false,
+        cells: Vec::new(),
+        footnotes: Vec::new(),
+        index: index::groups(&document.nodes, &headings, &options.locale),
+        headings,
+        next_heading: 0,
+        citations: Citations::new(&document.nodes),
+        abbreviations: Abbreviations::new(&document.nodes)
+            .reset_at_sections(options.reset_abbreviations),
+        report: RenderReport::new(),
+    };
+    render_document(document, &mut writer)?;
+    writer.write_footnotes()?;
+    Ok(writer.report)
+}
+
+/// Renders a document as a man(7) page to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    options: &RoffOptions,
+    out: &mut F,
+) -> Result<RenderReport, Error> {
+    render(document, options, &mut FmtWriter::new(out))
+}
+
+/// Renders a document as a man(7) page, returning the result as a `String`. Any warnings
+/// are discarded.
+pub fn render_to_string(document: &Document, options: &RoffOptions) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, options, &mut out)?;
+    Ok(out)
+}
+
+/// Escapes the backslashes in `s`, which would otherwise begin roff escape sequences.
+pub fn escape(s: &str) -> String {
+    s.replace('\\', "\\e")
+}
+
+/// Protects a line of text which starts with `.` or `'`, which would otherwise be interpreted as
+/// a request.
+fn protect(line: &str) -> String {
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line.to_owned()
+    }
+}
+
+/// Returns whether there is a table among the nodes, at any depth.
+fn has_table(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match *node {
+        Node::Table(_) => true,
+        Node::Paragraph(ref nodes) | Node::Group(ref nodes) => has_table(nodes),
+        Node::Command(ref command) => command.args.iter().any(|arg| has_table(arg)),
+        Node::Environment(ref env) => has_table(&env.nodes),
+        Node::Text(_) | Node::Verbatim(_) | Node::Raw(_) => false,
+    })
+}
+
+/// Returns the text of a table cell as it is written in a tbl(1) row. A cell which would
+/// otherwise be read as a rule, or as a request, is protected with `\&`.
+fn table_cell(text: &str) -> String {
+    match text {
+        "_" | "=" => format!("\\&{}", text),
+        text => protect(text),
+    }
+}
+
+/// Returns the tbl(1) key letter which aligns a column.
+fn column_key(alignment: Alignment) -> &'static str {
+    match alignment {
+        Alignment::Left => "l",
+        Alignment::Center => "c",
+        Alignment::Right => "r",
+    }
+}
+
+/// Returns the `\l` escape which draws a horizontal rule across the given fraction of the line
+/// length, with the given character, if any.
+fn rule_escape(style: RuleStyle) -> String {
+    let mut rule = match style.width {
+        Some(width) => format!("\\l'\\n(.lu*{}/100", (width * 100.0).round() as usize),
+        None => "\\l'\\n(.lu".to_owned(),
+    };
+    if let Some(c) = style.character {
+        // `\&` keeps the character from being read as part of the length.
+        rule.push_str("\\&");
+        match c {
+            '\'' => rule.push_str("\\(aq"),
+            '\\' => rule.push_str("\\e"),
+            c => rule.push(c),
+        }
+    }
+    rule.push('\'');
+    rule
+}
+
+/// Appends a line for each of the given entries of the index, and each of their sub-entries,
+/// indented by unpaddable spaces according to its depth.
+fn index_lines(entries: &[Entry], depth: usize, lines: &mut Vec<String>) {
+    for entry in entries {
+        let text = escape(&entry.text());
+        lines.push(match depth {
+            0 => protect(&text),
+            depth => format!("{}{}", "\\ \\ ".repeat(depth), text),
+        });
+        index_lines(&entry.subentries, depth + 1, lines);
+    }
+}
+
+/// Returns `s` as a quoted macro argument.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s).replace('"', "\\(dq"))
+}
+
+/// A font selected by a formatting command.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Font {
+    bold: bool,
+    italic: bool,
+}
+
+impl Font {
+    /// Returns the escape sequence which selects this font.
+    fn escape(self) -> &'static str {
+        match (self.bold, self.italic) {
+            (false, false) => "\\fR",
+            (true, false) => "\\fB",
+            (false, true) => "\\fI",
+            (true, true) => "\\f(BI",
+        }
+    }
+}
+
+/// The `Renderer` implementation backing `render`.
+struct RoffWriter<'a, W: 'a> {
+    out: &'a mut W,
+    /// Whether numbered headings start with their numbers.
+    number_headings: bool,
+    /// The flag which cancels rendering, if any.
+    cancel_token: Option<&'a Arc<AtomicBool>>,
+    /// The text of the current paragraph or heading, with whitespace collapsed.
+    buffer: String,
+    /// Whether the buffer is empty or ends with a collapsed space.
+    at_space: bool,
+    /// The lines of the current paragraph or heading which have been ended by a `\break`, before
+    /// the one held in `buffer`.
+    lines: Vec<String>,
+    /// The fonts of the enclosing formatting commands, innermost last.
+    fonts: Vec<Font>,
+    /// The lists enclosing the current block, innermost last, with the number of the next item
+    /// if the list is numbered.
+    lists: Vec<Option<usize>>,
+    /// Whether a list item has been started, but none of its content written.
+    item_started: bool,
+    /// The text of the cells of the current row of a table.
+    cells: Vec<String>,
+    /// The content of the footnotes, in order of their numbers.
+    footnotes: Vec<Vec<Node>>,
+    /// The headings of the document, which are used to resolve references.
+    headings: Vec<Heading>,
+    /// The index in `headings` of the next heading to be rendered.
+    next_heading: usize,
+    /// The numbers of the entries of the bibliography, which are used to resolve citations.
+    citations: Citations,
+    /// The index of the document.
+    index: Vec<Group>,
+    /// The abbreviations of the document, and which have been used.
+    abbreviations: Abbreviations,
+    /// The warnings encountered so far.
+    report: RenderReport,
+}
+
+impl<'a, W: Write> RoffWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> Result<(), Error> {
+        self.out.write_all(s.as_bytes()).context(ErrorKind::Write)?;
+        Ok(())
+    }
+
+    /// Returns the text of the current line, and clears it.
+    fn take_line(&mut self) -> String {
+        let text = self.buffer.trim_end().to_owned();
+        self.buffer.clear();
+        self.at_space = true;
+        text
+    }
+
+    /// Returns the lines of the current paragraph or heading, as ended by `\break`, and clears
+    /// it. Breaks at the end are dropped, so there is always at least one line.
+    fn take_lines(&mut self) -> Vec<String> {
+        let line = self.take_line();
+        self.lines.push(line);
+        let mut lines = self.lines.split_off(0);
+        while lines.len() > 1 && lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+    }
+
+    /// Returns the text of the current paragraph or heading, with the lines ended by `\break`
+    /// joined by a space, and clears it.
+    fn take_text(&mut self) -> String {
+        self.take_lines().join(" ")
+    }
+
+    /// Starts a paragraph, which is indented if it is within a list. The first paragraph of a
+    /// list item is part of the item's `.IP` paragraph.
+    fn begin_block(&mut self) -> Result<(), Error> {
+        self.buffer.clear();
+        self.at_space = true;
+        self.lines.clear();
+        if self.item_started {
+            self.item_started = false;
+            Ok(())
+        } else if self.lists.is_empty() {
+            self.write_str(".PP\n")
+        } else {
+            self.write_str(".IP\n")
+        }
+    }
+
+    /// Writes the index, as a paragraph for each group, headed by its letter in bold, with each
+    /// entry on a line of its own, and sub-entries indented.
+    fn print_index(&mut self) -> Result<(), Error> {
+        for group in self.index.clone() {
+            self.begin_block()?;
+            self.write_str(&format!("\\fB{}\\fR\n", escape(&group.letter)))?;
+            let mut lines = Vec::new();
+            index_lines(&group.entries, 0, &mut lines);
+            for line in lines {
+                self.write_str(".br\n")?;
+                self.write_str(&line)?;
+                self.write_str("\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the footnotes at the end of the document, each as an `.IP` paragraph tagged with its
+    /// number in brackets. If there are no footnotes, nothing is written.
+    fn write_footnotes(&mut self) -> Result<(), Error> {
+        let mut index = 0;
+        // Footnotes may contain footnotes of their own, which are added to the end of the list.
+        while let Some(content) = self.footnotes.get(index).cloned() {
+            index += 1;
+            let tag = format!("[{}]", index);
+            self.write_str(&format!(".IP {} {}\n", tag, tag.len() + 1))?;
+            self.item_started = true;
+            self.begin_paragraph()?;
+            render_document(&Document { nodes: content }, self)?;
+            self.end_paragraph()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the font changes made by a formatting command, or `None` if it is not one.
+    fn command_font(name: &str) -> Option<Font> {
+        match name {
+            "bold" | "code" => Some(Font {
+                bold: true,
+                italic: false,
+            }),
+            "emph" => Some(Font {
+                bold: false,
+                italic: true,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, W: Write> Renderer for RoffWriter<'a, W> {
+    fn is_cancelled(&self) -> bool {
+        is_set(self.cancel_token)
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        for c in text.chars() {
+            if c == '\\' {
+                self.buffer.push_str("\\e");
+                self.at_space = false;
+            } else if c == SOFT_HYPHEN {
+                self.buffer.push_str("\\%");
+                self.at_space = false;
+            } else if !c.is_whitespace() {
+                self.buffer.push(c);
+                self.at_space = false;
+            } else if !self.at_space {
+                self.buffer.push(' ');
+                self.at_space = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn verbatim(&mut self, verbatim: &Verbatim) -> Result<(), Error> {
+        let content = &verbatim.content;
+        self.begin_block()?;
+        self.write_str(".nf\n")?;
+        for line in content.lines() {
+            let line = protect(&escape(line));
+            self.write_str(&line)?;
+            self.write_str("\n")?;
+        }
+        self.write_str(".fi\n")
+    }
+
+    fn raw(&mut self, content: &str) -> Result<(), Error> {
+        if !content.is_empty() {
+            self.buffer.push_str(content);
+            self.at_space = content.ends_with(' ');
+        }
+        Ok(())
+    }
+
+    fn begin_paragraph(&mut self) -> Result<(), Error> {
+        self.begin_block()
+    }
+
+    fn end_paragraph(&mut self) -> Result<(), Error> {
+        // A line is ended by `.br`, and an empty line is left by `.sp`, since consecutive breaks
+        // have no more effect than one.
+        let lines = self.take_lines();
+        // A paragraph with no text, such as one holding only a `\printindex`, leaves no line.
+        if let [ref line] = *lines {
+            if line.is_empty() {
+                return Ok(());
+            }
+        }
+        let last = lines.len() - 1;
+        for (i, line) in lines.iter().enumerate() {
+            if line.is_empty() && last > 0 {
+                self.write_str(".sp\n")?;
+                continue;
+            }
+            self.write_str(&protect(line))?;
+            self.write_str("\n")?;
+            if i < last {
+                self.write_str(".br\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_command(&mut self, command: &Command) -> Result<Visit, Error> {
+        if let Some(font) = Self::command_font(&command.name) {
+            let outer = self.fonts.last().copied().unwrap_or_default();
+            let font = Font {
+                bold: outer.bold || font.bold,
+                italic: outer.italic || font.italic,
+            };
+            self.fonts.push(font);
+            self.buffer.push_str(font.escape());
+            return Ok(Visit::Children);
+        }
+        match outline::unstarred(&command.name) {
+            "color" | "link" => Ok(Visit::Children),
+            "break" => {
+                let line = self.take_line();
+                self.lines.push(line);
+                Ok(Visit::SkipChildren)
+            }
+            "ref" => {
+                let reference = outline::reference_text(&self.headings, command, &mut self.report);
+                self.text(&reference)?;
+                Ok(Visit::SkipChildren)
+            }
+            "printindex" => {
+                self.print_index()?;
+                Ok(Visit::SkipChildren)
+            }
+            "footnote" => {
+                self.footnotes
+                    .push(command.args.first().cloned().unwrap_or_default());
+                self.raw(&format!("[{}]", self.footnotes.len()))?;
+                Ok(Visit::SkipChildren)
+            }
+            "image" => {
+                let image = Image::new(command, &mut self.report);
+                match image.alt {
+                    Some(ref alt) if alt.is_empty() => {}
+                    alt => self.text(&format!("[{}]", alt.unwrap_or(image.path)))?,
+                }
+                Ok(Visit::SkipChildren)
+            }
+            name if is_horizontal_rule(name) => {
+                self.begin_block()?;
+                self.write_str(&format!("{}\n", rule_escape(RuleStyle::new(command))))?;
+                Ok(Visit::SkipChildren)
+            }
+            "cite" => {
+                let marker = self.citations.marker(command, &mut self.report);
+                self.text(&marker)?;
+                Ok(Visit::SkipChildren)
+            }
+            "abbr" => {
+                let abbreviation = self
+                    .abbreviations
+                    .use_abbreviation(command, &mut self.report);
+                self.text(&abbreviation.text())?;
+                Ok(Visit::SkipChildren)
+            }
+            "section" | "subsection" => {
+                self.abbreviations.heading(command);
+                self.take_text();
+                let number = self
+                    .headings
+                    .get(self.next_heading)
+                    .and_then(|heading| heading.formatted_number.clone())
+                    .filter(|_| self.number_headings);
+                self.next_heading += 1;
+                if let Some(number) = number {
+                    self.text(&format!("{} ", number))?;
+                }
+                Ok(Visit::Children)
+            }
+            // Alignment is not supported by the man macros, so the content becomes an ordinary
+            // paragraph.
+            "centered" | "rightalign" => {
+                self.begin_block()?;
+                Ok(Visit::Children)
+            }
+            // The `.TP` paragraph of a description item is started once its term is known.
+            "item" if item_term(command).is_some() => Ok(Visit::Children),
+            "item" => {
+                let tag = match self.lists.last_mut() {
+                    Some(&mut Some(ref mut number)) => {
+                        *number += 1;
+                        format!("{}. 4", *number - 1)
+                    }
+                    _ => "\\(bu 2".to_owned(),
+                };
+                self.write_str(&format!(".IP {}\n", tag))?;
+                self.item_started = true;
+                Ok(Visit::Children)
+            }
+            "bibitem" => {
+                let number = self.citations.number(&bibliography::bibitem_key(command));
+                let tag = format!("[{}]", bibliography::number_text(number));
+                self.write_str(&format!(".IP {} {}\n", tag, tag.len() + 1))?;
+                self.item_started = true;
+                Ok(Visit::Children)
+            }
+            _ => Ok(Visit::SkipChildren),
+        }
+    }
+
+    fn end_command(&mut self, command: &Command) -> Result<(), Error> {
+        if Self::command_font(&command.name).is_some() {
+            self.fonts.pop();
+            let font = self.fonts.last().copied().unwrap_or_default();
+            self.buffer.push_str(font.escape());
+            return Ok(());
+        }
+        if command.name == "centered" || command.name == "rightalign" {
+            return self.end_paragraph();
+        }
+        if command.name == "link" {
+            if has_link_text(command) {
+                self.text(" ")?;
+            }
+            return self.text(&format!("<{}>", link_url(command)));
+        }
+        let request = match outline::unstarred(&command.name) {
+            "section" => ".SH",
+            "subsection" => ".SS",
+            _ => return Ok(()),
+        };
+        let heading = self.take_text();
+        self.write_str(&format!("{} {}\n", request, heading))
+    }
+
+    fn begin_argument(&mut self, command: &Command, index: usize) -> Result<Visit, Error> {
+        // The first argument of `\color` names the color, that of `\link` gives the URL, and that
+        // of `\bibitem` gives the key, and none is part of the content.
+        if let ("color" | "link" | "bibitem", 0) = (&*command.name, index) {
+            return Ok(Visit::SkipChildren);
+        }
+        if item_term(command).is_some() && index == 0 {
+            self.take_text();
+        }
+        // The text of a `\bibitem` is the paragraph of its `.IP`.
+        if let ("bibitem", 1) = (&*command.name, index) {
+            self.begin_paragraph()?;
+        }
+        Ok(Visit::Children)
+    }
+
+    fn end_argument(&mut self, command: &Command, index: usize) -> Result<(), Error> {
+        // An empty tag line would be a blank line, so a missing term is written as `\&`.
+        if item_term(command).is_some() && index == 0 {
+            let term = match self.take_text() {
+                ref term if term.is_empty() => "\\&".to_owned(),
+                term => protect(&term),
+            };
+            self.write_str(&format!(".TP\n{}\n", term))?;
+            self.item_started = true;
+        }
+        if let ("bibitem", 1) = (&*command.name, index) {
+            self.end_paragraph()?;
+        }
+        Ok(())
+    }
+
+    fn begin_environment(&mut self, env: &Environment) -> Result<Visit, Error> {
+        if env.name == "indent" {
+            let level = plain::indent_level(env, &mut self.report);
+            self.write_str(&format!(".RS {}\n", level * 2))?;
+            return Ok(Visit::Children);
+        }
+        if env.name == "quote" {
+            self.write_str(".RS\n")?;
+            return Ok(Visit::Children);
+        }
+        let number = match &*env.name {
+            "itemize" | "description" | "references" => None,
+            "enumerate" => Some(1),
+            _ => return Ok(Visit::Children),
+        };
+        if !self.lists.is_empty() {
+            self.write_str(".RS\n")?;
+        }
+        self.item_started = false;
+        self.lists.push(number);
+        Ok(Visit::Children)
+    }
+
+    fn end_environment(&mut self, env: &Environment) -> Result<(), Error> {
+        if env.name == "indent" {
+            return self.write_str(".RE\n");
+        }
+        if env.name == "quote" {
+            if let Some(attribution) = quote_attribution(env) {
+                self.begin_block()?;
+                self.text(&attribution)?;
+                self.end_paragraph()?;
+            }
+            return self.write_str(".RE\n");
+        }
+        if let "itemize" | "enumerate" | "description" | "references" = &*env.name {
+            self.lists.pop();
+            if !self.lists.is_empty() {
+                self.write_str(".RE\n")?;
+            }
+        }
+        Ok(())
+    }
+    fn begin_table(&mut self, table: &Table) -> Result<Visit, Error> {
+        self.begin_block()?;
+        self.write_str(".TS\nallbox;\n")?;
+        // The header row is bold, and the format of the last line applies to the rest.
+        let keys: Vec<_> = (0..table.column_count())
+            .map(|column| column_key(table.alignment(column)))
+            .collect();
+        if table.header {
+            let bold: Vec<_> = keys.iter().map(|key| format!("{}b", key)).collect();
+            self.write_str(&format!("{}\n", bold.join(" ")))?;
+        }
+        self.write_str(&format!("{}.\n", keys.join(" ")))?;
+        Ok(Visit::Children)
+    }
+
+    fn end_table(&mut self, table: &Table) -> Result<(), Error> {
+        self.write_str(".TE\n")?;
+        if !table.caption.is_empty() {
+            self.begin_block()?;
+            let caption = Document {
+                nodes: table.caption.clone(),
+            };
+            render_document(&caption, self)?;
+            self.end_paragraph()?;
+        }
+        Ok(())
+    }
+
+    fn begin_cell(&mut self, _table: &Table, _row: usize, _column: usize) -> Result<(), Error> {
+        self.take_lines();
+        Ok(())
+    }
+
+    fn end_cell(&mut self, _table: &Table, _row: usize, _column: usize) -> Result<(), Error> {
+        let text = self.take_text();
+        self.cells.push(table_cell(&text));
+        Ok(())
+    }
+
+    fn end_row(&mut self, table: &Table, _row: usize) -> Result<(), Error> {
+        // Cells are separated by tabs, and a short row is filled with empty cells.
+        let mut cells = self.cells.split_off(0);
+        cells.resize(table.column_count(), String::new());
+        self.write_str(&format!("{}\n", cells.join("\t")))
+    }
+}
@@ -0,0 +1,424 @@
+//! Sanitization of raw HTML, for documents which are not trusted.
+
+use std::collections::{HashMap, HashSet};
+
+use super::html::escape;
+
+/// The elements whose content is not markup, and which are removed along with their content
+/// unless they are allowed.
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "script", "style", "textarea", "title", "iframe", "noscript", "noembed", "noframes", "xmp",
+    "plaintext",
+];
+
+/// The elements which have no content or closing tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// The attributes whose values are URLs.
+const URL_ATTRIBUTES: &[&str] = &[
+    "href", "src", "action", "formaction", "cite", "poster", "background", "xlink:href",
+];
+
+/// The schemes permitted in URL attributes. URLs without a scheme are always permitted.
+const URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// The elements and attributes permitted by `sanitize`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Allowlist {
+    elements: HashMap<String, HashSet<String>>,
+}
+
+impl Allowlist {
+    /// Constructs a new `Allowlist` which permits nothing.
+    pub fn new() -> Allowlist {
+        Allowlist::default()
+    }
+
+    /// Constructs a new `Allowlist` which permits common inline and block formatting, links, and
+    /// images.
+    pub fn basic() -> Allowlist {
+        Allowlist::new()
+            .element("a", &["href", "title"])
+            .element("img", &["src", "alt", "title", "width", "height"])
+            .element("abbr", &["title"])
+            .elements(&[
+                "b", "strong", "i", "em", "u", "s", "code", "kbd", "samp", "var", "sub", "sup",
+                "small", "mark", "span", "br", "p", "div", "pre", "blockquote", "ul", "ol", "li",
+                "hr",
+            ])
+    }
+
+    /// Permits the named element, with the given attributes. Names are case-insensitive.
+    pub fn element(mut self, name: &str, attributes: &[&str]) -> Allowlist {
+        self.elements
+            .entry(name.to_ascii_lowercase())
+            .or_default()
+            .extend(attributes.iter().map(|a| a.to_ascii_lowercase()));
+        self
+    }
+
+    /// Permits each of the named elements, without any attributes.
+    pub fn elements(self, names: &[&str]) -> Allowlist {
+        names
+            .iter()
+            .fold(self, |allowlist, name| allowlist.element(name, &[]))
+    }
+
+    /// Returns whether the named element is permitted.
+    pub fn allows_element(&self, name: &str) -> bool {
+        self.elements.contains_key(name)
+    }
+
+    /// Returns whether the named attribute is permitted on the named element.
+    pub fn allows_attribute(&self, element: &str, attribute: &str) -> bool {
+        self.elements
+            .get(element)
+            .is_some_and(|attributes| attributes.contains(attribute))
+    }
+}
+
+/// Returns the given HTML with everything not permitted by the allowlist removed.
+///
+/// Tags of elements which are not permitted are removed, but their content is kept, except for
+/// elements such as `<script>` and `<style>`, whose content is removed as well. Attributes which
+/// are not permitted are removed, as are URL attributes such as `href` and `src` with a scheme
+/// other than `http`, `https`, or `mailto`. Comments, doctypes, and processing instructions are
+/// removed. Permitted tags are written in a normalized form, with lowercase names and quoted
+/// attribute values, closing tags without a matching open element are removed, and elements left
+/// open at the end are closed, so that the result cannot affect markup around it. A `<`, `>`, or
+/// `&` which is not part of markup or an entity is escaped.
+pub fn sanitize(html: &str, allowlist: &Allowlist) -> String {
+    let mut sanitizer = Sanitizer {
+        allowlist,
+        input: html,
+        out: String::with_capacity(html.len()),
+        open: Vec::new(),
+    };
+    sanitizer.run();
+    sanitizer.out
+}
+
+struct Sanitizer<'a> {
+    allowlist: &'a Allowlist,
+    /// The input which has not yet been read.
+    input: &'a str,
+    out: String,
+    /// The names of the permitted elements which are open.
+    open: Vec<String>,
+}
+
+impl<'a> Sanitizer<'a> {
+    fn run(&mut self) {
+        while let Some(c) = self.input.chars().next() {
+            match c {
+                '<' => self.markup(),
+                '&' => self.entity(),
+                '>' => {
+                    self.out.push_str("&gt;");
+                    self.input = &self.input[1..];
+                }
+                c => {
+                    self.out.push(c);
+                    self.input = &self.input[c.len_utf8()..];
+                }
+            }
+        }
+        while let Some(name) = self.open.pop() {
+            self.close(&name);
+        }
+    }
+
+    /// Reads markup starting with `<`, or escapes the `<` if it does not start any.
+    fn markup(&mut self) {
+        let rest = &self.input[1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            self.input = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with('!') || rest.starts_with('?') {
+            self.input = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(closing) = rest.strip_prefix('/') {
+            if closing.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                self.input = closing;
+                self.closing_tag();
+            } else {
+                self.input = closing.find('>').map_or("", |end| &closing[end + 1..]);
+            }
+        } else if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            self.input = rest;
+            self.opening_tag();
+        } else {
+            self.out.push_str("&lt;");
+            self.input = rest;
+        }
+    }
+
+    /// Reads a tag name, lowercased.
+    fn name(&mut self) -> String {
+        let end = self
+            .input
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .unwrap_or(self.input.len());
+        let name = self.input[..end].to_ascii_lowercase();
+        self.input = &self.input[end..];
+        name
+    }
+
+    /// Reads an opening tag, after the `<`.
+    fn opening_tag(&mut self) {
+        let name = self.name();
+        let mut attributes = Vec::new();
+        loop {
+            self.input = self.input.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+            if self.input.is_empty() {
+                break;
+            }
+            if let Some(rest) = self.input.strip_prefix('>') {
+                self.input = rest;
+                break;
+            }
+            attributes.extend(self.attribute());
+        }
+        if !self.allowlist.allows_element(&name) {
+            if RAW_TEXT_ELEMENTS.contains(&&*name) {
+                self.skip_raw_text(&name);
+            }
+            return;
+        }
+        self.out.push('<');
+        self.out.push_str(&name);
+        for (attribute, value) in attributes {
+            if !self.allowlist.allows_attribute(&name, &attribute)
+                || (URL_ATTRIBUTES.contains(&&*attribute) && !safe_url(&value))
+            {
+                continue;
+            }
+            self.out.push(' ');
+            self.out.push_str(&attribute);
+            self.out.push_str("=\"");
+            self.out.push_str(&escape(&value, true));
+            self.out.push('"');
+        }
+        self.out.push('>');
+        if !VOID_ELEMENTS.contains(&&*name) {
+            self.open.push(name);
+        }
+    }
+
+    /// Reads an attribute, returning its lowercased name and its value with entities decoded.
+    fn attribute(&mut self) -> Option<(String, String)> {
+        // The name has at least one character, so that something is always read.
+        let first = self.input.chars().next().map_or(0, char::len_utf8);
+        let end = self.input[first..]
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+            .map_or(self.input.len(), |end| first + end);
+        let name = self.input[..end].to_ascii_lowercase();
+        self.input = self.input[end..].trim_start();
+        let value = match self.input.strip_prefix('=') {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                let (value, rest) = match rest.chars().next() {
+                    Some(quote @ '"') | Some(quote @ '\'') => {
+                        let rest = &rest[1..];
+                        match rest.find(quote) {
+                            Some(end) => (&rest[..end], &rest[end + 1..]),
+                            None => (rest, ""),
+                        }
+                    }
+                    _ => {
+                        let end = rest
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(rest.len());
+                        (&rest[..end], &rest[end..])
+                    }
+                };
+                self.input = rest;
+                decode_entities(value)
+            }
+            None => String::new(),
+        };
+        if name.chars().all(|c| c.is_alphanumeric() || "-_:.".contains(c)) {
+            Some((name, value))
+        } else {
+            None
+        }
+    }
+
+    /// Reads a closing tag, after the `</`.
+    fn closing_tag(&mut self) {
+        let name = self.name();
+        self.input = self.input.find('>').map_or("", |end| &self.input[end + 1..]);
+        if let Some(index) = self.open.iter().rposition(|open| *open == name) {
+            for open in self.open.split_off(index).into_iter().rev() {
+                self.close(&open);
+            }
+        }
+    }
+
+    /// Skips the content and closing tag of a raw text element such as `<script>`.
+    fn skip_raw_text(&mut self, name: &str) {
+        let closing = format!("</{}", name);
+        let lower = self.input.to_ascii_lowercase();
+        self.input = match lower.find(&closing) {
+            Some(start) => {
+                let rest = &self.input[start..];
+                rest.find('>').map_or("", |end| &rest[end + 1..])
+            }
+            None => "",
+        };
+    }
+
+    /// Writes a closing tag.
+    fn close(&mut self, name: &str) {
+        self.out.push_str("</");
+        self.out.push_str(name);
+        self.out.push('>');
+    }
+
+    /// Writes an entity starting with `&` as it is, or escapes the `&` if it does not start one.
+    fn entity(&mut self) {
+        let len = entity_len(self.input);
+        if len > 0 {
+            self.out.push_str(&self.input[..len]);
+            self.input = &self.input[len..];
+        } else {
+            self.out.push_str("&amp;");
+            self.input = &self.input[1..];
+        }
+    }
+}
+
+/// Returns the length of the character reference at the start of `s`, such as `&amp;` or
+/// `&#39;`, or 0 if there is none.
+fn entity_len(s: &str) -> usize {
+    let body = &s[1..];
+    let (digits, start): (fn(char) -> bool, usize) =
+        if body.starts_with("#x") || body.starts_with("#X") {
+            (|c| c.is_ascii_hexdigit(), 2)
+        } else if body.starts_with('#') {
+            (|c| c.is_ascii_digit(), 1)
+        } else {
+            (|c| c.is_ascii_alphanumeric(), 0)
+        };
+    let len = body[start..].find(|c| !digits(c)).unwrap_or(body.len() - start);
+    if len > 0 && body[start + len..].starts_with(';') {
+        1 + start + len + 1
+    } else {
+        0
+    }
+}
+
+/// Decodes the numeric character references and the references `&amp;`, `&lt;`, `&gt;`,
+/// `&quot;`, and `&apos;` in an attribute value. Any other reference is left as it is, and so has
+/// its `&` escaped when the value is written.
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let len = entity_len(rest);
+        let name = if len > 0 { &rest[1..len - 1] } else { "" };
+        let c = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let number = match name.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16),
+                    Some(decimal) => decimal.parse(),
+                    None => "".parse(),
+                };
+                number.ok().and_then(std::char::from_u32)
+            }
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns whether a URL has no scheme, or one of the permitted schemes. Whitespace and control
+/// characters are ignored, since browsers ignore them in URLs.
+fn safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let scheme_end = match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => end,
+        _ => return true,
+    };
+    let scheme = url[..scheme_end].to_ascii_lowercase();
+    URL_SCHEMES.contains(&&*scheme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize, Allowlist};
+
+    fn basic(html: &str) -> String {
+        sanitize(html, &Allowlist::basic())
+    }
+
+    #[test]
+    fn keeps_permitted_elements_and_attributes() {
+        assert_eq!(
+            basic("<P>a <A HREF='https://example.com/' title=\"t\">b</A></P>"),
+            "<p>a <a href=\"https://example.com/\" title=\"t\">b</a></p>"
+        );
+        assert_eq!(basic("<img src=a.png alt=x>"), "<img src=\"a.png\" alt=\"x\">");
+        assert_eq!(
+            basic("<a href=\"mailto:a@example.com\">"),
+            "<a href=\"mailto:a@example.com\"></a>"
+        );
+    }
+
+    #[test]
+    fn removes_elements_which_are_not_permitted() {
+        assert_eq!(basic("<font color=red>a</font>"), "a");
+        assert_eq!(basic("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(basic("a<STYLE>p {}</Style >b"), "ab");
+        assert_eq!(basic("a<!-- c -->b<!doctype html><?x?>"), "ab");
+        assert_eq!(sanitize("<b>a</b>", &Allowlist::new()), "a");
+    }
+
+    #[test]
+    fn removes_attributes_which_are_not_permitted() {
+        assert_eq!(basic("<b onclick=\"f()\" class=c>a</b>"), "<b>a</b>");
+        assert_eq!(basic("<a href=x onmouseover=f()>a</a>"), "<a href=\"x\">a</a>");
+    }
+
+    #[test]
+    fn removes_unsafe_urls() {
+        assert_eq!(basic("<a href=\"javascript:alert(1)\">a</a>"), "<a>a</a>");
+        assert_eq!(basic("<a href=\" Java\tScript:alert(1)\">a</a>"), "<a>a</a>");
+        assert_eq!(basic("<a href=\"&#106;avascript:alert(1)\">a</a>"), "<a>a</a>");
+        assert_eq!(basic("<img src=\"data:image/png;base64,AA\">"), "<img>");
+        assert_eq!(basic("<a href=\"/a:b\">a</a>"), "<a href=\"/a:b\">a</a>");
+    }
+
+    #[test]
+    fn balances_tags() {
+        assert_eq!(basic("</b><i>a"), "<i>a</i>");
+        assert_eq!(basic("<b><i>a</b>b"), "<b><i>a</i></b>b");
+    }
+
+    #[test]
+    fn escapes_text_and_attribute_values() {
+        assert_eq!(basic("1 < 2 & 3 > 2 &amp; &#39;"), "1 &lt; 2 &amp; 3 &gt; 2 &amp; &#39;");
+        assert_eq!(basic("<a title='a\"<b'>"), "<a title=\"a&quot;&lt;b\"></a>");
+    }
+}
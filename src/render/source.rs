@@ -0,0 +1,482 @@
+//! Writes a document back out as source, in a canonical layout.
+//!
+//! Unlike the other output formats, this does not use `Renderer`, since the source of a node may
+//! depend on the node which follows it.
+
+use std::fmt;
+use std::io::Write;
+use std::{iter, mem, slice};
+
+use ast::{Alignment, Command, Document, Environment, Node, Table};
+use errors::{Error, ErrorKind, ResultExt};
+use tokenize::{self, Token, SOFT_HYPHEN};
+use super::plain::text_width;
+use super::{item_term, FmtWriter, DEFAULT_MAX_DEPTH};
+
+/// Options controlling the layout of formatted source.
+#[derive(Clone, Debug)]
+pub struct SourceOptions {
+    width: usize,
+    indent: usize,
+}
+
+impl Default for SourceOptions {
+    fn default() -> SourceOptions {
+        SourceOptions {
+            width: 80,
+            indent: 2,
+        }
+    }
+}
+
+impl SourceOptions {
+    /// Constructs a new `SourceOptions` with the default settings.
+    pub fn new() -> SourceOptions {
+        SourceOptions::default()
+    }
+
+    /// Sets the width to which lines are wrapped, in columns. Lines are only broken at
+    /// whitespace, so a line may be longer if it has no whitespace at which to break. Defaults to
+    /// 80.
+    pub fn width(mut self, width: usize) -> SourceOptions {
+        self.width = width;
+        self
+    }
+
+    /// Sets the number of spaces by which each level of nesting is indented. Defaults to 2.
+    pub fn indent(mut self, indent: usize) -> SourceOptions {
+        self.indent = indent;
+        self
+    }
+}
+
+/// Writes a document as source to the given output.
+///
+/// Blocks are separated by a blank line, except for consecutive list items, which are on
+/// consecutive lines. The content of environments and list items is indented by one level, as
+/// are the rows of tables, and lines wrapped within groups and command arguments are indented by
+/// one level for each group they are within. Each run of whitespace in the text is written as a
+/// single space, or as a line break where a line is wrapped, except that a run containing a blank
+/// line is kept as a blank line. Verbatim and raw content is written unchanged.
+///
+/// The output parses, with the same parser options, to the same document, up to the whitespace in
+/// the text, and formatting it again gives the same output. Definitions have already been expanded
+/// in a parsed document, so they are not written. The output is meant to be parsed without
+/// `ParserOptions::smart_punctuation`, since quotes and dashes in the text are written as they
+/// are.
+pub fn render<W: Write>(
+    document: &Document,
+    options: &SourceOptions,
+    out: &mut W,
+) -> Result<(), Error> {
+    let mut source = SourceWriter::new(options, false).write(document)?;
+    if !source.is_empty() {
+        source.push('\n');
+    }
+    out.write_all(source.as_bytes()).context(ErrorKind::Write)?;
+    Ok(())
+}
+
+/// Writes a document as source to the given `fmt::Write`.
+pub fn render_fmt<F: fmt::Write + ?Sized>(
+    document: &Document,
+    options: &SourceOptions,
+    out: &mut F,
+) -> Result<(), Error> {
+    render(document, options, &mut FmtWriter::new(out))
+}
+
+/// Writes a document as source, returning the result as a `String`.
+pub fn render_to_string(document: &Document, options: &SourceOptions) -> Result<String, Error> {
+    let mut out = String::new();
+    render_fmt(document, options, &mut out)?;
+    Ok(out)
+}
+
+/// Writes a document as source in as little space as possible.
+///
+/// Each run of whitespace in the text is written as a single space, and there is no other
+/// whitespace, except for a blank line between consecutive paragraphs and a line break between
+/// other consecutive blocks. Verbatim and raw content is written unchanged. As with `render`,
+/// the output parses to the same document, up to the whitespace in the text.
+pub fn minify(document: &Document) -> Result<String, Error> {
+    let options = SourceOptions::new().width(usize::MAX).indent(0);
+    SourceWriter::new(&options, true).write(document)
+}
+
+/// Returns the letter giving the alignment of a table column.
+fn alignment_letter(alignment: Alignment) -> char {
+    match alignment {
+        Alignment::Left => 'l',
+        Alignment::Center => 'c',
+        Alignment::Right => 'r',
+    }
+}
+
+/// Returns `text` with the characters which are special in source escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            SOFT_HYPHEN => escaped.push_str("\\-"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns whether a node is a list item, whose content is written after `\item` rather than as
+/// an argument. The item of a `description` list also holds its term.
+fn is_item(node: &Node) -> bool {
+    match *node {
+        Node::Command(ref command) if command.name == "item" => {
+            command.args.len() == 1 || item_term(command).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// The state of the formatted output.
+///
+/// Output is collected a word at a time, where a word is everything between two points at which
+/// the line may be broken, so that the whole of a word can be moved to the next line if it does
+/// not fit.
+struct SourceWriter<'a> {
+    options: &'a SourceOptions,
+    /// The output up to the current word.
+    out: String,
+    /// The current word.
+    word: String,
+    /// The column at the end of `out`.
+    column: usize,
+    /// The indentation of the next line, if the current word is preceded by a point at which the
+    /// line may be broken.
+    space: Option<usize>,
+    /// Whether `out` ends with the indentation of a line, with nothing after it.
+    line_start: bool,
+    /// Whether line breaks are omitted wherever they are not needed, as for `minify`.
+    minify: bool,
+}
+
+impl<'a> SourceWriter<'a> {
+    fn new(options: &'a SourceOptions, minify: bool) -> SourceWriter<'a> {
+        SourceWriter {
+            options,
+            out: String::new(),
+            word: String::new(),
+            column: 0,
+            space: None,
+            line_start: true,
+            minify,
+        }
+    }
+
+    /// Writes a document, returning the source.
+    fn write(mut self, document: &Document) -> Result<String, Error> {
+        self.blocks(&document.nodes, 0, 0)?;
+        self.flush();
+        Ok(self.out)
+    }
+
+    /// Moves the current word to the output, preceded by a space or, if it would not fit on the
+    /// current line, a line break.
+    fn flush(&mut self) {
+        if self.word.is_empty() {
+            return;
+        }
+        if let Some(indent) = self.space.take() {
+            let width = text_width(self.word.lines().next().unwrap_or(""));
+            if !self.line_start && self.column + 1 + width > self.options.width {
+                self.new_line(false, indent);
+            } else if !self.line_start {
+                self.out.push(' ');
+                self.column += 1;
+            }
+        }
+        let word = mem::take(&mut self.word);
+        match word.rfind('\n') {
+            Some(i) => self.column = text_width(&word[i + 1..]),
+            None => self.column += text_width(&word),
+        }
+        self.out.push_str(&word);
+        self.line_start = false;
+    }
+
+    /// Starts a new line at the given indentation, preceded by a blank line if `blank` is set.
+    /// When minifying, only a blank line is written.
+    fn new_line(&mut self, blank: bool, indent: usize) {
+        self.line_start = true;
+        if self.minify {
+            if blank {
+                self.out.push_str("\n\n");
+            }
+            return;
+        }
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        if blank {
+            self.out.push('\n');
+        }
+        self.out.extend(iter::repeat_n(' ', indent));
+        self.column = indent;
+    }
+
+    /// Ends the current line, after the current word, and starts a new one.
+    fn line_break(&mut self, blank: bool, indent: usize) {
+        self.flush();
+        self.space = None;
+        self.new_line(blank, indent);
+    }
+
+    /// Marks a point at which the line may be broken, continuing at the given indentation.
+    fn space(&mut self, indent: usize) {
+        self.flush();
+        if !self.line_start {
+            self.space = Some(indent);
+        }
+    }
+
+    /// Writes a sequence of blocks, the first of which starts at the current position, and the
+    /// rest on new lines at the given indentation.
+    fn blocks(&mut self, nodes: &[Node], indent: usize, depth: usize) -> Result<(), Error> {
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                if self.minify {
+                    // Only paragraphs need a blank line to separate them, but a line break keeps
+                    // other blocks from running into what follows.
+                    let paragraphs = [&nodes[i - 1], node]
+                        .iter()
+                        .all(|node| matches!(**node, Node::Paragraph(_)));
+                    self.line_break(paragraphs, indent);
+                    if !paragraphs {
+                        self.out.push('\n');
+                    }
+                } else {
+                    let blank = !(is_item(&nodes[i - 1]) && is_item(node));
+                    self.line_break(blank, indent);
+                }
+            }
+            self.block(node, indent, depth)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a single block.
+    fn block(&mut self, node: &Node, indent: usize, depth: usize) -> Result<(), Error> {
+        match *node {
+            Node::Paragraph(ref nodes) => self.inline(nodes, indent, depth + 1),
+            Node::Command(ref command) if is_item(node) => self.item(command, indent, depth),
+            _ => self.inline(slice::from_ref(node), indent, depth),
+        }
+    }
+
+    /// Writes a list item, with its first paragraph on the same line as the `\item` and its term,
+    /// if it has one, and the rest of its content indented.
+    fn item(&mut self, command: &Command, indent: usize, depth: usize) -> Result<(), Error> {
+        self.word.push_str("\\item");
+        let inner = indent + self.options.indent;
+        if let Some(term) = item_term(command) {
+            self.bracketed(term, indent, depth)?;
+        }
+        let content = &command.args[command.args.len() - 1];
+        match content.first() {
+            Some(&Node::Paragraph(_)) => self.space(inner),
+            Some(_) => self.line_break(false, inner),
+            None => return Ok(()),
+        }
+        self.blocks(content, inner, depth + 1)
+    }
+
+    /// Writes a sequence of inline nodes, with lines wrapped at the given indentation.
+    fn inline(&mut self, nodes: &[Node], indent: usize, depth: usize) -> Result<(), Error> {
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ErrorKind::NestingTooDeep.into());
+        }
+        for (i, node) in nodes.iter().enumerate() {
+            let next = nodes.get(i + 1);
+            match *node {
+                Node::Text(ref text) => {
+                    let before_group = matches!(next, Some(&Node::Group(_)));
+                    self.text(text, indent, before_group);
+                }
+                Node::Paragraph(ref nodes) => self.inline(nodes, indent, depth + 1)?,
+                Node::Group(ref nodes) => self.argument(nodes, indent, depth)?,
+                Node::Command(ref command) => {
+                    self.word.push('\\');
+                    self.word.push_str(&command.name);
+                    let mut args = &command.args[..];
+                    // The first argument of `\image` holds its options, which are bracketed.
+                    if command.name == "image" {
+                        if let Some((options, rest)) = args.split_first() {
+                            self.bracketed(options, indent, depth)?;
+                            args = rest;
+                        }
+                    }
+                    for arg in args {
+                        self.argument(arg, indent, depth)?;
+                    }
+                    // Text starting with a letter would be read as part of the command name.
+                    let merges = match next {
+                        Some(Node::Text(text)) => text.starts_with(char::is_alphabetic),
+                        _ => false,
+                    };
+                    if command.args.is_empty() && merges {
+                        self.word.push_str("{}");
+                    }
+                }
+                Node::Environment(ref env) => self.environment(env, indent, depth)?,
+                Node::Table(ref table) => self.table(table, indent, depth)?,
+                Node::Verbatim(ref verbatim) => {
+                    self.token(Token::Verbatim(Box::new(verbatim.clone())), next)?
+                }
+                Node::Raw(ref content) => self.token(Token::Raw(content.as_str().into()), next)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a run of text. Whitespace at the end of the text is written as a single space,
+    /// rather than as a point at which the line may be broken, if `before_group` is set, since a
+    /// group following a line break may be read as an argument of a preceding command.
+    fn text(&mut self, text: &str, indent: usize, before_group: bool) {
+        let mut rest = text;
+        while !rest.is_empty() {
+            let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            self.word.push_str(&escape(&rest[..word_len]));
+            rest = &rest[word_len..];
+            let space_len = rest.len() - rest.trim_start().len();
+            if space_len == 0 {
+                continue;
+            }
+            let space = &rest[..space_len];
+            rest = &rest[space_len..];
+            if space.matches('\n').count() > 1 && !self.minify {
+                self.line_break(true, indent);
+            } else if before_group && rest.is_empty() {
+                self.word.push(' ');
+            } else {
+                self.space(indent);
+            }
+        }
+    }
+
+    /// Writes a brace-delimited group, such as the argument of a command.
+    fn argument(&mut self, nodes: &[Node], indent: usize, depth: usize) -> Result<(), Error> {
+        self.word.push('{');
+        self.inline(nodes, indent + self.options.indent, depth + 1)?;
+        self.word.push('}');
+        Ok(())
+    }
+
+    /// Writes a verbatim or raw token, whose delimiter is chosen so as not to collide with the
+    /// text of the following node.
+    fn token(&mut self, token: Token, next: Option<&Node>) -> Result<(), Error> {
+        let following = match next {
+            Some(Node::Text(text)) => text
+                .chars()
+                .next()
+                .filter(|c| escape(&c.to_string()) == c.to_string()),
+            _ => None,
+        };
+        let mut source = Vec::new();
+        tokenize::write_tokens(
+            Some(token).into_iter().chain(following.map(Token::Char)),
+            &mut source,
+        )?;
+        let mut source = String::from_utf8(source).map_err(|_| ErrorKind::UnwritableToken)?;
+        if let Some(c) = following {
+            source.truncate(source.len() - c.len_utf8());
+        }
+        self.word.push_str(&source);
+        Ok(())
+    }
+
+    /// Writes `\begin` for an environment, with its arguments.
+    fn begin(
+        &mut self,
+        name: &str,
+        mut args: &[Vec<Node>],
+        indent: usize,
+        depth: usize,
+    ) -> Result<(), Error> {
+        self.word.push_str("\\begin{");
+        self.word.push_str(&escape(name));
+        self.word.push('}');
+        // The first argument of a `quote` environment holds its options, which are bracketed.
+        if name == "quote" {
+            if let Some((options, rest)) = args.split_first() {
+                self.bracketed(options, indent, depth)?;
+                args = rest;
+            }
+        }
+        for arg in args {
+            self.argument(arg, indent, depth)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a bracketed list of options, unless it is empty.
+    fn bracketed(&mut self, options: &[Node], indent: usize, depth: usize) -> Result<(), Error> {
+        if !options.is_empty() {
+            self.word.push('[');
+            self.inline(options, indent + self.options.indent, depth + 1)?;
+            self.word.push(']');
+        }
+        Ok(())
+    }
+
+    /// Writes `\end` for an environment, on a new line.
+    fn end(&mut self, name: &str, indent: usize) {
+        self.line_break(false, indent);
+        self.word.push_str("\\end{");
+        self.word.push_str(&escape(name));
+        self.word.push('}');
+    }
+
+    /// Writes an environment, with its content indented on the lines between `\begin` and
+    /// `\end`.
+    fn environment(&mut self, env: &Environment, indent: usize, depth: usize) -> Result<(), Error> {
+        self.begin(&env.name, &env.args, indent, depth)?;
+        if !env.nodes.is_empty() {
+            let inner = indent + self.options.indent;
+            self.line_break(false, inner);
+            self.blocks(&env.nodes, inner, depth + 1)?;
+        }
+        self.end(&env.name, indent);
+        Ok(())
+    }
+
+    /// Writes a table as a `table` environment, with each row on a line of its own.
+    fn table(&mut self, table: &Table, indent: usize, depth: usize) -> Result<(), Error> {
+        let columns = table
+            .columns
+            .iter()
+            .cloned()
+            .map(alignment_letter)
+            .collect::<String>();
+        let args = [vec![Node::Text(columns)], table.caption.clone()];
+        self.begin("table", &args, indent, depth)?;
+        let inner = indent + self.options.indent;
+        for (i, row) in table.rows.iter().enumerate() {
+            self.line_break(false, inner);
+            self.word.push_str(if i == 0 && table.header {
+                "\\header"
+            } else {
+                "\\row"
+            });
+            for cell in row {
+                self.argument(cell, inner, depth + 1)?;
+            }
+        }
+        self.end("table", indent);
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! The width of the output of the text renderers, when it is not given explicitly.
+//!
+//! The width of the terminal is asked of the operating system only if the output is known to be
+//! written to it, and only with the `terminal` feature. Otherwise, and if that fails, the
+//! `COLUMNS` environment variable is read, which shells set for the programs they start, and
+//! failing that, the width is 80.
+
+use std::env;
+
+/// The width used when no other can be found.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Returns the width of the terminal to which standard output is written, or `None` if it is not
+/// a terminal.
+#[cfg(feature = "terminal")]
+pub(super) fn detect_width() -> Option<usize> {
+    let (terminal_size::Width(width), _) = terminal_size::terminal_size()?;
+    Some(usize::from(width)).filter(|&width| width > 0)
+}
+
+/// Returns `None`, since finding the width of the terminal requires the `terminal` feature.
+#[cfg(not(feature = "terminal"))]
+pub(super) fn detect_width() -> Option<usize> {
+    None
+}
+
+/// Returns the width of the output: `explicit`, if it is given; otherwise the width found by
+/// `detect`, if the output is written to a terminal; otherwise the value of `COLUMNS`, if it is a
+/// positive number; otherwise 80.
+pub(super) fn resolve_width<D>(explicit: Option<usize>, terminal: bool, detect: D) -> usize
+where
+    D: FnOnce() -> Option<usize>,
+{
+    choose_width(explicit, terminal, detect, env::var("COLUMNS").ok().as_deref())
+}
+
+/// Returns the width of the output as `resolve_width` does, given the value of `COLUMNS`.
+fn choose_width<D>(
+    explicit: Option<usize>,
+    terminal: bool,
+    detect: D,
+    columns: Option<&str>,
+) -> usize
+where
+    D: FnOnce() -> Option<usize>,
+{
+    let columns = || columns?.trim().parse().ok().filter(|&width| width > 0);
+    explicit
+        .or_else(|| if terminal { detect() } else { None })
+        .or_else(columns)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the detection of the width of a terminal which should not be asked.
+    fn unreachable() -> Option<usize> {
+        panic!("the width of the terminal was asked for");
+    }
+
+    #[test]
+    fn explicit_width_comes_first() {
+        assert_eq!(choose_width(Some(40), true, || Some(120), Some("100")), 40);
+        assert_eq!(choose_width(Some(40), false, unreachable, Some("100")), 40);
+    }
+
+    #[test]
+    fn terminal_width_comes_before_columns() {
+        assert_eq!(choose_width(None, true, || Some(120), Some("100")), 120);
+        assert_eq!(choose_width(None, true, || None, Some("100")), 100);
+    }
+
+    #[test]
+    fn other_output_is_never_detected() {
+        assert_eq!(choose_width(None, false, unreachable, Some("100")), 100);
+        assert_eq!(choose_width(None, false, unreachable, None), DEFAULT_WIDTH);
+    }
+
+    #[test]
+    fn invalid_columns_are_ignored() {
+        assert_eq!(choose_width(None, false, unreachable, Some(" 72 ")), 72);
+        assert_eq!(choose_width(None, false, unreachable, Some("0")), DEFAULT_WIDTH);
+        assert_eq!(choose_width(None, false, unreachable, Some("wide")), DEFAULT_WIDTH);
+        assert_eq!(choose_width(None, true, || None, None), DEFAULT_WIDTH);
+    }
+}
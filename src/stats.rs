@@ -0,0 +1,121 @@
+//! Counters of the work done by the tokenizer and the parser, for telemetry and for checking the
+//! cost of large builds.
+//!
+//! `Tokens::stats` returns the counts of what has been read and produced so far, and
+//! `Parser::stats` adds those of the parser. The counters are always kept, since each is a single
+//! increment; the time taken by each phase is measured only if `ParserOptions::timings` is set.
+
+use core::fmt;
+use core::time::Duration;
+
+use tokenize::Token;
+
+/// The number of tokens of each kind which have been produced.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TokenCounts {
+    pub chars: usize,
+    pub commands: usize,
+    pub begin_groups: usize,
+    pub end_groups: usize,
+    pub verbatims: usize,
+    pub raws: usize,
+    pub texts: usize,
+    pub verbatim_chunks: usize,
+    pub raw_chunks: usize,
+}
+
+impl TokenCounts {
+    /// Counts a token.
+    pub fn add(&mut self, token: &Token) {
+        let count = match *token {
+            Token::Char(_) => &mut self.chars,
+            Token::Command(_) => &mut self.commands,
+            Token::BeginGroup => &mut self.begin_groups,
+            Token::EndGroup => &mut self.end_groups,
+            Token::Verbatim(_) => &mut self.verbatims,
+            Token::Raw(_) => &mut self.raws,
+            Token::Text(_) => &mut self.texts,
+            Token::VerbatimChunk(_) => &mut self.verbatim_chunks,
+            Token::RawChunk(_) => &mut self.raw_chunks,
+        };
+        *count += 1;
+    }
+
+    /// Returns the number of tokens of every kind.
+    pub fn total(&self) -> usize {
+        self.chars
+            + self.commands
+            + self.begin_groups
+            + self.end_groups
+            + self.verbatims
+            + self.raws
+            + self.texts
+            + self.verbatim_chunks
+            + self.raw_chunks
+    }
+}
+
+/// The time taken by each phase of a conversion, where it was measured.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Timings {
+    /// Tokenizing and parsing, which are interleaved, including loading the definitions file.
+    pub parse: Option<Duration>,
+    /// Resolving citations, as by `bibliography::resolve`.
+    pub resolve: Option<Duration>,
+    /// Rendering.
+    pub render: Option<Duration>,
+}
+
+/// Counters of the work done by the tokenizer and the parser.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Stats {
+    /// The number of lines read, counting a last line without a line ending.
+    pub lines: usize,
+    /// The number of bytes read, as UTF-8 once any encoding has been decoded.
+    pub bytes: usize,
+    /// The tokens produced by the tokenizer, not counting those of the expansions of
+    /// user-defined commands and environments.
+    pub tokens: TokenCounts,
+    /// The greatest number of groups, arguments, and environments which were open at once.
+    pub max_depth: usize,
+    /// The number of expansions of user-defined commands and environments.
+    pub expansions: usize,
+    pub timings: Timings,
+}
+
+impl fmt::Display for Stats {
+    /// Writes a summary of the counters, one to a line, followed by the timings which were
+    /// measured.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tokens = &self.tokens;
+        writeln!(f, "lines:      {}", self.lines)?;
+        writeln!(f, "bytes:      {}", self.bytes)?;
+        writeln!(
+            f,
+            "tokens:     {} ({} chars, {} text, {} commands, {} groups, {} verbatim, {} raw)",
+            tokens.total(),
+            tokens.chars,
+            tokens.texts,
+            tokens.commands,
+            tokens.begin_groups,
+            tokens.verbatims + tokens.verbatim_chunks,
+            tokens.raws + tokens.raw_chunks,
+        )?;
+        writeln!(f, "max depth:  {}", self.max_depth)?;
+        write!(f, "expansions: {}", self.expansions)?;
+        let timings = [
+            ("parse", self.timings.parse),
+            ("resolve", self.timings.resolve),
+            ("render", self.timings.render),
+        ];
+        for (phase, time) in &timings {
+            if let Some(time) = time {
+                write!(f, "\n{0}:{1:2$}{3:?}", phase, "", 11 - phase.len(), time)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,437 @@
+//! Generators of tokens and documents for property tests, as `proptest` strategies, for crates
+//! which build on this one. Requires the `testing` feature.
+//!
+//! Documents are generated as trees of `GenNode`s, which `proptest` shrinks by dropping and
+//! simplifying nodes, and are then written as source. `gen_document` produces the source
+//! directly, and `gen_tree` the tree, for tests which need to know what the source contains.
+//!
+//! The source of a generated document is always parsed without error by a `Parser` with the
+//! default options, as long as the depth is less than the nesting limit of the parser, 128.
+//!
+//! `assert_semantic_eq!` compares two documents or nodes as `Document::semantic_eq` does, and
+//! lists the differences between them if they are not equal.
+
+use std::fmt::{self, Write};
+
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+
+use ast::{self, Change, Document, Equivalence, Node, Verbatim};
+use tokenize::{self, Token, SOFT_HYPHEN};
+
+/// The inline commands which generated documents use, with the number of arguments of each.
+const COMMANDS: &[(&str, usize)] = &[
+    ("emph", 1),
+    ("bold", 1),
+    ("code", 1),
+    ("footnote", 1),
+    ("link", 2),
+    ("color", 2),
+];
+
+/// The environments which generated documents use, none of which take arguments.
+const ENVIRONMENTS: &[&str] = &["quote", "center"];
+
+/// Which constructs generated documents may contain, besides text. Every construct is allowed by
+/// default.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Mix {
+    commands: bool,
+    groups: bool,
+    environments: bool,
+    verbatim: bool,
+}
+
+impl Default for Mix {
+    fn default() -> Mix {
+        Mix {
+            commands: true,
+            groups: true,
+            environments: true,
+            verbatim: true,
+        }
+    }
+}
+
+impl Mix {
+    /// Constructs a new `Mix` which allows every construct.
+    pub fn new() -> Mix {
+        Mix::default()
+    }
+
+    /// Constructs a new `Mix` which allows only text.
+    pub fn text_only() -> Mix {
+        Mix {
+            commands: false,
+            groups: false,
+            environments: false,
+            verbatim: false,
+        }
+    }
+
+    /// Sets whether documents may contain built-in commands with arguments, such as `\emph{..}`.
+    pub fn commands(mut self, allow: bool) -> Mix {
+        self.commands = allow;
+        self
+    }
+
+    /// Returns whether documents may contain commands.
+    pub fn get_commands(&self) -> bool {
+        self.commands
+    }
+
+    /// Sets whether documents may contain groups, `{..}`.
+    pub fn groups(mut self, allow: bool) -> Mix {
+        self.groups = allow;
+        self
+    }
+
+    /// Returns whether documents may contain groups.
+    pub fn get_groups(&self) -> bool {
+        self.groups
+    }
+
+    /// Sets whether documents may contain environments, `\begin{..}..\end{..}`.
+    pub fn environments(mut self, allow: bool) -> Mix {
+        self.environments = allow;
+        self
+    }
+
+    /// Returns whether documents may contain environments.
+    pub fn get_environments(&self) -> bool {
+        self.environments
+    }
+
+    /// Sets whether documents may contain `\verbatim` and `\raw` commands.
+    pub fn verbatim(mut self, allow: bool) -> Mix {
+        self.verbatim = allow;
+        self
+    }
+
+    /// Returns whether documents may contain `\verbatim` and `\raw` commands.
+    pub fn get_verbatim(&self) -> bool {
+        self.verbatim
+    }
+
+    /// Returns whether any construct which contains other nodes is allowed.
+    fn nests(&self) -> bool {
+        self.commands || self.groups || self.environments
+    }
+}
+
+/// A node of a generated document, which is written as source by `GenDocument`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GenNode {
+    /// Words of letters and punctuation, separated by single spaces.
+    Text(String),
+    /// A built-in command, with one list of nodes for each of its arguments.
+    Command(&'static str, Vec<Vec<GenNode>>),
+    /// A group.
+    Group(Vec<GenNode>),
+    /// An environment, with its content.
+    Environment(&'static str, Vec<GenNode>),
+    /// A `\verbatim` command, with its content and language.
+    Verbatim(Verbatim),
+    /// A `\raw` command, with its content.
+    Raw(String),
+}
+
+impl GenNode {
+    /// Appends the tokens of the node to `tokens`.
+    fn push_tokens(&self, tokens: &mut Vec<Token>) {
+        match *self {
+            GenNode::Text(ref text) => tokens.extend(text.chars().map(Token::Char)),
+            GenNode::Command(name, ref args) => {
+                tokens.push(Token::Command(name.into()));
+                for arg in args {
+                    push_group(arg, tokens);
+                }
+            }
+            GenNode::Group(ref nodes) => push_group(nodes, tokens),
+            GenNode::Environment(name, ref content) => {
+                tokens.push(Token::Command("begin".into()));
+                push_name(name, tokens);
+                for node in content {
+                    node.push_tokens(tokens);
+                }
+                tokens.push(Token::Command("end".into()));
+                push_name(name, tokens);
+            }
+            GenNode::Verbatim(ref verbatim) => {
+                tokens.push(Token::Verbatim(Box::new(verbatim.clone())))
+            }
+            GenNode::Raw(ref content) => tokens.push(Token::Raw(content.as_str().into())),
+        }
+    }
+}
+
+/// Appends the tokens of a group containing the nodes to `tokens`.
+fn push_group(nodes: &[GenNode], tokens: &mut Vec<Token>) {
+    tokens.push(Token::BeginGroup);
+    for node in nodes {
+        node.push_tokens(tokens);
+    }
+    tokens.push(Token::EndGroup);
+}
+
+/// Appends the tokens of a name in braces, as given to `\begin` and `\end`, to `tokens`.
+fn push_name(name: &str, tokens: &mut Vec<Token>) {
+    tokens.push(Token::BeginGroup);
+    tokens.extend(name.chars().map(Token::Char));
+    tokens.push(Token::EndGroup);
+}
+
+/// A generated document, as a list of paragraphs. `Display` writes it as source, with paragraphs
+/// separated by blank lines.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GenDocument {
+    pub paragraphs: Vec<Vec<GenNode>>,
+}
+
+impl GenDocument {
+    /// Returns the tokens which the source of the document consists of, as read by `Tokens` with
+    /// the default options.
+    pub fn tokens(&self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        for (i, paragraph) in self.paragraphs.iter().enumerate() {
+            if i > 0 {
+                tokens.extend([Token::Char('\n'), Token::Char('\n')]);
+            }
+            for node in paragraph {
+                node.push_tokens(&mut tokens);
+            }
+        }
+        tokens
+    }
+
+    /// Returns the source of the document.
+    pub fn to_source(&self) -> String {
+        let mut source = Vec::new();
+        // Generated tokens are always writable, and writing to a `Vec` cannot fail.
+        let _ = tokenize::write_tokens(self.tokens(), &mut source);
+        String::from_utf8(source).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for GenDocument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_source())
+    }
+}
+
+/// Returns a strategy for single tokens, of every kind which `Tokens` produces with the default
+/// options: ordinary and escaped characters, commands, group delimiters, and the contents of
+/// `\verbatim` and `\raw` commands.
+pub fn gen_token() -> BoxedStrategy<Token> {
+    prop_oneof![
+        4 => gen_char().prop_map(Token::Char),
+        2 => "[a-zA-Z]{1,10}"
+            .prop_filter("not a verbatim-like command", |name| {
+                name != "verbatim" && name != "raw"
+            })
+            .prop_map(|name| Token::Command(name.into())),
+        1 => Just(Token::BeginGroup),
+        1 => Just(Token::EndGroup),
+        1 => gen_verbatim().prop_map(|verbatim| Token::Verbatim(Box::new(verbatim))),
+        1 => gen_content().prop_map(|content| Token::Raw(content.into())),
+    ]
+    .boxed()
+}
+
+/// Returns a strategy for sequences of at most `len` tokens, as produced by `gen_token`, which
+/// `write_tokens` writes as source that `Tokens` with the default options reads back as the same
+/// sequence. Groups need not be balanced.
+pub fn gen_token_stream(len: usize) -> BoxedStrategy<Vec<Token>> {
+    vec(gen_token(), 0..=len)
+        .prop_map(|mut tokens| {
+            // A letter after a command would be read as part of its name.
+            let mut after_command = false;
+            tokens.retain(|token| {
+                let keep = !(after_command && matches!(*token, Token::Char(c) if c.is_alphabetic()));
+                if keep {
+                    after_command = matches!(*token, Token::Command(_));
+                }
+                keep
+            });
+            tokens
+        })
+        .boxed()
+}
+
+/// Returns a strategy for the source of documents which may contain every construct, nested at
+/// most `depth` levels deep, with about `size` nodes in all. See `gen_tree`.
+pub fn gen_document(depth: u32, size: u32) -> BoxedStrategy<String> {
+    gen_tree(Mix::new(), depth, size)
+        .prop_map(|document| document.to_source())
+        .boxed()
+}
+
+/// Returns a strategy for documents containing the constructs allowed by `mix`, nested at most
+/// `depth` levels deep, with about `size` nodes in all. Each document is parsed without error.
+pub fn gen_tree(mix: Mix, depth: u32, size: u32) -> BoxedStrategy<GenDocument> {
+    let leaf = if mix.verbatim {
+        prop_oneof![
+            4 => gen_text(),
+            1 => gen_verbatim().prop_map(GenNode::Verbatim),
+            1 => gen_content().prop_map(GenNode::Raw),
+        ]
+        .boxed()
+    } else {
+        gen_text()
+    };
+    let node = if mix.nests() {
+        leaf.prop_recursive(depth, size, 4, move |inner| {
+            let nodes = vec(inner, 0..4);
+            let mut branches: Vec<BoxedStrategy<GenNode>> = Vec::new();
+            if mix.commands {
+                let nodes = nodes.clone();
+                branches.push(
+                    prop::sample::select(COMMANDS)
+                        .prop_flat_map(move |(name, arguments)| {
+                            vec(nodes.clone(), arguments)
+                                .prop_map(move |args| GenNode::Command(name, args))
+                        })
+                        .boxed(),
+                );
+            }
+            if mix.groups {
+                branches.push(nodes.clone().prop_map(GenNode::Group).boxed());
+            }
+            if mix.environments {
+                branches.push(
+                    (prop::sample::select(ENVIRONMENTS), nodes)
+                        .prop_map(|(name, content)| GenNode::Environment(name, content))
+                        .boxed(),
+                );
+            }
+            prop::strategy::Union::new(branches)
+        })
+        .boxed()
+    } else {
+        leaf
+    };
+    vec(vec(node, 1..6), 0..4)
+        .prop_map(|paragraphs| GenDocument { paragraphs })
+        .boxed()
+}
+
+/// Returns a strategy for the characters of `Token::Char`s: printable ASCII, including the
+/// characters which are escaped, whitespace, the soft hyphen, and a few other letters.
+fn gen_char() -> impl Strategy<Value = char> {
+    prop_oneof![
+        8 => prop::char::range(' ', '~'),
+        1 => prop::sample::select(&['\n', '\t', SOFT_HYPHEN, '\u{e9}', '\u{3b1}', '\u{4e2d}'][..]),
+    ]
+}
+
+/// Returns a strategy for `GenNode::Text`.
+fn gen_text() -> BoxedStrategy<GenNode> {
+    "[a-zA-Z,.!?]{1,8}( [a-zA-Z,.!?]{1,8}){0,5}".prop_map(GenNode::Text).boxed()
+}
+
+/// Returns a strategy for the contents of `\verbatim` and `\raw` commands, which may contain any
+/// printable ASCII character and newlines.
+fn gen_content() -> impl Strategy<Value = String> {
+    "[ -~\n]{0,24}"
+}
+
+/// Returns a strategy for the contents and language of `\verbatim` commands.
+fn gen_verbatim() -> impl Strategy<Value = Verbatim> {
+    (gen_content(), option::of("[a-z]{1,8}"))
+        .prop_map(|(content, language)| Verbatim { content, language })
+}
+
+/// A tree which `assert_semantic_eq!` can compare: a `Document` or a `Node`.
+pub trait SemanticTree {
+    /// Returns the tree in normal form, as a document, as described by `Equivalence`.
+    fn normal_form(&self) -> Document;
+}
+
+impl SemanticTree for Document {
+    fn normal_form(&self) -> Document {
+        Equivalence::new().normalize(self)
+    }
+}
+
+impl SemanticTree for Node {
+    fn normal_form(&self) -> Document {
+        Document {
+            nodes: Equivalence::new().normalize_node(self),
+        }
+    }
+}
+
+/// Returns `None` if the two trees are semantically equal, and otherwise a description of the
+/// differences between their normal forms, with one line for each change found by `ast::diff`.
+/// This is what `assert_semantic_eq!` reports when it fails.
+pub fn semantic_diff<T: SemanticTree + ?Sized>(left: &T, right: &T) -> Option<String> {
+    let (left, right) = (left.normal_form(), right.normal_form());
+    if left == right {
+        return None;
+    }
+    let mut out = String::new();
+    let node = |document: &Document, path: &[usize]| {
+        ast::diff::node_at(&document.nodes, path)
+            .map_or_else(|| "?".into(), |node| format!("{:?}", node))
+    };
+    for change in ast::diff(&left, &right) {
+        // Writing to a `String` cannot fail.
+        let _ = match change {
+            Change::Inserted { ref new } => {
+                writeln!(out, "  right {:?}: {}", new, node(&right, new))
+            }
+            Change::Deleted { ref old } => {
+                writeln!(out, "  left {:?}: {}", old, node(&left, old))
+            }
+            Change::Moved { ref old, ref new } => {
+                writeln!(out, "  moved from left {:?} to right {:?}", old, new)
+            }
+            Change::Text {
+                ref old,
+                ref new,
+                ref old_text,
+                ref new_text,
+            } => writeln!(
+                out,
+                "  text at left {:?}, right {:?}: {:?} != {:?}",
+                old, new, old_text, new_text
+            ),
+            Change::Modified { ref old, ref new } => writeln!(
+                out,
+                "  left {:?}: {}\n  right {:?}: {}",
+                old,
+                node(&left, old),
+                new,
+                node(&right, new)
+            ),
+        };
+    }
+    Some(out)
+}
+
+/// Asserts that two documents or nodes are semantically equal, as with `Document::semantic_eq`
+/// and `Node::semantic_eq`, ignoring insignificant whitespace, the way text is split into nodes,
+/// and empty groups. On failure, the panic message lists each difference, with the paths of the
+/// nodes as described by `ast::Path`. Like `assert_eq!`, it may be given a message as further
+/// arguments.
+#[macro_export]
+macro_rules! assert_semantic_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        if let Some(diff) = $crate::testing::semantic_diff(&$left, &$right) {
+            panic!(
+                "assertion failed: `left` and `right` are not semantically equal\n{}",
+                diff
+            );
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        if let Some(diff) = $crate::testing::semantic_diff(&$left, &$right) {
+            panic!(
+                "assertion failed: `left` and `right` are not semantically equal: {}\n{}",
+                format_args!($($arg)+),
+                diff
+            );
+        }
+    };
+}
@@ -0,0 +1,57 @@
+use std::io::BufRead;
+use std::mem;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use errors::Error;
+
+use super::{Token, Tokens};
+
+/// The number of batches which may wait in the channel before the worker blocks, so that a slow
+/// consumer does not make the tokenizer buffer the whole input.
+const BOUND: usize = 4;
+
+/// The receiving end of the channel returned by `spawn`, which yields batches of tokens, or the
+/// error which ended the input.
+pub type Batches = Receiver<Result<Vec<Token>, Error>>;
+
+// Tokens and errors are sent between threads.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Token>();
+    assert_send::<Error>();
+};
+
+/// Runs the tokenizer on a new thread, so that tokenizing the input overlaps with processing the
+/// tokens, and returns the thread along with a channel of the tokens.
+///
+/// The tokens are sent in batches of up to `chunk_size`, as read by `Tokens::next_chunk`, with
+/// at most a few batches waiting in the channel at once. The channel closes at the end of input,
+/// or after the first error, which is sent after any tokens read before it. If the receiver is
+/// dropped, the thread stops at the next batch. Joining the thread returns the tokenizer, from
+/// which the warnings of the lints can be taken.
+///
+/// A `chunk_size` of 0 is treated as 1.
+pub fn spawn<R>(mut tokens: Tokens<R>, chunk_size: usize) -> (JoinHandle<Tokens<R>>, Batches)
+where
+    R: BufRead + Send + 'static,
+{
+    let chunk_size = chunk_size.max(1);
+    let (sender, receiver) = mpsc::sync_channel(BOUND);
+    let handle = thread::spawn(move || {
+        let mut batch = Vec::with_capacity(chunk_size);
+        loop {
+            let result = match tokens.next_chunk(&mut batch, chunk_size) {
+                Ok(0) => break,
+                Ok(_) => Ok(mem::replace(&mut batch, Vec::with_capacity(chunk_size))),
+                Err(error) => Err(error),
+            };
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        }
+        tokens
+    });
+    (handle, receiver)
+}
@@ -0,0 +1,357 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use ast::Verbatim;
+use errors::{Error, ErrorKind, Position, Warning};
+use lints::Lints;
+use super::striter::StrIter;
+use super::{is_special, opens_quote, CharSet, Name, Token, SOFT_HYPHEN};
+
+/// A single token of input, borrowing its contents from the input where it can, as produced by
+/// `StrTokens`. The variants correspond to those of `Token`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BorrowedToken<'a> {
+    /// An ordinary character, or a special character escaped with a backslash.
+    Char(char),
+    /// A command, with its name stored without the backslash.
+    Command(&'a str),
+    /// The start of a group (`{`).
+    BeginGroup,
+    /// The end of a group (`}`).
+    EndGroup,
+    /// The contents of a `\verbatim` command, which are owned only if they contained a doubled
+    /// delimiter, and its language, if it has one.
+    Verbatim {
+        content: Cow<'a, str>,
+        language: Option<&'a str>,
+    },
+    /// The contents of a `\raw` command, which are owned only if they contained a doubled
+    /// delimiter.
+    Raw(Cow<'a, str>),
+    /// A run of two or more ordinary characters, produced only if `StrTokens::coalesce_text` is
+    /// set.
+    Text(&'a str),
+}
+
+impl<'a> BorrowedToken<'a> {
+    /// Converts the token into the equivalent `Token`, copying any borrowed contents.
+    pub fn into_owned(self) -> Token {
+        match self {
+            BorrowedToken::Char(c) => Token::Char(c),
+            BorrowedToken::Command(name) => Token::Command(Name::new(name)),
+            BorrowedToken::BeginGroup => Token::BeginGroup,
+            BorrowedToken::EndGroup => Token::EndGroup,
+            BorrowedToken::Verbatim { content, language } => Token::Verbatim(Box::new(Verbatim {
+                content: content.into_owned(),
+                language: language.map(String::from),
+            })),
+            BorrowedToken::Raw(content) => Token::Raw(content.into()),
+            BorrowedToken::Text(text) => Token::Text(text.into()),
+        }
+    }
+}
+
+impl<'a> From<BorrowedToken<'a>> for Token {
+    fn from(token: BorrowedToken<'a>) -> Token {
+        token.into_owned()
+    }
+}
+
+/// A tokenizer over a `&str`, which produces the same tokens as `Tokens`, but borrows command
+/// names, runs of text, and the contents of verbatim and raw commands from the input rather than
+/// copying them.
+#[derive(Debug)]
+pub struct StrTokens<'a> {
+    input: StrIter<'a>,
+    /// The position at which the most recently read token started.
+    location: Position,
+    smart_punctuation: bool,
+    coalesce_text: bool,
+    /// The characters other than `\`, `{`, and `}` which end a run of text.
+    special_chars: CharSet,
+    /// The most recently read character, which decides whether a quote opens or closes.
+    previous: Option<char>,
+    /// The name of the input, such as the name of a file, which is attached to errors.
+    name: Option<String>,
+}
+
+impl<'a> StrTokens<'a> {
+    /// Constructs a new `StrTokens` over the given string.
+    pub fn new(input: &'a str) -> StrTokens<'a> {
+        StrTokens {
+            input: StrIter::new(input),
+            location: Position::default(),
+            smart_punctuation: false,
+            coalesce_text: false,
+            special_chars: CharSet::default(),
+            previous: None,
+            name: None,
+        }
+    }
+
+    /// Sets whether typographic punctuation is substituted, as with `Tokens::smart_punctuation`.
+    pub fn smart_punctuation(mut self, smart: bool) -> StrTokens<'a> {
+        self.smart_punctuation = smart;
+        self
+    }
+
+    /// Sets whether runs of ordinary characters are read as a single `BorrowedToken::Text`, as
+    /// with `Tokens::coalesce_text`.
+    pub fn coalesce_text(mut self, coalesce: bool) -> StrTokens<'a> {
+        self.coalesce_text = coalesce;
+        self
+    }
+
+    /// Sets additional characters which end a run of text, as with `Tokens::special_chars`.
+    pub fn special_chars(mut self, chars: &str) -> StrTokens<'a> {
+        self.special_chars = CharSet::new(chars);
+        self
+    }
+
+    /// Sets the lints checked on each line of input, as with `Tokens::lints`.
+    pub fn lints(mut self, lints: Lints) -> StrTokens<'a> {
+        self.input.set_lints(lints);
+        self
+    }
+
+    /// Returns the warnings produced by the lints since they were last taken, leaving none.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.input.take_warnings()
+    }
+
+    /// Sets the name of the input, which is attached to any errors, as with `Tokens::with_name`.
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> StrTokens<'a> {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Returns the name of the input, if it has one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the position at which the most recently read token started.
+    pub fn location(&self) -> Position {
+        self.location
+    }
+
+    /// Returns the byte offset in the input of the end of the most recently read token.
+    pub fn position(&self) -> usize {
+        self.input.position()
+    }
+
+    /// Returns the next token, or `None` if the end of input has been reached.
+    pub fn next_res(&mut self) -> Result<Option<BorrowedToken<'a>>, Error> {
+        self.read_token().map_err(|e| match self.name {
+            Some(ref name) if e.file().is_none() => e.with_file(name.clone()),
+            _ => e,
+        })
+    }
+
+    /// Returns the position of the most recently read character.
+    fn current(&self, c: char) -> Position {
+        Position {
+            line: self.input.line(),
+            column: self.input.column(),
+            offset: self.input.position() - c.len_utf8(),
+            utf16_column: self.input.utf16_position() - c.len_utf16() + 1,
+        }
+    }
+
+    fn read_token(&mut self) -> Result<Option<BorrowedToken<'a>>, Error> {
+        let c = match self.input.next()? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        self.location = self.current(c);
+        let token = match c {
+            '\\' => self.command()?,
+            '{' => BorrowedToken::BeginGroup,
+            '}' => BorrowedToken::EndGroup,
+            c if self.coalesce_text
+                && !self.is_special(c)
+                && self.input.peek().is_some_and(|next| !self.is_special(next)) =>
+            {
+                self.text()
+            }
+            c if self.smart_punctuation => BorrowedToken::Char(self.smart_char(c)?),
+            c => BorrowedToken::Char(c),
+        };
+        self.previous = Some(match token {
+            BorrowedToken::Char(produced) => produced,
+            BorrowedToken::Text(text) => text.chars().next_back().unwrap_or(c),
+            _ => c,
+        });
+        Ok(Some(token))
+    }
+
+    /// Returns whether a character ends a run of text.
+    fn is_special(&self, c: char) -> bool {
+        is_special(c, &self.special_chars, self.smart_punctuation)
+    }
+
+    /// Reads a run of ordinary characters in the current line, starting with the
+    /// already-consumed character at the location of the token.
+    fn text(&mut self) -> BorrowedToken<'a> {
+        let special_chars = &self.special_chars;
+        let smart = self.smart_punctuation;
+        self.input.skip_while(|c| !is_special(c, special_chars, smart));
+        BorrowedToken::Text(self.input.slice(self.location.offset, self.input.position()))
+    }
+
+    /// Returns the typographic replacement for a character read from the input, consuming the
+    /// rest of a dash or ellipsis.
+    fn smart_char(&mut self, c: char) -> Result<char, Error> {
+        let opening = opens_quote(self.previous);
+        let smart = match c {
+            '\'' if opening => '\u{2018}',
+            '\'' => '\u{2019}',
+            '"' if opening => '\u{201c}',
+            '"' => '\u{201d}',
+            '-' if self.input.peek() == Some('-') => {
+                self.input.next()?;
+                if self.input.peek() == Some('-') {
+                    self.input.next()?;
+                    '\u{2014}'
+                } else {
+                    '\u{2013}'
+                }
+            }
+            '.' if self.input.peek() == Some('.') && self.input.peek_second() == Some('.') => {
+                self.input.next()?;
+                self.input.next()?;
+                '\u{2026}'
+            }
+            c => c,
+        };
+        Ok(smart)
+    }
+
+    /// Reads the remainder of a command or escaped character, after the initial backslash.
+    fn command(&mut self) -> Result<BorrowedToken<'a>, Error> {
+        let position = self.location();
+        let c = self
+            .input
+            .expect_next("a character to escape or a command name", position)?;
+        if c == '-' {
+            return Ok(BorrowedToken::Char(SOFT_HYPHEN));
+        }
+        if !c.is_alphabetic() {
+            return Ok(BorrowedToken::Char(c));
+        }
+        self.input.skip_while(char::is_alphabetic);
+        let name = self.input.slice(position.offset + 1, self.input.position());
+        match name {
+            "verbatim" => {
+                let language = self.language(position)?;
+                let content = self.verbatim(position)?;
+                Ok(BorrowedToken::Verbatim { content, language })
+            }
+            "raw" => self.verbatim(position).map(BorrowedToken::Raw),
+            _ => Ok(BorrowedToken::Command(name)),
+        }
+    }
+
+    /// Reads the optional language of a `\verbatim` command which started at the given position,
+    /// as with `Tokens`.
+    fn language(&mut self, position: Position) -> Result<Option<&'a str>, Error> {
+        if self.input.peek() != Some('[') {
+            return Ok(None);
+        }
+        self.input.next()?;
+        let start = self.input.position();
+        loop {
+            match self.input.next()? {
+                Some(']') => break,
+                Some('\n') => {
+                    return Err(ErrorKind::UnclosedVerbatim {
+                        start: position,
+                        delimiter: Some(']'),
+                        end: self.current('\n'),
+                    }
+                    .into());
+                }
+                None => return Err(self.unclosed(position, Some(']'))),
+                Some(_) => {}
+            }
+        }
+        let language = self.input.slice(start, self.input.position() - 1).trim();
+        Ok(if language.is_empty() {
+            None
+        } else {
+            Some(language)
+        })
+    }
+
+    /// Reads the uninterpreted argument of a verbatim-like command which started at the given
+    /// position, as with `Tokens`. The argument is borrowed unless it contains a doubled
+    /// delimiter, which must be replaced by a single one.
+    fn verbatim(&mut self, position: Position) -> Result<Cow<'a, str>, Error> {
+        let delim = match self.input.next()? {
+            Some(delim) => delim,
+            None => return Err(self.unclosed(position, None)),
+        };
+        let closing = if delim == '{' { '}' } else { delim };
+        let mut start = self.input.position();
+        if delim == '{' {
+            let mut depth = 0;
+            loop {
+                match self.input.next()?.ok_or_else(|| self.unclosed(position, Some(closing)))? {
+                    '}' if depth == 0 => break,
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            let end = self.input.position() - 1;
+            return Ok(Cow::Borrowed(self.input.slice(start, end)));
+        }
+        let mut owned: Option<String> = None;
+        loop {
+            match self.input.next()?.ok_or_else(|| self.unclosed(position, Some(closing)))? {
+                c if c == delim => {
+                    if self.input.peek() != Some(delim) {
+                        break;
+                    }
+                    // Keep the first of the pair, and skip the second.
+                    let end = self.input.position();
+                    owned
+                        .get_or_insert_with(String::new)
+                        .push_str(self.input.slice(start, end));
+                    self.input.next()?;
+                    start = self.input.position();
+                }
+                _ => {}
+            }
+        }
+        let rest = self.input.slice(start, self.input.position() - delim.len_utf8());
+        Ok(match owned {
+            Some(mut content) => {
+                content.push_str(rest);
+                Cow::Owned(content)
+            }
+            None => Cow::Borrowed(rest),
+        })
+    }
+
+    /// Returns an `ErrorKind::UnclosedVerbatim` error for a command which started at `start`, and
+    /// was not closed by `delimiter` before the end of input.
+    fn unclosed(&self, start: Position, delimiter: Option<char>) -> Error {
+        ErrorKind::UnclosedVerbatim {
+            start,
+            delimiter,
+            end: self.input.end_position(),
+        }
+        .into()
+    }
+}
+
+impl<'a> Iterator for StrTokens<'a> {
+    type Item = Result<BorrowedToken<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<BorrowedToken<'a>, Error>> {
+        self.next_res().transpose()
+    }
+}
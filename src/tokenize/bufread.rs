@@ -0,0 +1,232 @@
+use std::io::{BufRead, Read};
+use std::mem;
+use std::str;
+
+use errors::{Error, ErrorKind, Position, ResultExt, Warning};
+use limits::{Limit, Limits};
+use lints::Lints;
+
+/// A struct providing `next` and `peek` methods to iterate over the chars of a `BufRead`.
+///
+/// This struct is not actually an `Iterator`, because `next` returns `Result<Option<char>,
+/// Error>`, instead of `Option<_>`.
+#[derive(Debug)]
+pub struct BufReadIter<R> {
+    input: R,
+    byte_buf: Vec<u8>,
+    vec_buf: Vec<char>,
+    column: usize,
+    line: usize,
+    /// The number of lines read, including a last line without a line ending.
+    lines_read: usize,
+    /// The number of bytes read, up to the end of the current character.
+    position: usize,
+    /// The number of UTF-16 code units of the current line read, up to the end of the current
+    /// character.
+    utf16_position: usize,
+    lints: Lints,
+    /// The number of bytes, not counting the line ending, above which a line is an error.
+    max_line_length: usize,
+    /// The warnings produced by the lints which have not yet been taken.
+    warnings: Vec<Warning>,
+    /// Whether the end of input has been reached.
+    ended: bool,
+}
+
+impl<R: BufRead> BufReadIter<R> {
+    /// Constructs a new `BufReadIter` from the given `BufRead`.
+    pub fn new(input: R) -> BufReadIter<R> {
+        BufReadIter {
+            input,
+            byte_buf: Vec::new(),
+            vec_buf: Vec::new(),
+            column: 0,
+            line: 0,
+            lines_read: 0,
+            position: 0,
+            utf16_position: 0,
+            lints: Lints::default(),
+            max_line_length: Limits::default().get_max_line_length(),
+            warnings: Vec::new(),
+            ended: false,
+        }
+    }
+
+    /// Sets the lints checked on each line as it is read.
+    pub fn set_lints(&mut self, lints: Lints) {
+        self.lints = lints;
+    }
+
+    /// Sets the number of bytes, not counting the line ending, above which a line is an error.
+    pub fn set_max_line_length(&mut self, length: usize) {
+        self.max_line_length = length;
+    }
+
+    /// Returns the warnings produced by the lints since they were last taken, leaving none.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// Returns the current character number in the line.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns the current line number.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the number of lines read, including a last line without a line ending.
+    pub fn lines_read(&self) -> usize {
+        self.lines_read
+    }
+
+    /// Returns the number of bytes read, up to the end of the current character.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the number of UTF-16 code units of the current line read, up to the end of the
+    /// current character.
+    pub fn utf16_position(&self) -> usize {
+        self.utf16_position
+    }
+
+    /// Returns the position of the end of input, once `next` has returned `None`: just after the
+    /// last character, or at the start of the following line if that character is a newline.
+    pub fn end_position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column + 1,
+            offset: self.position,
+            utf16_column: self.utf16_position + 1,
+        }
+    }
+
+    /// Fills the internal buffer, discarding its old contents.
+    ///
+    /// The line is read as bytes and then validated, so that an error can give the position of
+    /// the first character which could not be read: the start of an invalid UTF-8 sequence, or
+    /// the character after those read before an IO error. The lints are then checked on the line.
+    /// The buffers are reused from line to line, so that reading a line does not allocate.
+    ///
+    /// No more of a line than its maximum length and a line ending is read, so that a line which
+    /// is too long fails without being held in memory as a whole.
+    fn fill_buffer(&mut self) -> Result<(), Error> {
+        self.byte_buf.clear();
+        let max = self.max_line_length.saturating_add(2);
+        let read = (&mut self.input)
+            .take(max as u64)
+            .read_until(b'\n', &mut self.byte_buf);
+        if let Ok(0) = read {
+            // The position stays just after the last character, unless it ends a line.
+            if self.vec_buf.last().is_none_or(|&c| c == '\n') {
+                self.column = 0;
+                self.utf16_position = 0;
+                self.line += 1;
+            }
+            self.vec_buf.clear();
+            return Ok(());
+        }
+        self.column = 0;
+        self.utf16_position = 0;
+        self.line += 1;
+        self.lines_read += 1;
+        let text = str::from_utf8(&self.byte_buf);
+        let position = |valid: usize| {
+            let before = String::from_utf8_lossy(&self.byte_buf[..valid]);
+            Position {
+                line: self.line,
+                column: before.chars().count() + 1,
+                offset: self.position + valid,
+                utf16_column: before.encode_utf16().count() + 1,
+            }
+        };
+        read.with_context(|e| {
+            let valid = text.as_ref().map_or_else(|e| e.valid_up_to(), |text| text.len());
+            ErrorKind::from_io(e, position(valid))
+        })?;
+        let content = self.byte_buf.strip_suffix(b"\n").unwrap_or(&self.byte_buf);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        if content.len() > self.max_line_length {
+            let valid = match str::from_utf8(&content[..self.max_line_length]) {
+                Ok(text) => text.len(),
+                // The limit falls within a character.
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => return Err(Error::new(ErrorKind::Unicode(position(e.valid_up_to())), e)),
+            };
+            return Err(ErrorKind::LimitExceeded {
+                what: Limit::Line,
+                limit: self.max_line_length,
+                position: position(valid),
+            }
+            .into());
+        }
+        let text = text.map_err(|e| Error::new(ErrorKind::Unicode(position(e.valid_up_to())), e))?;
+        self.vec_buf.clear();
+        self.vec_buf.extend(text.chars());
+        let start = Position {
+            line: self.line,
+            column: 1,
+            offset: self.position,
+            utf16_column: 1,
+        };
+        self.lints.check_line(text, start, &mut self.warnings)
+    }
+
+    /// Advances the iterator, returning the next character if present, or any errors encountered.
+    pub fn next(&mut self) -> Result<Option<char>, Error> {
+        if self.ended {
+            return Ok(None);
+        }
+        self.column += 1;
+        if self.column >= self.vec_buf.len() {
+            self.fill_buffer()?;
+        }
+        let c = self.vec_buf.get(self.column).cloned();
+        match c {
+            Some(c) => {
+                self.position += c.len_utf8();
+                self.utf16_position += c.len_utf16();
+            }
+            None => self.ended = true,
+        }
+        Ok(c)
+    }
+
+    /// Advances the iterator, returning the next character. If end of input is reached, returns an
+    /// `ErrorKind::EndOfInput` error for a token which started at `start`, and which required
+    /// `expected` to complete it.
+    pub fn expect_next(&mut self, expected: &'static str, start: Position) -> Result<char, Error> {
+        self.next()?.ok_or_else(|| {
+            ErrorKind::EndOfInput {
+                expected,
+                position: start,
+            }
+            .into()
+        })
+    }
+
+    /// Advances the iterator past the characters remaining in the line for which `predicate`
+    /// holds, stopping at the first for which it does not, and appends them to `out`.
+    pub fn next_while<F: FnMut(char) -> bool>(&mut self, out: &mut String, mut predicate: F) {
+        let rest = self.vec_buf.get(self.column + 1..).unwrap_or_default();
+        let len = rest.iter().position(|&c| !predicate(c)).unwrap_or(rest.len());
+        out.extend(&rest[..len]);
+        self.position += rest[..len].iter().map(|c| c.len_utf8()).sum::<usize>();
+        self.utf16_position += rest[..len].iter().map(|c| c.len_utf16()).sum::<usize>();
+        self.column += len;
+    }
+
+    /// Returns the next character in the line without advancing the stream. A `None` value just
+    /// indicates that the end of the line has been reached, not necessarily the end of the text.
+    pub fn peek(&self) -> Option<&char> {
+        self.vec_buf.get(self.column + 1)
+    }
+
+    /// Returns the character after the next one in the line, as with `peek`.
+    pub fn peek_second(&self) -> Option<&char> {
+        self.vec_buf.get(self.column + 2)
+    }
+}
@@ -0,0 +1,236 @@
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::mem;
+
+/// An encoding other than UTF-8 from which a `Decoder` transcodes its input.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Encoding {
+    /// ISO-8859-1, in which each byte is the character with the same code point, so that every
+    /// byte can be decoded.
+    Latin1,
+    /// Little-endian UTF-16. A leading byte order mark is skipped.
+    Utf16Le,
+    /// Big-endian UTF-16. A leading byte order mark is skipped.
+    Utf16Be,
+    /// UTF-16 in the byte order given by a leading byte order mark, which is skipped, or
+    /// big-endian if there is none.
+    Utf16,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Encoding::Latin1 => "Latin-1",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf16 => "UTF-16",
+        })
+    }
+}
+
+/// The error with which a strict `Decoder` fails on input which is not valid in its encoding,
+/// which `ErrorKind::from_io` converts to `ErrorKind::Undecodable`.
+#[derive(Debug)]
+pub(crate) struct DecodeError {
+    pub(crate) encoding: Encoding,
+    /// The byte offset in the input of the start of the invalid sequence.
+    offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} at byte {}", self.encoding, self.offset)
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// A `BufRead` which transcodes its input from another encoding into UTF-8, as it is read, so that
+/// it can be given to `Tokens` or `Parser`.
+///
+/// Since the decoded text is what is tokenized, the lines and columns of tokens and errors count
+/// the decoded characters, and their byte offsets count the bytes of the decoded UTF-8 rather
+/// than those of the input. Input which is not valid in the encoding, such as an unpaired UTF-16
+/// surrogate or a trailing odd byte, fails with `ErrorKind::Undecodable` at the position of the
+/// character it would have been, unless the decoder is lossy.
+#[derive(Debug)]
+pub struct Decoder<R> {
+    input: R,
+    encoding: Encoding,
+    lossy: bool,
+    /// The decoded UTF-8, of which the bytes from `start` on have not yet been consumed.
+    buf: Vec<u8>,
+    start: usize,
+    /// Input which has been read but not yet decoded, such as the first byte of a UTF-16 code
+    /// unit which was split across reads.
+    pending: Vec<u8>,
+    /// The byte offset in the input of the start of `pending`.
+    offset: usize,
+    /// Whether the start of input, at which a byte order mark may be, has been decoded.
+    started: bool,
+    /// An error which ended the decoded text in `buf`, to be returned once it has been consumed.
+    error: Option<DecodeError>,
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Constructs a new `Decoder` reading the given `BufRead` in the given encoding.
+    pub fn new(input: R, encoding: Encoding) -> Decoder<R> {
+        Decoder {
+            input,
+            encoding,
+            lossy: false,
+            buf: Vec::new(),
+            start: 0,
+            pending: Vec::new(),
+            offset: 0,
+            started: false,
+            error: None,
+        }
+    }
+
+    /// Sets whether input which is not valid in the encoding is replaced with U+FFFD REPLACEMENT
+    /// CHARACTER, rather than failing with `ErrorKind::Undecodable`. Defaults to `false`.
+    pub fn lossy(mut self, lossy: bool) -> Decoder<R> {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Returns the encoding of the input. For `Encoding::Utf16`, this is `Utf16Le` or `Utf16Be`
+    /// once the byte order mark has been read.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the underlying `BufRead`.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Decodes as much of `pending` as possible into `buf`, leaving any incomplete sequence at
+    /// its end, unless the end of input has been reached. Stops at the first invalid sequence
+    /// unless the decoder is lossy.
+    fn decode(&mut self, end: bool) {
+        let bytes = mem::take(&mut self.pending);
+        let mut i = 0;
+        if !self.started {
+            if self.encoding != Encoding::Latin1 && bytes.len() < 2 && !end {
+                self.pending = bytes;
+                return;
+            }
+            self.started = true;
+            let bom = match bytes.get(..2) {
+                Some([0xff, 0xfe]) => Some(Encoding::Utf16Le),
+                Some([0xfe, 0xff]) => Some(Encoding::Utf16Be),
+                _ => None,
+            };
+            match (self.encoding, bom) {
+                (Encoding::Utf16, Some(bom)) => {
+                    self.encoding = bom;
+                    i = 2;
+                }
+                (Encoding::Utf16, None) => self.encoding = Encoding::Utf16Be,
+                (encoding, Some(bom)) if encoding == bom => i = 2,
+                _ => {}
+            }
+        }
+        match self.encoding {
+            Encoding::Latin1 => {
+                let text: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+                self.buf.extend_from_slice(text.as_bytes());
+                i = bytes.len();
+            }
+            _ => i = self.decode_utf16(&bytes, i, end),
+        }
+        self.offset += i;
+        self.pending = bytes;
+        self.pending.drain(..i);
+    }
+
+    /// Decodes the UTF-16 in `bytes` from index `i` on, returning the index at which it stopped.
+    fn decode_utf16(&mut self, bytes: &[u8], mut i: usize, end: bool) -> usize {
+        let big_endian = self.encoding == Encoding::Utf16Be;
+        let unit = |i: usize| {
+            let pair = [bytes[i], bytes[i + 1]];
+            if big_endian {
+                u16::from_be_bytes(pair)
+            } else {
+                u16::from_le_bytes(pair)
+            }
+        };
+        let mut text = String::new();
+        while i < bytes.len() {
+            let (c, len) = if i + 2 > bytes.len() {
+                (None, 1)
+            } else {
+                match unit(i) {
+                    high @ 0xd800..=0xdbff if i + 4 <= bytes.len() => match unit(i + 2) {
+                        low @ 0xdc00..=0xdfff => {
+                            let c = 0x10000 + ((u32::from(high) - 0xd800) << 10)
+                                + (u32::from(low) - 0xdc00);
+                            (char::from_u32(c), 4)
+                        }
+                        _ => (None, 2),
+                    },
+                    0xd800..=0xdbff if !end => break,
+                    unit => (char::from_u32(u32::from(unit)), 2),
+                }
+            };
+            // A lone byte is incomplete until the end of input.
+            if len == 1 && !end {
+                break;
+            }
+            match c {
+                Some(c) => text.push(c),
+                None if self.lossy => text.push(char::REPLACEMENT_CHARACTER),
+                None => {
+                    self.error = Some(DecodeError {
+                        encoding: self.encoding,
+                        offset: self.offset + i,
+                    });
+                    i += len;
+                    break;
+                }
+            }
+            i += len;
+        }
+        self.buf.extend_from_slice(text.as_bytes());
+        i
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.start == self.buf.len() {
+            if let Some(error) = self.error.take() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+            }
+            self.buf.clear();
+            self.start = 0;
+            let input = self.input.fill_buf()?;
+            let len = input.len();
+            self.pending.extend_from_slice(input);
+            self.input.consume(len);
+            let end = len == 0;
+            self.decode(end);
+            if end && self.pending.is_empty() && self.error.is_none() {
+                break;
+            }
+        }
+        Ok(&self.buf[self.start..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.start = (self.start + amount).min(self.buf.len());
+    }
+}
@@ -0,0 +1,800 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+#[cfg(feature = "std")]
+use std::io::BufRead;
+
+use ast::Verbatim;
+#[cfg(feature = "std")]
+use errors::{Error, ErrorKind, Position, Warning};
+#[cfg(feature = "std")]
+use limits::{Limit, Limits};
+#[cfg(feature = "std")]
+use lints::Lints;
+#[cfg(feature = "std")]
+use stats::{Stats, TokenCounts};
+#[cfg(feature = "std")]
+use self::bufread::BufReadIter;
+#[cfg(feature = "unicode")]
+use unicode_segmentation::UnicodeSegmentation;
+
+#[cfg(feature = "std")]
+mod background;
+mod borrowed;
+#[cfg(feature = "std")]
+mod bufread;
+#[cfg(feature = "encoding")]
+mod decode;
+mod name;
+mod rewrite;
+mod striter;
+#[cfg(feature = "std")]
+mod write;
+
+#[cfg(feature = "std")]
+pub use self::background::{spawn, Batches};
+pub use self::borrowed::{BorrowedToken, StrTokens};
+#[cfg(feature = "encoding")]
+pub use self::decode::{Decoder, Encoding};
+#[cfg(feature = "encoding")]
+pub(crate) use self::decode::DecodeError;
+pub use self::name::Name;
+pub use self::rewrite::{Arrows, Chain, Rewritten, TokenRewriter};
+#[cfg(feature = "std")]
+pub(crate) use self::rewrite::Rewriting;
+#[cfg(feature = "std")]
+pub use self::write::write_tokens;
+
+/// The character produced by `\-`, which marks a point at which a word may be hyphenated.
+pub const SOFT_HYPHEN: char = '\u{ad}';
+
+/// The characters which always end a run of text.
+const ALWAYS_SPECIAL: CharSet = CharSet::ascii("\\{}");
+
+/// The characters which end a run of text when typographic punctuation is substituted, since they
+/// may be replaced.
+const SMART_SPECIAL: CharSet = CharSet::ascii("'\"-.");
+
+/// A single token of input.
+///
+/// With the `serde` feature, a token is serialized as an object whose `type` is the name of its
+/// variant in snake case, such as `"begin_group"`, and whose `value`, if it has one, is its
+/// payload.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Token {
+    /// An ordinary character, or a special character escaped with a backslash. The discretionary
+    /// hyphen `\-` is represented by `SOFT_HYPHEN`.
+    Char(char),
+    /// A command, consisting of a backslash followed by one or more alphabetic characters. The
+    /// name is stored without the backslash.
+    Command(Name),
+    /// The start of a group (`{`).
+    BeginGroup,
+    /// The end of a group (`}`).
+    EndGroup,
+    /// The contents of a `\verbatim` command.
+    Verbatim(Box<Verbatim>),
+    /// The contents of a `\raw` command.
+    Raw(Box<str>),
+    /// A run of two or more ordinary characters, which is produced in place of their
+    /// `Token::Char`s only if `Tokens::coalesce_text` is set, or a single grapheme cluster of
+    /// more than one character, if `Tokens::graphemes` is set.
+    Text(Box<str>),
+    /// A leading part of the contents of a `\verbatim` command, along with its language, which is
+    /// produced only if `Tokens::verbatim_chunk_size` is set. The rest of the contents follow as
+    /// further chunks, ending with a `Token::Verbatim`.
+    VerbatimChunk(Box<Verbatim>),
+    /// A leading part of the contents of a `\raw` command, as with `Token::VerbatimChunk`. The
+    /// chunks end with a `Token::Raw`.
+    RawChunk(Box<str>),
+}
+
+// Tokens are kept small, since most are `Char`s or delimiters, and large documents are often
+// buffered as vectors of tokens. Larger payloads are boxed.
+#[cfg(target_pointer_width = "64")]
+const _: () = assert!(mem::size_of::<Token>() == 24);
+
+impl Token {
+    /// Returns a short form of the token for use in messages, such as `` `\section` `` or
+    /// `` `{` ``. Whitespace characters are described in words, and verbatim and raw content is
+    /// represented by its command.
+    pub fn summary(&self) -> String {
+        match *self {
+            Token::Char(' ') => "a space".into(),
+            Token::Char('\t') => "a tab".into(),
+            Token::Char('\n') => "a newline".into(),
+            Token::Char(c) => format!("`{}`", c),
+            Token::Command(ref name) => format!("`\\{}`", name),
+            Token::BeginGroup => "`{`".into(),
+            Token::EndGroup => "`}`".into(),
+            Token::Verbatim(_) | Token::VerbatimChunk(_) => "`\\verbatim`".into(),
+            Token::Raw(_) | Token::RawChunk(_) => "`\\raw`".into(),
+            Token::Text(ref text) => format!("`{}`", text),
+        }
+    }
+}
+
+/// Returns `text` escaped so that it can be embedded in source as ordinary text, and is read back
+/// as the same characters, whether or not typographic punctuation is substituted.
+///
+/// Backslashes, braces, and quotes are escaped with a backslash, as is each `.` followed by
+/// another, which would otherwise start an ellipsis; consecutive hyphens are kept apart by an
+/// empty group, since `\-` is a soft hyphen, which is itself written as `\-`. Whitespace is left
+/// as it is, so a blank line in `text` still ends a paragraph, and text written directly after a
+/// command must not start with a letter, which would be read as part of its name.
+pub fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '{' | '}' | '\'' | '"' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '.' if chars.peek() == Some(&'.') => escaped.push_str("\\."),
+            '-' if chars.peek() == Some(&'-') => escaped.push_str("-{}"),
+            SOFT_HYPHEN => escaped.push_str("\\-"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A tokenizer over a `BufRead`.
+///
+/// Tokens are produced by `next_res`, which returns `Result<Option<Token>, Error>`; the
+/// `Iterator` implementation wraps it as `Option<Result<Token, Error>>`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Tokens<R> {
+    input: BufReadIter<R>,
+    /// The position at which the most recently read token started.
+    location: Position,
+    smart_punctuation: bool,
+    coalesce_text: bool,
+    /// The characters other than `\`, `{`, and `}` which end a run of text.
+    special_chars: CharSet,
+    /// The most recently read character, which decides whether a quote opens or closes.
+    previous: Option<char>,
+    /// The name of the input, such as the name of a file, which is attached to errors.
+    name: Option<String>,
+    /// A buffer for reading command names and runs of text, which is kept so that each is
+    /// allocated at most once, at its final size.
+    buffer: String,
+    /// An error which ended a chunk read by `next_chunk`, to be returned by the next read.
+    error: Option<Error>,
+    verbatim_chunk_size: Option<usize>,
+    /// The argument of a verbatim-like command whose contents are being produced in chunks.
+    argument: Option<Argument>,
+    limits: Limits,
+    #[cfg(feature = "unicode")]
+    graphemes: bool,
+    /// The number of tokens of each kind produced so far.
+    counts: TokenCounts,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> Tokens<R> {
+    /// Constructs a new `Tokens` from the given `BufRead`.
+    pub fn new(input: R) -> Tokens<R> {
+        Tokens {
+            input: BufReadIter::new(input),
+            location: Position::default(),
+            smart_punctuation: false,
+            coalesce_text: false,
+            special_chars: CharSet::default(),
+            previous: None,
+            name: None,
+            buffer: String::new(),
+            error: None,
+            verbatim_chunk_size: None,
+            argument: None,
+            limits: Limits::default(),
+            #[cfg(feature = "unicode")]
+            graphemes: false,
+            counts: TokenCounts::default(),
+        }
+    }
+
+    /// Sets whether typographic punctuation is substituted for unescaped ASCII punctuation:
+    /// curly quotes for `'` and `"`, an en dash for `--`, an em dash for `---`, and an ellipsis
+    /// for `...`. A quote opens if it is at the start of input or follows whitespace, an opening
+    /// bracket, or another opening quote, and closes otherwise, so that an apostrophe within a
+    /// word becomes a closing single quote. The escapes `\'` and `\"` produce straight quotes,
+    /// and dashes may be kept apart with an empty group, as in `-{}-`. Defaults to `false`.
+    pub fn smart_punctuation(mut self, smart: bool) -> Tokens<R> {
+        self.smart_punctuation = smart;
+        self
+    }
+
+    /// Sets whether runs of ordinary characters are read as a single `Token::Text`, rather than as
+    /// a `Token::Char` for each character, which makes reading prose much cheaper. A run ends at
+    /// the end of a line, or at a special character: `\`, `{`, `}`, those given to
+    /// `special_chars`, and, if typographic punctuation is substituted, the characters which may
+    /// be replaced. Special characters and a single ordinary character between two of them are
+    /// still read as `Token::Char`s. Defaults to `false`.
+    pub fn coalesce_text(mut self, coalesce: bool) -> Tokens<R> {
+        self.coalesce_text = coalesce;
+        self
+    }
+
+    /// Sets additional characters which end a run of text, as described at `coalesce_text`, such
+    /// as characters which the consumer of the tokens needs to see on their own. Defaults to
+    /// none.
+    pub fn special_chars(mut self, chars: &str) -> Tokens<R> {
+        self.special_chars = CharSet::new(chars);
+        self
+    }
+
+    /// Sets the size, in bytes, at which the contents of a `\verbatim` or `\raw` command are split
+    /// into chunks, so that a huge block need not be held in memory all at once. Contents which
+    /// reach the size are produced as a `Token::VerbatimChunk` or `Token::RawChunk` of that many
+    /// bytes (or a few more, to end on a character boundary), followed by further chunks, ending
+    /// with an ordinary `Token::Verbatim` or `Token::Raw` holding the rest. Each chunk is located
+    /// at the command. Since input is read a line at a time, a single line of the contents is
+    /// still held in memory as a whole. Defaults to `None`, which does not split contents.
+    pub fn verbatim_chunk_size(mut self, size: Option<usize>) -> Tokens<R> {
+        self.verbatim_chunk_size = size;
+        self
+    }
+
+    /// Sets whether each extended grapheme cluster of the input, such as a letter followed by
+    /// combining marks, a flag, or an emoji joined with zero-width joiners, is kept within a
+    /// single token, so that a consumer which styles or breaks text between tokens never
+    /// separates what is read as one character. A cluster of more than one character which would
+    /// otherwise be split is read as a `Token::Text`, and a command name takes any combining marks
+    /// which follow it. An escape still produces the character it names, and a carriage return
+    /// and line feed are still read apart. Defaults to `false`. Requires the `unicode` feature.
+    #[cfg(feature = "unicode")]
+    pub fn graphemes(mut self, graphemes: bool) -> Tokens<R> {
+        self.graphemes = graphemes;
+        self
+    }
+
+    /// Sets the limits on the lengths of command names, verbatim contents, and lines, and on the
+    /// number of tokens, beyond which reading fails with `ErrorKind::LimitExceeded`. Each length
+    /// is checked as the value is read, so that no more than the limit is held in memory.
+    /// Defaults to `Limits::default()`.
+    pub fn limits(mut self, limits: Limits) -> Tokens<R> {
+        self.input.set_max_line_length(limits.get_max_line_length());
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the lints checked on each line of input as it is read. Warnings are collected, to be
+    /// returned by `take_warnings`, and a denied lint is returned as an error by `next_res`.
+    /// Defaults to allowing every lint.
+    pub fn lints(mut self, lints: Lints) -> Tokens<R> {
+        self.input.set_lints(lints);
+        self
+    }
+
+    /// Returns the warnings produced by the lints since they were last taken, leaving none.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.input.take_warnings()
+    }
+
+    /// Sets the name of the input, such as the name of the file it was read from, which is
+    /// attached to any errors, as with `Error::with_file`.
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Tokens<R> {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Returns the name of the input, if it has one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Attaches the name of the input to an error, unless it has no name, or the error already
+    /// has one, such as an error from another file.
+    pub(crate) fn name_error(&self, error: Error) -> Error {
+        match self.name {
+            Some(ref name) if error.file().is_none() => error.with_file(name.clone()),
+            _ => error,
+        }
+    }
+
+    /// Returns the line number at which the most recently read token started.
+    pub fn line(&self) -> usize {
+        self.location.line
+    }
+
+    /// Returns the (one-based) column at which the most recently read token started.
+    pub fn column(&self) -> usize {
+        self.location.column
+    }
+
+    /// Returns the position at which the most recently read token started.
+    pub fn location(&self) -> Position {
+        self.location
+    }
+
+    /// Returns the byte offset at which the most recently read token started.
+    pub fn offset(&self) -> usize {
+        self.location.offset
+    }
+
+    /// Returns the byte offset in the input of the end of the most recently read token.
+    pub fn position(&self) -> usize {
+        self.input.position()
+    }
+
+    /// Returns the lines and bytes read and the tokens produced so far. The other counters of the
+    /// `Stats` are those of the parser, and are zero.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            lines: self.input.lines_read(),
+            bytes: self.input.position(),
+            tokens: self.counts,
+            ..Stats::default()
+        }
+    }
+
+    /// Returns the next token, or `None` if the end of input has been reached.
+    pub fn next_res(&mut self) -> Result<Option<Token>, Error> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        let token = self.read_token().map_err(|e| self.name_error(e))?;
+        if let Some(ref token) = token {
+            self.counts.add(token);
+            let max = self.limits.get_max_tokens();
+            if self.counts.total() > max {
+                let error = ErrorKind::LimitExceeded {
+                    what: Limit::Tokens,
+                    limit: max,
+                    position: self.location,
+                };
+                return Err(self.name_error(error.into()));
+            }
+        }
+        Ok(token)
+    }
+
+    /// Reads up to `max` tokens, appending them to `buf`, and returns the number read, which is
+    /// less than `max` only at the end of input or if an error occurred. The tokens are those
+    /// which repeated calls to `next_res` would return.
+    ///
+    /// If an error occurs after some tokens have been read, those tokens are returned, and the
+    /// error is returned by the next call to `next_chunk` or `next_res`. Reading in chunks
+    /// amortizes the cost of passing tokens across a boundary such as a channel.
+    pub fn next_chunk(&mut self, buf: &mut Vec<Token>, max: usize) -> Result<usize, Error> {
+        let mut read = 0;
+        while read < max {
+            match self.next_res() {
+                Ok(Some(token)) => buf.push(token),
+                Ok(None) => break,
+                Err(error) if read == 0 => return Err(error),
+                Err(error) => {
+                    self.error = Some(error);
+                    break;
+                }
+            }
+            read += 1;
+        }
+        Ok(read)
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token>, Error> {
+        if let Some(argument) = self.argument.take() {
+            return self.argument(argument).map(Some);
+        }
+        let c = match self.input.next()? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        self.location = Position {
+            line: self.input.line(),
+            column: self.input.column() + 1,
+            offset: self.input.position() - c.len_utf8(),
+            utf16_column: self.input.utf16_position() - c.len_utf16() + 1,
+        };
+        let token = match c {
+            '\\' => self.command()?,
+            '{' => Token::BeginGroup,
+            '}' => Token::EndGroup,
+            c if self.coalesce_text
+                && !self.is_special(c)
+                && self.input.peek().is_some_and(|&next| !self.is_special(next)) =>
+            {
+                self.text(c)
+            }
+            c if self.smart_punctuation => Token::Char(self.smart_char(c)?),
+            c => Token::Char(c),
+        };
+        // An escape produces the character it names, alone.
+        #[cfg(feature = "unicode")]
+        let token = if c == '\\' {
+            token
+        } else {
+            self.extend_cluster(token)?
+        };
+        self.previous = Some(match token {
+            Token::Char(produced) => produced,
+            Token::Text(ref text) => text.chars().next_back().unwrap_or(c),
+            _ => c,
+        });
+        Ok(Some(token))
+    }
+
+    /// Returns whether a character ends a run of text.
+    fn is_special(&self, c: char) -> bool {
+        is_special(c, &self.special_chars, self.smart_punctuation)
+    }
+
+    /// Reads a run of ordinary characters in the current line, starting with the
+    /// already-consumed character `first`.
+    fn text(&mut self, first: char) -> Token {
+        self.buffer.clear();
+        self.buffer.push(first);
+        let special_chars = &self.special_chars;
+        let smart = self.smart_punctuation;
+        self.input
+            .next_while(&mut self.buffer, |c| !is_special(c, special_chars, smart));
+        Token::Text(self.buffer.as_str().into())
+    }
+
+    /// Returns the typographic replacement for a character read from the input, consuming the
+    /// rest of a dash or ellipsis.
+    fn smart_char(&mut self, c: char) -> Result<char, Error> {
+        let opening = opens_quote(self.previous);
+        let smart = match c {
+            '\'' if opening => '\u{2018}',
+            '\'' => '\u{2019}',
+            '"' if opening => '\u{201c}',
+            '"' => '\u{201d}',
+            '-' if self.input.peek() == Some(&'-') => {
+                self.input.next()?;
+                if self.input.peek() == Some(&'-') {
+                    self.input.next()?;
+                    '\u{2014}'
+                } else {
+                    '\u{2013}'
+                }
+            }
+            '.' if self.input.peek() == Some(&'.') && self.input.peek_second() == Some(&'.') => {
+                self.input.next()?;
+                self.input.next()?;
+                '\u{2026}'
+            }
+            c => c,
+        };
+        Ok(smart)
+    }
+
+    /// Reads the remainder of a command or escaped character, after the initial backslash.
+    fn command(&mut self) -> Result<Token, Error> {
+        let position = self.location();
+        let c = self
+            .input
+            .expect_next("a character to escape or a command name", position)?;
+        if c == '-' {
+            return Ok(Token::Char(SOFT_HYPHEN));
+        }
+        if !c.is_alphabetic() {
+            return Ok(Token::Char(c));
+        }
+        let name = self.ident(c)?;
+        let verbatim = match &*name {
+            "verbatim" => Some(self.language(position)?),
+            "raw" => None,
+            _ => return Ok(Token::Command(name)),
+        };
+        let delim = match self.input.next()? {
+            Some(delim) => delim,
+            None => return Err(self.unclosed(position, None)),
+        };
+        self.argument(Argument {
+            position,
+            delim,
+            depth: 0,
+            verbatim,
+        })
+    }
+
+    /// Reads a command name, starting with the already-consumed character `first`.
+    fn ident(&mut self, first: char) -> Result<Name, Error> {
+        self.buffer.clear();
+        self.buffer.push(first);
+        self.letters();
+        #[cfg(feature = "unicode")]
+        loop {
+            let mut name = mem::take(&mut self.buffer);
+            let len = name.len();
+            let extended = self.read_cluster(&mut name);
+            self.buffer = name;
+            extended?;
+            if self.buffer.len() == len {
+                break;
+            }
+            self.letters();
+        }
+        let max = self.limits.get_max_name_length();
+        if self.buffer.len() > max || self.input.peek().is_some_and(|c| c.is_alphabetic()) {
+            return Err(ErrorKind::LimitExceeded {
+                what: Limit::Name,
+                limit: max,
+                position: self.location,
+            }
+            .into());
+        }
+        Ok(Name::new(&self.buffer))
+    }
+
+    /// Reads the letters of a command name onto the end of the buffer, stopping before any which
+    /// would make it longer than the limit.
+    fn letters(&mut self) {
+        let max = self.limits.get_max_name_length();
+        let mut len = self.buffer.len();
+        self.input.next_while(&mut self.buffer, |c| {
+            len += c.len_utf8();
+            c.is_alphabetic() && len <= max
+        });
+    }
+
+    /// Extends a token of text, which may end within a grapheme cluster, with the rest of the
+    /// cluster, if grapheme clusters are kept together.
+    #[cfg(feature = "unicode")]
+    fn extend_cluster(&mut self, token: Token) -> Result<Token, Error> {
+        let mut text = match token {
+            _ if !self.graphemes => return Ok(token),
+            Token::Char(c) if self.input.peek().is_some_and(|&next| continues(c, next)) => {
+                String::from(c)
+            }
+            Token::Text(ref text) => String::from(&**text),
+            token => return Ok(token),
+        };
+        let len = text.len();
+        self.read_cluster(&mut text)?;
+        Ok(match token {
+            Token::Text(_) if text.len() == len => token,
+            _ => Token::Text(text.into()),
+        })
+    }
+
+    /// Reads the characters which continue the last grapheme cluster of `text` onto its end.
+    #[cfg(feature = "unicode")]
+    fn read_cluster(&mut self, text: &mut String) -> Result<(), Error> {
+        if !self.graphemes {
+            return Ok(());
+        }
+        let start = text.graphemes(true).next_back().map_or(0, |last| text.len() - last.len());
+        while let Some(&next) = self.input.peek() {
+            if !cluster_continues(&text[start..], next) {
+                break;
+            }
+            self.input.next()?;
+            text.push(next);
+        }
+        Ok(())
+    }
+
+    /// Reads the optional language of a `\verbatim` command which started at the given line and
+    /// column, given in brackets before its argument, as in `\verbatim[rust]{...}`.
+    fn language(&mut self, position: Position) -> Result<Option<String>, Error> {
+        if self.input.peek() != Some(&'[') {
+            return Ok(None);
+        }
+        self.input.next()?;
+        let mut language = String::new();
+        loop {
+            match self.input.next()? {
+                Some(']') => break,
+                Some('\n') => {
+                    return Err(ErrorKind::UnclosedVerbatim {
+                        start: position,
+                        delimiter: Some(']'),
+                        end: Position {
+                            line: self.input.line(),
+                            column: self.input.column() + 1,
+                            offset: self.input.position() - 1,
+                            utf16_column: self.input.utf16_position(),
+                        },
+                    }
+                    .into());
+                }
+                None => return Err(self.unclosed(position, Some(']'))),
+                Some(c) => language.push(c),
+            }
+        }
+        let language = language.trim();
+        Ok(if language.is_empty() {
+            None
+        } else {
+            Some(language.to_owned())
+        })
+    }
+
+    /// Reads the uninterpreted argument of a verbatim-like command, or the next chunk of it, and
+    /// returns it as a token.
+    ///
+    /// If the argument starts with `{`, it extends to the matching `}`, and may contain balanced
+    /// pairs of braces. Otherwise, the first character is used as the delimiter, and the argument
+    /// extends to the next occurrence of that character; a doubled delimiter stands for a literal
+    /// occurrence of it.
+    fn argument(&mut self, mut argument: Argument) -> Result<Token, Error> {
+        let closing = if argument.delim == '{' { '}' } else { argument.delim };
+        let limit = self.verbatim_chunk_size.unwrap_or(usize::MAX);
+        let max = match self.verbatim_chunk_size {
+            Some(_) => usize::MAX,
+            None => self.limits.get_max_verbatim_length(),
+        };
+        let mut content = String::new();
+        let closed = loop {
+            if content.len() >= limit {
+                break false;
+            }
+            let c = self
+                .input
+                .next()?
+                .ok_or_else(|| self.unclosed(argument.position, Some(closing)))?;
+            if argument.delim == '{' {
+                match c {
+                    '}' if argument.depth == 0 => break true,
+                    '{' => argument.depth += 1,
+                    '}' => argument.depth -= 1,
+                    _ => {}
+                }
+            } else if c == argument.delim {
+                if self.input.peek() != Some(&c) {
+                    break true;
+                }
+                self.input.next()?;
+            }
+            if content.len() + c.len_utf8() > max {
+                return Err(ErrorKind::LimitExceeded {
+                    what: Limit::Verbatim,
+                    limit: max,
+                    position: argument.position,
+                }
+                .into());
+            }
+            content.push(c);
+        };
+        self.location = argument.position;
+        let token = match (argument.verbatim.clone(), closed) {
+            (Some(language), true) => Token::Verbatim(Box::new(Verbatim { content, language })),
+            (Some(language), false) => {
+                Token::VerbatimChunk(Box::new(Verbatim { content, language }))
+            }
+            (None, true) => Token::Raw(content.into()),
+            (None, false) => Token::RawChunk(content.into()),
+        };
+        if !closed {
+            self.argument = Some(argument);
+        }
+        Ok(token)
+    }
+
+    /// Returns an `ErrorKind::UnclosedVerbatim` error for a command which started at `start`, and
+    /// was not closed by `delimiter` before the end of input.
+    fn unclosed(&self, start: Position, delimiter: Option<char>) -> Error {
+        ErrorKind::UnclosedVerbatim {
+            start,
+            delimiter,
+            end: self.input.end_position(),
+        }
+        .into()
+    }
+}
+
+/// The argument of a verbatim-like command which is being read.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+struct Argument {
+    /// The position of the command.
+    position: Position,
+    /// The character which started the argument: `{`, or the delimiter.
+    delim: char,
+    /// The depth of nested braces within a brace-delimited argument.
+    depth: usize,
+    /// The language of a `\verbatim` command, or `None` for a `\raw` command.
+    verbatim: Option<Option<String>>,
+}
+
+/// Returns whether `next` continues a grapheme cluster which ends with `c`.
+#[cfg(feature = "unicode")]
+fn continues(c: char, next: char) -> bool {
+    let mut buf = [0; 4];
+    cluster_continues(c.encode_utf8(&mut buf), next)
+}
+
+/// Returns whether `next` continues the grapheme cluster `cluster`, rather than starting another.
+/// A line feed never does, so that lines are still ended by a `Token::Char('\n')`.
+#[cfg(feature = "unicode")]
+fn cluster_continues(cluster: &str, next: char) -> bool {
+    if next == '\n' {
+        return false;
+    }
+    let mut joined = String::with_capacity(cluster.len() + next.len_utf8());
+    joined.push_str(cluster);
+    joined.push(next);
+    joined.graphemes(true).nth(1).is_none()
+}
+
+/// Returns whether a quote following the given character, or at the start of input, opens rather
+/// than closes.
+fn opens_quote(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(p) => p.is_whitespace() || "([{<\u{2018}\u{201c}\u{2013}\u{2014}".contains(p),
+    }
+}
+
+/// Returns whether a character ends a run of text, given the additional special characters and
+/// whether typographic punctuation is substituted.
+fn is_special(c: char, special_chars: &CharSet, smart_punctuation: bool) -> bool {
+    ALWAYS_SPECIAL.contains(c)
+        || special_chars.contains(c)
+        || (smart_punctuation && SMART_SPECIAL.contains(c))
+}
+
+/// A set of characters, whose ASCII members are held as a bit mask so that testing for them is
+/// cheap.
+#[derive(Clone, Debug, Default)]
+struct CharSet {
+    ascii: u128,
+    other: Vec<char>,
+}
+
+impl CharSet {
+    /// Constructs a new `CharSet` holding the given characters.
+    fn new(chars: &str) -> CharSet {
+        CharSet {
+            ascii: CharSet::ascii(chars).ascii,
+            other: chars.chars().filter(|c| !c.is_ascii()).collect(),
+        }
+    }
+
+    /// Constructs a new `CharSet` holding the ASCII characters among the given characters.
+    const fn ascii(chars: &str) -> CharSet {
+        let bytes = chars.as_bytes();
+        let mut ascii = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] < 128 {
+                ascii |= 1 << bytes[i];
+            }
+            i += 1;
+        }
+        CharSet {
+            ascii,
+            other: Vec::new(),
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        if c.is_ascii() {
+            self.ascii & (1 << c as u32) != 0
+        } else {
+            self.other.contains(&c)
+        }
+    }
+}
+
+#[cfg(feature = "encoding")]
+impl<R: BufRead> Tokens<Decoder<R>> {
+    /// Constructs a new `Tokens` from a `BufRead` in the given encoding, which is transcoded to
+    /// UTF-8 as it is read by a `Decoder`. Input which is not valid in the encoding fails with
+    /// `ErrorKind::Undecodable`; for a lossy decoder, give one to `Tokens::new` instead. Requires
+    /// the `encoding` feature.
+    pub fn with_encoding(input: R, encoding: Encoding) -> Tokens<Decoder<R>> {
+        Tokens::new(Decoder::new(input, encoding))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> Iterator for Tokens<R> {
+    type Item = Result<Token, Error>;
+
+    fn next(&mut self) -> Option<Result<Token, Error>> {
+        self.next_res().transpose()
+    }
+}
@@ -0,0 +1,144 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+use core::str;
+
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, Serializer};
+
+/// The length, in bytes, of the longest name which is stored without allocating.
+const INLINE: usize = 22;
+
+/// The name of a command, as held by `Token::Command`.
+///
+/// Names of up to 22 bytes, which include nearly all command names, are stored inline, so that
+/// reading a command does not allocate. A `Name` dereferences to a `str`, and hashes and compares
+/// like one, so that a map keyed by `Name` can be looked up by a `&str`.
+#[derive(Clone)]
+pub struct Name(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, bytes: [u8; INLINE] },
+    Heap(Box<str>),
+}
+
+impl Name {
+    /// Constructs a new `Name` holding the given string.
+    pub fn new(name: &str) -> Name {
+        if name.len() > INLINE {
+            return Name(Repr::Heap(name.into()));
+        }
+        let mut bytes = [0; INLINE];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Name(Repr::Inline {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    /// Returns the name as a string slice.
+    pub fn as_str(&self) -> &str {
+        match self.0 {
+            // The bytes were copied from a `str`, so they are always valid UTF-8.
+            Repr::Inline { len, ref bytes } => {
+                str::from_utf8(&bytes[..len as usize]).unwrap_or_default()
+            }
+            Repr::Heap(ref name) => name,
+        }
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> From<&'a str> for Name {
+    fn from(name: &'a str) -> Name {
+        Name::new(name)
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Name {
+        if name.len() > INLINE {
+            Name(Repr::Heap(name.into_boxed_str()))
+        } else {
+            Name::new(&name)
+        }
+    }
+}
+
+impl From<Name> for String {
+    fn from(name: Name) -> String {
+        match name.0 {
+            Repr::Heap(name) => name.into(),
+            Repr::Inline { .. } => name.as_str().into(),
+        }
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Name {}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Name {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+/// A name is serialized as a string.
+#[cfg(feature = "serde")]
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,341 @@
+//! Rewriting of tokens between the tokenizer and the parser, for syntax which can be written in
+//! terms of the tokens the tokenizer already produces.
+//!
+//! A `TokenRewriter` is given each token in turn and writes out the tokens which replace it, which
+//! may be none, or several. It may also hold tokens back until it has seen those which follow
+//! them, writing them out in a later call. Rewriters are applied to a stream of tokens by
+//! `Rewritten`, or to the input of a parser by `Parser::with_rewriter`, and several are applied
+//! in turn by chaining them with `TokenRewriter::then`.
+//!
+//! The contents of `\verbatim` and `\raw` commands are never rewritten. A rewriter is given them
+//! only whole, as a `Token::Verbatim` or `Token::Raw`; their leading chunks, if the contents are
+//! split into chunks, are passed on without being seen, after the rewriter is asked to write out
+//! any tokens it holds.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+
+use errors::Error;
+use super::Token;
+
+/// A transformation of a stream of tokens, in which each token is replaced by zero or more tokens.
+pub trait TokenRewriter {
+    /// Writes the tokens which replace `token` to `out`.
+    fn rewrite(&mut self, token: Token, out: &mut Vec<Token>) -> Result<(), Error>;
+
+    /// Writes any tokens which have been held back to `out`. This is called at the end of the
+    /// input, and before the leading chunks of verbatim or raw contents, which the rewriter does
+    /// not see. The default writes nothing.
+    fn finish(&mut self, out: &mut Vec<Token>) -> Result<(), Error> {
+        let _ = out;
+        Ok(())
+    }
+
+    /// Returns a rewriter which applies this rewriter, and then `next` to the tokens it writes.
+    fn then<B: TokenRewriter>(self, next: B) -> Chain<Self, B>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<T: TokenRewriter + ?Sized> TokenRewriter for Box<T> {
+    fn rewrite(&mut self, token: Token, out: &mut Vec<Token>) -> Result<(), Error> {
+        (**self).rewrite(token, out)
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token>) -> Result<(), Error> {
+        (**self).finish(out)
+    }
+}
+
+/// Two rewriters applied in turn, as returned by `TokenRewriter::then`.
+#[derive(Clone, Debug)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+    /// The tokens written by `first`, which are yet to be given to `second`.
+    buffer: Vec<Token>,
+}
+
+impl<A: TokenRewriter, B: TokenRewriter> Chain<A, B> {
+    /// Gives the tokens written by `first` to `second`.
+    fn forward(&mut self, out: &mut Vec<Token>) -> Result<(), Error> {
+        for token in self.buffer.drain(..) {
+            self.second.rewrite(token, out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: TokenRewriter, B: TokenRewriter> TokenRewriter for Chain<A, B> {
+    fn rewrite(&mut self, token: Token, out: &mut Vec<Token>) -> Result<(), Error> {
+        self.first.rewrite(token, &mut self.buffer)?;
+        self.forward(out)
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token>) -> Result<(), Error> {
+        self.first.finish(&mut self.buffer)?;
+        self.forward(out)?;
+        self.second.finish(out)
+    }
+}
+
+/// The state of a rewriter applied to a stream of tokens, which is shared by `Rewritten` and the
+/// parser.
+pub(crate) struct Rewriting<R> {
+    rewriter: R,
+    /// The tokens written by the rewriter which are yet to be returned.
+    queue: VecDeque<Token>,
+    buffer: Vec<Token>,
+    /// Whether the end of the input has been reached, and the rewriter finished.
+    finished: bool,
+}
+
+impl<R: TokenRewriter> Rewriting<R> {
+    pub(crate) fn new(rewriter: R) -> Rewriting<R> {
+        Rewriting {
+            rewriter,
+            queue: VecDeque::new(),
+            buffer: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the inner rewriter.
+    pub(crate) fn into_inner(self) -> R {
+        self.rewriter
+    }
+
+    /// Returns the next rewritten token, reading tokens with `read` until the rewriter writes
+    /// one, or `None` at the end of input.
+    pub(crate) fn next<F>(&mut self, mut read: F) -> Result<Option<Token>, Error>
+    where
+        F: FnMut() -> Result<Option<Token>, Error>,
+    {
+        loop {
+            if let Some(token) = self.queue.pop_front() {
+                return Ok(Some(token));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            match read()? {
+                Some(chunk @ Token::VerbatimChunk(_)) | Some(chunk @ Token::RawChunk(_)) => {
+                    self.rewriter.finish(&mut self.buffer)?;
+                    self.buffer.push(chunk);
+                }
+                Some(token) => self.rewriter.rewrite(token, &mut self.buffer)?,
+                None => {
+                    self.finished = true;
+                    self.rewriter.finish(&mut self.buffer)?;
+                }
+            }
+            self.queue.extend(self.buffer.drain(..));
+        }
+    }
+}
+
+// The rewriters held by the parser are boxed trait objects, which need not implement `Debug`.
+impl<R> fmt::Debug for Rewriting<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rewriting")
+            .field("queue", &self.queue)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+/// A stream of tokens, such as `Tokens`, or `StrTokens` with its tokens converted by
+/// `BorrowedToken::into_owned`, rewritten by a `TokenRewriter`.
+///
+/// An error read from the stream is returned as it is, and the stream is read on after it if
+/// iteration continues.
+#[derive(Debug)]
+pub struct Rewritten<I, R> {
+    tokens: I,
+    rewriting: Rewriting<R>,
+}
+
+impl<I, R> Rewritten<I, R>
+where
+    I: Iterator<Item = Result<Token, Error>>,
+    R: TokenRewriter,
+{
+    /// Constructs a new `Rewritten` applying `rewriter` to `tokens`.
+    pub fn new(tokens: I, rewriter: R) -> Rewritten<I, R> {
+        Rewritten {
+            tokens,
+            rewriting: Rewriting::new(rewriter),
+        }
+    }
+
+    /// Returns the stream of tokens and the rewriter. Any tokens which have been rewritten but
+    /// not yet returned are lost.
+    pub fn into_inner(self) -> (I, R) {
+        (self.tokens, self.rewriting.into_inner())
+    }
+}
+
+impl<I, R> Iterator for Rewritten<I, R>
+where
+    I: Iterator<Item = Result<Token, Error>>,
+    R: TokenRewriter,
+{
+    type Item = Result<Token, Error>;
+
+    fn next(&mut self) -> Option<Result<Token, Error>> {
+        let tokens = &mut self.tokens;
+        self.rewriting.next(|| tokens.next().transpose()).transpose()
+    }
+}
+
+/// The arrows replaced by `Arrows`, longest first, so that `<->` is not read as `<-` and `>`.
+const ARROWS: &[(&str, char)] = &[("<->", '↔'), ("->", '→'), ("<-", '←'), ("=>", '⇒')];
+
+/// A `TokenRewriter` which replaces arrows written with ASCII characters in text by the Unicode
+/// arrows they stand for: `->` by `→`, `<-` by `←`, `<->` by `↔`, and `=>` by `⇒`.
+///
+/// An arrow may be split across tokens, as when it is written with `=`, which the parser reads as
+/// a token of its own, so the characters which may start one are held back until the following
+/// token is seen. Text with no arrows is passed on as the same tokens. Since an escaped character
+/// is read as the same token as an unescaped one, an arrow which is to be kept as it is written is
+/// split by an empty group instead, as in `-{}>`. With typographic punctuation, `-->` is read as
+/// an en dash followed by `>`.
+#[derive(Clone, Debug, Default)]
+pub struct Arrows {
+    /// The characters at the end of the text seen so far which may start an arrow.
+    held: String,
+}
+
+impl Arrows {
+    /// Constructs a new `Arrows`.
+    pub fn new() -> Arrows {
+        Arrows::default()
+    }
+
+    /// Writes out the characters held back, which are followed by a token which does not continue
+    /// an arrow.
+    fn flush(&mut self, out: &mut Vec<Token>) {
+        let held = mem::take(&mut self.held);
+        match arrow_at(&held) {
+            Some((len, arrow)) if len == held.len() => out.push(Token::Char(arrow)),
+            _ => out.extend(held.chars().map(Token::Char)),
+        }
+    }
+
+    /// Writes out the text of `token`, with any arrows in it replaced, holding back any characters
+    /// at its end which may start an arrow.
+    fn text(&mut self, text: &str, token: Token, out: &mut Vec<Token>) {
+        let mut replaced = String::with_capacity(text.len());
+        let mut changed = false;
+        let mut rest = text;
+        while !rest.is_empty() {
+            if starts_arrow(rest) {
+                self.held.push_str(rest);
+                break;
+            }
+            if let Some((len, arrow)) = arrow_at(rest) {
+                replaced.push(arrow);
+                changed = true;
+                rest = &rest[len..];
+            } else {
+                let c = rest.chars().next().unwrap_or_default();
+                replaced.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        if !changed && self.held.is_empty() {
+            out.push(token);
+        } else {
+            push_text(replaced, out);
+        }
+    }
+}
+
+impl TokenRewriter for Arrows {
+    fn rewrite(&mut self, token: Token, out: &mut Vec<Token>) -> Result<(), Error> {
+        let text = match token {
+            Token::Char(c) => String::from(c),
+            Token::Text(ref text) => String::from(&**text),
+            _ => {
+                self.flush(out);
+                out.push(token);
+                return Ok(());
+            }
+        };
+        if self.held.is_empty() {
+            self.text(&text, token, out);
+            return Ok(());
+        }
+        let joined = mem::take(&mut self.held) + &text;
+        if starts_arrow(&joined) {
+            self.held = joined;
+            return Ok(());
+        }
+        let held = joined.len() - text.len();
+        match arrow_at(&joined) {
+            Some((len, arrow)) if len > held => {
+                out.push(Token::Char(arrow));
+                let rest = &joined[len..];
+                if !rest.is_empty() {
+                    let token = text_token(rest.into());
+                    self.text(rest, token, out);
+                }
+            }
+            _ => {
+                self.held = String::from(&joined[..held]);
+                self.flush(out);
+                self.text(&text, token, out);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token>) -> Result<(), Error> {
+        self.flush(out);
+        Ok(())
+    }
+}
+
+/// Returns the length of the arrow at the start of `text`, and the character which replaces it.
+fn arrow_at(text: &str) -> Option<(usize, char)> {
+    ARROWS
+        .iter()
+        .find(|&&(ascii, _)| text.starts_with(ascii))
+        .map(|&(ascii, arrow)| (ascii.len(), arrow))
+}
+
+/// Returns whether `text` is the start of an arrow, but not the whole of the longest arrow it
+/// starts, so that the text which follows it must be seen before it is replaced.
+fn starts_arrow(text: &str) -> bool {
+    ARROWS
+        .iter()
+        .any(|&(ascii, _)| ascii.len() > text.len() && ascii.starts_with(text))
+}
+
+/// Returns the token holding `text`: a `Token::Char` for a single character, and otherwise a
+/// `Token::Text`.
+fn text_token(text: String) -> Token {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Token::Char(c),
+        _ => Token::Text(text.into()),
+    }
+}
+
+/// Writes out the token holding `text`, if it is not empty.
+fn push_text(text: String, out: &mut Vec<Token>) {
+    if !text.is_empty() {
+        out.push(text_token(text));
+    }
+}
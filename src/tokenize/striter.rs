@@ -0,0 +1,174 @@
+use alloc::vec::Vec;
+use core::mem;
+
+use errors::{Error, ErrorKind, Position, Warning};
+use lints::Lints;
+
+/// A struct providing the same methods as `BufReadIter` over the chars of a `&str`, which also
+/// allows slices of the input to be borrowed.
+///
+/// As with `BufReadIter`, the input is read a line at a time: `peek` and `peek_second` do not look
+/// past the end of the line of the most recently read character, and the lints are checked on
+/// each line as its first character is read.
+#[derive(Debug)]
+pub struct StrIter<'a> {
+    input: &'a str,
+    /// The byte offset of the end of the current line, including its line ending.
+    line_end: usize,
+    /// The (one-based) column of the most recently read character, or zero at the start of a line.
+    column: usize,
+    line: usize,
+    /// The number of bytes read, up to the end of the current character.
+    position: usize,
+    /// The number of UTF-16 code units of the current line read, up to the end of the current
+    /// character.
+    utf16_position: usize,
+    lints: Lints,
+    /// The warnings produced by the lints which have not yet been taken.
+    warnings: Vec<Warning>,
+    /// Whether the end of input has been reached.
+    ended: bool,
+}
+
+impl<'a> StrIter<'a> {
+    /// Constructs a new `StrIter` over the given string.
+    pub fn new(input: &'a str) -> StrIter<'a> {
+        StrIter {
+            input,
+            line_end: 0,
+            column: 0,
+            line: 0,
+            position: 0,
+            utf16_position: 0,
+            lints: Lints::default(),
+            warnings: Vec::new(),
+            ended: false,
+        }
+    }
+
+    /// Sets the lints checked on each line as it is read.
+    pub fn set_lints(&mut self, lints: Lints) {
+        self.lints = lints;
+    }
+
+    /// Returns the warnings produced by the lints since they were last taken, leaving none.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// Returns the (one-based) column of the most recently read character.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns the current line number.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the number of bytes read, up to the end of the current character.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the number of UTF-16 code units of the current line read, up to the end of the
+    /// current character.
+    pub fn utf16_position(&self) -> usize {
+        self.utf16_position
+    }
+
+    /// Returns the position of the end of input, once `next` has returned `None`: just after the
+    /// last character, or at the start of the following line if that character is a newline.
+    pub fn end_position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column + 1,
+            offset: self.position,
+            utf16_column: self.utf16_position + 1,
+        }
+    }
+
+    /// Returns the input between the given byte offsets.
+    pub fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.input[start..end]
+    }
+
+    /// Moves on to the line starting at the current position, and checks the lints on it.
+    fn next_line(&mut self) -> Result<(), Error> {
+        let rest = &self.input[self.position..];
+        let line = rest.find('\n').map_or(rest, |end| &rest[..=end]);
+        self.line_end = self.position + line.len();
+        self.column = 0;
+        self.utf16_position = 0;
+        self.line += 1;
+        let start = Position {
+            line: self.line,
+            column: 1,
+            offset: self.position,
+            utf16_column: 1,
+        };
+        self.lints.check_line(line, start, &mut self.warnings)
+    }
+
+    /// Advances the iterator, returning the next character if present, or any errors encountered.
+    pub fn next(&mut self) -> Result<Option<char>, Error> {
+        if self.ended {
+            return Ok(None);
+        }
+        if self.position == self.line_end {
+            if self.position == self.input.len() {
+                // The position stays just after the last character, unless it ends a line.
+                if self.input.is_empty() || self.input.ends_with('\n') {
+                    self.column = 0;
+                    self.utf16_position = 0;
+                    self.line += 1;
+                }
+                self.ended = true;
+                return Ok(None);
+            }
+            self.next_line()?;
+        }
+        let c = self.input[self.position..].chars().next();
+        if let Some(c) = c {
+            self.position += c.len_utf8();
+            self.utf16_position += c.len_utf16();
+            self.column += 1;
+        }
+        Ok(c)
+    }
+
+    /// Advances the iterator, returning the next character. If end of input is reached, returns an
+    /// `ErrorKind::EndOfInput` error for a token which started at `start`, and which required
+    /// `expected` to complete it.
+    pub fn expect_next(&mut self, expected: &'static str, start: Position) -> Result<char, Error> {
+        self.next()?.ok_or_else(|| {
+            ErrorKind::EndOfInput {
+                expected,
+                position: start,
+            }
+            .into()
+        })
+    }
+
+    /// Advances the iterator past the characters remaining in the line for which `predicate`
+    /// holds, stopping at the first for which it does not.
+    pub fn skip_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        let rest = &self.input[self.position..self.line_end];
+        for c in rest.chars().take_while(|&c| predicate(c)) {
+            self.position += c.len_utf8();
+            self.utf16_position += c.len_utf16();
+            self.column += 1;
+        }
+    }
+
+    /// Returns the next character in the line without advancing the stream. A `None` value just
+    /// indicates that the end of the line has been reached, not necessarily the end of the text.
+    pub fn peek(&self) -> Option<char> {
+        self.input[self.position..self.line_end].chars().next()
+    }
+
+    /// Returns the character after the next one in the line, as with `peek`.
+    pub fn peek_second(&self) -> Option<char> {
+        self.input[self.position..self.line_end].chars().nth(1)
+    }
+}
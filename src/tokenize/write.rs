@@ -0,0 +1,211 @@
+use std::io::Write;
+
+use errors::{Error, ErrorKind, ResultExt};
+use super::{Token, SOFT_HYPHEN};
+
+/// The delimiters tried, in order, for a verbatim-like argument which cannot be delimited by
+/// braces.
+const DELIMITERS: &str = "|!/+=#@~^";
+
+/// The delimiter of a verbatim-like argument written in chunks, whose contents are not known in
+/// advance, so that occurrences of the delimiter within it are doubled.
+const CHUNK_DELIMITER: char = '|';
+
+/// Writes a sequence of tokens as source which `Tokens` (without typographic punctuation) reads
+/// back as the same sequence. A `Token::Text` is written as its characters, escaped in the same
+/// way as a `Token::Char`, so it is read back as a single token only by `Tokens` with
+/// `Tokens::coalesce_text` set, the same special characters, and no ordinary characters on either
+/// side of it.
+///
+/// Backslashes and braces are escaped, and the argument of `\verbatim` or `\raw` is delimited by
+/// braces if its braces are balanced, and otherwise by a character which does not occur in it.
+/// An argument split into `Token::VerbatimChunk`s or `Token::RawChunk`s is written as each chunk
+/// arrives, delimited by `|` with any `|` in it doubled, and a `|` immediately after it is
+/// escaped.
+///
+/// Some sequences have no source form, and produce an `UnwritableToken` error: a command whose
+/// name is empty, contains a non-alphabetic character, or is `verbatim` or `raw`; a command
+/// followed by an alphabetic character, which would be read as part of its name; and a verbatim
+/// language which is empty, has surrounding whitespace, or contains `]` or a newline; and chunks
+/// which are not followed by the rest of their argument.
+pub fn write_tokens<I, W>(tokens: I, mut out: W) -> Result<(), Error>
+where
+    I: IntoIterator<Item = Token>,
+    W: Write,
+{
+    let mut tokens = tokens.into_iter().peekable();
+    let mut after_command = false;
+    // Whether the argument being written in chunks, if any, is that of a `\raw` command.
+    let mut chunked: Option<bool> = None;
+    // The delimiter of an argument written in chunks which has just been closed.
+    let mut closed = None;
+    while let Some(token) = tokens.next() {
+        if let Some(raw) = chunked {
+            let continues = match token {
+                Token::VerbatimChunk(_) | Token::Verbatim(_) => !raw,
+                Token::RawChunk(_) | Token::Raw(_) => raw,
+                _ => false,
+            };
+            if !continues {
+                return Err(ErrorKind::UnwritableToken.into());
+            }
+        }
+        // A character immediately after a delimited argument must not be its delimiter.
+        let following = match tokens.peek() {
+            Some(&Token::Char(c)) => Some(c),
+            Some(Token::Text(text)) => text.chars().next(),
+            _ => None,
+        };
+        match token {
+            Token::Char(c) if after_command && c.is_alphabetic() => {
+                return Err(ErrorKind::UnwritableToken.into());
+            }
+            Token::Char(c) if closed == Some(c) => {
+                write!(out, "\\{}", c).context(ErrorKind::Write)?
+            }
+            Token::Char(c) => write_char(&mut out, c)?,
+            Token::Text(ref text) if after_command && text.starts_with(char::is_alphabetic) => {
+                return Err(ErrorKind::UnwritableToken.into());
+            }
+            Token::Text(ref text) => {
+                if closed.is_some_and(|d| text.starts_with(d)) {
+                    write_str(&mut out, "\\")?;
+                }
+                for c in text.chars() {
+                    write_char(&mut out, c)?;
+                }
+            }
+            Token::Command(ref name) => {
+                if name.is_empty()
+                    || !name.chars().all(char::is_alphabetic)
+                    || name == "verbatim"
+                    || name == "raw"
+                {
+                    return Err(ErrorKind::UnwritableToken.into());
+                }
+                write!(out, "\\{}", name).context(ErrorKind::Write)?
+            }
+            Token::BeginGroup => write_str(&mut out, "{")?,
+            Token::EndGroup => write_str(&mut out, "}")?,
+            Token::Verbatim(ref verbatim) if chunked.is_some() => {
+                write_chunk(&mut out, &verbatim.content)?;
+                write!(out, "{}", CHUNK_DELIMITER).context(ErrorKind::Write)?;
+            }
+            Token::Raw(ref content) if chunked.is_some() => {
+                write_chunk(&mut out, content)?;
+                write!(out, "{}", CHUNK_DELIMITER).context(ErrorKind::Write)?;
+            }
+            Token::Verbatim(ref verbatim) => {
+                write_verbatim(&mut out, &verbatim.language)?;
+                write_argument(&mut out, &verbatim.content, following)?;
+            }
+            Token::Raw(ref content) => {
+                write_str(&mut out, "\\raw")?;
+                write_argument(&mut out, content, following)?;
+            }
+            Token::VerbatimChunk(ref verbatim) => {
+                if chunked.is_none() {
+                    write_verbatim(&mut out, &verbatim.language)?;
+                    write!(out, "{}", CHUNK_DELIMITER).context(ErrorKind::Write)?;
+                }
+                write_chunk(&mut out, &verbatim.content)?;
+            }
+            Token::RawChunk(ref content) => {
+                if chunked.is_none() {
+                    write!(out, "\\raw{}", CHUNK_DELIMITER).context(ErrorKind::Write)?;
+                }
+                write_chunk(&mut out, content)?;
+            }
+        }
+        after_command = matches!(token, Token::Command(_));
+        closed = match token {
+            Token::Verbatim(_) | Token::Raw(_) if chunked.is_some() => Some(CHUNK_DELIMITER),
+            _ => None,
+        };
+        chunked = match token {
+            Token::VerbatimChunk(_) => Some(false),
+            Token::RawChunk(_) => Some(true),
+            _ => None,
+        };
+    }
+    if chunked.is_some() {
+        return Err(ErrorKind::UnwritableToken.into());
+    }
+    Ok(())
+}
+
+/// Writes `\verbatim`, followed by its language, if any.
+fn write_verbatim<W: Write>(out: &mut W, language: &Option<String>) -> Result<(), Error> {
+    write_str(out, "\\verbatim")?;
+    if let Some(ref language) = *language {
+        if language.is_empty() || language.trim() != language || language.contains([']', '\n']) {
+            return Err(ErrorKind::UnwritableToken.into());
+        }
+        write!(out, "[{}]", language).context(ErrorKind::Write)?;
+    }
+    Ok(())
+}
+
+/// Writes a chunk of a verbatim-like argument delimited by `CHUNK_DELIMITER`, doubling any
+/// occurrences of the delimiter.
+fn write_chunk<W: Write>(out: &mut W, content: &str) -> Result<(), Error> {
+    let mut rest = content;
+    while let Some(index) = rest.find(CHUNK_DELIMITER) {
+        write!(out, "{}{}", &rest[..=index], CHUNK_DELIMITER).context(ErrorKind::Write)?;
+        rest = &rest[index + 1..];
+    }
+    write_str(out, rest)
+}
+
+/// Writes an ordinary character, escaping it if necessary.
+fn write_char<W: Write>(out: &mut W, c: char) -> Result<(), Error> {
+    match c {
+        SOFT_HYPHEN => write_str(out, "\\-"),
+        '\\' | '{' | '}' => write!(out, "\\{}", c).context(ErrorKind::Write),
+        c => write!(out, "{}", c).context(ErrorKind::Write),
+    }
+}
+
+/// Writes the delimited argument of a verbatim-like command, which is followed by the character
+/// `following`, if any.
+fn write_argument<W: Write>(
+    out: &mut W,
+    content: &str,
+    following: Option<char>,
+) -> Result<(), Error> {
+    if balanced(content) {
+        write!(out, "{{{}}}", content).context(ErrorKind::Write)?;
+        return Ok(());
+    }
+    let mut delimiters = DELIMITERS.chars().filter(|&d| Some(d) != following);
+    match delimiters.clone().find(|&d| !content.contains(d)) {
+        Some(delim) => write!(out, "{}{}{}", delim, content, delim).context(ErrorKind::Write)?,
+        None => {
+            // Every delimiter occurs in the content, so occurrences of the first are doubled.
+            let delim = delimiters.next().unwrap_or('|');
+            let doubled: String = [delim, delim].iter().collect();
+            let escaped = content.replace(delim, &doubled);
+            write!(out, "{}{}{}", delim, escaped, delim).context(ErrorKind::Write)?
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether the braces in the content are balanced, so that it may be delimited by braces.
+fn balanced(content: &str) -> bool {
+    let mut depth = 0usize;
+    for c in content.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return false,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+fn write_str<W: Write>(out: &mut W, s: &str) -> Result<(), Error> {
+    out.write_all(s.as_bytes()).context(ErrorKind::Write)?;
+    Ok(())
+}
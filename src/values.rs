@@ -0,0 +1,533 @@
+//! Values given as the arguments of commands, which are parsed from their text.
+//!
+//! A `Length` is a number followed by a unit, such as `2em`, `10px`, `1.5in`, or `50%`, or a bare
+//! number, such as `200`, which is taken to be in pixels, as it is by the `width` of an HTML
+//! image. The number may be negative, and may have a fractional part, but no exponent, and there
+//! is no whitespace between it and its unit.
+//!
+//! The absolute units are related as they are in CSS, with a font size of 12pt:
+//!
+//! | Unit | Size              |
+//! |------|-------------------|
+//! | `in` | 96px, or 72pt     |
+//! | `cm` | 1/2.54 in         |
+//! | `mm` | 1/10 cm           |
+//! | `pt` | 1/72 in           |
+//! | `px` | 1/96 in           |
+//! | `em` | 12pt, or 16px     |
+//! | `ex` | 1/2 em            |
+//!
+//! A percentage is relative to the available width, which only the renderer knows.
+//!
+//! A `Color` is written as one of the named colors of CSS, such as `red` or `rebeccapurple`, in
+//! any case, as `#` followed by three or six hexadecimal digits, such as `#c00` or `#cc0000`, or as
+//! `rgb(r, g, b)`, with each component from 0 to 255. The eight colors of a terminal may also be
+//! written with the prefix `bright-`, such as `bright-red`, for their bright forms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::num::ParseIntError;
+use core::ops::{Mul, Neg};
+use core::str::FromStr;
+
+/// The number of pixels in an em, at a font size of 12pt.
+const PIXELS_PER_EM: f64 = 16.0;
+
+/// The unit of a `Length`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Unit {
+    /// Points, of which there are 72 to an inch.
+    Pt,
+    /// The size of the font.
+    Em,
+    /// Half the size of the font.
+    Ex,
+    /// Centimeters.
+    Cm,
+    /// Millimeters.
+    Mm,
+    /// Inches.
+    In,
+    /// Pixels, of which there are 96 to an inch.
+    Px,
+    /// A percentage of the available width, written `%`.
+    Percent,
+    /// No unit, for a bare number, which is taken to be in pixels.
+    Number,
+}
+
+/// The units written after a number.
+const UNITS: &[(&str, Unit)] = &[
+    ("pt", Unit::Pt),
+    ("em", Unit::Em),
+    ("ex", Unit::Ex),
+    ("cm", Unit::Cm),
+    ("mm", Unit::Mm),
+    ("in", Unit::In),
+    ("px", Unit::Px),
+    ("%", Unit::Percent),
+];
+
+impl Unit {
+    /// Returns the unit as it is written after a number, such as `em`, or an empty string for
+    /// `Unit::Number`.
+    pub fn as_str(self) -> &'static str {
+        UNITS
+            .iter()
+            .find(|&&(_, unit)| unit == self)
+            .map_or("", |&(name, _)| name)
+    }
+
+    /// Returns the number of pixels in one of the unit, or `None` for `Unit::Percent`.
+    fn pixels(self) -> Option<f64> {
+        Some(match self {
+            Unit::Pt => 96.0 / 72.0,
+            Unit::Em => PIXELS_PER_EM,
+            Unit::Ex => PIXELS_PER_EM / 2.0,
+            Unit::Cm => 96.0 / 2.54,
+            Unit::Mm => 96.0 / 25.4,
+            Unit::In => 96.0,
+            Unit::Px | Unit::Number => 1.0,
+            Unit::Percent => return None,
+        })
+    }
+}
+
+/// A length, such as `2em`, as written in the argument of a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Length {
+    /// The number, which may be negative.
+    pub value: f64,
+    /// The unit of the number.
+    pub unit: Unit,
+}
+
+/// The reason a string is not a valid `Length`.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum LengthError {
+    #[error("A length cannot be empty")]
+    Empty,
+    #[error("`{0}` does not start with a number, as in `10px`")]
+    MissingNumber(String),
+    #[error("`{0}` is not a valid number")]
+    InvalidNumber(String),
+    #[error("`{0}` has whitespace between its number and its unit")]
+    Whitespace(String),
+    #[error(
+        "Unknown unit `{0}`; expected one of `pt`, `em`, `ex`, `cm`, `mm`, `in`, `px`, or `%`"
+    )]
+    UnknownUnit(String),
+}
+
+impl Length {
+    /// Constructs a new `Length` of the given number of the unit.
+    pub fn new(value: f64, unit: Unit) -> Length {
+        Length { value, unit }
+    }
+
+    /// Returns the length in pixels, or `None` if it is a percentage.
+    pub fn to_pixels(self) -> Option<f64> {
+        self.unit.pixels().map(|pixels| self.value * pixels)
+    }
+
+    /// Returns the length as it is written in CSS, such as `2em` or `50%`. A bare number is
+    /// written in pixels, since CSS requires a unit.
+    pub fn to_css(self) -> String {
+        match self.unit {
+            Unit::Number => format!("{}px", self.value),
+            unit => format!("{}{}", self.value, unit.as_str()),
+        }
+    }
+
+    /// Returns the length as a number of character cells of a terminal, given the number of cells
+    /// in an em, and the number of cells available, which a percentage is relative to. The
+    /// length is rounded to the nearest cell, and is 0 if it is negative.
+    pub fn to_cells(self, cells_per_em: f64, available: usize) -> usize {
+        let cells = match self.to_pixels() {
+            Some(pixels) => pixels / PIXELS_PER_EM * cells_per_em,
+            None => available as f64 * self.value / 100.0,
+        };
+        // A negative or NaN number of cells converts to 0.
+        (cells + 0.5) as usize
+    }
+}
+
+impl FromStr for Length {
+    type Err = LengthError;
+
+    fn from_str(s: &str) -> Result<Length, LengthError> {
+        if s.is_empty() {
+            return Err(LengthError::Empty);
+        }
+        let digits = s
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+            .map_or(s.len(), |(i, _)| i);
+        let (number, unit) = s.split_at(digits);
+        if !number.bytes().any(|b| b.is_ascii_digit()) {
+            return Err(LengthError::MissingNumber(s.into()));
+        }
+        let value = number
+            .parse()
+            .map_err(|_| LengthError::InvalidNumber(number.into()))?;
+        let unit = match unit {
+            "" => Unit::Number,
+            _ if unit.starts_with(char::is_whitespace) => {
+                return Err(LengthError::Whitespace(s.into()))
+            }
+            _ => UNITS
+                .iter()
+                .find(|&&(name, _)| name == unit)
+                .map(|&(_, unit)| unit)
+                .ok_or_else(|| LengthError::UnknownUnit(unit.into()))?,
+        };
+        Ok(Length { value, unit })
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit.as_str())
+    }
+}
+
+/// Scales a length by a factor, keeping its unit.
+impl Mul<f64> for Length {
+    type Output = Length;
+
+    fn mul(self, factor: f64) -> Length {
+        Length::new(self.value * factor, self.unit)
+    }
+}
+
+impl Neg for Length {
+    type Output = Length;
+
+    fn neg(self) -> Length {
+        Length::new(-self.value, self.unit)
+    }
+}
+
+/// A color, such as the first argument of `\color`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Color {
+    /// The red component.
+    pub red: u8,
+    /// The green component.
+    pub green: u8,
+    /// The blue component.
+    pub blue: u8,
+}
+
+/// The named colors of CSS. Where two names give the same color, the first is the one written by
+/// `Color::to_css`, so `cyan` and `magenta` come before `aqua` and `fuchsia`.
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("aliceblue", Color::rgb(0xf0, 0xf8, 0xff)),
+    ("antiquewhite", Color::rgb(0xfa, 0xeb, 0xd7)),
+    ("cyan", Color::rgb(0x00, 0xff, 0xff)),
+    ("aqua", Color::rgb(0x00, 0xff, 0xff)),
+    ("aquamarine", Color::rgb(0x7f, 0xff, 0xd4)),
+    ("azure", Color::rgb(0xf0, 0xff, 0xff)),
+    ("beige", Color::rgb(0xf5, 0xf5, 0xdc)),
+    ("bisque", Color::rgb(0xff, 0xe4, 0xc4)),
+    ("black", Color::rgb(0x00, 0x00, 0x00)),
+    ("blanchedalmond", Color::rgb(0xff, 0xeb, 0xcd)),
+    ("blue", Color::rgb(0x00, 0x00, 0xff)),
+    ("blueviolet", Color::rgb(0x8a, 0x2b, 0xe2)),
+    ("brown", Color::rgb(0xa5, 0x2a, 0x2a)),
+    ("burlywood", Color::rgb(0xde, 0xb8, 0x87)),
+    ("cadetblue", Color::rgb(0x5f, 0x9e, 0xa0)),
+    ("chartreuse", Color::rgb(0x7f, 0xff, 0x00)),
+    ("chocolate", Color::rgb(0xd2, 0x69, 0x1e)),
+    ("coral", Color::rgb(0xff, 0x7f, 0x50)),
+    ("cornflowerblue", Color::rgb(0x64, 0x95, 0xed)),
+    ("cornsilk", Color::rgb(0xff, 0xf8, 0xdc)),
+    ("crimson", Color::rgb(0xdc, 0x14, 0x3c)),
+    ("darkblue", Color::rgb(0x00, 0x00, 0x8b)),
+    ("darkcyan", Color::rgb(0x00, 0x8b, 0x8b)),
+    ("darkgoldenrod", Color::rgb(0xb8, 0x86, 0x0b)),
+    ("darkgray", Color::rgb(0xa9, 0xa9, 0xa9)),
+    ("darkgreen", Color::rgb(0x00, 0x64, 0x00)),
+    ("darkgrey", Color::rgb(0xa9, 0xa9, 0xa9)),
+    ("darkkhaki", Color::rgb(0xbd, 0xb7, 0x6b)),
+    ("darkmagenta", Color::rgb(0x8b, 0x00, 0x8b)),
+    ("darkolivegreen", Color::rgb(0x55, 0x6b, 0x2f)),
+    ("darkorange", Color::rgb(0xff, 0x8c, 0x00)),
+    ("darkorchid", Color::rgb(0x99, 0x32, 0xcc)),
+    ("darkred", Color::rgb(0x8b, 0x00, 0x00)),
+    ("darksalmon", Color::rgb(0xe9, 0x96, 0x7a)),
+    ("darkseagreen", Color::rgb(0x8f, 0xbc, 0x8f)),
+    ("darkslateblue", Color::rgb(0x48, 0x3d, 0x8b)),
+    ("darkslategray", Color::rgb(0x2f, 0x4f, 0x4f)),
+    ("darkslategrey", Color::rgb(0x2f, 0x4f, 0x4f)),
+    ("darkturquoise", Color::rgb(0x00, 0xce, 0xd1)),
+    ("darkviolet", Color::rgb(0x94, 0x00, 0xd3)),
+    ("deeppink", Color::rgb(0xff, 0x14, 0x93)),
+    ("deepskyblue", Color::rgb(0x00, 0xbf, 0xff)),
+    ("dimgray", Color::rgb(0x69, 0x69, 0x69)),
+    ("dimgrey", Color::rgb(0x69, 0x69, 0x69)),
+    ("dodgerblue", Color::rgb(0x1e, 0x90, 0xff)),
+    ("firebrick", Color::rgb(0xb2, 0x22, 0x22)),
+    ("floralwhite", Color::rgb(0xff, 0xfa, 0xf0)),
+    ("forestgreen", Color::rgb(0x22, 0x8b, 0x22)),
+    ("magenta", Color::rgb(0xff, 0x00, 0xff)),
+    ("fuchsia", Color::rgb(0xff, 0x00, 0xff)),
+    ("gainsboro", Color::rgb(0xdc, 0xdc, 0xdc)),
+    ("ghostwhite", Color::rgb(0xf8, 0xf8, 0xff)),
+    ("gold", Color::rgb(0xff, 0xd7, 0x00)),
+    ("goldenrod", Color::rgb(0xda, 0xa5, 0x20)),
+    ("gray", Color::rgb(0x80, 0x80, 0x80)),
+    ("green", Color::rgb(0x00, 0x80, 0x00)),
+    ("greenyellow", Color::rgb(0xad, 0xff, 0x2f)),
+    ("grey", Color::rgb(0x80, 0x80, 0x80)),
+    ("honeydew", Color::rgb(0xf0, 0xff, 0xf0)),
+    ("hotpink", Color::rgb(0xff, 0x69, 0xb4)),
+    ("indianred", Color::rgb(0xcd, 0x5c, 0x5c)),
+    ("indigo", Color::rgb(0x4b, 0x00, 0x82)),
+    ("ivory", Color::rgb(0xff, 0xff, 0xf0)),
+    ("khaki", Color::rgb(0xf0, 0xe6, 0x8c)),
+    ("lavender", Color::rgb(0xe6, 0xe6, 0xfa)),
+    ("lavenderblush", Color::rgb(0xff, 0xf0, 0xf5)),
+    ("lawngreen", Color::rgb(0x7c, 0xfc, 0x00)),
+    ("lemonchiffon", Color::rgb(0xff, 0xfa, 0xcd)),
+    ("lightblue", Color::rgb(0xad, 0xd8, 0xe6)),
+    ("lightcoral", Color::rgb(0xf0, 0x80, 0x80)),
+    ("lightcyan", Color::rgb(0xe0, 0xff, 0xff)),
+    ("lightgoldenrodyellow", Color::rgb(0xfa, 0xfa, 0xd2)),
+    ("lightgray", Color::rgb(0xd3, 0xd3, 0xd3)),
+    ("lightgreen", Color::rgb(0x90, 0xee, 0x90)),
+    ("lightgrey", Color::rgb(0xd3, 0xd3, 0xd3)),
+    ("lightpink", Color::rgb(0xff, 0xb6, 0xc1)),
+    ("lightsalmon", Color::rgb(0xff, 0xa0, 0x7a)),
+    ("lightseagreen", Color::rgb(0x20, 0xb2, 0xaa)),
+    ("lightskyblue", Color::rgb(0x87, 0xce, 0xfa)),
+    ("lightslategray", Color::rgb(0x77, 0x88, 0x99)),
+    ("lightslategrey", Color::rgb(0x77, 0x88, 0x99)),
+    ("lightsteelblue", Color::rgb(0xb0, 0xc4, 0xde)),
+    ("lightyellow", Color::rgb(0xff, 0xff, 0xe0)),
+    ("lime", Color::rgb(0x00, 0xff, 0x00)),
+    ("limegreen", Color::rgb(0x32, 0xcd, 0x32)),
+    ("linen", Color::rgb(0xfa, 0xf0, 0xe6)),
+    ("maroon", Color::rgb(0x80, 0x00, 0x00)),
+    ("mediumaquamarine", Color::rgb(0x66, 0xcd, 0xaa)),
+    ("mediumblue", Color::rgb(0x00, 0x00, 0xcd)),
+    ("mediumorchid", Color::rgb(0xba, 0x55, 0xd3)),
+    ("mediumpurple", Color::rgb(0x93, 0x70, 0xdb)),
+    ("mediumseagreen", Color::rgb(0x3c, 0xb3, 0x71)),
+    ("mediumslateblue", Color::rgb(0x7b, 0x68, 0xee)),
+    ("mediumspringgreen", Color::rgb(0x00, 0xfa, 0x9a)),
+    ("mediumturquoise", Color::rgb(0x48, 0xd1, 0xcc)),
+    ("mediumvioletred", Color::rgb(0xc7, 0x15, 0x85)),
+    ("midnightblue", Color::rgb(0x19, 0x19, 0x70)),
+    ("mintcream", Color::rgb(0xf5, 0xff, 0xfa)),
+    ("mistyrose", Color::rgb(0xff, 0xe4, 0xe1)),
+    ("moccasin", Color::rgb(0xff, 0xe4, 0xb5)),
+    ("navajowhite", Color::rgb(0xff, 0xde, 0xad)),
+    ("navy", Color::rgb(0x00, 0x00, 0x80)),
+    ("oldlace", Color::rgb(0xfd, 0xf5, 0xe6)),
+    ("olive", Color::rgb(0x80, 0x80, 0x00)),
+    ("olivedrab", Color::rgb(0x6b, 0x8e, 0x23)),
+    ("orange", Color::rgb(0xff, 0xa5, 0x00)),
+    ("orangered", Color::rgb(0xff, 0x45, 0x00)),
+    ("orchid", Color::rgb(0xda, 0x70, 0xd6)),
+    ("palegoldenrod", Color::rgb(0xee, 0xe8, 0xaa)),
+    ("palegreen", Color::rgb(0x98, 0xfb, 0x98)),
+    ("paleturquoise", Color::rgb(0xaf, 0xee, 0xee)),
+    ("palevioletred", Color::rgb(0xdb, 0x70, 0x93)),
+    ("papayawhip", Color::rgb(0xff, 0xef, 0xd5)),
+    ("peachpuff", Color::rgb(0xff, 0xda, 0xb9)),
+    ("peru", Color::rgb(0xcd, 0x85, 0x3f)),
+    ("pink", Color::rgb(0xff, 0xc0, 0xcb)),
+    ("plum", Color::rgb(0xdd, 0xa0, 0xdd)),
+    ("powderblue", Color::rgb(0xb0, 0xe0, 0xe6)),
+    ("purple", Color::rgb(0x80, 0x00, 0x80)),
+    ("rebeccapurple", Color::rgb(0x66, 0x33, 0x99)),
+    ("red", Color::rgb(0xff, 0x00, 0x00)),
+    ("rosybrown", Color::rgb(0xbc, 0x8f, 0x8f)),
+    ("royalblue", Color::rgb(0x41, 0x69, 0xe1)),
+    ("saddlebrown", Color::rgb(0x8b, 0x45, 0x13)),
+    ("salmon", Color::rgb(0xfa, 0x80, 0x72)),
+    ("sandybrown", Color::rgb(0xf4, 0xa4, 0x60)),
+    ("seagreen", Color::rgb(0x2e, 0x8b, 0x57)),
+    ("seashell", Color::rgb(0xff, 0xf5, 0xee)),
+    ("sienna", Color::rgb(0xa0, 0x52, 0x2d)),
+    ("silver", Color::rgb(0xc0, 0xc0, 0xc0)),
+    ("skyblue", Color::rgb(0x87, 0xce, 0xeb)),
+    ("slateblue", Color::rgb(0x6a, 0x5a, 0xcd)),
+    ("slategray", Color::rgb(0x70, 0x80, 0x90)),
+    ("slategrey", Color::rgb(0x70, 0x80, 0x90)),
+    ("snow", Color::rgb(0xff, 0xfa, 0xfa)),
+    ("springgreen", Color::rgb(0x00, 0xff, 0x7f)),
+    ("steelblue", Color::rgb(0x46, 0x82, 0xb4)),
+    ("tan", Color::rgb(0xd2, 0xb4, 0x8c)),
+    ("teal", Color::rgb(0x00, 0x80, 0x80)),
+    ("thistle", Color::rgb(0xd8, 0xbf, 0xd8)),
+    ("tomato", Color::rgb(0xff, 0x63, 0x47)),
+    ("turquoise", Color::rgb(0x40, 0xe0, 0xd0)),
+    ("violet", Color::rgb(0xee, 0x82, 0xee)),
+    ("wheat", Color::rgb(0xf5, 0xde, 0xb3)),
+    ("white", Color::rgb(0xff, 0xff, 0xff)),
+    ("whitesmoke", Color::rgb(0xf5, 0xf5, 0xf5)),
+    ("yellow", Color::rgb(0xff, 0xff, 0x00)),
+    ("yellowgreen", Color::rgb(0x9a, 0xcd, 0x32)),
+];
+
+/// The colors of the 16-color terminal palette, as xterm shows them by default: the eight colors
+/// named in `TERMINAL_COLORS`, followed by their bright forms.
+const ANSI_PALETTE: [Color; 16] = [
+    Color::rgb(0x00, 0x00, 0x00),
+    Color::rgb(0xcd, 0x00, 0x00),
+    Color::rgb(0x00, 0xcd, 0x00),
+    Color::rgb(0xcd, 0xcd, 0x00),
+    Color::rgb(0x00, 0x00, 0xee),
+    Color::rgb(0xcd, 0x00, 0xcd),
+    Color::rgb(0x00, 0xcd, 0xcd),
+    Color::rgb(0xe5, 0xe5, 0xe5),
+    Color::rgb(0x7f, 0x7f, 0x7f),
+    Color::rgb(0xff, 0x00, 0x00),
+    Color::rgb(0x00, 0xff, 0x00),
+    Color::rgb(0xff, 0xff, 0x00),
+    Color::rgb(0x5c, 0x5c, 0xff),
+    Color::rgb(0xff, 0x00, 0xff),
+    Color::rgb(0x00, 0xff, 0xff),
+    Color::rgb(0xff, 0xff, 0xff),
+];
+
+/// The names of the eight colors of a terminal, in the order of their SGR codes.
+pub const TERMINAL_COLORS: [&str; 8] =
+    ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// The levels of each component of the 6×6×6 color cube of the 256-color terminal palette.
+const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+/// The reason a string is not a valid `Color`.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ColorError {
+    #[error("A color cannot be empty")]
+    Empty,
+    #[error("Unknown color `{0}`")]
+    UnknownName(String),
+    #[error("`{0}` is not `#` followed by three or six hexadecimal digits")]
+    InvalidHex(String),
+    #[error("`{0}` is not `rgb(r, g, b)` with each component from 0 to 255")]
+    InvalidRgb(String),
+}
+
+impl Color {
+    /// Constructs a new `Color` from its red, green, and blue components.
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Color {
+        Color { red, green, blue }
+    }
+
+    /// Returns the color as it is written in CSS: its name, if it has one, or otherwise `#`
+    /// followed by six hexadecimal digits.
+    pub fn to_css(self) -> String {
+        match NAMED_COLORS.iter().find(|&&(_, color)| color == self) {
+            Some(&(name, _)) => name.into(),
+            None => format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue),
+        }
+    }
+
+    /// Returns the index of the nearest color of the 16-color terminal palette, from 0 to 15,
+    /// where 0 to 7 are the colors named in `TERMINAL_COLORS`, and 8 to 15 are their bright
+    /// forms.
+    pub fn to_ansi16(self) -> u8 {
+        nearest(self, ANSI_PALETTE.iter().cloned()) as u8
+    }
+
+    /// Returns the index of the nearest color of the 256-color terminal palette, from the color
+    /// cube, 16 to 231, or the grayscale ramp, 232 to 255. The first 16, whose colors vary
+    /// between terminals, are not used.
+    pub fn to_ansi256(self) -> u8 {
+        let level = |component: u8| nearest_level(component, CUBE_LEVELS.iter().cloned());
+        let (r, g, b) = (level(self.red), level(self.green), level(self.blue));
+        let cube = Color::rgb(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+        let average = (u32::from(self.red) + u32::from(self.green) + u32::from(self.blue)) / 3;
+        let gray = nearest_level(average as u8, (0..24).map(|i| 8 + 10 * i));
+        let gray_level = 8 + 10 * gray as u8;
+        let gray_color = Color::rgb(gray_level, gray_level, gray_level);
+        if distance(self, gray_color) < distance(self, cube) {
+            232 + gray as u8
+        } else {
+            16 + 36 * r as u8 + 6 * g as u8 + b as u8
+        }
+    }
+}
+
+/// Returns the squared distance between two colors.
+fn distance(a: Color, b: Color) -> u32 {
+    let component = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2) as u32;
+    component(a.red, b.red) + component(a.green, b.green) + component(a.blue, b.blue)
+}
+
+/// Returns the index of the nearest of the given colors.
+fn nearest<I: Iterator<Item = Color>>(color: Color, palette: I) -> usize {
+    palette
+        .enumerate()
+        .min_by_key(|&(_, other)| distance(color, other))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Returns the index of the nearest of the given levels of a component.
+fn nearest_level<I: Iterator<Item = u8>>(component: u8, levels: I) -> usize {
+    levels
+        .enumerate()
+        .min_by_key(|&(_, level)| (i32::from(component) - i32::from(level)).abs())
+        .map_or(0, |(i, _)| i)
+}
+
+impl FromStr for Color {
+    type Err = ColorError;
+
+    fn from_str(s: &str) -> Result<Color, ColorError> {
+        if s.is_empty() {
+            return Err(ColorError::Empty);
+        }
+        if let Some(hex) = s.strip_prefix('#') {
+            let invalid = || ColorError::InvalidHex(s.into());
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).map(|d| d * 17);
+            let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            let color = match hex.len() {
+                3 => (digit(0), digit(1), digit(2)),
+                6 => (pair(0), pair(2), pair(4)),
+                _ => return Err(invalid()),
+            };
+            return match color {
+                (Ok(red), Ok(green), Ok(blue)) => Ok(Color::rgb(red, green, blue)),
+                _ => Err(invalid()),
+            };
+        }
+        let lower = s.to_ascii_lowercase();
+        if let Some(components) = lower
+            .strip_prefix("rgb(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let components: Result<Vec<u8>, ParseIntError> =
+                components.split(',').map(|c| c.trim().parse()).collect();
+            return match components.as_deref() {
+                Ok(&[red, green, blue]) => Ok(Color::rgb(red, green, blue)),
+                _ => Err(ColorError::InvalidRgb(s.into())),
+            };
+        }
+        if let Some(name) = lower.strip_prefix("bright-") {
+            if let Some(i) = TERMINAL_COLORS.iter().position(|&color| color == name) {
+                return Ok(ANSI_PALETTE[8 + i]);
+            }
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|&&(name, _)| name == lower)
+            .map(|&(_, color)| color)
+            .ok_or_else(|| ColorError::UnknownName(s.into()))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_css())
+    }
+}
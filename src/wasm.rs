@@ -0,0 +1,78 @@
+//! Wrappers for use from JavaScript, exported with `wasm_bindgen`, which work on strings and need
+//! no filesystem.
+//!
+//! Each function which can fail throws a string holding the diagnostics, in the format of
+//! `Diagnostics::to_json`.
+
+use wasm_bindgen::prelude::*;
+
+use diagnostics::Diagnostics;
+use errors::{Error, Position};
+use parser::Parser;
+use render::html::{HtmlOptions, HtmlRenderer};
+use tokenize::{StrTokens, Token};
+
+/// A single token and the position at which it started, as returned by `tokenize_str_json`.
+#[derive(Serialize)]
+struct PositionedToken {
+    position: Position,
+    token: Token,
+}
+
+/// Returns the diagnostics for an error, as a value to be thrown.
+fn thrown(error: &Error) -> JsValue {
+    let mut diagnostics = Diagnostics::new();
+    diagnostics.push_error(error);
+    JsValue::from_str(&diagnostics.to_json())
+}
+
+/// Tokenizes a string, and returns the tokens as a JSON array of objects with the `position` at
+/// which each started and the `token` itself, serialized as described at `Token`.
+#[wasm_bindgen]
+pub fn tokenize_str_json(input: &str) -> Result<String, JsValue> {
+    let mut tokens = StrTokens::new(input).coalesce_text(true);
+    let mut positioned = Vec::new();
+    while let Some(token) = tokens.next_res().map_err(|e| thrown(&e))? {
+        positioned.push(PositionedToken {
+            position: tokens.location(),
+            token: token.into_owned(),
+        });
+    }
+    // Serializing tokens and positions to a `String` cannot fail.
+    Ok(serde_json::to_string(&positioned).unwrap_or_default())
+}
+
+/// Parses a string, and returns the document rendered as an HTML fragment with the default
+/// options.
+#[wasm_bindgen]
+pub fn render_html_str(input: &str) -> Result<String, JsValue> {
+    let document = Parser::new(input.as_bytes()).parse().map_err(|e| thrown(&e))?;
+    HtmlRenderer::new(HtmlOptions::new())
+        .render_to_string(&document)
+        .map_err(|e| thrown(&e))
+}
+
+/// Parses a string and renders it as HTML, as with `render_html_str`, and returns the warnings
+/// of the parser and the renderer along with the error, if any, as a JSON array in the format of
+/// `Diagnostics::to_json`. The array is empty if there were no problems. The warnings of the
+/// renderer are positioned at the start of the block in which they occurred.
+#[wasm_bindgen]
+pub fn check_str_json(input: &str) -> String {
+    let mut diagnostics = Diagnostics::new();
+    let mut blocks = Parser::new(input.as_bytes()).into_blocks();
+    let result = blocks.parse_spanned();
+    diagnostics.extend(blocks.warnings().iter().cloned());
+    let result = result.and_then(|(document, spans)| {
+        let mut html = Vec::new();
+        let renderer = HtmlRenderer::new(HtmlOptions::new());
+        let (report, _) = renderer.render_mapped(&document, &spans, &mut html)?;
+        for diagnostic in report.diagnostics(input) {
+            diagnostics.push(diagnostic);
+        }
+        Ok(())
+    });
+    if let Err(ref error) = result {
+        diagnostics.push_error(error);
+    }
+    diagnostics.to_json()
+}
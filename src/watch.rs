@@ -0,0 +1,265 @@
+//! Waiting for changes to a set of files, such as the input of a document, so that it can be
+//! rendered again whenever it is edited.
+//!
+//! With the `watch` feature, a `Watcher` is notified of changes by the operating system. Without
+//! it, or if notifications cannot be set up, it polls the modification time and length of each
+//! file instead.
+
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "watch")]
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "watch")]
+use notify::{self, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+/// The default for `Watcher::debounce`.
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The default interval between polls, for a `Watcher` which polls.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The state of a file as seen by polling: its modification time and length, or `None` if it
+/// could not be read.
+type Stamp = Option<(SystemTime, u64)>;
+
+/// How a `Watcher` learns of changes.
+#[derive(Debug)]
+enum Backend {
+    /// Checking the stamp of each file, with the given interval between checks.
+    Poll {
+        interval: Duration,
+        stamps: Vec<Stamp>,
+    },
+    /// Notifications from the operating system, for the directories containing the files.
+    #[cfg(feature = "watch")]
+    Notify {
+        // Held only so that the notifications continue until the `Watcher` is dropped.
+        _watcher: RecommendedWatcher,
+        events: Receiver<notify::Result<notify::Event>>,
+    },
+}
+
+/// Waits for changes to a set of files.
+///
+/// A file which is removed and recreated, as many editors do when saving, counts as changed, and
+/// continues to be watched. Successive changes which follow each other within the debounce
+/// period, such as those made by a single save, are reported together.
+#[derive(Debug)]
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    debounce: Duration,
+    backend: Backend,
+}
+
+impl Watcher {
+    /// Constructs a new `Watcher` for the given files, which uses the operating system's
+    /// notifications with the `watch` feature, falling back to polling if they cannot be set up.
+    pub fn new<I, P>(paths: I) -> Watcher
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let paths: Vec<PathBuf> = paths.into_iter().map(|p| absolute(p.as_ref())).collect();
+        #[cfg(feature = "watch")]
+        {
+            if let Some(backend) = notify_backend(&paths) {
+                return Watcher {
+                    paths,
+                    debounce: DEFAULT_DEBOUNCE,
+                    backend,
+                };
+            }
+        }
+        Watcher::polling(paths, DEFAULT_POLL_INTERVAL)
+    }
+
+    /// Constructs a new `Watcher` for the given files, which checks whether any of them has
+    /// changed at the given interval.
+    pub fn polling<I, P>(paths: I, interval: Duration) -> Watcher
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let paths: Vec<PathBuf> = paths.into_iter().map(|p| absolute(p.as_ref())).collect();
+        let stamps = paths.iter().map(|path| stamp(path)).collect();
+        Watcher {
+            paths,
+            debounce: DEFAULT_DEBOUNCE,
+            backend: Backend::Poll { interval, stamps },
+        }
+    }
+
+    /// Sets how long the files must go unchanged after a change before it is reported. Defaults
+    /// to 100 milliseconds.
+    pub fn debounce(mut self, debounce: Duration) -> Watcher {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Returns the files being watched, made absolute.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Returns whether the watcher polls the files, rather than being notified of changes.
+    pub fn is_polling(&self) -> bool {
+        match self.backend {
+            Backend::Poll { .. } => true,
+            #[cfg(feature = "watch")]
+            Backend::Notify { .. } => false,
+        }
+    }
+
+    /// Blocks until at least one of the files changes, and then until none has changed for the
+    /// debounce period, and returns those which changed, in the order in which they were given.
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![false; self.paths.len()];
+        while !self.poll(&mut changed, None) {}
+        while self.poll(&mut changed, Some(self.debounce)) {}
+        self.paths
+            .iter()
+            .zip(changed)
+            .filter(|&(_, changed)| changed)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Waits for changes until the timeout has passed, or indefinitely if there is none, marking
+    /// the files which changed. Returns whether any did.
+    fn poll(&mut self, changed: &mut [bool], timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        match self.backend {
+            Backend::Poll {
+                interval,
+                ref mut stamps,
+            } => poll_stamps(&self.paths, stamps, interval, changed, deadline),
+            #[cfg(feature = "watch")]
+            Backend::Notify { ref events, .. } => {
+                match receive_events(&self.paths, events, changed, deadline) {
+                    Some(any) => any,
+                    None => {
+                        // The notifications have stopped, so fall back to polling.
+                        let stamps = self.paths.iter().map(|path| stamp(path)).collect();
+                        self.backend = Backend::Poll {
+                            interval: DEFAULT_POLL_INTERVAL,
+                            stamps,
+                        };
+                        self.poll(changed, timeout)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks the stamps of the files at the given interval until one has changed or the deadline
+/// has passed, marking those which changed. Returns whether any did.
+fn poll_stamps(
+    paths: &[PathBuf],
+    stamps: &mut [Stamp],
+    interval: Duration,
+    changed: &mut [bool],
+    deadline: Option<Instant>,
+) -> bool {
+    loop {
+        let mut any = false;
+        for ((path, old), changed) in paths.iter().zip(&mut *stamps).zip(&mut *changed) {
+            let new = stamp(path);
+            if mem::replace(old, new) != new {
+                *changed = true;
+                any = true;
+            }
+        }
+        if any {
+            return true;
+        }
+        match deadline {
+            Some(deadline) if Instant::now() >= deadline => return false,
+            _ => thread::sleep(interval),
+        }
+    }
+}
+
+/// Receives notifications until one concerns any of the files or the deadline has passed,
+/// marking the files which changed. Returns whether any did, or `None` if the notifications have
+/// stopped.
+#[cfg(feature = "watch")]
+fn receive_events(
+    paths: &[PathBuf],
+    events: &Receiver<notify::Result<notify::Event>>,
+    changed: &mut [bool],
+    deadline: Option<Instant>,
+) -> Option<bool> {
+    loop {
+        let received = match deadline {
+            Some(deadline) => {
+                events.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let event = match received {
+            Ok(Ok(event)) => event,
+            // An error may mean that changes were missed, so it counts as a change to every file.
+            Ok(Err(_)) => {
+                changed.iter_mut().for_each(|changed| *changed = true);
+                return Some(true);
+            }
+            Err(RecvTimeoutError::Timeout) => return Some(false),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        };
+        if event.kind.is_access() {
+            continue;
+        }
+        let mut any = false;
+        for (path, changed) in paths.iter().zip(&mut *changed) {
+            if event.paths.contains(path) {
+                *changed = true;
+                any = true;
+            }
+        }
+        if any {
+            return Some(true);
+        }
+    }
+}
+
+/// Sets up notifications for the directories containing the given files, or returns `None` if
+/// they cannot be.
+#[cfg(feature = "watch")]
+fn notify_backend(paths: &[PathBuf]) -> Option<Backend> {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).ok()?;
+    let mut directories: Vec<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+    directories.sort();
+    directories.dedup();
+    for directory in directories {
+        watcher.watch(directory, RecursiveMode::NonRecursive).ok()?;
+    }
+    Some(Backend::Notify {
+        _watcher: watcher,
+        events,
+    })
+}
+
+/// Returns the path made absolute, with its directory resolved through any symbolic links, so
+/// that it can be compared to the paths given by notifications. The file itself need not exist.
+fn absolute(path: &Path) -> PathBuf {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(directory), path.file_name()) {
+        (Ok(directory), Some(name)) => directory.join(name),
+        _ => path.to_owned(),
+    }
+}
+
+/// Returns the current stamp of a file.
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -0,0 +1,184 @@
+//! Runs the command-line tool on documents given as files and on standard input, checking what it
+//! writes and the code with which it exits.
+
+#![cfg(feature = "std")]
+
+extern crate tempfile;
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// The document used by most of the tests.
+const DOCUMENT: &str = "x \\emph{y}\n";
+
+/// Runs the tool with the given arguments, writing `stdin` to its standard input.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_formatting"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("running formatting");
+    // The tool may exit without reading its input, closing the pipe, which is not an error here.
+    let mut input = child.stdin.take().expect("opening standard input");
+    let _ = input.write_all(stdin.as_bytes());
+    drop(input);
+    child.wait_with_output().expect("running formatting")
+}
+
+/// Writes a document to a file in a new temporary directory, which is removed when the returned
+/// value is dropped.
+fn input(name: &str, document: &str) -> tempfile::TempDir {
+    let directory = tempfile::tempdir().expect("creating a temporary directory");
+    fs::write(directory.path().join(name), document).expect("writing the input");
+    directory
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).expect("standard output is UTF-8")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).expect("standard error is UTF-8")
+}
+
+/// Returns the path of a file as an argument.
+fn arg(path: &Path) -> &str {
+    path.to_str().expect("the temporary directory is UTF-8")
+}
+
+#[test]
+fn tokens() {
+    let output = run(&["tokens", "-"], DOCUMENT);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "1:1\tText(\"x \")\n1:3\tCommand(\"emph\")\n1:8\tBeginGroup\n1:9\tChar('y')\n\
+         1:10\tEndGroup\n1:11\tChar('\\n')\n"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn tokens_as_json() {
+    let output = run(&["tokens", "--json", "-"], "x\n");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "{\"position\":{\"line\":1,\"column\":1,\"offset\":0,\"utf16_column\":1},\
+         \"token\":{\"type\":\"text\",\"value\":\"x\\n\"}}\n"
+    );
+}
+
+#[test]
+fn ast() {
+    let directory = input("input.mle", DOCUMENT);
+    let path = directory.path().join("input.mle");
+    let output = run(&["ast", arg(&path)], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let ast = stdout(&output);
+    assert!(ast.starts_with("Document {"), "{}", ast);
+    assert!(ast.contains("name: \"emph\""), "{}", ast);
+}
+
+#[test]
+fn render_from_standard_input() {
+    let cases = [
+        ("html", "<p>x <em>y</em></p>\n"),
+        ("plain", "x y\n"),
+        ("markdown", "x *y*\n"),
+    ];
+    for &(format, expected) in &cases {
+        let output = run(&["render", "--to", format, "-"], DOCUMENT);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        assert_eq!(stdout(&output), expected, "--to {}", format);
+    }
+}
+
+#[test]
+fn render_to_a_file() {
+    let directory = input("input.mle", DOCUMENT);
+    let path = directory.path().join("input.mle");
+    let rendered = directory.path().join("output.html");
+    let output = run(&["render", "--to", "html", "-o", arg(&rendered), arg(&path)], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+    let html = fs::read_to_string(&rendered).expect("reading the output");
+    assert_eq!(html, "<p>x <em>y</em></p>\n");
+}
+
+#[test]
+fn usage_errors() {
+    let cases: [&[&str]; 4] = [
+        &[],
+        &["render", "-"],
+        &["render", "--to", "docx", "-"],
+        &["tokens", "--to", "html", "-"],
+    ];
+    for args in &cases {
+        let output = run(args, DOCUMENT);
+        assert_eq!(output.status.code(), Some(64), "{:?}", args);
+        assert!(stderr(&output).contains("usage:"), "{:?}", args);
+        assert_eq!(stdout(&output), "", "{:?}", args);
+    }
+}
+
+#[test]
+fn syntax_errors() {
+    let directory = input("input.mle", "a \\emph{b");
+    let path = directory.path().join("input.mle");
+    let output = run(&["render", "--to", "html", arg(&path)], "");
+    assert_eq!(output.status.code(), Some(65));
+    let message = stderr(&output);
+    assert!(message.contains("error[E0107]"), "{}", message);
+    assert!(message.contains(&format!("{}:1:8", path.display())), "{}", message);
+
+    let output = run(&["ast", "-"], "a \\emph{b");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(stderr(&output).contains("<stdin>:1:8"), "{}", stderr(&output));
+}
+
+#[test]
+fn missing_input() {
+    let directory = tempfile::tempdir().expect("creating a temporary directory");
+    let path = directory.path().join("missing.mle");
+    for command in &["tokens", "ast"] {
+        let output = run(&[command, arg(&path)], "");
+        assert_eq!(output.status.code(), Some(66), "{}", command);
+        assert!(stderr(&output).contains("Could not open"), "{}", stderr(&output));
+    }
+    let output = run(&["render", "--to", "plain", arg(&path)], "");
+    assert_eq!(output.status.code(), Some(66));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn strict_checks() {
+    let directory = input("input.mle", "x \nabcdefghij\n");
+    let path = directory.path().join("input.mle");
+    let config = directory.path().join("formatting.toml");
+    fs::write(&config, "[lints]\nlong_line = \"deny\"\nmax_line_length = 5\n")
+        .expect("writing the configuration");
+
+    let output = run(&["check", arg(&path)], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("W0104"), "{}", stderr(&output));
+
+    // Strict mode reports every lint, and those which the configuration denies are errors.
+    let output = run(&["check", "--strict", arg(&path)], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let message = stderr(&output);
+    assert!(message.contains("warning[W0104]"), "{}", message);
+    assert!(message.contains("1 warning"), "{}", message);
+
+    let output = run(&["check", "--strict", "--config", arg(&config), arg(&path)], "");
+    assert_eq!(output.status.code(), Some(65));
+    let message = stderr(&output);
+    assert!(message.contains("warning[W0104]"), "{}", message);
+    assert!(message.contains("error[E0106]"), "{}", message);
+    assert!(message.contains(&format!("{}:2:6", path.display())), "{}", message);
+}
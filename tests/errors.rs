@@ -0,0 +1,222 @@
+//! Checks the errors and the warnings of lints which the parser reports, and how errors are shown.
+//!
+//! The diagnostics of `tests/diagnostics/NAME.mle` are compared with `NAME.json` as they are
+//! serialized. Setting `UPDATE_GOLDEN` writes them to that file instead, as for `tests/golden`.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+
+#[cfg(feature = "serde")]
+use std::env;
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use formatting::diagnostics::Diagnostic;
+use formatting::diagnostics::{Diagnostics, Severity};
+use formatting::errors::{Error, ErrorKind, Position, Warning};
+use formatting::lints::{Level, Lint, Lints};
+use formatting::parser::{Parser, ParserOptions};
+
+/// Parses a document which is expected to be invalid, returning the error.
+fn error(source: &str) -> Error {
+    match Parser::new(source.as_bytes()).parse() {
+        Ok(document) => panic!("{:?} parsed as {:?}", source, document),
+        Err(error) => error,
+    }
+}
+
+#[test]
+fn unexpected_tokens() {
+    let cases = [
+        (
+            "{a \\end{quote} b}",
+            "`}` to close the group",
+            "`\\end{quote}`",
+            (1, 4),
+        ),
+        (
+            "one\ntwo \\end{quote}",
+            "an open environment",
+            "`\\end{quote}`",
+            (2, 5),
+        ),
+        (
+            "\\begin{description}\n\\item[a \\end{description}",
+            "`]` to close the options",
+            "`\\end{description}`",
+            (2, 9),
+        ),
+    ];
+    for &(source, expected, found, (line, column)) in &cases {
+        let error = error(source);
+        match *error.kind() {
+            ErrorKind::UnexpectedToken {
+                expected: e,
+                found: ref f,
+                position,
+            } => {
+                assert_eq!((e, &**f), (expected, found), "{:?}", source);
+                assert_eq!((position.line, position.column), (line, column), "{:?}", source);
+            }
+            ref kind => panic!("{:?} gave {:?}", source, kind),
+        }
+        let message = format!("expected {}, found {} at {}:{}", expected, found, line, column);
+        assert_eq!(error.kind().to_string(), message);
+        assert_eq!(
+            error.to_string(),
+            format!("<input>:{}:{}: [E0101] {}", line, column, message)
+        );
+    }
+}
+
+#[test]
+fn annotated_errors() {
+    let cases = [
+        // Tabs are expanded, and the whole of the unexpected token is underlined.
+        (
+            "first line\n\tx {a \\end{quote} b}\n",
+            "error[E0101]: expected `}` to close the group, found `\\end{quote}` at 2:7\n \
+             --> <input>:2:7\n  |\n2 |     x {a \\end{quote} b}\n  |          ^^^^^^^^^^^",
+        ),
+        // The line on which an unclosed `\verbatim` started is shown, not where the input ended.
+        (
+            "Some text\n\\verbatim|never closed\nmore\n",
+            "error[E0102]: Unclosed `\\verbatim` command started at 2:1: expected `|` before \
+             4:1\n --> <input>:2:1\n  |\n2 | \\verbatim|never closed\n  | ^",
+        ),
+        (
+            "a \\emph{b\n",
+            "error[E0107]: End of input while the argument opened at 1:8 was still open\n \
+             --> <input>:1:8\n  |\n1 | a \\emph{b\n  |        ^",
+        ),
+    ];
+    for &(source, expected) in &cases {
+        assert_eq!(error(source).annotate(source).to_string(), expected);
+    }
+}
+
+/// Parses a document one block at a time, continuing after each block which fails to parse, and
+/// returns the diagnostics collected with the given error limit, and the number of blocks parsed.
+fn recover(source: &str, error_limit: usize) -> (Diagnostics, usize) {
+    let mut blocks = Parser::new(source.as_bytes()).into_blocks();
+    let mut diagnostics = Diagnostics::new().error_limit(error_limit);
+    let mut parsed = 0;
+    for block in &mut blocks {
+        match block {
+            Ok(_) => parsed += 1,
+            Err(error) => diagnostics.push_error(&error),
+        }
+    }
+    diagnostics.extend(blocks.warnings().iter().cloned());
+    (diagnostics, parsed)
+}
+
+#[test]
+fn errors_beyond_the_limit_are_suppressed() {
+    // Each stray `}` is an error, and the `\item` without a term a warning.
+    let source = "a }\n\n".repeat(100) + "\\begin{description}\n\\item x\n\\end{description}\n";
+    let (diagnostics, parsed) = recover(&source, 5);
+    assert_eq!(diagnostics.count(Severity::Error), 5);
+    assert_eq!(diagnostics.count(Severity::Warning), 1);
+    assert_eq!(diagnostics.count(Severity::Note), 1);
+    assert_eq!(diagnostics.suppressed(), 95);
+    let sorted = diagnostics.sorted();
+    let last = sorted.last().expect("no diagnostics");
+    assert_eq!(last.severity, Severity::Note);
+    assert_eq!(last.message, "too many errors, aborting: 95 more suppressed after the first 5");
+    // The blocks parsed around the errors are still produced.
+    assert!(parsed >= 100, "only {} blocks were parsed", parsed);
+
+    // The errors up to the limit are kept, with the note in place of those after them.
+    let (diagnostics, _) = recover(&"}\n\n".repeat(51), 50);
+    assert_eq!(diagnostics.len(), 51);
+    assert_eq!(diagnostics.count(Severity::Error), 50);
+    let (diagnostics, _) = recover(&"}\n\n".repeat(50), 50);
+    assert_eq!(diagnostics.len(), 50);
+    assert_eq!(Diagnostics::new().get_error_limit(), 50);
+}
+
+/// Parses the document `tests/diagnostics/NAME.mle`, as the file `NAME.mle`, returning its
+/// diagnostics.
+#[cfg(feature = "serde")]
+fn diagnostics(name: &str) -> Diagnostics {
+    let file = format!("{}.mle", name);
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/diagnostics").join(&file);
+    let source = fs::read(&path).expect("reading a document");
+    let mut parser = Parser::new(&*source);
+    let result = parser.parse();
+    let mut diagnostics = Diagnostics::new();
+    for warning in parser.warnings() {
+        let mut diagnostic = Diagnostic::warning(warning.clone());
+        diagnostic.file = Some(file.clone());
+        diagnostics.push(diagnostic);
+    }
+    if let Err(error) = result {
+        diagnostics.push_error(&error.with_file(file));
+    }
+    diagnostics
+}
+
+/// Checks the diagnostics of `tests/diagnostics/broken.mle` against `broken.json`, which is
+/// written instead if `UPDATE_GOLDEN` is set.
+#[cfg(feature = "serde")]
+#[test]
+fn diagnostics_as_json() {
+    let json = diagnostics("broken").to_json() + "\n";
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/diagnostics/broken.json");
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &json).expect("writing a golden file");
+        return;
+    }
+    let expected = fs::read_to_string(&path).expect("reading a golden file");
+    assert_eq!(json, expected);
+}
+
+/// Returns the position of a character, given its line, column, and byte offset, in a document
+/// of ASCII text.
+fn position(line: usize, column: usize, offset: usize) -> Position {
+    Position {
+        line,
+        column,
+        offset,
+        utf16_column: column,
+    }
+}
+
+#[test]
+fn lints_are_reported_with_positions() {
+    let source = "a\u{7}b\nabcdefghijklmnopqrstuvwxyz\n \tx\ny \nz % note\n";
+    let parse = |lints: Lints| {
+        let options = ParserOptions::new().lints(lints);
+        let mut parser = Parser::with_options(source.as_bytes(), options);
+        let result = parser.parse().map(|_| ()).map_err(|e| e.kind().clone());
+        (result, parser.warnings().to_vec())
+    };
+    let lints = Lints::all(Level::Warn).max_line_length(20);
+
+    let (result, warnings) = parse(lints.clone());
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        warnings,
+        [
+            Warning::ControlCharacter('\u{7}', position(1, 2, 1)),
+            Warning::LongLine(20, position(2, 21, 24)),
+            Warning::MixedIndentation(position(3, 2, 32)),
+            Warning::TrailingWhitespace(position(4, 2, 36)),
+            Warning::PercentComment(position(5, 3, 40)),
+        ]
+    );
+
+    let (result, warnings) = parse(lints.clone().level(Lint::PercentComment, Level::Allow));
+    assert_eq!(result, Ok(()));
+    assert_eq!(warnings.len(), 4);
+    assert!(!warnings.iter().any(|w| matches!(w, Warning::PercentComment(_))));
+
+    let (result, _) = parse(lints.level(Lint::MixedIndentation, Level::Deny));
+    let warning = Warning::MixedIndentation(position(3, 2, 32));
+    assert_eq!(result, Err(ErrorKind::Denied(warning)));
+}
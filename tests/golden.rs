@@ -0,0 +1,72 @@
+//! Renders each document in `tests/golden`, named `NAME.mle`, in each format for which there is
+//! a file `NAME.FORMAT`, such as `NAME.plain`, and compares the output with that file. Plain
+//! text and ANSI output is laid out in 80 columns, whatever the terminal, and HTML tables have
+//! captions. HTML output is also parsed as the body of a page, which must give no errors.
+//!
+//! Setting `UPDATE_GOLDEN` writes the output to the files instead, for reviewing with `git diff`.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+extern crate html5ever;
+extern crate markup5ever_rcdom;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use formatting::convert::{convert_str_to_string, OutputFormat, Options};
+use formatting::render::ansi::AnsiOptions;
+use formatting::render::html::HtmlOptions;
+use formatting::render::plain::PlainOptions;
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, QualName};
+use markup5ever_rcdom::RcDom;
+
+/// Returns the errors found parsing HTML output as the content of the body of a page.
+fn html_errors(html: &str) -> Vec<String> {
+    let body = QualName::new(None, ns!(html), local_name!("body"));
+    let parser = html5ever::parse_fragment(RcDom::default(), Default::default(), body, Vec::new());
+    let dom = parser.one(html);
+    dom.errors.iter().map(|error| error.to_string()).collect()
+}
+
+#[test]
+fn golden() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut inputs: Vec<_> = fs::read_dir(&directory)
+        .expect("reading tests/golden")
+        .map(|entry| entry.expect("reading tests/golden").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "mle"))
+        .collect();
+    inputs.sort();
+    let mut options = Options::default();
+    options.plain = PlainOptions::new().width(80);
+    options.ansi = AnsiOptions::new().plain(options.plain.clone());
+    options.html = HtmlOptions::new().table_captions(true);
+    let mut compared = 0;
+    for input in &inputs {
+        let source = fs::read_to_string(input).expect("reading a golden input");
+        for &format in OutputFormat::all() {
+            let expected_path = input.with_extension(format.name());
+            if !expected_path.exists() {
+                continue;
+            }
+            let (output, _) = convert_str_to_string(&source, format, &options)
+                .unwrap_or_else(|e| panic!("{}: {}", input.display(), e));
+            if format == OutputFormat::Html {
+                let errors = html_errors(&output);
+                assert!(errors.is_empty(), "{}: {:?}", expected_path.display(), errors);
+            }
+            if update {
+                fs::write(&expected_path, &output).expect("writing a golden file");
+                continue;
+            }
+            let expected = fs::read_to_string(&expected_path).expect("reading a golden file");
+            assert_eq!(output, expected, "{} differs", expected_path.display());
+            compared += 1;
+        }
+    }
+    assert!(update || compared > 0, "no golden files in tests/golden");
+}
@@ -0,0 +1,120 @@
+//! Checks that reading tokens in chunks with `Tokens::next_chunk` gives the same tokens and errors
+//! as reading them one at a time with `Tokens::next_res`.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+#[cfg(feature = "testing")]
+#[macro_use]
+extern crate proptest;
+
+use formatting::errors::ErrorKind;
+use formatting::tokenize::{Token, Tokens};
+
+/// The most tokens and errors read from one input, in case reading does not stop after an error.
+const LIMIT: usize = 10_000;
+
+/// What reading a token gave.
+#[derive(Debug, PartialEq)]
+enum Read {
+    Token(Token),
+    Error(ErrorKind),
+}
+
+fn tokens(source: &str, coalesce: bool) -> Tokens<&[u8]> {
+    Tokens::new(source.as_bytes()).coalesce_text(coalesce)
+}
+
+/// Reads every token of the source with `next_res`, continuing after errors.
+fn one_at_a_time(source: &str, coalesce: bool) -> Vec<Read> {
+    let mut tokens = tokens(source, coalesce);
+    let mut read = Vec::new();
+    while read.len() < LIMIT {
+        match tokens.next_res() {
+            Ok(Some(token)) => read.push(Read::Token(token)),
+            Ok(None) => break,
+            Err(error) => read.push(Read::Error(error.kind().clone())),
+        }
+    }
+    read
+}
+
+/// Reads every token of the source with `next_chunk`, continuing after errors.
+fn in_chunks(source: &str, coalesce: bool, size: usize) -> Vec<Read> {
+    let mut tokens = tokens(source, coalesce);
+    let mut read = Vec::new();
+    let mut buf = Vec::new();
+    while read.len() < LIMIT {
+        match tokens.next_chunk(&mut buf, size) {
+            Ok(0) => break,
+            Ok(n) => {
+                assert!(n <= size, "{} tokens read in a chunk of {}", n, size);
+                assert_eq!(n, buf.len());
+                read.extend(buf.drain(..).map(Read::Token));
+            }
+            Err(error) => read.push(Read::Error(error.kind().clone())),
+        }
+    }
+    read
+}
+
+/// Checks that reading the source in chunks of several sizes gives what reading it one token at
+/// a time does.
+fn check(source: &str) {
+    for &coalesce in &[false, true] {
+        let expected = one_at_a_time(source, coalesce);
+        for &size in &[1, 2, 3, 7, 64] {
+            assert_eq!(
+                in_chunks(source, coalesce, size),
+                expected,
+                "{:?} in chunks of {}, coalesce_text({})",
+                source,
+                size,
+                coalesce
+            );
+        }
+    }
+}
+
+#[test]
+fn chunks_match_single_tokens() {
+    let sources = [
+        "",
+        "text",
+        "Some \\emph{text}, with {groups} and \\verbatim[rust]{let x = {1};} and \\raw|<b>|.",
+        "\\begin{quote}\nquoted\n\\end{quote}\n\nescapes: \\\\ \\{ \\} \\- and caf\u{e9}",
+    ];
+    for source in &sources {
+        check(source);
+    }
+}
+
+#[test]
+fn chunks_stop_at_errors() {
+    let sources = [
+        // An error at the very end, after a chunk's worth of tokens.
+        "abcdefgh\\",
+        // An error in the middle, after which reading continues.
+        "ab \\verbatim|x\n\\emph{y} z",
+        "\\",
+        "one \\verbatim[unclosed two",
+    ];
+    for source in &sources {
+        check(source);
+        assert!(
+            one_at_a_time(source, false)
+                .iter()
+                .any(|read| matches!(*read, Read::Error(_))),
+            "{:?} gave no error",
+            source
+        );
+    }
+}
+
+#[cfg(feature = "testing")]
+proptest! {
+    #[test]
+    fn chunks_match_single_tokens_for_any_input(source in "[a-z \\\\{}|\\[\\]\n]{0,64}") {
+        check(&source);
+    }
+}
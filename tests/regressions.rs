@@ -0,0 +1,68 @@
+//! Replays the inputs in `fuzz/regressions`, which once made the library panic, hang, or overflow
+//! the stack, so that `cargo test` catches them without a nightly toolchain.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use formatting::ast::Document;
+use formatting::convert::{convert, convert_document, OutputFormat, Options};
+use formatting::parser::{Parser, ParserOptions};
+
+/// Reads each input in `fuzz/regressions`, with its name.
+fn regressions() -> Vec<(String, Vec<u8>)> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+    let mut inputs = fs::read_dir(directory)
+        .expect("reading fuzz/regressions")
+        .map(|entry| {
+            let path = entry.expect("reading fuzz/regressions").path();
+            let name = path.display().to_string();
+            let input = fs::read(&path).expect("reading a regression");
+            (name, input)
+        })
+        .collect::<Vec<_>>();
+    inputs.sort();
+    inputs
+}
+
+/// Returns the options given by the first byte of an input, as the `parse` fuzz target reads
+/// them, and the rest of the input.
+fn options(data: &[u8]) -> (Options, &[u8]) {
+    let (&flags, input) = data.split_first().unwrap_or((&0, data));
+    let mut options = Options::new();
+    options.parser = ParserOptions::new()
+        .smart_punctuation(flags & 1 != 0)
+        .skip_space_before_arguments(flags & 2 != 0)
+        .skip_newline_before_arguments(flags & 4 != 0);
+    (options, input)
+}
+
+#[test]
+fn regressions_render_in_every_format() {
+    let inputs = regressions();
+    assert!(!inputs.is_empty(), "no inputs in fuzz/regressions");
+    for (name, data) in &inputs {
+        let (mut options, input) = options(data);
+        // Most of them fail to parse, so the blocks which parse around the errors are rendered.
+        let blocks = Parser::with_options(input, options.parser.clone()).into_blocks();
+        let nodes = panic::catch_unwind(AssertUnwindSafe(|| blocks.flatten().collect()))
+            .unwrap_or_else(|_| panic!("{} panicked parsing", name));
+        let document = Document { nodes };
+        // Whether rendering fails does not matter, only that it returns.
+        for &format in OutputFormat::all() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let _ = convert_document(document.clone(), "", Vec::new(), format, &options);
+            }));
+            assert!(result.is_ok(), "{} panicked rendering {}", name, format);
+        }
+        options.html = options.html.stream(true);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = convert(input, Vec::new(), OutputFormat::Html, &options);
+        }));
+        assert!(result.is_ok(), "{} panicked streaming html", name);
+    }
+}
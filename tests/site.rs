@@ -0,0 +1,138 @@
+//! Renders `tests/site/manual.mle`, which has three sections, as a site in a temporary directory,
+//! and checks the pages written, the links between them, and the links across them.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+extern crate tempfile;
+
+use std::fs;
+use std::path::Path;
+
+use formatting::parser::Parser;
+use formatting::render::html::{render_site, HtmlOptions};
+
+/// Renders the manual as a site with the given options, returning the name and the contents of
+/// each file written, in order of name.
+fn site(options: &HtmlOptions) -> Vec<(String, String)> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/site/manual.mle");
+    let source = fs::read(path).expect("reading the manual");
+    let document = Parser::new(&source[..]).parse().expect("parsing the manual");
+    let directory = tempfile::tempdir().expect("creating a temporary directory");
+    render_site(&document, directory.path(), options).expect("rendering the site");
+    let mut files = fs::read_dir(directory.path())
+        .expect("reading the site")
+        .map(|entry| {
+            let path = entry.expect("reading the site").path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let contents = fs::read_to_string(&path).expect("reading a page");
+            (name, contents)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Returns the contents of the file of the site with the given name.
+fn page<'a>(files: &'a [(String, String)], name: &str) -> &'a str {
+    let file = files.iter().find(|file| file.0 == name);
+    &file.unwrap_or_else(|| panic!("no page {}", name)).1
+}
+
+#[test]
+fn a_page_per_section() {
+    let files = site(&HtmlOptions::new());
+    let names = files.iter().map(|file| &*file.0).collect::<Vec<_>>();
+    assert_eq!(names, ["config.html", "index.html", "intro.html", "usage.html"]);
+}
+
+/// Returns the links of the navigation of a page, which is written at its start and its end.
+fn nav(page: &str) -> Vec<&str> {
+    let navs = page.split("<nav class=\"pages\">\n").skip(1);
+    let navs = navs.map(|nav| nav.split("</nav>").next().unwrap()).collect::<Vec<_>>();
+    assert_eq!(navs.len(), 2, "{}", page);
+    assert_eq!(navs[0], navs[1], "{}", page);
+    navs[0].lines().collect()
+}
+
+#[test]
+fn navigation_links() {
+    let files = site(&HtmlOptions::new());
+    let cases: [(&str, &[&str]); 4] = [
+        ("index.html", &["<a class=\"next\" href=\"intro.html\">Introduction</a>"]),
+        (
+            "intro.html",
+            &[
+                "<a class=\"prev\" href=\"index.html\">Manual</a>",
+                "<a class=\"up\" href=\"index.html\">Manual</a>",
+                "<a class=\"next\" href=\"usage.html\">Usage</a>",
+            ],
+        ),
+        (
+            "usage.html",
+            &[
+                "<a class=\"prev\" href=\"intro.html\">Introduction</a>",
+                "<a class=\"up\" href=\"index.html\">Manual</a>",
+                "<a class=\"next\" href=\"config.html\">Configuration</a>",
+            ],
+        ),
+        (
+            "config.html",
+            &[
+                "<a class=\"prev\" href=\"usage.html\">Usage</a>",
+                "<a class=\"up\" href=\"index.html\">Manual</a>",
+            ],
+        ),
+    ];
+    for &(name, links) in &cases {
+        assert_eq!(nav(page(&files, name)), links, "{}", name);
+    }
+}
+
+#[test]
+fn references_across_pages() {
+    let files = site(&HtmlOptions::new());
+    let usage = page(&files, "usage.html");
+    assert!(usage.contains("<a class=\"ref\" href=\"intro.html#intro\">1</a>"), "{}", usage);
+    assert!(usage.contains("<a class=\"ref\" href=\"config.html#config\">3</a>"), "{}", usage);
+    assert!(usage.contains("<title>Usage - Manual</title>"), "{}", usage);
+    // The table of contents on the index page links to each heading on its own page.
+    let index = page(&files, "index.html");
+    assert!(index.contains("<a href=\"intro.html#intro\">"), "{}", index);
+    assert!(index.contains("<a href=\"usage.html#options\">"), "{}", index);
+}
+
+#[test]
+fn footnotes_are_numbered_on_each_page() {
+    let files = site(&HtmlOptions::new());
+    let cases = [
+        ("intro.html", "And what it is not for."),
+        ("config.html", "Which is optional."),
+    ];
+    for &(name, footnote) in &cases {
+        let page = page(&files, name);
+        assert!(page.contains("<a href=\"#fn-1\" id=\"fnref-1\">1</a>"), "{}", page);
+        assert!(page.contains(&format!("<li id=\"fn-1\">{} ", footnote)), "{}", page);
+        assert!(!page.contains("fn-2"), "{}", page);
+    }
+}
+
+#[test]
+fn split_at_subsections() {
+    let files = site(&HtmlOptions::new().split_at(2));
+    let names = files.iter().map(|file| &*file.0).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["config.html", "index.html", "intro.html", "options.html", "usage.html"]
+    );
+    assert_eq!(
+        nav(page(&files, "options.html")),
+        [
+            "<a class=\"prev\" href=\"usage.html\">Usage</a>",
+            "<a class=\"up\" href=\"usage.html\">Usage</a>",
+            "<a class=\"next\" href=\"config.html\">Configuration</a>",
+        ]
+    );
+    let index = page(&files, "index.html");
+    assert!(index.contains("<a href=\"options.html#options\">"), "{}", index);
+}
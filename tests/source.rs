@@ -0,0 +1,57 @@
+//! Formats each document in `tests/source` as source, and checks that the output parses to the
+//! same document, and that formatting it again changes nothing, at several widths.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+
+use std::fs;
+use std::path::Path;
+
+use formatting::ast::Document;
+use formatting::parser::Parser;
+use formatting::render::source::{self, SourceOptions};
+
+/// The widths at which each document is formatted, the narrowest of which wraps most lines.
+const WIDTHS: &[usize] = &[80, 40, 20];
+
+fn parse(source: &str, name: &str) -> Document {
+    Parser::new(source.as_bytes())
+        .parse()
+        .unwrap_or_else(|e| panic!("{}: {}\n{}", name, e, source))
+}
+
+#[test]
+fn formatting_is_idempotent_and_preserves_documents() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/source");
+    let mut inputs: Vec<_> = fs::read_dir(&directory)
+        .expect("reading tests/source")
+        .map(|entry| entry.expect("reading tests/source").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "mle"))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no documents in tests/source");
+    for input in &inputs {
+        let original = fs::read_to_string(input).expect("reading a document");
+        let document = parse(&original, &input.display().to_string());
+        for &width in WIDTHS {
+            let name = format!("{} at width {}", input.display(), width);
+            let options = SourceOptions::new().width(width);
+            let formatted = source::render_to_string(&document, &options)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let reparsed = parse(&formatted, &name);
+            assert!(
+                reparsed.semantic_eq(&document),
+                "{} parses to a different document:\n{}",
+                name,
+                formatted
+            );
+            let again = source::render_to_string(&reparsed, &options)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(again, formatted, "{} changes when formatted again", name);
+        }
+        let minified = source::minify(&document).expect("minifying a document");
+        let name = format!("{} minified", input.display());
+        assert!(parse(&minified, &name).semantic_eq(&document), "{}:\n{}", name, minified);
+    }
+}
@@ -0,0 +1,75 @@
+//! Renders documents which trigger the warnings of the renderers, checking that each is reported
+//! at the start of the block in which it occurred.
+
+#![cfg(feature = "std")]
+
+extern crate formatting;
+
+use formatting::ast::Span;
+use formatting::convert::{convert_str_to_string, OutputFormat, Options};
+use formatting::parser::Parser;
+
+/// A document with a warning in each block after the first, at the line given by its text.
+const DOCUMENT: &str = "\\section{One}
+
+At line 3, see \\ref{nope}.
+
+  \\image{pic.png}
+
+At line 7, \\cite{nobody}.
+\\abbr{XYZ} is on line 8, in the same block.
+
+\\unknown{line 10}
+";
+
+/// Converts a document, returning the code and the line of each diagnostic, or 0 if it has no
+/// position.
+fn warnings(document: &str, format: OutputFormat, options: &Options) -> Vec<(&'static str, usize)> {
+    let (_, report) = convert_str_to_string(document, format, options).expect("converting");
+    report
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| {
+            let code = diagnostic.kind.code().unwrap_or_default();
+            let line = diagnostic.position.map_or(0, |position| position.line);
+            (code, line)
+        })
+        .collect()
+}
+
+#[test]
+fn render_warnings_have_positions() {
+    let options = Options::default();
+    let expected = [("W0501", 3), ("W0503", 5), ("W0507", 7), ("W0508", 7), ("W0500", 10)];
+    assert_eq!(warnings(DOCUMENT, OutputFormat::Html, &options), expected);
+    // Plain text passes unknown commands through without a warning.
+    assert_eq!(warnings(DOCUMENT, OutputFormat::Plain, &options), expected[..4]);
+}
+
+#[test]
+fn streamed_warnings_have_positions() {
+    let document = "First.\n\n\\image{pic.png}\n\nThird \\unknown{x}.\n";
+    let mut options = Options::default();
+    let expected = [("W0503", 3), ("W0500", 5)];
+    assert_eq!(warnings(document, OutputFormat::Html, &options), expected);
+    // HTML which is always streamed is not read ahead, so its positions are not known.
+    options.html = options.html.stream(true);
+    let expected = [("W0503", 0), ("W0500", 0)];
+    assert_eq!(warnings(document, OutputFormat::Html, &options), expected);
+}
+
+#[test]
+fn paragraphs_have_spans_of_their_own() {
+    let document = "One.\n\nTwo \\emph{x}\n\n\\image{pic.png}\n\nThree.\n\\section{Four}\n";
+    let mut blocks = Parser::new(document.as_bytes()).into_blocks();
+    let (parsed, spans) = blocks.parse_spanned().expect("parsing");
+    assert_eq!(parsed, Parser::new(document.as_bytes()).parse().expect("parsing"));
+    let text = spans
+        .iter()
+        .map(|&Span { start, end }| document[start..end].trim())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        text,
+        ["One.", "Two \\emph{x}", "\\image{pic.png}", "Three.", "\\section{Four}"]
+    );
+}
@@ -0,0 +1,27 @@
+//! Checks that `write_tokens` writes random token streams as source which reads back as the same
+//! stream.
+
+#![cfg(feature = "testing")]
+
+extern crate formatting;
+#[macro_use]
+extern crate proptest;
+
+use formatting::testing::gen_token_stream;
+use formatting::tokenize::{write_tokens, Token, Tokens};
+
+/// Reads the tokens of the source, with the default options.
+fn tokenize(source: &[u8]) -> Vec<Token> {
+    Tokens::new(source)
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| panic!("{:?}: {}", String::from_utf8_lossy(source), e))
+}
+
+proptest! {
+    #[test]
+    fn written_tokens_read_back(tokens in gen_token_stream(64)) {
+        let mut source = Vec::new();
+        write_tokens(tokens.clone(), &mut source).expect("writing the tokens");
+        prop_assert_eq!(tokenize(&source), tokens, "{:?}", String::from_utf8_lossy(&source));
+    }
+}